@@ -0,0 +1,61 @@
+//! Desktop shell: runs the backend in-process on a free local port and opens
+//! the Leptos frontend in a native window, told the backend's address through
+//! `window.__API_BASE__`. Configuration is read as by the server binary.
+
+// No console window next to the app in Windows release builds.
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+
+use std::sync::Arc;
+
+use tauri::{WebviewUrl, WebviewWindowBuilder};
+use tracing::warn;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter};
+
+use rust_ai_experiments::app;
+use rust_ai_experiments::config::{AppConfig, ConfigStore};
+
+fn main() {
+    dotenvy::dotenv().ok();
+
+    let config_path = ConfigStore::path_from_env();
+    let app_config = AppConfig::load(&config_path).expect("Failed to load configuration");
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&app_config.log_filter));
+    tracing_subscriber::registry().with(filter).with(tracing_subscriber::fmt::layer()).init();
+    let reload_log_filter = Arc::new(move |directive: &str| {
+        let filter = EnvFilter::try_new(directive).map_err(|e| e.to_string())?;
+        filter_handle.reload(filter).map_err(|e| e.to_string())
+    });
+    let config = ConfigStore::new(app_config, config_path, reload_log_filter);
+
+    // The backend runs on this runtime, which Tauri shares, while the window
+    // owns the main thread.
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the async runtime");
+    tauri::async_runtime::set(runtime.handle().clone());
+    let server = runtime
+        .block_on(async {
+            if let Err(e) = config.watch() {
+                warn!("Config hot-reload disabled: {e}");
+            }
+            let settings = config.get();
+            let schema = settings.database_schema.as_deref();
+            let pool = app::connect_database(&settings.database_url, schema).await?;
+            app::spawn_server(app::build_router(config.clone(), pool), "127.0.0.1:0").await
+        })
+        .expect("Failed to start the backend");
+    let api_base = serde_json::to_string(&server.url()).expect("a URL is valid JSON");
+
+    tauri::Builder::default()
+        .setup(move |app| {
+            WebviewWindowBuilder::new(app, "main", WebviewUrl::default())
+                .title("Rust AI Chat")
+                .inner_size(1200.0, 800.0)
+                .min_inner_size(480.0, 360.0)
+                .initialization_script(format!("window.__API_BASE__ = {api_base};"))
+                .build()?;
+            Ok(())
+        })
+        .run(tauri::generate_context!())
+        .expect("Failed to run the desktop app");
+}
@@ -1,13 +1,266 @@
 use gloo_net::http::Request;
+use js_sys::{Reflect, Uint8Array};
+use uuid::Uuid;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::ReadableStreamDefaultReader;
 
-use crate::models::{ChatRequest, ChatResponse, Conversation, Message};
+use crate::models::{
+    Artifact, AssistantPreset, Conversation, ConversationSort, ConversationStats, MaintenanceStatus,
+    Message, ModelsResponse, PromptHistoryEntry, PullProgress, RetrievalFeedback, RetrievedChunk,
+    ServerConfig, ServerVersion, SetActiveVersionRequest, StarredMessage, SystemStatus,
+    TimelineEntry, TokenizeResponse, UserProfile, VersionDiff,
+};
 
-/// Base URL of the backend API server.
-const API_BASE: &str = "http://localhost:3000";
+/// Base URL of the backend API server; empty means the page's own origin,
+/// which is how the backend serves the built app (`frontend_dir`) and how
+/// `trunk serve` proxies it. A host that starts the backend on a port of its
+/// own choosing (e.g. a desktop shell) sets `window.__API_BASE__` before the
+/// app loads; a build for a backend elsewhere sets `API_BASE` at compile time.
+pub fn api_base() -> String {
+    web_sys::window()
+        .and_then(|w| js_sys::Reflect::get(&w, &"__API_BASE__".into()).ok())
+        .and_then(|v| v.as_string())
+        .or_else(|| option_env!("API_BASE").map(str::to_string))
+        .map(|base| base.trim_end_matches('/').to_string())
+        .unwrap_or_default()
+}
+
+/// Fetches the list of all conversations from the backend, in the order
+/// `sort` asks for or, without it, the one saved in the profile.
+pub async fn fetch_conversations(
+    sort: Option<ConversationSort>,
+) -> Result<Vec<Conversation>, String> {
+    let resp = Request::get(&format!("{}/api/conversations", api_base()))
+        .query(sort.map(|sort| ("sort", sort.as_str())))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<Conversation>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Whether the server is in maintenance mode.
+pub async fn fetch_maintenance() -> Result<MaintenanceStatus, String> {
+    let resp = Request::get(&format!("{}/api/maintenance", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<MaintenanceStatus>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches the current user's profile.
+pub async fn fetch_profile() -> Result<UserProfile, String> {
+    let resp = Request::get(&format!("{}/api/me", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<UserProfile>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches the current model and its context size.
+pub async fn fetch_models() -> Result<ModelsResponse, String> {
+    let resp = Request::get(&format!("{}/api/models", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<ModelsResponse>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches the models Ollama has loaded and the host's memory and load.
+pub async fn fetch_system_status() -> Result<SystemStatus, String> {
+    let resp = Request::get(&format!("{}/api/system/status", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<SystemStatus>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches what the server offers: its protocol version, build and features.
+pub async fn fetch_server_config() -> Result<ServerConfig, String> {
+    let resp = Request::get(&format!("{}/api/config", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<ServerConfig>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches which build of the server is running.
+pub async fn fetch_version() -> Result<ServerVersion, String> {
+    let resp = Request::get(&format!("{}/api/version", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<ServerVersion>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches the assistant presets conversations can switch to.
+pub async fn fetch_presets() -> Result<Vec<AssistantPreset>, String> {
+    let resp = Request::get(&format!("{}/api/presets", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<AssistantPreset>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Switches a conversation to a preset, or (with `None`) back to the default
+/// assistant.
+pub async fn set_conversation_preset(
+    id: Uuid,
+    preset: Option<&str>,
+) -> Result<Conversation, String> {
+    let resp = Request::patch(&format!("{}/api/conversations/{id}", api_base()))
+        .json(&serde_json::json!({ "preset": preset }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Conversation>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Counts the tokens of `text` for the model new turns use.
+pub async fn tokenize(text: &str) -> Result<usize, String> {
+    let resp = Request::post(&format!("{}/api/tokenize", api_base()))
+        .json(&serde_json::json!({ "text": text }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<TokenizeResponse>()
+        .await
+        .map(|tokens| tokens.count)
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Sets or (with `None`) clears a conversation's icon and color.
+pub async fn update_conversation_appearance(
+    id: Uuid,
+    icon: Option<&str>,
+    color: Option<&str>,
+) -> Result<Conversation, String> {
+    let resp = Request::patch(&format!("{}/api/conversations/{id}", api_base()))
+        .json(&serde_json::json!({ "icon": icon, "color": color }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Conversation>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Saves the preferred theme to the user's profile.
+pub async fn set_theme(theme: &str) -> Result<UserProfile, String> {
+    let resp = Request::patch(&format!("{}/api/me", api_base()))
+        .json(&serde_json::json!({ "theme": theme }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<UserProfile>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Saves the order of the conversation list in the profile.
+pub async fn set_conversation_sort(sort: ConversationSort) -> Result<UserProfile, String> {
+    let resp = Request::patch(&format!("{}/api/me", api_base()))
+        .json(&serde_json::json!({ "conversation_sort": sort }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
 
-/// Fetches the list of all conversations from the backend.
-pub async fn fetch_conversations() -> Result<Vec<Conversation>, String> {
-    let resp = Request::get(&format!("{API_BASE}/api/conversations"))
+    resp.json::<UserProfile>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Searches conversation titles and message content on the backend.
+pub async fn search_conversations(query: &str) -> Result<Vec<Conversation>, String> {
+    let resp = Request::get(&format!("{}/api/search", api_base()))
+        .query([("q", query)])
         .send()
         .await
         .map_err(|e| format!("Network error: {e}"))?;
@@ -22,13 +275,12 @@ pub async fn fetch_conversations() -> Result<Vec<Conversation>, String> {
 }
 
 /// Fetches all messages for a given conversation.
-pub async fn fetch_messages(conversation_id: &str) -> Result<Vec<Message>, String> {
-    let resp = Request::get(&format!(
-        "{API_BASE}/api/conversations/{conversation_id}/messages"
-    ))
-    .send()
-    .await
-    .map_err(|e| format!("Network error: {e}"))?;
+pub async fn fetch_messages(conversation_id: Uuid) -> Result<Vec<Message>, String> {
+    let url = format!("{}/api/conversations/{conversation_id}/messages", api_base());
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
 
     if !resp.ok() {
         return Err(format!("Server error: {}", resp.status()));
@@ -39,18 +291,201 @@ pub async fn fetch_messages(conversation_id: &str) -> Result<Vec<Message>, Strin
         .map_err(|e| format!("Parse error: {e}"))
 }
 
-/// Sends a chat message via the REST API (non-streaming).
-pub async fn send_chat(
-    message: &str,
-    conversation_id: Option<&str>,
-) -> Result<ChatResponse, String> {
-    let body = ChatRequest {
-        message: message.to_string(),
-        conversation_id: conversation_id.map(|s| s.to_string()),
-    };
+/// A conversation's messages, the oldest of a long one condensed into a
+/// summary block.
+pub async fn fetch_timeline(conversation_id: Uuid) -> Result<Vec<TimelineEntry>, String> {
+    let url = format!(
+        "{}/api/conversations/{conversation_id}/messages?summaries=true",
+        api_base()
+    );
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<TimelineEntry>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// The chunks retrieved for a reply's active version, best first.
+pub async fn fetch_retrieval(message_id: Uuid) -> Result<Vec<RetrievedChunk>, String> {
+    let url = format!("{}/api/messages/{message_id}/retrieval", api_base());
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<RetrievedChunk>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Records whether a chunk retrieved for a reply was relevant; returns the
+/// reply's retrieved chunks.
+pub async fn rate_retrieval(
+    message_id: Uuid,
+    chunk_id: Uuid,
+    relevant: Option<bool>,
+) -> Result<Vec<RetrievedChunk>, String> {
+    let url = format!("{}/api/messages/{message_id}/retrieval/{chunk_id}", api_base());
+    let resp = Request::put(&url)
+        .json(&RetrievalFeedback { relevant })
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<RetrievedChunk>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// The artifacts of a conversation's replies, oldest first.
+pub async fn fetch_artifacts(conversation_id: Uuid) -> Result<Vec<Artifact>, String> {
+    let url = format!("{}/api/conversations/{conversation_id}/artifacts", api_base());
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<Artifact>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Where the browser downloads an artifact as a file.
+pub fn artifact_download_url(id: Uuid) -> String {
+    format!("{}/api/artifacts/{id}/download", api_base())
+}
+
+/// Fetches token usage and cost totals for a conversation.
+pub async fn fetch_conversation_stats(conversation_id: Uuid) -> Result<ConversationStats, String> {
+    let url = format!("{}/api/conversations/{conversation_id}/stats", api_base());
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<ConversationStats>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Pulls `model` (or the server's configured model) into Ollama, calling
+/// `on_progress` for each server-sent progress event until the pull ends.
+pub async fn pull_model(
+    model: Option<String>,
+    on_progress: impl Fn(PullProgress),
+) -> Result<(), String> {
+    let resp = Request::post(&format!("{}/api/models/pull", api_base()))
+        .json(&serde_json::json!({ "model": model }))
+        .map_err(|e| format!("Serialize error: {e}"))?
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+    let body = resp.body().ok_or("Empty pull response")?;
+    let reader: ReadableStreamDefaultReader = body.get_reader().unchecked_into();
+
+    // Server-sent events: blocks of `event:`/`data:` lines separated by a blank line.
+    let mut buffer = String::new();
+    loop {
+        let chunk = JsFuture::from(reader.read())
+            .await
+            .map_err(|e| format!("Network error: {e:?}"))?;
+        let done = Reflect::get(&chunk, &"done".into()).ok().and_then(|d| d.as_bool());
+        if done != Some(false) {
+            return Err("The server closed the pull before it finished".to_string());
+        }
+        let bytes = Reflect::get(&chunk, &"value".into()).map_err(|e| format!("{e:?}"))?;
+        buffer.push_str(&String::from_utf8_lossy(&Uint8Array::new(&bytes).to_vec()));
+
+        while let Some(end) = buffer.find("\n\n") {
+            let block: String = buffer.drain(..end + 2).collect();
+            let field = |name: &str| {
+                let value = block.lines().find_map(|l| l.strip_prefix(name));
+                value.map(str::trim).unwrap_or_default()
+            };
+            let data = field("data:");
+            match field("event:") {
+                "progress" => {
+                    if let Ok(progress) = serde_json::from_str::<PullProgress>(data) {
+                        on_progress(progress);
+                    }
+                }
+                "done" => return Ok(()),
+                "error" => {
+                    let error = serde_json::from_str::<serde_json::Value>(data).unwrap_or_default();
+                    return Err(error["error"].as_str().unwrap_or("Pull failed").to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Asks the backend for a new answer to an assistant message. The returned
+/// message has the new answer active; earlier ones are kept as versions.
+pub async fn regenerate_message(message_id: Uuid) -> Result<Message, String> {
+    let resp = Request::post(&format!("{}/api/messages/{message_id}/regenerate", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Message>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches the word diff between `version` of a reply and the one before it.
+pub async fn fetch_version_diff(message_id: Uuid, version: i32) -> Result<VersionDiff, String> {
+    let url = format!("{}/api/messages/{message_id}/versions/{version}/diff", api_base());
+    let resp = Request::get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<VersionDiff>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
 
-    let resp = Request::post(&format!("{API_BASE}/api/chat"))
-        .json(&body)
+/// Switches which version of a message is shown.
+pub async fn set_active_version(message_id: Uuid, version: i32) -> Result<Message, String> {
+    let resp = Request::put(&format!("{}/api/messages/{message_id}/active-version", api_base()))
+        .json(&SetActiveVersionRequest { version })
         .map_err(|e| format!("Serialize error: {e}"))?
         .send()
         .await
@@ -60,12 +495,82 @@ pub async fn send_chat(
         return Err(format!("Server error: {}", resp.status()));
     }
 
-    resp.json::<ChatResponse>()
+    resp.json::<Message>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Stars or unstars a message.
+pub async fn set_starred(message_id: Uuid, starred: bool) -> Result<Message, String> {
+    let url = format!("{}/api/messages/{message_id}/star", api_base());
+    let request = if starred { Request::put(&url) } else { Request::delete(&url) };
+    let resp = request.send().await.map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Message>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches starred messages from every conversation, most recently starred
+/// first.
+pub async fn fetch_starred() -> Result<Vec<StarredMessage>, String> {
+    let resp = Request::get(&format!("{}/api/starred", api_base()))
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<StarredMessage>>()
+        .await
+        .map_err(|e| format!("Parse error: {e}"))
+}
+
+/// Fetches up to `limit` prompts the user has sent that contain `query`, most
+/// recently used first.
+pub async fn fetch_prompt_history(
+    query: &str,
+    limit: usize,
+) -> Result<Vec<PromptHistoryEntry>, String> {
+    let resp = Request::get(&format!("{}/api/me/prompts", api_base()))
+        .query([("q", query), ("limit", &limit.to_string())])
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {e}"))?;
+
+    if !resp.ok() {
+        return Err(format!("Server error: {}", resp.status()));
+    }
+
+    resp.json::<Vec<PromptHistoryEntry>>()
         .await
         .map_err(|e| format!("Parse error: {e}"))
 }
 
 /// Returns the WebSocket URL for the chat streaming endpoint.
 pub fn ws_url() -> String {
-    format!("ws://localhost:3000/ws/chat")
+    format!("{}/ws/chat", ws_base())
+}
+
+/// Returns the WebSocket URL that carries a conversation's live turns.
+pub fn events_url(conversation_id: Uuid) -> String {
+    format!("{}/ws/conversations/{conversation_id}/events", ws_base())
+}
+
+/// WebSockets need an absolute URL, so a same-origin base is built from the
+/// page's.
+fn ws_base() -> String {
+    let base = match api_base() {
+        base if base.is_empty() => web_sys::window()
+            .and_then(|w| w.location().origin().ok())
+            .unwrap_or_default(),
+        base => base,
+    };
+    base.replacen("https://", "wss://", 1).replacen("http://", "ws://", 1)
 }
@@ -0,0 +1,124 @@
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::components::sidebar::SidebarToggle;
+use crate::i18n::{fill, Text};
+use crate::models::{LoadedModel, SystemStatus};
+use crate::state::AppState;
+
+/// Administration page; for now the system status of the server.
+#[component]
+pub fn AdminView() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+
+    view! {
+        <main class="chat-area">
+            <div class="chat-header">
+                <SidebarToggle />
+                {move || locale.get().tr(Text::AdminTitle)}
+            </div>
+            <div class="messages-container">
+                <SystemStatusPanel />
+            </div>
+        </main>
+    }
+}
+
+/// What Ollama has loaded and how busy the host is, refreshed on demand and
+/// every half minute.
+#[component]
+fn SystemStatusPanel() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let on_refresh = {
+        let state = state.clone();
+        move |_| {
+            let state = state.clone();
+            spawn_local(async move { state.load_system_status().await });
+        }
+    };
+
+    view! {
+        <section class="status-panel">
+            <div class="status-panel-header">
+                <h3>{move || locale.get().tr(Text::SystemStatus)}</h3>
+                <button class="status-refresh" on:click=on_refresh>
+                    {move || locale.get().tr(Text::Refresh)}
+                </button>
+            </div>
+            {move || state.system_status.get().map(|status| view! { <StatusDetails status=status /> })}
+        </section>
+    }
+}
+
+#[component]
+fn StatusDetails(status: SystemStatus) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let host = status.host;
+    let used = format_bytes(host.memory_used_bytes);
+    let total = format_bytes(host.memory_total_bytes);
+    let (load, cpus) = (format!("{:.2}", host.load_average[0]), host.cpus.to_string());
+    let models = if !status.ollama_reachable {
+        view! { <p class="status-warning">{move || locale.get().tr(Text::OllamaUnreachable)}</p> }
+            .into_any()
+    } else if status.loaded_models.is_empty() {
+        view! { <p class="status-muted">{move || locale.get().tr(Text::NoLoadedModels)}</p> }
+            .into_any()
+    } else {
+        view! {
+            <ul class="loaded-models">
+                {status.loaded_models.into_iter().map(|model| view! {
+                    <LoadedModelRow model=model />
+                }).collect_view()}
+            </ul>
+        }
+        .into_any()
+    };
+
+    view! {
+        <div class="status-host">
+            <div>
+                {move || fill(locale.get().tr(Text::HostMemory), &[("used", &used), ("total", &total)])}
+            </div>
+            <div>
+                {move || fill(locale.get().tr(Text::HostLoad), &[("load", &load), ("cpus", &cpus)])}
+            </div>
+        </div>
+        <h4>{move || locale.get().tr(Text::LoadedModels)}</h4>
+        {models}
+    }
+}
+
+#[component]
+fn LoadedModelRow(model: LoadedModel) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let (size, vram) = (format_bytes(model.size), format_bytes(model.size_vram));
+
+    view! {
+        <li>
+            <span class="loaded-model-name">{model.name}</span>
+            <span class="status-muted">
+                {move || fill(locale.get().tr(Text::ModelMemory), &[("size", &size), ("vram", &vram)])}
+            </span>
+        </li>
+    }
+}
+
+/// `3.2 GB`, in powers of 1024 as Ollama and `free` report them.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,96 @@
+use leptos::prelude::*;
+use uuid::Uuid;
+
+use crate::api;
+use crate::components::chat::CopyButton;
+use crate::components::markdown::Markdown;
+use crate::i18n::Text;
+use crate::models::ArtifactKind;
+use crate::state::AppState;
+
+/// One button per artifact of a reply, opening it in the side panel.
+#[component]
+pub fn ArtifactLinks(message_id: Uuid) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let links = move || {
+        state.artifacts.with(|artifacts| {
+            artifacts
+                .iter()
+                .filter(|a| a.message_id == message_id)
+                .map(|a| (a.id, a.kind, a.title.clone()))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    view! {
+        {move || {
+            let links = links();
+            (!links.is_empty()).then(|| view! {
+                <div class="artifact-links">
+                    {links.into_iter().map(|(id, kind, title)| {
+                        let icon = if kind == ArtifactKind::Code { "</>" } else { "¶" };
+                        view! {
+                            <button
+                                class="artifact-link"
+                                class:open=move || state.open_artifact.get() == Some(id)
+                                on:click=move |_| state.set_open_artifact.set(Some(id))
+                            >
+                                <span class="artifact-icon">{icon}</span>
+                                {title}
+                            </button>
+                        }
+                    }).collect_view()}
+                </div>
+            })
+        }}
+    }
+}
+
+/// The open artifact beside the chat: code as it is, documents as markdown,
+/// with copy and download.
+#[component]
+pub fn ArtifactPanel() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let artifact = move || {
+        let id = state.open_artifact.get()?;
+        state.artifacts.with(|artifacts| artifacts.iter().find(|a| a.id == id).cloned())
+    };
+
+    move || artifact().map(|artifact| {
+        let body = match artifact.kind {
+            ArtifactKind::Code => view! {
+                <pre class="artifact-code"><code>{artifact.content.clone()}</code></pre>
+            }
+            .into_any(),
+            ArtifactKind::Document => view! { <Markdown text=artifact.content.clone() /> }.into_any(),
+        };
+        view! {
+            <aside class="artifact-panel">
+                <div class="artifact-header">
+                    <span class="artifact-title">{artifact.title.clone()}</span>
+                    {artifact.language.clone().map(|language| view! {
+                        <span class="artifact-language">{language}</span>
+                    })}
+                    <div class="message-actions">
+                        <CopyButton text=artifact.content.clone() />
+                        <a
+                            class="artifact-download"
+                            href=api::artifact_download_url(artifact.id)
+                            download=artifact.file_name()
+                        >
+                            {move || locale.get().tr(Text::DownloadArtifact)}
+                        </a>
+                        <button
+                            title=move || locale.get().tr(Text::CloseArtifact)
+                            on:click=move |_| state.set_open_artifact.set(None)
+                        >
+                            "✕"
+                        </button>
+                    </div>
+                </div>
+                <div class="artifact-body">{body}</div>
+            </aside>
+        }
+    })
+}
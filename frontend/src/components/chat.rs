@@ -1,32 +1,130 @@
+use gloo_timers::future::TimeoutFuture;
 use leptos::prelude::*;
 use leptos::ev;
+use leptos::task::spawn_local;
+use uuid::Uuid;
+use wasm_bindgen_futures::JsFuture;
 
-use crate::state::AppState;
+use crate::api;
+use crate::components::artifacts::ArtifactLinks;
+use crate::components::retrieval::RetrievalDrawer;
+use crate::components::markdown::Markdown;
+use crate::components::sidebar::SidebarToggle;
+use crate::components::starred::StarButton;
+use crate::i18n::{fill, Locale, Text};
+use crate::models::{
+    CompletionStats, DiffOp, DiffSpan, FinishReason, Message, MessageRole, PromptHistoryEntry,
+    Source, SummaryBlock, ToolCallStatus, Verbosity,
+};
+use crate::state::{is_saved, AppState, Quote};
+use crate::time;
+use crate::tokens::{Counts, History, TokenBudget};
+use crate::ws::WsStatus;
+
+/// Wait for typing to pause before counting the message's tokens.
+const COUNT_DEBOUNCE_MS: u32 = 300;
+/// Earlier prompts Up and Down step through.
+const RECALLED_PROMPTS: usize = 50;
+/// Prompts listed in the history popover.
+const LISTED_PROMPTS: usize = 20;
+/// How long a copy button shows that it worked.
+const COPIED_FEEDBACK_MS: u32 = 1_500;
 
 /// Main chat area with message history, streaming display, and input.
 #[component]
 pub fn ChatArea() -> impl IntoView {
     let state = expect_context::<AppState>();
+    let locale = state.locale;
+
+    let is_streaming_text = Memo::new(move |_| state.streaming_text.with(Option::is_some));
+
+    // Offered under the newest reply once it is complete.
+    let follow_ups = move || {
+        if state.is_streaming.get() || state.failed_turn.get().is_some() {
+            return None;
+        }
+        state.messages.with(|msgs| {
+            let last = msgs.last().filter(|m| m.role == MessageRole::Assistant)?;
+            (!last.suggestions.is_empty()).then(|| last.suggestions.clone())
+        })
+    };
 
     view! {
         <main class="chat-area">
-            // Error banner
-            {move || {
-                state.error.get().map(|err| {
-                    view! {
-                        <div class="error-banner">{err}</div>
-                    }
-                })
-            }}
+            // Maintenance mode: chat is refused until it ends
+            {move || state.maintenance.get().map(|status| view! {
+                <div class="maintenance-banner" role="status">
+                    {status.message.unwrap_or_else(|| locale.get().tr(Text::Maintenance).to_string())}
+                </div>
+            })}
+
+            // Deployed since this app loaded: it may not speak the server's protocol
+            {move || state.outdated.get().then(|| view! {
+                <div class="update-banner" role="status">
+                    <span>{locale.get().tr(Text::AppUpdated)}</span>
+                    <button on:click=move |_| {
+                        if let Some(window) = web_sys::window() {
+                            let _ = window.location().reload();
+                        }
+                    }>{locale.get().tr(Text::Reload)}</button>
+                </div>
+            })}
+
+            // Offline: cached history only, sends wait for the connection
+            {move || (!state.online.get()).then(|| view! {
+                <div class="connection-banner offline" role="status">
+                    {locale.get().tr(Text::Offline)}
+                </div>
+            })}
+
+            // Socket dropped: requests wait until it is back
+            {move || (state.online.get() && state.ws_status.get() == WsStatus::Reconnecting).then(|| view! {
+                <div class="connection-banner" role="status">
+                    {locale.get().tr(Text::Reconnecting)}
+                </div>
+            })}
+
+            // Missing model: offer to pull it
+            {move || state.missing_model.get().map(|model| view! { <ModelPullBanner model=model /> })}
 
             // Chat header
             <div class="chat-header">
+                <SidebarToggle />
                 {move || {
+                    let locale = locale.get();
+                    let kind = if state.ephemeral.get() { Text::IncognitoConversation } else { Text::Conversation };
                     match state.active_conversation.get() {
-                        Some(id) => format!("Conversation: {}", &id[..8.min(id.len())]),
-                        None => "New conversation".to_string(),
+                        Some(id) => format!("{}: {}", locale.tr(kind), &id.to_string()[..8]).into_any(),
+                        None => view! {
+                            {locale.tr(Text::NewConversation)}
+                            <label class="incognito-toggle">
+                                <input
+                                    type="checkbox"
+                                    prop:checked=state.ephemeral
+                                    on:change=move |ev| state.set_ephemeral.set(event_target_checked(&ev))
+                                />
+                                {locale.tr(Text::IncognitoToggle)}
+                            </label>
+                        }.into_any(),
                     }
                 }}
+                <label class="incognito-toggle" title=move || locale.get().tr(Text::StatelessHint)>
+                    <input
+                        type="checkbox"
+                        prop:checked=state.stateless
+                        on:change=move |ev| state.set_stateless.set(event_target_checked(&ev))
+                    />
+                    {move || locale.get().tr(Text::StatelessToggle)}
+                </label>
+                {move || state.conversation_cost.get().map(|cost| view! {
+                    <span class="conversation-cost">{format_cost(cost)}</span>
+                })}
+                {move || {
+                    let offered = state.presets.with(|presets| !presets.is_empty());
+                    (offered && state.active_conversation.get().is_some())
+                        .then(|| view! { <PresetPicker /> })
+                }}
+                <ModelStatus />
             </div>
 
             // Messages
@@ -36,25 +134,65 @@ pub fn ChatArea() -> impl IntoView {
                     if msgs.is_empty() && state.streaming_text.get().is_none() {
                         view! {
                             <div class="empty-state">
-                                "Send a message to start chatting"
+                                {move || locale.get().tr(Text::EmptyState)}
                             </div>
                         }.into_any()
                     } else {
                         view! {
+                            {move || state.summary.get().map(|block| view! { <SummaryCard block=block /> })}
                             <For
-                                each=move || state.messages.get()
-                                key=|m| m.id.clone()
-                                let:msg
+                                each=move || with_places(state.messages.get())
+                                key=|(m, place)| (m.id, m.active_version, m.version_count, m.starred, *place)
+                                let:item
                             >
-                                <MessageBubble role=msg.role.clone() content=msg.content.clone() />
+                                <MessageBubble msg=item.0 place=item.1 />
                             </For>
-                            // Streaming message (assistant typing)
+                            {move || state.failed_turn.get().map(|error| view! { <FailedTurn error=error /> })}
+                            {move || follow_ups().map(|suggestions| view! { <FollowUps suggestions=suggestions /> })}
+                            // Streaming message (assistant typing). Rebuilt only when a
+                            // stream starts; chunks update the markdown inside.
                             {move || {
-                                state.streaming_text.get().map(|text| {
+                                is_streaming_text.get().then(|| {
+                                    let text = Signal::derive(move || {
+                                        state.streaming_text.get().unwrap_or_default()
+                                    });
                                     view! {
                                         <div class="message assistant">
-                                            <div class="role-label">"assistant"</div>
-                                            <div class="streaming-cursor">{text}</div>
+                                            <div class="role-label">{move || locale.get().tr(Text::RoleAssistant)}</div>
+                                            {move || (!state.online.get()).then(|| view! {
+                                                <div class="queue-notice">
+                                                    {locale.get().tr(Text::OfflineQueued)}
+                                                </div>
+                                            })}
+                                            {move || state.queue_position.get().map(|pos| view! {
+                                                <div class="queue-notice">
+                                                    {fill(
+                                                        locale.get().tr(Text::QueuePosition),
+                                                        &[("position", &pos.to_string())],
+                                                    )}
+                                                </div>
+                                            })}
+                                            {move || state.streaming_tools.get().into_iter().map(|tool| {
+                                                let text = match tool.status {
+                                                    ToolCallStatus::Running => Text::ToolRunning,
+                                                    ToolCallStatus::Done => Text::ToolDone,
+                                                    ToolCallStatus::Failed => Text::ToolFailed,
+                                                    ToolCallStatus::TimedOut => Text::ToolTimedOut,
+                                                };
+                                                let failed = matches!(tool.status, ToolCallStatus::Failed | ToolCallStatus::TimedOut);
+                                                view! {
+                                                    <div class="tool-activity" class:failed=failed>
+                                                        {fill(locale.get().tr(text), &[("tool", &tool.name)])}
+                                                    </div>
+                                                }
+                                            }).collect_view()}
+                                            <Show
+                                                when=move || text.with(|text| !text.is_empty())
+                                                fallback=|| view! { <div class="streaming-cursor" /> }
+                                            >
+                                                <Markdown text streaming=true />
+                                            </Show>
+                                            <SourcesSection sources=state.streaming_sources.get() />
                                         </div>
                                     }
                                 })
@@ -70,46 +208,725 @@ pub fn ChatArea() -> impl IntoView {
     }
 }
 
-/// A single chat message bubble.
+/// The oldest messages of a long conversation, condensed into its summary.
+/// Expanding the card fetches the messages themselves and shows them in it.
+#[component]
+fn SummaryCard(block: SummaryBlock) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let (expanded, set_expanded) = signal(false);
+    let on_toggle = {
+        let state = state.clone();
+        move |_| {
+            let expand = !expanded.get_untracked();
+            if expand && state.earlier.with_untracked(Option::is_none) {
+                state.load_earlier();
+            }
+            set_expanded.set(expand);
+        }
+    };
+    let count = block.message_count.to_string();
+
+    view! {
+        <section class="summary-card">
+            <button
+                class="summary-toggle"
+                aria-expanded=move || expanded.get().to_string()
+                on:click=on_toggle
+            >
+                <span class="summary-chevron">{move || if expanded.get() { "▾" } else { "▸" }}</span>
+                {move || locale.get().tr(Text::EarlierSummary)}
+                <span class="summary-count">
+                    {move || fill(locale.get().tr(Text::SummarizedMessages), &[("count", &count)])}
+                </span>
+            </button>
+            <p class="summary-text">{block.summary}</p>
+            {move || expanded.get().then(|| match state.earlier.get() {
+                Some(msgs) => view! {
+                    <div class="summary-originals">
+                        {with_places(msgs).into_iter().map(|(msg, place)| view! {
+                            <MessageBubble msg=msg place=place />
+                        }).collect_view()}
+                    </div>
+                }.into_any(),
+                None => view! {
+                    <div class="summary-loading">{move || locale.get().tr(Text::LoadingEarlier)}</div>
+                }.into_any(),
+            })}
+        </section>
+    }
+}
+
+/// Whether the model the next turn uses is loaded in Ollama or cold, so the
+/// first reply will wait for it to load. Hidden while Ollama is unreachable.
+#[component]
+fn ModelStatus() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let model = Memo::new(move |_| {
+        let status = state.system_status.get().filter(|status| status.ollama_reachable)?;
+        let preset_model = state.preset.with(|preset| {
+            let preset = preset.as_ref()?;
+            state.presets.with(|presets| presets.iter().find(|p| &p.id == preset)?.model.clone())
+        });
+        let model = preset_model.unwrap_or(status.current_model);
+        let loaded = status.loaded_models.iter().any(|m| same_model(&m.name, &model));
+        Some((model, loaded))
+    });
+
+    move || model.get().map(|(model, loaded)| {
+        let text = if loaded { Text::ModelLoaded } else { Text::ModelCold };
+        let title = fill(locale.get().tr(text), &[("model", &model)]);
+        view! {
+            <span class="model-status" class:loaded=loaded title=title.clone() aria-label=title>
+                <span class="model-status-dot" />
+                {model}
+            </span>
+        }
+    })
+}
+
+/// Whether two model names are the same model; an untagged name means
+/// `:latest`, as in Ollama.
+fn same_model(a: &str, b: &str) -> bool {
+    let tagged = |name: &str| {
+        if name.contains(':') { name.to_string() } else { format!("{name}:latest") }
+    };
+    tagged(a) == tagged(b)
+}
+
+/// Picks the assistant preset answering the open conversation. The server
+/// notes each switch in the conversation; it applies from the next turn.
+#[component]
+fn PresetPicker() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let on_change = {
+        let state = state.clone();
+        move |ev| {
+            let preset = event_target_value(&ev);
+            state.switch_preset((!preset.is_empty()).then_some(preset));
+        }
+    };
+
+    view! {
+        <select
+            class="preset-picker"
+            aria-label=move || locale.get().tr(Text::Assistant)
+            title=move || locale.get().tr(Text::Assistant)
+            disabled=move || state.is_streaming.get()
+            on:change=on_change
+        >
+            <option value="" prop:selected=move || state.preset.get().is_none()>
+                {move || locale.get().tr(Text::DefaultAssistant)}
+            </option>
+            {move || state.presets.get().into_iter().map(|preset| {
+                let id = preset.id.clone();
+                view! {
+                    <option
+                        value=preset.id
+                        prop:selected=move || state.preset.with(|p| p.as_ref() == Some(&id))
+                    >
+                        {preset.name}
+                    </option>
+                }
+            }).collect_view()}
+        </select>
+    }
+}
+
+/// Follow-up prompts for the newest reply; clicking one sends it.
+#[component]
+fn FollowUps(suggestions: Vec<String>) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let busy = move || state.is_streaming.get() || state.maintenance.get().is_some();
+
+    view! {
+        <div class="follow-ups" aria-label=move || locale.get().tr(Text::FollowUps)>
+            {suggestions.into_iter().map(|suggestion| {
+                let send = {
+                    let (state, text) = (state.clone(), suggestion.clone());
+                    move |_| state.send_message(text.clone(), None)
+                };
+                view! {
+                    <button class="follow-up" disabled=busy on:click=send>
+                        {suggestion}
+                    </button>
+                }
+            }).collect_view()}
+        </div>
+    }
+}
+
+/// Stands in for the reply of a turn that failed, with a button to run it
+/// again.
+#[component]
+fn FailedTurn(error: String) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let busy = state.is_streaming;
+    let retry = move |_| state.retry_turn();
+
+    view! {
+        <div class="message assistant failed-turn" role="alert">
+            <div>{move || fill(locale.get().tr(Text::GenerationFailed), &[("error", &error)])}</div>
+            <div class="message-actions">
+                <button on:click=retry disabled=busy>{move || locale.get().tr(Text::Retry)}</button>
+            </div>
+        </div>
+    }
+}
+
+/// Offers to download a model the last turn needed, then shows a progress
+/// bar until the pull finishes and the turn is retried.
+#[component]
+fn ModelPullBanner(model: String) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let pull = {
+        let state = state.clone();
+        move |_| state.pull_missing_model()
+    };
+
+    view! {
+        <div class="model-pull-banner">
+            {move || match state.pull_progress.get() {
+                None => view! {
+                    <span>{fill(locale.get().tr(Text::ModelMissing), &[("model", &model)])}</span>
+                    <button on:click=pull.clone()>{locale.get().tr(Text::DownloadAndRetry)}</button>
+                }.into_any(),
+                Some(progress) => {
+                    let percent = match (progress.completed, progress.total) {
+                        (Some(done), Some(total)) if total > 0 => Some(done * 100 / total),
+                        _ => None,
+                    };
+                    let label = match percent {
+                        Some(p) => format!("{} — {p}%", progress.status),
+                        None => progress.status,
+                    };
+                    view! {
+                        <span>{label}</span>
+                        {match percent {
+                            Some(p) => view! { <progress max="100" value=p /> }.into_any(),
+                            None => view! { <progress /> }.into_any(),
+                        }}
+                    }.into_any()
+                }
+            }}
+        </div>
+    }
+}
+
+/// A single chat message bubble, followed by the sources it cites. Tool calls
+/// and their results render as collapsed steps instead, and system notes as
+/// dividers. The last message the model did not see is followed by a marker.
 #[component]
-fn MessageBubble(role: String, content: String) -> impl IntoView {
-    let css_class = if role == "user" {
-        "message user"
+fn MessageBubble(msg: Message, place: MessagePlace) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let id = msg.id;
+    let is_cutoff = move || state.history_cutoff.get() == Some(id);
+    let ms = time::millis(&msg.created_at);
+    let separator = place.new_day.then(|| view! {
+        <div class="date-separator">
+            <span>{move || time::day_label(ms, state.now.get(), locale.get())}</span>
+        </div>
+    });
+    let timestamp = view! {
+        <time
+            class="message-time"
+            datetime=msg.created_at.to_rfc3339()
+            title=move || time::absolute(ms, locale.get())
+        >
+            {move || time::relative(ms, state.now.get(), locale.get())}
+        </time>
+    };
+
+    let body = if msg.role == MessageRole::System {
+        // Notes such as a switch of assistant preset, across the conversation.
+        view! {
+            <div class="system-note" role="note">
+                <span>{msg.content}</span>
+            </div>
+        }
+        .into_any()
+    } else if msg.role.is_tool_traffic() {
+        let label =
+            if msg.role == MessageRole::Function { Text::ToolCall } else { Text::ToolResult };
+        let tool = msg.tool_name.unwrap_or_default();
+        view! {
+            <details class="message tool-step">
+                <summary>{move || fill(locale.get().tr(label), &[("tool", &tool)])}</summary>
+                <pre>{msg.content}</pre>
+            </details>
+        }
+        .into_any()
     } else {
-        "message assistant"
+        let is_user = msg.role == MessageRole::User;
+        let role = if is_user { Text::RoleUser } else { Text::RoleAssistant };
+        let css_class = match (is_user, place.continues) {
+            (true, false) => "message user",
+            (true, true) => "message user grouped",
+            (false, false) => "message assistant",
+            (false, true) => "message assistant grouped",
+        };
+        // Only messages the server has persisted can be starred, quoted or
+        // regenerated; any can be copied.
+        let persisted = is_saved(msg.id);
+        let star = persisted.then(|| view! {
+            <StarButton message_id=msg.id starred=msg.starred />
+        });
+        let quote = persisted.then(|| view! {
+            <QuoteButton message_id=msg.id is_user=is_user content=msg.content.clone() />
+        });
+        let versions = (persisted && !is_user).then(|| view! {
+            <VersionControls
+                message_id=msg.id
+                active_version=msg.active_version
+                version_count=msg.version_count
+            />
+        });
+        let retrieval = (persisted && !is_user).then(|| view! {
+            <RetrievalDrawer message_id=msg.id />
+        });
+        let controls = view! {
+            <div class="message-actions">
+                {star}
+                <CopyButton text=msg.content.clone() />
+                {quote}
+                {versions}
+                {retrieval}
+            </div>
+        };
+        // Users type plain text, opened by a quote when they replied to a
+        // message; replies are markdown.
+        let quoted = msg.quoted_message_id.and_then(|_| split_quote(&msg.content));
+        let stats = state.reply_stats.with_untracked(|stats| stats.get(&msg.id).cloned());
+        let content = match quoted {
+            Some((attribution, quoted, rest)) if is_user => view! {
+                <blockquote class="quoted-message">
+                    <div class="quote-attribution">{attribution}</div>
+                    <div class="quote-text">{quoted}</div>
+                </blockquote>
+                <div>{rest}</div>
+            }
+            .into_any(),
+            _ if is_user => view! { <div>{msg.content}</div> }.into_any(),
+            _ => view! { <Markdown text=msg.content /> }.into_any(),
+        };
+        view! {
+            <div class=css_class>
+                {(!place.continues).then(|| view! {
+                    <div class="role-label">{move || locale.get().tr(role)}</div>
+                })}
+                {content}
+                <SourcesSection sources=msg.sources />
+                {(!is_user).then(|| view! { <ArtifactLinks message_id=msg.id /> })}
+                {stats.map(|stats| view! {
+                    <div class="message-stats">{move || format_stats(&stats, locale.get())}</div>
+                })}
+                {controls}
+                {timestamp}
+            </div>
+        }
+        .into_any()
     };
-    let label = role.clone();
 
     view! {
-        <div class=css_class>
-            <div class="role-label">{label}</div>
-            <div>{content}</div>
+        {separator}
+        {body}
+        <Show when=is_cutoff>
+            <div class="history-cutoff">{move || locale.get().tr(Text::HistoryCutoff)}</div>
+        </Show>
+    }
+}
+
+/// Where a message sits relative to the one before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct MessagePlace {
+    /// Same role as the previous message on the same day: shown without its
+    /// role label, tucked under the previous bubble.
+    continues: bool,
+    /// First message of its day: preceded by a date separator.
+    new_day: bool,
+}
+
+fn with_places(messages: Vec<Message>) -> Vec<(Message, MessagePlace)> {
+    let mut previous: Option<(MessageRole, f64)> = None;
+    messages
+        .into_iter()
+        .map(|msg| {
+            let sent_at = time::millis(&msg.created_at);
+            let new_day = previous.is_none_or(|(_, before)| !time::same_day(before, sent_at));
+            let continues = !new_day && previous.is_some_and(|(role, _)| role == msg.role);
+            previous = Some((msg.role, sent_at));
+            (msg, MessagePlace { continues, new_day })
+        })
+        .collect()
+}
+
+/// `llama3.2 · 12 → 48 tokens · 1.9s`, plus why the reply stopped if it was
+/// not a normal finish.
+fn format_stats(stats: &CompletionStats, locale: Locale) -> String {
+    let mut parts = vec![stats.model.clone()];
+    if let (Some(prompt), Some(completion)) = (stats.prompt_tokens, stats.completion_tokens) {
+        parts.push(fill(
+            locale.tr(Text::TokenCounts),
+            &[("prompt", &prompt.to_string()), ("completion", &completion.to_string())],
+        ));
+    }
+    parts.push(format!("{:.1}s", stats.duration_ms as f64 / 1000.0));
+    parts.extend(stats.cost.map(format_cost));
+    let finish = match stats.finish_reason {
+        FinishReason::Length => Some(Text::FinishLength),
+        FinishReason::Cancelled => Some(Text::FinishCancelled),
+        FinishReason::Error => Some(Text::FinishError),
+        FinishReason::Stop => None,
+    };
+    parts.extend(finish.map(|f| locale.tr(f).to_string()));
+    parts.join(" · ")
+}
+
+/// Dollars with enough decimals to show the cost of a single reply.
+fn format_cost(cost: f64) -> String {
+    format!("${cost:.4}")
+}
+
+/// The blockquote a quoting message opens with, split into its attribution
+/// line and the quoted text, and what the user wrote after it.
+fn split_quote(content: &str) -> Option<(String, String, String)> {
+    let unquote = |line: &str| {
+        let line = line.strip_prefix('>').unwrap_or(line);
+        line.strip_prefix(' ').unwrap_or(line).to_string()
+    };
+    let quoted: Vec<&str> = content.lines().take_while(|line| line.starts_with('>')).collect();
+    let (attribution, text) = quoted.split_first()?;
+    let text: Vec<String> = text.iter().map(|line| unquote(line)).collect();
+    let rest: Vec<&str> = content.lines().skip(quoted.len()).collect();
+    Some((unquote(attribution), text.join("\n"), rest.join("\n").trim().to_string()))
+}
+
+/// `content` as a markdown blockquote under an `attribution` line.
+fn blockquote(attribution: &str, content: &str) -> String {
+    std::iter::once(attribution)
+        .chain(content.trim().lines())
+        .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {line}") })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Copies a message's text (the markdown, for replies) to the clipboard.
+#[component]
+pub fn CopyButton(text: String) -> impl IntoView {
+    let locale = expect_context::<AppState>().locale;
+    let (copied, set_copied) = signal(false);
+    let copy = move |_| {
+        let text = text.clone();
+        spawn_local(async move {
+            let Some(window) = web_sys::window() else { return };
+            // Missing outside secure contexts (plain http other than localhost).
+            let clipboard = window.navigator().clipboard();
+            if clipboard.is_undefined() {
+                log::warn!("The clipboard is not available on this page");
+                return;
+            }
+            match JsFuture::from(clipboard.write_text(&text)).await {
+                Ok(_) => {
+                    set_copied.set(true);
+                    TimeoutFuture::new(COPIED_FEEDBACK_MS).await;
+                    set_copied.set(false);
+                }
+                Err(e) => log::warn!("Copying to the clipboard failed: {e:?}"),
+            }
+        });
+    };
+
+    view! {
+        <button
+            class="copy-btn"
+            class:copied=copied
+            title=move || locale.get().tr(if copied.get() { Text::Copied } else { Text::Copy })
+            on:click=copy
+        >
+            {move || if copied.get() { "✓" } else { "⧉" }}
+        </button>
+    }
+}
+
+/// Puts a message into the chat input as a blockquote naming its author, to
+/// reply to it.
+#[component]
+fn QuoteButton(message_id: Uuid, is_user: bool, content: String) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let quote = move |_| {
+        let author = if is_user { Text::QuoteFromUser } else { Text::QuoteFromAssistant };
+        let attribution = locale.get_untracked().tr(author);
+        let text = blockquote(attribution, &content);
+        state.set_quote.set(Some(Quote { message_id, text }));
+    };
+
+    view! {
+        <button class="quote-btn" title=move || locale.get().tr(Text::Quote) on:click=quote>
+            "❝"
+        </button>
+    }
+}
+
+/// `‹ 2 / 3 ›` arrows for switching between generations of an assistant
+/// reply, plus a button to generate another one. From the second version on,
+/// "Show changes" highlights what differs from the version before.
+#[component]
+fn VersionControls(
+    message_id: Uuid,
+    active_version: i32,
+    version_count: i32,
+) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let busy = {
+        let (is_streaming, regenerating) = (state.is_streaming, state.regenerating);
+        move || is_streaming.get() || regenerating.get() == Some(message_id)
+    };
+
+    let prev = {
+        let state = state.clone();
+        move |_| state.switch_version(message_id, active_version - 1)
+    };
+    let next = {
+        let state = state.clone();
+        move |_| state.switch_version(message_id, active_version + 1)
+    };
+    let diff = RwSignal::new(None::<Vec<DiffSpan>>);
+    let toggle_diff = {
+        let state = state.clone();
+        move |_| {
+            if diff.get_untracked().is_some() {
+                diff.set(None);
+                return;
+            }
+            let state = state.clone();
+            spawn_local(async move {
+                match api::fetch_version_diff(message_id, active_version).await {
+                    Ok(d) => diff.set(Some(d.diff)),
+                    Err(e) => {
+                        log::error!("Failed to fetch the version diff: {e}");
+                        state.notify_error(e, None);
+                    }
+                }
+            });
+        }
+    };
+    let locale = state.locale;
+    let regenerate = move |_| state.regenerate(message_id);
+    let busy_label = busy;
+
+    view! {
+        {(version_count > 1).then(|| view! {
+            <button class="version-btn" on:click=prev disabled=active_version <= 1>"‹"</button>
+            <span class="version-label">{format!("{active_version} / {version_count}")}</span>
+            <button class="version-btn" on:click=next disabled=active_version >= version_count>"›"</button>
+        })}
+        {(active_version > 1).then(|| view! {
+            <button class="diff-btn" class:active=move || diff.with(Option::is_some) on:click=toggle_diff>
+                {move || locale.get().tr(
+                    if diff.with(Option::is_some) { Text::HideChanges } else { Text::ShowChanges },
+                )}
+            </button>
+        })}
+        <button class="regenerate-btn" on:click=regenerate disabled=busy>
+            {move || locale.get().tr(if busy_label() { Text::Regenerating } else { Text::Regenerate })}
+        </button>
+        {move || diff.get().map(|spans| view! { <VersionDiffView spans /> })}
+    }
+}
+
+/// A word diff of two reply versions, with additions and removals marked.
+#[component]
+fn VersionDiffView(spans: Vec<DiffSpan>) -> impl IntoView {
+    view! {
+        <div class="version-diff">
+            {spans.into_iter().map(|span| match span.op {
+                DiffOp::Equal => view! { <span>{span.text}</span> }.into_any(),
+                DiffOp::Insert => view! { <ins>{span.text}</ins> }.into_any(),
+                DiffOp::Delete => view! { <del>{span.text}</del> }.into_any(),
+            }).collect_view()}
         </div>
     }
 }
 
+/// Collapsible "Sources" list. Items are numbered so they line up with the
+/// `[n]` citation markers in the message content.
+#[component]
+fn SourcesSection(sources: Vec<Source>) -> impl IntoView {
+    let locale = expect_context::<AppState>().locale;
+    (!sources.is_empty()).then(|| {
+        let count = sources.len().to_string();
+        view! {
+            <details class="sources">
+                <summary>{move || fill(locale.get().tr(Text::Sources), &[("count", &count)])}</summary>
+                <ol>
+                    {sources.into_iter().map(|s| {
+                        let title = if s.title.is_empty() { s.url.clone() } else { s.title };
+                        view! {
+                            <li>
+                                <a href=s.url target="_blank" rel="noopener noreferrer">{title}</a>
+                                {s.score.map(|score| view! {
+                                    <span class="source-score">{format!("{score:.2}")}</span>
+                                })}
+                                {(!s.snippet.is_empty()).then(|| view! {
+                                    <div class="source-snippet">{s.snippet}</div>
+                                })}
+                            </li>
+                        }
+                    }).collect_view()}
+                </ol>
+            </details>
+        }
+    })
+}
+
 /// Chat input form with textarea and send button.
 #[component]
 fn ChatInput() -> impl IntoView {
     let state = expect_context::<AppState>();
+    let locale = state.locale;
     let (input, set_input) = signal(String::new());
 
     let is_sending = move || state.is_streaming.get();
+    let is_locked = move || is_sending() || state.maintenance.get().is_some();
+    // The server refuses longer messages; caught here before sending.
+    let max_length = move || state.server_config.with(|c| c.as_ref().map(|c| c.max_message_length));
+    let too_long = move || {
+        let length = input.with(|text| text.trim().len());
+        max_length().filter(|max| length > *max).map(|max| (length, max))
+    };
+
+    // Shown once the next turn nears the model's context size.
+    let history = Memo::new(move |_| {
+        let cutoff = state.history_cutoff.get();
+        state.messages.with(|messages| History::new(messages, cutoff))
+    });
+    let (counts, set_counts) = signal(Counts::default());
+    let context_window = state.context_window;
+    Effect::new(move |_| {
+        let text = history.with(|history| history.text.clone());
+        if text.is_empty() || context_window.get().is_none() {
+            return;
+        }
+        spawn_local(async move {
+            match api::tokenize(&text).await {
+                Ok(tokens) => set_counts.update(|counts| counts.history = Some((text, tokens))),
+                Err(e) => log::warn!("Counting history tokens failed: {e}"),
+            }
+        });
+    });
+    Effect::new(move |_| {
+        let draft = input.get();
+        if draft.trim().is_empty() || context_window.get().is_none() {
+            return;
+        }
+        spawn_local(async move {
+            TimeoutFuture::new(COUNT_DEBOUNCE_MS).await;
+            if input.get_untracked() != draft {
+                return;
+            }
+            match api::tokenize(&draft).await {
+                Ok(tokens) => set_counts.update(|counts| counts.draft = Some((draft, tokens))),
+                Err(e) => log::warn!("Counting message tokens failed: {e}"),
+            }
+        });
+    });
+    let budget = move || {
+        let limit = context_window.get()?;
+        let budget = history.with(|history| {
+            input.with(|draft| {
+                counts.with(|counts| TokenBudget::new(history, draft, limit, counts))
+            })
+        });
+        budget.is_near().then_some(budget)
+    };
+
+    // Earlier prompts, newest first, and which of them the input shows while
+    // it shows one unedited.
+    let (recent, set_recent) = signal(Vec::<String>::new());
+    let (recalled, set_recalled) = signal(None::<usize>);
+    let (show_history, set_show_history) = signal(false);
+    spawn_local(async move {
+        match api::fetch_prompt_history("", RECALLED_PROMPTS).await {
+            Ok(prompts) => set_recent.set(prompts.into_iter().map(|p| p.prompt).collect()),
+            Err(e) => log::warn!("Loading prompt history failed: {e}"),
+        }
+    });
+    let recall = move |index: Option<usize>| {
+        let prompt = index.and_then(|i| recent.with_untracked(|recent| recent.get(i).cloned()));
+        set_recalled.set(index);
+        set_input.set(prompt.unwrap_or_default());
+    };
+
+    // The message the input opens by quoting, sent along so the server can
+    // tell the quote from what the user wrote.
+    let (quoted, set_quoted) = signal(None::<Uuid>);
+    let textarea = NodeRef::<leptos::html::Textarea>::new();
+    Effect::new(move |_| {
+        let Some(Quote { message_id, text }) = state.quote.get() else { return };
+        state.set_quote.set(None);
+        set_recalled.set(None);
+        set_input.update(|input| *input = format!("{text}\n\n{}", input.trim_start()));
+        set_quoted.set(Some(message_id));
+        if let Some(textarea) = textarea.get_untracked() {
+            let _ = textarea.focus();
+        }
+    });
+    // Quotes are of the conversation on screen only.
+    Effect::new(move |_| {
+        state.active_conversation.track();
+        set_quoted.set(None);
+    });
 
     let send = move || {
         let text = input.get().trim().to_string();
-        if text.is_empty() || is_sending() {
+        if text.is_empty() || is_locked() || too_long().is_some() {
             return;
         }
         set_input.set(String::new());
-        state.send_message(text);
+        set_recalled.set(None);
+        // Unless the quote was deleted from the input.
+        let quoted_message_id = quoted.get_untracked().filter(|_| text.starts_with('>'));
+        set_quoted.set(None);
+        // The server keeps no history of incognito prompts either.
+        if !state.ephemeral.get_untracked() {
+            set_recent.update(|recent| {
+                recent.retain(|prompt| *prompt != text);
+                recent.insert(0, text.clone());
+                recent.truncate(RECALLED_PROMPTS);
+            });
+        }
+        state.send_message(text, quoted_message_id);
     };
 
     let send_clone = send.clone();
     let on_keydown = move |ev: ev::KeyboardEvent| {
-        if ev.key() == "Enter" && !ev.shift_key() {
-            ev.prevent_default();
-            send_clone();
+        // The arrows only recall prompts from an empty input or one showing
+        // a recalled prompt, so they still move the caret while editing.
+        let recalling = recalled.get_untracked();
+        match ev.key().as_str() {
+            "Enter" if !ev.shift_key() => {
+                ev.prevent_default();
+                send_clone();
+            }
+            "ArrowUp" if recalling.is_some() || input.with_untracked(String::is_empty) => {
+                let next = recalling.map_or(0, |i| i + 1);
+                if next < recent.with_untracked(Vec::len) {
+                    ev.prevent_default();
+                    recall(Some(next));
+                }
+            }
+            "ArrowDown" if recalling.is_some() => {
+                ev.prevent_default();
+                recall(recalling.and_then(|i| i.checked_sub(1)));
+            }
+            _ => {}
         }
     };
 
@@ -119,25 +936,169 @@ fn ChatInput() -> impl IntoView {
 
     view! {
         <div class="input-area">
+            {move || budget().map(|budget| {
+                let text = if budget.is_over() { Text::TokenBudgetOver } else { Text::TokenBudgetNear };
+                let values = [
+                    ("total", budget.total().to_string()),
+                    ("limit", budget.limit.to_string()),
+                    ("history", budget.history.to_string()),
+                    ("message", budget.message.to_string()),
+                ];
+                let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+                view! {
+                    <div class="token-budget" class:over=budget.is_over() role="status">
+                        {fill(locale.get().tr(text), &values)}
+                    </div>
+                }
+            })}
+            {move || too_long().map(|(length, max)| {
+                let values = [("length", length.to_string()), ("max", max.to_string())];
+                let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+                view! {
+                    <div class="token-budget over" role="status">
+                        {fill(locale.get().tr(Text::MessageTooLong), &values)}
+                    </div>
+                }
+            })}
+            <Show when=move || show_history.get()>
+                <PromptHistoryPopover set_input set_open=set_show_history />
+            </Show>
             <div class="input-row">
+                <button
+                    class="prompt-history-btn"
+                    class:active=move || show_history.get()
+                    title=move || locale.get().tr(Text::PromptHistory)
+                    on:click=move |_| set_show_history.update(|open| *open = !*open)
+                    disabled=is_locked
+                >
+                    "🕘"
+                </button>
                 <textarea
+                    node_ref=textarea
                     rows="1"
-                    placeholder="Type a message… (Enter to send, Shift+Enter for newline)"
+                    placeholder=move || locale.get().tr(Text::InputPlaceholder)
                     prop:value=input
                     on:input=move |ev| {
+                        set_recalled.set(None);
                         set_input.set(event_target_value(&ev));
                     }
                     on:keydown=on_keydown
-                    disabled=is_sending
+                    disabled=is_locked
                 />
+                <VerbosityToggle />
                 <button
                     class="send-btn"
                     on:click=on_submit
-                    disabled=move || is_sending() || input.get().trim().is_empty()
+                    disabled=move || is_locked() || input.get().trim().is_empty() || too_long().is_some()
                 >
-                    {move || if is_sending() { "Sending…" } else { "Send" }}
+                    {move || locale.get().tr(if is_sending() { Text::Sending } else { Text::Send })}
                 </button>
             </div>
         </div>
     }
 }
+
+/// Concise / normal / detailed switch for the reply length. The choice is
+/// sent with each turn and kept by the server for the conversation.
+#[component]
+fn VerbosityToggle() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let options = [
+        (Verbosity::Concise, Text::Concise),
+        (Verbosity::Normal, Text::Normal),
+        (Verbosity::Detailed, Text::Detailed),
+    ];
+
+    view! {
+        <div class="verbosity-toggle" role="radiogroup" aria-label=move || locale.get().tr(Text::ReplyLength)>
+            {options.into_iter().map(|(verbosity, label)| view! {
+                <button
+                    role="radio"
+                    class:active=move || state.verbosity.get() == verbosity
+                    aria-checked=move || (state.verbosity.get() == verbosity).to_string()
+                    title=move || locale.get().tr(Text::ReplyLength)
+                    on:click=move |_| state.set_verbosity.set(verbosity)
+                >
+                    {move || locale.get().tr(label)}
+                </button>
+            }).collect_view()}
+        </div>
+    }
+}
+
+/// Searchable list of every prompt sent before; picking one puts it in the
+/// input.
+#[component]
+fn PromptHistoryPopover(
+    set_input: WriteSignal<String>,
+    set_open: WriteSignal<bool>,
+) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let (query, set_query) = signal(String::new());
+    let (prompts, set_prompts) = signal(Vec::<PromptHistoryEntry>::new());
+
+    Effect::new(move |_| {
+        let searched = query.get();
+        spawn_local(async move {
+            // Wait for typing to pause, as for token counts.
+            if !searched.is_empty() {
+                TimeoutFuture::new(COUNT_DEBOUNCE_MS).await;
+                if query.get_untracked() != searched {
+                    return;
+                }
+            }
+            match api::fetch_prompt_history(&searched, LISTED_PROMPTS).await {
+                Ok(found) => set_prompts.set(found),
+                Err(e) => log::warn!("Searching prompt history failed: {e}"),
+            }
+        });
+    });
+
+    view! {
+        <div class="prompt-history" role="dialog">
+            <input
+                class="prompt-history-search"
+                type="search"
+                autofocus
+                placeholder=move || locale.get().tr(Text::SearchPrompts)
+                prop:value=query
+                on:input=move |ev| set_query.set(event_target_value(&ev))
+                on:keydown=move |ev: ev::KeyboardEvent| {
+                    if ev.key() == "Escape" {
+                        set_open.set(false);
+                    }
+                }
+            />
+            {move || {
+                if prompts.with(Vec::is_empty) {
+                    view! {
+                        <div class="prompt-history-empty">
+                            {move || locale.get().tr(Text::NoPrompts)}
+                        </div>
+                    }.into_any()
+                } else {
+                    view! {
+                        <ul class="prompt-history-list">
+                            {prompts.get().into_iter().map(|entry| {
+                                let prompt = entry.prompt.clone();
+                                let pick = move |_| {
+                                    set_input.set(prompt.clone());
+                                    set_open.set(false);
+                                };
+                                view! {
+                                    <li>
+                                        <button class="prompt-history-item" on:click=pick>
+                                            {entry.prompt}
+                                        </button>
+                                    </li>
+                                }
+                            }).collect_view()}
+                        </ul>
+                    }.into_any()
+                }
+            }}
+        </div>
+    }
+}
@@ -0,0 +1,86 @@
+use leptos::prelude::*;
+
+use shared_models::markdown::{self, Block, Inline};
+
+/// An assistant reply rendered as markdown. While `streaming`, each chunk
+/// only renders the reply's last block again; the ones before it are done.
+#[component]
+pub fn Markdown(
+    #[prop(into)] text: Signal<String>,
+    #[prop(optional)] streaming: bool,
+) -> impl IntoView {
+    let blocks = Memo::new(move |_| text.with(|text| markdown::split(text)));
+    // Keyed by source, so finished blocks keep their elements.
+    let keyed = move || {
+        let blocks = blocks.get();
+        let last = blocks.len().saturating_sub(1);
+        blocks
+            .into_iter()
+            .enumerate()
+            .map(|(i, source)| (i, source, streaming && i == last))
+            .collect::<Vec<_>>()
+    };
+
+    view! {
+        <div class="markdown" class:streaming=streaming>
+            <For
+                each=keyed
+                key=|block| block.clone()
+                children=|(_, source, partial)| block_view(markdown::parse_block(&source, partial))
+            />
+        </div>
+    }
+}
+
+fn block_view(block: Block) -> AnyView {
+    match block {
+        Block::Paragraph(inlines) => view! { <p>{inline_views(inlines)}</p> }.into_any(),
+        Block::Heading(level, inlines) => {
+            let inlines = inline_views(inlines);
+            match level {
+                1 => view! { <h1>{inlines}</h1> }.into_any(),
+                2 => view! { <h2>{inlines}</h2> }.into_any(),
+                3 => view! { <h3>{inlines}</h3> }.into_any(),
+                4 => view! { <h4>{inlines}</h4> }.into_any(),
+                5 => view! { <h5>{inlines}</h5> }.into_any(),
+                _ => view! { <h6>{inlines}</h6> }.into_any(),
+            }
+        }
+        Block::Code { language, code } => view! {
+            <pre class="code-block" data-language=language><code>{code}</code></pre>
+        }
+        .into_any(),
+        Block::Quote(inlines) => {
+            view! { <blockquote>{inline_views(inlines)}</blockquote> }.into_any()
+        }
+        Block::List { ordered, start, items } => {
+            let items = items
+                .into_iter()
+                .map(|item| view! { <li>{inline_views(item)}</li> })
+                .collect_view();
+            if ordered {
+                view! { <ol start=start>{items}</ol> }.into_any()
+            } else {
+                view! { <ul>{items}</ul> }.into_any()
+            }
+        }
+        Block::Rule => view! { <hr /> }.into_any(),
+    }
+}
+
+fn inline_views(inlines: Vec<Inline>) -> AnyView {
+    inlines.into_iter().map(inline_view).collect_view().into_any()
+}
+
+fn inline_view(inline: Inline) -> AnyView {
+    match inline {
+        Inline::Text(text) => text.into_any(),
+        Inline::Code(code) => view! { <code>{code}</code> }.into_any(),
+        Inline::Strong(inner) => view! { <strong>{inline_views(inner)}</strong> }.into_any(),
+        Inline::Emphasis(inner) => view! { <em>{inline_views(inner)}</em> }.into_any(),
+        Inline::Link { text, url } => view! {
+            <a href=url target="_blank" rel="noopener noreferrer">{inline_views(text)}</a>
+        }
+        .into_any(),
+    }
+}
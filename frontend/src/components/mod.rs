@@ -1,2 +1,8 @@
+pub mod admin;
+pub mod artifacts;
 pub mod chat;
+pub mod markdown;
+pub mod retrieval;
 pub mod sidebar;
+pub mod starred;
+pub mod toasts;
@@ -0,0 +1,122 @@
+use leptos::ev::MouseEvent;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use uuid::Uuid;
+
+use crate::api;
+use crate::i18n::Text;
+use crate::models::RetrievedChunk;
+use crate::state::AppState;
+
+/// Opens a drawer under a reply listing the chunks retrieved for it, with
+/// their scores and ranks: what the model was given from the indexed
+/// collections. Each chunk can be marked relevant or not.
+#[component]
+pub fn RetrievalDrawer(message_id: Uuid) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let chunks = RwSignal::new(None::<Vec<RetrievedChunk>>);
+    let toggle = {
+        let state = state.clone();
+        move |_| {
+            if chunks.get_untracked().is_some() {
+                chunks.set(None);
+                return;
+            }
+            let state = state.clone();
+            spawn_local(async move {
+                match api::fetch_retrieval(message_id).await {
+                    Ok(found) => chunks.set(Some(found)),
+                    Err(e) => {
+                        log::error!("Failed to fetch the retrieved chunks: {e}");
+                        state.notify_error(e, None);
+                    }
+                }
+            });
+        }
+    };
+    // Choosing the verdict a chunk already has takes it back.
+    let rate = move |chunk_id: Uuid, current: Option<bool>, verdict: bool| {
+        let state = state.clone();
+        let relevant = (current != Some(verdict)).then_some(verdict);
+        spawn_local(async move {
+            match api::rate_retrieval(message_id, chunk_id, relevant).await {
+                Ok(found) => chunks.set(Some(found)),
+                Err(e) => {
+                    log::error!("Failed to rate the retrieved chunk: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    };
+
+    view! {
+        <button
+            class="retrieval-btn"
+            class:active=move || chunks.with(Option::is_some)
+            on:click=toggle
+        >
+            {move || locale.get().tr(
+                if chunks.with(Option::is_some) { Text::HideRetrieval } else { Text::ShowRetrieval },
+            )}
+        </button>
+        {move || chunks.get().map(|chunks| view! {
+            <div class="retrieval-drawer">
+                {chunks.is_empty().then(|| view! {
+                    <div class="retrieval-empty">{move || locale.get().tr(Text::NothingRetrieved)}</div>
+                })}
+                {chunks.into_iter().map(|chunk| {
+                    let (id, current) = (chunk.chunk_id, chunk.relevant);
+                    // The buttons sit in the summary: clicking them should
+                    // not fold the chunk as well.
+                    let rate_up = {
+                        let rate = rate.clone();
+                        move |ev: MouseEvent| {
+                            ev.prevent_default();
+                            rate(id, current, true)
+                        }
+                    };
+                    let rate = rate.clone();
+                    let rate_down = move |ev: MouseEvent| {
+                        ev.prevent_default();
+                        rate(id, current, false)
+                    };
+                    let ranks = [("vec", chunk.vector_rank), ("text", chunk.text_rank)]
+                        .into_iter()
+                        .filter_map(|(name, rank)| rank.map(|r| format!("{name} #{r}")))
+                        .collect::<Vec<_>>()
+                        .join(" · ");
+                    view! {
+                        <details class="retrieved-chunk">
+                            <summary>
+                                <span class="retrieved-path">
+                                    {format!("{}:{}-{}", chunk.path, chunk.start_line, chunk.end_line)}
+                                </span>
+                                <span class="retrieved-collection">{chunk.collection}</span>
+                                <span class="retrieved-ranks">{ranks}</span>
+                                <span class="retrieved-score">{format!("{:.3}", chunk.score)}</span>
+                                <button
+                                    class="retrieval-verdict"
+                                    class:active=current == Some(true)
+                                    title=move || locale.get().tr(Text::RetrievedRelevant)
+                                    on:click=rate_up
+                                >
+                                    "👍"
+                                </button>
+                                <button
+                                    class="retrieval-verdict"
+                                    class:active=current == Some(false)
+                                    title=move || locale.get().tr(Text::RetrievedIrrelevant)
+                                    on:click=rate_down
+                                >
+                                    "👎"
+                                </button>
+                            </summary>
+                            <pre class="artifact-code"><code>{chunk.content}</code></pre>
+                        </details>
+                    }
+                }).collect_view()}
+            </div>
+        })}
+    }
+}
@@ -1,16 +1,118 @@
+use gloo_timers::future::TimeoutFuture;
+use leptos::ev;
 use leptos::prelude::*;
+use leptos::task::spawn_local;
+use uuid::Uuid;
 
+use crate::api;
+use crate::i18n::{Locale, Text};
+use crate::models::{Conversation, ConversationSort, Verbosity};
 use crate::state::AppState;
 
+/// Filters at least this long also search message content on the backend.
+const SERVER_SEARCH_MIN_CHARS: usize = 3;
+/// Wait for typing to pause before hitting the backend.
+const SEARCH_DEBOUNCE_MS: u32 = 250;
+/// Offered in a conversation's icon and color picker.
+const ICONS: [&str; 8] = ["💬", "💡", "📌", "🛠️", "📚", "✈️", "🎯", "❤️"];
+const COLORS: [&str; 8] =
+    ["#e5484d", "#f76b15", "#ffc53d", "#46a758", "#0090ff", "#8e4ec6", "#d6409f", "#8d8d8d"];
+
 /// Sidebar showing conversation list and "New Chat" button.
 #[component]
 pub fn Sidebar() -> impl IntoView {
     let state = expect_context::<AppState>();
+    let (locale, set_locale) = (state.locale, state.set_locale);
+    let (filter, set_filter) = signal(String::new());
+    // Backend matches for the current filter, including content-only matches.
+    let (server_matches, set_server_matches) = signal(Vec::<Conversation>::new());
+    // The conversation whose icon and color picker is open.
+    let (editing, set_editing) = signal(None::<Uuid>);
+
+    let on_toggle_theme = {
+        let state = state.clone();
+        move |_| state.toggle_theme()
+    };
+    let is_light = {
+        let state = state.clone();
+        move || state.is_light_theme()
+    };
 
-    let on_new = move |_| {
-        state.set_active_conversation.set(None);
-        state.set_messages.set(Vec::new());
-        state.set_streaming_text.set(None);
+    let on_new = {
+        let state = state.clone();
+        move |_| {
+            state.set_active_conversation.set(None);
+            state.set_sidebar_open.set(false);
+            state.set_show_starred.set(false);
+            state.set_show_admin.set(false);
+            state.set_failed_turn.set(None);
+            state.set_ephemeral.set(false);
+            state.set_verbosity.set(Verbosity::default());
+            state.set_stateless.set(false);
+            state.set_preset.set(None);
+            state.set_messages.set(Vec::new());
+            state.set_summary.set(None);
+            state.set_earlier.set(None);
+            state.set_artifacts.set(Vec::new());
+            state.set_open_artifact.set(None);
+            state.set_streaming_text.set(None);
+            state.set_conversation_cost.set(None);
+        }
+    };
+
+    let show_admin = state.show_admin;
+    let on_admin = {
+        let state = state.clone();
+        move |_| state.open_admin()
+    };
+
+    let show_starred = state.show_starred;
+    let on_starred = {
+        let state = state.clone();
+        move |_| state.open_starred()
+    };
+
+    let on_filter = move |ev| {
+        let query = event_target_value(&ev);
+        set_filter.set(query.clone());
+        set_server_matches.set(Vec::new());
+        if query.trim().chars().count() < SERVER_SEARCH_MIN_CHARS {
+            return;
+        }
+        spawn_local(async move {
+            TimeoutFuture::new(SEARCH_DEBOUNCE_MS).await;
+            if filter.get_untracked() != query {
+                return;
+            }
+            match api::search_conversations(query.trim()).await {
+                // Drop responses for a filter the user has already changed.
+                Ok(found) if filter.get_untracked() == query => set_server_matches.set(found),
+                Ok(_) => {}
+                Err(e) => log::error!("Conversation search failed: {e}"),
+            }
+        });
+    };
+
+    let visible = {
+        let state = state.clone();
+        Memo::new(move |_| {
+            let convos = state.conversations.get();
+            let query = filter.get();
+            let query = query.trim();
+            if query.is_empty() {
+                return convos;
+            }
+            let mut shown: Vec<Conversation> = convos
+                .into_iter()
+                .filter(|c| find_ignore_case(&c.title, query).is_some())
+                .collect();
+            for c in server_matches.get() {
+                if !shown.iter().any(|s| s.id == c.id) {
+                    shown.push(c);
+                }
+            }
+            shown
+        })
     };
 
     view! {
@@ -18,44 +120,108 @@ pub fn Sidebar() -> impl IntoView {
             <div class="sidebar-header">
                 <h2>"Rust AI Chat"</h2>
                 <button class="new-chat-btn" on:click=on_new>
-                    "+ New Chat"
+                    {move || locale.get().tr(Text::NewChat)}
+                </button>
+                <button
+                    class="starred-btn"
+                    class:active=move || show_starred.get()
+                    on:click=on_starred
+                >
+                    {move || locale.get().tr(Text::Starred)}
+                </button>
+                <input
+                    class="conversation-filter"
+                    type="search"
+                    placeholder=move || locale.get().tr(Text::SearchConversations)
+                    prop:value=filter
+                    on:input=on_filter
+                />
+                <SortPicker />
+                <select
+                    class="locale-picker"
+                    on:change=move |ev| {
+                        if let Some(l) = Locale::from_code(&event_target_value(&ev)) {
+                            set_locale.set(l);
+                        }
+                    }
+                >
+                    {Locale::ALL.into_iter().map(|l| view! {
+                        <option value=l.code() selected=move || locale.get() == l>{l.label()}</option>
+                    }).collect_view()}
+                </select>
+                <button class="theme-toggle" on:click=on_toggle_theme>
+                    {move || {
+                        let text = if is_light() { Text::DarkTheme } else { Text::LightTheme };
+                        locale.get().tr(text)
+                    }}
                 </button>
             </div>
             <div class="conversation-list">
                 {move || {
-                    let convos = state.conversations.get();
-                    if convos.is_empty() {
+                    if visible.get().is_empty() {
+                        let empty = if filter.get().trim().is_empty() {
+                            Text::NoConversations
+                        } else {
+                            Text::NoMatchingConversations
+                        };
                         view! {
                             <div style="padding:1rem;color:var(--text-secondary);font-size:0.85rem">
-                                "No conversations yet"
+                                {move || locale.get().tr(empty)}
                             </div>
                         }.into_any()
                     } else {
                         let state = state.clone();
                         view! {
                             <For
-                                each=move || state.conversations.get()
-                                key=|c| c.id.clone()
+                                each=move || visible.get()
+                                key=|c| (c.id, c.icon.clone(), c.color.clone())
                                 let:conv
                             >
                                 {
                                     let state = state.clone();
-                                    let id = conv.id.clone();
-                                    let title = conv.title.clone()
-                                        .unwrap_or_else(|| "Untitled chat".to_string());
-                                    let id_click = id.clone();
-                                    let id_active = id.clone();
+                                    let id = conv.id;
+                                    let title = conv.title.clone();
+                                    let summary = conv.summary.clone();
+                                    let on_edit = move |ev: ev::MouseEvent| {
+                                        ev.stop_propagation();
+                                        let open = editing.get_untracked() == Some(id);
+                                        set_editing.set((!open).then_some(id));
+                                    };
+                                    let (icon, color) = (conv.icon.clone(), conv.color.clone());
+                                    let is_editing = move || editing.get() == Some(id);
                                     view! {
                                         <div
                                             class="conversation-item"
+                                            class:colored=color.is_some()
+                                            style:border-left-color=color
                                             class:active=move || {
-                                                state.active_conversation.get().as_deref() == Some(id_active.as_str())
-                                            }
-                                            on:click=move |_| {
-                                                state.select_conversation(id_click.clone());
+                                                state.active_conversation.get() == Some(id)
                                             }
+                                            on:click=move |_| state.select_conversation(id)
                                         >
-                                            {title}
+                                            <div class="conversation-title">
+                                                {icon.map(|icon| view! {
+                                                    <span class="conversation-icon">{icon}</span>
+                                                })}
+                                                {move || {
+                                                    let untitled = locale.get().tr(Text::UntitledChat);
+                                                    let title = if title.is_empty() { untitled } else { &title };
+                                                    highlight(title, filter.get().trim())
+                                                }}
+                                                <button
+                                                    class="appearance-btn"
+                                                    title=move || locale.get().tr(Text::ConversationAppearance)
+                                                    on:click=on_edit
+                                                >
+                                                    "🎨"
+                                                </button>
+                                            </div>
+                                            <Show when=is_editing>
+                                                <AppearancePicker conv=conv.clone() />
+                                            </Show>
+                                            {summary.map(|summary| view! {
+                                                <div class="conversation-summary">{summary}</div>
+                                            })}
                                         </div>
                                     }
                                 }
@@ -64,7 +230,159 @@ pub fn Sidebar() -> impl IntoView {
                     }
                 }}
             </div>
+            <div class="sidebar-footer">
+                <a class="export-link" href=format!("{}/api/export/all", api::api_base()) download>
+                    {move || locale.get().tr(Text::DownloadData)}
+                </a>
+                <button class="admin-link" class:active=move || show_admin.get() on:click=on_admin>
+                    {move || locale.get().tr(Text::Admin)}
+                </button>
+            </div>
         </aside>
     }
 }
 
+/// Picks the order of the conversation list, saved in the profile.
+#[component]
+fn SortPicker() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let (locale, sort) = (state.locale, state.conversation_sort);
+    let on_change = move |ev| {
+        if let Ok(picked) = ConversationSort::try_from(event_target_value(&ev)) {
+            state.sort_conversations(picked);
+        }
+    };
+
+    view! {
+        <select
+            class="sort-picker"
+            aria-label=move || locale.get().tr(Text::SortConversations)
+            on:change=on_change
+        >
+            {ConversationSort::ALL.into_iter().map(|choice| {
+                let label = match choice {
+                    ConversationSort::Updated => Text::SortUpdated,
+                    ConversationSort::Created => Text::SortCreated,
+                    ConversationSort::Title => Text::SortTitle,
+                    ConversationSort::MessageCount => Text::SortMessageCount,
+                };
+                view! {
+                    <option
+                        value=choice.as_str()
+                        selected=move || sort.get().unwrap_or_default() == choice
+                    >
+                        {move || locale.get().tr(label)}
+                    </option>
+                }
+            }).collect_view()}
+        </select>
+    }
+}
+
+/// Slides the sidebar in or out. Only shown on screens too narrow to keep the
+/// sidebar next to the page.
+#[component]
+pub fn SidebarToggle() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let (locale, open) = (state.locale, state.sidebar_open);
+    let toggle = move |_| state.toggle_sidebar();
+
+    view! {
+        <button
+            class="sidebar-toggle"
+            aria-label=move || locale.get().tr(Text::ToggleSidebar)
+            aria-expanded=move || open.get().to_string()
+            on:click=toggle
+        >
+            "☰"
+        </button>
+    }
+}
+
+/// Preset icons and colors for a conversation, plus a field for any other
+/// emoji. Each choice is saved right away.
+#[component]
+fn AppearancePicker(conv: Conversation) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let Conversation { id, icon, color, .. } = conv;
+    let save = move |icon: Option<String>, color: Option<String>| {
+        state.set_conversation_appearance(id, icon, color);
+    };
+
+    view! {
+        <div class="appearance-picker" on:click=|ev| ev.stop_propagation()>
+            <div class="appearance-icons">
+                {ICONS.into_iter().map(|choice| {
+                    let (save, color) = (save.clone(), color.clone());
+                    view! {
+                        <button
+                            class:selected=icon.as_deref() == Some(choice)
+                            on:click=move |_| save(Some(choice.to_string()), color.clone())
+                        >
+                            {choice}
+                        </button>
+                    }
+                }).collect_view()}
+                <input
+                    type="text"
+                    maxlength="16"
+                    placeholder=move || locale.get().tr(Text::OtherIcon)
+                    on:change={
+                        let (save, color) = (save.clone(), color.clone());
+                        move |ev| save(Some(event_target_value(&ev)), color.clone())
+                    }
+                />
+            </div>
+            <div class="appearance-colors">
+                {COLORS.into_iter().map(|choice| {
+                    let (save, icon) = (save.clone(), icon.clone());
+                    view! {
+                        <button
+                            class="swatch"
+                            class:selected=color.as_deref() == Some(choice)
+                            style:background=choice
+                            on:click=move |_| save(icon.clone(), Some(choice.to_string()))
+                        />
+                    }
+                }).collect_view()}
+                <button class="appearance-clear" on:click=move |_| save(None, None)>
+                    {move || locale.get().tr(Text::ClearAppearance)}
+                </button>
+            </div>
+        </div>
+    }
+}
+
+/// Renders `title` with the first case-insensitive match of `query` marked.
+fn highlight(title: &str, query: &str) -> AnyView {
+    match find_ignore_case(title, query) {
+        Some((start, end)) if !query.is_empty() => view! {
+            {title[..start].to_string()}
+            <mark>{title[start..end].to_string()}</mark>
+            {title[end..].to_string()}
+        }
+        .into_any(),
+        _ => title.to_string().into_any(),
+    }
+}
+
+/// Byte range of the first case-insensitive occurrence of `needle` in
+/// `haystack`. Compares per character, so it is safe for non-ASCII titles
+/// whose lowercase form has a different byte length.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<(usize, usize)> {
+    if needle.is_empty() {
+        return Some((0, 0));
+    }
+    haystack.char_indices().find_map(|(start, _)| {
+        let mut rest = haystack[start..].char_indices();
+        for n in needle.chars() {
+            let (_, h) = rest.next()?;
+            if !h.to_lowercase().eq(n.to_lowercase()) {
+                return None;
+            }
+        }
+        let end = rest.next().map_or(haystack.len(), |(i, _)| start + i);
+        Some((start, end))
+    })
+}
@@ -0,0 +1,101 @@
+use leptos::prelude::*;
+use uuid::Uuid;
+
+use crate::components::sidebar::SidebarToggle;
+use crate::i18n::Text;
+use crate::models::{MessageRole, StarredMessage};
+use crate::state::AppState;
+use crate::time;
+
+/// Starred messages from every conversation, most recently starred first.
+/// Each links back to its conversation.
+#[component]
+pub fn StarredView() -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+
+    view! {
+        <main class="chat-area">
+            <div class="chat-header">
+                <SidebarToggle />
+                {move || locale.get().tr(Text::StarredTitle)}
+            </div>
+            <div class="messages-container">
+                {move || {
+                    if state.starred.get().is_empty() {
+                        view! {
+                            <div class="empty-state">{move || locale.get().tr(Text::NoStarred)}</div>
+                        }.into_any()
+                    } else {
+                        view! {
+                            <For
+                                each=move || state.starred.get()
+                                key=|s| s.message.id
+                                children=move |s| view! { <StarredItem starred=s /> }
+                            />
+                        }.into_any()
+                    }
+                }}
+            </div>
+        </main>
+    }
+}
+
+#[component]
+fn StarredItem(starred: StarredMessage) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let message = starred.message;
+    let is_user = message.role == MessageRole::User;
+    let role = if is_user { Text::RoleUser } else { Text::RoleAssistant };
+    let css_class = if is_user { "message user" } else { "message assistant" };
+    let open = {
+        let (state, id) = (state.clone(), message.conversation_id);
+        move |_| state.select_conversation(id)
+    };
+    let ms = time::millis(&message.created_at);
+    let sent_at = view! {
+        <time
+            class="message-time"
+            datetime=message.created_at.to_rfc3339()
+            title=move || time::absolute(ms, locale.get())
+        >
+            {move || time::relative(ms, state.now.get(), locale.get())}
+        </time>
+    };
+
+    view! {
+        <div class="starred-item">
+            <button class="starred-conversation" on:click=open>
+                {starred.conversation_title}
+            </button>
+            <div class=css_class>
+                <div class="role-label">{move || locale.get().tr(role)}</div>
+                <div>{message.content}</div>
+                <div class="message-actions">
+                    <StarButton message_id=message.id starred=true />
+                </div>
+                {sent_at}
+            </div>
+        </div>
+    }
+}
+
+/// ★ / ☆ toggle for a stored message.
+#[component]
+pub fn StarButton(message_id: Uuid, starred: bool) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let toggle = move |_| state.set_message_starred(message_id, !starred);
+
+    view! {
+        <button
+            class="star-btn"
+            class:starred=starred
+            title=move || locale.get().tr(if starred { Text::Unstar } else { Text::Star })
+            on:click=toggle
+        >
+            {if starred { "★" } else { "☆" }}
+        </button>
+    }
+}
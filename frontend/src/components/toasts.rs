@@ -0,0 +1,53 @@
+use leptos::prelude::*;
+
+use crate::i18n::Text;
+use crate::state::{AppState, Notification, NotificationKind};
+
+/// Stack of notifications in the corner of the screen.
+#[component]
+pub fn Toasts() -> impl IntoView {
+    let state = expect_context::<AppState>();
+
+    view! {
+        <div class="toasts">
+            <For
+                each=move || state.notifications.get()
+                key=|n| n.id
+                children=move |n| view! { <Toast notification=n /> }
+            />
+        </div>
+    }
+}
+
+#[component]
+fn Toast(notification: Notification) -> impl IntoView {
+    let state = expect_context::<AppState>();
+    let locale = state.locale;
+    let id = notification.id;
+    let class = match notification.kind {
+        NotificationKind::Error => "toast toast-error",
+        NotificationKind::Info => "toast",
+    };
+    let retry = notification.retry.is_some().then(|| {
+        let state = state.clone();
+        view! {
+            <button on:click=move |_| state.retry(id)>
+                {move || locale.get().tr(Text::Retry)}
+            </button>
+        }
+    });
+
+    view! {
+        <div class=class role="alert">
+            <span class="toast-message">{notification.message}</span>
+            {retry}
+            <button
+                class="toast-dismiss"
+                title=move || locale.get().tr(Text::Dismiss)
+                on:click=move |_| state.dismiss(id)
+            >
+                "×"
+            </button>
+        </div>
+    }
+}
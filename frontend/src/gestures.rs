@@ -0,0 +1,50 @@
+use leptos::ev;
+use leptos::prelude::*;
+
+use crate::state::AppState;
+
+/// Swipes that open the sidebar must start this close to the left edge, so
+/// horizontal scrolling elsewhere (e.g. wide code blocks) is left alone.
+const EDGE_PX: f64 = 24.0;
+/// How far a finger must travel sideways to count as a swipe.
+const MIN_SWIPE_PX: f64 = 60.0;
+
+/// Touch handlers for the app's root: swiping right from the left edge opens
+/// the sidebar, and swiping left anywhere closes it.
+pub fn sidebar_swipe(
+    state: AppState,
+) -> (impl Fn(ev::TouchEvent) + Clone + 'static, impl Fn(ev::TouchEvent) + Clone + 'static) {
+    let start = StoredValue::new(None::<(f64, f64)>);
+    let on_start = move |ev: ev::TouchEvent| start.set_value(touch_point(&ev));
+    let on_end = move |ev: ev::TouchEvent| {
+        let (Some(from), Some(to)) = (start.get_value(), touch_point(&ev)) else { return };
+        start.set_value(None);
+        let open = state.sidebar_open.get_untracked();
+        if let Some(open) = swipe_result(from, to, open) {
+            state.set_sidebar_open.set(open);
+        }
+    };
+    (on_start, on_end)
+}
+
+/// Where the touch that started or ended `ev` is.
+fn touch_point(ev: &ev::TouchEvent) -> Option<(f64, f64)> {
+    let touch = ev.changed_touches().get(0)?;
+    Some((f64::from(touch.client_x()), f64::from(touch.client_y())))
+}
+
+/// Whether a swipe from `from` to `to` opens or closes the sidebar, if it
+/// does either. Mostly vertical movement is scrolling, not a swipe.
+fn swipe_result(from: (f64, f64), to: (f64, f64), open: bool) -> Option<bool> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    if dx.abs() < MIN_SWIPE_PX || dy.abs() > dx.abs() {
+        return None;
+    }
+    if !open && dx > 0.0 && from.0 <= EDGE_PX {
+        Some(true)
+    } else if open && dx < 0.0 {
+        Some(false)
+    } else {
+        None
+    }
+}
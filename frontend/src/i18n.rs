@@ -0,0 +1,442 @@
+//! UI strings in every supported language.
+//!
+//! Components look strings up with [`Locale::tr`] inside a reactive closure
+//! (`move || state.locale.get().tr(Text::Send)`), so switching the language
+//! re-renders them in place. Strings with `{placeholders}` are filled in with
+//! [`fill`].
+
+use js_sys::Reflect;
+
+/// A language the UI can be shown in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    Es,
+}
+
+/// Every translatable UI string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Text {
+    NewChat,
+    SearchConversations,
+    NoConversations,
+    NoMatchingConversations,
+    UntitledChat,
+    ConversationAppearance,
+    OtherIcon,
+    ClearAppearance,
+    NewConversation,
+    Conversation,
+    IncognitoConversation,
+    IncognitoToggle,
+    StatelessToggle,
+    StatelessHint,
+    EmptyState,
+    /// `{position}`
+    QueuePosition,
+    HistoryCutoff,
+    RoleUser,
+    RoleAssistant,
+    ToolCall,
+    ToolResult,
+    Regenerate,
+    Regenerating,
+    ShowChanges,
+    HideChanges,
+    /// `{error}`
+    GenerationFailed,
+    /// `{count}`
+    Sources,
+    FollowUps,
+    InputPlaceholder,
+    /// `{total}`, `{limit}`, `{history}`, `{message}`
+    TokenBudgetNear,
+    /// `{total}`, `{limit}`, `{history}`, `{message}`
+    TokenBudgetOver,
+    /// `{length}`, `{max}`
+    MessageTooLong,
+    Send,
+    Sending,
+    PromptHistory,
+    SearchPrompts,
+    NoPrompts,
+    ReplyLength,
+    Copy,
+    Copied,
+    /// Actions of the artifact side panel.
+    DownloadArtifact,
+    CloseArtifact,
+    /// The drawer of chunks retrieved for a reply.
+    ShowRetrieval,
+    HideRetrieval,
+    NothingRetrieved,
+    RetrievedRelevant,
+    RetrievedIrrelevant,
+    Quote,
+    QuoteFromUser,
+    QuoteFromAssistant,
+    Concise,
+    Normal,
+    Detailed,
+    /// `{model}`
+    ModelMissing,
+    DownloadAndRetry,
+    /// `{model}`
+    ModelPulled,
+    Retry,
+    Dismiss,
+    Maintenance,
+    AppUpdated,
+    Reload,
+    /// Tool call progress of the streaming reply; `{tool}` is the tool's name.
+    ToolRunning,
+    ToolDone,
+    ToolFailed,
+    ToolTimedOut,
+    Reconnecting,
+    /// Label of the conversation order picker.
+    SortConversations,
+    SortUpdated,
+    SortCreated,
+    SortTitle,
+    SortMessageCount,
+    ToggleSidebar,
+    Offline,
+    OfflineQueued,
+    OfflineReadOnly,
+    JustNow,
+    /// `{count}`
+    MinutesAgo,
+    /// `{count}`
+    HoursAgo,
+    /// `{count}`
+    DaysAgo,
+    Today,
+    Yesterday,
+    /// `{prompt}`, `{completion}`
+    TokenCounts,
+    FinishLength,
+    FinishCancelled,
+    FinishError,
+    LightTheme,
+    DarkTheme,
+    /// `{reason}`
+    ModerationBlocked,
+    Busy,
+    /// Label of the preset picker.
+    Assistant,
+    DefaultAssistant,
+    EarlierSummary,
+    /// `{count}`
+    SummarizedMessages,
+    LoadingEarlier,
+    Admin,
+    AdminTitle,
+    SystemStatus,
+    Refresh,
+    OllamaUnreachable,
+    LoadedModels,
+    NoLoadedModels,
+    /// `{size}`, `{vram}`
+    ModelMemory,
+    /// `{used}`, `{total}`
+    HostMemory,
+    /// `{load}`, `{cpus}`
+    HostLoad,
+    /// `{model}`
+    ModelLoaded,
+    /// `{model}`
+    ModelCold,
+    DownloadData,
+    Starred,
+    StarredTitle,
+    NoStarred,
+    Star,
+    Unstar,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Es];
+
+    /// The browser's preferred language when it is supported, else English.
+    pub fn detect() -> Self {
+        web_sys::window()
+            .and_then(|w| Reflect::get(&w, &"navigator".into()).ok())
+            .and_then(|nav| Reflect::get(&nav, &"language".into()).ok())
+            .and_then(|lang| lang.as_string())
+            .and_then(|lang| Self::from_code(&lang))
+            .unwrap_or(Locale::En)
+    }
+
+    /// Matches a BCP 47 tag such as `es-ES` by its primary language.
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.split('-').next()?.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        }
+    }
+
+    /// The language's name in itself, for the language picker.
+    pub fn label(self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::Es => "Español",
+        }
+    }
+
+    pub fn tr(self, text: Text) -> &'static str {
+        match self {
+            Locale::En => english(text),
+            Locale::Es => spanish(text),
+        }
+    }
+}
+
+/// Replaces each `{name}` in `template` with its value.
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |out, (name, value)| {
+        out.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+fn english(text: Text) -> &'static str {
+    match text {
+        Text::NewChat => "+ New Chat",
+        Text::SearchConversations => "Search conversations",
+        Text::NoConversations => "No conversations yet",
+        Text::NoMatchingConversations => "No matching conversations",
+        Text::UntitledChat => "Untitled chat",
+        Text::ConversationAppearance => "Icon and color",
+        Text::OtherIcon => "Other emoji",
+        Text::ClearAppearance => "Clear",
+        Text::NewConversation => "New conversation",
+        Text::Conversation => "Conversation",
+        Text::IncognitoConversation => "Incognito conversation",
+        Text::IncognitoToggle => "Incognito (not saved)",
+        Text::StatelessToggle => "No history",
+        Text::StatelessHint => "Answer each message on its own, without the earlier ones",
+        Text::EmptyState => "Send a message to start chatting",
+        Text::QueuePosition => "Waiting for the model — position {position} in queue",
+        Text::HistoryCutoff => "Older messages not sent to the model",
+        Text::RoleUser => "user",
+        Text::RoleAssistant => "assistant",
+        Text::ToolCall => "Called {tool}",
+        Text::ToolResult => "{tool} returned",
+        Text::Regenerate => "↻ Regenerate",
+        Text::Regenerating => "Regenerating…",
+        Text::ShowChanges => "Show changes",
+        Text::HideChanges => "Hide changes",
+        Text::GenerationFailed => "Generation failed: {error}",
+        Text::Sources => "Sources ({count})",
+        Text::FollowUps => "Suggested follow-ups",
+        Text::InputPlaceholder => "Type a message… (Enter to send, Shift+Enter for newline)",
+        Text::TokenBudgetNear => "≈{total} of {limit} tokens: {history} of history + {message} for this message",
+        Text::TokenBudgetOver => "≈{total} of {limit} tokens: {history} of history + {message} for this message. The model will not see all of it.",
+        Text::MessageTooLong => "This message is {length} bytes long; the server accepts up to {max}.",
+        Text::Send => "Send",
+        Text::Sending => "Sending…",
+        Text::PromptHistory => "Earlier prompts (↑/↓ in an empty input)",
+        Text::SearchPrompts => "Search your prompts…",
+        Text::NoPrompts => "No matching prompts",
+        Text::ReplyLength => "Reply length",
+        Text::Copy => "Copy",
+        Text::Copied => "Copied",
+        Text::DownloadArtifact => "Download",
+        Text::CloseArtifact => "Close",
+        Text::ShowRetrieval => "Retrieved context",
+        Text::HideRetrieval => "Hide retrieved context",
+        Text::NothingRetrieved => "Nothing was retrieved for this reply.",
+        Text::RetrievedRelevant => "Relevant to the question",
+        Text::RetrievedIrrelevant => "Not relevant to the question",
+        Text::Quote => "Quote in a reply",
+        Text::QuoteFromUser => "You wrote:",
+        Text::QuoteFromAssistant => "The assistant wrote:",
+        Text::Concise => "Concise",
+        Text::Normal => "Normal",
+        Text::Detailed => "Detailed",
+        Text::ModelMissing => "The model '{model}' is not installed in Ollama.",
+        Text::DownloadAndRetry => "Download and retry",
+        Text::ModelPulled => "Downloaded '{model}'.",
+        Text::Retry => "Retry",
+        Text::Dismiss => "Dismiss",
+        Text::Reconnecting => "Connection lost. Reconnecting…",
+        Text::SortConversations => "Sort conversations",
+        Text::SortUpdated => "Recently updated",
+        Text::SortCreated => "Recently created",
+        Text::SortTitle => "Title (A–Z)",
+        Text::SortMessageCount => "Most messages",
+        Text::ToggleSidebar => "Show or hide conversations",
+        Text::Offline => "You are offline: showing saved history. Messages you send go out once you are back online.",
+        Text::OfflineQueued => "Waiting for a connection to send this message",
+        Text::OfflineReadOnly => "Not available offline: history is read-only until you reconnect.",
+        Text::JustNow => "just now",
+        Text::MinutesAgo => "{count} min ago",
+        Text::HoursAgo => "{count} h ago",
+        Text::DaysAgo => "{count} d ago",
+        Text::Today => "Today",
+        Text::Yesterday => "Yesterday",
+        Text::Maintenance => "Down for maintenance: chat is paused, but your history can still be read.",
+        Text::AppUpdated => "The app was updated. Reload to keep chatting with the new version.",
+        Text::Reload => "Reload",
+        Text::ToolRunning => "Calling {tool}…",
+        Text::ToolDone => "{tool} answered",
+        Text::ToolFailed => "{tool} failed",
+        Text::ToolTimedOut => "{tool} did not answer in time",
+        Text::TokenCounts => "{prompt} → {completion} tokens",
+        Text::FinishLength => "cut off at the token limit",
+        Text::FinishCancelled => "cancelled",
+        Text::FinishError => "interrupted by an error",
+        Text::LightTheme => "☀ Light theme",
+        Text::DarkTheme => "☾ Dark theme",
+        Text::ModerationBlocked => "Your message was not sent: {reason}.",
+        Text::Assistant => "Assistant",
+        Text::DefaultAssistant => "Default assistant",
+        Text::EarlierSummary => "Earlier conversation summary",
+        Text::SummarizedMessages => "{count} messages",
+        Text::LoadingEarlier => "Loading earlier messages…",
+        Text::Admin => "⚙ Admin",
+        Text::AdminTitle => "Administration",
+        Text::SystemStatus => "System status",
+        Text::Refresh => "Refresh",
+        Text::OllamaUnreachable => "Ollama is not reachable.",
+        Text::LoadedModels => "Loaded models",
+        Text::NoLoadedModels => "No model is loaded; the next turn loads one first.",
+        Text::ModelMemory => "{size}, {vram} in VRAM",
+        Text::HostMemory => "RAM: {used} of {total}",
+        Text::HostLoad => "CPU load: {load} on {cpus} CPUs",
+        Text::ModelLoaded => "{model} is loaded",
+        Text::ModelCold => "{model} is cold: the next reply waits for it to load",
+        Text::Busy => "A reply in this conversation is still streaming; send again once it ends.",
+        Text::DownloadData => "⤓ Download my data",
+        Text::Starred => "★ Starred",
+        Text::StarredTitle => "Starred messages",
+        Text::NoStarred => "Star a message to collect it here",
+        Text::Star => "Star",
+        Text::Unstar => "Unstar",
+    }
+}
+
+fn spanish(text: Text) -> &'static str {
+    match text {
+        Text::NewChat => "+ Nuevo chat",
+        Text::SearchConversations => "Buscar conversaciones",
+        Text::NoConversations => "Todavía no hay conversaciones",
+        Text::NoMatchingConversations => "Ninguna conversación coincide",
+        Text::UntitledChat => "Chat sin título",
+        Text::ConversationAppearance => "Icono y color",
+        Text::OtherIcon => "Otro emoji",
+        Text::ClearAppearance => "Quitar",
+        Text::NewConversation => "Nueva conversación",
+        Text::Conversation => "Conversación",
+        Text::IncognitoConversation => "Conversación de incógnito",
+        Text::IncognitoToggle => "Incógnito (no se guarda)",
+        Text::StatelessToggle => "Sin historial",
+        Text::StatelessHint => "Responder cada mensaje por separado, sin los anteriores",
+        Text::EmptyState => "Envía un mensaje para empezar",
+        Text::QueuePosition => "Esperando al modelo — posición {position} en la cola",
+        Text::HistoryCutoff => "Los mensajes anteriores no se enviaron al modelo",
+        Text::RoleUser => "usuario",
+        Text::RoleAssistant => "asistente",
+        Text::ToolCall => "Llamada a {tool}",
+        Text::ToolResult => "Resultado de {tool}",
+        Text::Regenerate => "↻ Regenerar",
+        Text::Regenerating => "Regenerando…",
+        Text::ShowChanges => "Ver cambios",
+        Text::HideChanges => "Ocultar cambios",
+        Text::GenerationFailed => "No se pudo generar la respuesta: {error}",
+        Text::Sources => "Fuentes ({count})",
+        Text::FollowUps => "Preguntas sugeridas",
+        Text::InputPlaceholder => "Escribe un mensaje… (Intro para enviar, Mayús+Intro para salto de línea)",
+        Text::TokenBudgetNear => "≈{total} de {limit} tokens: {history} de historial + {message} de este mensaje",
+        Text::TokenBudgetOver => "≈{total} de {limit} tokens: {history} de historial + {message} de este mensaje. El modelo no lo verá todo.",
+        Text::MessageTooLong => "Este mensaje ocupa {length} bytes; el servidor acepta hasta {max}.",
+        Text::Send => "Enviar",
+        Text::Sending => "Enviando…",
+        Text::PromptHistory => "Mensajes anteriores (↑/↓ con el campo vacío)",
+        Text::SearchPrompts => "Busca en tus mensajes…",
+        Text::NoPrompts => "Ningún mensaje coincide",
+        Text::ReplyLength => "Longitud de la respuesta",
+        Text::Copy => "Copiar",
+        Text::Copied => "Copiado",
+        Text::DownloadArtifact => "Descargar",
+        Text::CloseArtifact => "Cerrar",
+        Text::ShowRetrieval => "Contexto recuperado",
+        Text::HideRetrieval => "Ocultar el contexto recuperado",
+        Text::NothingRetrieved => "No se recuperó nada para esta respuesta.",
+        Text::RetrievedRelevant => "Relevante para la pregunta",
+        Text::RetrievedIrrelevant => "No es relevante para la pregunta",
+        Text::Quote => "Citar en una respuesta",
+        Text::QuoteFromUser => "Escribiste:",
+        Text::QuoteFromAssistant => "El asistente escribió:",
+        Text::Concise => "Breve",
+        Text::Normal => "Normal",
+        Text::Detailed => "Detallada",
+        Text::ModelMissing => "El modelo '{model}' no está instalado en Ollama.",
+        Text::DownloadAndRetry => "Descargar y reintentar",
+        Text::ModelPulled => "Se descargó '{model}'.",
+        Text::Retry => "Reintentar",
+        Text::Dismiss => "Cerrar",
+        Text::Reconnecting => "Se perdió la conexión. Reconectando…",
+        Text::SortConversations => "Ordenar conversaciones",
+        Text::SortUpdated => "Actualizadas recientemente",
+        Text::SortCreated => "Creadas recientemente",
+        Text::SortTitle => "Título (A–Z)",
+        Text::SortMessageCount => "Más mensajes",
+        Text::ToggleSidebar => "Mostrar u ocultar conversaciones",
+        Text::Offline => "Sin conexión: se muestra el historial guardado. Los mensajes que envíes saldrán al recuperar la conexión.",
+        Text::OfflineQueued => "Esperando conexión para enviar este mensaje",
+        Text::OfflineReadOnly => "No disponible sin conexión: el historial es de solo lectura hasta que vuelvas a conectarte.",
+        Text::JustNow => "ahora mismo",
+        Text::MinutesAgo => "hace {count} min",
+        Text::HoursAgo => "hace {count} h",
+        Text::DaysAgo => "hace {count} d",
+        Text::Today => "Hoy",
+        Text::Yesterday => "Ayer",
+        Text::Maintenance => "En mantenimiento: el chat está en pausa, pero puedes seguir leyendo tu historial.",
+        Text::AppUpdated => "La aplicación se ha actualizado. Recarga para seguir chateando con la nueva versión.",
+        Text::Reload => "Recargar",
+        Text::ToolRunning => "Llamando a {tool}…",
+        Text::ToolDone => "{tool} respondió",
+        Text::ToolFailed => "{tool} falló",
+        Text::ToolTimedOut => "{tool} no respondió a tiempo",
+        Text::TokenCounts => "{prompt} → {completion} tokens",
+        Text::FinishLength => "cortado por el límite de tokens",
+        Text::FinishCancelled => "cancelado",
+        Text::FinishError => "interrumpido por un error",
+        Text::LightTheme => "☀ Tema claro",
+        Text::DarkTheme => "☾ Tema oscuro",
+        Text::ModerationBlocked => "Tu mensaje no se envió: {reason}.",
+        Text::Assistant => "Asistente",
+        Text::DefaultAssistant => "Asistente predeterminado",
+        Text::EarlierSummary => "Resumen de la conversación anterior",
+        Text::SummarizedMessages => "{count} mensajes",
+        Text::LoadingEarlier => "Cargando mensajes anteriores…",
+        Text::Admin => "⚙ Administración",
+        Text::AdminTitle => "Administración",
+        Text::SystemStatus => "Estado del sistema",
+        Text::Refresh => "Actualizar",
+        Text::OllamaUnreachable => "No se puede contactar con Ollama.",
+        Text::LoadedModels => "Modelos cargados",
+        Text::NoLoadedModels => "No hay ningún modelo cargado; el próximo turno cargará uno primero.",
+        Text::ModelMemory => "{size}, {vram} en VRAM",
+        Text::HostMemory => "RAM: {used} de {total}",
+        Text::HostLoad => "Carga de CPU: {load} en {cpus} CPU",
+        Text::ModelLoaded => "{model} está cargado",
+        Text::ModelCold => "{model} está en frío: la próxima respuesta esperará a que se cargue",
+        Text::Busy => "Aún se está generando una respuesta en esta conversación; vuelve a enviarlo cuando termine.",
+        Text::DownloadData => "⤓ Descargar mis datos",
+        Text::Starred => "★ Destacados",
+        Text::StarredTitle => "Mensajes destacados",
+        Text::NoStarred => "Destaca un mensaje para guardarlo aquí",
+        Text::Star => "Destacar",
+        Text::Unstar => "Quitar de destacados",
+    }
+}
@@ -1,14 +1,23 @@
 mod api;
 mod components;
+mod gestures;
+mod i18n;
 mod models;
+mod pwa;
 mod state;
+mod time;
+mod tokens;
 mod ws;
 
 use leptos::prelude::*;
 use leptos::mount::mount_to_body;
 
+use components::admin::AdminView;
+use components::artifacts::ArtifactPanel;
 use components::chat::ChatArea;
 use components::sidebar::Sidebar;
+use components::starred::StarredView;
+use components::toasts::Toasts;
 use state::AppState;
 
 /// Root application component.
@@ -16,18 +25,49 @@ use state::AppState;
 fn App() -> impl IntoView {
     let state = AppState::provide();
 
-    // Load conversations on mount
+    // Load conversations and preferences on mount
     state.load_conversations();
+    state.load_profile();
+    state.load_models();
+    state.load_presets();
+    state.load_server_config();
+    state.watch_maintenance();
+    state.watch_version();
+    state.watch_system_status();
+    state.watch_connectivity();
+
+    let (on_touch_start, on_touch_end) = gestures::sidebar_swipe(state.clone());
+    let (sidebar_open, set_sidebar_open) = (state.sidebar_open, state.set_sidebar_open);
+    let close_sidebar = move |_| set_sidebar_open.set(false);
 
     view! {
-        <div class="app-container">
+        <div
+            class="app-container"
+            class:theme-light=move || state.is_light_theme()
+            class:sidebar-open=move || sidebar_open.get()
+            on:touchstart=on_touch_start
+            on:touchend=on_touch_end
+        >
             <Sidebar />
-            <ChatArea />
+            // Covers the page while the sidebar is slid over it on narrow screens
+            <div class="sidebar-backdrop" on:click=close_sidebar />
+            {move || if state.show_admin.get() {
+                view! { <AdminView /> }.into_any()
+            } else if state.show_starred.get() {
+                view! { <StarredView /> }.into_any()
+            } else {
+                view! {
+                    <ChatArea />
+                    <ArtifactPanel />
+                }.into_any()
+            }}
+            <Toasts />
         </div>
     }
 }
 
 fn main() {
     console_log::init_with_level(log::Level::Debug).expect("Failed to init logger");
+    pwa::register_service_worker();
     mount_to_body(App);
 }
@@ -1,62 +1,16 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
-/// Matches the backend `Conversation` model.
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
-pub struct Conversation {
-    pub id: String,
-    pub title: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
-}
-
-/// Matches the backend `Message` model.
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
-pub struct Message {
-    pub id: String,
-    pub conversation_id: String,
-    pub role: String,
-    pub content: String,
-    pub created_at: String,
-}
-
-/// Request body for the chat API and WebSocket.
-#[derive(Clone, Debug, Serialize)]
-pub struct ChatRequest {
-    pub message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub conversation_id: Option<String>,
-}
-
-/// Response from the REST chat API.
-#[derive(Clone, Debug, Deserialize)]
-pub struct ChatResponse {
-    pub conversation_id: String,
-    pub message: Message,
-}
+pub use shared_models::{
+    Artifact, ArtifactKind, AssistantPreset, CompletionStats, Conversation, ConversationSort,
+    ConversationStats, DiffOp, DiffSpan, ErrorCode, FinishReason, LoadedModel, MaintenanceStatus,
+    Message, MessageRole, ModelsResponse, PromptHistoryEntry, PullProgress, RetrievalFeedback,
+    RetrievedChunk, ServerConfig, ServerVersion, Source, StarredMessage, SummaryBlock,
+    SystemStatus, TimelineEntry, TokenizeResponse, ToolCallStatus, UserProfile, Verbosity,
+    VersionDiff, WsChatRequest, WsControl, WsEncoding, WsEvent, WsFrame, BUILD_COMMIT,
+};
 
-/// WebSocket request sent by the client.
+/// Request body for switching the displayed version of a message.
 #[derive(Clone, Debug, Serialize)]
-pub struct WsChatRequest {
-    pub message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub conversation_id: Option<String>,
-}
-
-/// WebSocket event received from the server.
-/// Matches the backend `WsEvent` enum (internally tagged).
-#[derive(Clone, Debug, Deserialize)]
-#[serde(tag = "type")]
-pub enum WsEvent {
-    #[serde(rename = "stream_start")]
-    StreamStart { conversation_id: String },
-    #[serde(rename = "stream_chunk")]
-    StreamChunk { content: String },
-    #[serde(rename = "stream_end")]
-    StreamEnd {
-        full_content: String,
-        #[serde(default)]
-        message_id: Option<String>,
-    },
-    #[serde(rename = "error")]
-    Error { message: String },
+pub struct SetActiveVersionRequest {
+    pub version: i32,
 }
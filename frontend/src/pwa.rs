@@ -0,0 +1,35 @@
+use js_sys::Reflect;
+use leptos::ev;
+use leptos::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// Registers `sw.js`, which caches the app shell and the last-fetched
+/// history so the app opens, and conversations can be read, offline.
+/// Browsers only offer service workers on secure origins (or localhost);
+/// elsewhere this does nothing.
+pub fn register_service_worker() {
+    let Some(navigator) = web_sys::window().map(|w| w.navigator()) else { return };
+    if !Reflect::has(&navigator, &"serviceWorker".into()).unwrap_or(false) {
+        return;
+    }
+    let registration = navigator.service_worker().register("/sw.js");
+    leptos::task::spawn_local(async move {
+        if let Err(e) = JsFuture::from(registration).await {
+            log::warn!("Failed to register the service worker: {e:?}");
+        }
+    });
+}
+
+/// Whether the browser believes it has a network connection.
+pub fn is_online() -> bool {
+    web_sys::window().is_none_or(|w| w.navigator().on_line())
+}
+
+/// Calls `on_change` with the new state whenever the browser goes offline or
+/// back online, for as long as the app runs.
+pub fn watch_connectivity(on_change: impl Fn(bool) + Clone + 'static) {
+    let offline = on_change.clone();
+    // Dropping the handles keeps the listeners; the app never unmounts.
+    let _ = window_event_listener(ev::online, move |_| on_change(true));
+    let _ = window_event_listener(ev::offline, move |_| offline(false));
+}
@@ -1,55 +1,394 @@
+use std::collections::HashMap;
+
+use gloo_timers::future::TimeoutFuture;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
+use uuid::Uuid;
 
 use crate::api;
-use crate::models::{Conversation, Message};
-use crate::ws;
+use crate::pwa;
+use crate::i18n::{fill, Locale, Text};
+use crate::models::{
+    Artifact, AssistantPreset, CompletionStats, Conversation, ConversationSort, MaintenanceStatus, Message, MessageRole, PullProgress, ServerConfig, Source, BUILD_COMMIT,
+    StarredMessage, SummaryBlock, SystemStatus, TimelineEntry, ToolCallStatus, Verbosity, WsChatRequest, WsEvent, WsFrame,
+};
+use crate::ws::{ConversationWatcher, WsClient, WsStatus};
+
+/// How often relative timestamps ("5 min ago") are refreshed.
+const CLOCK_TICK_MS: u32 = 30_000;
+
+/// How often the maintenance flag is re-read.
+const MAINTENANCE_POLL_MS: u32 = 30_000;
+/// How often the server's build is compared with this app's.
+const VERSION_POLL_MS: u32 = 300_000;
+/// How often the loaded models and host load are re-read.
+const SYSTEM_STATUS_POLL_MS: u32 = 30_000;
+
+/// Toasts shown at once; the oldest is dropped to make room.
+const MAX_NOTIFICATIONS: usize = 4;
+const INFO_TIMEOUT_MS: u32 = 4_000;
+const ERROR_TIMEOUT_MS: u32 = 8_000;
+/// Toasts offering an action stay up longer, to give time to use it.
+const ACTION_TIMEOUT_MS: u32 = 15_000;
+
+/// How a toast is styled and how long it stays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationKind {
+    Info,
+    Error,
+}
+
+/// What a toast's "Retry" button does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RetryAction {
+    /// Send this message (and the id of the message it quotes) again; the
+    /// first attempt never reached the server.
+    Resend(String, Option<Uuid>),
+    LoadConversations,
+    LoadMessages(Uuid),
+    LoadStarred,
+    Regenerate(Uuid),
+    PullModel,
+}
+
+/// A tool call of the streaming reply, as last reported by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToolActivity {
+    pub call_id: String,
+    pub name: String,
+    pub status: ToolCallStatus,
+}
+
+/// A message picked with "Quote", waiting for the chat input to insert it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub message_id: Uuid,
+    /// The message as a markdown blockquote, opened by who wrote it.
+    pub text: String,
+}
+
+/// One toast in the notification queue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub id: u64,
+    pub kind: NotificationKind,
+    pub message: String,
+    pub retry: Option<RetryAction>,
+}
+
+/// Id for a message shown before the server has stored it. The server hands
+/// out random (version 4) UUIDs; these hold the time instead and carry no
+/// version, so the two never collide.
+pub fn unsaved_id() -> Uuid {
+    Uuid::from_u128(js_sys::Date::now() as u128)
+}
+
+/// Whether `id` was handed out by the server rather than [`unsaved_id`].
+pub fn is_saved(id: Uuid) -> bool {
+    id.get_version_num() != 0
+}
 
 /// Shared application state, provided via Leptos context.
 #[derive(Clone)]
 pub struct AppState {
     // --- Read signals (for components to subscribe to) ---
     pub conversations: ReadSignal<Vec<Conversation>>,
-    pub active_conversation: ReadSignal<Option<String>>,
+    pub active_conversation: ReadSignal<Option<Uuid>>,
     pub messages: ReadSignal<Vec<Message>>,
+    /// The oldest messages of the active conversation, condensed into its
+    /// summary and shown as a card in front of `messages`.
+    pub summary: ReadSignal<Option<SummaryBlock>>,
+    /// The messages behind `summary`, once the card was expanded.
+    pub earlier: ReadSignal<Option<Vec<Message>>>,
+    /// Long code blocks and documents of the active conversation's replies.
+    pub artifacts: ReadSignal<Vec<Artifact>>,
+    /// Artifact shown in the side panel.
+    pub open_artifact: ReadSignal<Option<Uuid>>,
     pub streaming_text: ReadSignal<Option<String>>,
+    pub streaming_sources: ReadSignal<Vec<Source>>,
+    /// Position in the server's generation queue while waiting for Ollama.
+    pub queue_position: ReadSignal<Option<usize>>,
+    /// Tool calls of the streaming reply, in the order they started.
+    pub streaming_tools: ReadSignal<Vec<ToolActivity>>,
     pub is_streaming: ReadSignal<bool>,
-    pub error: ReadSignal<Option<String>>,
+    /// Whether the active (or next new) conversation is ephemeral: kept in
+    /// server memory only and never listed in the sidebar.
+    pub ephemeral: ReadSignal<bool>,
+    /// Reply length picked next to the Send button for the active (or next
+    /// new) conversation.
+    pub verbosity: ReadSignal<Verbosity>,
+    /// Whether the active (or next new) conversation answers each message on
+    /// its own, without replaying its history; toggled in the chat header.
+    pub stateless: ReadSignal<bool>,
+    /// Id of the assistant preset answering the active conversation; `None`
+    /// is the default assistant.
+    pub preset: ReadSignal<Option<String>>,
+    /// Message to quote in the chat input, until the input takes it.
+    pub quote: ReadSignal<Option<Quote>>,
+    /// Id of the message currently being regenerated.
+    pub regenerating: ReadSignal<Option<Uuid>>,
+    /// Why the active conversation's last turn failed; shown as a bubble
+    /// offering to retry it.
+    pub failed_turn: ReadSignal<Option<String>>,
+    /// Model the last turn needed but Ollama does not have installed.
+    pub missing_model: ReadSignal<Option<String>>,
+    /// Latest progress while that model is being pulled.
+    pub pull_progress: ReadSignal<Option<PullProgress>>,
+    /// Set while the server is in maintenance mode and refuses to chat.
+    pub maintenance: ReadSignal<Option<MaintenanceStatus>>,
+    /// The server was deployed from another build than this app; shown as a
+    /// banner asking to reload.
+    pub outdated: ReadSignal<bool>,
+    /// State of the chat socket.
+    pub ws_status: ReadSignal<WsStatus>,
+    /// Whether the browser has a network connection. Offline, history is
+    /// read from the service worker's cache and can only be read; sent
+    /// messages wait until the connection is back.
+    pub online: ReadSignal<bool>,
+    /// Current time in milliseconds, ticking every [`CLOCK_TICK_MS`].
+    pub now: ReadSignal<f64>,
+    /// Language of the UI strings.
+    pub locale: ReadSignal<Locale>,
+    /// Theme from the user's profile; unset means dark.
+    pub theme: ReadSignal<Option<String>>,
+    /// Order of the conversation list from the user's profile, once loaded;
+    /// until then the server applies it.
+    pub conversation_sort: ReadSignal<Option<ConversationSort>>,
+    /// Newest message the history limit kept from the model on the last turn.
+    pub history_cutoff: ReadSignal<Option<Uuid>>,
+    /// Running cost of the active conversation, when its models are priced.
+    pub conversation_cost: ReadSignal<Option<f64>>,
+    /// Tokens of context the model runs with, once known.
+    pub context_window: ReadSignal<Option<usize>>,
+    /// Assistant presets a conversation can switch to.
+    pub presets: ReadSignal<Vec<AssistantPreset>>,
+    /// What the server offers, once known.
+    pub server_config: ReadSignal<Option<ServerConfig>>,
+    /// Toasts currently shown, oldest first.
+    pub notifications: ReadSignal<Vec<Notification>>,
+    /// Whether the starred messages are shown instead of a conversation.
+    pub show_starred: ReadSignal<bool>,
+    /// Starred messages, loaded when that view opens.
+    pub starred: ReadSignal<Vec<StarredMessage>>,
+    /// Whether the admin page is shown instead of a conversation.
+    pub show_admin: ReadSignal<bool>,
+    /// Whether the sidebar is slid over the page. Only narrow screens hide
+    /// it; wider ones always show it and ignore this.
+    pub sidebar_open: ReadSignal<bool>,
+    /// Models Ollama has loaded and the host's memory and load, once known.
+    pub system_status: ReadSignal<Option<SystemStatus>>,
+    /// Completion stats of the replies streamed during this session, by
+    /// message id.
+    pub reply_stats: ReadSignal<HashMap<Uuid, CompletionStats>>,
 
     // --- Write signals (for mutating state) ---
     pub set_conversations: WriteSignal<Vec<Conversation>>,
-    pub set_active_conversation: WriteSignal<Option<String>>,
+    pub set_active_conversation: WriteSignal<Option<Uuid>>,
     pub set_messages: WriteSignal<Vec<Message>>,
+    pub set_summary: WriteSignal<Option<SummaryBlock>>,
+    pub set_earlier: WriteSignal<Option<Vec<Message>>>,
+    pub set_artifacts: WriteSignal<Vec<Artifact>>,
+    pub set_open_artifact: WriteSignal<Option<Uuid>>,
     pub set_streaming_text: WriteSignal<Option<String>>,
+    pub set_streaming_sources: WriteSignal<Vec<Source>>,
+    pub set_queue_position: WriteSignal<Option<usize>>,
+    pub set_streaming_tools: WriteSignal<Vec<ToolActivity>>,
     pub set_is_streaming: WriteSignal<bool>,
-    pub set_error: WriteSignal<Option<String>>,
+    pub set_ephemeral: WriteSignal<bool>,
+    pub set_verbosity: WriteSignal<Verbosity>,
+    pub set_stateless: WriteSignal<bool>,
+    pub set_preset: WriteSignal<Option<String>>,
+    pub set_quote: WriteSignal<Option<Quote>>,
+    pub set_regenerating: WriteSignal<Option<Uuid>>,
+    pub set_failed_turn: WriteSignal<Option<String>>,
+    pub set_missing_model: WriteSignal<Option<String>>,
+    pub set_pull_progress: WriteSignal<Option<PullProgress>>,
+    pub set_maintenance: WriteSignal<Option<MaintenanceStatus>>,
+    pub set_outdated: WriteSignal<bool>,
+    pub set_online: WriteSignal<bool>,
+    pub set_locale: WriteSignal<Locale>,
+    pub set_theme: WriteSignal<Option<String>>,
+    pub set_conversation_sort: WriteSignal<Option<ConversationSort>>,
+    pub set_history_cutoff: WriteSignal<Option<Uuid>>,
+    pub set_conversation_cost: WriteSignal<Option<f64>>,
+    pub set_context_window: WriteSignal<Option<usize>>,
+    pub set_presets: WriteSignal<Vec<AssistantPreset>>,
+    pub set_server_config: WriteSignal<Option<ServerConfig>>,
+    pub set_notifications: WriteSignal<Vec<Notification>>,
+    pub set_show_starred: WriteSignal<bool>,
+    pub set_starred: WriteSignal<Vec<StarredMessage>>,
+    pub set_show_admin: WriteSignal<bool>,
+    pub set_sidebar_open: WriteSignal<bool>,
+    pub set_system_status: WriteSignal<Option<SystemStatus>>,
+    pub set_reply_stats: WriteSignal<HashMap<Uuid, CompletionStats>>,
+    next_notification_id: StoredValue<u64>,
+    ws: StoredValue<WsClient, LocalStorage>,
+    /// Live turns of the open conversation started elsewhere.
+    watcher: StoredValue<Option<ConversationWatcher>, LocalStorage>,
 }
 
 impl AppState {
     /// Create a new `AppState` and provide it in the current Leptos context.
     pub fn provide() -> Self {
         let (conversations, set_conversations) = signal(Vec::<Conversation>::new());
-        let (active_conversation, set_active_conversation) = signal(None::<String>);
+        let (active_conversation, set_active_conversation) = signal(None::<Uuid>);
         let (messages, set_messages) = signal(Vec::<Message>::new());
+        let (summary, set_summary) = signal(None::<SummaryBlock>);
+        let (earlier, set_earlier) = signal(None::<Vec<Message>>);
+        let (artifacts, set_artifacts) = signal(Vec::<Artifact>::new());
+        let (open_artifact, set_open_artifact) = signal(None::<Uuid>);
         let (streaming_text, set_streaming_text) = signal(None::<String>);
+        let (streaming_sources, set_streaming_sources) = signal(Vec::<Source>::new());
+        let (queue_position, set_queue_position) = signal(None::<usize>);
+        let (streaming_tools, set_streaming_tools) = signal(Vec::<ToolActivity>::new());
         let (is_streaming, set_is_streaming) = signal(false);
-        let (error, set_error) = signal(None::<String>);
+        let (ephemeral, set_ephemeral) = signal(false);
+        let (verbosity, set_verbosity) = signal(Verbosity::default());
+        let (stateless, set_stateless) = signal(false);
+        let (preset, set_preset) = signal(None::<String>);
+        let (quote, set_quote) = signal(None::<Quote>);
+        let (regenerating, set_regenerating) = signal(None::<Uuid>);
+        let (failed_turn, set_failed_turn) = signal(None::<String>);
+        let (missing_model, set_missing_model) = signal(None::<String>);
+        let (pull_progress, set_pull_progress) = signal(None::<PullProgress>);
+        let (maintenance, set_maintenance) = signal(None::<MaintenanceStatus>);
+        let (outdated, set_outdated) = signal(false);
+        let (online, set_online) = signal(pwa::is_online());
+        let (locale, set_locale) = signal(Locale::detect());
+        let (theme, set_theme) = signal(None::<String>);
+        let (conversation_sort, set_conversation_sort) = signal(None::<ConversationSort>);
+        let (history_cutoff, set_history_cutoff) = signal(None::<Uuid>);
+        let (conversation_cost, set_conversation_cost) = signal(None::<f64>);
+        let (context_window, set_context_window) = signal(None::<usize>);
+        let (presets, set_presets) = signal(Vec::<AssistantPreset>::new());
+        let (server_config, set_server_config) = signal(None::<ServerConfig>);
+        let (notifications, set_notifications) = signal(Vec::<Notification>::new());
+        let (show_starred, set_show_starred) = signal(false);
+        let (starred, set_starred) = signal(Vec::<StarredMessage>::new());
+        let (show_admin, set_show_admin) = signal(false);
+        let (sidebar_open, set_sidebar_open) = signal(false);
+        let (system_status, set_system_status) = signal(None::<SystemStatus>);
+        let (reply_stats, set_reply_stats) = signal(HashMap::<Uuid, CompletionStats>::new());
+        let ws = WsClient::new();
+        let ws_status = ws.status();
+        // Suggestions arrive after their turn ended; attach them to the reply.
+        let suggested = ws.suggestions();
+        Effect::new(move |_| {
+            let Some((message_id, suggestions)) = suggested.get() else { return };
+            set_messages.update(|msgs| {
+                if let Some(msg) = msgs.iter_mut().find(|m| m.id == message_id) {
+                    msg.suggestions = suggestions;
+                }
+            });
+        });
+        let (now, set_now) = signal(js_sys::Date::now());
+        spawn_local(async move {
+            loop {
+                TimeoutFuture::new(CLOCK_TICK_MS).await;
+                set_now.set(js_sys::Date::now());
+            }
+        });
 
         let state = Self {
             conversations,
             active_conversation,
             messages,
+            summary,
+            earlier,
+            artifacts,
+            open_artifact,
             streaming_text,
+            streaming_sources,
+            queue_position,
+            streaming_tools,
             is_streaming,
-            error,
+            ephemeral,
+            verbosity,
+            stateless,
+            preset,
+            quote,
+            regenerating,
+            failed_turn,
+            missing_model,
+            pull_progress,
+            maintenance,
+            outdated,
+            ws_status,
+            online,
+            now,
+            locale,
+            theme,
+            conversation_sort,
+            history_cutoff,
+            conversation_cost,
+            context_window,
+            presets,
+            server_config,
+            notifications,
+            show_starred,
+            starred,
+            show_admin,
+            sidebar_open,
+            system_status,
+            reply_stats,
             set_conversations,
             set_active_conversation,
             set_messages,
+            set_summary,
+            set_earlier,
+            set_artifacts,
+            set_open_artifact,
             set_streaming_text,
+            set_streaming_sources,
+            set_queue_position,
+            set_streaming_tools,
             set_is_streaming,
-            set_error,
+            set_ephemeral,
+            set_verbosity,
+            set_stateless,
+            set_preset,
+            set_quote,
+            set_regenerating,
+            set_failed_turn,
+            set_missing_model,
+            set_pull_progress,
+            set_maintenance,
+            set_outdated,
+            set_online,
+            set_locale,
+            set_theme,
+            set_conversation_sort,
+            set_history_cutoff,
+            set_conversation_cost,
+            set_context_window,
+            set_presets,
+            set_server_config,
+            set_notifications,
+            set_show_starred,
+            set_starred,
+            set_show_admin,
+            set_sidebar_open,
+            set_system_status,
+            set_reply_stats,
+            next_notification_id: StoredValue::new(0),
+            ws: StoredValue::new_local(ws),
+            watcher: StoredValue::new_local(None),
         };
 
+        // Follow turns other tabs and clients stream in the open conversation.
+        Effect::new({
+            let state = state.clone();
+            move |_| {
+                let active = state.active_conversation.get();
+                // Drop the old watcher first, closing its socket.
+                state.watcher.set_value(None);
+                let Some(id) = active else { return };
+                let watching = state.clone();
+                let watcher = ConversationWatcher::open(id, move |frame| watching.watched(frame));
+                state.watcher.set_value(watcher);
+            }
+        });
+
         provide_context(state.clone());
         state
     }
@@ -58,105 +397,796 @@ impl AppState {
     pub fn load_conversations(&self) {
         let state = self.clone();
         spawn_local(async move {
-            match api::fetch_conversations().await {
+            match api::fetch_conversations(state.conversation_sort.get_untracked()).await {
                 Ok(convos) => state.set_conversations.set(convos),
                 Err(e) => {
                     log::error!("Failed to fetch conversations: {e}");
-                    state.set_error.set(Some(e));
+                    state.notify_error(e, Some(RetryAction::LoadConversations));
+                }
+            }
+        });
+    }
+
+    /// Load the user's profile to pick up their theme and list order.
+    pub fn load_profile(&self) {
+        let (set_theme, set_sort) = (self.set_theme, self.set_conversation_sort);
+        spawn_local(async move {
+            match api::fetch_profile().await {
+                Ok(profile) => {
+                    set_theme.set(profile.theme);
+                    set_sort.set(Some(profile.conversation_sort));
+                }
+                Err(e) => log::error!("Failed to fetch profile: {e}"),
+            }
+        });
+    }
+
+    /// Load the model's context size for the token budget warning.
+    pub fn load_models(&self) {
+        let set_context_window = self.set_context_window;
+        spawn_local(async move {
+            match api::fetch_models().await {
+                Ok(models) => set_context_window.set(Some(models.context_window)),
+                Err(e) => log::error!("Failed to fetch models: {e}"),
+            }
+        });
+    }
+
+    /// Load the assistant presets for the preset picker.
+    pub fn load_presets(&self) {
+        let set_presets = self.set_presets;
+        spawn_local(async move {
+            match api::fetch_presets().await {
+                Ok(presets) => set_presets.set(presets),
+                Err(e) => log::error!("Failed to fetch presets: {e}"),
+            }
+        });
+    }
+
+    /// Load what the server offers, so the UI can match it.
+    pub fn load_server_config(&self) {
+        let set_server_config = self.set_server_config;
+        spawn_local(async move {
+            match api::fetch_server_config().await {
+                Ok(config) => set_server_config.set(Some(config)),
+                Err(e) => log::error!("Failed to fetch server config: {e}"),
+            }
+        });
+    }
+
+    /// Re-read the maintenance flag now and every [`MAINTENANCE_POLL_MS`] after.
+    pub fn watch_maintenance(&self) {
+        let state = self.clone();
+        spawn_local(async move {
+            loop {
+                state.load_maintenance().await;
+                TimeoutFuture::new(MAINTENANCE_POLL_MS).await;
+            }
+        });
+    }
+
+    /// Compare the server's build with this app's now and every
+    /// [`VERSION_POLL_MS`] after, until they differ: after a deploy, this
+    /// app may no longer speak the server's protocol.
+    pub fn watch_version(&self) {
+        let set_outdated = self.set_outdated;
+        spawn_local(async move {
+            loop {
+                match api::fetch_version().await {
+                    Ok(version) if !version.matches_this_build() => {
+                        let server = version.build.commit;
+                        log::warn!("Server runs build {server:?}, this app {BUILD_COMMIT:?}");
+                        set_outdated.set(true);
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Failed to fetch server version: {e}"),
                 }
+                TimeoutFuture::new(VERSION_POLL_MS).await;
+            }
+        });
+    }
+
+    /// Follow the browser going offline and back online. Back online, queued
+    /// messages are sent right away and the history is refreshed.
+    pub fn watch_connectivity(&self) {
+        let state = self.clone();
+        pwa::watch_connectivity(move |online| {
+            state.set_online.set(online);
+            if online {
+                state.ws.with_value(WsClient::reconnect_now);
+                state.load_conversations();
+                state.reload_messages();
+            }
+        });
+    }
+
+    /// Whether changes can be made; offline, history is read-only and this
+    /// says so with a toast.
+    fn require_online(&self) -> bool {
+        let online = self.online.get_untracked();
+        if !online {
+            let text = self.locale.get_untracked().tr(Text::OfflineReadOnly);
+            self.notify_error(text.to_string(), None);
+        }
+        online
+    }
+
+    async fn load_maintenance(&self) {
+        match api::fetch_maintenance().await {
+            Ok(status) => self.set_maintenance.set(status.enabled.then_some(status)),
+            Err(e) => log::error!("Failed to fetch maintenance mode: {e}"),
+        }
+    }
+
+    /// Re-read the system status now and every [`SYSTEM_STATUS_POLL_MS`]
+    /// after, so the model indicator notices Ollama unloading it.
+    pub fn watch_system_status(&self) {
+        let state = self.clone();
+        spawn_local(async move {
+            loop {
+                state.load_system_status().await;
+                TimeoutFuture::new(SYSTEM_STATUS_POLL_MS).await;
+            }
+        });
+    }
+
+    pub async fn load_system_status(&self) {
+        match api::fetch_system_status().await {
+            Ok(status) => self.set_system_status.set(Some(status)),
+            Err(e) => log::error!("Failed to fetch system status: {e}"),
+        }
+    }
+
+    /// Slide the sidebar in or out on narrow screens.
+    pub fn toggle_sidebar(&self) {
+        self.set_sidebar_open.update(|open| *open = !*open);
+    }
+
+    /// Show the admin page with a fresh system status.
+    pub fn open_admin(&self) {
+        self.set_sidebar_open.set(false);
+        self.set_show_starred.set(false);
+        self.set_show_admin.set(true);
+        let state = self.clone();
+        spawn_local(async move { state.load_system_status().await });
+    }
+
+    pub fn is_light_theme(&self) -> bool {
+        self.theme.get().as_deref() == Some("light")
+    }
+
+    /// Switch between the light and dark theme and save the choice.
+    pub fn toggle_theme(&self) {
+        if !self.require_online() {
+            return;
+        }
+        let theme = if self.theme.get_untracked().as_deref() == Some("light") {
+            "dark"
+        } else {
+            "light"
+        };
+        self.set_theme.set(Some(theme.to_string()));
+        let state = self.clone();
+        spawn_local(async move {
+            if let Err(e) = api::set_theme(theme).await {
+                log::error!("Failed to save theme: {e}");
+                state.notify_error(e, None);
+            }
+        });
+    }
+
+    /// Reorder the conversation list and save the choice.
+    pub fn sort_conversations(&self, sort: ConversationSort) {
+        if !self.require_online() {
+            return;
+        }
+        self.set_conversation_sort.set(Some(sort));
+        self.load_conversations();
+        let state = self.clone();
+        spawn_local(async move {
+            if let Err(e) = api::set_conversation_sort(sort).await {
+                log::error!("Failed to save conversation order: {e}");
+                state.notify_error(e, None);
             }
         });
     }
 
     /// Select a conversation and load its messages.
-    pub fn select_conversation(&self, id: String) {
+    pub fn select_conversation(&self, id: Uuid) {
         let state = self.clone();
-        self.set_active_conversation.set(Some(id.clone()));
+        self.set_active_conversation.set(Some(id));
+        self.set_sidebar_open.set(false);
+        self.set_show_starred.set(false);
+        self.set_show_admin.set(false);
+        self.set_ephemeral.set(false);
         self.set_streaming_text.set(None);
-        self.set_error.set(None);
+        let (failed, verbosity, stateless, preset) = self.conversations.with_untracked(|convos| {
+            let conversation = convos.iter().find(|c| c.id == id);
+            (
+                conversation.and_then(|c| c.failed_turn_error.clone()),
+                conversation.map(|c| c.verbosity).unwrap_or_default(),
+                conversation.is_some_and(|c| c.stateless),
+                conversation.and_then(|c| c.preset.clone()),
+            )
+        });
+        self.set_failed_turn.set(failed);
+        self.set_verbosity.set(verbosity);
+        self.set_stateless.set(stateless);
+        self.set_preset.set(preset);
+        self.set_history_cutoff.set(None);
+        self.set_conversation_cost.set(None);
+        self.set_summary.set(None);
+        self.set_earlier.set(None);
+        self.set_artifacts.set(Vec::new());
+        self.set_open_artifact.set(None);
+        self.load_cost(id);
+        self.load_artifacts(id);
 
         spawn_local(async move {
-            match api::fetch_messages(&id).await {
-                Ok(msgs) => state.set_messages.set(msgs),
+            match api::fetch_timeline(id).await {
+                Ok(entries) => state.show_timeline(entries),
                 Err(e) => {
                     log::error!("Failed to fetch messages: {e}");
-                    state.set_error.set(Some(e));
+                    state.notify_error(e, Some(RetryAction::LoadMessages(id)));
                 }
             }
         });
     }
 
-    /// Send a message via WebSocket streaming.
-    pub fn send_message(&self, text: String) {
+    /// Fetch the artifacts of a conversation's replies, for the side panel.
+    fn load_artifacts(&self, conversation_id: Uuid) {
         let state = self.clone();
+        spawn_local(async move {
+            match api::fetch_artifacts(conversation_id).await {
+                // Unless the user has moved on meanwhile.
+                Ok(artifacts) if state.active_conversation.get_untracked() == Some(conversation_id) => {
+                    state.set_artifacts.set(artifacts)
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Failed to fetch artifacts: {e}"),
+            }
+        });
+    }
+
+    /// Refresh the running cost shown in the chat header.
+    pub fn load_cost(&self, conversation_id: Uuid) {
+        let set_cost = self.set_conversation_cost;
+        spawn_local(async move {
+            match api::fetch_conversation_stats(conversation_id).await {
+                Ok(stats) => set_cost.set(stats.cost),
+                Err(e) => log::error!("Failed to fetch conversation stats: {e}"),
+            }
+        });
+    }
+
+    /// Send a message via WebSocket streaming, with the id of the message it
+    /// opens by quoting, if any.
+    pub fn send_message(&self, text: String, quoted_message_id: Option<Uuid>) {
         let conv_id = self.active_conversation.get_untracked();
 
-        // Optimistically add the user message to the display
+        // Optimistically add the user message to the display; a new
+        // conversation's id is filled in once the server has picked it.
         let temp_user_msg = Message {
-            id: format!("temp-{}", js_sys::Date::now() as u64),
-            conversation_id: conv_id.clone().unwrap_or_default(),
-            role: "user".to_string(),
-            content: text.clone(),
-            created_at: String::new(),
+            quoted_message_id,
+            ..Message::with_id(
+                unsaved_id(),
+                conv_id.unwrap_or_default(),
+                MessageRole::User,
+                text.clone(),
+            )
         };
         self.set_messages.update(|msgs| msgs.push(temp_user_msg));
+        self.stream_turn(WsChatRequest {
+            message: text,
+            conversation_id: conv_id,
+            ephemeral: self.ephemeral.get_untracked(),
+            verbosity: Some(self.verbosity.get_untracked()),
+            stateless: Some(self.stateless.get_untracked()),
+            quoted_message_id,
+            ..WsChatRequest::default()
+        });
+    }
+
+    /// Answers the active conversation's last message again, after its turn
+    /// failed or once the model it was waiting for has been pulled.
+    pub fn retry_turn(&self) {
+        self.stream_turn(WsChatRequest {
+            conversation_id: self.active_conversation.get_untracked(),
+            ephemeral: self.ephemeral.get_untracked(),
+            retry: true,
+            verbosity: Some(self.verbosity.get_untracked()),
+            ..WsChatRequest::default()
+        });
+    }
+
+    /// Streams one turn over the shared WebSocket, updating state as events arrive.
+    fn stream_turn(&self, request: WsChatRequest) {
+        self.set_failed_turn.set(None);
         self.set_is_streaming.set(true);
         self.set_streaming_text.set(Some(String::new()));
-        self.set_error.set(None);
-
-        let set_active = self.set_active_conversation;
-        let set_streaming = self.set_streaming_text;
-        let set_is_streaming = self.set_is_streaming;
-        let set_messages = self.set_messages;
-        let set_error = self.set_error;
-
-        // Callbacks to update state from WebSocket events
-        let on_start = move |new_conv_id: String| {
-            set_active.set(Some(new_conv_id.clone()));
-            // Update the temp user message's conversation_id
-            set_messages.update(|msgs| {
-                for m in msgs.iter_mut() {
-                    if m.conversation_id.is_empty() {
-                        m.conversation_id = new_conv_id.clone();
+        self.set_streaming_sources.set(Vec::new());
+        self.set_streaming_tools.set(Vec::new());
+
+        // Whether the server accepted the turn; after that the user message
+        // is stored, so a retry must not send it again.
+        let mut started = request.retry;
+        let resend = RetryAction::Resend(request.message.clone(), request.quoted_message_id);
+        let mut events = self.ws.with_value(|ws| ws.stream(request));
+
+        let state = self.clone();
+        spawn_local(async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    WsEvent::StreamStart { conversation_id, history_cutoff } => {
+                        started = true;
+                        state.set_active_conversation.set(Some(conversation_id));
+                        state.set_history_cutoff.set(history_cutoff);
+                        // Update the temp user message's conversation_id
+                        state.set_messages.update(|msgs| {
+                            for m in msgs.iter_mut() {
+                                if m.conversation_id.is_nil() {
+                                    m.conversation_id = conversation_id;
+                                }
+                            }
+                        });
+                    }
+                    WsEvent::Queued { position } => state.set_queue_position.set(Some(position)),
+                    WsEvent::ToolCall { call_id, name, status, .. } => {
+                        state.set_queue_position.set(None);
+                        state.set_streaming_tools.update(|tools| {
+                            match tools.iter_mut().find(|t| t.call_id == call_id) {
+                                Some(tool) => tool.status = status,
+                                None => tools.push(ToolActivity { call_id, name, status }),
+                            }
+                        });
+                    }
+                    WsEvent::StreamChunk { content } => {
+                        state.set_queue_position.set(None);
+                        state.set_streaming_text.update(|current| {
+                            if let Some(text) = current {
+                                text.push_str(&content);
+                            }
+                        });
+                    }
+                    WsEvent::StreamSources { sources } => state.set_streaming_sources.set(sources),
+                    WsEvent::Artifact { artifact } => {
+                        state.set_artifacts.update(|artifacts| artifacts.push(artifact));
+                    }
+                    WsEvent::StreamEnd { full_content, message_id, stats, .. } => {
+                        state.finish_turn(full_content, message_id, stats);
+                        return;
+                    }
+                    WsEvent::ModelMissing { model } => {
+                        state.set_missing_model.set(Some(model));
+                        state.end_streaming();
+                        return;
+                    }
+                    WsEvent::ModerationBlocked { reason } => {
+                        // The server did not save the message; drop the optimistic copy.
+                        state.set_messages.update(|msgs| {
+                            if msgs.last().is_some_and(|m| !is_saved(m.id)) {
+                                msgs.pop();
+                            }
+                        });
+                        let text = state.locale.get_untracked().tr(Text::ModerationBlocked);
+                        state.notify_error(fill(text, &[("reason", &reason)]), None);
+                        state.end_streaming();
+                        return;
+                    }
+                    WsEvent::Busy { .. } => {
+                        // Refused before the message was saved, like a blocked one.
+                        state.set_messages.update(|msgs| {
+                            if msgs.last().is_some_and(|m| !is_saved(m.id)) {
+                                msgs.pop();
+                            }
+                        });
+                        let text = state.locale.get_untracked().tr(Text::Busy);
+                        state.notify_error(text.to_string(), Some(resend));
+                        state.end_streaming();
+                        return;
+                    }
+                    // Routed to `WsClient::suggestions`, never to a turn.
+                    WsEvent::FollowUpSuggestions { .. } => {}
+                    // Handled by `WsClient` itself.
+                    WsEvent::Hello { .. } => {}
+                    WsEvent::Error { message, .. } => {
+                        log::error!("WebSocket error: {message}");
+                        if started {
+                            // The message is stored; offer to answer it again in place.
+                            state.set_failed_turn.set(Some(message));
+                        } else {
+                            state.notify_error(message, Some(resend));
+                        }
+                        state.end_streaming();
+                        // The socket is refused while in maintenance; show why right away.
+                        state.load_maintenance().await;
+                        return;
                     }
                 }
-            });
-        };
+            }
+        });
+    }
 
-        let on_chunk = move |chunk: String| {
-            set_streaming.update(|current| {
-                if let Some(text) = current {
-                    text.push_str(&chunk);
+    /// Shows a turn of the open conversation that another tab or client is
+    /// streaming. Ignored while this tab streams a turn of its own.
+    fn watched(&self, frame: WsFrame) {
+        let Some(stream_id) = frame.stream_id else { return };
+        if self.is_streaming.get_untracked() || self.ws.with_value(|ws| ws.is_own(&stream_id)) {
+            return;
+        }
+        match frame.event {
+            WsEvent::StreamStart { conversation_id, .. } => {
+                if self.active_conversation.get_untracked() != Some(conversation_id) {
+                    return;
                 }
-            });
-        };
+                // Pick up the message that started the turn.
+                self.reload_messages();
+                self.set_streaming_sources.set(Vec::new());
+                self.set_streaming_text.set(Some(String::new()));
+            }
+            WsEvent::StreamChunk { content } => {
+                self.set_streaming_text.update(|current| {
+                    if let Some(text) = current {
+                        text.push_str(&content);
+                    }
+                });
+            }
+            WsEvent::StreamSources { sources } => self.set_streaming_sources.set(sources),
+            WsEvent::Artifact { artifact } => {
+                self.set_artifacts.update(|artifacts| artifacts.push(artifact));
+            }
+            WsEvent::StreamEnd { .. } => {
+                self.set_streaming_text.set(None);
+                self.set_streaming_sources.set(Vec::new());
+                self.reload_messages();
+                self.load_conversations();
+            }
+            WsEvent::ModelMissing { .. }
+            | WsEvent::ModerationBlocked { .. }
+            | WsEvent::Error { .. } => {
+                self.set_streaming_text.set(None);
+                self.reload_messages();
+            }
+            WsEvent::FollowUpSuggestions { message_id, suggestions } => {
+                self.set_messages.update(|msgs| {
+                    if let Some(msg) = msgs.iter_mut().find(|m| m.id == message_id) {
+                        msg.suggestions = suggestions;
+                    }
+                });
+            }
+            WsEvent::Queued { .. }
+            | WsEvent::ToolCall { .. }
+            | WsEvent::Busy { .. }
+            | WsEvent::Hello { .. } => {}
+        }
+    }
 
-        let st2 = state.clone();
-        let on_end = move |full_content: String| {
-            // Convert streaming text into a proper assistant message
-            let conv = state.active_conversation.get_untracked().unwrap_or_default();
-            let assistant_msg = Message {
-                id: format!("msg-{}", js_sys::Date::now() as u64),
-                conversation_id: conv,
-                role: "assistant".to_string(),
-                content: full_content,
-                created_at: String::new(),
-            };
-            set_messages.update(|msgs| msgs.push(assistant_msg));
-            set_streaming.set(None);
-            set_is_streaming.set(false);
-
-            // Refresh conversations list to pick up any new/updated ones
-            st2.load_conversations();
-        };
+    /// Re-reads the open conversation's messages.
+    fn reload_messages(&self) {
+        let Some(id) = self.active_conversation.get_untracked() else { return };
+        let state = self.clone();
+        spawn_local(async move {
+            match api::fetch_timeline(id).await {
+                // Unless the user has moved on meanwhile.
+                Ok(entries) if state.active_conversation.get_untracked() == Some(id) => {
+                    state.show_timeline(entries)
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Failed to fetch messages: {e}"),
+            }
+        });
+    }
+
+    /// Shows the open conversation's timeline: its summary block, if any,
+    /// and the messages after it. Fetched messages behind an unchanged
+    /// block are kept.
+    fn show_timeline(&self, entries: Vec<TimelineEntry>) {
+        let mut summary = None;
+        let mut messages = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match entry {
+                TimelineEntry::Summary(block) => summary = Some(block),
+                TimelineEntry::Message(msg) => messages.push(msg),
+            }
+        }
+        if self.summary.with_untracked(|current| current != &summary) {
+            self.set_earlier.set(None);
+            self.set_summary.set(summary);
+        }
+        self.set_messages.set(messages);
+    }
+
+    /// Fetch the messages the open conversation's summary block stands for.
+    pub fn load_earlier(&self) {
+        let Some(id) = self.active_conversation.get_untracked() else { return };
+        let Some(block) = self.summary.get_untracked() else { return };
+        let state = self.clone();
+        spawn_local(async move {
+            match api::fetch_messages(id).await {
+                Ok(mut msgs) if state.active_conversation.get_untracked() == Some(id) => {
+                    let end = msgs
+                        .iter()
+                        .position(|m| m.id == block.last_message_id)
+                        .map_or(block.message_count, |index| index + 1);
+                    msgs.truncate(end);
+                    state.set_earlier.set(Some(msgs));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Failed to fetch earlier messages: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    }
+
+    /// Turns the streamed reply into a proper assistant message.
+    fn finish_turn(&self, content: String, message_id: Uuid, stats: CompletionStats) {
+        let conv = self.active_conversation.get_untracked().unwrap_or_default();
+        let sources = self.set_streaming_sources.try_update(std::mem::take).unwrap_or_default();
+        let assistant_msg = Message::with_id(message_id, conv, MessageRole::Assistant, content)
+            .with_sources(sources);
+        self.set_reply_stats.update(|all| {
+            all.insert(message_id, stats);
+        });
+        self.set_messages.update(|msgs| msgs.push(assistant_msg));
+        self.end_streaming();
+
+        // Refresh conversations list to pick up any new/updated ones
+        self.load_conversations();
+        if let Some(conv) = self.active_conversation.get_untracked() {
+            self.load_cost(conv);
+        }
+        // The turn loaded its model, if it was cold.
+        let state = self.clone();
+        spawn_local(async move { state.load_system_status().await });
+    }
+
+    fn end_streaming(&self) {
+        self.set_streaming_text.set(None);
+        self.set_queue_position.set(None);
+        self.set_streaming_tools.set(Vec::new());
+        self.set_is_streaming.set(false);
+    }
+
+    /// Pulls the missing model, then retries the turn that needed it.
+    pub fn pull_missing_model(&self) {
+        if !self.require_online() {
+            return;
+        }
+        let Some(model) = self.missing_model.get_untracked() else { return };
+        let state = self.clone();
+        self.set_pull_progress.set(Some(PullProgress {
+            status: "starting".to_string(),
+            digest: None,
+            total: None,
+            completed: None,
+        }));
+
+        spawn_local(async move {
+            let set_progress = state.set_pull_progress;
+            let result =
+                api::pull_model(Some(model.clone()), move |p| set_progress.set(Some(p))).await;
+            state.set_pull_progress.set(None);
+            match result {
+                Ok(()) => {
+                    let text = state.locale.get_untracked().tr(Text::ModelPulled);
+                    state.notify(NotificationKind::Info, fill(text, &[("model", &model)]), None);
+                    state.set_missing_model.set(None);
+                    state.retry_turn();
+                }
+                Err(e) => {
+                    log::error!("Failed to pull model: {e}");
+                    state.notify_error(e, Some(RetryAction::PullModel));
+                }
+            }
+        });
+    }
+
+    /// Generate a new answer for an assistant message, keeping the old one as a version.
+    pub fn regenerate(&self, message_id: Uuid) {
+        if !self.require_online() {
+            return;
+        }
+        let state = self.clone();
+        self.set_regenerating.set(Some(message_id));
 
-        let on_error = move |err: String| {
-            log::error!("WebSocket error: {err}");
-            set_error.set(Some(err));
-            set_streaming.set(None);
-            set_is_streaming.set(false);
+        spawn_local(async move {
+            match api::regenerate_message(message_id).await {
+                Ok(msg) => {
+                    // Each version has artifacts of its own.
+                    state.load_artifacts(msg.conversation_id);
+                    state.replace_message(msg);
+                }
+                Err(e) => {
+                    log::error!("Failed to regenerate message: {e}");
+                    state.notify_error(e, Some(RetryAction::Regenerate(message_id)));
+                }
+            }
+            state.set_regenerating.set(None);
+        });
+    }
+
+    /// Show a different version of a regenerated message.
+    pub fn switch_version(&self, message_id: Uuid, version: i32) {
+        if !self.require_online() {
+            return;
+        }
+        let state = self.clone();
+        spawn_local(async move {
+            match api::set_active_version(message_id, version).await {
+                Ok(msg) => {
+                    // Each version has artifacts of its own.
+                    state.load_artifacts(msg.conversation_id);
+                    state.replace_message(msg);
+                }
+                Err(e) => {
+                    log::error!("Failed to switch message version: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    }
+
+    /// Show the starred messages of every conversation.
+    pub fn open_starred(&self) {
+        self.set_sidebar_open.set(false);
+        self.set_show_admin.set(false);
+        self.set_show_starred.set(true);
+        let state = self.clone();
+        spawn_local(async move {
+            match api::fetch_starred().await {
+                Ok(starred) => state.set_starred.set(starred),
+                Err(e) => {
+                    log::error!("Failed to fetch starred messages: {e}");
+                    state.notify_error(e, Some(RetryAction::LoadStarred));
+                }
+            }
+        });
+    }
+
+    /// Star or unstar a message, wherever it is shown.
+    pub fn set_message_starred(&self, message_id: Uuid, starred: bool) {
+        if !self.require_online() {
+            return;
+        }
+        let state = self.clone();
+        spawn_local(async move {
+            match api::set_starred(message_id, starred).await {
+                Ok(msg) => {
+                    if !starred {
+                        state.set_starred.update(|list| list.retain(|s| s.message.id != msg.id));
+                    }
+                    state.replace_message(msg);
+                }
+                Err(e) => {
+                    log::error!("Failed to update star: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    }
+
+    /// Switch the active conversation to a preset, or (with `None`) back to
+    /// the default assistant. The server notes the switch in the
+    /// conversation, so its messages are reloaded.
+    pub fn switch_preset(&self, preset: Option<String>) {
+        if !self.require_online() {
+            return;
+        }
+        let Some(id) = self.active_conversation.get_untracked() else { return };
+        let state = self.clone();
+        spawn_local(async move {
+            match api::set_conversation_preset(id, preset.as_deref()).await {
+                Ok(updated) => {
+                    state.set_preset.set(updated.preset.clone());
+                    state.set_conversations.update(|convos| {
+                        if let Some(conv) = convos.iter_mut().find(|c| c.id == updated.id) {
+                            *conv = updated;
+                        }
+                    });
+                    state.reload_messages();
+                }
+                Err(e) => {
+                    log::error!("Failed to switch preset: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    }
+
+    /// Set or clear a conversation's icon and color.
+    pub fn set_conversation_appearance(
+        &self,
+        id: Uuid,
+        icon: Option<String>,
+        color: Option<String>,
+    ) {
+        if !self.require_online() {
+            return;
+        }
+        let state = self.clone();
+        spawn_local(async move {
+            match api::update_conversation_appearance(id, icon.as_deref(), color.as_deref()).await
+            {
+                Ok(updated) => state.set_conversations.update(|convos| {
+                    if let Some(conv) = convos.iter_mut().find(|c| c.id == updated.id) {
+                        *conv = updated;
+                    }
+                }),
+                Err(e) => {
+                    log::error!("Failed to update conversation: {e}");
+                    state.notify_error(e, None);
+                }
+            }
+        });
+    }
+
+    /// Shows a toast that dismisses itself after a while.
+    pub fn notify(&self, kind: NotificationKind, message: String, retry: Option<RetryAction>) {
+        self.next_notification_id.update_value(|id| *id += 1);
+        let id = self.next_notification_id.get_value();
+        let timeout = match (kind, &retry) {
+            (_, Some(_)) => ACTION_TIMEOUT_MS,
+            (NotificationKind::Error, None) => ERROR_TIMEOUT_MS,
+            (NotificationKind::Info, None) => INFO_TIMEOUT_MS,
         };
+        self.set_notifications.update(|list| {
+            list.push(Notification { id, kind, message, retry });
+            if list.len() > MAX_NOTIFICATIONS {
+                list.remove(0);
+            }
+        });
 
-        ws::start_streaming(text, conv_id, on_start, on_chunk, on_end, on_error);
+        let state = self.clone();
+        spawn_local(async move {
+            TimeoutFuture::new(timeout).await;
+            state.dismiss(id);
+        });
+    }
+
+    pub fn notify_error(&self, message: String, retry: Option<RetryAction>) {
+        self.notify(NotificationKind::Error, message, retry);
+    }
+
+    pub fn dismiss(&self, id: u64) {
+        self.set_notifications.update(|list| list.retain(|n| n.id != id));
+    }
+
+    /// Runs a toast's retry action and dismisses it.
+    pub fn retry(&self, id: u64) {
+        let action = self
+            .notifications
+            .get_untracked()
+            .into_iter()
+            .find(|n| n.id == id)
+            .and_then(|n| n.retry);
+        self.dismiss(id);
+        match action {
+            Some(RetryAction::Resend(text, quoted_message_id)) => {
+                // Drop the optimistic copy left by the failed attempt.
+                self.set_messages.update(|msgs| {
+                    if msgs.last().is_some_and(|m| !is_saved(m.id) && m.content == text) {
+                        msgs.pop();
+                    }
+                });
+                self.send_message(text, quoted_message_id);
+            }
+            Some(RetryAction::LoadConversations) => self.load_conversations(),
+            Some(RetryAction::LoadMessages(id)) => self.select_conversation(id),
+            Some(RetryAction::LoadStarred) => self.open_starred(),
+            Some(RetryAction::Regenerate(message_id)) => self.regenerate(message_id),
+            Some(RetryAction::PullModel) => self.pull_missing_model(),
+            None => {}
+        }
+    }
+
+    fn replace_message(&self, updated: Message) {
+        self.set_messages.update(|msgs| {
+            if let Some(m) = msgs.iter_mut().find(|m| m.id == updated.id) {
+                *m = updated;
+            }
+        });
     }
 }
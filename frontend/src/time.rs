@@ -0,0 +1,68 @@
+//! Message timestamps: "2 min ago" labels and day separators, in the
+//! browser's time zone.
+
+use chrono::{DateTime, Utc};
+use js_sys::Date;
+use wasm_bindgen::JsValue;
+
+use crate::i18n::{fill, Locale, Text};
+
+const MINUTE_MS: f64 = 60_000.0;
+const HOUR_MS: f64 = 60.0 * MINUTE_MS;
+const DAY_MS: f64 = 24.0 * HOUR_MS;
+
+/// Milliseconds since the epoch of a timestamp, as `js_sys::Date` counts them.
+pub fn millis(at: &DateTime<Utc>) -> f64 {
+    at.timestamp_millis() as f64
+}
+
+/// "just now", "5 min ago", "3 h ago", then the date for anything older
+/// than a week.
+pub fn relative(then: f64, now: f64, locale: Locale) -> String {
+    let elapsed = (now - then).max(0.0);
+    let count = |unit: f64| ((elapsed / unit).floor() as u64).to_string();
+    if elapsed < MINUTE_MS {
+        locale.tr(Text::JustNow).to_string()
+    } else if elapsed < HOUR_MS {
+        fill(locale.tr(Text::MinutesAgo), &[("count", &count(MINUTE_MS))])
+    } else if elapsed < DAY_MS {
+        fill(locale.tr(Text::HoursAgo), &[("count", &count(HOUR_MS))])
+    } else if elapsed < 7.0 * DAY_MS {
+        fill(locale.tr(Text::DaysAgo), &[("count", &count(DAY_MS))])
+    } else {
+        date(then, locale)
+    }
+}
+
+/// Full local date and time, for tooltips.
+pub fn absolute(ms: f64, locale: Locale) -> String {
+    let date = Date::new(&JsValue::from_f64(ms));
+    let day = date.to_locale_date_string(locale.code(), &JsValue::UNDEFINED);
+    let time = date.to_locale_time_string(locale.code());
+    format!("{day} {time}")
+}
+
+/// Whether two timestamps fall on the same local day.
+pub fn same_day(a: f64, b: f64) -> bool {
+    let (a, b) = (Date::new(&JsValue::from_f64(a)), Date::new(&JsValue::from_f64(b)));
+    a.get_full_year() == b.get_full_year()
+        && a.get_month() == b.get_month()
+        && a.get_date() == b.get_date()
+}
+
+/// "Today", "Yesterday" or the date, for separators between days.
+pub fn day_label(ms: f64, now: f64, locale: Locale) -> String {
+    if same_day(ms, now) {
+        locale.tr(Text::Today).to_string()
+    } else if same_day(ms, now - DAY_MS) {
+        locale.tr(Text::Yesterday).to_string()
+    } else {
+        date(ms, locale)
+    }
+}
+
+fn date(ms: f64, locale: Locale) -> String {
+    Date::new(&JsValue::from_f64(ms))
+        .to_locale_date_string(locale.code(), &JsValue::UNDEFINED)
+        .into()
+}
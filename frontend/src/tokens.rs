@@ -0,0 +1,95 @@
+//! Token counts of the next turn, for the warning shown in the chat input as
+//! it nears the model's context size.
+//!
+//! Counts come from `POST /api/tokenize`; rough estimates stand in until they
+//! arrive and while the text they were made for is being edited.
+
+use uuid::Uuid;
+
+use crate::models::Message;
+
+/// Characters per token of typical English text with Llama-style tokenizers.
+const CHARS_PER_TOKEN: usize = 4;
+/// Role markers and separators the chat template adds around each message.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// Share of the context window at which the warning appears.
+const WARN_AT_PERCENT: usize = 80;
+
+/// Approximate tokens of one message.
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) + TOKENS_PER_MESSAGE
+}
+
+/// The history the next turn sends: the messages after `cutoff` (the newest
+/// one the history limit left out last turn).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History {
+    /// Their contents, one per line, to be counted in one request.
+    pub text: String,
+    pub messages: usize,
+    pub estimate: usize,
+}
+
+impl History {
+    pub fn new(messages: &[Message], cutoff: Option<Uuid>) -> Self {
+        let sent = match cutoff.and_then(|id| messages.iter().position(|m| m.id == id)) {
+            Some(index) => &messages[index + 1..],
+            None => messages,
+        };
+        Self {
+            text: sent.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n"),
+            messages: sent.len(),
+            estimate: sent.iter().map(|m| estimate(&m.content)).sum(),
+        }
+    }
+}
+
+/// Server token counts, each with the text it was made for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub history: Option<(String, usize)>,
+    pub draft: Option<(String, usize)>,
+}
+
+/// The count in `counted` if it is for `text`.
+fn count_of(counted: &Option<(String, usize)>, text: &str) -> Option<usize> {
+    counted.as_ref().filter(|(counted, _)| counted == text).map(|(_, tokens)| *tokens)
+}
+
+/// What the next turn is expected to send the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenBudget {
+    pub history: usize,
+    pub message: usize,
+    pub limit: usize,
+}
+
+impl TokenBudget {
+    /// Counts `history` plus the message being written, from `counts` where
+    /// they are current and estimates otherwise.
+    pub fn new(history: &History, draft: &str, limit: usize, counts: &Counts) -> Self {
+        let history = match count_of(&counts.history, &history.text) {
+            Some(tokens) => tokens + history.messages * TOKENS_PER_MESSAGE,
+            None => history.estimate,
+        };
+        let message = match count_of(&counts.draft, draft) {
+            _ if draft.trim().is_empty() => 0,
+            Some(tokens) => tokens + TOKENS_PER_MESSAGE,
+            None => estimate(draft),
+        };
+        Self { history, message, limit }
+    }
+
+    pub fn total(&self) -> usize {
+        self.history + self.message
+    }
+
+    /// Close enough to the limit to warn about.
+    pub fn is_near(&self) -> bool {
+        self.limit > 0 && self.total() * 100 >= self.limit * WARN_AT_PERCENT
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.limit > 0 && self.total() > self.limit
+    }
+}
@@ -1,83 +1,392 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::{Rc, Weak};
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use gloo_timers::future::TimeoutFuture;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use uuid::Uuid;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{MessageEvent, WebSocket};
-
-use crate::api::ws_url;
-use crate::models::{WsChatRequest, WsEvent};
-
-/// Opens a WebSocket connection, sends a chat request, and invokes callbacks
-/// for each streaming event. Returns a handle that auto-closes on drop.
-pub fn start_streaming(
-    message: String,
-    conversation_id: Option<String>,
-    on_start: impl Fn(String) + 'static,
-    on_chunk: impl Fn(String) + 'static,
-    on_end: impl Fn(String) + 'static,
-    on_error: impl Fn(String) + 'static,
-) -> Option<WebSocket> {
-    let url = ws_url();
-    let ws = match WebSocket::new(&url) {
-        Ok(ws) => ws,
-        Err(e) => {
-            on_error(format!("Failed to connect: {e:?}"));
-            return None;
+use web_sys::{BinaryType, MessageEvent, WebSocket};
+
+use crate::api::{events_url, ws_url};
+use crate::models::{ErrorCode, WsChatRequest, WsControl, WsEncoding, WsEvent, WsFrame};
+
+/// First reconnect delay; doubled after every failed attempt.
+const RECONNECT_BASE_MS: u32 = 500;
+const RECONNECT_MAX_MS: u32 = 30_000;
+/// Encoding each chat socket asks for in its `hello`. MessagePack frames are
+/// smaller; JSON ones are easier to read in the browser's devtools.
+const ENCODING: WsEncoding = WsEncoding::Msgpack;
+
+/// State of the shared chat socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WsStatus {
+    Connecting,
+    Open,
+    /// The socket dropped and a new one is being attempted.
+    Reconnecting,
+}
+
+/// Events of one chat turn, in order. Ends after the turn's final event
+/// (`stream_end`, `model_missing`, `moderation_blocked` or `error`).
+pub struct ChatStream {
+    events: UnboundedReceiver<WsEvent>,
+}
+
+impl ChatStream {
+    pub async fn next(&mut self) -> Option<WsEvent> {
+        self.events.next().await
+    }
+}
+
+/// One long-lived WebSocket shared by every chat turn. Turns are told apart
+/// by `stream_id`; requests made while the socket is down wait and go out
+/// once it reconnects.
+#[derive(Clone)]
+pub struct WsClient {
+    inner: Rc<RefCell<Inner>>,
+    status: RwSignal<WsStatus>,
+}
+
+struct Inner {
+    socket: Option<WebSocket>,
+    open: bool,
+    turns: HashMap<String, Turn>,
+    /// Requests waiting for the socket to open.
+    outbox: VecDeque<(String, String)>,
+    failed_attempts: u32,
+    reconnect_pending: bool,
+    /// Prefixes this tab's stream ids, which other tabs may see while
+    /// watching the same conversation.
+    tab_id: String,
+    next_stream: u64,
+    /// Keep the current socket's handlers alive; replaced on reconnect.
+    handlers: Vec<Closure<dyn FnMut(JsValue)>>,
+    /// The latest `follow_up_suggestions`: reply id and prompts.
+    suggestions: RwSignal<Option<(Uuid, Vec<String>)>>,
+}
+
+struct Turn {
+    events: UnboundedSender<WsEvent>,
+    /// Whether the request reached the server. Such turns cannot survive a
+    /// dropped socket; unsent ones are simply sent again.
+    sent: bool,
+}
+
+impl WsClient {
+    /// Creates the client; the socket opens on the first request.
+    pub fn new() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Inner {
+                socket: None,
+                open: false,
+                turns: HashMap::new(),
+                outbox: VecDeque::new(),
+                failed_attempts: 0,
+                reconnect_pending: false,
+                tab_id: format!("{:08x}", (js_sys::Math::random() * f64::from(u32::MAX)) as u32),
+                next_stream: 0,
+                handlers: Vec::new(),
+                suggestions: RwSignal::new(None),
+            })),
+            status: RwSignal::new(WsStatus::Connecting),
         }
-    };
-    ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
-
-    // --- onopen: send the chat request ---
-    let ws_clone = ws.clone();
-    let onopen = Closure::<dyn Fn()>::new(move || {
-        let req = WsChatRequest {
-            message: message.clone(),
-            conversation_id: conversation_id.clone(),
-        };
-        if let Ok(json) = serde_json::to_string(&req) {
-            let _ = ws_clone.send_with_str(&json);
+    }
+
+    pub fn status(&self) -> ReadSignal<WsStatus> {
+        self.status.read_only()
+    }
+
+    /// Follow-up suggestions for replies, which arrive after their turn's
+    /// events have ended.
+    pub fn suggestions(&self) -> ReadSignal<Option<(Uuid, Vec<String>)>> {
+        self.inner.borrow().suggestions.read_only()
+    }
+
+    /// Sends `request` as a new turn and returns its events.
+    pub fn stream(&self, mut request: WsChatRequest) -> ChatStream {
+        let (tx, rx) = unbounded();
+        let mut inner = self.inner.borrow_mut();
+        inner.next_stream += 1;
+        let stream_id = format!("turn-{}-{}", inner.tab_id, inner.next_stream);
+        request.stream_id = Some(stream_id.clone());
+
+        match serde_json::to_string(&request) {
+            Ok(json) => {
+                inner.turns.insert(stream_id.clone(), Turn { events: tx, sent: false });
+                inner.outbox.push_back((stream_id, json));
+            }
+            Err(e) => {
+                let message = format!("Serialize error: {e}");
+                let _ = tx.unbounded_send(WsEvent::Error { code: ErrorCode::Internal, message });
+            }
         }
-    });
-    ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
-    onopen.forget();
-
-    // --- onmessage: dispatch WsEvent ---
-    let onmessage = Closure::<dyn Fn(MessageEvent)>::new(move |ev: MessageEvent| {
-        if let Some(text) = ev.data().as_string() {
-            match serde_json::from_str::<WsEvent>(&text) {
-                Ok(WsEvent::StreamStart { conversation_id }) => {
-                    on_start(conversation_id);
-                }
-                Ok(WsEvent::StreamChunk { content }) => {
-                    on_chunk(content);
-                }
-                Ok(WsEvent::StreamEnd { full_content, .. }) => {
-                    on_end(full_content);
+
+        if inner.open {
+            inner.flush();
+        } else if inner.socket.is_none() && !inner.reconnect_pending {
+            drop(inner);
+            self.connect();
+        }
+        ChatStream { events: rx }
+    }
+
+    /// Reconnects right away instead of waiting out the backoff, e.g. once
+    /// the browser is back online, so queued requests go out.
+    pub fn reconnect_now(&self) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            if inner.socket.is_some() {
+                return;
+            }
+            inner.failed_attempts = 0;
+            // The pending timer finds nothing to do.
+            inner.reconnect_pending = false;
+        }
+        self.connect();
+    }
+
+    /// Whether `stream_id` is one of this tab's turns.
+    pub fn is_own(&self, stream_id: &str) -> bool {
+        let inner = self.inner.borrow();
+        stream_id.strip_prefix("turn-").is_some_and(|rest| {
+            rest.strip_prefix(inner.tab_id.as_str()).is_some_and(|n| n.starts_with('-'))
+        })
+    }
+
+    fn connect(&self) {
+        let socket = match WebSocket::new(&ws_url()) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to open WebSocket: {e:?}");
+                self.schedule_reconnect();
+                return;
+            }
+        };
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        let weak = Rc::downgrade(&self.inner);
+        let status = self.status;
+
+        let onopen = Closure::<dyn FnMut(JsValue)>::new({
+            let weak = weak.clone();
+            move |_| {
+                let Some(inner) = weak.upgrade() else { return };
+                {
+                    let mut inner = inner.borrow_mut();
+                    inner.open = true;
+                    inner.failed_attempts = 0;
+                    inner.say_hello();
+                    inner.flush();
                 }
-                Ok(WsEvent::Error { message }) => {
-                    on_error(message);
+                status.set(WsStatus::Open);
+            }
+        });
+
+        let onmessage = Closure::<dyn FnMut(JsValue)>::new({
+            let weak = weak.clone();
+            move |ev: JsValue| {
+                let frame = match decode_frame(ev.unchecked_into::<MessageEvent>().data()) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        log::error!("Unreadable WebSocket frame: {e}");
+                        return;
+                    }
+                };
+                let Some(inner) = weak.upgrade() else { return };
+                inner.borrow_mut().dispatch(frame);
+            }
+        });
+
+        let client = WeakClient { inner: weak, status };
+        let onclose = Closure::<dyn FnMut(JsValue)>::new(move |_| {
+            let Some(client) = client.upgrade() else { return };
+            client.disconnected();
+        });
+
+        socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+        let mut inner = self.inner.borrow_mut();
+        inner.socket = Some(socket);
+        inner.handlers = vec![onopen, onmessage, onclose];
+    }
+
+    /// The socket closed (or never opened): fail the turns it was carrying
+    /// and try again after a backoff.
+    fn disconnected(&self) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.socket = None;
+            inner.open = false;
+            inner.turns.retain(|_, turn| {
+                if turn.sent {
+                    let message = "Connection to the server was lost".to_string();
+                    let code = ErrorCode::Disconnected;
+                    let _ = turn.events.unbounded_send(WsEvent::Error { code, message });
                 }
-                Err(e) => {
-                    on_error(format!("Parse error: {e}"));
+                !turn.sent
+            });
+        }
+        log::warn!("WebSocket closed");
+        self.schedule_reconnect();
+    }
+
+    fn schedule_reconnect(&self) {
+        let delay = {
+            let mut inner = self.inner.borrow_mut();
+            if inner.reconnect_pending {
+                return;
+            }
+            inner.reconnect_pending = true;
+            inner.failed_attempts += 1;
+            let doublings = inner.failed_attempts.saturating_sub(1).min(16);
+            RECONNECT_BASE_MS.saturating_mul(1 << doublings).min(RECONNECT_MAX_MS)
+        };
+        self.status.set(WsStatus::Reconnecting);
+
+        let client = self.clone();
+        spawn_local(async move {
+            TimeoutFuture::new(delay).await;
+            {
+                let mut inner = client.inner.borrow_mut();
+                if !inner.reconnect_pending {
+                    // `reconnect_now` got there first.
+                    return;
                 }
+                inner.reconnect_pending = false;
+            }
+            client.connect();
+        });
+    }
+}
+
+impl Default for WsClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle held by the socket's own handlers, so they do not keep the client
+/// alive.
+struct WeakClient {
+    inner: Weak<RefCell<Inner>>,
+    status: RwSignal<WsStatus>,
+}
+
+impl WeakClient {
+    fn upgrade(&self) -> Option<WsClient> {
+        Some(WsClient { inner: self.inner.upgrade()?, status: self.status })
+    }
+}
+
+/// Reads a server frame: JSON from text messages, MessagePack from binary ones.
+fn decode_frame(data: JsValue) -> Result<WsFrame, String> {
+    if let Some(text) = data.as_string() {
+        return serde_json::from_str(&text).map_err(|e| e.to_string());
+    }
+    let bytes = js_sys::Uint8Array::new(&data).to_vec();
+    rmp_serde::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+impl Inner {
+    /// Asks a new socket for [`ENCODING`], ahead of any queued request.
+    fn say_hello(&self) {
+        let Some(socket) = &self.socket else { return };
+        if ENCODING == WsEncoding::Json {
+            return;
+        }
+        let hello = WsControl::Hello { encoding: ENCODING };
+        if let Ok(json) = serde_json::to_string(&hello) {
+            let _ = socket.send_with_str(&json);
+        }
+    }
+
+    /// Sends every queued request over the open socket.
+    fn flush(&mut self) {
+        let Some(socket) = self.socket.clone() else { return };
+        while let Some((stream_id, json)) = self.outbox.pop_front() {
+            if socket.send_with_str(&json).is_err() {
+                self.outbox.push_front((stream_id, json));
+                return;
+            }
+            if let Some(turn) = self.turns.get_mut(&stream_id) {
+                turn.sent = true;
             }
         }
-    });
-    ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
-    onmessage.forget();
-
-    // --- onerror ---
-    let on_error_clone = {
-        // We can't move on_error again so we use a simple log here
-        Closure::<dyn Fn()>::new(move || {
-            log::error!("WebSocket connection error");
-        })
-    };
-    ws.set_onerror(Some(on_error_clone.as_ref().unchecked_ref()));
-    on_error_clone.forget();
+    }
 
-    Some(ws)
+    /// Routes one server frame to the turn it belongs to.
+    fn dispatch(&mut self, frame: WsFrame) {
+        match frame.event {
+            WsEvent::FollowUpSuggestions { message_id, suggestions } => {
+                self.suggestions.set(Some((message_id, suggestions)));
+                return;
+            }
+            // Later frames are decoded by what kind of message they come in.
+            WsEvent::Hello { .. } => return,
+            _ => {}
+        }
+        let Some(stream_id) = frame.stream_id else {
+            log::error!("WebSocket event for no stream: {:?}", frame.event);
+            return;
+        };
+        let finished = matches!(
+            frame.event,
+            WsEvent::StreamEnd { .. }
+                | WsEvent::ModelMissing { .. }
+                | WsEvent::ModerationBlocked { .. }
+                | WsEvent::Busy { .. }
+                | WsEvent::Error { .. }
+        );
+        if let Some(turn) = self.turns.get(&stream_id) {
+            let _ = turn.events.unbounded_send(frame.event);
+        }
+        if finished {
+            self.turns.remove(&stream_id);
+        }
+    }
+}
+
+/// A socket on a conversation's live turns, whichever tab or client started
+/// them. Closed when dropped; not reconnected, since the next conversation
+/// switch opens a new one anyway.
+pub struct ConversationWatcher {
+    socket: WebSocket,
+    _onmessage: Closure<dyn FnMut(JsValue)>,
+}
+
+impl ConversationWatcher {
+    /// Watches `conversation_id`, passing every frame to `on_frame`.
+    pub fn open(
+        conversation_id: Uuid,
+        mut on_frame: impl FnMut(WsFrame) + 'static,
+    ) -> Option<Self> {
+        let socket = match WebSocket::new(&events_url(conversation_id)) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to watch conversation: {e:?}");
+                return None;
+            }
+        };
+        let onmessage = Closure::<dyn FnMut(JsValue)>::new(move |ev: JsValue| {
+            match decode_frame(ev.unchecked_into::<MessageEvent>().data()) {
+                Ok(frame) => on_frame(frame),
+                Err(e) => log::error!("Unreadable WebSocket frame: {e}"),
+            }
+        });
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        Some(Self { socket, _onmessage: onmessage })
+    }
 }
 
-/// Close a WebSocket connection gracefully.
-pub fn close_ws(ws: &WebSocket) {
-    let _ = ws.close();
+impl Drop for ConversationWatcher {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        let _ = self.socket.close();
+    }
 }
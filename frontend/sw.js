@@ -0,0 +1,84 @@
+// Service worker: keeps the app shell and the last-fetched history so the app
+// opens, and conversations can be read, while offline. Registered by
+// src/pwa.rs; copied next to index.html by Trunk.
+
+// Bump to drop everything cached by an older version.
+const VERSION = "v1";
+const SHELL_CACHE = `shell-${VERSION}`;
+const API_CACHE = `api-${VERSION}`;
+
+// API reads kept for offline browsing: the conversation list, each
+// conversation's messages and stats, the profile and the starred messages.
+const CACHED_API = [
+    /\/api\/conversations(\/[^/]+\/(messages|stats))?$/,
+    /\/api\/me$/,
+    /\/api\/starred$/,
+];
+
+self.addEventListener("install", (event) => {
+    event.waitUntil(
+        caches.open(SHELL_CACHE)
+            .then((cache) => cache.addAll(["/", "/manifest.webmanifest", "/icon.svg"]))
+            .then(() => self.skipWaiting()),
+    );
+});
+
+self.addEventListener("activate", (event) => {
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(
+                keys.filter((key) => key !== SHELL_CACHE && key !== API_CACHE)
+                    .map((key) => caches.delete(key)),
+            ))
+            .then(() => self.clients.claim()),
+    );
+});
+
+self.addEventListener("fetch", (event) => {
+    const request = event.request;
+    if (request.method !== "GET") {
+        return;
+    }
+    const url = new URL(request.url);
+    if (CACHED_API.some((pattern) => pattern.test(url.pathname))) {
+        event.respondWith(networkFirst(request, API_CACHE));
+    } else if (request.mode === "navigate") {
+        // Client-side routes all load the same page.
+        event.respondWith(networkFirst(request, SHELL_CACHE, "/"));
+    } else if (url.origin === self.location.origin && !url.pathname.startsWith("/api/")) {
+        // Trunk hashes asset names, so a cached asset never goes stale.
+        event.respondWith(cacheFirst(request, SHELL_CACHE));
+    }
+});
+
+// Fetches `request`, keeping a copy of good responses; offline, answers with
+// the last copy (or that of `fallback`).
+async function networkFirst(request, cacheName, fallback) {
+    const cache = await caches.open(cacheName);
+    try {
+        const response = await fetch(request);
+        if (response.ok) {
+            await cache.put(fallback ?? request, response.clone());
+        }
+        return response;
+    } catch (error) {
+        const cached = await cache.match(fallback ?? request);
+        if (cached) {
+            return cached;
+        }
+        throw error;
+    }
+}
+
+async function cacheFirst(request, cacheName) {
+    const cache = await caches.open(cacheName);
+    const cached = await cache.match(request);
+    if (cached) {
+        return cached;
+    }
+    const response = await fetch(request);
+    if (response.ok) {
+        await cache.put(request, response.clone());
+    }
+    return response;
+}
@@ -0,0 +1,2 @@
+-- Web pages cited by an assistant message, in footnote order.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS sources JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,18 @@
+-- Citations move from the JSONB column into their own table so any retrieval
+-- path (web search, documents, …) can attach scored sources to a message.
+CREATE TABLE IF NOT EXISTS message_sources (
+    message_id VARCHAR(36)      NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    position   INTEGER          NOT NULL,
+    url        TEXT             NOT NULL,
+    title      TEXT             NOT NULL,
+    snippet    TEXT             NOT NULL,
+    score      DOUBLE PRECISION,
+    PRIMARY KEY (message_id, position)
+);
+
+INSERT INTO message_sources (message_id, position, url, title, snippet)
+SELECT m.id, s.ordinality, s.value->>'url', COALESCE(s.value->>'title', ''), COALESCE(s.value->>'snippet', '')
+FROM messages m, jsonb_array_elements(m.sources) WITH ORDINALITY AS s(value, ordinality)
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE messages DROP COLUMN IF EXISTS sources;
@@ -0,0 +1,16 @@
+-- Regenerated assistant replies keep every attempt. messages.content always
+-- holds the active version so history replay stays a plain read.
+CREATE TABLE IF NOT EXISTS message_versions (
+    message_id VARCHAR(36) NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    version    INTEGER     NOT NULL,
+    content    TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, version)
+);
+
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS active_version INTEGER NOT NULL DEFAULT 1;
+
+-- Sources belong to a specific version of a message.
+ALTER TABLE message_sources ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE message_sources DROP CONSTRAINT IF EXISTS message_sources_pkey;
+ALTER TABLE message_sources ADD PRIMARY KEY (message_id, version, position);
@@ -0,0 +1,3 @@
+-- Language the user writes in, as an ISO 639-3 code ("eng", "spa", ...).
+-- NULL until a message is long enough to detect it reliably.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS language VARCHAR(3);
@@ -0,0 +1,11 @@
+-- Per-user preferences. Until authentication exists every request acts as
+-- the single 'local' user. NULL columns fall back to the server defaults.
+CREATE TABLE IF NOT EXISTS user_profiles (
+    user_id             VARCHAR(64) PRIMARY KEY,
+    display_name        TEXT,
+    preferred_model     TEXT,
+    temperature         DOUBLE PRECISION,
+    theme               VARCHAR(16),
+    custom_instructions TEXT,
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
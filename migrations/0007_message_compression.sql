@@ -0,0 +1,3 @@
+-- Large message bodies are stored zstd-compressed. When content_compressed is
+-- set, content is left empty and the repository decompresses on read.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS content_compressed BYTEA;
@@ -0,0 +1,8 @@
+-- Token usage of assistant replies, for cost reporting. Only streamed turns
+-- report usage; other replies have no row.
+CREATE TABLE IF NOT EXISTS message_usage (
+    message_id        VARCHAR(36) PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    model             TEXT        NOT NULL,
+    prompt_tokens     BIGINT,
+    completion_tokens BIGINT
+);
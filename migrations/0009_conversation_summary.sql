@@ -0,0 +1,6 @@
+-- Short generated summary of each conversation, shown under its title.
+-- `summary_message_count` is how many messages the summary covered, so the
+-- background job knows when it is due again.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS summary TEXT;
+ALTER TABLE conversations
+    ADD COLUMN IF NOT EXISTS summary_message_count INTEGER NOT NULL DEFAULT 0;
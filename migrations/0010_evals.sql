@@ -0,0 +1,25 @@
+-- Eval runs replay stored conversations' user turns against another model.
+-- Each result keeps the original reply next to the new one. Results do not
+-- reference conversations, so reports outlive deleted conversations.
+CREATE TABLE IF NOT EXISTS eval_runs (
+    id           VARCHAR(36) PRIMARY KEY,
+    model        TEXT        NOT NULL,
+    status       VARCHAR(16) NOT NULL,
+    error        TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS eval_results (
+    id               VARCHAR(36) PRIMARY KEY,
+    run_id           VARCHAR(36) NOT NULL REFERENCES eval_runs(id) ON DELETE CASCADE,
+    conversation_id  VARCHAR(36) NOT NULL,
+    turn             INTEGER     NOT NULL,
+    user_message     TEXT        NOT NULL,
+    original_content TEXT,
+    new_content      TEXT,
+    error            TEXT,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_eval_results_run ON eval_results(run_id, conversation_id, turn);
@@ -0,0 +1,4 @@
+-- Per-conversation override of the `max_history_messages` setting: how many
+-- earlier messages are sent to the model with each turn. NULL follows the
+-- global setting; 0 sends the whole conversation.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS max_history_messages INTEGER;
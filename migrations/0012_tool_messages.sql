@@ -0,0 +1,5 @@
+-- Tool interactions are stored as messages of their own: a FUNCTION message
+-- is a call the model made (content holds the JSON arguments) and a TOOL
+-- message is what the tool returned. `tool_call_id` pairs the two.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS tool_name VARCHAR(100);
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS tool_call_id VARCHAR(100);
@@ -0,0 +1,21 @@
+-- Batch jobs answer a list of standalone prompts in the background. Items
+-- are numbered by their position in the request.
+CREATE TABLE IF NOT EXISTS batch_jobs (
+    id           VARCHAR(36) PRIMARY KEY,
+    status       VARCHAR(16) NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS batch_items (
+    job_id       VARCHAR(36) NOT NULL REFERENCES batch_jobs(id) ON DELETE CASCADE,
+    position     INTEGER     NOT NULL,
+    prompt       TEXT        NOT NULL,
+    model        TEXT        NOT NULL,
+    status       VARCHAR(16) NOT NULL,
+    content      TEXT,
+    error        TEXT,
+    started_at   TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+    PRIMARY KEY (job_id, position)
+);
@@ -0,0 +1,11 @@
+-- Maintenance switch, set through `PUT /api/admin/maintenance`. A single row:
+-- while `enabled`, chat and every other write is refused with 503 and only
+-- reads (history, search, export) are served.
+CREATE TABLE IF NOT EXISTS maintenance_mode (
+    id          SMALLINT PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    enabled     BOOLEAN NOT NULL DEFAULT FALSE,
+    message     TEXT,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO maintenance_mode (id) VALUES (1) ON CONFLICT (id) DO NOTHING;
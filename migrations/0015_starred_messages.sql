@@ -0,0 +1,8 @@
+-- Messages the user starred to find again later (GET /api/starred). A row per
+-- starred message; unstarring deletes it.
+CREATE TABLE IF NOT EXISTS starred_messages (
+    message_id  VARCHAR(36) PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    starred_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_starred_messages_starred_at ON starred_messages (starred_at DESC);
@@ -0,0 +1,4 @@
+-- Why the conversation's last turn failed before a reply was saved (model
+-- unavailable, generation error, ...). Cleared by the next saved reply; the
+-- turn can be re-run with POST /api/conversations/{id}/retry-last.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS failed_turn_error TEXT;
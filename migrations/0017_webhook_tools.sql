@@ -0,0 +1,18 @@
+-- Tools users register for the model to call over HTTP. A tool without a
+-- conversation is offered in every conversation; a conversation's own tool
+-- replaces a global one of the same name.
+CREATE TABLE IF NOT EXISTS webhook_tools (
+    id              VARCHAR(36) PRIMARY KEY,
+    conversation_id VARCHAR(36) REFERENCES conversations(id) ON DELETE CASCADE,
+    name            VARCHAR(64) NOT NULL,
+    description     TEXT        NOT NULL,
+    -- JSON schema of the arguments
+    parameters      TEXT        NOT NULL,
+    url             TEXT        NOT NULL,
+    -- Sent as the Authorization header; never returned by the API
+    auth_header     TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_webhook_tools_scope_name
+    ON webhook_tools ((COALESCE(conversation_id, '')), name);
@@ -0,0 +1,8 @@
+-- Conversations can be encrypted with a passphrase. Their message bodies (and
+-- earlier versions) are then stored sealed with AES-256-GCM under a key derived
+-- from the passphrase and key_salt; key_check is a known value sealed with the
+-- same key, to tell a wrong passphrase from a right one. The passphrase and
+-- key are never stored.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS encrypted BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS key_salt TEXT;
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS key_check TEXT;
@@ -0,0 +1,4 @@
+-- An emoji (or other short symbol) and a #rrggbb color picked for a
+-- conversation, shown next to its title in the sidebar.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS icon TEXT;
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS color TEXT;
@@ -0,0 +1,4 @@
+-- Short prompts the model proposed as the user's next message, generated in
+-- the background after an assistant reply. Empty until then, and cleared
+-- when the reply is regenerated.
+ALTER TABLE messages ADD COLUMN IF NOT EXISTS suggestions TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,18 @@
+-- Turns streaming right now and their reply so far, so a client watching a
+-- conversation (GET /ws/conversations/{id}/events) can catch up on whichever
+-- replica it connects to. node_id is the server generating the turn. Rows are
+-- deleted when their turn ends; ones a crashed server left behind are ignored
+-- once stale. Turns of ephemeral and encrypted conversations are never stored.
+CREATE TABLE IF NOT EXISTS active_streams (
+    socket_id       VARCHAR(36) NOT NULL,
+    stream_id       TEXT        NOT NULL,
+    conversation_id VARCHAR(36) NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    node_id         VARCHAR(36) NOT NULL,
+    -- The turn's stream_start event, as JSON
+    start_event     TEXT        NOT NULL,
+    content         TEXT        NOT NULL DEFAULT '',
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (socket_id, stream_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_active_streams_conversation ON active_streams (conversation_id);
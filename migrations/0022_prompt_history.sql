@@ -0,0 +1,15 @@
+-- Prompts each user has sent, for recalling them in the chat input
+-- (GET /api/me/prompts). Sending the same prompt again bumps its row. Prompts
+-- of ephemeral and encrypted conversations are never recorded.
+CREATE TABLE IF NOT EXISTS prompt_history (
+    user_id      VARCHAR(64) NOT NULL,
+    prompt       TEXT        NOT NULL,
+    uses         INTEGER     NOT NULL DEFAULT 1,
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Hashed, as prompts can outgrow a btree entry.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_prompt_history_prompt
+    ON prompt_history (user_id, md5(prompt));
+CREATE INDEX IF NOT EXISTS idx_prompt_history_last_used
+    ON prompt_history (user_id, last_used_at DESC);
@@ -0,0 +1,3 @@
+-- How long replies in a conversation should be: 'concise', 'normal' or
+-- 'detailed'. Picked next to the Send button and kept for later turns.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS verbosity VARCHAR(16) NOT NULL DEFAULT 'normal';
@@ -0,0 +1,4 @@
+-- The earlier message a user message quotes, so the quoted part can be shown
+-- apart from what the user wrote.
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS quoted_message_id VARCHAR(36) REFERENCES messages(id) ON DELETE SET NULL;
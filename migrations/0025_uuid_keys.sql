@@ -0,0 +1,78 @@
+-- Ids become native UUIDs: 16 bytes instead of up to 36 characters in every
+-- key and index, and malformed ids are rejected by the database. An id that
+-- is not a UUID (conversations could be started under any client-chosen id)
+-- becomes the UUID of its MD5 hash, the same wherever it appears, so rows
+-- still reference each other.
+CREATE FUNCTION pg_temp.to_uuid(id TEXT) RETURNS UUID AS $$
+    SELECT CASE
+        WHEN id ~* '^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$' THEN id::UUID
+        ELSE md5(id)::UUID
+    END
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Foreign keys cannot survive their columns changing type one at a time;
+-- they are dropped here and restored below.
+ALTER TABLE messages
+    DROP CONSTRAINT IF EXISTS messages_conversation_id_fkey,
+    DROP CONSTRAINT IF EXISTS messages_quoted_message_id_fkey;
+ALTER TABLE message_sources DROP CONSTRAINT IF EXISTS message_sources_message_id_fkey;
+ALTER TABLE message_versions DROP CONSTRAINT IF EXISTS message_versions_message_id_fkey;
+ALTER TABLE message_usage DROP CONSTRAINT IF EXISTS message_usage_message_id_fkey;
+ALTER TABLE starred_messages DROP CONSTRAINT IF EXISTS starred_messages_message_id_fkey;
+ALTER TABLE eval_results DROP CONSTRAINT IF EXISTS eval_results_run_id_fkey;
+ALTER TABLE batch_items DROP CONSTRAINT IF EXISTS batch_items_job_id_fkey;
+ALTER TABLE webhook_tools DROP CONSTRAINT IF EXISTS webhook_tools_conversation_id_fkey;
+ALTER TABLE active_streams DROP CONSTRAINT IF EXISTS active_streams_conversation_id_fkey;
+-- Compares conversation_id with '', which is no UUID.
+DROP INDEX IF EXISTS idx_webhook_tools_scope_name;
+
+ALTER TABLE conversations ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id);
+ALTER TABLE messages
+    ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id),
+    ALTER COLUMN conversation_id TYPE UUID USING pg_temp.to_uuid(conversation_id),
+    ALTER COLUMN quoted_message_id TYPE UUID USING pg_temp.to_uuid(quoted_message_id);
+ALTER TABLE message_sources ALTER COLUMN message_id TYPE UUID USING pg_temp.to_uuid(message_id);
+ALTER TABLE message_versions ALTER COLUMN message_id TYPE UUID USING pg_temp.to_uuid(message_id);
+ALTER TABLE message_usage ALTER COLUMN message_id TYPE UUID USING pg_temp.to_uuid(message_id);
+ALTER TABLE starred_messages ALTER COLUMN message_id TYPE UUID USING pg_temp.to_uuid(message_id);
+ALTER TABLE eval_runs ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id);
+ALTER TABLE eval_results
+    ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id),
+    ALTER COLUMN run_id TYPE UUID USING pg_temp.to_uuid(run_id),
+    ALTER COLUMN conversation_id TYPE UUID USING pg_temp.to_uuid(conversation_id);
+ALTER TABLE batch_jobs ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id);
+ALTER TABLE batch_items ALTER COLUMN job_id TYPE UUID USING pg_temp.to_uuid(job_id);
+ALTER TABLE webhook_tools
+    ALTER COLUMN id TYPE UUID USING pg_temp.to_uuid(id),
+    ALTER COLUMN conversation_id TYPE UUID USING pg_temp.to_uuid(conversation_id);
+ALTER TABLE active_streams
+    ALTER COLUMN conversation_id TYPE UUID USING pg_temp.to_uuid(conversation_id);
+
+ALTER TABLE messages
+    ADD CONSTRAINT messages_conversation_id_fkey
+        FOREIGN KEY (conversation_id) REFERENCES conversations(id),
+    ADD CONSTRAINT messages_quoted_message_id_fkey
+        FOREIGN KEY (quoted_message_id) REFERENCES messages(id) ON DELETE SET NULL;
+ALTER TABLE message_sources ADD CONSTRAINT message_sources_message_id_fkey
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE message_versions ADD CONSTRAINT message_versions_message_id_fkey
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE message_usage ADD CONSTRAINT message_usage_message_id_fkey
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE starred_messages ADD CONSTRAINT starred_messages_message_id_fkey
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE eval_results ADD CONSTRAINT eval_results_run_id_fkey
+    FOREIGN KEY (run_id) REFERENCES eval_runs(id) ON DELETE CASCADE;
+ALTER TABLE batch_items ADD CONSTRAINT batch_items_job_id_fkey
+    FOREIGN KEY (job_id) REFERENCES batch_jobs(id) ON DELETE CASCADE;
+ALTER TABLE webhook_tools ADD CONSTRAINT webhook_tools_conversation_id_fkey
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE;
+ALTER TABLE active_streams ADD CONSTRAINT active_streams_conversation_id_fkey
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE;
+
+-- Global tools (no conversation) share the nil UUID as their scope.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_webhook_tools_scope_name
+    ON webhook_tools ((COALESCE(conversation_id, '00000000-0000-0000-0000-000000000000')), name);
+-- Deleting a message looks for the messages quoting it.
+CREATE INDEX IF NOT EXISTS idx_messages_quoted_message_id
+    ON messages (quoted_message_id) WHERE quoted_message_id IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Deleting a conversation deletes its messages, and with them their sources,
+-- versions, usage and stars, in one statement.
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_conversation_id_fkey;
+ALTER TABLE messages ADD CONSTRAINT messages_conversation_id_fkey
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE;
+
+-- A conversation's messages are always read in order; the composite index
+-- serves that and everything the single-column one did.
+CREATE INDEX IF NOT EXISTS idx_messages_conversation_created
+    ON messages (conversation_id, created_at);
+DROP INDEX IF EXISTS idx_messages_conversation_id;
+
+-- The unique index on webhook tools is over an expression, which neither the
+-- cascade from conversations nor lookups by conversation can use.
+CREATE INDEX IF NOT EXISTS idx_webhook_tools_conversation
+    ON webhook_tools (conversation_id) WHERE conversation_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Assistant preset answering the conversation, by its id in the config; NULL
+-- is the default assistant.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS preset TEXT;
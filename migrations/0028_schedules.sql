@@ -0,0 +1,16 @@
+-- Prompts run on a cron schedule; each run posts its reply to the target
+-- conversation. A server claims a due run by moving next_run_at on, so
+-- replicas never run it twice.
+CREATE TABLE IF NOT EXISTS schedules (
+    id              UUID        PRIMARY KEY,
+    conversation_id UUID        NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    cron            TEXT        NOT NULL,
+    prompt          TEXT        NOT NULL,
+    model           TEXT,
+    next_run_at     TIMESTAMPTZ NOT NULL,
+    last_run_at     TIMESTAMPTZ,
+    last_error      TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_schedules_next_run_at ON schedules (next_run_at);
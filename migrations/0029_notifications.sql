@@ -0,0 +1,24 @@
+-- Where each user wants to hear about finished batch jobs and scheduled
+-- prompts; NULL turns that channel off.
+ALTER TABLE user_profiles
+    ADD COLUMN IF NOT EXISTS notify_webhook_url TEXT,
+    ADD COLUMN IF NOT EXISTS notify_email       TEXT;
+
+-- Every notification sent, one row per channel, with how its delivery went.
+CREATE TABLE IF NOT EXISTS notifications (
+    id           UUID        PRIMARY KEY,
+    user_id      VARCHAR(64) NOT NULL,
+    event        VARCHAR(32) NOT NULL,
+    subject_id   UUID        NOT NULL,
+    summary      TEXT        NOT NULL,
+    channel      VARCHAR(16) NOT NULL,
+    target       TEXT        NOT NULL,
+    status       VARCHAR(16) NOT NULL,
+    attempts     INTEGER     NOT NULL DEFAULT 0,
+    last_error   TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_created
+    ON notifications (user_id, created_at DESC);
@@ -0,0 +1,3 @@
+-- Order of the conversation list: 'updated', 'created', 'title' or
+-- 'message_count'. Picked in the sidebar and kept for later visits.
+ALTER TABLE user_profiles ADD COLUMN IF NOT EXISTS conversation_sort VARCHAR(16) NOT NULL DEFAULT 'updated';
@@ -0,0 +1,3 @@
+-- Stateless conversations answer each turn on its own: earlier messages are
+-- not replayed to the model. Toggled in the chat header, for one-shot Q&A.
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS stateless BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,27 @@
+-- URLs told about every assistant message saved to a conversation; each
+-- payload is signed with the webhook's secret.
+CREATE TABLE IF NOT EXISTS conversation_webhooks (
+    id              UUID        PRIMARY KEY,
+    conversation_id UUID        NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    url             TEXT        NOT NULL,
+    secret          TEXT        NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_webhooks_conversation
+    ON conversation_webhooks (conversation_id);
+
+-- Every message sent to a webhook, with how its delivery went.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id           UUID        PRIMARY KEY,
+    webhook_id   UUID        NOT NULL REFERENCES conversation_webhooks(id) ON DELETE CASCADE,
+    message_id   UUID        NOT NULL,
+    status       VARCHAR(16) NOT NULL,
+    attempts     INTEGER     NOT NULL DEFAULT 0,
+    last_error   TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_created
+    ON webhook_deliveries (webhook_id, created_at DESC);
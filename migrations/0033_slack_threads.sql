@@ -0,0 +1,9 @@
+-- The conversation each Slack thread the bot answers in is relayed to; a
+-- thread is named by its channel and the timestamp of its first message.
+CREATE TABLE IF NOT EXISTS slack_threads (
+    channel         VARCHAR(32) NOT NULL,
+    thread_ts       VARCHAR(32) NOT NULL,
+    conversation_id UUID        NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (channel, thread_ts)
+);
@@ -0,0 +1,8 @@
+-- Emails the gateway has taken and the replies it sent, by Message-ID, so a
+-- reply's References lead back to its conversation. conversation_id is NULL
+-- while an email is still being answered.
+CREATE TABLE IF NOT EXISTS email_messages (
+    message_id      TEXT        PRIMARY KEY,
+    conversation_id UUID        REFERENCES conversations(id) ON DELETE CASCADE,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
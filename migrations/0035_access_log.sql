@@ -0,0 +1,12 @@
+-- Every time a conversation was read or exported, and by whom.
+CREATE TABLE IF NOT EXISTS access_log (
+    id              UUID        PRIMARY KEY,
+    conversation_id UUID        NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    user_id         VARCHAR(64) NOT NULL,
+    action          VARCHAR(16) NOT NULL,
+    user_agent      TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_log_conversation_created
+    ON access_log (conversation_id, created_at DESC);
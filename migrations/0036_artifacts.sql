@@ -0,0 +1,14 @@
+-- Long code blocks and documents taken out of assistant replies. Like
+-- sources, they belong to one version of a reply.
+CREATE TABLE IF NOT EXISTS artifacts (
+    id         UUID        PRIMARY KEY,
+    message_id UUID        NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    version    INTEGER     NOT NULL,
+    position   INTEGER     NOT NULL,
+    kind       VARCHAR(16) NOT NULL,
+    language   VARCHAR(32),
+    title      TEXT        NOT NULL,
+    content    TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (message_id, version, position)
+);
@@ -0,0 +1,12 @@
+-- Every file of the workspace the agent read, and in which conversation. Not
+-- tied to conversations by a foreign key: incognito ones are never stored.
+CREATE TABLE IF NOT EXISTS workspace_file_reads (
+    id              UUID        PRIMARY KEY,
+    conversation_id UUID        NOT NULL,
+    path            TEXT        NOT NULL,
+    bytes           BIGINT      NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_workspace_file_reads_created
+    ON workspace_file_reads (created_at DESC);
@@ -0,0 +1,37 @@
+-- Documents indexed for retrieval, grouped into named collections, and the
+-- embedded chunks they are split into. Embeddings are plain REAL arrays
+-- compared in the application, so no vector extension is needed.
+CREATE TABLE IF NOT EXISTS documents (
+    id         UUID        PRIMARY KEY,
+    collection VARCHAR(64) NOT NULL,
+    -- Where the document came from, e.g. the repository it is a file of.
+    source     TEXT        NOT NULL,
+    path       TEXT        NOT NULL,
+    language   VARCHAR(32),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (collection, source, path)
+);
+
+CREATE TABLE IF NOT EXISTS document_chunks (
+    id          UUID    PRIMARY KEY,
+    document_id UUID    NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    position    INTEGER NOT NULL,
+    content     TEXT    NOT NULL,
+    start_line  INTEGER NOT NULL,
+    end_line    INTEGER NOT NULL,
+    embedding   REAL[]  NOT NULL,
+    UNIQUE (document_id, position)
+);
+
+-- Background runs filling a collection from a source.
+CREATE TABLE IF NOT EXISTS ingestions (
+    id           UUID        PRIMARY KEY,
+    collection   VARCHAR(64) NOT NULL,
+    source       TEXT        NOT NULL,
+    status       VARCHAR(16) NOT NULL,
+    files        INTEGER     NOT NULL DEFAULT 0,
+    chunks       INTEGER     NOT NULL DEFAULT 0,
+    error        TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
@@ -0,0 +1,34 @@
+-- Named retrieval collections, each with its own chunking and embedding
+-- settings (NULL uses the configured default). Global collections are
+-- searched by every conversation, others only by those they are attached to.
+CREATE TABLE IF NOT EXISTS collections (
+    name            VARCHAR(64)  PRIMARY KEY,
+    description     TEXT,
+    embedding_model VARCHAR(200),
+    chunk_max_lines INTEGER,
+    global          BOOLEAN      NOT NULL DEFAULT FALSE,
+    created_at      TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+-- The collection git repositories were indexed in so far, searched everywhere.
+INSERT INTO collections (name, description, global)
+VALUES ('codebase', 'Ingested git repositories', TRUE)
+ON CONFLICT DO NOTHING;
+INSERT INTO collections (name)
+SELECT DISTINCT collection FROM documents
+UNION SELECT DISTINCT collection FROM ingestions
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE documents
+    ADD CONSTRAINT documents_collection_fkey
+    FOREIGN KEY (collection) REFERENCES collections(name) ON DELETE CASCADE;
+ALTER TABLE ingestions
+    ADD CONSTRAINT ingestions_collection_fkey
+    FOREIGN KEY (collection) REFERENCES collections(name) ON DELETE CASCADE;
+
+CREATE TABLE IF NOT EXISTS conversation_collections (
+    conversation_id UUID        NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    collection      VARCHAR(64) NOT NULL REFERENCES collections(name) ON DELETE CASCADE,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, collection)
+);
@@ -0,0 +1,18 @@
+-- The chunks retrieved for each version of an assistant reply, copied as
+-- they were injected so re-indexing or deleting a collection does not
+-- rewrite what a past answer was based on.
+CREATE TABLE IF NOT EXISTS message_retrievals (
+    message_id  UUID        NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    version     INTEGER     NOT NULL,
+    position    INTEGER     NOT NULL,
+    chunk_id    UUID        NOT NULL,
+    document_id UUID        NOT NULL,
+    collection  VARCHAR(64) NOT NULL,
+    path        TEXT        NOT NULL,
+    language    VARCHAR(32),
+    content     TEXT        NOT NULL,
+    start_line  INTEGER     NOT NULL,
+    end_line    INTEGER     NOT NULL,
+    score       REAL        NOT NULL,
+    PRIMARY KEY (message_id, version, position)
+);
@@ -0,0 +1,16 @@
+-- Full-text search over indexed chunks, fused with embedding similarity when
+-- retrieving, and users' verdicts on the chunks their replies were given.
+ALTER TABLE document_chunks
+    ADD COLUMN IF NOT EXISTS search TSVECTOR
+        GENERATED ALWAYS AS (to_tsvector('english', content)) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_document_chunks_search
+    ON document_chunks USING GIN (search);
+
+ALTER TABLE message_retrievals
+    ADD COLUMN IF NOT EXISTS vector_rank INTEGER,
+    ADD COLUMN IF NOT EXISTS text_rank   INTEGER,
+    ADD COLUMN IF NOT EXISTS relevant    BOOLEAN;
+
+-- Chunks retrieved so far were ranked by similarity alone.
+UPDATE message_retrievals SET vector_rank = position WHERE vector_rank IS NULL;
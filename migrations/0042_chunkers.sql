@@ -0,0 +1,7 @@
+-- The chunker each collection splits its documents with, and the text of
+-- indexed documents, kept so a collection can be split again another way.
+ALTER TABLE collections
+    ADD COLUMN IF NOT EXISTS chunker       VARCHAR(16) NOT NULL DEFAULT 'auto',
+    ADD COLUMN IF NOT EXISTS chunk_overlap INTEGER;
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS content TEXT;
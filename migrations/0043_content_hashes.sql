@@ -0,0 +1,14 @@
+-- SHA-256 of indexed documents and of the text embedded for each chunk, so
+-- indexing unchanged files again embeds nothing, and what each ingestion
+-- added, updated, skipped and removed.
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS content_hash VARCHAR(64);
+ALTER TABLE document_chunks ADD COLUMN IF NOT EXISTS content_hash VARCHAR(64);
+CREATE INDEX IF NOT EXISTS idx_document_chunks_content_hash
+    ON document_chunks (content_hash);
+
+ALTER TABLE ingestions
+    ADD COLUMN IF NOT EXISTS added    INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS updated  INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS skipped  INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS removed  INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS embedded INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,30 @@
+-- Chunk embeddings as pgvector vectors, so similarity search runs in the
+-- database instead of loading a collection's chunks into the application.
+-- Embedding models differ in dimensions, so the column is untyped and each
+-- dimension gets a partial HNSW index on the embeddings cast to it; the
+-- application creates them as chunks of new dimensions arrive. pgvector
+-- cannot index more than 2000 dimensions: larger embeddings are scanned.
+-- The extension lives in `public`, which tenant schemas keep on their
+-- search path, so every schema migrated after the first still finds it.
+CREATE EXTENSION IF NOT EXISTS vector WITH SCHEMA public;
+
+ALTER TABLE document_chunks
+    ALTER COLUMN embedding TYPE vector USING embedding::vector;
+
+DO $$
+DECLARE
+    dimensions INTEGER;
+BEGIN
+    FOR dimensions IN
+        SELECT DISTINCT vector_dims(embedding) FROM document_chunks
+    LOOP
+        IF dimensions <= 2000 THEN
+            EXECUTE format(
+                'CREATE INDEX IF NOT EXISTS idx_document_chunks_embedding_%1$s
+                 ON document_chunks USING hnsw ((embedding::vector(%1$s)) vector_cosine_ops)
+                 WHERE vector_dims(embedding) = %1$s',
+                dimensions
+            );
+        END IF;
+    END LOOP;
+END $$;
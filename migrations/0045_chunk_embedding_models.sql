@@ -0,0 +1,4 @@
+-- The model that embedded each chunk, so embeddings are only reused for
+-- chunks embedded by the same model after the embedding model changes.
+-- Chunks embedded before are left unset and never reused.
+ALTER TABLE document_chunks ADD COLUMN IF NOT EXISTS embedding_model VARCHAR(255);
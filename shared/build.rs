@@ -0,0 +1,25 @@
+//! Records the git commit being built in `GIT_COMMIT`, for [`BUILD_COMMIT`].
+//! A `GIT_COMMIT` already set in the environment is used as is, for builds
+//! outside a checkout (such as a Docker build without `.git`).
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    if std::env::var_os("GIT_COMMIT").is_some() {
+        return;
+    }
+    let git = |args: &[&str]| {
+        let output = Command::new("git").args(args).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    // Rebuilt when HEAD moves: on checkout, commit, or a fetched branch.
+    if let Some(dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={dir}/HEAD");
+        println!("cargo:rerun-if-changed={dir}/refs");
+        println!("cargo:rerun-if-changed={dir}/packed-refs");
+    }
+    if let Some(commit) = git(&["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=GIT_COMMIT={commit}");
+    }
+}
@@ -0,0 +1,1094 @@
+//! Types the backend and the frontend exchange over REST and the chat
+//! WebSocket, defined once so the two cannot drift apart. [`markdown`] is
+//! here too, so the UI and server-side exports read replies the same way.
+//!
+//! The `server` feature adds what only the backend needs: `sqlx` row mapping
+//! and constructors that generate ids.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod markdown;
+
+// ── Conversations and messages ───────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct Conversation {
+    pub id: Uuid,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// ISO 639-3 code of the language the user writes in, once detected.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// One or two generated sentences recalling what the conversation is about.
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Messages the summary covered; it is refreshed as more arrive.
+    #[serde(skip)]
+    pub summary_message_count: i32,
+    /// Overrides the `max_history_messages` setting for this conversation;
+    /// 0 sends the whole conversation.
+    #[serde(default)]
+    pub max_history_messages: Option<i32>,
+    /// Why the last turn failed, while it has not been answered since.
+    #[serde(default)]
+    pub failed_turn_error: Option<String>,
+    /// Messages are stored encrypted with a passphrase; they can only be read
+    /// or added while the conversation is unlocked.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Emoji shown before the title.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// `#rrggbb` accent the conversation is marked with.
+    #[serde(default)]
+    pub color: Option<String>,
+    /// How long replies should be; set with each turn and kept for the next.
+    #[serde(default)]
+    #[cfg_attr(feature = "server", sqlx(try_from = "String"))]
+    pub verbosity: Verbosity,
+    /// Id of the [`AssistantPreset`] answering; `None` is the default assistant.
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Each turn is answered on its own, without replaying earlier messages.
+    #[serde(default)]
+    pub stateless: bool,
+}
+
+impl Conversation {
+    pub fn new(id: Uuid, title: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            title,
+            created_at: now,
+            updated_at: now,
+            language: None,
+            summary: None,
+            summary_message_count: 0,
+            max_history_messages: None,
+            failed_turn_error: None,
+            encrypted: false,
+            icon: None,
+            color: None,
+            verbosity: Verbosity::default(),
+            preset: None,
+            stateless: false,
+        }
+    }
+}
+
+/// A named assistant a conversation can switch to, with its own system prompt
+/// and model (`presets` in the server's config).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssistantPreset {
+    /// Stable id stored on conversations, e.g. `code_reviewer`.
+    pub id: String,
+    /// Shown in the UI, e.g. "Code Reviewer".
+    pub name: String,
+    /// Replaces the configured system prompt; the same placeholders apply.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Replaces the configured (and the user's preferred) model.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Reply length a conversation asks for, applied through the system prompt
+/// and, for `concise`, a cap on generated tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    Concise,
+    #[default]
+    Normal,
+    Detailed,
+}
+
+impl Verbosity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verbosity::Concise => "concise",
+            Verbosity::Normal => "normal",
+            Verbosity::Detailed => "detailed",
+        }
+    }
+}
+
+impl TryFrom<String> for Verbosity {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "concise" => Ok(Verbosity::Concise),
+            "normal" => Ok(Verbosity::Normal),
+            "detailed" => Ok(Verbosity::Detailed),
+            other => Err(format!("Unknown verbosity: {other}")),
+        }
+    }
+}
+
+/// A run of words that is unchanged, added or removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSpan {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// What changed in a regenerated reply since the version before it, as
+/// returned by `GET /api/messages/{id}/versions/{version}/diff`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionDiff {
+    pub message_id: Uuid,
+    pub from_version: i32,
+    pub to_version: i32,
+    /// Word diff of the markdown source turning `from_version` into
+    /// `to_version`.
+    pub diff: Vec<DiffSpan>,
+}
+
+/// Order of the conversation list (`GET /api/conversations?sort=...`), kept
+/// in the user's profile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationSort {
+    /// Most recently updated first.
+    #[default]
+    Updated,
+    /// Newest first.
+    Created,
+    /// Alphabetically, ignoring case.
+    Title,
+    /// Longest first.
+    MessageCount,
+}
+
+impl ConversationSort {
+    pub const ALL: [ConversationSort; 4] = [
+        ConversationSort::Updated,
+        ConversationSort::Created,
+        ConversationSort::Title,
+        ConversationSort::MessageCount,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConversationSort::Updated => "updated",
+            ConversationSort::Created => "created",
+            ConversationSort::Title => "title",
+            ConversationSort::MessageCount => "message_count",
+        }
+    }
+}
+
+impl TryFrom<String> for ConversationSort {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "updated" => Ok(ConversationSort::Updated),
+            "created" => Ok(ConversationSort::Created),
+            "title" => Ok(ConversationSort::Title),
+            "message_count" => Ok(ConversationSort::MessageCount),
+            other => Err(format!("Unknown conversation sort: {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MessageRole {
+    User,
+    Assistant,
+    System,
+    /// What a tool returned to the model.
+    Tool,
+    /// A call the model made to a tool; the content holds its JSON arguments.
+    Function,
+}
+
+impl MessageRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageRole::User => "USER",
+            MessageRole::Assistant => "ASSISTANT",
+            MessageRole::System => "SYSTEM",
+            MessageRole::Tool => "TOOL",
+            MessageRole::Function => "FUNCTION",
+        }
+    }
+
+    /// Whether the message is part of a tool interaction rather than the
+    /// conversation proper.
+    pub fn is_tool_traffic(&self) -> bool {
+        matches!(self, MessageRole::Tool | MessageRole::Function)
+    }
+}
+
+impl std::fmt::Display for MessageRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for MessageRole {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_uppercase().as_str() {
+            "USER" => Ok(MessageRole::User),
+            "ASSISTANT" => Ok(MessageRole::Assistant),
+            "SYSTEM" => Ok(MessageRole::System),
+            "TOOL" => Ok(MessageRole::Tool),
+            "FUNCTION" => Ok(MessageRole::Function),
+            other => Err(format!("Unknown role: {other}")),
+        }
+    }
+}
+
+/// A document cited by the assistant, numbered in the order it was first returned
+/// so the `[n]` markers in the message content line up with `sources[n - 1]`.
+///
+/// `score` is the retrieval relevance when the producing path has one (web search
+/// results are unscored).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub url: String,
+    pub title: String,
+    pub snippet: String,
+    #[serde(default)]
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub role: MessageRole,
+    pub content: String,
+    #[serde(default)]
+    pub sources: Vec<Source>,
+    pub created_at: DateTime<Utc>,
+    /// Which regeneration of this reply `content` holds (1-based).
+    #[serde(default = "first_version")]
+    pub active_version: i32,
+    /// How many versions exist; 1 until the reply is regenerated.
+    #[serde(default = "first_version")]
+    pub version_count: i32,
+    /// Tool a `TOOL` or `FUNCTION` message belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// Pairs a `FUNCTION` call with the `TOOL` result it produced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Whether the user starred it; see `GET /api/starred`.
+    #[serde(default)]
+    pub starred: bool,
+    /// Follow-up prompts proposed after an assistant reply; filled in shortly
+    /// after the reply is saved.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+    /// Earlier message of the conversation a user message quotes; its
+    /// content starts with the quote as a markdown blockquote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quoted_message_id: Option<Uuid>,
+}
+
+fn first_version() -> i32 {
+    1
+}
+
+impl Message {
+    /// A message with the given id, as first created: one version, no
+    /// sources, not starred.
+    pub fn with_id(id: Uuid, conversation_id: Uuid, role: MessageRole, content: String) -> Self {
+        Self {
+            id,
+            conversation_id,
+            role,
+            content,
+            sources: Vec::new(),
+            created_at: Utc::now(),
+            active_version: 1,
+            version_count: 1,
+            tool_name: None,
+            tool_call_id: None,
+            starred: false,
+            suggestions: Vec::new(),
+            quoted_message_id: None,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub fn new(conversation_id: Uuid, role: MessageRole, content: String) -> Self {
+        Self::with_id(Uuid::new_v4(), conversation_id, role, content)
+    }
+
+    /// A `FUNCTION` or `TOOL` message for one step of a tool call.
+    #[cfg(feature = "server")]
+    pub fn tool(
+        conversation_id: Uuid,
+        role: MessageRole,
+        tool_name: String,
+        tool_call_id: String,
+        content: String,
+    ) -> Self {
+        Self {
+            tool_name: Some(tool_name),
+            tool_call_id: Some(tool_call_id),
+            ..Self::new(conversation_id, role, content)
+        }
+    }
+
+    pub fn with_sources(mut self, sources: Vec<Source>) -> Self {
+        self.sources = sources;
+        self
+    }
+}
+
+/// A starred message and the conversation it belongs to, as listed by
+/// `GET /api/starred`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StarredMessage {
+    pub message: Message,
+    pub conversation_title: String,
+    pub starred_at: DateTime<Utc>,
+}
+
+/// The oldest messages of a conversation, condensed into its summary, as they
+/// appear in `GET /api/conversations/{id}/messages?summaries=true`. The
+/// messages themselves are still in the plain message list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryBlock {
+    pub summary: String,
+    /// How many messages it stands for, from the first one on.
+    pub message_count: usize,
+    /// The newest of them.
+    pub last_message_id: Uuid,
+}
+
+/// One entry of a conversation's timeline, tagged with its `type`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimelineEntry {
+    Message(Message),
+    Summary(SummaryBlock),
+}
+
+/// Body of `POST /api/chat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub conversation_id: Option<Uuid>,
+    pub message: String,
+    /// Start the conversation in ephemeral mode: kept in memory only, never
+    /// listed or persisted. Ignored for conversations that already exist.
+    #[serde(default)]
+    pub ephemeral: bool,
+    /// Changes the conversation's reply length, from this turn on.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+    /// Turns history replay off (`true`) or back on for the conversation,
+    /// from this turn on.
+    #[serde(default)]
+    pub stateless: Option<bool>,
+    /// Message of the conversation that `message` opens by quoting.
+    #[serde(default)]
+    pub quoted_message_id: Option<Uuid>,
+}
+
+/// Response of `POST /api/chat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub conversation_id: Uuid,
+    pub message: Message,
+    pub sources: Vec<Source>,
+    /// Newest message left out of the model's context by the history limit.
+    pub history_cutoff: Option<Uuid>,
+    /// Long code blocks and documents of the reply, also listed by
+    /// `GET /api/conversations/{id}/artifacts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Token usage and cost of a conversation's streamed replies, returned by
+/// `GET /api/conversations/:id/stats`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversationStats {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Sum over the priced models; `None` when none of them is priced.
+    pub cost: Option<f64>,
+    pub models: Vec<ModelUsage>,
+}
+
+/// One model's share of a [`ConversationStats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct ModelUsage {
+    pub model: String,
+    pub replies: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    #[cfg_attr(feature = "server", sqlx(skip))]
+    pub cost: Option<f64>,
+}
+
+// ── Users ────────────────────────────────────────────────────────────────────
+
+/// Preferences of one user, returned by `GET /api/me`. Unset fields fall back
+/// to the server configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct UserProfile {
+    #[serde(skip)]
+    pub user_id: String,
+    /// Used for `{{user_name}}` in the system prompt.
+    pub display_name: Option<String>,
+    /// Ollama model used for this user's turns instead of the configured one.
+    pub preferred_model: Option<String>,
+    pub temperature: Option<f64>,
+    /// `"light"` or `"dark"`; only the frontend reads it.
+    pub theme: Option<String>,
+    /// Appended to the system prompt of every conversation.
+    pub custom_instructions: Option<String>,
+    /// Receives a JSON POST when a batch job or scheduled prompt finishes.
+    pub notify_webhook_url: Option<String>,
+    /// Receives an email when a batch job or scheduled prompt finishes;
+    /// needs `smtp_url` in the configuration.
+    pub notify_email: Option<String>,
+    /// Order of the conversation list when none is asked for.
+    #[cfg_attr(feature = "server", sqlx(try_from = "String"))]
+    pub conversation_sort: ConversationSort,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UserProfile {
+    pub fn new(user_id: String) -> Self {
+        Self {
+            user_id,
+            display_name: None,
+            preferred_model: None,
+            temperature: None,
+            theme: None,
+            custom_instructions: None,
+            notify_webhook_url: None,
+            notify_email: None,
+            conversation_sort: ConversationSort::default(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+/// A prompt the user has sent, as listed by `GET /api/me/prompts`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct PromptHistoryEntry {
+    pub prompt: String,
+    /// Times it was sent.
+    pub uses: i32,
+    pub last_used_at: DateTime<Utc>,
+}
+
+// ── Artifacts ────────────────────────────────────────────────────────────────
+
+/// A long code block or document taken out of an assistant reply, shown in a
+/// side panel rather than inline. Belongs to one version of the reply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct Artifact {
+    pub id: Uuid,
+    pub message_id: Uuid,
+    #[cfg_attr(feature = "server", sqlx(try_from = "String"))]
+    pub kind: ArtifactKind,
+    /// Language of the code block's fence (`rust`, `python`, ...), if it
+    /// named one.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// File name the code names in its first line, or the document's heading.
+    pub title: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Artifact {
+    /// Name to save the artifact under: its title when that is a file name,
+    /// otherwise `artifact` with an extension for its language.
+    pub fn file_name(&self) -> String {
+        let base = self.title.rsplit(['/', '\\']).next().unwrap_or_default();
+        let is_file_name = base.contains('.')
+            && base.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+        if is_file_name {
+            return base.to_string();
+        }
+        let extension = match (self.kind, self.language.as_deref()) {
+            (ArtifactKind::Document, Some("text" | "txt")) => "txt",
+            (ArtifactKind::Document, _) => "md",
+            (ArtifactKind::Code, Some(language)) => match language {
+                "rust" => "rs",
+                "python" | "py" => "py",
+                "javascript" | "js" => "js",
+                "typescript" | "ts" => "ts",
+                "bash" | "sh" | "shell" => "sh",
+                "c++" | "cpp" => "cpp",
+                "csharp" | "c#" | "cs" => "cs",
+                "kotlin" | "kt" => "kt",
+                "ruby" | "rb" => "rb",
+                "yaml" | "yml" => "yaml",
+                "go" | "java" | "c" | "sql" | "html" | "css" | "json" | "toml" | "xml" | "swift" => {
+                    language
+                }
+                _ => "txt",
+            },
+            (ArtifactKind::Code, None) => "txt",
+        };
+        format!("artifact.{extension}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Code,
+    /// Prose: a markdown or text block, or a reply written as a document.
+    Document,
+}
+
+impl ArtifactKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactKind::Code => "code",
+            ArtifactKind::Document => "document",
+        }
+    }
+}
+
+impl TryFrom<String> for ArtifactKind {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "code" => Ok(ArtifactKind::Code),
+            "document" => Ok(ArtifactKind::Document),
+            other => Err(format!("Unknown artifact kind: {other}")),
+        }
+    }
+}
+
+// ── Retrieval ────────────────────────────────────────────────────────────────
+
+/// An indexed chunk retrieved for a turn and added to its context, as it was
+/// at the time: re-indexing its document does not change it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct RetrievedChunk {
+    pub chunk_id: Uuid,
+    pub document_id: Uuid,
+    pub collection: String,
+    pub path: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    pub content: String,
+    /// 1-based lines of the document the chunk spans, inclusive.
+    pub start_line: i32,
+    pub end_line: i32,
+    /// Weighted reciprocal-rank fusion of the rankings below, or the
+    /// reranker's verdict from 0 to 1 when one is configured. Higher is better.
+    pub score: f32,
+    /// Places of the chunk, from 1, by embedding similarity to the query and
+    /// by full-text match; unset when a ranking did not return it.
+    #[serde(default)]
+    pub vector_rank: Option<i32>,
+    #[serde(default)]
+    pub text_rank: Option<i32>,
+    /// The user's verdict on whether the chunk helped answer the turn.
+    #[serde(default)]
+    pub relevant: Option<bool>,
+}
+
+/// Body of `PUT /api/messages/{id}/retrieval/{chunk_id}`: whether a chunk
+/// retrieved for the reply was relevant. `null` takes the verdict back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalFeedback {
+    pub relevant: Option<bool>,
+}
+
+// ── System status ────────────────────────────────────────────────────────────
+
+/// Response of `GET /api/system/status`: what Ollama has in memory and how
+/// busy the host is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemStatus {
+    /// Model new turns use, unless a conversation's preset picks another.
+    pub current_model: String,
+    /// `false` when Ollama could not be asked; `loaded_models` is then empty.
+    pub ollama_reachable: bool,
+    pub loaded_models: Vec<LoadedModel>,
+    pub host: HostStats,
+}
+
+/// A model Ollama holds in memory, as reported by its `/api/ps`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadedModel {
+    /// With its tag, e.g. `llama3.2:latest`.
+    pub name: String,
+    /// Bytes of memory it takes in all.
+    #[serde(default)]
+    pub size: u64,
+    /// Bytes of that in GPU memory; 0 when it runs on the CPU.
+    #[serde(default)]
+    pub size_vram: u64,
+    /// When Ollama unloads it unless it is used again.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
+/// Memory and CPU load of the machine the server runs on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostStats {
+    pub memory_total_bytes: u64,
+    pub memory_used_bytes: u64,
+    pub cpus: usize,
+    /// Over the last 1, 5 and 15 minutes; zeros where the OS keeps none.
+    pub load_average: [f64; 3],
+}
+
+/// Returned by `GET /api/maintenance`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(sqlx::FromRow))]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    /// Shown to users instead of the default notice.
+    pub message: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// ── Models ───────────────────────────────────────────────────────────────────
+
+/// A model installed in Ollama, as reported by `/api/tags`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub modified_at: Option<String>,
+}
+
+/// Response of `GET /api/models`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelsResponse {
+    /// Model used for new turns.
+    pub current: String,
+    pub models: Vec<ModelInfo>,
+    /// Tokens of context the model runs with.
+    #[serde(default)]
+    pub context_window: usize,
+}
+
+/// One progress line of a model pull, as reported by Ollama's `/api/pull`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Bytes of the current layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+/// Response of `POST /api/tokenize`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenizeResponse {
+    pub model: String,
+    /// Vocabulary the text was split with, e.g. `cl100k_base`.
+    pub encoding: String,
+    /// `false` when the model's own vocabulary is unknown and `encoding` only
+    /// approximates it, as for most Ollama models.
+    pub exact: bool,
+    pub count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<TokenSpan>>,
+}
+
+/// One token of a [`TokenizeResponse`]. `start` and `end` are byte offsets
+/// into the text; `text` is lossy where a token splits a UTF-8 character.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenSpan {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+// ── Server configuration ─────────────────────────────────────────────────────
+
+/// Version of the REST and WebSocket protocol these types describe. Bumped
+/// whenever a change breaks clients built against the one before.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Git commit the workspace was built from, recorded by `build.rs`; `None`
+/// when built outside a checkout without `GIT_COMMIT` set.
+pub const BUILD_COMMIT: Option<&str> = option_env!("GIT_COMMIT");
+
+/// Response of `GET /api/version`: which build of the server is running.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerVersion {
+    /// [`PROTOCOL_VERSION`] of the server.
+    pub protocol_version: u32,
+    pub build: BuildInfo,
+}
+
+impl ServerVersion {
+    /// Whether a client built from this workspace was built with the server
+    /// reporting `self`: the same protocol, and the same commit when both
+    /// know theirs. A frontend left open across a deploy is not.
+    pub fn matches_this_build(&self) -> bool {
+        let same_commit = match (self.build.commit.as_deref(), BUILD_COMMIT) {
+            (Some(server), Some(client)) => server == client,
+            _ => true,
+        };
+        self.protocol_version == PROTOCOL_VERSION && same_commit
+    }
+}
+
+/// Response of `GET /api/config`: what this server offers, so the UI shows
+/// only what works against it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(flatten)]
+    pub version: ServerVersion,
+    /// Model providers turns run on.
+    pub providers: Vec<String>,
+    /// Model new turns use.
+    pub model: String,
+    /// Built-in tools the model may call; webhook tools are listed by
+    /// `GET /api/tools`.
+    pub tools: Vec<String>,
+    /// Longest message a turn accepts, in bytes.
+    pub max_message_length: usize,
+    pub features: Features,
+}
+
+/// Version of a server build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Crate version, e.g. `0.1.0`.
+    pub version: String,
+    /// Git commit built from, when the build recorded it; see
+    /// [`BUILD_COMMIT`].
+    #[serde(default)]
+    pub commit: Option<String>,
+}
+
+/// Optional parts of the server, on or off depending on its configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Features {
+    /// Replies end with suggested follow-up messages.
+    pub follow_up_suggestions: bool,
+    /// Assistant presets a conversation can switch to are configured.
+    pub presets: bool,
+    /// Notifications of finished scheduled prompts can be emailed.
+    pub email_notifications: bool,
+    /// The Slack bot is set up.
+    pub slack: bool,
+    /// Emails to the gateway's mailbox are answered.
+    pub email_gateway: bool,
+    /// Long code blocks and documents of replies are kept as artifacts.
+    pub artifacts: bool,
+    /// Artifacts can be applied to files of the server's workspace.
+    pub workspace: bool,
+    /// Turns get chunks of the indexed documents their conversation searches.
+    pub retrieval: bool,
+}
+
+// ── WebSocket protocol ───────────────────────────────────────────────────────
+
+/// Incoming WebSocket message from the client.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WsChatRequest {
+    pub conversation_id: Option<Uuid>,
+    #[serde(default)]
+    pub message: String,
+    /// Start the conversation in ephemeral mode: kept in memory only, never
+    /// listed or persisted. Ignored for conversations that already exist.
+    #[serde(default)]
+    pub ephemeral: bool,
+    /// Answer the conversation's last user message again instead of sending
+    /// `message` — used after a turn failed before any reply was saved, e.g.
+    /// because the model had to be pulled first.
+    #[serde(default)]
+    pub retry: bool,
+    /// Client-chosen id echoed on every event of this turn, so several turns
+    /// can stream over one socket at once. Generated by the server if absent.
+    #[serde(default)]
+    pub stream_id: Option<String>,
+    /// `json` constrains the reply to a single JSON value, validated while it
+    /// streams and repaired once before saving if it comes out malformed.
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// Changes the conversation's reply length, from this turn on. With
+    /// `retry`, applies to the retried turn only.
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+    /// Turns history replay off (`true`) or back on for the conversation,
+    /// from this turn on. Ignored with `retry`.
+    #[serde(default)]
+    pub stateless: Option<bool>,
+    /// Message of the conversation that `message` opens by quoting.
+    #[serde(default)]
+    pub quoted_message_id: Option<Uuid>,
+    /// How the reply is grouped into `stream_chunk` events.
+    #[serde(default)]
+    pub chunking: ChunkMode,
+    /// What to do if this socket is already streaming a turn of the same
+    /// conversation, or under the same `stream_id`: by default the request
+    /// is refused with `busy`; with `replace` the running turn is cancelled,
+    /// keeping what it streamed so far, and this one starts once it has ended.
+    #[serde(default)]
+    pub replace: bool,
+}
+
+/// Control messages a client may send on `/ws/chat` instead of a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsControl {
+    /// Chooses how the server encodes the frames it sends from now on. The
+    /// server answers with a `hello` event, itself still in the old encoding.
+    Hello {
+        #[serde(default)]
+        encoding: WsEncoding,
+    },
+}
+
+/// How WebSocket frames are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsEncoding {
+    /// JSON in text messages.
+    #[default]
+    Json,
+    /// MessagePack, with field names, in binary messages.
+    Msgpack,
+}
+
+/// Granularity of `stream_chunk` events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkMode {
+    /// Each chunk as the model produced it.
+    #[default]
+    Token,
+    /// Whole words, each with the whitespace after it.
+    Word,
+    /// Whole sentences or lines.
+    Sentence,
+}
+
+/// Shape of the reply a turn asks for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Outcome of validating a `json` turn's reply, reported with `stream_end`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JsonValidation {
+    Valid,
+    /// The streamed reply was malformed and a second attempt fixed it; the
+    /// saved message is the repaired one.
+    Repaired { error: String },
+    /// Still malformed after the repair attempt; the streamed reply was saved.
+    Invalid { error: String },
+}
+
+/// Outgoing WebSocket frame: a [`WsEvent`] tagged with the turn it belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsFrame {
+    /// Absent only for errors that cannot be tied to a request, such as a
+    /// frame that is not JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    #[serde(flatten)]
+    pub event: WsEvent,
+}
+
+/// Outgoing WebSocket events sent to the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsEvent {
+    /// Stream is starting — includes the (possibly new) conversation id.
+    StreamStart {
+        conversation_id: Uuid,
+        /// Id of the newest message left out of the model's context by the
+        /// history limit; it and everything before it were not sent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        history_cutoff: Option<Uuid>,
+    },
+    /// Waiting for a free generation slot; sent again whenever the position changes.
+    Queued {
+        position: usize,
+    },
+    /// A single content chunk from the LLM.
+    StreamChunk {
+        content: String,
+    },
+    /// Sources cited by the response, sent before `StreamEnd` when there are any,
+    /// and again if post-processing reordered them.
+    StreamSources {
+        sources: Vec<Source>,
+    },
+    /// A long code block or document of the saved reply, one event per
+    /// artifact, sent just before `StreamEnd`.
+    Artifact {
+        artifact: Artifact,
+    },
+    /// Stream finished — full message has been persisted. `full_content` is
+    /// the reply as saved, after post-processing.
+    StreamEnd {
+        message_id: Uuid,
+        full_content: String,
+        #[serde(flatten)]
+        stats: CompletionStats,
+        /// Only for turns that asked for `response_format: json`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        json_validation: Option<JsonValidation>,
+    },
+    /// The configured model is not installed in Ollama. Pull it with
+    /// `POST /api/models/pull`, then resend the turn with `retry: true`.
+    ModelMissing {
+        model: String,
+    },
+    /// The message failed content moderation and was not saved.
+    ModerationBlocked {
+        reason: String,
+    },
+    /// The request was refused because the socket is already streaming a turn
+    /// of the same conversation, or under the same stream id. Resend it with
+    /// `replace` to cancel that turn instead.
+    Busy {
+        /// Stream id of the turn in the way.
+        active_stream_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        conversation_id: Option<Uuid>,
+    },
+    /// Acknowledges a `hello`: every later frame uses `encoding`.
+    Hello {
+        encoding: WsEncoding,
+    },
+    /// A tool call of the turn started or finished. The calls the model asks
+    /// for together run at the same time, so their events interleave.
+    ToolCall {
+        /// Same for every event of one call.
+        call_id: String,
+        name: String,
+        status: ToolCallStatus,
+        /// How long the call took; absent while it runs.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+    },
+    /// Follow-up prompts for a reply, sent some time after its `StreamEnd`.
+    FollowUpSuggestions {
+        message_id: Uuid,
+        suggestions: Vec<String>,
+    },
+    /// Something went wrong.
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+/// Where a tool call of a streamed turn stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolCallStatus {
+    Running,
+    Done,
+    /// The tool returned an error, which the model was given instead.
+    Failed,
+    /// The tool did not answer within `tool_timeout_secs`.
+    TimedOut,
+}
+
+/// Why a streamed reply ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its answer.
+    Stop,
+    /// The model hit its output token limit.
+    Length,
+    /// The client went away mid-stream, or replaced the turn with another.
+    Cancelled,
+    /// The stream failed after some content had arrived; that part was kept.
+    Error,
+}
+
+/// Completion statistics reported with `stream_end`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionStats {
+    pub model: String,
+    /// Not every backend reports token counts.
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub finish_reason: FinishReason,
+    /// Wall-clock time of the turn, including any wait for a generation slot.
+    pub duration_ms: u64,
+    /// Dollars, when the model is priced and reported its token counts.
+    pub cost: Option<f64>,
+}
+
+// ── Errors ───────────────────────────────────────────────────────────────────
+
+/// What went wrong, for clients to act on without parsing the message. Sent
+/// as `code` in REST error bodies and `error` events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed or a field fails validation.
+    InvalidRequest,
+    NotFound,
+    /// The conversation is encrypted and has not been unlocked.
+    ConversationLocked,
+    WrongPassphrase,
+    ModerationBlocked,
+    /// The model is not installed in Ollama.
+    ModelNotFound,
+    OllamaUnavailable,
+    /// Too many generations are waiting; try again shortly.
+    QueueFull,
+    /// Too many requests of this kind are in progress.
+    Overloaded,
+    Timeout,
+    /// An administrator cancelled the generation, or the client replaced
+    /// the turn before any of it had streamed.
+    Cancelled,
+    /// The model failed while generating.
+    InferenceFailed,
+    /// The server is in maintenance mode and refuses writes.
+    Maintenance,
+    InvalidConfig,
+    Internal,
+    /// The socket closed before the turn ended; raised by clients, never sent.
+    Disconnected,
+}
+
+/// Body of every REST error response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// Human-readable description.
+    pub error: String,
+    pub code: ErrorCode,
+}
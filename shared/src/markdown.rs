@@ -0,0 +1,441 @@
+//! Markdown of assistant replies, parsed so a reply can be shown while it is
+//! still streaming.
+//!
+//! Text is split into blocks first. Every block but the last is complete, so
+//! only the last one changes as chunks arrive and only it needs rendering
+//! again. Incomplete syntax in it is shown as it will most likely end up: an
+//! unterminated code fence is closed at the end of the text, and while
+//! streaming an unclosed `**` or `` ` `` runs to the end and a link whose URL
+//! is still arriving shows just its text.
+
+/// A block of a reply.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    /// Level 1 to 6.
+    Heading(u8, Vec<Inline>),
+    Code { language: String, code: String },
+    Quote(Vec<Inline>),
+    List { ordered: bool, start: u32, items: Vec<Vec<Inline>> },
+    Rule,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Strong(Vec<Inline>),
+    Emphasis(Vec<Inline>),
+    Link { text: Vec<Inline>, url: String },
+}
+
+/// Splits `text` into the source of each block.
+pub fn split(text: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut kind = Kind::None;
+    let mut fence: Option<String> = None;
+
+    let flush = |current: &mut String, blocks: &mut Vec<String>| {
+        if !current.is_empty() {
+            blocks.push(std::mem::take(current));
+        }
+    };
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(marker) = &fence {
+            current.push_str(line);
+            if closes_fence(trimmed, marker) {
+                fence = None;
+                flush(&mut current, &mut blocks);
+                kind = Kind::None;
+            }
+            continue;
+        }
+        if let Some(marker) = fence_marker(trimmed) {
+            flush(&mut current, &mut blocks);
+            fence = Some(marker);
+            current.push_str(line);
+            kind = Kind::Fence;
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush(&mut current, &mut blocks);
+            kind = Kind::None;
+            continue;
+        }
+        let line_kind = Kind::of(trimmed);
+        let joins = match (kind, line_kind) {
+            (Kind::None | Kind::Heading | Kind::Rule | Kind::Fence, _) => false,
+            (_, Kind::Heading | Kind::Rule) => false,
+            (Kind::List, _) => true,
+            (Kind::Quote, next) => next == Kind::Quote,
+            (Kind::Paragraph, next) => next == Kind::Paragraph,
+        };
+        if !joins {
+            flush(&mut current, &mut blocks);
+            kind = line_kind;
+        }
+        current.push_str(line);
+    }
+    flush(&mut current, &mut blocks);
+    blocks
+}
+
+/// What a line starts, for [`split`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    None,
+    Paragraph,
+    Heading,
+    Fence,
+    Quote,
+    List,
+    Rule,
+}
+
+impl Kind {
+    fn of(line: &str) -> Self {
+        if heading(line).is_some() {
+            Kind::Heading
+        } else if is_rule(line) {
+            Kind::Rule
+        } else if line.starts_with('>') {
+            Kind::Quote
+        } else if list_item(line).is_some() {
+            Kind::List
+        } else {
+            Kind::Paragraph
+        }
+    }
+}
+
+/// Whether `line` closes the fence opened with `marker`.
+fn closes_fence(line: &str, marker: &str) -> bool {
+    line.starts_with(marker) && line.chars().all(|c| marker.starts_with(c))
+}
+
+/// The fence a line opens: three or more backticks or tildes.
+fn fence_marker(line: &str) -> Option<String> {
+    let first = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == first).count();
+    (len >= 3).then(|| first.to_string().repeat(len))
+}
+
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')))
+        .then(|| (level as u8, rest.trim()))
+}
+
+/// `---`, `***` or `___`, possibly spaced out.
+fn is_rule(line: &str) -> bool {
+    let mut marks = line.chars().filter(|c| !c.is_whitespace());
+    let Some(first) = marks.next().filter(|c| matches!(c, '-' | '*' | '_')) else {
+        return false;
+    };
+    let mut count = 1;
+    for c in marks {
+        if c != first {
+            return false;
+        }
+        count += 1;
+    }
+    count >= 3
+}
+
+/// A list item's number (`None` for bullets) and text.
+fn list_item(line: &str) -> Option<(Option<u32>, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return Some((None, rest));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+    let rest = line[digits..].strip_prefix(". ").or_else(|| line[digits..].strip_prefix(") "))?;
+    Some((line[..digits].parse().ok(), rest))
+}
+
+/// Parses the source of one block, as returned by [`split`]. `partial` is for
+/// the last block of a reply still streaming.
+pub fn parse_block(source: &str, partial: bool) -> Block {
+    let first = source.lines().next().unwrap_or_default().trim();
+    if let Some(marker) = fence_marker(first) {
+        let language = first.trim_start_matches(marker.as_str()).trim().to_string();
+        let mut lines: Vec<&str> = source.lines().skip(1).collect();
+        // Closed fences end with their marker; open ones run to the end.
+        if lines.last().is_some_and(|line| closes_fence(line.trim(), &marker)) {
+            lines.pop();
+        }
+        return Block::Code { language, code: lines.join("\n") };
+    }
+    if let Some((level, text)) = heading(first) {
+        return Block::Heading(level, inlines(text, partial));
+    }
+    if is_rule(first) {
+        return Block::Rule;
+    }
+    if first.starts_with('>') {
+        let text: Vec<&str> = source
+            .lines()
+            .map(|line| {
+                let line = line.trim().trim_start_matches('>');
+                line.strip_prefix(' ').unwrap_or(line)
+            })
+            .collect();
+        return Block::Quote(inlines(&text.join("\n"), partial));
+    }
+    if let Some((number, _)) = list_item(first) {
+        let mut items: Vec<String> = Vec::new();
+        for line in source.lines() {
+            match list_item(line.trim()) {
+                Some((_, text)) => items.push(text.to_string()),
+                // Continuation of the previous item
+                None => {
+                    if let Some(item) = items.last_mut() {
+                        item.push('\n');
+                        item.push_str(line.trim());
+                    }
+                }
+            }
+        }
+        let last = items.len().saturating_sub(1);
+        return Block::List {
+            ordered: number.is_some(),
+            start: number.unwrap_or(1),
+            items: items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| inlines(item, partial && i == last))
+                .collect(),
+        };
+    }
+    Block::Paragraph(inlines(source.trim_end(), partial))
+}
+
+/// Parses inline markup. With `partial`, markup left open at the end of
+/// `text` is closed there instead of shown literally.
+pub fn inlines(text: &str, partial: bool) -> Vec<Inline> {
+    let mut parsed = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let found = match c {
+            '`' => code_span(rest, partial),
+            '*' | '_' if rest[1..].starts_with(c) => strong(rest, c, partial),
+            '*' | '_' => emphasis(rest, c, plain.chars().last()),
+            '[' => link(rest, partial),
+            _ => None,
+        };
+        match found {
+            Some((inline, len)) => {
+                if !plain.is_empty() {
+                    parsed.push(Inline::Text(std::mem::take(&mut plain)));
+                }
+                if let Some(inline) = inline {
+                    parsed.push(inline);
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !plain.is_empty() {
+        parsed.push(Inline::Text(plain));
+    }
+    parsed
+}
+
+/// A parsed element (none for markup that shows nothing yet) and how many
+/// bytes of the text it took.
+type Found = Option<(Option<Inline>, usize)>;
+
+fn code_span(text: &str, partial: bool) -> Found {
+    let ticks = text.chars().take_while(|c| *c == '`').count();
+    let marker = &text[..ticks];
+    match text[ticks..].find(marker) {
+        Some(end) => {
+            let code = text[ticks..ticks + end].trim().to_string();
+            Some((Some(Inline::Code(code)), ticks + end + ticks))
+        }
+        None if partial => Some((Some(Inline::Code(text[ticks..].to_string())), text.len())),
+        None => None,
+    }
+}
+
+fn strong(text: &str, c: char, partial: bool) -> Found {
+    let marker = if c == '*' { "**" } else { "__" };
+    let inner = &text[2..];
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    match inner.find(marker) {
+        Some(0) => None,
+        Some(end) => Some((Some(Inline::Strong(inlines(&inner[..end], false))), 2 + end + 2)),
+        None if partial && !inner.is_empty() => {
+            Some((Some(Inline::Strong(inlines(inner, true))), text.len()))
+        }
+        // Nothing after the marker yet: wait for more.
+        None if partial => Some((None, text.len())),
+        None => None,
+    }
+}
+
+/// `*text*` or `_text_`. Underscores inside words (`snake_case`) are left
+/// alone.
+fn emphasis(text: &str, c: char, before: Option<char>) -> Found {
+    if c == '_' && before.is_some_and(char::is_alphanumeric) {
+        return None;
+    }
+    let inner = &text[1..];
+    if inner.is_empty() || inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = inner.find(c)?;
+    let after = inner[end + 1..].chars().next();
+    if end == 0
+        || inner[..end].ends_with(char::is_whitespace)
+        || (c == '_' && after.is_some_and(char::is_alphanumeric))
+    {
+        return None;
+    }
+    Some((Some(Inline::Emphasis(inlines(&inner[..end], false))), 1 + end + 1))
+}
+
+/// `[text](url)`. Only web and mail links are made clickable.
+fn link(text: &str, partial: bool) -> Found {
+    let close = text.find(']')?;
+    let label = &text[1..close];
+    let after = &text[close + 1..];
+    if !after.starts_with('(') {
+        return None;
+    }
+    match after.find(')') {
+        Some(end) => {
+            let url = after[1..end].trim();
+            let len = close + 1 + end + 1;
+            let safe = ["http://", "https://", "mailto:"].iter().any(|s| url.starts_with(s));
+            if !safe {
+                return Some((Some(Inline::Text(label.to_string())), len));
+            }
+            let text = inlines(label, false);
+            Some((Some(Inline::Link { text, url: url.to_string() }), len))
+        }
+        // The URL is still arriving.
+        None if partial => Some((Some(Inline::Text(label.to_string())), text.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.to_string())
+    }
+
+    /// Every block of `source`, the last one as still streaming.
+    fn render(source: &str) -> Vec<Block> {
+        let blocks = split(source);
+        let last = blocks.len().saturating_sub(1);
+        blocks.iter().enumerate().map(|(i, block)| parse_block(block, i == last)).collect()
+    }
+
+    #[test]
+    fn unterminated_fences_are_closed_at_the_end() {
+        let blocks = render("Look:\n\n```rust\nfn main() {\n    println!(\"hi\");");
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            blocks[1],
+            Block::Code {
+                language: "rust".to_string(),
+                code: "fn main() {\n    println!(\"hi\");".to_string(),
+            }
+        );
+        // Text after an open fence is code until it closes.
+        assert_eq!(split("```\n# not a heading\n\nstill code").len(), 1);
+        let closed = render("~~~~\ncode\n~~~~\nafter");
+        assert_eq!(closed[0], Block::Code { language: String::new(), code: "code".to_string() });
+        assert_eq!(closed[1], Block::Paragraph(vec![text("after")]));
+    }
+
+    #[test]
+    fn open_strong_and_code_run_to_the_end_while_streaming() {
+        assert_eq!(
+            inlines("a **bold", true),
+            [text("a "), Inline::Strong(vec![text("bold")])]
+        );
+        assert_eq!(inlines("a `let x", true), [text("a "), Inline::Code("let x".to_string())]);
+        // A marker with nothing after it yet shows nothing.
+        assert_eq!(inlines("a **", true), [text("a ")]);
+        // Once complete, markup left open is shown as it was written.
+        assert_eq!(inlines("a **bold", false), [text("a **bold")]);
+        assert_eq!(inlines("a `let x", false), [text("a `let x")]);
+    }
+
+    #[test]
+    fn open_emphasis_is_shown_literally_until_it_closes() {
+        assert_eq!(inlines("an *open", true), [text("an *open")]);
+        assert_eq!(
+            inlines("an *open* one", true),
+            [text("an "), Inline::Emphasis(vec![text("open")]), text(" one")]
+        );
+        assert_eq!(inlines("snake_case_name", true), [text("snake_case_name")]);
+    }
+
+    #[test]
+    fn links_show_their_text_while_the_url_arrives() {
+        assert_eq!(
+            inlines("see [the docs](http://example.com/pa", true),
+            [text("see "), text("the docs")]
+        );
+        assert_eq!(
+            inlines("see [the docs](http://example.com/pa", false),
+            [text("see [the docs](http://example.com/pa")]
+        );
+        assert_eq!(
+            inlines("[the docs](https://example.com)", true),
+            [Inline::Link {
+                text: vec![text("the docs")],
+                url: "https://example.com".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_web_and_mail_links_are_clickable() {
+        for url in ["javascript:alert%281%29", "data:text/html,<script>", "JavaScript:void"] {
+            let parsed = inlines(&format!("[click]({url})"), false);
+            assert_eq!(parsed, [text("click")], "{url}");
+        }
+        let parsed = inlines("[mail](mailto:a@example.com)", false);
+        assert!(matches!(&parsed[0], Inline::Link { url, .. } if url == "mailto:a@example.com"));
+    }
+
+    #[test]
+    fn multibyte_text_parses_at_every_chunk_boundary() {
+        let reply = "# Título ñ\n\nÉl dijo **«hola»** y *adiós* — `código ✓` \
+                     [enlace 🔗](https://example.com/ü) __négrita__\n\n\
+                     - ítem 一\n- ítem 二 _énfasis_\n\n> cita 🎉\n\n```py\nprint('日本')\n```\n";
+        for (end, _) in reply.char_indices() {
+            let streamed = render(&reply[..end]);
+            assert!(streamed.len() <= split(reply).len(), "{end}");
+        }
+        let blocks = render(reply);
+        assert_eq!(blocks[0], Block::Heading(1, vec![text("Título ñ")]));
+        assert_eq!(
+            blocks[4],
+            Block::Code { language: "py".to_string(), code: "print('日本')".to_string() }
+        );
+    }
+}
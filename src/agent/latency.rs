@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::models::FirstTokenLatency;
+
+/// Fewer samples than this in the window are too few for a p95.
+const MIN_SAMPLES: usize = 5;
+
+/// Time to first token of recent streamed generations, checked against the
+/// `first_token_sla_ms` threshold.
+///
+/// The service is degraded while the p95 over the last
+/// `first_token_sla_window_secs` is above the threshold. Entering and leaving
+/// that state is logged once each, with the figures as structured fields.
+#[derive(Clone, Default)]
+pub struct FirstTokenMonitor {
+    inner: Arc<Mutex<Samples>>,
+}
+
+#[derive(Default)]
+struct Samples {
+    /// When each first token arrived and how long it took, oldest first.
+    recent: VecDeque<(Instant, Duration)>,
+    degraded: bool,
+}
+
+impl FirstTokenMonitor {
+    /// Records the first-token latency of one generation.
+    pub fn record(&self, latency: Duration, threshold_ms: u64, window: Duration) {
+        let mut samples = self.lock();
+        samples.recent.push_back((Instant::now(), latency));
+        samples.evaluate(threshold_ms, window);
+    }
+
+    /// The figures as of now; samples that have left the window no longer count.
+    pub fn status(&self, threshold_ms: u64, window: Duration) -> FirstTokenLatency {
+        self.lock().evaluate(threshold_ms, window)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Samples> {
+        self.inner.lock().expect("first-token samples poisoned")
+    }
+}
+
+impl Samples {
+    fn evaluate(&mut self, threshold_ms: u64, window: Duration) -> FirstTokenLatency {
+        let now = Instant::now();
+        while self.recent.front().is_some_and(|(at, _)| now.duration_since(*at) > window) {
+            self.recent.pop_front();
+        }
+        let p95_ms = (self.recent.len() >= MIN_SAMPLES).then(|| {
+            let mut millis: Vec<u64> =
+                self.recent.iter().map(|(_, latency)| latency.as_millis() as u64).collect();
+            millis.sort_unstable();
+            millis[(millis.len() * 95).div_ceil(100) - 1]
+        });
+        let degraded = threshold_ms > 0 && p95_ms.is_some_and(|p95| p95 > threshold_ms);
+        let status = FirstTokenLatency {
+            p95_ms,
+            threshold_ms,
+            window_secs: window.as_secs(),
+            samples: self.recent.len(),
+            degraded,
+        };
+        if degraded != self.degraded {
+            self.degraded = degraded;
+            if degraded {
+                warn!(
+                    p95_ms,
+                    threshold_ms,
+                    samples = status.samples,
+                    "First-token latency is over its SLA; Ollama is answering slowly"
+                );
+            } else {
+                let samples = status.samples;
+                info!(p95_ms, threshold_ms, samples, "First-token latency is back within its SLA");
+            }
+        }
+        status
+    }
+}
@@ -1,22 +1,287 @@
-use rig::agent::MultiTurnStreamItem;
+pub mod latency;
+pub mod preamble;
+pub mod scheduler;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rig::agent::{Agent, PromptRequest};
 use rig::client::Nothing;
-use rig::completion::Chat;
-use rig::message::Message as RigMessage;
+use rig::completion::{GetTokenUsage, Usage};
+use rig::message::{AssistantContent, Message as RigMessage, ToolCall};
 use rig::prelude::CompletionClient;
 use rig::providers::ollama;
-use rig::streaming::{StreamedAssistantContent, StreamingChat};
+use rig::streaming::{StreamedAssistantContent, StreamingCompletion};
+use rig::OneOrMany;
+use futures_util::future::{join_all, BoxFuture};
 use futures_util::StreamExt;
-use tracing::error;
+use tokio::sync::mpsc;
+use tracing::{error, field, info, instrument, warn, Span};
+use uuid::Uuid;
 
+use crate::agent::latency::FirstTokenMonitor;
+use crate::agent::preamble::{ContextBlock, ContextKind, ContextProvider};
+use crate::agent::scheduler::{GenerationLabel, GenerationScheduler};
+use crate::config::ConfigStore;
 use crate::errors::AppError;
-use crate::models::{Message, MessageRole};
+use crate::models::{
+    ChatContext, FinishReason, FirstTokenLatency, GenerationInfo, LoadedModel, Message, MessageRole,
+    ModelInfo, PullProgress, ResponseFormat, Source, ToolCallStatus, Verbosity,
+};
+use crate::tools::{SourceCollector, ToolRegistry};
+
+const TOOLS_PREAMBLE: &str = "Use the web_search tool when the question needs current \
+                              information. Cite search results inline as [id], using the \
+                              id returned with each result.";
+const WORKSPACE_PREAMBLE: &str = "The user's project is available through the list_files and \
+                                  read_file tools. Look at the relevant files before answering \
+                                  questions about its code, and name the files you rely on.";
+const JSON_PREAMBLE: &str = "Reply with a single JSON object and nothing else: no prose, \
+                             no markdown code fences.";
+const CONCISE_PREAMBLE: &str = "Keep replies short: answer in a few sentences or a brief \
+                                list, without introductions or recaps.";
+const DETAILED_PREAMBLE: &str = "Give thorough replies: explain your reasoning, cover \
+                                 caveats and alternatives, and include examples where they \
+                                 help.";
+/// Tokens a `concise` reply may run to before Ollama stops it.
+const CONCISE_MAX_TOKENS: u64 = 400;
+const SUMMARY_PROMPT: &str = "Summarize the following conversation in one or two short \
+                              sentences, so the user can recall what it was about. Reply \
+                              with the summary only.";
+/// `{count}` is replaced with how many suggestions are wanted.
+const SUGGESTIONS_PROMPT: &str = "Suggest {count} short follow-up messages the user might send \
+                                  next in the following conversation, written as the user. \
+                                  Reply with one per line and nothing else.";
+const RERANK_PROMPT: &str = "Judge how useful a document is for answering a question. Reply \
+                             with a single number from 0 (unrelated) to 10 (answers it) \
+                             and nothing else.";
+/// Longest follow-up suggestion kept, in characters.
+const MAX_SUGGESTION_CHARS: usize = 120;
+/// Upper bound on tool-call round trips before the model must answer.
+const MAX_TOOL_TURNS: usize = 3;
+/// Tool calls of a non-streamed turn run at most this many at a time.
+const PARALLEL_TOOL_CALLS: usize = 8;
+/// Lowercase fragments of Ollama errors that mean the prompt did not fit:
+/// too long for the context window, or too big for the memory available.
+const CONTEXT_ERRORS: &[&str] = &[
+    "context length",
+    "context window",
+    "exceeds the context",
+    "out of memory",
+    "requires more system memory",
+    "failed to allocate",
+];
+
+/// Progress reported by [`AgentService::stream_chat`].
+#[derive(Debug)]
+pub enum StreamUpdate {
+    /// Waiting for a free generation slot; 1-based position in the queue.
+    Queued { position: usize },
+    /// A text chunk from the model.
+    Chunk(String),
+    /// A tool call started (without `duration_ms`) or finished.
+    Tool {
+        call_id: String,
+        name: String,
+        status: ToolCallStatus,
+        duration_ms: Option<u64>,
+    },
+}
+
+/// How a streamed turn ended, returned by [`AgentService::stream_chat`].
+#[derive(Debug, Clone)]
+pub struct StreamOutcome {
+    /// Sources cited during the turn.
+    pub sources: Vec<Source>,
+    /// Tool calls made during the turn and their results, in order, as
+    /// `FUNCTION` and `TOOL` messages to store ahead of the reply.
+    pub tool_messages: Vec<Message>,
+    /// Model that produced the reply.
+    pub model: String,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub finish_reason: FinishReason,
+}
+
+/// Runs chat turns for [`ChatService`](crate::service::chat_service::ChatService).
+///
+/// [`OllamaAgentService`] is the production implementation; tests substitute
+/// their own to avoid needing a model.
+pub trait AgentService: Send + Sync {
+    /// Runs one turn to completion and returns the assistant reply, including
+    /// any cited sources.
+    fn chat<'a>(&'a self, ctx: &'a ChatContext) -> BoxFuture<'a, Result<Message, AppError>>;
+
+    /// Streams one turn, sending queue positions and content chunks through
+    /// `tx`. Returns the cited sources and completion details; the caller
+    /// accumulates and persists the content.
+    fn stream_chat<'a>(
+        &'a self,
+        ctx: &'a ChatContext,
+        tx: mpsc::Sender<StreamUpdate>,
+    ) -> BoxFuture<'a, Result<StreamOutcome, AppError>>;
+
+    /// Models available to [`AgentService::chat`].
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelInfo>, AppError>>;
+
+    /// Models held in memory right now, ready to answer without loading.
+    fn loaded_models(&self) -> BoxFuture<'_, Result<Vec<LoadedModel>, AppError>>;
+
+    /// Loads `model` into memory without generating anything, so the next
+    /// turn with it starts answering right away.
+    fn warm_up<'a>(&'a self, model: &'a str) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Downloads `model`, sending progress through `tx`. Resolves once the
+    /// model is ready to use.
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        tx: mpsc::Sender<PullProgress>,
+    ) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Runs `text` through the safety classifier `model`. Returns the
+    /// violated categories (e.g. `"S1,S10"`) when it is judged unsafe.
+    fn moderate<'a>(
+        &'a self,
+        model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>>;
+
+    /// Writes a one- or two-sentence summary of a conversation `transcript`
+    /// with `model`.
+    fn summarize<'a>(
+        &'a self,
+        model: &'a str,
+        transcript: &'a str,
+    ) -> BoxFuture<'a, Result<String, AppError>>;
+
+    /// Proposes up to `count` short messages the user might send next in a
+    /// conversation `transcript`, with `model`.
+    fn suggest_follow_ups<'a>(
+        &'a self,
+        model: &'a str,
+        transcript: &'a str,
+        count: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>>;
+
+    /// Embeds each of `texts` with the embedding `model`, in order.
+    fn embed<'a>(
+        &'a self,
+        model: &'a str,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, AppError>>;
+
+    /// Scores how relevant each of `documents` is to `query` with `model`,
+    /// from 0 to 1, in order.
+    fn rerank<'a>(
+        &'a self,
+        model: &'a str,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>>;
+
+    /// Generations running right now, oldest first.
+    fn generations(&self) -> Vec<GenerationInfo>;
+
+    /// Stops the running generation `id`. A streamed turn keeps what it has
+    /// produced so far. `false` if no such generation is running.
+    fn cancel_generation(&self, id: &str) -> bool;
+
+    /// Time to first token of recent streamed turns, against the configured SLA.
+    fn first_token_latency(&self) -> FirstTokenLatency;
+
+    /// Names of the built-in tools turns may call.
+    fn tools(&self) -> Vec<String>;
+}
+
+/// Body of Ollama's `GET /api/tags`.
+#[derive(serde::Deserialize)]
+struct OllamaTags {
+    models: Vec<ModelInfo>,
+}
+
+/// Body of Ollama's `GET /api/ps`.
+#[derive(serde::Deserialize)]
+struct OllamaRunning {
+    models: Vec<LoadedModel>,
+}
+
+/// Body of a non-streaming Ollama `POST /api/chat`.
+#[derive(serde::Deserialize)]
+struct OllamaChatReply {
+    message: OllamaReplyMessage,
+}
+
+#[derive(serde::Deserialize)]
+struct OllamaReplyMessage {
+    content: String,
+}
+
+/// Body of Ollama's `POST /api/embed`.
+#[derive(serde::Deserialize)]
+struct OllamaEmbeddings {
+    embeddings: Vec<Vec<f32>>,
+}
 
-const DEFAULT_MODEL: &str = "llama3.2";
-const PREAMBLE: &str = "You are a helpful AI assistant running locally via Ollama. \
-                        Be concise, accurate, and friendly. \
-                        If you don't know something, say so.";
+/// Reads one suggestion per line, dropping list markers and quotes the model
+/// may add anyway.
+fn parse_suggestions(reply: &str, count: usize) -> Vec<String> {
+    reply
+        .lines()
+        .map(|line| {
+            let line = line.trim().trim_start_matches(['-', '*', '•']);
+            let line = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            let line = line.trim_start_matches(['.', ')']).trim();
+            line.trim_matches('"').trim().to_string()
+        })
+        .filter(|line| !line.is_empty() && line.chars().count() <= MAX_SUGGESTION_CHARS)
+        .take(count)
+        .collect()
+}
+
+/// Reads a Llama Guard verdict: `safe`, or `unsafe` followed by a line of
+/// category codes. Anything else is treated as unsafe with no category.
+fn parse_guard_verdict(reply: &str) -> Option<String> {
+    let mut lines = reply.lines().map(str::trim).filter(|l| !l.is_empty());
+    match lines.next() {
+        Some(first) if first.eq_ignore_ascii_case("safe") => None,
+        Some(first) if first.eq_ignore_ascii_case("unsafe") => {
+            Some(lines.next().unwrap_or_default().to_string())
+        }
+        _ => Some(String::new()),
+    }
+}
+
+/// Reads a reranker's 0–10 verdict as a score from 0 to 1. A reply without
+/// a number scores 0.
+fn parse_relevance(reply: &str) -> f32 {
+    let number = reply
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .find_map(|word| word.trim_matches('.').parse::<f32>().ok());
+    number.map_or(0.0, |n| (n / 10.0).clamp(0.0, 1.0))
+}
+
+/// Parses one NDJSON line of Ollama's `/api/pull`. Blank lines yield `None`;
+/// an `{"error": ...}` line yields the error text.
+fn parse_pull_line(line: &[u8]) -> Result<Option<PullProgress>, String> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let value: serde_json::Value =
+        serde_json::from_str(line).map_err(|e| format!("Unexpected pull response: {e}"))?;
+    if let Some(message) = value["error"].as_str() {
+        return Err(message.to_string());
+    }
+    serde_json::from_value(value)
+        .map(Some)
+        .map_err(|e| format!("Unexpected pull response: {e}"))
+}
 
 /// Builds a rig [`RigMessage`] history list from stored [`Message`] records.
+/// Tool calls become assistant tool-call content and their results user
+/// tool-result content, which is how rig hands them to Ollama.
 fn to_rig_history(messages: &[Message]) -> Vec<RigMessage> {
     messages
         .iter()
@@ -24,14 +289,42 @@ fn to_rig_history(messages: &[Message]) -> Vec<RigMessage> {
             MessageRole::User => Some(RigMessage::user(&m.content)),
             MessageRole::Assistant => Some(RigMessage::assistant(&m.content)),
             MessageRole::System => None, // system prompt is set via preamble
+            MessageRole::Function => {
+                let name = m.tool_name.as_deref()?;
+                let arguments = serde_json::from_str(&m.content)
+                    .unwrap_or_else(|_| serde_json::Value::String(m.content.clone()));
+                let id = m.tool_call_id.as_deref().unwrap_or(name);
+                Some(RigMessage::Assistant {
+                    id: None,
+                    content: OneOrMany::one(AssistantContent::tool_call(id, name, arguments)),
+                })
+            }
+            // Ollama matches results to calls by tool name, which rig takes
+            // from the result's id.
+            MessageRole::Tool => Some(RigMessage::tool_result_with_call_id(
+                m.tool_name.as_deref()?,
+                m.tool_call_id.clone(),
+                &m.content,
+            )),
         })
         .collect()
 }
 
+fn label(kind: &'static str, conversation_id: Option<Uuid>, model: &str) -> GenerationLabel {
+    GenerationLabel { kind, conversation_id, model: model.to_string() }
+}
+
 /// Maps a rig error string to an [`AppError`].
 fn map_rig_error(e: &str, base_url: &str, model: &str) -> AppError {
-    if e.contains("Connection refused") || e.contains("connect") {
+    let lowercase = e.to_lowercase();
+    if e.contains("Connection refused")
+        || e.contains("connect")
+        || e.contains("error sending request")
+    {
         AppError::OllamaUnavailable { host: base_url.to_string() }
+    } else if CONTEXT_ERRORS.iter().any(|needle| lowercase.contains(needle)) {
+        // Checked before the catch-all below: these mention the model too.
+        AppError::ContextTooLarge { model_name: model.to_string(), message: e.to_string() }
     } else if e.contains("model") {
         AppError::ModelNotFound { model_name: model.to_string() }
     } else {
@@ -40,106 +333,741 @@ fn map_rig_error(e: &str, base_url: &str, model: &str) -> AppError {
 }
 
 /// Service that uses the rig [`ollama::Client`] to run chat turns.
-/// A fresh agent is built per request so the history is replayed from the DB each time.
+/// A fresh agent is built per request so the history is replayed from the DB each time,
+/// and the model and system prompt are read from the live [`ConfigStore`].
 #[derive(Clone)]
 pub struct OllamaAgentService {
     client: ollama::Client,
+    http: reqwest::Client,
     base_url: String,
-    model: String,
+    config: ConfigStore,
+    scheduler: GenerationScheduler,
+    first_token: FirstTokenMonitor,
+    tools: ToolRegistry,
+    context_providers: Vec<Arc<dyn ContextProvider>>,
 }
 
 impl OllamaAgentService {
-    pub fn new(base_url: &str) -> Self {
+    pub fn new(config: ConfigStore, tools: ToolRegistry) -> Self {
+        let startup = config.get();
+        let base_url = startup.ollama_base_url.clone();
+        let scheduler = GenerationScheduler::new(
+            startup.max_concurrent_generations,
+            startup.max_queued_generations,
+        );
         let client = ollama::Client::builder()
             .api_key(Nothing)
-            .base_url(base_url)
+            .base_url(&base_url)
             .build()
             .expect("Failed to build Ollama client");
         Self {
             client,
-            base_url: base_url.to_string(),
-            model: DEFAULT_MODEL.to_string(),
+            http: reqwest::Client::new(),
+            base_url,
+            config,
+            scheduler,
+            first_token: FirstTokenMonitor::default(),
+            tools,
+            context_providers: Vec::new(),
+        }
+        .with_context_provider(Arc::new(preamble::RetrievedChunks))
+    }
+
+    /// Registers a provider whose blocks are injected into every turn's preamble.
+    pub fn with_context_provider(mut self, provider: Arc<dyn ContextProvider>) -> Self {
+        self.context_providers.push(provider);
+        self
+    }
+
+    /// Renders the preamble template for this turn (the preset's, if the
+    /// conversation has one with its own), including the user's custom
+    /// instructions and every provider's blocks.
+    async fn resolve_preamble(&self, template: &str, ctx: &ChatContext) -> String {
+        let template = ctx.preferences.system_prompt.as_deref().unwrap_or(template);
+        let mut blocks = Vec::new();
+        if let Some(instructions) = &ctx.preferences.custom_instructions {
+            blocks.push(ContextBlock {
+                kind: ContextKind::Instructions,
+                title: "Custom instructions".to_string(),
+                content: instructions.clone(),
+            });
+        }
+        for provider in &self.context_providers {
+            blocks.extend(provider.blocks(ctx).await);
         }
+        preamble::render(template, ctx, blocks)
     }
 
-    /// Sends a chat turn to the local Ollama LLM, replaying `history` as context.
-    /// Returns the complete response (non-streaming).
-    pub async fn chat(
+    /// Builds a fresh agent for `ctx`'s turn whose tools report cited pages
+    /// into `sources`.
+    fn build_agent(
         &self,
-        conversation_id: &str,
-        history: &[Message],
-        user_message: &str,
-    ) -> Result<Message, AppError> {
-        let agent = self
-            .client
-            .agent(&self.model)
-            .preamble(PREAMBLE)
-            .build();
-
-        let rig_history = to_rig_history(history);
-
-        let content = agent
-            .chat(user_message, rig_history)
+        model: &str,
+        ctx: &ChatContext,
+        preamble: &str,
+        sources: &SourceCollector,
+    ) -> Agent<ollama::CompletionModel> {
+        let preferences = &ctx.preferences;
+        let mut builder = self.client.agent(model).preamble(preamble);
+        if let Some(temperature) = preferences.temperature {
+            builder = builder.temperature(temperature);
+        }
+        // rig sends `keep_alive` at the top level and the rest as `options`.
+        let mut params = serde_json::Map::new();
+        if let Some(keep_alive) = &self.config.get().keep_alive {
+            params.insert("keep_alive".to_string(), keep_alive.clone().into());
+        }
+        match preferences.verbosity {
+            Verbosity::Concise => {
+                // Ollama reads the cap from `options.num_predict`.
+                builder = builder.append_preamble(CONCISE_PREAMBLE);
+                params.insert("num_predict".to_string(), CONCISE_MAX_TOKENS.into());
+            }
+            Verbosity::Normal => {}
+            Verbosity::Detailed => builder = builder.append_preamble(DETAILED_PREAMBLE),
+        }
+        if !params.is_empty() {
+            builder = builder.additional_params(serde_json::Value::Object(params));
+        }
+        if preferences.response_format == ResponseFormat::Json {
+            // Ollama constrains the output to the schema passed as `format`.
+            builder = builder
+                .append_preamble(JSON_PREAMBLE)
+                .output_schema_raw(schemars::json_schema!({ "type": "object" }));
+        }
+        let tools = self.tools.build(ctx.conversation_id, sources, &ctx.webhook_tools);
+        if tools.is_empty() {
+            return builder.build();
+        }
+        // Webhook tools describe themselves.
+        if self.tools.has_web_search() {
+            builder = builder.append_preamble(TOOLS_PREAMBLE);
+        }
+        if self.tools.has_workspace() {
+            builder = builder.append_preamble(WORKSPACE_PREAMBLE);
+        }
+        builder.default_max_turns(MAX_TOOL_TURNS).tools(tools).build()
+    }
+}
+
+impl AgentService for OllamaAgentService {
+    fn chat<'a>(&'a self, ctx: &'a ChatContext) -> BoxFuture<'a, Result<Message, AppError>> {
+        Box::pin(self.run_chat(ctx))
+    }
+
+    fn stream_chat<'a>(
+        &'a self,
+        ctx: &'a ChatContext,
+        tx: mpsc::Sender<StreamUpdate>,
+    ) -> BoxFuture<'a, Result<StreamOutcome, AppError>> {
+        Box::pin(self.run_stream_chat(ctx, tx))
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelInfo>, AppError>> {
+        Box::pin(async move {
+            let unavailable = |e: reqwest::Error| {
+                error!("Failed to list Ollama models: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
+            };
+            let tags: OllamaTags = self
+                .http
+                .get(format!("{}/api/tags", self.base_url.trim_end_matches('/')))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(unavailable)?
+                .json()
+                .await
+                .map_err(unavailable)?;
+            Ok(tags.models)
+        })
+    }
+
+    fn loaded_models(&self) -> BoxFuture<'_, Result<Vec<LoadedModel>, AppError>> {
+        Box::pin(async move {
+            let unavailable = |e: reqwest::Error| {
+                error!("Failed to list loaded Ollama models: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
+            };
+            let running: OllamaRunning = self
+                .http
+                .get(format!("{}/api/ps", self.base_url.trim_end_matches('/')))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(unavailable)?
+                .json()
+                .await
+                .map_err(unavailable)?;
+            Ok(running.models)
+        })
+    }
+
+    fn warm_up<'a>(&'a self, model: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(self.run_warm_up(model))
+    }
+
+    fn pull_model<'a>(
+        &'a self,
+        model: &'a str,
+        tx: mpsc::Sender<PullProgress>,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(self.run_pull(model, tx))
+    }
+
+    fn moderate<'a>(
+        &'a self,
+        model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(self.run_moderate(model, text))
+    }
+
+    fn summarize<'a>(
+        &'a self,
+        model: &'a str,
+        transcript: &'a str,
+    ) -> BoxFuture<'a, Result<String, AppError>> {
+        Box::pin(self.run_summarize(model, transcript))
+    }
+
+    fn suggest_follow_ups<'a>(
+        &'a self,
+        model: &'a str,
+        transcript: &'a str,
+        count: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>> {
+        Box::pin(self.run_suggest_follow_ups(model, transcript, count))
+    }
+
+    fn embed<'a>(
+        &'a self,
+        model: &'a str,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, AppError>> {
+        Box::pin(self.run_embed(model, texts))
+    }
+
+    fn rerank<'a>(
+        &'a self,
+        model: &'a str,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>> {
+        Box::pin(self.run_rerank(model, query, documents))
+    }
+
+    fn generations(&self) -> Vec<GenerationInfo> {
+        self.scheduler.running()
+    }
+
+    fn cancel_generation(&self, id: &str) -> bool {
+        self.scheduler.cancel(id)
+    }
+
+    fn first_token_latency(&self) -> FirstTokenLatency {
+        let config = self.config.get();
+        let window = Duration::from_secs(config.first_token_sla_window_secs);
+        self.first_token.status(config.first_token_sla_ms, window)
+    }
+
+    fn tools(&self) -> Vec<String> {
+        self.tools.names()
+    }
+}
+
+impl OllamaAgentService {
+    /// Pulls `model` through Ollama's streaming `/api/pull`, forwarding each
+    /// progress line. The download carries on if `tx`'s receiver goes away.
+    #[instrument(skip(self, tx))]
+    async fn run_pull(&self, model: &str, tx: mpsc::Sender<PullProgress>) -> Result<(), AppError> {
+        let failed = |message: String| {
+            error!("Failed to pull model {model}: {message}");
+            AppError::ModelPullFailed { model_name: model.to_string(), message }
+        };
+        let mut resp = self
+            .http
+            .post(format!("{}/api/pull", self.base_url.trim_end_matches('/')))
+            .json(&serde_json::json!({ "model": model, "stream": true }))
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to reach Ollama to pull {model}: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
+            })?;
+        if !resp.status().is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            let message = parse_pull_line(body.as_bytes()).err().unwrap_or(body);
+            return Err(failed(message));
+        }
+
+        let mut buffer = Vec::new();
+        let mut succeeded = false;
+        loop {
+            let chunk = resp.chunk().await.map_err(|e| failed(e.to_string()))?;
+            match &chunk {
+                Some(bytes) => buffer.extend_from_slice(bytes),
+                // Terminate a last line that lacks its newline.
+                None => buffer.push(b'\n'),
+            }
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                if let Some(progress) = parse_pull_line(&line).map_err(failed)? {
+                    succeeded |= progress.status == "success";
+                    let _ = tx.send(progress).await;
+                }
+            }
+            if chunk.is_none() {
+                break;
+            }
+        }
+
+        if !succeeded {
+            return Err(failed("Ollama ended the pull without reporting success".to_string()));
+        }
+        info!("Pulled model {model}");
+        Ok(())
+    }
+
+    /// Classifies a single user message. Runs outside the generation queue:
+    /// guard models are small and answer with a few tokens.
+    #[instrument(skip(self, text))]
+    async fn run_moderate(&self, model: &str, text: &str) -> Result<Option<String>, AppError> {
+        let messages = serde_json::json!([{ "role": "user", "content": text }]);
+        let reply = self.complete(model, messages, "moderation").await?;
+        Ok(parse_guard_verdict(&reply))
+    }
+
+    /// Summarizes a conversation. Background work, but still a full
+    /// generation, so it waits for a slot like a chat turn.
+    #[instrument(skip(self, transcript))]
+    async fn run_summarize(&self, model: &str, transcript: &str) -> Result<String, AppError> {
+        let generation = self.scheduler.start(label("summary", None, model), |_| {}).await?;
+        let messages = serde_json::json!([
+            { "role": "system", "content": SUMMARY_PROMPT },
+            { "role": "user", "content": transcript },
+        ]);
+        let reply = generation.unless_cancelled(self.complete(model, messages, "summary")).await?;
+        Ok(reply.trim().to_string())
+    }
+
+    /// Proposes follow-up messages. Like summaries, waits for a generation
+    /// slot.
+    #[instrument(skip(self, transcript))]
+    async fn run_suggest_follow_ups(
+        &self,
+        model: &str,
+        transcript: &str,
+        count: usize,
+    ) -> Result<Vec<String>, AppError> {
+        let generation = self.scheduler.start(label("suggestions", None, model), |_| {}).await?;
+        let prompt = SUGGESTIONS_PROMPT.replace("{count}", &count.to_string());
+        let messages = serde_json::json!([
+            { "role": "system", "content": prompt },
+            { "role": "user", "content": transcript },
+        ]);
+        let reply =
+            generation.unless_cancelled(self.complete(model, messages, "suggestions")).await?;
+        Ok(parse_suggestions(&reply, count))
+    }
+
+    /// A `/api/generate` request without a prompt, which makes Ollama load
+    /// `model` and keep it for `keep_alive`.
+    #[instrument(skip(self))]
+    async fn run_warm_up(&self, model: &str) -> Result<(), AppError> {
+        let mut body = serde_json::json!({ "model": model });
+        if let Some(keep_alive) = &self.config.get().keep_alive {
+            body["keep_alive"] = keep_alive.clone().into();
+        }
+        let resp = self
+            .http
+            .post(format!("{}/api/generate", self.base_url.trim_end_matches('/')))
+            .json(&body)
+            .send()
             .await
             .map_err(|e| {
-                error!("Ollama inference failed for conversation {conversation_id}: {e}");
-                map_rig_error(&e.to_string(), &self.base_url, &self.model)
+                error!("Failed to reach Ollama to warm up {model}: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
             })?;
+        if !resp.status().is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(map_rig_error(&body, &self.base_url, model));
+        }
+        Ok(())
+    }
+
+    /// Embeds `texts` in one `/api/embed` request. Runs outside the generation
+    /// queue: embedding models are small and do not generate.
+    #[instrument(skip(self, texts), fields(count = texts.len()))]
+    async fn run_embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, AppError> {
+        let mut body = serde_json::json!({ "model": model, "input": texts });
+        if let Some(keep_alive) = &self.config.get().keep_alive {
+            body["keep_alive"] = keep_alive.clone().into();
+        }
+        let resp = self
+            .http
+            .post(format!("{}/api/embed", self.base_url.trim_end_matches('/')))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to reach Ollama to embed with {model}: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
+            })?;
+        if !resp.status().is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            error!("Ollama embedding request with {model} failed: {body}");
+            return Err(map_rig_error(&body, &self.base_url, model));
+        }
+        let reply: OllamaEmbeddings = resp.json().await.map_err(|e| AppError::InferenceError {
+            message: format!("Unexpected embedding response: {e}"),
+        })?;
+        if reply.embeddings.len() != texts.len() {
+            return Err(AppError::InferenceError {
+                message: format!(
+                    "Ollama returned {} embeddings for {} texts",
+                    reply.embeddings.len(),
+                    texts.len()
+                ),
+            });
+        }
+        Ok(reply.embeddings)
+    }
+
+    /// Asks `model` for a verdict on each document, all at once. Like
+    /// moderation, runs outside the generation queue: rerankers are small and
+    /// answer with a few tokens.
+    #[instrument(skip(self, query, documents), fields(count = documents.len()))]
+    async fn run_rerank(
+        &self,
+        model: &str,
+        query: &str,
+        documents: &[String],
+    ) -> Result<Vec<f32>, AppError> {
+        let verdicts = documents.iter().map(|document| {
+            let question = format!("Question: {query}\n\nDocument:\n{document}");
+            let messages = serde_json::json!([
+                { "role": "system", "content": RERANK_PROMPT },
+                { "role": "user", "content": question },
+            ]);
+            self.complete(model, messages, "rerank")
+        });
+        join_all(verdicts)
+            .await
+            .into_iter()
+            .map(|reply| reply.map(|reply| parse_relevance(&reply)))
+            .collect()
+    }
+
+    /// One non-streaming `/api/chat` request without tools; returns the reply
+    /// text. `purpose` names the request in logs and errors.
+    async fn complete(
+        &self,
+        model: &str,
+        messages: serde_json::Value,
+        purpose: &str,
+    ) -> Result<String, AppError> {
+        let mut body = serde_json::json!({
+            "model": model,
+            "messages": messages,
+            "stream": false,
+        });
+        if let Some(keep_alive) = &self.config.get().keep_alive {
+            body["keep_alive"] = keep_alive.clone().into();
+        }
+        let resp = self
+            .http
+            .post(format!("{}/api/chat", self.base_url.trim_end_matches('/')))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to reach Ollama for {purpose}: {e}");
+                AppError::OllamaUnavailable { host: self.base_url.clone() }
+            })?;
+        if !resp.status().is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            error!("Ollama {purpose} request with {model} failed: {body}");
+            return Err(map_rig_error(&body, &self.base_url, model));
+        }
+        let reply: OllamaChatReply = resp.json().await.map_err(|e| AppError::InferenceError {
+            message: format!("Unexpected {purpose} response: {e}"),
+        })?;
+        Ok(reply.message.content)
+    }
+
+    /// Sends a chat turn to the local Ollama LLM, replaying the context's history.
+    /// Returns the complete response (non-streaming), including any cited sources.
+    /// Waits for a free generation slot first.
+    #[instrument(skip_all, fields(conversation_id = %ctx.conversation_id, model = field::Empty))]
+    async fn run_chat(&self, ctx: &ChatContext) -> Result<Message, AppError> {
+        let config = self.config.get();
+        let model = ctx.preferences.model.as_deref().unwrap_or(&config.model);
+        let conversation_id = Some(ctx.conversation_id);
+        let generation = self.scheduler.start(label("chat", conversation_id, model), |_| {}).await?;
+        let sources = SourceCollector::default();
+        Span::current().record("model", model);
+        let preamble = self.resolve_preamble(&config.system_prompt, ctx).await;
+        let agent = self.build_agent(model, ctx, &preamble, &sources);
+
+        let mut rig_history = to_rig_history(&ctx.history);
+
+        let reply = async {
+            let prompt = PromptRequest::from_agent(&agent, ctx.user_message.as_str())
+                .with_history(&mut rig_history)
+                .with_tool_concurrency(PARALLEL_TOOL_CALLS);
+            prompt.await.map_err(|e| {
+                error!("Ollama inference failed for conversation {}: {e}", ctx.conversation_id);
+                map_rig_error(&e.to_string(), &self.base_url, model)
+            })
+        };
+        let content = generation.unless_cancelled(reply).await?;
 
         Ok(Message::new(
-            conversation_id.to_string(),
+            ctx.conversation_id,
             MessageRole::Assistant,
             content,
-        ))
+        )
+        .with_sources(sources.take()))
     }
 
-    /// Streams a chat response from Ollama token-by-token using rig's native
-    /// [`StreamingChat`] trait.
+    /// Streams a chat response from Ollama token-by-token.
+    ///
+    /// Queue positions (while waiting for a generation slot), content chunks
+    /// and the progress of tool calls are sent through `tx`. The caller is
+    /// responsible for accumulating the full response and persisting it.
+    /// Returns the sources cited by tool calls made during the turn, along with
+    /// token usage (summed over its requests) and why the stream ended.
+    ///
+    /// When a response asks for tools, they are run (see [`Self::run_tools`])
+    /// and their results sent back in a new request, for up to
+    /// [`MAX_TOOL_TURNS`] rounds.
     ///
-    /// Each content chunk is sent through `tx`. The caller is responsible for
-    /// accumulating the full response and persisting it.
-    pub async fn stream_chat(
+    /// An error before any content arrives fails the turn; a later one ends it
+    /// with [`FinishReason::Error`] so the partial reply can still be kept.
+    #[instrument(skip_all, fields(
+        conversation_id = %ctx.conversation_id,
+        model = field::Empty,
+        prompt_tokens = field::Empty,
+        completion_tokens = field::Empty,
+        finish_reason = field::Empty,
+    ))]
+    async fn run_stream_chat(
         &self,
-        conversation_id: &str,
-        history: &[Message],
-        user_message: &str,
-        tx: tokio::sync::mpsc::Sender<String>,
-    ) -> Result<(), AppError> {
-        let agent = self
-            .client
-            .agent(&self.model)
-            .preamble(PREAMBLE)
-            .build();
-
-        let rig_history = to_rig_history(history);
-
-        let mut stream = agent
-            .stream_chat(user_message, rig_history)
-            .await;
-
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(MultiTurnStreamItem::StreamAssistantItem(
-                    StreamedAssistantContent::Text(text),
-                )) => {
-                    // Send the text chunk to the WebSocket handler
-                    if tx.send(text.text).await.is_err() {
-                        // Receiver dropped — client disconnected
-                        return Ok(());
+        ctx: &ChatContext,
+        tx: mpsc::Sender<StreamUpdate>,
+    ) -> Result<StreamOutcome, AppError> {
+        let config = self.config.get();
+        let model = ctx.preferences.model.as_deref().unwrap_or(&config.model);
+        let generation = self
+            .scheduler
+            .start(label("chat", Some(ctx.conversation_id), model), |position| {
+                let _ = tx.try_send(StreamUpdate::Queued { position });
+            })
+            .await?;
+        let sources = SourceCollector::default();
+        Span::current().record("model", model);
+        let preamble = self.resolve_preamble(&config.system_prompt, ctx).await;
+        let agent = self.build_agent(model, ctx, &preamble, &sources);
+        let tool_timeout = Duration::from_secs(config.tool_timeout_secs);
+
+        let mut history = to_rig_history(&ctx.history);
+        let mut prompt = RigMessage::user(ctx.user_message.as_str());
+        // Keep the partial reply, if there is one.
+        let cancelled = |streamed_any: bool| {
+            if !streamed_any {
+                return Err(generation.cancelled_error());
+            }
+            info!("Generation {} cancelled", generation.id());
+            Ok(FinishReason::Cancelled)
+        };
+        let failed = |e: &dyn std::fmt::Display, streamed_any: bool| {
+            error!("Streaming error for conversation {}: {e}", ctx.conversation_id);
+            if !streamed_any {
+                return Err(map_rig_error(&e.to_string(), &self.base_url, model));
+            }
+            Ok(FinishReason::Error)
+        };
+
+        let mut streamed_any = false;
+        let mut done_reason = None;
+        let mut usage = Usage::new();
+        let mut finish_reason = None;
+        let mut tool_messages: Vec<Message> = Vec::new();
+        'turn: for round in 0..=MAX_TOOL_TURNS {
+            let request = async {
+                agent.stream_completion(prompt.clone(), history.clone()).await?.stream().await
+            };
+            let mut stream = tokio::select! {
+                stream = request => match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        finish_reason = Some(failed(&e, streamed_any)?);
+                        break;
                     }
+                },
+                () = generation.cancelled() => {
+                    finish_reason = Some(cancelled(streamed_any)?);
+                    break;
                 }
-                Ok(_) => {
-                    // Ignore tool calls, user items, final responses, etc.
+            };
+
+            let mut calls = Vec::new();
+            loop {
+                let item = tokio::select! {
+                    item = stream.next() => item,
+                    () = generation.cancelled() => {
+                        finish_reason = Some(cancelled(streamed_any)?);
+                        break 'turn;
+                    }
+                };
+                let Some(item) = item else { break };
+                match item {
+                    Ok(StreamedAssistantContent::Text(text)) => {
+                        if !streamed_any {
+                            let window = Duration::from_secs(config.first_token_sla_window_secs);
+                            let sla = config.first_token_sla_ms;
+                            self.first_token.record(generation.elapsed(), sla, window);
+                        }
+                        streamed_any = true;
+                        generation.add_tokens(1);
+                        // Send the text chunk to the WebSocket handler
+                        if tx.send(StreamUpdate::Chunk(text.text)).await.is_err() {
+                            // Receiver dropped — client disconnected
+                            finish_reason = Some(FinishReason::Cancelled);
+                            break 'turn;
+                        }
+                    }
+                    Ok(StreamedAssistantContent::ToolCall { tool_call, internal_call_id }) => {
+                        tool_messages.push(Message::tool(
+                            ctx.conversation_id,
+                            MessageRole::Function,
+                            tool_call.function.name.clone(),
+                            internal_call_id.clone(),
+                            tool_call.function.arguments.to_string(),
+                        ));
+                        calls.push((tool_call, internal_call_id));
+                    }
+                    Ok(StreamedAssistantContent::Final(response)) => {
+                        if let Some(round_usage) = response.token_usage() {
+                            usage += round_usage;
+                        }
+                        done_reason = response.done_reason;
+                    }
+                    Ok(_) => {
+                        // Ignore tool-call deltas, reasoning, etc.
+                    }
+                    Err(e) => {
+                        finish_reason = Some(failed(&e, streamed_any)?);
+                        break 'turn;
+                    }
                 }
-                Err(e) => {
-                    error!("Streaming error for conversation {conversation_id}: {e}");
+            }
+            if calls.is_empty() {
+                break;
+            }
+            if round == MAX_TOOL_TURNS {
+                if !streamed_any {
                     return Err(AppError::InferenceError {
-                        message: e.to_string(),
+                        message: format!("Still calling tools after {MAX_TOOL_TURNS} rounds"),
                     });
                 }
+                break;
             }
+
+            let results = tokio::select! {
+                results = Self::run_tools(&agent, &calls, tool_timeout, &tx) => results,
+                () = generation.cancelled() => {
+                    finish_reason = Some(cancelled(streamed_any)?);
+                    break;
+                }
+            };
+            history.push(prompt);
+            let requested = calls.iter().map(|(call, _)| AssistantContent::ToolCall(call.clone()));
+            history.push(RigMessage::Assistant {
+                id: stream.message_id.clone(),
+                content: OneOrMany::many(requested).expect("at least one tool call"),
+            });
+            for ((call, internal_call_id), result) in calls.into_iter().zip(results) {
+                tool_messages.push(Message::tool(
+                    ctx.conversation_id,
+                    MessageRole::Tool,
+                    call.function.name.clone(),
+                    internal_call_id,
+                    result.clone(),
+                ));
+                // Ollama matches results to calls by tool name, then order.
+                history.push(RigMessage::tool_result_with_call_id(
+                    call.function.name,
+                    call.call_id,
+                    result,
+                ));
+            }
+            prompt = history.pop().expect("a tool result was just added");
         }
 
-        Ok(())
+        let finish_reason = finish_reason.unwrap_or(match done_reason.as_deref() {
+            Some("length") => FinishReason::Length,
+            _ => FinishReason::Stop,
+        });
+        // Ollama omits the counts in some responses; report them as unknown.
+        let usage = Some(usage).filter(|u| u.input_tokens + u.output_tokens > 0);
+        let span = Span::current();
+        span.record("prompt_tokens", usage.map(|u| u.input_tokens));
+        span.record("completion_tokens", usage.map(|u| u.output_tokens));
+        span.record("finish_reason", field::debug(finish_reason));
+        Ok(StreamOutcome {
+            sources: sources.take(),
+            tool_messages,
+            model: model.to_string(),
+            prompt_tokens: usage.map(|u| u.input_tokens),
+            completion_tokens: usage.map(|u| u.output_tokens),
+            finish_reason,
+        })
+    }
+
+    /// Runs the tool calls the model asked for in one response, all at the
+    /// same time, each given up on after `timeout`. A call that fails or times
+    /// out gets its error as its result, for the model to work with. Results
+    /// are returned in the order of `calls`, and the progress of each call is
+    /// reported through `tx`.
+    ///
+    /// Calls asked for together never depend on each other's results: one
+    /// that needs another's result is asked for in a later round.
+    async fn run_tools(
+        agent: &Agent<ollama::CompletionModel>,
+        calls: &[(ToolCall, String)],
+        timeout: Duration,
+        tx: &mpsc::Sender<StreamUpdate>,
+    ) -> Vec<String> {
+        join_all(calls.iter().map(|(call, call_id)| async move {
+            let name = call.function.name.as_str();
+            let update = |status, duration_ms| StreamUpdate::Tool {
+                call_id: call_id.clone(),
+                name: name.to_string(),
+                status,
+                duration_ms,
+            };
+            let _ = tx.send(update(ToolCallStatus::Running, None)).await;
+            let started = Instant::now();
+            let arguments = call.function.arguments.to_string();
+            let run = agent.tool_server_handle.call_tool(name, &arguments);
+            let (status, result) = match tokio::time::timeout(timeout, run).await {
+                Ok(Ok(result)) => (ToolCallStatus::Done, result),
+                Ok(Err(e)) => {
+                    warn!("Tool {name} failed: {e}");
+                    (ToolCallStatus::Failed, e.to_string())
+                }
+                Err(_) => {
+                    warn!("Tool {name} timed out after {timeout:?}");
+                    let secs = timeout.as_secs();
+                    (ToolCallStatus::TimedOut, format!("The tool did not answer within {secs}s"))
+                }
+            };
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let _ = tx.send(update(status, Some(duration_ms))).await;
+            result
+        }))
+        .await
     }
 }
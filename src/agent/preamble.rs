@@ -0,0 +1,101 @@
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+
+use crate::models::{ChatContext, RetrievedChunk};
+use crate::service::language;
+
+/// Default system prompt. `{{...}}` variables are resolved per request by [`render`].
+pub const DEFAULT_PREAMBLE: &str = "You are a helpful AI assistant running locally via Ollama. \
+                                    Be concise, accurate, and friendly. \
+                                    If you don't know something, say so. \
+                                    Today's date is {{date}}.";
+
+/// Where a context block comes from. Blocks are injected into the preamble in
+/// this order regardless of which provider produced them first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContextKind {
+    /// Standing instructions from the user or deployment.
+    Instructions,
+    /// Chunks retrieved for this specific turn.
+    Retrieved,
+}
+
+/// A titled block of text appended to the system prompt.
+#[derive(Debug, Clone)]
+pub struct ContextBlock {
+    pub kind: ContextKind,
+    pub title: String,
+    pub content: String,
+}
+
+/// Hook for app-provided context (retrieved chunks, …). Registered on
+/// [`super::OllamaAgentService`] and consulted before every turn.
+pub trait ContextProvider: Send + Sync {
+    fn blocks<'a>(&'a self, ctx: &'a ChatContext) -> BoxFuture<'a, Vec<ContextBlock>>;
+}
+
+/// Provides the chunks retrieved for the turn ([`ChatContext::retrieved`]);
+/// every [`super::OllamaAgentService`] has it.
+pub struct RetrievedChunks;
+
+impl ContextProvider for RetrievedChunks {
+    fn blocks<'a>(&'a self, ctx: &'a ChatContext) -> BoxFuture<'a, Vec<ContextBlock>> {
+        let blocks = if ctx.retrieved.is_empty() {
+            Vec::new()
+        } else {
+            vec![ContextBlock {
+                kind: ContextKind::Retrieved,
+                title: "Relevant code from the indexed codebase".to_string(),
+                content: retrieved_content(&ctx.retrieved),
+            }]
+        };
+        Box::pin(async move { blocks })
+    }
+}
+
+/// Resolves `{{date}}`, `{{user_name}}` and `{{conversation_title}}` in `template`,
+/// asks for replies in the conversation's language when it is known, then
+/// appends `blocks` ordered by [`ContextKind`]. Unknown variables are left as-is.
+pub fn render(template: &str, ctx: &ChatContext, mut blocks: Vec<ContextBlock>) -> String {
+    let date = Utc::now().format("%A, %B %-d, %Y").to_string();
+    let user_name = ctx.user_name.as_deref().unwrap_or("the user");
+
+    let mut preamble = template
+        .replace("{{date}}", &date)
+        .replace("{{user_name}}", user_name)
+        .replace("{{conversation_title}}", &ctx.conversation_title);
+
+    if let Some(name) = ctx.language.as_deref().and_then(language::english_name) {
+        preamble.push_str(&format!(
+            "\n\nThe user writes in {name}. Reply in {name} unless they ask otherwise."
+        ));
+    }
+
+    blocks.sort_by_key(|b| b.kind);
+    for block in blocks.iter().filter(|b| !b.content.trim().is_empty()) {
+        preamble.push_str("\n\n## ");
+        preamble.push_str(&block.title);
+        preamble.push('\n');
+        preamble.push_str(block.content.trim());
+    }
+    preamble
+}
+
+/// Retrieved chunks as a context block's content: each fenced, under its
+/// path and lines so the model can name where the code comes from.
+fn retrieved_content(chunks: &[RetrievedChunk]) -> String {
+    chunks
+        .iter()
+        .map(|chunk| {
+            format!(
+                "{} (lines {}-{}):\n```{}\n{}\n```",
+                chunk.path,
+                chunk.start_line,
+                chunk.end_line,
+                chunk.language.as_deref().unwrap_or_default(),
+                chunk.content
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
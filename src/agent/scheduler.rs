@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::GenerationInfo;
+
+/// Limits how many generations run against Ollama at once.
+///
+/// Requests beyond the limit wait in a FIFO queue (tokio's semaphore is fair);
+/// callers are told their position whenever it changes so the UI can explain
+/// why a stream has not started yet. Running generations are listed for
+/// operators, who can cancel them.
+#[derive(Clone)]
+pub struct GenerationScheduler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    permits: Arc<Semaphore>,
+    max_queued: usize,
+    next_ticket: AtomicU64,
+    /// Tickets currently waiting, front = next to run.
+    queue: Mutex<VecDeque<u64>>,
+    /// Pinged whenever a ticket leaves the queue.
+    queue_changed: watch::Sender<()>,
+    /// Generations holding a slot, by id.
+    running: Mutex<HashMap<String, Running>>,
+}
+
+/// What a generation is for, as shown to operators.
+#[derive(Debug, Clone)]
+pub struct GenerationLabel {
+    /// `chat`, `summary` or `suggestions`.
+    pub kind: &'static str,
+    pub conversation_id: Option<Uuid>,
+    pub model: String,
+}
+
+struct Running {
+    label: GenerationLabel,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    tokens: Arc<AtomicU64>,
+    cancel: CancellationToken,
+}
+
+/// A generation holding a slot. The slot is freed, and the generation
+/// unlisted, when this is dropped.
+pub struct Generation {
+    id: String,
+    inner: Arc<Inner>,
+    tokens: Arc<AtomicU64>,
+    cancel: CancellationToken,
+    started: Instant,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Generation {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Time since the generation got its slot; queueing is not counted.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Counts tokens the model has produced, for the listing.
+    pub fn add_tokens(&self, tokens: u64) {
+        self.tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// Resolves once an operator cancels the generation.
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    pub fn cancelled_error(&self) -> AppError {
+        AppError::GenerationCancelled { id: self.id.clone() }
+    }
+
+    /// Runs `work`, unless the generation is cancelled first.
+    pub async fn unless_cancelled<T>(
+        &self,
+        work: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        tokio::select! {
+            result = work => result,
+            () = self.cancelled() => Err(self.cancelled_error()),
+        }
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        self.inner.running.lock().expect("running generations poisoned").remove(&self.id);
+    }
+}
+
+/// Removes a ticket from the queue however the wait ends (permit or cancellation).
+struct QueueSlot<'a> {
+    inner: &'a Inner,
+    ticket: u64,
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.inner
+            .queue
+            .lock()
+            .expect("generation queue poisoned")
+            .retain(|t| *t != self.ticket);
+        self.inner.queue_changed.send_replace(());
+    }
+}
+
+impl GenerationScheduler {
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+                max_queued,
+                next_ticket: AtomicU64::new(0),
+                queue: Mutex::new(VecDeque::new()),
+                queue_changed: watch::channel(()).0,
+                running: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Waits for a generation slot, then lists the generation as running
+    /// until the returned guard is dropped. `on_queued` is called with the
+    /// 1-based queue position each time it changes; it is never called if a
+    /// slot is free.
+    pub async fn start(
+        &self,
+        label: GenerationLabel,
+        on_queued: impl Fn(usize),
+    ) -> Result<Generation, AppError> {
+        let permit = self.acquire(on_queued).await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let tokens = Arc::new(AtomicU64::new(0));
+        let cancel = CancellationToken::new();
+        let started = Instant::now();
+        let running = Running {
+            label,
+            started_at: Utc::now(),
+            started,
+            tokens: tokens.clone(),
+            cancel: cancel.clone(),
+        };
+        let mut generations = self.inner.running.lock().expect("running generations poisoned");
+        generations.insert(id.clone(), running);
+        drop(generations);
+        let inner = self.inner.clone();
+        Ok(Generation { id, inner, tokens, cancel, started, _permit: permit })
+    }
+
+    /// Generations holding a slot, oldest first.
+    pub fn running(&self) -> Vec<GenerationInfo> {
+        let running = self.inner.running.lock().expect("running generations poisoned");
+        let mut generations: Vec<GenerationInfo> = running
+            .iter()
+            .map(|(id, running)| GenerationInfo {
+                id: id.clone(),
+                kind: running.label.kind.to_string(),
+                conversation_id: running.label.conversation_id,
+                model: running.label.model.clone(),
+                started_at: running.started_at,
+                elapsed_ms: running.started.elapsed().as_millis() as u64,
+                tokens: running.tokens.load(Ordering::Relaxed),
+            })
+            .collect();
+        generations.sort_by_key(|generation| generation.started_at);
+        generations
+    }
+
+    /// Asks the running generation `id` to stop. `false` if there is none.
+    pub fn cancel(&self, id: &str) -> bool {
+        let running = self.inner.running.lock().expect("running generations poisoned");
+        match running.get(id) {
+            Some(running) => {
+                running.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Waits for a generation slot. `on_queued` is called with the 1-based queue
+    /// position each time it changes; it is never called if a slot is free.
+    async fn acquire(
+        &self,
+        on_queued: impl Fn(usize),
+    ) -> Result<OwnedSemaphorePermit, AppError> {
+        if let Ok(permit) = self.inner.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let ticket = self.inner.next_ticket.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut queue = self.inner.queue.lock().expect("generation queue poisoned");
+            if queue.len() >= self.inner.max_queued {
+                return Err(AppError::GenerationQueueFull { max_queued: self.inner.max_queued });
+            }
+            queue.push_back(ticket);
+        }
+        let _slot = QueueSlot { inner: &self.inner, ticket };
+
+        let mut changed = self.inner.queue_changed.subscribe();
+        let acquire = self.inner.permits.clone().acquire_owned();
+        tokio::pin!(acquire);
+
+        let mut last_position = 0;
+        loop {
+            let position = self.position(ticket);
+            if position != last_position {
+                on_queued(position);
+                last_position = position;
+            }
+            tokio::select! {
+                permit = &mut acquire => {
+                    return permit.map_err(|_| AppError::Unexpected(
+                        "Generation scheduler closed".to_string(),
+                    ));
+                }
+                _ = changed.changed() => {}
+            }
+        }
+    }
+
+    fn position(&self, ticket: u64) -> usize {
+        let queue = self.inner.queue.lock().expect("generation queue poisoned");
+        queue.iter().position(|t| *t == ticket).map_or(1, |i| i + 1)
+    }
+}
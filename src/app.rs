@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::FromRef;
+use axum::Router;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::agent::{AgentService, OllamaAgentService};
+use crate::config::{ConfigStore, TenantsConfig};
+use crate::db::access_log_repository::AccessLogRepository;
+use crate::db::artifact_repository::ArtifactRepository;
+use crate::db::document_repository::DocumentRepository;
+use crate::db::batch_repository::BatchRepository;
+use crate::db::collection_repository::CollectionRepository;
+use crate::db::conversation_repository::ConversationRepository;
+use crate::db::conversation_webhook_repository::ConversationWebhookRepository;
+use crate::db::email_message_repository::EmailMessageRepository;
+use crate::db::eval_repository::EvalRepository;
+use crate::db::maintenance_repository::MaintenanceRepository;
+use crate::db::message_repository::MessageRepository;
+use crate::db::notification_repository::NotificationRepository;
+use crate::db::profile_repository::ProfileRepository;
+use crate::db::schedule_repository::ScheduleRepository;
+use crate::db::slack_thread_repository::SlackThreadRepository;
+use crate::db::stream_repository::StreamRepository;
+use crate::db::webhook_tool_repository::WebhookToolRepository;
+use crate::db::workspace_read_repository::WorkspaceReadRepository;
+use crate::errors::AppError;
+use crate::events;
+use crate::routes;
+use crate::routes::mcp_routes::McpSessions;
+use crate::routes::ws_routes::LiveStreams;
+use crate::service::chat_service::ChatService;
+use crate::service::email_gateway::EmailGateway;
+use crate::service::slack::SlackBot;
+use crate::service::workspace::Workspace;
+use crate::storage::{self, BlobStore};
+use crate::tools::web_search::SearchProvider;
+use crate::tools::workspace::WorkspaceAccess;
+use crate::tools::ToolRegistry;
+
+/// Everything the handlers need. Handlers extract the individual parts
+/// (`State<ChatService>`, `State<ConfigStore>`) via [`FromRef`].
+#[derive(Clone)]
+pub struct AppState {
+    pub chat_service: ChatService,
+    pub config: ConfigStore,
+    /// Read by the maintenance middleware on every write.
+    pub maintenance: MaintenanceRepository,
+    /// Stored data exports.
+    pub blobs: Arc<dyn BlobStore>,
+    /// Connected MCP clients.
+    pub mcp_sessions: McpSessions,
+    /// Turns streaming right now, for sockets watching their conversation.
+    pub live_streams: LiveStreams,
+    /// Answers Slack threads, when `slack` is configured.
+    pub slack: SlackBot,
+    /// Files the agent read from the workspace.
+    pub workspace_reads: WorkspaceReadRepository,
+}
+
+impl AppState {
+    /// Wires the production services: repositories over `pool` and the Ollama
+    /// agent with whichever tools the environment enables. Starts warming up
+    /// the current model, so it must run inside a Tokio runtime.
+    pub fn new(config: ConfigStore, pool: PgPool) -> Self {
+        let mut tools = ToolRegistry::new();
+        if let Some(provider) = SearchProvider::from_env() {
+            info!("Web search enabled via {}", provider.name());
+            tools = tools.with_web_search(provider);
+        }
+        let startup = config.get();
+        if let (Some(dir), true) = (&startup.workspace_dir, startup.workspace_tools) {
+            info!("Workspace tools enabled over {}", dir.display());
+            tools = tools.with_workspace(WorkspaceAccess::new(
+                Workspace::new(dir),
+                startup.workspace_max_file_bytes,
+                startup.workspace_extensions.clone(),
+                WorkspaceReadRepository::new(pool.clone()),
+            ));
+        }
+        let agent = Arc::new(OllamaAgentService::new(config.clone(), tools));
+        let state = Self::with_agent(config, pool, agent);
+        state.chat_service.warm_up(None);
+        state
+    }
+
+    /// Same wiring as [`AppState::new`] with a caller-supplied agent. Starts
+    /// the runner of scheduled prompts and the email gateway, so it too must
+    /// run inside a Tokio runtime.
+    ///
+    /// # Panics
+    /// If `blob_store` is configured with settings the S3 client rejects.
+    pub fn with_agent(config: ConfigStore, pool: PgPool, agent: Arc<dyn AgentService>) -> Self {
+        let blobs = storage::from_config(&config.get().blob_store)
+            .expect("Invalid blob_store configuration");
+        let chat_service = ChatService::new(
+            ConversationRepository::new(pool.clone()),
+            MessageRepository::new(pool.clone()),
+            ProfileRepository::new(pool.clone()),
+            EvalRepository::new(pool.clone()),
+            BatchRepository::new(pool.clone()),
+            WebhookToolRepository::new(pool.clone()),
+            ScheduleRepository::new(pool.clone()),
+            NotificationRepository::new(pool.clone()),
+            ConversationWebhookRepository::new(pool.clone()),
+            AccessLogRepository::new(pool.clone()),
+            ArtifactRepository::new(pool.clone()),
+            DocumentRepository::new(pool.clone()),
+            CollectionRepository::new(pool.clone()),
+            agent,
+            config.clone(),
+        );
+        let bus = events::from_config(&config.get().event_bus, pool.clone());
+        let streams = StreamRepository::new(pool.clone());
+        let slack = SlackBot::new(chat_service.clone(), SlackThreadRepository::new(pool.clone()));
+        let emails = EmailMessageRepository::new(pool.clone());
+        let workspace_reads = WorkspaceReadRepository::new(pool.clone());
+        let maintenance = MaintenanceRepository::new(pool);
+        let live_streams = LiveStreams::new(bus, streams);
+        tokio::spawn(run_schedules(chat_service.clone(), live_streams.clone()));
+        tokio::spawn(EmailGateway::new(chat_service.clone(), emails).run());
+        Self {
+            chat_service,
+            config,
+            maintenance,
+            blobs,
+            mcp_sessions: McpSessions::default(),
+            live_streams,
+            slack,
+            workspace_reads,
+        }
+    }
+}
+
+/// Runs the scheduled prompts that are due every `schedule_poll_secs`, and
+/// shows each reply to whoever watches its conversation.
+async fn run_schedules(svc: ChatService, live: LiveStreams) {
+    loop {
+        let poll_secs = svc.config().get().schedule_poll_secs.max(1);
+        tokio::time::sleep(Duration::from_secs(poll_secs)).await;
+        for (message, stats) in svc.run_due_schedules().await {
+            live.announce(&message, stats);
+        }
+    }
+}
+
+impl FromRef<AppState> for ChatService {
+    fn from_ref(state: &AppState) -> Self {
+        state.chat_service.clone()
+    }
+}
+
+impl FromRef<AppState> for ConfigStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for MaintenanceRepository {
+    fn from_ref(state: &AppState) -> Self {
+        state.maintenance.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn BlobStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.blobs.clone()
+    }
+}
+
+impl FromRef<AppState> for McpSessions {
+    fn from_ref(state: &AppState) -> Self {
+        state.mcp_sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for LiveStreams {
+    fn from_ref(state: &AppState) -> Self {
+        state.live_streams.clone()
+    }
+}
+
+impl FromRef<AppState> for SlackBot {
+    fn from_ref(state: &AppState) -> Self {
+        state.slack.clone()
+    }
+}
+
+impl FromRef<AppState> for WorkspaceReadRepository {
+    fn from_ref(state: &AppState) -> Self {
+        state.workspace_reads.clone()
+    }
+}
+
+/// Builds the application router with production wiring.
+pub fn build_router(config: ConfigStore, pool: PgPool) -> Router {
+    routes::router(AppState::new(config, pool))
+}
+
+/// Builds the application of every tenant in `tenants`, with production
+/// wiring over its own schema, behind a router picking the tenant of each
+/// request.
+pub async fn build_tenant_router(
+    config: ConfigStore,
+    tenants: &TenantsConfig,
+) -> Result<Router, AppError> {
+    build_tenant_router_with(config, tenants, AppState::new).await
+}
+
+/// Same as [`build_tenant_router`], with `state` wiring a tenant's services
+/// over the pool of its schema.
+pub async fn build_tenant_router_with(
+    config: ConfigStore,
+    tenants: &TenantsConfig,
+    state: impl Fn(ConfigStore, PgPool) -> AppState,
+) -> Result<Router, AppError> {
+    if !tenants.trust_header && tenants.base_domain.is_none() {
+        return Err(AppError::InvalidConfig {
+            message: "tenants need a base_domain, or trust_header behind a proxy setting \
+                      the header"
+                .to_string(),
+        });
+    }
+    let database_url = config.get().database_url.clone();
+    let mut routers = HashMap::new();
+    for name in &tenants.names {
+        let pool = connect_database(&database_url, Some(&tenants.schema(name))).await?;
+        routers.insert(name.clone(), routes::router(state(config.clone(), pool)));
+        info!("Serving tenant {name}");
+    }
+    Ok(routes::tenants::router(tenants, routers))
+}
+
+/// The backend running on a background task, for hosts that embed it (such
+/// as a desktop shell) instead of running the binary.
+pub struct EmbeddedServer {
+    /// Address actually bound; differs from the requested one when it used port 0.
+    pub addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl EmbeddedServer {
+    /// Base URL for the frontend's API calls.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Stops serving. Open WebSocket turns are dropped.
+    pub fn shutdown(self) {
+        self.task.abort();
+    }
+}
+
+/// Binds `addr` and serves `router` in the background. Pass port 0 to let
+/// the OS pick a free port, then read it back from [`EmbeddedServer::addr`].
+pub async fn spawn_server(router: Router, addr: &str) -> Result<EmbeddedServer, AppError> {
+    let bind_failed = |e: std::io::Error| AppError::Unexpected(format!("Failed to bind {addr}: {e}"));
+    let listener = TcpListener::bind(addr).await.map_err(bind_failed)?;
+    let addr = listener.local_addr().map_err(bind_failed)?;
+    let task = tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("Embedded server stopped: {e}");
+        }
+    });
+    info!("Serving on http://{addr}/");
+    Ok(EmbeddedServer { addr, task })
+}
+
+/// Connects to Postgres and applies pending migrations. With `schema`, the
+/// tables live in that schema, which is created if missing; `public` stays
+/// on the search path for the extensions installed there, like pgvector.
+pub async fn connect_database(
+    database_url: &str,
+    schema: Option<&str>,
+) -> Result<PgPool, AppError> {
+    let mut options = PgPoolOptions::new().max_connections(10);
+    if let Some(schema) = schema {
+        if !is_schema_name(schema) {
+            return Err(AppError::InvalidConfig {
+                message: format!(
+                    "'{schema}' is not a valid schema: use lowercase letters, digits and _"
+                ),
+            });
+        }
+        let search_path = format!("SET search_path TO {schema}, public");
+        options = options.after_connect(move |conn, _| {
+            let search_path = search_path.clone();
+            Box::pin(async move { conn.execute(search_path.as_str()).await.map(|_| ()) })
+        });
+    }
+    let pool = options.connect(database_url).await.map_err(AppError::DatabaseConnectionFailed)?;
+
+    if let Some(schema) = schema {
+        sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS {schema}"))
+            .execute(&pool)
+            .await
+            .map_err(|e| AppError::db_query(format!("Failed to create schema {schema}"), e))?;
+    }
+
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .map_err(|e| AppError::Unexpected(format!("Failed to run database migrations: {e}")))?;
+
+    info!("Database connection established and migrations applied");
+    Ok(pool)
+}
+
+/// Whether `name` can be used as a schema as is, unquoted: it goes straight
+/// into SQL.
+fn is_schema_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && name.len() <= 63
+}
@@ -0,0 +1,604 @@
+//! Terminal client for the chat server.
+//!
+//! Speaks the same REST API and WebSocket protocol as the web frontend.
+//! Connection settings come from `--server`/`--api-key` (or `CHAT_SERVER_URL`/
+//! `CHAT_API_KEY`), falling back to a named profile in the CLI config file:
+//!
+//! ```toml
+//! default_profile = "local"
+//!
+//! [profiles.local]
+//! server_url = "http://localhost:3000"
+//!
+//! [profiles.staging]
+//! server_url = "https://chat.example.com"
+//! api_key = "..."
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Method, RequestBuilder};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use uuid::Uuid;
+
+use rust_ai_experiments::models::{
+    Artifact, ChunkMode, CompletionStats, Conversation, CreateEvalRequest, DiffOp, EvalReport,
+    EvalRun, EvalStatus, FinishReason, Message, ModelsResponse, PullProgress, ResponseFormat,
+    Source, ToolCallStatus, WsChatRequest, WsEvent, WsFrame,
+};
+
+const DEFAULT_SERVER_URL: &str = "http://localhost:3000";
+const EVAL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Parser)]
+#[command(name = "chat-cli", about = "Chat with the server and manage conversations")]
+struct Cli {
+    /// Profile from the config file to use.
+    #[arg(long, global = true, env = "CHAT_PROFILE")]
+    profile: Option<String>,
+    /// Server base URL; overrides the profile.
+    #[arg(long, global = true, env = "CHAT_SERVER_URL")]
+    server: Option<String>,
+    /// API key sent as a bearer token; overrides the profile.
+    #[arg(long, global = true, env = "CHAT_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+    /// CLI config file (default: `<config dir>/rust-ai-chat/cli.toml`).
+    #[arg(long, global = true, env = "CHAT_CLI_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Interactive streaming chat. `/new` starts a new conversation, `/quit` exits.
+    Chat {
+        /// Continue an existing conversation.
+        #[arg(long)]
+        conversation: Option<Uuid>,
+        /// Start new conversations in ephemeral mode (kept in server memory only).
+        #[arg(long)]
+        ephemeral: bool,
+    },
+    /// Manage conversations.
+    #[command(subcommand)]
+    Conversations(ConversationsCommand),
+    /// Inspect models.
+    #[command(subcommand)]
+    Models(ModelsCommand),
+    /// Replay stored conversations against another model and compare.
+    #[command(subcommand)]
+    Evals(EvalsCommand),
+}
+
+#[derive(Subcommand)]
+enum ConversationsCommand {
+    /// List conversations, most recently updated first.
+    List,
+    /// Delete a conversation and all of its messages.
+    Delete { id: Uuid },
+    /// Print a conversation (or write it to `--output`).
+    Export {
+        id: Uuid,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelsCommand {
+    /// List models installed in Ollama.
+    List,
+    /// Download a model into Ollama (the server's configured model by default).
+    Pull { model: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum EvalsCommand {
+    /// Replay the user turns of conversations against `--model`, wait for the
+    /// run to finish and print its report.
+    Run {
+        #[arg(long)]
+        model: String,
+        /// Conversation to replay; repeat for several.
+        #[arg(long = "conversation", required_unless_present = "all")]
+        conversations: Vec<Uuid>,
+        /// Replay every stored conversation.
+        #[arg(long, conflicts_with = "conversations")]
+        all: bool,
+    },
+    /// List eval runs, newest first.
+    List,
+    /// Print a run's replies with word diffs against the originals.
+    Report {
+        id: Uuid,
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Markdown,
+    Json,
+    /// Rendered by the server; needs `--output`.
+    Pdf,
+}
+
+// ── Profiles ─────────────────────────────────────────────────────────────────
+
+#[derive(Default, Deserialize)]
+struct CliConfig {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+struct Profile {
+    server_url: Option<String>,
+    api_key: Option<String>,
+}
+
+impl CliConfig {
+    fn load(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let explicit = path.is_some();
+        let Some(path) = path.or_else(|| dirs::config_dir().map(|d| d.join("rust-ai-chat/cli.toml")))
+        else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => toml::from_str(&raw).with_context(|| format!("Invalid {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn profile(&self, name: Option<&str>) -> anyhow::Result<Profile> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .with_context(|| format!("No profile named '{name}'")),
+            None => Ok(Profile::default()),
+        }
+    }
+}
+
+// ── Server client ────────────────────────────────────────────────────────────
+
+struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Client {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let req = self.http.request(method, format!("{}{path}", self.base_url));
+        match &self.api_key {
+            Some(key) => req.bearer_auth(key),
+            None => req,
+        }
+    }
+
+    async fn send(&self, req: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let resp = req.send().await.context("Request failed")?;
+        if resp.status().is_success() {
+            return Ok(resp);
+        }
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        bail!("Server returned {status}: {message}")
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let resp = self.send(self.request(Method::GET, path)).await?;
+        resp.json().await.context("Unexpected response body")
+    }
+
+    fn ws_url(&self) -> String {
+        let url = self
+            .base_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        format!("{url}/ws/chat")
+    }
+}
+
+// ── Commands ─────────────────────────────────────────────────────────────────
+
+async fn list_conversations(client: &Client) -> anyhow::Result<()> {
+    let conversations: Vec<Conversation> = client.get("/api/conversations").await?;
+    if conversations.is_empty() {
+        println!("No conversations yet.");
+    }
+    for c in conversations {
+        let icon = c.icon.map(|icon| format!("{icon} ")).unwrap_or_default();
+        println!("{}  {}  {icon}{}", c.id, c.updated_at.format("%Y-%m-%d %H:%M"), c.title);
+    }
+    Ok(())
+}
+
+async fn delete_conversation(client: &Client, id: Uuid) -> anyhow::Result<()> {
+    client
+        .send(client.request(Method::DELETE, &format!("/api/conversations/{id}")))
+        .await?;
+    println!("Deleted {id}");
+    Ok(())
+}
+
+async fn export_conversation(
+    client: &Client,
+    id: Uuid,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    if let ExportFormat::Pdf = format {
+        return export_pdf(client, id, output).await;
+    }
+    let messages: Vec<Message> = client.get(&format!("/api/conversations/{id}/messages")).await?;
+    let rendered = match format {
+        ExportFormat::Pdf => unreachable!("handled above"),
+        ExportFormat::Json => serde_json::to_string_pretty(&messages)?,
+        ExportFormat::Markdown => {
+            let mut out = format!("# Conversation {id}\n");
+            for m in &messages {
+                out.push_str(&format!("\n## {}\n\n{}\n", m.role, m.content));
+                for (i, s) in m.sources.iter().enumerate() {
+                    out.push_str(&format!("\n[{}] {} — {}", i + 1, s.title, s.url));
+                }
+                if !m.sources.is_empty() {
+                    out.push('\n');
+                }
+            }
+            out
+        }
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(&path, rendered)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Exported {} messages to {}", messages.len(), path.display());
+        }
+        None => println!("{rendered}"),
+    }
+    Ok(())
+}
+
+async fn export_pdf(client: &Client, id: Uuid, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let Some(path) = output else {
+        bail!("PDF exports are binary; choose a file with --output");
+    };
+    let path_and_query = format!("/api/conversations/{id}/export?format=pdf");
+    let resp = client.send(client.request(Method::GET, &path_and_query)).await?;
+    let pdf = resp.bytes().await.context("Unexpected response body")?;
+    std::fs::write(&path, &pdf).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Exported conversation {id} to {}", path.display());
+    Ok(())
+}
+
+async fn list_models(client: &Client) -> anyhow::Result<()> {
+    let models: ModelsResponse = client.get("/api/models").await?;
+    for m in models.models {
+        // Ollama reports tagged names ("llama3.2:latest"); the config may omit the tag.
+        let current = m.name == models.current || m.name == format!("{}:latest", models.current);
+        let size = m.size.map(|b| format!("{:.1} GB", b as f64 / 1e9)).unwrap_or_default();
+        println!("{} {:<40} {size}", if current { "*" } else { " " }, m.name);
+    }
+    Ok(())
+}
+
+async fn pull_model(client: &Client, model: Option<String>) -> anyhow::Result<()> {
+    let req = client
+        .request(Method::POST, "/api/models/pull")
+        .json(&serde_json::json!({ "model": model }));
+    let mut resp = client.send(req).await?;
+
+    // Server-sent events: blocks of `event:`/`data:` lines separated by a blank line.
+    let mut buffer = String::new();
+    while let Some(chunk) = resp.chunk().await.context("Pull interrupted")? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = buffer.find("\n\n") {
+            let block: String = buffer.drain(..end + 2).collect();
+            let field = |name: &str| {
+                let value = block.lines().find_map(|l| l.strip_prefix(name));
+                value.map(str::trim).unwrap_or_default().to_string()
+            };
+            let data: serde_json::Value = serde_json::from_str(&field("data:")).unwrap_or_default();
+            match field("event:").as_str() {
+                "progress" => {
+                    let progress: PullProgress = serde_json::from_value(data)?;
+                    match (progress.completed, progress.total) {
+                        (Some(done), Some(total)) if total > 0 => eprint!(
+                            "\r{} {:>5.1}% of {:.1} GB   ",
+                            progress.status,
+                            done as f64 * 100.0 / total as f64,
+                            total as f64 / 1e9
+                        ),
+                        _ => eprint!("\r{:<60}", progress.status),
+                    }
+                }
+                "done" => {
+                    eprintln!();
+                    println!("Pulled {}", data["model"].as_str().unwrap_or_default());
+                    return Ok(());
+                }
+                "error" => {
+                    eprintln!();
+                    bail!("{}", data["error"].as_str().unwrap_or("Pull failed"));
+                }
+                _ => {}
+            }
+        }
+    }
+    bail!("Server closed the connection before the pull finished")
+}
+
+async fn run_eval(
+    client: &Client,
+    model: String,
+    mut conversation_ids: Vec<Uuid>,
+    all: bool,
+) -> anyhow::Result<()> {
+    if all {
+        let conversations: Vec<Conversation> = client.get("/api/conversations").await?;
+        conversation_ids = conversations.into_iter().map(|c| c.id).collect();
+    }
+    let req = client
+        .request(Method::POST, "/api/evals")
+        .json(&CreateEvalRequest { model, conversation_ids });
+    let run: EvalRun = client.send(req).await?.json().await.context("Unexpected response body")?;
+    eprintln!("Started eval run {} against {}", run.id, run.model);
+
+    loop {
+        let report: EvalReport = client.get(&format!("/api/evals/{}", run.id)).await?;
+        if report.run.status != EvalStatus::Running {
+            eprintln!();
+            print_eval_report(&report);
+            return Ok(());
+        }
+        eprint!("\r{} turns replayed", report.summary.turns);
+        tokio::time::sleep(EVAL_POLL_INTERVAL).await;
+    }
+}
+
+async fn list_evals(client: &Client) -> anyhow::Result<()> {
+    let runs: Vec<EvalRun> = client.get("/api/evals").await?;
+    if runs.is_empty() {
+        println!("No eval runs yet.");
+    }
+    for r in runs {
+        let created = r.created_at.format("%Y-%m-%d %H:%M");
+        println!("{}  {created}  {:<9}  {}", r.id, r.status.as_str(), r.model);
+    }
+    Ok(())
+}
+
+async fn show_eval_report(client: &Client, id: Uuid, json: bool) -> anyhow::Result<()> {
+    let report: EvalReport = client.get(&format!("/api/evals/{id}")).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_eval_report(&report);
+    }
+    Ok(())
+}
+
+/// Plain-text report: each turn's diff in wdiff notation (`[-removed-]`,
+/// `{+added+}`), then the totals.
+fn print_eval_report(report: &EvalReport) {
+    for turn in &report.turns {
+        let r = &turn.result;
+        println!("── {} turn {} ──", r.conversation_id, r.turn);
+        println!("> {}\n", r.user_message.trim());
+        if let Some(error) = &r.error {
+            println!("(failed: {error})\n");
+            continue;
+        }
+        let rendered: String = turn
+            .diff
+            .iter()
+            .map(|span| match span.op {
+                DiffOp::Equal => span.text.clone(),
+                DiffOp::Delete => format!("[-{}-]", span.text),
+                DiffOp::Insert => format!("{{+{}+}}", span.text),
+            })
+            .collect();
+        println!("{}\n", rendered.trim_end());
+    }
+    let s = &report.summary;
+    println!(
+        "{}: {} turns, {} changed, {} failed; {} → {} words",
+        report.run.model, s.turns, s.changed, s.errors, s.original_words, s.new_words
+    );
+    if let Some(error) = &report.run.error {
+        println!("Run failed: {error}");
+    }
+}
+
+async fn chat(
+    client: &Client,
+    mut conversation_id: Option<Uuid>,
+    ephemeral: bool,
+) -> anyhow::Result<()> {
+    let mut request = client.ws_url().into_client_request()?;
+    if let Some(key) = &client.api_key {
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, format!("Bearer {key}").parse()?);
+    }
+    let (mut socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .with_context(|| format!("Failed to connect to {}", client.ws_url()))?;
+
+    println!("Connected. Type a message; /new starts a new conversation, /quit exits.");
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("\n> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else { break };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "/quit" | "/exit" => break,
+            "/new" => {
+                conversation_id = None;
+                println!("Started a new conversation.");
+                continue;
+            }
+            _ => {}
+        }
+
+        let req = WsChatRequest {
+            conversation_id,
+            message: line.to_string(),
+            ephemeral,
+            retry: false,
+            // One turn at a time, so the server-assigned id is enough.
+            stream_id: None,
+            response_format: ResponseFormat::Text,
+            verbosity: None,
+            stateless: None,
+            quoted_message_id: None,
+            chunking: ChunkMode::Token,
+            replace: false,
+        };
+        socket
+            .send(WsMessage::Text(serde_json::to_string(&req)?.into()))
+            .await?;
+
+        let mut sources: Vec<Source> = Vec::new();
+        let mut artifacts: Vec<Artifact> = Vec::new();
+        while let Some(frame) = socket.next().await {
+            let WsMessage::Text(text) = frame? else { continue };
+            match serde_json::from_str::<WsFrame>(&text)?.event {
+                WsEvent::StreamStart { conversation_id: id, .. } => conversation_id = Some(id),
+                WsEvent::Queued { position } => {
+                    eprint!("\r(waiting for the model — position {position} in queue)");
+                }
+                WsEvent::ToolCall { name, status, .. } => match status {
+                    ToolCallStatus::Running => eprintln!("(calling {name})"),
+                    ToolCallStatus::Done => {}
+                    ToolCallStatus::Failed => eprintln!("({name} failed)"),
+                    ToolCallStatus::TimedOut => eprintln!("({name} timed out)"),
+                },
+                WsEvent::StreamChunk { content } => {
+                    print!("{content}");
+                    std::io::stdout().flush()?;
+                }
+                WsEvent::StreamSources { sources: s } => sources = s,
+                WsEvent::Artifact { artifact } => artifacts.push(artifact),
+                WsEvent::StreamEnd { stats, .. } => {
+                    println!();
+                    for (i, s) in sources.iter().enumerate() {
+                        println!("  [{}] {} — {}", i + 1, s.title, s.url);
+                    }
+                    for artifact in &artifacts {
+                        eprintln!(
+                            "(artifact {}: /api/artifacts/{}/download)",
+                            artifact.file_name(),
+                            artifact.id
+                        );
+                    }
+                    eprintln!("{}", format_stats(&stats));
+                    break;
+                }
+                WsEvent::ModelMissing { model } => {
+                    eprintln!("\nModel '{model}' is not installed. Run `models pull` and try again.");
+                    break;
+                }
+                WsEvent::ModerationBlocked { reason } => {
+                    eprintln!("\nMessage blocked: {reason}");
+                    break;
+                }
+                WsEvent::Busy { .. } => {
+                    eprintln!("\nerror: a reply is already streaming in this conversation");
+                    break;
+                }
+                WsEvent::Error { message, .. } => {
+                    eprintln!("\nerror: {message}");
+                    break;
+                }
+                // Arrives after the turn ended; not offered in the terminal.
+                WsEvent::FollowUpSuggestions { .. } => {}
+                // Only sent in answer to a `hello`, which the CLI never sends.
+                WsEvent::Hello { .. } => {}
+            }
+        }
+    }
+
+    let _ = socket.close(None).await;
+    Ok(())
+}
+
+/// One-line summary such as `(llama3.2 · 12 → 48 tokens · 1.9s)`.
+fn format_stats(stats: &CompletionStats) -> String {
+    let mut parts = vec![stats.model.clone()];
+    if let (Some(prompt), Some(completion)) = (stats.prompt_tokens, stats.completion_tokens) {
+        parts.push(format!("{prompt} → {completion} tokens"));
+    }
+    parts.push(format!("{:.1}s", stats.duration_ms as f64 / 1000.0));
+    parts.extend(stats.cost.map(|cost| format!("${cost:.4}")));
+    match stats.finish_reason {
+        FinishReason::Stop => {}
+        FinishReason::Length => parts.push("cut off at the token limit".to_string()),
+        FinishReason::Cancelled => parts.push("cancelled".to_string()),
+        FinishReason::Error => parts.push("interrupted by an error".to_string()),
+    }
+    format!("({})", parts.join(" · "))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let profile = CliConfig::load(cli.config)?.profile(cli.profile.as_deref())?;
+
+    let client = Client {
+        http: reqwest::Client::new(),
+        base_url: cli
+            .server
+            .or(profile.server_url)
+            .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string())
+            .trim_end_matches('/')
+            .to_string(),
+        api_key: cli.api_key.or(profile.api_key),
+    };
+
+    match cli.command {
+        Command::Chat { conversation, ephemeral } => chat(&client, conversation, ephemeral).await,
+        Command::Conversations(ConversationsCommand::List) => list_conversations(&client).await,
+        Command::Conversations(ConversationsCommand::Delete { id }) => {
+            delete_conversation(&client, id).await
+        }
+        Command::Conversations(ConversationsCommand::Export { id, format, output }) => {
+            export_conversation(&client, id, format, output).await
+        }
+        Command::Models(ModelsCommand::List) => list_models(&client).await,
+        Command::Models(ModelsCommand::Pull { model }) => pull_model(&client, model).await,
+        Command::Evals(EvalsCommand::Run { model, conversations, all }) => {
+            run_eval(&client, model, conversations, all).await
+        }
+        Command::Evals(EvalsCommand::List) => list_evals(&client).await,
+        Command::Evals(EvalsCommand::Report { id, json }) => {
+            show_eval_report(&client, id, json).await
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::AccessLogEntry;
+
+const COLUMNS: &str = "id, conversation_id, user_id, action, user_agent, created_at";
+
+#[derive(Clone)]
+pub struct AccessLogRepository {
+    pool: PgPool,
+}
+
+impl AccessLogRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(conversation_id = %entry.conversation_id))]
+    pub async fn save(&self, entry: &AccessLogEntry) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO access_log (id, conversation_id, user_id, action, user_agent, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(entry.id)
+        .bind(entry.conversation_id)
+        .bind(&entry.user_id)
+        .bind(entry.action.as_str())
+        .bind(&entry.user_agent)
+        .bind(entry.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to log access to {}: {e}", entry.conversation_id);
+            AppError::db_query("Failed to save access log entry", e)
+        })?;
+        Ok(())
+    }
+
+    /// The latest `limit` accesses to `conversation_id`, newest first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_recent(
+        &self,
+        conversation_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<AccessLogEntry>, AppError> {
+        sqlx::query_as::<_, AccessLogEntry>(&format!(
+            "SELECT {COLUMNS} FROM access_log
+             WHERE conversation_id = $1
+             ORDER BY created_at DESC
+             LIMIT $2"
+        ))
+        .bind(conversation_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list accesses to {conversation_id}: {e}");
+            AppError::db_query("Failed to list access log", e)
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::Artifact;
+
+const COLUMNS: &str = "a.id, a.message_id, a.kind, a.language, a.title, a.content, a.created_at";
+
+#[derive(Clone)]
+pub struct ArtifactRepository {
+    pool: PgPool,
+}
+
+impl ArtifactRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Saves the artifacts of one version of a message, numbered in the order
+    /// they appear in it.
+    #[instrument(level = "debug", skip(self, artifacts), fields(count = artifacts.len()))]
+    pub async fn save_all(
+        &self,
+        message_id: Uuid,
+        version: i32,
+        artifacts: &[Artifact],
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for artifacts of message {message_id}: {e}");
+            AppError::db_query("Failed to save artifacts", e)
+        })?;
+        for (position, artifact) in artifacts.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO artifacts
+                    (id, message_id, version, position, kind, language, title, content, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .bind(artifact.id)
+            .bind(message_id)
+            .bind(version)
+            .bind(position as i32 + 1)
+            .bind(artifact.kind.as_str())
+            .bind(&artifact.language)
+            .bind(&artifact.title)
+            .bind(&artifact.content)
+            .bind(artifact.created_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to save artifact of message {message_id}: {e}");
+                AppError::db_query("Failed to save artifact", e)
+            })?;
+        }
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit artifacts of message {message_id}: {e}");
+            AppError::db_query("Failed to save artifacts", e)
+        })
+    }
+
+    /// The artifacts of a conversation's replies, oldest reply first. Only
+    /// those of each reply's active version are listed.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_conversation_id(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<Artifact>, AppError> {
+        sqlx::query_as::<_, Artifact>(&format!(
+            "SELECT {COLUMNS} FROM artifacts a
+             JOIN messages m ON m.id = a.message_id AND m.active_version = a.version
+             WHERE m.conversation_id = $1
+             ORDER BY m.created_at, a.position"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list artifacts of conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to list artifacts", e)
+        })
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<Artifact>, AppError> {
+        sqlx::query_as::<_, Artifact>(&format!("SELECT {COLUMNS} FROM artifacts a WHERE a.id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch artifact {id}: {e}");
+                AppError::db_query("Failed to fetch artifact", e)
+            })
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{BatchItem, BatchJob, BatchStatus};
+
+const JOB_COLUMNS: &str = "id, status, created_at, completed_at";
+const ITEM_COLUMNS: &str = "position, prompt, model, status, content, error, started_at, completed_at";
+
+#[derive(Clone)]
+pub struct BatchRepository {
+    pool: PgPool,
+}
+
+impl BatchRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Inserts the job and all of its items in a single transaction.
+    #[instrument(level = "debug", skip_all, fields(job_id = %job.id, items = job.items.len()))]
+    pub async fn save_job(&self, job: &BatchJob) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for batch job {}: {e}", job.id);
+            AppError::db_query("Failed to save batch job", e)
+        })?;
+
+        sqlx::query(
+            "INSERT INTO batch_jobs (id, status, created_at, completed_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(job.id)
+        .bind(job.status.as_str())
+        .bind(job.created_at)
+        .bind(job.completed_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to save batch job {}: {e}", job.id);
+            AppError::db_query("Failed to save batch job", e)
+        })?;
+
+        for item in &job.items {
+            sqlx::query(
+                "INSERT INTO batch_items (job_id, position, prompt, model, status)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(job.id)
+            .bind(item.position)
+            .bind(&item.prompt)
+            .bind(&item.model)
+            .bind(item.status.as_str())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to save item {} of batch job {}: {e}", item.position, job.id);
+                AppError::db_query("Failed to save batch job", e)
+            })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit batch job {}: {e}", job.id);
+            AppError::db_query("Failed to save batch job", e)
+        })
+    }
+
+    /// Marks a job as finished with `status`.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn finish_job(&self, id: Uuid, status: BatchStatus) -> Result<(), AppError> {
+        sqlx::query("UPDATE batch_jobs SET status = $1, completed_at = $2 WHERE id = $3")
+            .bind(status.as_str())
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to finish batch job {id}: {e}");
+                AppError::db_query("Failed to update batch job", e)
+            })?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn start_item(&self, job_id: Uuid, position: i32) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE batch_items SET status = $1, started_at = $2 WHERE job_id = $3 AND position = $4",
+        )
+        .bind(BatchStatus::Running.as_str())
+        .bind(Utc::now())
+        .bind(job_id)
+        .bind(position)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to start item {position} of batch job {job_id}: {e}");
+            AppError::db_query("Failed to update batch item", e)
+        })?;
+        Ok(())
+    }
+
+    /// Records an item's reply, or why it failed when `error` is set.
+    #[instrument(level = "debug", skip(self, content, error))]
+    pub async fn finish_item(
+        &self,
+        job_id: Uuid,
+        position: i32,
+        content: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let status = if error.is_some() { BatchStatus::Failed } else { BatchStatus::Completed };
+        sqlx::query(
+            "UPDATE batch_items SET status = $1, content = $2, error = $3, completed_at = $4
+             WHERE job_id = $5 AND position = $6",
+        )
+        .bind(status.as_str())
+        .bind(content)
+        .bind(error)
+        .bind(Utc::now())
+        .bind(job_id)
+        .bind(position)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to finish item {position} of batch job {job_id}: {e}");
+            AppError::db_query("Failed to update batch item", e)
+        })?;
+        Ok(())
+    }
+
+    /// The job with its items in request order.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_job(&self, id: Uuid) -> Result<Option<BatchJob>, AppError> {
+        let job = sqlx::query_as::<_, BatchJob>(&format!(
+            "SELECT {JOB_COLUMNS} FROM batch_jobs WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find batch job {id}: {e}");
+            AppError::db_query(format!("Failed to find batch job {id}"), e)
+        })?;
+        let Some(mut job) = job else { return Ok(None) };
+
+        job.items = sqlx::query_as::<_, BatchItem>(&format!(
+            "SELECT {ITEM_COLUMNS} FROM batch_items WHERE job_id = $1 ORDER BY position"
+        ))
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch items of batch job {id}: {e}");
+            AppError::db_query(format!("Failed to fetch items of batch job {id}"), e)
+        })?;
+        Ok(Some(job))
+    }
+}
@@ -0,0 +1,196 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{Chunker, Collection};
+
+/// Collections with how many documents and chunks they hold.
+const SELECT: &str = "SELECT c.name, c.description, c.embedding_model, c.chunker,
+        c.chunk_max_lines, c.chunk_overlap, c.global, c.created_at,
+        (SELECT COUNT(*) FROM documents d WHERE d.collection = c.name) AS documents,
+        (SELECT COUNT(*) FROM document_chunks k
+         JOIN documents d ON d.id = k.document_id
+         WHERE d.collection = c.name) AS chunks
+     FROM collections c";
+
+#[derive(Clone)]
+pub struct CollectionRepository {
+    pool: PgPool,
+}
+
+impl CollectionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Saves a new collection; `false` if one of that name exists.
+    #[instrument(level = "debug", skip_all, fields(name = %collection.name))]
+    pub async fn save(&self, collection: &Collection) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO collections
+                (name, description, embedding_model, chunker, chunk_max_lines, chunk_overlap,
+                 global, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&collection.name)
+        .bind(&collection.description)
+        .bind(&collection.embedding_model)
+        .bind(collection.chunker.as_str())
+        .bind(collection.chunk_max_lines)
+        .bind(collection.chunk_overlap)
+        .bind(collection.global)
+        .bind(collection.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save collection {}: {e}", collection.name);
+            AppError::db_query("Failed to save collection", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Every collection, by name.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_all(&self) -> Result<Vec<Collection>, AppError> {
+        sqlx::query_as::<_, Collection>(&format!("{SELECT} ORDER BY c.name"))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to list collections: {e}");
+                AppError::db_query("Failed to list collections", e)
+            })
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_name(&self, name: &str) -> Result<Option<Collection>, AppError> {
+        sqlx::query_as::<_, Collection>(&format!("{SELECT} WHERE c.name = $1"))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch collection {name}: {e}");
+                AppError::db_query("Failed to fetch collection", e)
+            })
+    }
+
+    /// Changes how a collection's documents are split; `false` if there is
+    /// no such collection.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_chunking(
+        &self,
+        name: &str,
+        chunker: Chunker,
+        chunk_max_lines: Option<i32>,
+        chunk_overlap: Option<i32>,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE collections SET chunker = $2, chunk_max_lines = $3, chunk_overlap = $4
+             WHERE name = $1",
+        )
+        .bind(name)
+        .bind(chunker.as_str())
+        .bind(chunk_max_lines)
+        .bind(chunk_overlap)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to update chunking of collection {name}: {e}");
+            AppError::db_query("Failed to update collection", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes a collection with its documents; `false` if there is none.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn delete(&self, name: &str) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM collections WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete collection {name}: {e}");
+                AppError::db_query("Failed to delete collection", e)
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The collections attached to a conversation, by name.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_attached(&self, conversation_id: Uuid) -> Result<Vec<Collection>, AppError> {
+        sqlx::query_as::<_, Collection>(&format!(
+            "{SELECT}
+             JOIN conversation_collections a ON a.collection = c.name
+             WHERE a.conversation_id = $1
+             ORDER BY c.name"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list collections of conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to list collections", e)
+        })
+    }
+
+    /// The collections a turn of `conversation_id` retrieves from: the
+    /// global ones and those attached to it.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_searched_by(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<Collection>, AppError> {
+        sqlx::query_as::<_, Collection>(&format!(
+            "{SELECT}
+             WHERE c.global OR EXISTS (
+                SELECT 1 FROM conversation_collections a
+                WHERE a.collection = c.name AND a.conversation_id = $1
+             )
+             ORDER BY c.name"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list collections searched by {conversation_id}: {e}");
+            AppError::db_query("Failed to list collections", e)
+        })
+    }
+
+    /// Attaches a collection to a conversation; attaching it again is a no-op.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn attach(&self, conversation_id: Uuid, name: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO conversation_collections (conversation_id, collection)
+             VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(conversation_id)
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to attach collection {name} to {conversation_id}: {e}");
+            AppError::db_query("Failed to attach collection", e)
+        })?;
+        Ok(())
+    }
+
+    /// Detaches a collection; `false` if it was not attached.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn detach(&self, conversation_id: Uuid, name: &str) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "DELETE FROM conversation_collections WHERE conversation_id = $1 AND collection = $2",
+        )
+        .bind(conversation_id)
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to detach collection {name} from {conversation_id}: {e}");
+            AppError::db_query("Failed to detach collection", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,32 @@
+use crate::errors::AppError;
+
+/// Message bodies longer than this many bytes are compressed. Shorter ones
+/// rarely shrink enough to be worth the CPU on every history replay.
+pub const COMPRESSION_THRESHOLD: usize = 4096;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Splits message content into the `content` and `content_compressed` column
+/// values. Content at or below the threshold, or that does not shrink, is
+/// stored as plain text.
+pub fn encode(content: &str) -> Result<(&str, Option<Vec<u8>>), AppError> {
+    if content.len() <= COMPRESSION_THRESHOLD {
+        return Ok((content, None));
+    }
+    let compressed = zstd::encode_all(content.as_bytes(), ZSTD_LEVEL)
+        .map_err(|e| AppError::Unexpected(format!("Failed to compress message: {e}")))?;
+    if compressed.len() >= content.len() {
+        return Ok((content, None));
+    }
+    Ok(("", Some(compressed)))
+}
+
+/// Reverses [`encode`]: the decompressed body when there is one, else `content`.
+pub fn decode(content: String, compressed: Option<Vec<u8>>) -> Result<String, AppError> {
+    let Some(compressed) = compressed else {
+        return Ok(content);
+    };
+    let bytes = zstd::decode_all(compressed.as_slice())
+        .map_err(|e| AppError::Unexpected(format!("Failed to decompress message: {e}")))?;
+    String::from_utf8(bytes)
+        .map_err(|e| AppError::Unexpected(format!("Compressed message is not UTF-8: {e}")))
+}
@@ -1,9 +1,11 @@
 use chrono::Utc;
 use sqlx::PgPool;
-use tracing::error;
+use tracing::{error, instrument};
+use uuid::Uuid;
 
+use crate::db::compression;
 use crate::errors::AppError;
-use crate::models::Conversation;
+use crate::models::{Conversation, ConversationSort, Verbosity};
 
 #[derive(Clone)]
 pub struct ConversationRepository {
@@ -15,10 +17,24 @@ impl ConversationRepository {
         Self { pool }
     }
 
-    pub async fn find_all(&self) -> Result<Vec<Conversation>, AppError> {
-        sqlx::query_as::<_, Conversation>(
-            "SELECT id, title, created_at, updated_at FROM conversations ORDER BY updated_at DESC",
-        )
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_all(&self, sort: ConversationSort) -> Result<Vec<Conversation>, AppError> {
+        let order = match sort {
+            ConversationSort::Updated => "updated_at DESC",
+            ConversationSort::Created => "created_at DESC",
+            ConversationSort::Title => "LOWER(title), updated_at DESC",
+            ConversationSort::MessageCount => {
+                "(SELECT COUNT(*) FROM messages m WHERE m.conversation_id = c.id) DESC,
+                 updated_at DESC"
+            }
+        };
+        sqlx::query_as::<_, Conversation>(&format!(
+            "SELECT id, title, created_at, updated_at, language, summary, summary_message_count,
+                    max_history_messages, failed_turn_error, encrypted, icon, color,
+                    verbosity, preset, stateless
+             FROM conversations c
+             ORDER BY {order}"
+        ))
         .fetch_all(&self.pool)
         .await
         .map_err(|e| {
@@ -27,9 +43,13 @@ impl ConversationRepository {
         })
     }
 
-    pub async fn find_by_id(&self, id: &str) -> Result<Option<Conversation>, AppError> {
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<Conversation>, AppError> {
         sqlx::query_as::<_, Conversation>(
-            "SELECT id, title, created_at, updated_at FROM conversations WHERE id = $1",
+            "SELECT id, title, created_at, updated_at, language, summary, summary_message_count,
+                    max_history_messages, failed_turn_error, encrypted, icon, color,
+                    verbosity, preset, stateless
+             FROM conversations WHERE id = $1",
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -40,15 +60,122 @@ impl ConversationRepository {
         })
     }
 
+    /// Up to `limit` conversations with ids after `after_id`, in id order, for
+    /// walking the whole table in batches.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_batch(
+        &self,
+        after_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Conversation>, AppError> {
+        sqlx::query_as::<_, Conversation>(
+            "SELECT id, title, created_at, updated_at, language, summary, summary_message_count,
+                    max_history_messages, failed_turn_error, encrypted, icon, color,
+                    verbosity, preset, stateless
+             FROM conversations
+             WHERE $1::UUID IS NULL OR id > $1
+             ORDER BY id
+             LIMIT $2",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch a batch of conversations: {e}");
+            AppError::db_query("Failed to fetch conversations", e)
+        })
+    }
+
+    /// Conversations whose title or any message contains `query`
+    /// (case-insensitive), most recently updated first. Compressed messages
+    /// are decompressed to be searched, in conversations nothing else matched.
+    #[instrument(level = "debug", skip(self, query))]
+    pub async fn search(&self, query: &str, limit: i64) -> Result<Vec<Conversation>, AppError> {
+        let failed = |e: sqlx::Error| {
+            error!("Failed to search conversations: {e}");
+            AppError::db_query("Failed to search conversations", e)
+        };
+        let pattern = format!("%{}%", escape_like(query));
+        let candidates: Vec<(Uuid, bool)> = sqlx::query_as(
+            "SELECT c.id, c.title ILIKE $1
+                    OR EXISTS (SELECT 1 FROM messages m
+                               WHERE m.conversation_id = c.id AND m.content ILIKE $1)
+             FROM conversations c
+             WHERE c.title ILIKE $1
+                OR EXISTS (SELECT 1 FROM messages m
+                           WHERE m.conversation_id = c.id
+                             AND (m.content ILIKE $1 OR m.content_compressed IS NOT NULL))
+             ORDER BY c.updated_at DESC",
+        )
+        .bind(pattern)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        let needle = query.to_lowercase();
+        let mut ids = Vec::new();
+        for (id, matched) in candidates {
+            if ids.len() as i64 >= limit {
+                break;
+            }
+            if matched || self.compressed_contain(id, &needle).await? {
+                ids.push(id);
+            }
+        }
+        sqlx::query_as::<_, Conversation>(
+            "SELECT id, title, created_at, updated_at, language, summary, summary_message_count,
+                    max_history_messages, failed_turn_error, encrypted, icon, color,
+                    verbosity, preset, stateless
+             FROM conversations
+             WHERE id = ANY($1)
+             ORDER BY updated_at DESC",
+        )
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)
+    }
+
+    /// Whether any compressed message of `conversation_id` contains `needle`,
+    /// which is lowercase.
+    async fn compressed_contain(
+        &self,
+        conversation_id: Uuid,
+        needle: &str,
+    ) -> Result<bool, AppError> {
+        let bodies: Vec<Vec<u8>> = sqlx::query_scalar(
+            "SELECT content_compressed FROM messages
+             WHERE conversation_id = $1 AND content_compressed IS NOT NULL",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load compressed messages of {conversation_id}: {e}");
+            AppError::db_query("Failed to search conversations", e)
+        })?;
+        for body in bodies {
+            if compression::decode(String::new(), Some(body))?.to_lowercase().contains(needle) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    #[instrument(level = "debug", skip_all, fields(conversation_id = %conversation.id))]
     pub async fn save(&self, conversation: &Conversation) -> Result<Conversation, AppError> {
         sqlx::query(
-            "INSERT INTO conversations (id, title, created_at, updated_at)
-             VALUES ($1, $2, $3, $4)",
+            "INSERT INTO conversations
+                 (id, title, created_at, updated_at, language, max_history_messages)
+             VALUES ($1, $2, $3, $4, $5, $6)",
         )
-        .bind(&conversation.id)
+        .bind(conversation.id)
         .bind(&conversation.title)
         .bind(conversation.created_at)
         .bind(conversation.updated_at)
+        .bind(&conversation.language)
+        .bind(conversation.max_history_messages)
         .execute(&self.pool)
         .await
         .map_err(|e| {
@@ -58,7 +185,8 @@ impl ConversationRepository {
         Ok(conversation.clone())
     }
 
-    pub async fn update_timestamp(&self, id: &str) -> Result<(), AppError> {
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_timestamp(&self, id: Uuid) -> Result<(), AppError> {
         sqlx::query("UPDATE conversations SET updated_at = $1 WHERE id = $2")
             .bind(Utc::now())
             .bind(id)
@@ -70,4 +198,179 @@ impl ConversationRepository {
             })?;
         Ok(())
     }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_language(&self, id: Uuid, language: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET language = $1 WHERE id = $2")
+            .bind(language)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation language {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    /// Records why the last turn failed, or (with `None`) that it has since
+    /// been answered.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_failed_turn(&self, id: Uuid, error: Option<&str>) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE conversations SET failed_turn_error = $1
+             WHERE id = $2 AND failed_turn_error IS DISTINCT FROM $1",
+        )
+        .bind(error)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to update failed turn of conversation {id}: {e}");
+            AppError::db_query("Failed to update conversation", e)
+        })?;
+        Ok(())
+    }
+
+    /// Sets or (with `None`) clears the conversation's history limit override.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_max_history_messages(
+        &self,
+        id: Uuid,
+        max_history_messages: Option<i32>,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET max_history_messages = $1 WHERE id = $2")
+            .bind(max_history_messages)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation history limit {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    /// Sets or (with `None`) clears the conversation's icon and color.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_appearance(
+        &self,
+        id: Uuid,
+        icon: Option<&str>,
+        color: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET icon = $1, color = $2 WHERE id = $3")
+            .bind(icon)
+            .bind(color)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation appearance {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_verbosity(&self, id: Uuid, verbosity: Verbosity) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET verbosity = $1 WHERE id = $2")
+            .bind(verbosity.as_str())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation verbosity {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_stateless(&self, id: Uuid, stateless: bool) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET stateless = $1 WHERE id = $2")
+            .bind(stateless)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation history replay {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    /// Sets or (with `None`) clears the conversation's assistant preset.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_preset(&self, id: Uuid, preset: Option<&str>) -> Result<(), AppError> {
+        sqlx::query("UPDATE conversations SET preset = $1 WHERE id = $2")
+            .bind(preset)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update conversation preset {id}: {e}");
+                AppError::db_query("Failed to update conversation", e)
+            })?;
+        Ok(())
+    }
+
+    /// Stores a new summary covering the first `message_count` messages.
+    #[instrument(level = "debug", skip(self, summary))]
+    pub async fn update_summary(
+        &self,
+        id: Uuid,
+        summary: &str,
+        message_count: i32,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE conversations SET summary = $1, summary_message_count = $2 WHERE id = $3",
+        )
+        .bind(summary)
+        .bind(message_count)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to update conversation summary {id}: {e}");
+            AppError::db_query("Failed to update conversation", e)
+        })?;
+        Ok(())
+    }
+
+    /// The key salt and key check of an encrypted conversation; `None` if
+    /// there is no such conversation or it is not encrypted.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_key_params(&self, id: Uuid) -> Result<Option<(String, String)>, AppError> {
+        sqlx::query_as::<_, (String, String)>(
+            "SELECT key_salt, key_check FROM conversations WHERE id = $1 AND encrypted",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find key of conversation {id}: {e}");
+            AppError::db_query("Failed to find conversation key", e)
+        })
+    }
+
+    /// Deletes a conversation; its messages, and everything hanging off them,
+    /// go with it by cascade. Returns `false` if it did not exist.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn delete(&self, id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM conversations WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete conversation {id}: {e}");
+                AppError::db_query(format!("Failed to delete conversation {id}"), e)
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Escapes `LIKE` wildcards so user input matches literally.
+pub(crate) fn escape_like(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
 }
@@ -0,0 +1,163 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{ConversationWebhook, NotificationStatus, WebhookDelivery};
+
+const COLUMNS: &str = "id, conversation_id, url, secret, created_at";
+const DELIVERY_COLUMNS: &str =
+    "id, webhook_id, message_id, status, attempts, last_error, created_at, delivered_at";
+
+#[derive(Clone)]
+pub struct ConversationWebhookRepository {
+    pool: PgPool,
+}
+
+impl ConversationWebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(webhook_id = %webhook.id))]
+    pub async fn save(&self, webhook: &ConversationWebhook) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO conversation_webhooks (id, conversation_id, url, secret, created_at)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(webhook.id)
+        .bind(webhook.conversation_id)
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .bind(webhook.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save webhook {}: {e}", webhook.id);
+            AppError::db_query("Failed to save webhook", e)
+        })?;
+        Ok(())
+    }
+
+    /// Every webhook, or only those of `conversation_id`, oldest first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_all(
+        &self,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<ConversationWebhook>, AppError> {
+        sqlx::query_as::<_, ConversationWebhook>(&format!(
+            "SELECT {COLUMNS} FROM conversation_webhooks
+             WHERE $1::UUID IS NULL OR conversation_id = $1
+             ORDER BY created_at"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list webhooks: {e}");
+            AppError::db_query("Failed to list webhooks", e)
+        })
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn exists(&self, id: Uuid) -> Result<bool, AppError> {
+        sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM conversation_webhooks WHERE id = $1)",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find webhook {id}: {e}");
+            AppError::db_query(format!("Failed to find webhook {id}"), e)
+        })
+    }
+
+    /// Returns `false` when there was no such webhook.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn delete(&self, id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM conversation_webhooks WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete webhook {id}: {e}");
+                AppError::db_query(format!("Failed to delete webhook {id}"), e)
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[instrument(level = "debug", skip_all, fields(delivery_id = %delivery.id))]
+    pub async fn save_delivery(&self, delivery: &WebhookDelivery) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (id, webhook_id, message_id, status, attempts,
+                                             created_at)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(delivery.id)
+        .bind(delivery.webhook_id)
+        .bind(delivery.message_id)
+        .bind(delivery.status.as_str())
+        .bind(delivery.attempts)
+        .bind(delivery.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save webhook delivery {}: {e}", delivery.id);
+            AppError::db_query("Failed to save webhook delivery", e)
+        })?;
+        Ok(())
+    }
+
+    /// Records one more delivery attempt and where it left the delivery;
+    /// `error` is why it failed.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn record_attempt(
+        &self,
+        id: Uuid,
+        status: NotificationStatus,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let delivered_at = (status == NotificationStatus::Delivered).then(Utc::now);
+        sqlx::query(
+            "UPDATE webhook_deliveries
+             SET status = $1, attempts = attempts + 1, last_error = $2, delivered_at = $3
+             WHERE id = $4",
+        )
+        .bind(status.as_str())
+        .bind(error)
+        .bind(delivered_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to record webhook delivery {id}: {e}");
+            AppError::db_query("Failed to update webhook delivery", e)
+        })?;
+        Ok(())
+    }
+
+    /// The latest `limit` deliveries of `webhook_id`, newest first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, AppError> {
+        sqlx::query_as::<_, WebhookDelivery>(&format!(
+            "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries
+             WHERE webhook_id = $1
+             ORDER BY created_at DESC
+             LIMIT $2"
+        ))
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list deliveries of webhook {webhook_id}: {e}");
+            AppError::db_query("Failed to list webhook deliveries", e)
+        })
+    }
+}
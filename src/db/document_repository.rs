@@ -0,0 +1,539 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use tracing::{error, instrument, warn};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{
+    CollectionChunk, Document, DocumentChunk, IndexedDocument, Ingestion, IngestionReport,
+    IngestionStatus, RetrievalPrecision, RetrievedChunk,
+};
+
+const INGESTION_COLUMNS: &str = "id, collection, source, status, files, chunks, added, updated,
+                                 skipped, removed, embedded, error, created_at, completed_at";
+
+/// Most dimensions pgvector can index; larger embeddings are searched by a
+/// full scan.
+const MAX_INDEXED_DIMENSIONS: usize = 2000;
+
+/// Groups of rated chunks [`DocumentRepository::find_precision`] counts by.
+pub const BY_COLLECTION: &str = "collection";
+pub const BY_RANKING: &str = "CASE WHEN vector_rank IS NOT NULL AND text_rank IS NOT NULL \
+                              THEN 'both' WHEN text_rank IS NOT NULL THEN 'text' \
+                              ELSE 'vector' END";
+pub const BY_POSITION: &str = "position::TEXT";
+
+#[derive(Clone)]
+pub struct DocumentRepository {
+    pool: PgPool,
+}
+
+impl DocumentRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Brings what is indexed from `source` in `collection` up to date, at
+    /// once: searches see either the old files or the new. Documents whose
+    /// path is not in `paths` are dropped, and `documents` replace those of
+    /// their paths; the others are left as they are.
+    #[instrument(level = "debug", skip(self, documents, paths), fields(count = documents.len()))]
+    pub async fn sync_source(
+        &self,
+        collection: &str,
+        source: &str,
+        documents: &[(Document, Vec<DocumentChunk>)],
+        paths: &[String],
+    ) -> Result<(), AppError> {
+        let failed = |e: sqlx::Error| {
+            error!("Failed to index {source} into {collection}: {e}");
+            AppError::db_query("Failed to save documents", e)
+        };
+        let replaced: Vec<&str> = documents.iter().map(|(d, _)| d.path.as_str()).collect();
+        self.index_embeddings(documents.iter().flat_map(|(_, chunks)| chunks)).await;
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        sqlx::query(
+            "DELETE FROM documents
+             WHERE collection = $1 AND source = $2 AND (path <> ALL($3) OR path = ANY($4))",
+        )
+        .bind(collection)
+        .bind(source)
+        .bind(paths)
+        .bind(&replaced)
+        .execute(&mut *tx)
+        .await
+        .map_err(failed)?;
+        for (document, chunks) in documents {
+            sqlx::query(
+                "INSERT INTO documents
+                    (id, collection, source, path, language, content, content_hash, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(document.id)
+            .bind(&document.collection)
+            .bind(&document.source)
+            .bind(&document.path)
+            .bind(&document.language)
+            .bind(&document.content)
+            .bind(&document.content_hash)
+            .bind(document.created_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+            insert_chunks(&mut tx, chunks).await.map_err(failed)?;
+        }
+        tx.commit().await.map_err(failed)
+    }
+
+    /// Replaces the chunks of each of `documents`, and their text, at once.
+    #[instrument(level = "debug", skip(self, documents), fields(count = documents.len()))]
+    pub async fn replace_chunks(
+        &self,
+        documents: &[(Document, Vec<DocumentChunk>)],
+    ) -> Result<(), AppError> {
+        let failed = |e: sqlx::Error| {
+            error!("Failed to replace chunks: {e}");
+            AppError::db_query("Failed to save documents", e)
+        };
+        self.index_embeddings(documents.iter().flat_map(|(_, chunks)| chunks)).await;
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        for (document, chunks) in documents {
+            sqlx::query("UPDATE documents SET content = $2, content_hash = $3 WHERE id = $1")
+                .bind(document.id)
+                .bind(&document.content)
+                .bind(&document.content_hash)
+                .execute(&mut *tx)
+                .await
+                .map_err(failed)?;
+            sqlx::query("DELETE FROM document_chunks WHERE document_id = $1")
+                .bind(document.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(failed)?;
+            insert_chunks(&mut tx, chunks).await.map_err(failed)?;
+        }
+        tx.commit().await.map_err(failed)
+    }
+
+    /// Creates the similarity index of each dimension of `chunks`'
+    /// embeddings that has none yet. It is done outside the transaction
+    /// saving them, so ingestions do not keep each other from writing; a
+    /// failure only leaves searches unindexed.
+    async fn index_embeddings<'a>(&self, chunks: impl Iterator<Item = &'a DocumentChunk>) {
+        let dimensions: BTreeSet<usize> = chunks
+            .map(|chunk| chunk.embedding.len())
+            .filter(|n| (1..=MAX_INDEXED_DIMENSIONS).contains(n))
+            .collect();
+        for n in dimensions {
+            let name = format!("idx_document_chunks_embedding_{n}");
+            // Looked up in this schema only: a tenant's index is its own.
+            let exists = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM pg_indexes
+                                WHERE schemaname = current_schema() AND indexname = $1)",
+            )
+            .bind(&name)
+            .fetch_one(&self.pool)
+            .await;
+            if matches!(exists, Ok(true)) {
+                continue;
+            }
+            let create = format!(
+                "CREATE INDEX IF NOT EXISTS {name}
+                 ON document_chunks USING hnsw ((embedding::vector({n})) vector_cosine_ops)
+                 WHERE vector_dims(embedding) = {n}"
+            );
+            if let Err(e) = sqlx::query(&create).execute(&self.pool).await {
+                warn!("Failed to index {n}-dimensional embeddings: {e}");
+            }
+        }
+    }
+
+    /// Every document of `collection`, or of one `source` in it, with its
+    /// text and chunk hashes, by path.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_documents(
+        &self,
+        collection: &str,
+        source: Option<&str>,
+    ) -> Result<Vec<IndexedDocument>, AppError> {
+        sqlx::query_as::<_, IndexedDocument>(
+            "SELECT d.id, d.collection, d.source, d.path, d.language, d.content, d.content_hash,
+                    d.created_at,
+                    ARRAY(SELECT COALESCE(c.content_hash, '') FROM document_chunks c
+                          WHERE c.document_id = d.id
+                          ORDER BY c.position) AS chunk_hashes
+             FROM documents d
+             WHERE d.collection = $1 AND ($2::TEXT IS NULL OR d.source = $2)
+             ORDER BY d.source, d.path",
+        )
+        .bind(collection)
+        .bind(source)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load documents of {collection}: {e}");
+            AppError::db_query("Failed to load documents", e)
+        })
+    }
+
+    /// The embeddings by `model` of the chunks of `collection` with any of
+    /// `hashes`, by hash.
+    #[instrument(level = "debug", skip(self, hashes), fields(count = hashes.len()))]
+    pub async fn find_embeddings(
+        &self,
+        collection: &str,
+        model: &str,
+        hashes: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>, AppError> {
+        let rows: Vec<(String, Vec<f32>)> = sqlx::query_as(
+            "SELECT DISTINCT ON (c.content_hash) c.content_hash, c.embedding::REAL[]
+             FROM document_chunks c
+             JOIN documents d ON d.id = c.document_id
+             WHERE d.collection = $1 AND c.embedding_model = $2 AND c.content_hash = ANY($3)",
+        )
+        .bind(collection)
+        .bind(model)
+        .bind(hashes)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load embeddings of {collection}: {e}");
+            AppError::db_query("Failed to load chunks", e)
+        })?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// The chunks of a document, in order.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_document_chunks(
+        &self,
+        document_id: Uuid,
+    ) -> Result<Vec<DocumentChunk>, AppError> {
+        sqlx::query_as::<_, DocumentChunk>(
+            "SELECT id, document_id, position, content, start_line, end_line,
+                    embedding::REAL[] AS embedding, content_hash, embedding_model
+             FROM document_chunks
+             WHERE document_id = $1
+             ORDER BY position",
+        )
+        .bind(document_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load chunks of document {document_id}: {e}");
+            AppError::db_query("Failed to load chunks", e)
+        })
+    }
+
+    /// The `limit` chunks of `collections` whose embeddings are closest to
+    /// `query`, best first, scored by cosine similarity. Chunks embedded in
+    /// other dimensions, by another model, are left out.
+    #[instrument(level = "debug", skip(self, query))]
+    pub async fn search_similar(
+        &self,
+        collections: &[String],
+        query: &[f32],
+        limit: i64,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        let n = query.len();
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        // Spelled like the index of `n` dimensions, and the only sort key,
+        // so the index can produce the order.
+        let distance = format!("c.embedding::vector({n}) <=> $2::REAL[]::vector({n})");
+        let hits = sqlx::query_as::<_, RetrievedChunk>(&format!(
+            "SELECT c.id AS chunk_id, c.document_id, d.collection, d.path, d.language, c.content,
+                    c.start_line, c.end_line, (1 - ({distance}))::REAL AS score,
+                    NULL::INTEGER AS vector_rank, NULL::INTEGER AS text_rank,
+                    NULL::BOOLEAN AS relevant
+             FROM document_chunks c
+             JOIN documents d ON d.id = c.document_id
+             WHERE d.collection = ANY($1) AND vector_dims(c.embedding) = {n}
+             ORDER BY {distance}
+             LIMIT $3"
+        ))
+        .bind(collections)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to search the embeddings of {collections:?}: {e}");
+            AppError::db_query("Failed to search chunks", e)
+        })?;
+        // Similarity to an all-zero vector is undefined.
+        Ok(hits.into_iter().filter(|hit| hit.score.is_finite()).collect())
+    }
+
+    /// The `limit` chunks of `collections` that best match `query`, in
+    /// `websearch_to_tsquery` syntax, best first, scored by `ts_rank`.
+    #[instrument(level = "debug", skip(self, query))]
+    pub async fn search_text(
+        &self,
+        collections: &[String],
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        sqlx::query_as::<_, RetrievedChunk>(
+            "SELECT c.id AS chunk_id, c.document_id, d.collection, d.path, d.language, c.content,
+                    c.start_line, c.end_line, ts_rank(c.search, q.query) AS score,
+                    NULL::INTEGER AS vector_rank, NULL::INTEGER AS text_rank,
+                    NULL::BOOLEAN AS relevant
+             FROM document_chunks c
+             JOIN documents d ON d.id = c.document_id
+             CROSS JOIN (SELECT websearch_to_tsquery('english', $2) AS query) q
+             WHERE d.collection = ANY($1) AND c.search @@ q.query
+             ORDER BY score DESC, c.id
+             LIMIT $3",
+        )
+        .bind(collections)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to search the text of {collections:?}: {e}");
+            AppError::db_query("Failed to search chunks", e)
+        })
+    }
+
+    /// A page of `collection`'s chunks, optionally of one document, in file
+    /// and position order.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_chunk_page(
+        &self,
+        collection: &str,
+        path: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<CollectionChunk>, AppError> {
+        sqlx::query_as::<_, CollectionChunk>(
+            "SELECT c.id, c.document_id, d.source, d.path, d.language, c.position, c.content,
+                    c.start_line, c.end_line, vector_dims(c.embedding) AS dimensions
+             FROM document_chunks c
+             JOIN documents d ON d.id = c.document_id
+             WHERE d.collection = $1 AND ($2::TEXT IS NULL OR d.path = $2)
+             ORDER BY d.source, d.path, c.position
+             LIMIT $3 OFFSET $4",
+        )
+        .bind(collection)
+        .bind(path)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list chunks of {collection}: {e}");
+            AppError::db_query("Failed to list chunks", e)
+        })
+    }
+
+    /// Saves the chunks retrieved for one version of a reply, best first.
+    #[instrument(level = "debug", skip(self, chunks), fields(count = chunks.len()))]
+    pub async fn save_retrieved(
+        &self,
+        message_id: Uuid,
+        version: i32,
+        chunks: &[RetrievedChunk],
+    ) -> Result<(), AppError> {
+        let failed = |e: sqlx::Error| {
+            error!("Failed to save retrieved chunks of message {message_id}: {e}");
+            AppError::db_query("Failed to save retrieved chunks", e)
+        };
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        for (position, chunk) in chunks.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO message_retrievals
+                    (message_id, version, position, chunk_id, document_id, collection, path,
+                     language, content, start_line, end_line, score, vector_rank, text_rank)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+            )
+            .bind(message_id)
+            .bind(version)
+            .bind(position as i32 + 1)
+            .bind(chunk.chunk_id)
+            .bind(chunk.document_id)
+            .bind(&chunk.collection)
+            .bind(&chunk.path)
+            .bind(&chunk.language)
+            .bind(&chunk.content)
+            .bind(chunk.start_line)
+            .bind(chunk.end_line)
+            .bind(chunk.score)
+            .bind(chunk.vector_rank)
+            .bind(chunk.text_rank)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+        }
+        tx.commit().await.map_err(failed)
+    }
+
+    /// The chunks retrieved for a reply's active version, best first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_retrieved(&self, message_id: Uuid) -> Result<Vec<RetrievedChunk>, AppError> {
+        sqlx::query_as::<_, RetrievedChunk>(
+            "SELECT r.chunk_id, r.document_id, r.collection, r.path, r.language, r.content,
+                    r.start_line, r.end_line, r.score, r.vector_rank, r.text_rank, r.relevant
+             FROM message_retrievals r
+             JOIN messages m ON m.id = r.message_id AND m.active_version = r.version
+             WHERE r.message_id = $1
+             ORDER BY r.position",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load retrieved chunks of message {message_id}: {e}");
+            AppError::db_query("Failed to load retrieved chunks", e)
+        })
+    }
+
+    /// Records whether a chunk retrieved for a reply's active version was
+    /// relevant. `false` if it was not retrieved for it.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn rate_retrieved(
+        &self,
+        message_id: Uuid,
+        chunk_id: Uuid,
+        relevant: Option<bool>,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE message_retrievals r SET relevant = $3
+             FROM messages m
+             WHERE m.id = r.message_id AND m.active_version = r.version
+               AND r.message_id = $1 AND r.chunk_id = $2",
+        )
+        .bind(message_id)
+        .bind(chunk_id)
+        .bind(relevant)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to rate chunk {chunk_id} of message {message_id}: {e}");
+            AppError::db_query("Failed to rate retrieved chunk", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// How many rated retrieved chunks there are, and how many were relevant,
+    /// per value of `group` (one of the `BY_` expressions), in its order.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_precision(
+        &self,
+        group: &'static str,
+    ) -> Result<Vec<RetrievalPrecision>, AppError> {
+        let rows: Vec<(String, i64, i64)> = sqlx::query_as(&format!(
+            "SELECT {group} AS grp, COUNT(*), COUNT(*) FILTER (WHERE relevant)
+             FROM message_retrievals
+             WHERE relevant IS NOT NULL
+             GROUP BY grp
+             ORDER BY MIN(position), grp"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to count rated chunks: {e}");
+            AppError::db_query("Failed to load retrieval metrics", e)
+        })?;
+        let precision = rows.into_iter().map(|(g, rated, relevant)| {
+            RetrievalPrecision::new(g, rated, relevant)
+        });
+        Ok(precision.collect())
+    }
+
+    #[instrument(level = "debug", skip_all, fields(id = %ingestion.id))]
+    pub async fn save_ingestion(&self, ingestion: &Ingestion) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO ingestions (id, collection, source, status, created_at)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(ingestion.id)
+        .bind(&ingestion.collection)
+        .bind(&ingestion.source)
+        .bind(ingestion.status.as_str())
+        .bind(ingestion.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save ingestion {}: {e}", ingestion.id);
+            AppError::db_query("Failed to save ingestion", e)
+        })?;
+        Ok(())
+    }
+
+    /// Records how an ingestion ended.
+    #[instrument(level = "debug", skip(self, report, error))]
+    pub async fn finish_ingestion(
+        &self,
+        id: Uuid,
+        status: IngestionStatus,
+        report: &IngestionReport,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE ingestions
+             SET status = $1, files = $2, chunks = $3, added = $4, updated = $5, skipped = $6,
+                 removed = $7, embedded = $8, error = $9, completed_at = $10
+             WHERE id = $11",
+        )
+        .bind(status.as_str())
+        .bind(report.files)
+        .bind(report.chunks)
+        .bind(report.added)
+        .bind(report.updated)
+        .bind(report.skipped)
+        .bind(report.removed)
+        .bind(report.embedded)
+        .bind(error)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to finish ingestion {id}: {e}");
+            AppError::db_query("Failed to update ingestion", e)
+        })?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_ingestion(&self, id: Uuid) -> Result<Option<Ingestion>, AppError> {
+        sqlx::query_as::<_, Ingestion>(&format!(
+            "SELECT {INGESTION_COLUMNS} FROM ingestions WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch ingestion {id}: {e}");
+            AppError::db_query("Failed to fetch ingestion", e)
+        })
+    }
+}
+
+async fn insert_chunks(conn: &mut PgConnection, chunks: &[DocumentChunk]) -> sqlx::Result<()> {
+    for chunk in chunks {
+        sqlx::query(
+            "INSERT INTO document_chunks
+                (id, document_id, position, content, start_line, end_line, embedding,
+                 content_hash, embedding_model)
+             VALUES ($1, $2, $3, $4, $5, $6, $7::REAL[]::vector, $8, $9)",
+        )
+        .bind(chunk.id)
+        .bind(chunk.document_id)
+        .bind(chunk.position)
+        .bind(&chunk.content)
+        .bind(chunk.start_line)
+        .bind(chunk.end_line)
+        .bind(&chunk.embedding)
+        .bind(&chunk.content_hash)
+        .bind(&chunk.embedding_model)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
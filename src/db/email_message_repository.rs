@@ -0,0 +1,75 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Emails the gateway has answered and the replies it sent, by Message-ID.
+#[derive(Clone)]
+pub struct EmailMessageRepository {
+    pool: PgPool,
+}
+
+impl EmailMessageRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Takes the email `message_id` to answer. Returns `false` when it was
+    /// taken before, by this server or another one.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn claim(&self, message_id: &str) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO email_messages (message_id) VALUES ($1)
+             ON CONFLICT (message_id) DO NOTHING",
+        )
+        .bind(message_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to claim email {message_id}: {e}");
+            AppError::db_query("Failed to claim email", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The conversation of the latest of `message_ids` that belongs to one.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_conversation(
+        &self,
+        message_ids: &[String],
+    ) -> Result<Option<Uuid>, AppError> {
+        sqlx::query_scalar::<_, Uuid>(
+            "SELECT conversation_id FROM email_messages
+             WHERE message_id = ANY($1) AND conversation_id IS NOT NULL
+             ORDER BY created_at DESC
+             LIMIT 1",
+        )
+        .bind(message_ids)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find the conversation of an email thread: {e}");
+            AppError::db_query("Failed to find email thread", e)
+        })
+    }
+
+    /// Files every one of `message_ids` under `conversation_id`.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn link(&self, message_ids: &[String], conversation_id: Uuid) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO email_messages (message_id, conversation_id)
+             SELECT UNNEST($1::TEXT[]), $2
+             ON CONFLICT (message_id) DO UPDATE SET conversation_id = EXCLUDED.conversation_id",
+        )
+        .bind(message_ids)
+        .bind(conversation_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to link emails to conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to link emails", e)
+        })?;
+        Ok(())
+    }
+}
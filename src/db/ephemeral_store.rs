@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::models::{Conversation, Message, Verbosity};
+
+/// In-memory home for ephemeral ("incognito") conversations.
+///
+/// Nothing here is written to Postgres. Conversations disappear when they have
+/// been idle for longer than the configured timeout, when deleted, or when the
+/// server restarts.
+#[derive(Clone, Default)]
+pub struct EphemeralStore {
+    conversations: Arc<Mutex<HashMap<Uuid, Entry>>>,
+}
+
+struct Entry {
+    conversation: Conversation,
+    messages: Vec<Message>,
+    last_active: Instant,
+}
+
+impl EphemeralStore {
+    pub fn create(&self, conversation: Conversation) {
+        self.lock().insert(
+            conversation.id,
+            Entry { conversation, messages: Vec::new(), last_active: Instant::now() },
+        );
+    }
+
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.lock().contains_key(&id)
+    }
+
+    pub fn find_conversation(&self, id: Uuid) -> Option<Conversation> {
+        self.lock().get(&id).map(|e| e.conversation.clone())
+    }
+
+    pub fn set_language(&self, id: Uuid, language: &str) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.language = Some(language.to_string());
+        }
+    }
+
+    pub fn set_max_history_messages(&self, id: Uuid, max_history_messages: Option<i32>) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.max_history_messages = max_history_messages;
+        }
+    }
+
+    pub fn set_appearance(&self, id: Uuid, icon: Option<String>, color: Option<String>) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.icon = icon;
+            entry.conversation.color = color;
+        }
+    }
+
+    pub fn set_verbosity(&self, id: Uuid, verbosity: Verbosity) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.verbosity = verbosity;
+        }
+    }
+
+    pub fn set_stateless(&self, id: Uuid, stateless: bool) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.stateless = stateless;
+        }
+    }
+
+    pub fn set_preset(&self, id: Uuid, preset: Option<String>) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.preset = preset;
+        }
+    }
+
+    pub fn set_failed_turn(&self, id: Uuid, error: Option<String>) {
+        if let Some(entry) = self.lock().get_mut(&id) {
+            entry.conversation.failed_turn_error = error;
+        }
+    }
+
+    /// Messages of a conversation in the order they were added.
+    pub fn messages(&self, id: Uuid) -> Option<Vec<Message>> {
+        self.lock().get(&id).map(|e| e.messages.clone())
+    }
+
+    /// Replaces a message's follow-up suggestions. Returns `false` if the
+    /// conversation is not ephemeral.
+    pub fn set_suggestions(&self, id: Uuid, message_id: Uuid, suggestions: Vec<String>) -> bool {
+        let mut conversations = self.lock();
+        let Some(entry) = conversations.get_mut(&id) else {
+            return false;
+        };
+        if let Some(message) = entry.messages.iter_mut().find(|m| m.id == message_id) {
+            message.suggestions = suggestions;
+        }
+        true
+    }
+
+    /// Appends `message` to its conversation. Returns `false` (and drops the
+    /// message) if the conversation is not ephemeral.
+    pub fn push_message(&self, message: Message) -> bool {
+        let mut conversations = self.lock();
+        let Some(entry) = conversations.get_mut(&message.conversation_id) else {
+            return false;
+        };
+        entry.conversation.updated_at = message.created_at;
+        entry.last_active = Instant::now();
+        entry.messages.push(message);
+        true
+    }
+
+    pub fn remove(&self, id: Uuid) -> bool {
+        self.lock().remove(&id).is_some()
+    }
+
+    /// Forgets conversations idle for longer than `max_idle`.
+    pub fn sweep(&self, max_idle: Duration) {
+        self.lock().retain(|_, e| e.last_active.elapsed() <= max_idle);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Entry>> {
+        self.conversations.lock().expect("ephemeral store poisoned")
+    }
+}
@@ -0,0 +1,132 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{EvalResult, EvalRun, EvalStatus};
+
+const RUN_COLUMNS: &str = "id, model, status, error, created_at, completed_at";
+const RESULT_COLUMNS: &str = "id, run_id, conversation_id, turn, user_message, original_content,
+                              new_content, error, created_at";
+
+#[derive(Clone)]
+pub struct EvalRepository {
+    pool: PgPool,
+}
+
+impl EvalRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(run_id = %run.id))]
+    pub async fn save_run(&self, run: &EvalRun) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO eval_runs (id, model, status, error, created_at, completed_at)
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(run.id)
+        .bind(&run.model)
+        .bind(run.status.as_str())
+        .bind(&run.error)
+        .bind(run.created_at)
+        .bind(run.completed_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save eval run {}: {e}", run.id);
+            AppError::db_query("Failed to save eval run", e)
+        })?;
+        Ok(())
+    }
+
+    /// Marks a run as finished with `status`.
+    #[instrument(level = "debug", skip(self, error))]
+    pub async fn finish_run(
+        &self,
+        id: Uuid,
+        status: EvalStatus,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE eval_runs SET status = $1, error = $2, completed_at = $3 WHERE id = $4")
+            .bind(status.as_str())
+            .bind(error)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to finish eval run {id}: {e}");
+                AppError::db_query("Failed to update eval run", e)
+            })?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_run(&self, id: Uuid) -> Result<Option<EvalRun>, AppError> {
+        sqlx::query_as::<_, EvalRun>(&format!("SELECT {RUN_COLUMNS} FROM eval_runs WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to find eval run {id}: {e}");
+                AppError::db_query(format!("Failed to find eval run {id}"), e)
+            })
+    }
+
+    /// Every run, newest first.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn find_runs(&self) -> Result<Vec<EvalRun>, AppError> {
+        sqlx::query_as::<_, EvalRun>(&format!(
+            "SELECT {RUN_COLUMNS} FROM eval_runs ORDER BY created_at DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch eval runs: {e}");
+            AppError::db_query("Failed to fetch eval runs", e)
+        })
+    }
+
+    #[instrument(level = "debug", skip_all, fields(run_id = %result.run_id, turn = result.turn))]
+    pub async fn save_result(&self, result: &EvalResult) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO eval_results (id, run_id, conversation_id, turn, user_message,
+                                       original_content, new_content, error, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(result.id)
+        .bind(result.run_id)
+        .bind(result.conversation_id)
+        .bind(result.turn)
+        .bind(&result.user_message)
+        .bind(&result.original_content)
+        .bind(&result.new_content)
+        .bind(&result.error)
+        .bind(result.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save eval result for run {}: {e}", result.run_id);
+            AppError::db_query("Failed to save eval result", e)
+        })?;
+        Ok(())
+    }
+
+    /// A run's results, grouped by conversation in turn order.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_results(&self, run_id: Uuid) -> Result<Vec<EvalResult>, AppError> {
+        sqlx::query_as::<_, EvalResult>(&format!(
+            "SELECT {RESULT_COLUMNS} FROM eval_results WHERE run_id = $1
+             ORDER BY conversation_id, turn"
+        ))
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch results of eval run {run_id}: {e}");
+            AppError::db_query(format!("Failed to fetch results of eval run {run_id}"), e)
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+
+use crate::errors::AppError;
+use crate::models::MaintenanceStatus;
+
+#[derive(Clone)]
+pub struct MaintenanceRepository {
+    pool: PgPool,
+}
+
+impl MaintenanceRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn get(&self) -> Result<MaintenanceStatus, AppError> {
+        sqlx::query_as::<_, MaintenanceStatus>(
+            "SELECT enabled, message, updated_at FROM maintenance_mode WHERE id = 1",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to read maintenance mode: {e}");
+            AppError::db_query("Failed to read maintenance mode", e)
+        })
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set(
+        &self,
+        enabled: bool,
+        message: Option<&str>,
+    ) -> Result<MaintenanceStatus, AppError> {
+        sqlx::query_as::<_, MaintenanceStatus>(
+            "UPDATE maintenance_mode SET enabled = $1, message = $2, updated_at = NOW()
+             WHERE id = 1
+             RETURNING enabled, message, updated_at",
+        )
+        .bind(enabled)
+        .bind(message)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to set maintenance mode: {e}");
+            AppError::db_query("Failed to set maintenance mode", e)
+        })
+    }
+}
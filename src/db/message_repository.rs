@@ -1,8 +1,36 @@
-use sqlx::PgPool;
-use tracing::error;
+use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::db::compression::{self, COMPRESSION_THRESHOLD};
 use crate::errors::AppError;
-use crate::models::{Message, MessageRole};
+use crate::models::{
+    Message, MessageRole, MessageVersion, ModelUsage, Source, StarredMessage,
+};
+
+/// Message columns plus how many versions exist (1 for never-regenerated replies)
+/// and whether the message is starred.
+const MESSAGE_SELECT: &str = "SELECT m.id, m.conversation_id, m.role, m.content,
+            m.content_compressed, m.created_at, m.active_version, m.tool_name, m.tool_call_id,
+            m.suggestions, m.quoted_message_id,
+            GREATEST(1, (SELECT COUNT(*) FROM message_versions v WHERE v.message_id = m.id))::INT4
+                AS version_count,
+            EXISTS (SELECT 1 FROM starred_messages s WHERE s.message_id = m.id) AS starred
+     FROM messages m";
+
+/// A `message_sources` row; sources are stored as children of their message.
+#[derive(sqlx::FromRow)]
+struct SourceRow {
+    message_id: Uuid,
+    url: String,
+    title: String,
+    snippet: String,
+    score: Option<f64>,
+}
 
 #[derive(Clone)]
 pub struct MessageRepository {
@@ -14,16 +42,16 @@ impl MessageRepository {
         Self { pool }
     }
 
+    #[instrument(level = "debug", skip(self))]
     pub async fn find_by_conversation_id(
         &self,
-        conversation_id: &str,
+        conversation_id: Uuid,
     ) -> Result<Vec<Message>, AppError> {
-        let rows = sqlx::query(
-            "SELECT id, conversation_id, role, content, created_at
-             FROM messages
-             WHERE conversation_id = $1
-             ORDER BY created_at ASC",
-        )
+        let rows = sqlx::query(&format!(
+            "{MESSAGE_SELECT}
+             WHERE m.conversation_id = $1
+             ORDER BY m.created_at ASC"
+        ))
         .bind(conversation_id)
         .fetch_all(&self.pool)
         .await
@@ -35,44 +63,608 @@ impl MessageRepository {
             )
         })?;
 
+        let mut sources = self.find_sources_by_conversation_id(conversation_id).await?;
+
+        rows.into_iter()
+            .map(|row| message_from_row(row, &mut sources))
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<Message>, AppError> {
+        let row = sqlx::query(&format!("{MESSAGE_SELECT} WHERE m.id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch message {id}: {e}");
+                AppError::db_query(format!("Failed to fetch message {id}"), e)
+            })?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let source_rows = sqlx::query_as::<_, SourceRow>(
+            "SELECT s.message_id, s.url, s.title, s.snippet, s.score
+             FROM message_sources s
+             JOIN messages m ON m.id = s.message_id AND s.version = m.active_version
+             WHERE m.id = $1
+             ORDER BY s.position",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sources for message {id}: {e}");
+            AppError::db_query("Failed to fetch message sources", e)
+        })?;
+
+        message_from_row(row, &mut group_sources(source_rows)).map(Some)
+    }
+
+    /// Loads the sources of every message in a conversation, keyed by message id
+    /// and kept in citation order. Only the active version's sources are returned.
+    async fn find_sources_by_conversation_id(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<HashMap<Uuid, Vec<Source>>, AppError> {
+        let rows = sqlx::query_as::<_, SourceRow>(
+            "SELECT s.message_id, s.url, s.title, s.snippet, s.score
+             FROM message_sources s
+             JOIN messages m ON m.id = s.message_id AND s.version = m.active_version
+             WHERE m.conversation_id = $1
+             ORDER BY s.message_id, s.position",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sources for conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to fetch message sources", e)
+        })?;
+
+        Ok(group_sources(rows))
+    }
+
+    /// Replaces a reply's follow-up suggestions.
+    #[instrument(level = "debug", skip(self, suggestions))]
+    pub async fn update_suggestions(
+        &self,
+        message_id: Uuid,
+        suggestions: &[String],
+    ) -> Result<(), AppError> {
+        sqlx::query("UPDATE messages SET suggestions = $2 WHERE id = $1")
+            .bind(message_id)
+            .bind(suggestions)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update suggestions of message {message_id}: {e}");
+                AppError::db_query("Failed to update message", e)
+            })?;
+        Ok(())
+    }
+
+    /// Stars or unstars a message. Returns `false` if no such message exists.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_starred(&self, message_id: Uuid, starred: bool) -> Result<bool, AppError> {
+        let query = if starred {
+            "INSERT INTO starred_messages (message_id)
+             SELECT id FROM messages WHERE id = $1
+             ON CONFLICT (message_id) DO NOTHING"
+        } else {
+            "DELETE FROM starred_messages WHERE message_id = $1"
+        };
+        sqlx::query(query).bind(message_id).execute(&self.pool).await.map_err(|e| {
+            error!("Failed to update star of message {message_id}: {e}");
+            AppError::db_query("Failed to update message star", e)
+        })?;
+        self.exists(message_id).await
+    }
+
+    async fn exists(&self, message_id: Uuid) -> Result<bool, AppError> {
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM messages WHERE id = $1)")
+            .bind(message_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to look up message {message_id}: {e}");
+                AppError::db_query(format!("Failed to fetch message {message_id}"), e)
+            })
+    }
+
+    /// Every starred message with its conversation's title, most recently
+    /// starred first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_starred(&self) -> Result<Vec<StarredMessage>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT sm.*, s.starred_at, c.title AS conversation_title
+             FROM ({MESSAGE_SELECT}) sm
+             JOIN starred_messages s ON s.message_id = sm.id
+             JOIN conversations c ON c.id = sm.conversation_id
+             ORDER BY s.starred_at DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch starred messages: {e}");
+            AppError::db_query("Failed to fetch starred messages", e)
+        })?;
+
+        let source_rows = sqlx::query_as::<_, SourceRow>(
+            "SELECT s.message_id, s.url, s.title, s.snippet, s.score
+             FROM message_sources s
+             JOIN messages m ON m.id = s.message_id AND s.version = m.active_version
+             JOIN starred_messages st ON st.message_id = m.id
+             ORDER BY s.message_id, s.position",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sources of starred messages: {e}");
+            AppError::db_query("Failed to fetch message sources", e)
+        })?;
+        let mut sources = group_sources(source_rows);
+
         rows.into_iter()
-            .map(|row: sqlx::postgres::PgRow| {
-                use sqlx::Row;
-                let role_str: String = row.try_get("role")
-                    .map_err(|e| AppError::db_query("Failed to read role", e))?;
-                let role = MessageRole::try_from(role_str)
-                    .map_err(|e| AppError::Unexpected(format!("Unknown message role: {e}")))?;
-                Ok(Message {
-                    id: row.try_get("id")
-                        .map_err(|e| AppError::db_query("Failed to read id", e))?,
-                    conversation_id: row.try_get("conversation_id")
-                        .map_err(|e| AppError::db_query("Failed to read conversation_id", e))?,
-                    role,
-                    content: row.try_get("content")
-                        .map_err(|e| AppError::db_query("Failed to read content", e))?,
-                    created_at: row.try_get("created_at")
-                        .map_err(|e| AppError::db_query("Failed to read created_at", e))?,
-                })
+            .map(|row| {
+                let conversation_title = row.try_get("conversation_title")
+                    .map_err(|e| AppError::db_query("Failed to read conversation_title", e))?;
+                let starred_at = row.try_get("starred_at")
+                    .map_err(|e| AppError::db_query("Failed to read starred_at", e))?;
+                let message = message_from_row(row, &mut sources)?;
+                Ok(StarredMessage { message, conversation_title, starred_at })
             })
             .collect()
     }
 
+    /// Lists the stored versions of a message, oldest first. Empty if the
+    /// message has never been regenerated.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_versions(&self, message_id: Uuid) -> Result<Vec<MessageVersion>, AppError> {
+        sqlx::query_as::<_, MessageVersion>(
+            "SELECT v.version, v.content, v.created_at, v.version = m.active_version AS is_active
+             FROM message_versions v
+             JOIN messages m ON m.id = v.message_id
+             WHERE v.message_id = $1
+             ORDER BY v.version",
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch versions for message {message_id}: {e}");
+            AppError::db_query("Failed to fetch message versions", e)
+        })
+    }
+
+    /// Stores a regenerated reply as the newest version of `message_id` and makes
+    /// it active. The first regeneration also records the original reply as
+    /// version 1 so nothing is lost.
+    #[instrument(level = "debug", skip(self, content, sources))]
+    pub async fn add_version(
+        &self,
+        message_id: Uuid,
+        content: &str,
+        sources: &[Source],
+    ) -> Result<Message, AppError> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to add version to message {message_id}: {e}");
+            AppError::db_query("Failed to save message version", e)
+        };
+        let mut tx = self.pool.begin().await.map_err(map_err)?;
+
+        let current: Option<(String, Option<Vec<u8>>, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT content, content_compressed, created_at FROM messages WHERE id = $1 FOR UPDATE",
+        )
+        .bind(message_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        let (content_column, compressed, created_at) =
+            current.ok_or_else(|| AppError::RecordNotFound {
+                entity_type: "message".to_string(),
+                id: message_id.to_string(),
+            })?;
+        let original = compression::decode(content_column, compressed)?;
+
+        let (latest,): (i32,) = sqlx::query_as(
+            "SELECT COALESCE(MAX(version), 0) FROM message_versions WHERE message_id = $1",
+        )
+        .bind(message_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(map_err)?;
+
+        if latest == 0 {
+            sqlx::query(
+                "INSERT INTO message_versions (message_id, version, content, created_at)
+                 VALUES ($1, 1, $2, $3)",
+            )
+            .bind(message_id)
+            .bind(&original)
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        }
+        let version = latest.max(1) + 1;
+
+        sqlx::query(
+            "INSERT INTO message_versions (message_id, version, content) VALUES ($1, $2, $3)",
+        )
+        .bind(message_id)
+        .bind(version)
+        .bind(content)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+
+        insert_sources(&mut tx, message_id, version, sources).await?;
+
+        let (plain, compressed) = compression::encode(content)?;
+        sqlx::query(
+            "UPDATE messages SET content = $2, content_compressed = $3, active_version = $4,
+                    suggestions = '{}'
+             WHERE id = $1",
+        )
+        .bind(message_id)
+        .bind(plain)
+        .bind(compressed)
+        .bind(version)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+
+        tx.commit().await.map_err(map_err)?;
+        self.find_by_id(message_id).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "message".to_string(),
+            id: message_id.to_string(),
+        })
+    }
+
+    /// Makes a stored version the one shown and replayed as history.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn set_active_version(&self, message_id: Uuid, version: i32) -> Result<(), AppError> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to switch message {message_id} to version {version}: {e}");
+            AppError::db_query("Failed to switch message version", e)
+        };
+        let content: Option<(String,)> = sqlx::query_as(
+            "SELECT content FROM message_versions WHERE message_id = $1 AND version = $2",
+        )
+        .bind(message_id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_err)?;
+        let Some((content,)) = content else {
+            return Err(AppError::RecordNotFound {
+                entity_type: "message version".to_string(),
+                id: format!("{message_id}#{version}"),
+            });
+        };
+
+        let (plain, compressed) = compression::encode(&content)?;
+        sqlx::query(
+            "UPDATE messages SET content = $2, content_compressed = $3, active_version = $4
+             WHERE id = $1",
+        )
+        .bind(message_id)
+        .bind(plain)
+        .bind(compressed)
+        .bind(version)
+        .execute(&self.pool)
+        .await
+        .map_err(map_err)?;
+        Ok(())
+    }
+
+    /// Records the token usage reported for a reply, replacing any earlier record.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn record_usage(
+        &self,
+        message_id: Uuid,
+        model: &str,
+        prompt_tokens: Option<u64>,
+        completion_tokens: Option<u64>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO message_usage (message_id, model, prompt_tokens, completion_tokens)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (message_id) DO UPDATE SET
+                model = EXCLUDED.model,
+                prompt_tokens = EXCLUDED.prompt_tokens,
+                completion_tokens = EXCLUDED.completion_tokens",
+        )
+        .bind(message_id)
+        .bind(model)
+        .bind(prompt_tokens.map(|t| t as i64))
+        .bind(completion_tokens.map(|t| t as i64))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to record usage for message {message_id}: {e}");
+            AppError::db_query("Failed to record message usage", e)
+        })?;
+        Ok(())
+    }
+
+    /// Token totals of a conversation's replies, per model. Costs are left
+    /// for the caller to fill in.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn usage_by_model(&self, conversation_id: Uuid) -> Result<Vec<ModelUsage>, AppError> {
+        sqlx::query_as::<_, ModelUsage>(
+            "SELECT u.model, COUNT(*) AS replies,
+                    COALESCE(SUM(u.prompt_tokens), 0)::INT8 AS prompt_tokens,
+                    COALESCE(SUM(u.completion_tokens), 0)::INT8 AS completion_tokens
+             FROM message_usage u
+             JOIN messages m ON m.id = u.message_id
+             WHERE m.conversation_id = $1
+             GROUP BY u.model
+             ORDER BY u.model",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch usage for conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to fetch conversation usage", e)
+        })
+    }
+
+    /// Compresses stored messages written before compression existed (or while
+    /// the threshold was higher), `batch_size` rows at a time. Returns how many
+    /// were compressed.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn compress_existing(&self, batch_size: i64) -> Result<u64, AppError> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to compress stored messages: {e}");
+            AppError::db_query("Failed to compress stored messages", e)
+        };
+        let mut compressed_count = 0;
+        // Keyset pagination: rows that do not shrink stay uncompressed and
+        // must not be selected again.
+        let mut after = Uuid::nil();
+        loop {
+            let batch: Vec<(Uuid, String)> = sqlx::query_as(
+                "SELECT id, content FROM messages
+                 WHERE content_compressed IS NULL AND octet_length(content) > $1 AND id > $2
+                 ORDER BY id
+                 LIMIT $3",
+            )
+            .bind(COMPRESSION_THRESHOLD as i32)
+            .bind(after)
+            .bind(batch_size)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(map_err)?;
+            let Some((last_id, _)) = batch.last() else {
+                return Ok(compressed_count);
+            };
+            after = *last_id;
+
+            for (id, content) in &batch {
+                let (plain, Some(compressed)) = compression::encode(content)? else {
+                    continue;
+                };
+                sqlx::query(
+                    "UPDATE messages SET content = $2, content_compressed = $3
+                     WHERE id = $1 AND content_compressed IS NULL",
+                )
+                .bind(id)
+                .bind(plain)
+                .bind(compressed)
+                .execute(&self.pool)
+                .await
+                .map_err(map_err)?;
+                compressed_count += 1;
+            }
+        }
+    }
+
+    /// Marks a conversation encrypted and rewrites the content of its messages
+    /// and their earlier versions through `seal`, in one transaction. The
+    /// conversation's summary is dropped since it would give the content away.
+    /// Returns `false`, changing nothing, if the conversation does not exist or
+    /// is already encrypted.
+    #[instrument(level = "debug", skip(self, key_salt, key_check, seal))]
+    pub async fn encrypt_conversation(
+        &self,
+        conversation_id: Uuid,
+        key_salt: &str,
+        key_check: &str,
+        seal: impl Fn(&str) -> Result<String, AppError>,
+    ) -> Result<bool, AppError> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to encrypt conversation {conversation_id}: {e}");
+            AppError::db_query("Failed to encrypt conversation", e)
+        };
+        let mut tx = self.pool.begin().await.map_err(map_err)?;
+
+        let updated = sqlx::query(
+            "UPDATE conversations
+             SET encrypted = TRUE, key_salt = $2, key_check = $3,
+                 summary = NULL, summary_message_count = 0
+             WHERE id = $1 AND NOT encrypted",
+        )
+        .bind(conversation_id)
+        .bind(key_salt)
+        .bind(key_check)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        let messages: Vec<(Uuid, String, Option<Vec<u8>>)> = sqlx::query_as(
+            "SELECT id, content, content_compressed FROM messages
+             WHERE conversation_id = $1 FOR UPDATE",
+        )
+        .bind(conversation_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        for (id, content, compressed) in messages {
+            let sealed = seal(&compression::decode(content, compressed)?)?;
+            sqlx::query(
+                "UPDATE messages SET content = $2, content_compressed = NULL WHERE id = $1",
+            )
+            .bind(id)
+            .bind(sealed)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        }
+
+        let versions: Vec<(Uuid, i32, String)> = sqlx::query_as(
+            "SELECT v.message_id, v.version, v.content
+             FROM message_versions v
+             JOIN messages m ON m.id = v.message_id
+             WHERE m.conversation_id = $1",
+        )
+        .bind(conversation_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(map_err)?;
+        for (message_id, version, content) in versions {
+            sqlx::query(
+                "UPDATE message_versions SET content = $3 WHERE message_id = $1 AND version = $2",
+            )
+            .bind(message_id)
+            .bind(version)
+            .bind(seal(&content)?)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_err)?;
+        }
+
+        tx.commit().await.map_err(map_err)?;
+        Ok(true)
+    }
+
+    /// Inserts the message and its sources in a single transaction.
+    #[instrument(level = "debug", skip_all, fields(message_id = %message.id, conversation_id = %message.conversation_id))]
     pub async fn save(&self, message: &Message) -> Result<Message, AppError> {
+        let (plain, compressed) = compression::encode(&message.content)?;
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for message {}: {e}", message.id);
+            AppError::db_query("Failed to save message", e)
+        })?;
+
         sqlx::query(
-            "INSERT INTO messages (id, conversation_id, role, content, created_at)
-             VALUES ($1, $2, $3, $4, $5)",
+            "INSERT INTO messages
+                (id, conversation_id, role, content, content_compressed, created_at, active_version,
+                 tool_name, tool_call_id, quoted_message_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
         )
-        .bind(&message.id)
-        .bind(&message.conversation_id)
+        .bind(message.id)
+        .bind(message.conversation_id)
         .bind(message.role.as_str())
-        .bind(&message.content)
+        .bind(plain)
+        .bind(compressed)
         .bind(message.created_at)
-        .execute(&self.pool)
+        .bind(message.active_version)
+        .bind(&message.tool_name)
+        .bind(&message.tool_call_id)
+        .bind(message.quoted_message_id)
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             error!("Failed to save message {}: {e}", message.id);
             AppError::db_query("Failed to save message", e)
         })?;
+
+        insert_sources(&mut tx, message.id, message.active_version, &message.sources).await?;
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit message {}: {e}", message.id);
+            AppError::db_query("Failed to save message", e)
+        })?;
         Ok(message.clone())
     }
 }
+
+/// Inserts `sources` for one version of a message, numbered from 1.
+async fn insert_sources(
+    tx: &mut Transaction<'_, Postgres>,
+    message_id: Uuid,
+    version: i32,
+    sources: &[Source],
+) -> Result<(), AppError> {
+    for (position, source) in sources.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO message_sources (message_id, version, position, url, title, snippet, score)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(message_id)
+        .bind(version)
+        .bind(position as i32 + 1)
+        .bind(&source.url)
+        .bind(&source.title)
+        .bind(&source.snippet)
+        .bind(source.score)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to save source for message {message_id}: {e}");
+            AppError::db_query("Failed to save message sources", e)
+        })?;
+    }
+    Ok(())
+}
+
+fn group_sources(rows: Vec<SourceRow>) -> HashMap<Uuid, Vec<Source>> {
+    let mut by_message: HashMap<Uuid, Vec<Source>> = HashMap::new();
+    for row in rows {
+        by_message.entry(row.message_id).or_default().push(Source {
+            url: row.url,
+            title: row.title,
+            snippet: row.snippet,
+            score: row.score,
+        });
+    }
+    by_message
+}
+
+fn message_from_row(
+    row: PgRow,
+    sources: &mut HashMap<Uuid, Vec<Source>>,
+) -> Result<Message, AppError> {
+    let role_str: String = row.try_get("role")
+        .map_err(|e| AppError::db_query("Failed to read role", e))?;
+    let role = MessageRole::try_from(role_str)
+        .map_err(|e| AppError::Unexpected(format!("Unknown message role: {e}")))?;
+    let id: Uuid = row.try_get("id")
+        .map_err(|e| AppError::db_query("Failed to read id", e))?;
+    Ok(Message {
+        sources: sources.remove(&id).unwrap_or_default(),
+        id,
+        conversation_id: row.try_get("conversation_id")
+            .map_err(|e| AppError::db_query("Failed to read conversation_id", e))?,
+        role,
+        content: compression::decode(
+            row.try_get("content")
+                .map_err(|e| AppError::db_query("Failed to read content", e))?,
+            row.try_get("content_compressed")
+                .map_err(|e| AppError::db_query("Failed to read content_compressed", e))?,
+        )?,
+        created_at: row.try_get("created_at")
+            .map_err(|e| AppError::db_query("Failed to read created_at", e))?,
+        active_version: row.try_get("active_version")
+            .map_err(|e| AppError::db_query("Failed to read active_version", e))?,
+        version_count: row.try_get("version_count")
+            .map_err(|e| AppError::db_query("Failed to read version_count", e))?,
+        tool_name: row.try_get("tool_name")
+            .map_err(|e| AppError::db_query("Failed to read tool_name", e))?,
+        tool_call_id: row.try_get("tool_call_id")
+            .map_err(|e| AppError::db_query("Failed to read tool_call_id", e))?,
+        starred: row.try_get("starred")
+            .map_err(|e| AppError::db_query("Failed to read starred", e))?,
+        suggestions: row.try_get("suggestions")
+            .map_err(|e| AppError::db_query("Failed to read suggestions", e))?,
+        quoted_message_id: row.try_get("quoted_message_id")
+            .map_err(|e| AppError::db_query("Failed to read quoted_message_id", e))?,
+    })
+}
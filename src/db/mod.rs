@@ -1,2 +1,20 @@
+pub mod access_log_repository;
+pub mod artifact_repository;
+pub mod batch_repository;
+pub mod collection_repository;
+pub mod compression;
 pub mod conversation_repository;
+pub mod conversation_webhook_repository;
+pub mod document_repository;
+pub mod email_message_repository;
+pub mod ephemeral_store;
+pub mod eval_repository;
+pub mod maintenance_repository;
 pub mod message_repository;
+pub mod notification_repository;
+pub mod profile_repository;
+pub mod schedule_repository;
+pub mod slack_thread_repository;
+pub mod stream_repository;
+pub mod webhook_tool_repository;
+pub mod workspace_read_repository;
@@ -0,0 +1,98 @@
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::{Notification, NotificationStatus};
+
+const COLUMNS: &str = "id, user_id, event, subject_id, summary, channel, target, status, attempts,
+                       last_error, created_at, delivered_at";
+
+#[derive(Clone)]
+pub struct NotificationRepository {
+    pool: PgPool,
+}
+
+impl NotificationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(notification_id = %notification.id))]
+    pub async fn save(&self, notification: &Notification) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO notifications (id, user_id, event, subject_id, summary, channel, target,
+                                        status, attempts, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(notification.id)
+        .bind(&notification.user_id)
+        .bind(notification.event.as_str())
+        .bind(notification.subject_id)
+        .bind(&notification.summary)
+        .bind(notification.channel.as_str())
+        .bind(&notification.target)
+        .bind(notification.status.as_str())
+        .bind(notification.attempts)
+        .bind(notification.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save notification {}: {e}", notification.id);
+            AppError::db_query("Failed to save notification", e)
+        })?;
+        Ok(())
+    }
+
+    /// Records one more delivery attempt and where it left the notification;
+    /// `error` is why it failed.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn record_attempt(
+        &self,
+        id: Uuid,
+        status: NotificationStatus,
+        error: Option<&str>,
+    ) -> Result<(), AppError> {
+        let delivered_at = (status == NotificationStatus::Delivered).then(Utc::now);
+        sqlx::query(
+            "UPDATE notifications
+             SET status = $1, attempts = attempts + 1, last_error = $2, delivered_at = $3
+             WHERE id = $4",
+        )
+        .bind(status.as_str())
+        .bind(error)
+        .bind(delivered_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to record delivery of notification {id}: {e}");
+            AppError::db_query("Failed to update notification", e)
+        })?;
+        Ok(())
+    }
+
+    /// The user's latest `limit` notifications, newest first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_recent(
+        &self,
+        user_id: &str,
+        limit: i64,
+    ) -> Result<Vec<Notification>, AppError> {
+        sqlx::query_as::<_, Notification>(&format!(
+            "SELECT {COLUMNS} FROM notifications
+             WHERE user_id = $1
+             ORDER BY created_at DESC
+             LIMIT $2"
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list notifications of {user_id}: {e}");
+            AppError::db_query("Failed to list notifications", e)
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+
+use crate::db::conversation_repository::escape_like;
+use crate::errors::AppError;
+use crate::models::{PromptHistoryEntry, UserProfile};
+
+/// Prompts kept per user; the least recently used go first.
+const MAX_PROMPTS_PER_USER: i64 = 1000;
+
+#[derive(Clone)]
+pub struct ProfileRepository {
+    pool: PgPool,
+}
+
+impl ProfileRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find(&self, user_id: &str) -> Result<Option<UserProfile>, AppError> {
+        sqlx::query_as::<_, UserProfile>(
+            "SELECT user_id, display_name, preferred_model, temperature, theme,
+                    custom_instructions, notify_webhook_url, notify_email, conversation_sort,
+                    updated_at
+             FROM user_profiles WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find profile {user_id}: {e}");
+            AppError::db_query(format!("Failed to find profile {user_id}"), e)
+        })
+    }
+
+    /// Inserts or replaces the whole profile.
+    #[instrument(level = "debug", skip_all, fields(user_id = %profile.user_id))]
+    pub async fn save(&self, profile: &UserProfile) -> Result<UserProfile, AppError> {
+        sqlx::query_as::<_, UserProfile>(
+            "INSERT INTO user_profiles (user_id, display_name, preferred_model, temperature,
+                                        theme, custom_instructions, notify_webhook_url,
+                                        notify_email, conversation_sort, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             ON CONFLICT (user_id) DO UPDATE SET
+                display_name = EXCLUDED.display_name,
+                preferred_model = EXCLUDED.preferred_model,
+                temperature = EXCLUDED.temperature,
+                theme = EXCLUDED.theme,
+                custom_instructions = EXCLUDED.custom_instructions,
+                notify_webhook_url = EXCLUDED.notify_webhook_url,
+                notify_email = EXCLUDED.notify_email,
+                conversation_sort = EXCLUDED.conversation_sort,
+                updated_at = EXCLUDED.updated_at
+             RETURNING user_id, display_name, preferred_model, temperature, theme,
+                       custom_instructions, notify_webhook_url, notify_email,
+                       conversation_sort, updated_at",
+        )
+        .bind(&profile.user_id)
+        .bind(&profile.display_name)
+        .bind(&profile.preferred_model)
+        .bind(profile.temperature)
+        .bind(&profile.theme)
+        .bind(&profile.custom_instructions)
+        .bind(&profile.notify_webhook_url)
+        .bind(&profile.notify_email)
+        .bind(profile.conversation_sort.as_str())
+        .bind(profile.updated_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save profile {}: {e}", profile.user_id);
+            AppError::db_query("Failed to save profile", e)
+        })
+    }
+
+    /// Adds `prompt` to the user's history, or marks it used again.
+    #[instrument(level = "debug", skip(self, prompt))]
+    pub async fn record_prompt(&self, user_id: &str, prompt: &str) -> Result<(), AppError> {
+        let map_err = |e: sqlx::Error| {
+            error!("Failed to record prompt for {user_id}: {e}");
+            AppError::db_query("Failed to record prompt", e)
+        };
+        sqlx::query(
+            "INSERT INTO prompt_history (user_id, prompt) VALUES ($1, $2)
+             ON CONFLICT (user_id, md5(prompt)) DO UPDATE SET
+                uses = prompt_history.uses + 1,
+                last_used_at = NOW()",
+        )
+        .bind(user_id)
+        .bind(prompt)
+        .execute(&self.pool)
+        .await
+        .map_err(map_err)?;
+        sqlx::query(
+            "DELETE FROM prompt_history
+             WHERE user_id = $1 AND last_used_at < (
+                 SELECT last_used_at FROM prompt_history WHERE user_id = $1
+                 ORDER BY last_used_at DESC OFFSET $2 LIMIT 1)",
+        )
+        .bind(user_id)
+        .bind(MAX_PROMPTS_PER_USER - 1)
+        .execute(&self.pool)
+        .await
+        .map_err(map_err)?;
+        Ok(())
+    }
+
+    /// The user's prompts containing `query` (all of them when empty), most
+    /// recently used first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_prompts(
+        &self,
+        user_id: &str,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<PromptHistoryEntry>, AppError> {
+        sqlx::query_as::<_, PromptHistoryEntry>(
+            "SELECT prompt, uses, last_used_at FROM prompt_history
+             WHERE user_id = $1 AND prompt ILIKE $2
+             ORDER BY last_used_at DESC
+             LIMIT $3",
+        )
+        .bind(user_id)
+        .bind(format!("%{}%", escape_like(query)))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list prompts of {user_id}: {e}");
+            AppError::db_query("Failed to list prompts", e)
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::Schedule;
+
+const COLUMNS: &str =
+    "id, conversation_id, cron, prompt, model, next_run_at, last_run_at, last_error, created_at";
+
+#[derive(Clone)]
+pub struct ScheduleRepository {
+    pool: PgPool,
+}
+
+impl ScheduleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(schedule_id = %schedule.id))]
+    pub async fn save(&self, schedule: &Schedule) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO schedules (id, conversation_id, cron, prompt, model, next_run_at,
+                                    created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(schedule.id)
+        .bind(schedule.conversation_id)
+        .bind(&schedule.cron)
+        .bind(&schedule.prompt)
+        .bind(&schedule.model)
+        .bind(schedule.next_run_at)
+        .bind(schedule.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save schedule {}: {e}", schedule.id);
+            AppError::db_query("Failed to save schedule", e)
+        })?;
+        Ok(())
+    }
+
+    /// Every schedule, or only those posting to `conversation_id`, soonest
+    /// first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_all(&self, conversation_id: Option<Uuid>) -> Result<Vec<Schedule>, AppError> {
+        sqlx::query_as::<_, Schedule>(&format!(
+            "SELECT {COLUMNS} FROM schedules
+             WHERE $1::UUID IS NULL OR conversation_id = $1
+             ORDER BY next_run_at"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list schedules: {e}");
+            AppError::db_query("Failed to list schedules", e)
+        })
+    }
+
+    /// Schedules whose next run is at or before `now`.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_due(&self, now: DateTime<Utc>) -> Result<Vec<Schedule>, AppError> {
+        sqlx::query_as::<_, Schedule>(&format!(
+            "SELECT {COLUMNS} FROM schedules WHERE next_run_at <= $1 ORDER BY next_run_at"
+        ))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find due schedules: {e}");
+            AppError::db_query("Failed to find due schedules", e)
+        })
+    }
+
+    /// Takes the run of `schedule` due at its `next_run_at`, moving the next
+    /// one to `next_run_at`. Returns `false` when another server took it first.
+    #[instrument(level = "debug", skip_all, fields(schedule_id = %schedule.id))]
+    pub async fn claim(
+        &self,
+        schedule: &Schedule,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "UPDATE schedules SET next_run_at = $1, last_run_at = $2
+             WHERE id = $3 AND next_run_at = $4",
+        )
+        .bind(next_run_at)
+        .bind(Utc::now())
+        .bind(schedule.id)
+        .bind(schedule.next_run_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to claim schedule {}: {e}", schedule.id);
+            AppError::db_query("Failed to update schedule", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records why the last run failed, or (with `None`) that it succeeded.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn update_last_error(&self, id: Uuid, error: Option<&str>) -> Result<(), AppError> {
+        sqlx::query("UPDATE schedules SET last_error = $1 WHERE id = $2")
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to record outcome of schedule {id}: {e}");
+                AppError::db_query("Failed to update schedule", e)
+            })?;
+        Ok(())
+    }
+
+    /// Returns `false` when there was no such schedule.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn delete(&self, id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM schedules WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete schedule {id}: {e}");
+                AppError::db_query(format!("Failed to delete schedule {id}"), e)
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+}
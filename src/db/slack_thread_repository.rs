@@ -0,0 +1,59 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Which conversation each Slack thread is relayed to.
+#[derive(Clone)]
+pub struct SlackThreadRepository {
+    pool: PgPool,
+}
+
+impl SlackThreadRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// The conversation of the thread starting at `thread_ts` in `channel`.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find(&self, channel: &str, thread_ts: &str) -> Result<Option<Uuid>, AppError> {
+        sqlx::query_scalar::<_, Uuid>(
+            "SELECT conversation_id FROM slack_threads WHERE channel = $1 AND thread_ts = $2",
+        )
+        .bind(channel)
+        .bind(thread_ts)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to find Slack thread {channel}/{thread_ts}: {e}");
+            AppError::db_query("Failed to find Slack thread", e)
+        })
+    }
+
+    /// Relays the thread to `conversation_id` from now on. A thread already
+    /// relayed elsewhere is left alone.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn save(
+        &self,
+        channel: &str,
+        thread_ts: &str,
+        conversation_id: Uuid,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO slack_threads (channel, thread_ts, conversation_id)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (channel, thread_ts) DO NOTHING",
+        )
+        .bind(channel)
+        .bind(thread_ts)
+        .bind(conversation_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save Slack thread {channel}/{thread_ts}: {e}");
+            AppError::db_query("Failed to save Slack thread", e)
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::ActiveStream;
+
+/// Turns older than this without an update were left by a server that went
+/// away mid-turn.
+const STALE_AFTER_SECS: f64 = 300.0;
+
+/// Turns streaming on any server, for watchers to catch up from.
+#[derive(Clone)]
+pub struct StreamRepository {
+    pool: PgPool,
+}
+
+impl StreamRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Records a turn that just started; its content starts empty.
+    #[instrument(level = "debug", skip(self, stream), fields(stream_id = %stream.stream_id))]
+    pub async fn start(&self, stream: &ActiveStream) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO active_streams
+                 (socket_id, stream_id, conversation_id, node_id, start_event, content)
+             VALUES ($1, $2, $3, $4, $5, '')
+             ON CONFLICT (socket_id, stream_id) DO UPDATE
+             SET conversation_id = $3, node_id = $4, start_event = $5, content = '',
+                 updated_at = NOW()",
+        )
+        .bind(&stream.socket_id)
+        .bind(&stream.stream_id)
+        .bind(stream.conversation_id)
+        .bind(&stream.node_id)
+        .bind(&stream.start_event)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to record stream {}: {e}", stream.stream_id);
+            AppError::db_query(format!("Failed to record stream {}", stream.stream_id), e)
+        })?;
+        Ok(())
+    }
+
+    /// Adds `text` to the end of a turn's reply.
+    #[instrument(level = "debug", skip(self, text))]
+    pub async fn append(
+        &self,
+        socket_id: &str,
+        stream_id: &str,
+        text: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE active_streams SET content = content || $3, updated_at = NOW()
+             WHERE socket_id = $1 AND stream_id = $2",
+        )
+        .bind(socket_id)
+        .bind(stream_id)
+        .bind(text)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to extend stream {stream_id}: {e}");
+            AppError::db_query(format!("Failed to extend stream {stream_id}"), e)
+        })?;
+        Ok(())
+    }
+
+    /// Forgets a turn that ended.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn finish(&self, socket_id: &str, stream_id: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM active_streams WHERE socket_id = $1 AND stream_id = $2")
+            .bind(socket_id)
+            .bind(stream_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to remove stream {stream_id}: {e}");
+                AppError::db_query(format!("Failed to remove stream {stream_id}"), e)
+            })?;
+        Ok(())
+    }
+
+    /// The conversation's turns still streaming.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_by_conversation(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ActiveStream>, AppError> {
+        sqlx::query_as::<_, ActiveStream>(
+            "SELECT socket_id, stream_id, conversation_id, node_id, start_event, content
+             FROM active_streams
+             WHERE conversation_id = $1 AND updated_at > NOW() - make_interval(secs => $2)",
+        )
+        .bind(conversation_id)
+        .bind(STALE_AFTER_SECS)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch streams of conversation {conversation_id}: {e}");
+            AppError::db_query(format!("Failed to fetch streams of {conversation_id}"), e)
+        })
+    }
+}
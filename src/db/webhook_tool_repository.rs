@@ -0,0 +1,118 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::WebhookTool;
+
+const COLUMNS: &str =
+    "id, conversation_id, name, description, parameters, url, auth_header, created_at";
+
+/// A `webhook_tools` row; `parameters` is stored as JSON text.
+#[derive(sqlx::FromRow)]
+struct WebhookToolRow {
+    id: Uuid,
+    conversation_id: Option<Uuid>,
+    name: String,
+    description: String,
+    parameters: String,
+    url: String,
+    auth_header: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookToolRow> for WebhookTool {
+    type Error = AppError;
+
+    fn try_from(row: WebhookToolRow) -> Result<Self, AppError> {
+        let parameters = serde_json::from_str(&row.parameters).map_err(|e| {
+            AppError::Unexpected(format!("Unreadable schema of webhook tool {}: {e}", row.id))
+        })?;
+        Ok(WebhookTool {
+            id: row.id,
+            conversation_id: row.conversation_id,
+            name: row.name,
+            description: row.description,
+            parameters,
+            url: row.url,
+            auth_header: row.auth_header,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct WebhookToolRepository {
+    pool: PgPool,
+}
+
+impl WebhookToolRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Inserts `tool`. Returns `false`, saving nothing, when its scope already
+    /// has a tool of the same name.
+    #[instrument(level = "debug", skip_all, fields(name = %tool.name))]
+    pub async fn save(&self, tool: &WebhookTool) -> Result<bool, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO webhook_tools (id, conversation_id, name, description, parameters, url,
+                                        auth_header, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(tool.id)
+        .bind(tool.conversation_id)
+        .bind(&tool.name)
+        .bind(&tool.description)
+        .bind(tool.parameters.to_string())
+        .bind(&tool.url)
+        .bind(&tool.auth_header)
+        .bind(tool.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to save webhook tool {}: {e}", tool.name);
+            AppError::db_query("Failed to save webhook tool", e)
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The global tools, plus those of `conversation_id` when given; a
+    /// conversation's tool hides a global one of the same name. Sorted by name.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_visible(
+        &self,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<WebhookTool>, AppError> {
+        let rows = sqlx::query_as::<_, WebhookToolRow>(&format!(
+            "SELECT DISTINCT ON (name) {COLUMNS}
+             FROM webhook_tools
+             WHERE conversation_id IS NULL OR conversation_id = $1
+             ORDER BY name, conversation_id NULLS LAST"
+        ))
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list webhook tools: {e}");
+            AppError::db_query("Failed to list webhook tools", e)
+        })?;
+        rows.into_iter().map(WebhookTool::try_from).collect()
+    }
+
+    /// Returns `false` when there was no such tool.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn delete(&self, id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query("DELETE FROM webhook_tools WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete webhook tool {id}: {e}");
+                AppError::db_query(format!("Failed to delete webhook tool {id}"), e)
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,53 @@
+use sqlx::PgPool;
+use tracing::{error, instrument};
+
+use crate::errors::AppError;
+use crate::models::WorkspaceFileRead;
+
+const COLUMNS: &str = "id, conversation_id, path, bytes, created_at";
+
+#[derive(Clone)]
+pub struct WorkspaceReadRepository {
+    pool: PgPool,
+}
+
+impl WorkspaceReadRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(level = "debug", skip_all, fields(path = %read.path))]
+    pub async fn save(&self, read: &WorkspaceFileRead) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO workspace_file_reads (id, conversation_id, path, bytes, created_at)
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(read.id)
+        .bind(read.conversation_id)
+        .bind(&read.path)
+        .bind(read.bytes)
+        .bind(read.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to log read of workspace file {}: {e}", read.path);
+            AppError::db_query("Failed to save workspace file read", e)
+        })?;
+        Ok(())
+    }
+
+    /// The latest `limit` reads, newest first.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn find_recent(&self, limit: i64) -> Result<Vec<WorkspaceFileRead>, AppError> {
+        sqlx::query_as::<_, WorkspaceFileRead>(&format!(
+            "SELECT {COLUMNS} FROM workspace_file_reads ORDER BY created_at DESC LIMIT $1"
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to list workspace file reads: {e}");
+            AppError::db_query("Failed to list workspace file reads", e)
+        })
+    }
+}
@@ -1,4 +1,8 @@
+use axum::http::StatusCode;
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::ErrorCode;
 
 /// Top-level application error — mirrors the Kotlin `AppError` sealed interface.
 /// All variants carry a human-readable message for display/logging.
@@ -26,9 +30,27 @@ pub enum AppError {
     #[error("Model '{model_name}' not found in Ollama")]
     ModelNotFound { model_name: String },
 
+    #[error("Failed to pull model '{model_name}': {message}")]
+    ModelPullFailed { model_name: String, message: String },
+
     #[error("Inference error: {message}")]
     InferenceError { message: String },
 
+    #[error("Ollama is busy: generation queue is full ({max_queued} waiting)")]
+    GenerationQueueFull { max_queued: usize },
+
+    #[error("Generation '{id}' was cancelled by an administrator")]
+    GenerationCancelled { id: String },
+
+    #[error("The client disconnected before the reply was ready")]
+    ClientDisconnected,
+
+    #[error("Model '{model_name}' ran out of context or memory: {message}")]
+    ContextTooLarge { model_name: String, message: String },
+
+    #[error("Tool '{tool_name}' failed: {message}")]
+    ToolFailed { tool_name: String, message: String },
+
     // ── Validation errors ────────────────────────────────────────────────────
     #[error("Field '{field_name}' cannot be empty")]
     EmptyField { field_name: String },
@@ -36,11 +58,39 @@ pub enum AppError {
     #[error("Field '{field_name}' exceeds max length of {max_length} (actual: {actual_length})")]
     FieldTooLong { field_name: String, max_length: usize, actual_length: usize },
 
+    #[error("Field '{field_name}' is invalid: {message}")]
+    InvalidField { field_name: String, message: String },
+
+    #[error("Message '{id}' is not an assistant reply and cannot be regenerated")]
+    NotAnAssistantMessage { id: Uuid },
+
+    #[error("Conversation '{id}' has no unanswered message to retry")]
+    NothingToRetry { id: Uuid },
+
+    #[error("Message blocked by content moderation: {reason}")]
+    ModerationBlocked { reason: String },
+
     // ── Conversation errors ──────────────────────────────────────────────────
     #[error("Conversation '{id}' not found")]
-    ConversationNotFound { id: String },
+    ConversationNotFound { id: Uuid },
+
+    #[error("Conversation '{id}' is encrypted and locked; unlock it with its passphrase first")]
+    ConversationLocked { id: Uuid },
+
+    #[error("Wrong passphrase for conversation '{id}'")]
+    WrongPassphrase { id: Uuid },
+
+    // ── Request limits ───────────────────────────────────────────────────────
+    #[error("Request timed out after {timeout_secs}s")]
+    RequestTimeout { timeout_secs: u64 },
+
+    #[error("Server is busy: {max_concurrent} '{route_group}' requests already in progress")]
+    TooManyRequests { route_group: String, max_concurrent: usize },
 
     // ── System errors ────────────────────────────────────────────────────────
+    #[error("Invalid configuration: {message}")]
+    InvalidConfig { message: String },
+
     #[error("Unexpected error: {0}")]
     Unexpected(String),
 }
@@ -50,15 +100,206 @@ impl AppError {
         AppError::DatabaseQueryFailed { message: message.into(), source }
     }
 
+    /// The HTTP status REST handlers answer this error with.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::EmptyField { .. }
+            | AppError::FieldTooLong { .. }
+            | AppError::InvalidField { .. }
+            | AppError::NotAnAssistantMessage { .. }
+            | AppError::NothingToRetry { .. }
+            | AppError::ModerationBlocked { .. }
+            | AppError::WrongPassphrase { .. } => StatusCode::BAD_REQUEST,
+            AppError::RecordNotFound { .. } | AppError::ConversationNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::OllamaUnavailable { .. }
+            | AppError::GenerationQueueFull { .. }
+            | AppError::TooManyRequests { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ConversationLocked { .. } => StatusCode::LOCKED,
+            AppError::RequestTimeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            AppError::DatabaseConnectionFailed(_)
+            | AppError::DatabaseQueryFailed { .. }
+            | AppError::ModelNotFound { .. }
+            | AppError::ModelPullFailed { .. }
+            | AppError::InferenceError { .. }
+            | AppError::ContextTooLarge { .. }
+            | AppError::GenerationCancelled { .. }
+            | AppError::ClientDisconnected
+            | AppError::ToolFailed { .. }
+            | AppError::InvalidConfig { .. }
+            | AppError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The code clients see for this error, serialized as a stable
+    /// snake_case string (see [`ErrorCode`]).
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::EmptyField { .. }
+            | AppError::FieldTooLong { .. }
+            | AppError::InvalidField { .. }
+            | AppError::NotAnAssistantMessage { .. }
+            | AppError::NothingToRetry { .. } => ErrorCode::InvalidRequest,
+            AppError::RecordNotFound { .. } | AppError::ConversationNotFound { .. } => {
+                ErrorCode::NotFound
+            }
+            AppError::ConversationLocked { .. } => ErrorCode::ConversationLocked,
+            AppError::WrongPassphrase { .. } => ErrorCode::WrongPassphrase,
+            AppError::ModerationBlocked { .. } => ErrorCode::ModerationBlocked,
+            AppError::ModelNotFound { .. } => ErrorCode::ModelNotFound,
+            AppError::OllamaUnavailable { .. } => ErrorCode::OllamaUnavailable,
+            AppError::GenerationQueueFull { .. } => ErrorCode::QueueFull,
+            AppError::TooManyRequests { .. } => ErrorCode::Overloaded,
+            AppError::RequestTimeout { .. } => ErrorCode::Timeout,
+            AppError::GenerationCancelled { .. } | AppError::ClientDisconnected => {
+                ErrorCode::Cancelled
+            }
+            AppError::InferenceError { .. } | AppError::ContextTooLarge { .. } => {
+                ErrorCode::InferenceFailed
+            }
+            AppError::InvalidConfig { .. } => ErrorCode::InvalidConfig,
+            AppError::DatabaseConnectionFailed(_)
+            | AppError::DatabaseQueryFailed { .. }
+            | AppError::ModelPullFailed { .. }
+            | AppError::ToolFailed { .. }
+            | AppError::Unexpected(_) => ErrorCode::Internal,
+        }
+    }
+
     pub fn is_not_found(&self) -> bool {
         matches!(self, AppError::ConversationNotFound { .. } | AppError::RecordNotFound { .. })
     }
 
+    /// The request itself was at fault: a field failed validation, or the
+    /// message was refused.
     pub fn is_validation(&self) -> bool {
-        matches!(self, AppError::EmptyField { .. } | AppError::FieldTooLong { .. })
+        self.status_code() == StatusCode::BAD_REQUEST
+    }
+
+    pub fn is_locked(&self) -> bool {
+        matches!(self, AppError::ConversationLocked { .. })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_error_maps_to_its_status_and_code() {
+        let id = Uuid::nil();
+        let text = || "x".to_string();
+        let table = [
+            (
+                AppError::DatabaseConnectionFailed(sqlx::Error::PoolTimedOut),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+            (
+                AppError::db_query("x", sqlx::Error::RowNotFound),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+            (
+                AppError::RecordNotFound { entity_type: text(), id: text() },
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+            ),
+            (
+                AppError::OllamaUnavailable { host: text() },
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::OllamaUnavailable,
+            ),
+            (
+                AppError::ModelNotFound { model_name: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::ModelNotFound,
+            ),
+            (
+                AppError::ModelPullFailed { model_name: text(), message: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+            (
+                AppError::InferenceError { message: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InferenceFailed,
+            ),
+            (
+                AppError::GenerationQueueFull { max_queued: 1 },
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::QueueFull,
+            ),
+            (
+                AppError::GenerationCancelled { id: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Cancelled,
+            ),
+            (AppError::ClientDisconnected, StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Cancelled),
+            (
+                AppError::ContextTooLarge { model_name: text(), message: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InferenceFailed,
+            ),
+            (
+                AppError::ToolFailed { tool_name: text(), message: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+            ),
+            (
+                AppError::EmptyField { field_name: text() },
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+            ),
+            (
+                AppError::FieldTooLong { field_name: text(), max_length: 1, actual_length: 2 },
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+            ),
+            (
+                AppError::InvalidField { field_name: text(), message: text() },
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+            ),
+            (
+                AppError::NotAnAssistantMessage { id },
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+            ),
+            (AppError::NothingToRetry { id }, StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+            (
+                AppError::ModerationBlocked { reason: text() },
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ModerationBlocked,
+            ),
+            (AppError::ConversationNotFound { id }, StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            (
+                AppError::ConversationLocked { id },
+                StatusCode::LOCKED,
+                ErrorCode::ConversationLocked,
+            ),
+            (AppError::WrongPassphrase { id }, StatusCode::BAD_REQUEST, ErrorCode::WrongPassphrase),
+            (
+                AppError::RequestTimeout { timeout_secs: 1 },
+                StatusCode::REQUEST_TIMEOUT,
+                ErrorCode::Timeout,
+            ),
+            (
+                AppError::TooManyRequests { route_group: text(), max_concurrent: 1 },
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::Overloaded,
+            ),
+            (
+                AppError::InvalidConfig { message: text() },
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InvalidConfig,
+            ),
+            (AppError::Unexpected(text()), StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal),
+        ];
 
-    pub fn is_agent_unavailable(&self) -> bool {
-        matches!(self, AppError::OllamaUnavailable { .. })
+        for (error, status, code) in table {
+            assert_eq!((error.status_code(), error.code()), (status, code), "{error:?}");
+        }
     }
 }
@@ -0,0 +1,61 @@
+//! An event bus within this process, for running a single server.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::BoxFuture;
+use futures_util::stream;
+use futures_util::{FutureExt, StreamExt};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::errors::AppError;
+use crate::events::{check_size, EventBus, EventStream};
+
+/// Events a slow subscriber may fall behind by before it misses some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Default)]
+pub struct MemoryEventBus {
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<String>>>>,
+}
+
+impl MemoryEventBus {
+    fn sender(&self, channel: &str) -> broadcast::Sender<String> {
+        let mut channels = self.channels.lock().expect("event bus poisoned");
+        channels
+            .entry(channel.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .clone()
+    }
+}
+
+impl EventBus for MemoryEventBus {
+    fn publish<'a>(
+        &'a self,
+        channel: &'a str,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        async move {
+            check_size(payload)?;
+            // Fails only when nobody is subscribed.
+            let _ = self.sender(channel).send(payload.to_string());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn subscribe<'a>(&'a self, channel: &'a str) -> BoxFuture<'a, Result<EventStream, AppError>> {
+        let rx = self.sender(channel).subscribe();
+        let events = stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(payload) => return Some((payload, rx)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        async move { Ok(events.boxed()) }.boxed()
+    }
+}
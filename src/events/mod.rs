@@ -0,0 +1,61 @@
+//! Publish/subscribe between the server's live subsystems, such as watched
+//! generations, picked by `event_bus` in the configuration: in-process for a
+//! single server, or Postgres `LISTEN`/`NOTIFY` so replicas behind a load
+//! balancer see each other's events.
+
+pub mod memory;
+pub mod postgres;
+
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use sqlx::PgPool;
+
+use crate::config::EventBusConfig;
+use crate::errors::AppError;
+use crate::events::memory::MemoryEventBus;
+use crate::events::postgres::PostgresEventBus;
+
+/// Largest event payload, in bytes. Postgres refuses larger notifications;
+/// every bus enforces it so behaviour does not depend on the backend.
+pub const MAX_EVENT_BYTES: usize = 7999;
+
+/// Payloads published on a channel, in the order they were published.
+pub type EventStream = BoxStream<'static, String>;
+
+/// Named channels of text (usually JSON) events. Every subscriber of a
+/// channel, on this server or another, gets every event published on it
+/// after it subscribed. Delivery is best effort: a subscriber that falls far
+/// behind or loses its connection misses events.
+pub trait EventBus: Send + Sync {
+    /// Sends `payload` to the subscribers of `channel`. Fails if it is longer
+    /// than [`MAX_EVENT_BYTES`].
+    fn publish<'a>(
+        &'a self,
+        channel: &'a str,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Events published on `channel` from now on.
+    fn subscribe<'a>(&'a self, channel: &'a str) -> BoxFuture<'a, Result<EventStream, AppError>>;
+}
+
+/// Opens the bus `config` describes; the Postgres one shares `pool`.
+pub fn from_config(config: &EventBusConfig, pool: PgPool) -> Arc<dyn EventBus> {
+    match config {
+        EventBusConfig::Memory => Arc::new(MemoryEventBus::default()),
+        EventBusConfig::Postgres => Arc::new(PostgresEventBus::new(pool)),
+    }
+}
+
+fn check_size(payload: &str) -> Result<(), AppError> {
+    if payload.len() > MAX_EVENT_BYTES {
+        return Err(AppError::FieldTooLong {
+            field_name: "event".to_string(),
+            max_length: MAX_EVENT_BYTES,
+            actual_length: payload.len(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! An event bus over Postgres `LISTEN`/`NOTIFY`, shared by every server on
+//! the same database.
+
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, StreamExt};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::errors::AppError;
+use crate::events::{check_size, EventBus, EventStream};
+
+#[derive(Clone)]
+pub struct PostgresEventBus {
+    pool: PgPool,
+}
+
+impl PostgresEventBus {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl EventBus for PostgresEventBus {
+    fn publish<'a>(
+        &'a self,
+        channel: &'a str,
+        payload: &'a str,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        async move {
+            check_size(payload)?;
+            sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(channel)
+                .bind(payload)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| AppError::db_query(format!("Failed to publish on '{channel}'"), e))?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Holds a connection of its own for as long as the stream lives. The
+    /// listener reconnects by itself; events sent meanwhile are lost.
+    fn subscribe<'a>(&'a self, channel: &'a str) -> BoxFuture<'a, Result<EventStream, AppError>> {
+        async move {
+            let mut listener = PgListener::connect_with(&self.pool)
+                .await
+                .map_err(AppError::DatabaseConnectionFailed)?;
+            listener
+                .listen(channel)
+                .await
+                .map_err(|e| AppError::db_query(format!("Failed to listen on '{channel}'"), e))?;
+            let events = listener.into_stream().filter_map(|notification| async move {
+                match notification {
+                    Ok(notification) => Some(notification.payload().to_string()),
+                    Err(e) => {
+                        warn!("Lost the event bus connection: {e}");
+                        None
+                    }
+                }
+            });
+            Ok(events.boxed())
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,16 @@
+//! Chat backend: Axum routes over a Postgres-backed chat service that talks to
+//! Ollama through rig. [`app::build_router`] assembles the whole application;
+//! the binary in `main.rs` only adds configuration, logging and a listener.
+
+pub mod agent;
+pub mod app;
+pub mod config;
+pub mod db;
+pub mod errors;
+pub mod events;
+pub mod models;
+pub mod routes;
+pub mod service;
+pub mod storage;
+pub mod telemetry;
+pub mod tools;
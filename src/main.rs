@@ -1,90 +1,97 @@
-mod agent;
-mod db;
-mod errors;
-mod models;
-mod routes;
-mod service;
+use std::sync::Arc;
 
-use axum::{Router, routing::get, routing::post};
-use sqlx::postgres::PgPoolOptions;
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter};
 
-use crate::agent::OllamaAgentService;
-use crate::db::conversation_repository::ConversationRepository;
-use crate::db::message_repository::MessageRepository;
-use crate::routes::api_routes::{chat_handler, list_conversations_handler, list_messages_handler};
-use crate::routes::ws_routes::ws_chat_handler;
-use crate::service::chat_service::ChatService;
+use rust_ai_experiments::app;
+use rust_ai_experiments::config::{AppConfig, ConfigStore};
+use rust_ai_experiments::db::message_repository::MessageRepository;
+use rust_ai_experiments::telemetry::{self, OtlpConfig};
+
+/// Rows per round trip when backfilling message compression.
+const COMPRESS_BATCH_SIZE: i64 = 500;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load .env if present (development convenience)
     dotenvy::dotenv().ok();
 
-    // Initialise tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rust_ai_experiments=debug,tower_http=debug".into()),
-        )
-        .init();
-
-    // ── Database ──────────────────────────────────────────────────────────────
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set (copy .env.example to .env)");
+    // ── Configuration (config.toml + env overrides) ───────────────────────────
+    let config_path = ConfigStore::path_from_env();
+    let app_config = AppConfig::load(&config_path).expect("Failed to load configuration");
 
-    let pool = PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to PostgreSQL");
+    // `rust_ai_experiments trunk-config` prints a Trunk.toml that proxies the
+    // dev frontend to this backend, then exits.
+    if std::env::args().nth(1).as_deref() == Some("trunk-config") {
+        print!("{}", app_config.trunk_config());
+        return Ok(());
+    }
 
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .expect("Failed to run database migrations");
-
-    info!("Database connection established and migrations applied");
+    // Initialise tracing behind a reload handle so log_filter can change at
+    // runtime; spans are also exported over OTLP when OTEL_* is configured.
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&app_config.log_filter));
+    let tracer_provider = OtlpConfig::from_env().map(|c| c.tracer_provider()).transpose()?;
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracer_provider.as_ref().map(telemetry::layer))
+        .init();
+    if tracer_provider.is_some() {
+        info!("Exporting traces over OTLP");
+    }
+    let reload_log_filter = Arc::new(move |directive: &str| {
+        let filter = EnvFilter::try_new(directive).map_err(|e| e.to_string())?;
+        filter_handle.reload(filter).map_err(|e| e.to_string())
+    });
 
-    // ── Dependency wiring ─────────────────────────────────────────────────────
-    let ollama_base_url = std::env::var("OLLAMA_API_BASE_URL")
-        .unwrap_or_else(|_| "http://localhost:11434".to_string());
+    let config = ConfigStore::new(app_config, config_path, reload_log_filter);
+    if let Err(e) = config.watch() {
+        warn!("Config hot-reload disabled: {e}");
+    }
 
-    let conversation_repo = ConversationRepository::new(pool.clone());
-    let message_repo = MessageRepository::new(pool.clone());
-    let agent = OllamaAgentService::new(&ollama_base_url);
-    let chat_service = ChatService::new(conversation_repo, message_repo, agent);
+    // ── Database ──────────────────────────────────────────────────────────────
+    let database_url = config.get().database_url.clone();
+    assert!(!database_url.is_empty(), "DATABASE_URL must be set (copy .env.example to .env)");
 
-    // ── CORS (allow the Leptos frontend dev server) ───────────────────────────
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    // `rust_ai_experiments compress-messages` compresses large messages stored
+    // before compression existed, in every tenant, then exits.
+    if std::env::args().nth(1).as_deref() == Some("compress-messages") {
+        for schema in config.get().schemas() {
+            let pool = app::connect_database(&database_url, schema.as_deref())
+                .await
+                .expect("Failed to set up PostgreSQL");
+            let compressed = MessageRepository::new(pool)
+                .compress_existing(COMPRESS_BATCH_SIZE)
+                .await?;
+            let place = schema.as_deref().unwrap_or("the database");
+            info!("Compressed {compressed} stored messages in {place}");
+        }
+        return Ok(());
+    }
 
     // ── Router ────────────────────────────────────────────────────────────────
-    let app = Router::new()
-        // REST JSON API
-        .route("/api/chat", post(chat_handler))
-        .route("/api/conversations", get(list_conversations_handler))
-        .route("/api/conversations/{id}/messages", get(list_messages_handler))
-        // WebSocket — streaming chat
-        .route("/ws/chat", get(ws_chat_handler))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(chat_service);
+    let router = match config.get().tenants.clone() {
+        Some(tenants) => app::build_tenant_router(config.clone(), &tenants)
+            .await
+            .expect("Failed to set up the tenants' schemas"),
+        None => {
+            let schema = config.get().database_schema.clone();
+            let pool = app::connect_database(&database_url, schema.as_deref())
+                .await
+                .expect("Failed to set up PostgreSQL");
+            app::build_router(config.clone(), pool)
+        }
+    };
 
     // ── Listen ────────────────────────────────────────────────────────────────
-    let port: u16 = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(3000);
+    let port = config.get().port;
     let addr = format!("0.0.0.0:{port}");
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    info!("Listening on http://{addr}/");
+    // `port = 0` picks a free port; log the one we got.
+    info!("Listening on http://{}/", listener.local_addr()?);
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, router).await?;
     Ok(())
 }
@@ -1,126 +1,1147 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Shared with the frontend; see the `shared-models` crate.
+pub use shared_models::{
+    Artifact, ArtifactKind, AssistantPreset, BuildInfo, ChatRequest, ChatResponse, ChunkMode,
+    CompletionStats, Conversation, ConversationSort, ConversationStats, DiffOp, DiffSpan, ErrorBody,
+    ErrorCode, Features, FinishReason, HostStats, JsonValidation, LoadedModel, MaintenanceStatus,
+    Message, MessageRole, ModelInfo, ModelUsage, ModelsResponse, PromptHistoryEntry, PullProgress,
+    ResponseFormat, RetrievalFeedback, RetrievedChunk, ServerConfig, ServerVersion, Source,
+    StarredMessage, SummaryBlock, SystemStatus, TimelineEntry, TokenSpan, TokenizeResponse,
+    ToolCallStatus, UserProfile, Verbosity, VersionDiff, WsChatRequest, WsControl, WsEncoding,
+    WsEvent, WsFrame, BUILD_COMMIT, PROTOCOL_VERSION,
+};
 
+/// One generation of an assistant reply, as listed by
+/// `GET /api/messages/:id/versions`.
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
-pub struct Conversation {
+pub struct MessageVersion {
+    pub version: i32,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub is_active: bool,
+}
+
+/// Body of `PUT /api/messages/:id/active-version`.
+#[derive(Debug, Deserialize)]
+pub struct SetActiveVersionRequest {
+    pub version: i32,
+}
+
+/// Body of `POST /api/models/pull`.
+#[derive(Debug, Default, Deserialize)]
+pub struct PullModelRequest {
+    /// Defaults to the configured model.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Query string of `GET /api/me/prompts`.
+#[derive(Debug, Deserialize)]
+pub struct PromptHistoryQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// Query string of `GET /api/conversations/{id}/messages`.
+#[derive(Debug, Deserialize)]
+pub struct MessagesQuery {
+    /// Condense the messages the conversation's summary covers into a
+    /// summary block.
+    #[serde(default)]
+    pub summaries: bool,
+}
+
+/// Query string of `GET /api/conversations`; without `sort`, the order saved
+/// in the profile is used.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConversationQuery {
+    #[serde(default)]
+    pub sort: Option<ConversationSort>,
+}
+
+/// Query string of `GET /api/conversations/{id}/export`.
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+}
+
+/// Documents a single conversation can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// The conversation laid out for printing, replies' markdown included.
+    Pdf,
+}
+
+/// Query string of `GET /api/search`.
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// Body of `POST /api/tokenize`.
+#[derive(Debug, Deserialize)]
+pub struct TokenizeRequest {
+    pub text: String,
+    /// Defaults to the model new turns use.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Also return each token's text and byte range.
+    #[serde(default)]
+    pub boundaries: bool,
+}
+
+/// Query string of `GET /api/admin/transcripts/:id`.
+#[derive(Debug, Deserialize)]
+pub struct TranscriptQuery {
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Body of `PUT /api/admin/maintenance`.
+#[derive(Debug, Deserialize)]
+pub struct SetMaintenanceRequest {
+    pub enabled: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Body of `PATCH /api/me`. Omitted fields are left alone; `null` clears one.
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateProfileRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub preferred_model: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub temperature: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub theme: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub custom_instructions: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notify_webhook_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notify_email: Option<Option<String>>,
+    #[serde(default)]
+    pub conversation_sort: Option<ConversationSort>,
+}
+
+/// Distinguishes a field set to `null` (`Some(None)`) from one that is absent
+/// (`None`, via `#[serde(default)]`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Body of `PATCH /api/conversations/{id}`. Absent fields are left as they
+/// are; `null` clears an override.
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateConversationRequest {
+    #[serde(default, deserialize_with = "nullable")]
+    pub max_history_messages: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub icon: Option<Option<String>>,
+    /// `#rrggbb`.
+    #[serde(default, deserialize_with = "nullable")]
+    pub color: Option<Option<String>>,
+    #[serde(default)]
+    pub verbosity: Option<Verbosity>,
+    /// Id of the assistant preset to answer from now on; `null` switches back
+    /// to the default assistant. A switch is noted in the conversation.
+    #[serde(default, deserialize_with = "nullable")]
+    pub preset: Option<Option<String>>,
+    /// Stops (`true`) or resumes replaying earlier messages with each turn.
+    #[serde(default)]
+    pub stateless: Option<bool>,
+}
+
+/// Body of `POST /api/conversations/{id}/encrypt` and `.../unlock`.
+#[derive(Debug, Deserialize)]
+pub struct PassphraseRequest {
+    pub passphrase: String,
+}
+
+/// Body of `POST /api/artifacts/{id}/diff`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ArtifactDiffRequest {
+    /// The file as the user has it. When left out, the file at `path` in the
+    /// workspace is compared (a missing one as empty).
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Where the file is in the workspace; also names it in the diff, which
+    /// otherwise uses the artifact's file name.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Also write the artifact to `path` in the workspace. Refused when an
+    /// uploaded `content` no longer matches the file there.
+    #[serde(default)]
+    pub apply: bool,
+}
+
+/// Response of `POST /api/artifacts/{id}/diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactDiff {
+    pub artifact_id: Uuid,
+    pub path: String,
+    /// Unified diff turning the file into the artifact; empty if they match.
+    pub diff: String,
+    /// Whether the artifact was written to the workspace.
+    pub applied: bool,
+}
+
+// ── WebSocket message types ──────────────────────────────────────────────────
+
+/// A generation running against Ollama, as listed by `GET /api/admin/streams`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationInfo {
     pub id: String,
-    pub title: String,
+    /// `chat`, `summary` or `suggestions`.
+    pub kind: String,
+    /// Absent for background work on a transcript.
+    pub conversation_id: Option<Uuid>,
+    pub model: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    /// Tokens streamed so far; non-streamed generations report 0.
+    pub tokens: u64,
+}
+
+/// Time to first token of recent streamed generations against its SLA.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirstTokenLatency {
+    /// Absent until the window holds enough samples.
+    pub p95_ms: Option<u64>,
+    /// 0 when no SLA is configured.
+    pub threshold_ms: u64,
+    pub window_secs: u64,
+    pub samples: usize,
+    pub degraded: bool,
+}
+
+/// Body of `GET /readyz`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    /// `ready`, or `degraded` while the model answers more slowly than its SLA.
+    pub status: String,
+    pub degraded: bool,
+    pub first_token: FirstTokenLatency,
+}
+
+/// A turn streaming on some server, as stored for watchers to catch up from.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ActiveStream {
+    /// The socket the turn streams to; stream ids are only unique per socket.
+    pub socket_id: String,
+    pub stream_id: String,
+    pub conversation_id: Uuid,
+    /// The server generating the turn.
+    pub node_id: String,
+    /// Its `stream_start` event, as JSON.
+    pub start_event: String,
+    /// The reply so far.
+    pub content: String,
+}
+
+/// One conversation in a data export: `conversations/<id>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub conversation: Conversation,
+    pub messages: Vec<Message>,
+    /// Every generation of each regenerated reply, keyed by message id.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<Uuid, Vec<MessageVersion>>,
+}
+
+/// Body of `POST /api/evals`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateEvalRequest {
+    /// Model to replay the conversations against.
+    pub model: String,
+    pub conversation_ids: Vec<Uuid>,
+}
+
+/// Progress of an [`EvalRun`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvalStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl EvalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvalStatus::Running => "running",
+            EvalStatus::Completed => "completed",
+            EvalStatus::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for EvalStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "running" => Ok(EvalStatus::Running),
+            "completed" => Ok(EvalStatus::Completed),
+            "failed" => Ok(EvalStatus::Failed),
+            other => Err(format!("Unknown eval status: {other}")),
+        }
+    }
+}
+
+/// One replay of stored conversations against `model`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EvalRun {
+    pub id: Uuid,
+    pub model: String,
+    #[sqlx(try_from = "String")]
+    pub status: EvalStatus,
+    /// Why the run stopped early, when it failed.
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// The new model's answer to one stored user turn, beside the original one.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EvalResult {
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub conversation_id: Uuid,
+    /// 1-based index of the user turn within its conversation.
+    pub turn: i32,
+    pub user_message: String,
+    /// `None` when the stored turn was never answered.
+    pub original_content: Option<String>,
+    /// `None` when the new model failed; see `error`.
+    pub new_content: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Comparison of an eval run with the original replies, returned by
+/// `GET /api/evals/:id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub run: EvalRun,
+    pub summary: EvalSummary,
+    pub turns: Vec<EvalTurnReport>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalSummary {
+    pub turns: usize,
+    /// Turns whose new reply differs from the original.
+    pub changed: usize,
+    pub errors: usize,
+    pub original_words: usize,
+    pub new_words: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalTurnReport {
+    #[serde(flatten)]
+    pub result: EvalResult,
+    /// Word-level diff from the original reply to the new one.
+    pub diff: Vec<DiffSpan>,
+}
+
+/// Body of `POST /api/batch`.
+#[derive(Debug, Deserialize)]
+pub struct CreateBatchRequest {
+    /// Model for prompts that do not name their own; defaults to `model`.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub prompts: Vec<BatchPrompt>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchPrompt {
+    pub prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Progress of a [`BatchJob`] or one of its items. Jobs go from `running` to
+/// `completed`; items start out `queued` and end `completed` or `failed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl BatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchStatus::Queued => "queued",
+            BatchStatus::Running => "running",
+            BatchStatus::Completed => "completed",
+            BatchStatus::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for BatchStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "queued" => Ok(BatchStatus::Queued),
+            "running" => Ok(BatchStatus::Running),
+            "completed" => Ok(BatchStatus::Completed),
+            "failed" => Ok(BatchStatus::Failed),
+            other => Err(format!("Unknown batch status: {other}")),
+        }
+    }
+}
+
+/// A set of prompts answered in the background, returned by
+/// `GET /api/batch/:id` with its items.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BatchJob {
+    pub id: Uuid,
+    #[sqlx(try_from = "String")]
+    pub status: BatchStatus,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub items: Vec<BatchItem>,
+}
+
+/// One prompt of a batch and, once answered, the reply or error.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BatchItem {
+    /// 0-based index of the prompt in the request.
+    pub position: i32,
+    pub prompt: String,
+    pub model: String,
+    #[sqlx(try_from = "String")]
+    pub status: BatchStatus,
+    pub content: Option<String>,
+    pub error: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// An HTTP endpoint the model can call as a tool: the arguments are POSTed to
+/// `url` as JSON and the JSON response becomes the tool result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookTool {
+    pub id: Uuid,
+    /// `None` for a tool offered in every conversation.
+    pub conversation_id: Option<Uuid>,
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments.
+    pub parameters: serde_json::Value,
+    pub url: String,
+    /// Value of the `Authorization` header sent with each call. Never returned.
+    #[serde(skip)]
+    pub auth_header: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of `POST /api/tools`.
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookToolRequest {
+    /// Omit to offer the tool in every conversation.
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+    pub url: String,
+    #[serde(default)]
+    pub auth_header: Option<String>,
+}
+
+/// Query of `GET /api/tools`.
+#[derive(Debug, Deserialize)]
+pub struct WebhookToolQuery {
+    /// List the tools a turn in this conversation may call, instead of only
+    /// the global ones.
+    pub conversation_id: Option<Uuid>,
+}
+
+/// A prompt run on a cron schedule; each run's reply is posted to
+/// `conversation_id` as an assistant message.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Schedule {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    /// Cron expression, in UTC; see `service::cron`.
+    pub cron: String,
+    pub prompt: String,
+    /// Replaces the model the conversation would otherwise use.
+    pub model: Option<String>,
+    pub next_run_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Why the last run failed; cleared by the next one that succeeds.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of `POST /api/schedules`.
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduleRequest {
+    pub conversation_id: Uuid,
+    pub cron: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Query of `GET /api/schedules`.
+#[derive(Debug, Deserialize)]
+pub struct ScheduleQuery {
+    /// Only the schedules posting to this conversation.
+    pub conversation_id: Option<Uuid>,
+}
+
+/// A URL told about every assistant message saved to a conversation, with a
+/// JSON `POST` signed by `secret`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConversationWebhook {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub url: String,
+    /// Key of the HMAC-SHA256 signatures; only returned when the webhook is
+    /// registered.
+    #[serde(skip)]
+    pub secret: String,
     pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
 }
 
-impl Conversation {
-    pub fn new(id: String, title: String) -> Self {
-        let now = Utc::now();
-        Self { id, title, created_at: now, updated_at: now }
+/// Response of `POST /api/webhooks`: the webhook, with the secret its
+/// payloads are signed with.
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: ConversationWebhook,
+    pub secret: String,
+}
+
+/// Body of `POST /api/webhooks`.
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub conversation_id: Uuid,
+    pub url: String,
+}
+
+/// Query of `GET /api/webhooks`.
+#[derive(Debug, Deserialize)]
+pub struct WebhookQuery {
+    /// Only the webhooks of this conversation.
+    pub conversation_id: Option<Uuid>,
+}
+
+/// One assistant message sent to a [`ConversationWebhook`]; listed by
+/// `GET /api/webhooks/{id}/deliveries`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub message_id: Uuid,
+    #[sqlx(try_from = "String")]
+    pub status: NotificationStatus,
+    pub attempts: i32,
+    /// Why the last attempt failed.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Query of `GET /api/webhooks/{id}/deliveries`.
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveryQuery {
+    pub limit: Option<i64>,
+}
+
+/// What was done with a conversation, in its [`AccessLogEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessAction {
+    /// Its messages were read.
+    View,
+    Export,
+}
+
+impl AccessAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessAction::View => "view",
+            AccessAction::Export => "export",
+        }
+    }
+}
+
+impl TryFrom<String> for AccessAction {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "view" => Ok(AccessAction::View),
+            "export" => Ok(AccessAction::Export),
+            other => Err(format!("Unknown access action: {other}")),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "UPPERCASE")]
-pub enum MessageRole {
-    User,
-    Assistant,
-    System,
+/// One read or export of a conversation; listed by
+/// `GET /api/conversations/{id}/access-log`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AccessLogEntry {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub user_id: String,
+    #[sqlx(try_from = "String")]
+    pub action: AccessAction,
+    /// `User-Agent` of the client, when it sent one.
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Query of `GET /api/conversations/{id}/access-log` and
+/// `GET /api/admin/workspace/reads`.
+#[derive(Debug, Deserialize)]
+pub struct AccessLogQuery {
+    pub limit: Option<i64>,
+}
+
+/// A file of the workspace read by the agent's `read_file` tool; listed by
+/// `GET /api/admin/workspace/reads`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WorkspaceFileRead {
+    pub id: Uuid,
+    /// The conversation whose turn read it.
+    pub conversation_id: Uuid,
+    /// Relative to the workspace directory.
+    pub path: String,
+    pub bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a [`Notification`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// Every prompt of a batch job was answered.
+    BatchCompleted,
+    /// A batch job finished, but some of its prompts failed.
+    BatchFailed,
+    ScheduleCompleted,
+    ScheduleFailed,
 }
 
-impl MessageRole {
+impl NotificationEvent {
     pub fn as_str(&self) -> &'static str {
         match self {
-            MessageRole::User => "USER",
-            MessageRole::Assistant => "ASSISTANT",
-            MessageRole::System => "SYSTEM",
+            NotificationEvent::BatchCompleted => "batch_completed",
+            NotificationEvent::BatchFailed => "batch_failed",
+            NotificationEvent::ScheduleCompleted => "schedule_completed",
+            NotificationEvent::ScheduleFailed => "schedule_failed",
         }
     }
 }
 
-impl std::fmt::Display for MessageRole {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_str())
+impl TryFrom<String> for NotificationEvent {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "batch_completed" => Ok(NotificationEvent::BatchCompleted),
+            "batch_failed" => Ok(NotificationEvent::BatchFailed),
+            "schedule_completed" => Ok(NotificationEvent::ScheduleCompleted),
+            "schedule_failed" => Ok(NotificationEvent::ScheduleFailed),
+            other => Err(format!("Unknown notification event: {other}")),
+        }
     }
 }
 
-impl TryFrom<String> for MessageRole {
+/// Where a [`Notification`] is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Webhook,
+    Email,
+}
+
+impl NotificationChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationChannel::Webhook => "webhook",
+            NotificationChannel::Email => "email",
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationChannel {
     type Error = String;
     fn try_from(s: String) -> Result<Self, Self::Error> {
-        match s.to_uppercase().as_str() {
-            "USER" => Ok(MessageRole::User),
-            "ASSISTANT" => Ok(MessageRole::Assistant),
-            "SYSTEM" => Ok(MessageRole::System),
-            other => Err(format!("Unknown role: {other}")),
+        match s.as_str() {
+            "webhook" => Ok(NotificationChannel::Webhook),
+            "email" => Ok(NotificationChannel::Email),
+            other => Err(format!("Unknown notification channel: {other}")),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Message {
-    pub id: String,
-    pub conversation_id: String,
-    pub role: MessageRole,
-    pub content: String,
+/// Delivery of a [`Notification`] or [`WebhookDelivery`]: `pending` while
+/// attempts remain, then `delivered` or `failed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl NotificationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationStatus::Pending => "pending",
+            NotificationStatus::Delivered => "delivered",
+            NotificationStatus::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending" => Ok(NotificationStatus::Pending),
+            "delivered" => Ok(NotificationStatus::Delivered),
+            "failed" => Ok(NotificationStatus::Failed),
+            other => Err(format!("Unknown notification status: {other}")),
+        }
+    }
+}
+
+/// A finished batch job or scheduled prompt, reported to one of the user's
+/// channels; listed by `GET /api/notifications`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub user_id: String,
+    #[sqlx(try_from = "String")]
+    pub event: NotificationEvent,
+    /// The batch job or schedule it is about.
+    pub subject_id: Uuid,
+    /// One line for people, e.g. "3 of 4 prompts answered".
+    pub summary: String,
+    #[sqlx(try_from = "String")]
+    pub channel: NotificationChannel,
+    /// The webhook URL or email address.
+    pub target: String,
+    #[sqlx(try_from = "String")]
+    pub status: NotificationStatus,
+    pub attempts: i32,
+    /// Why the last attempt failed.
+    pub last_error: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
 }
 
-impl Message {
-    pub fn new(conversation_id: String, role: MessageRole, content: String) -> Self {
-        Self {
-            id: uuid::Uuid::new_v4().to_string(),
-            conversation_id,
-            role,
-            content,
-            created_at: Utc::now(),
+/// Query of `GET /api/notifications`.
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    pub limit: Option<i64>,
+}
+
+/// Body of `POST /api/documents/git`.
+#[derive(Debug, Deserialize)]
+pub struct IngestGitRequest {
+    /// An `https://` URL to clone, or the path of a repository under
+    /// `workspace_dir`.
+    pub repository: String,
+    /// Branch to clone instead of the remote's default one.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Collection to index it in; `codebase` by default.
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+/// A named set of indexed documents, searched by every conversation when
+/// `global`, otherwise by those it is attached to.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Collection {
+    pub name: String,
+    pub description: Option<String>,
+    /// Model its documents are embedded with; the configured
+    /// `embedding_model` when unset.
+    pub embedding_model: Option<String>,
+    /// How its documents are split into chunks.
+    #[sqlx(try_from = "String")]
+    pub chunker: Chunker,
+    /// Most lines per chunk; the configured `chunk_max_lines` when unset.
+    pub chunk_max_lines: Option<i32>,
+    /// Lines a `fixed` chunk repeats from the one before; the configured
+    /// `chunk_overlap` when unset.
+    pub chunk_overlap: Option<i32>,
+    pub global: bool,
+    pub created_at: DateTime<Utc>,
+    pub documents: i64,
+    pub chunks: i64,
+}
+
+/// How the documents of a [`Collection`] are split into chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Chunker {
+    /// `markdown` for markdown files, `code` for the rest.
+    #[default]
+    Auto,
+    /// At top-level definitions, found from the syntax tree in the languages
+    /// there is a parser for and from indentation in the others.
+    Code,
+    /// At headings, outside fenced code blocks.
+    Markdown,
+    /// Between sentences, never through one.
+    Sentence,
+    /// Every `chunk_max_lines` lines, each chunk repeating the last
+    /// `chunk_overlap` lines of the one before.
+    Fixed,
+}
+
+impl Chunker {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Chunker::Auto => "auto",
+            Chunker::Code => "code",
+            Chunker::Markdown => "markdown",
+            Chunker::Sentence => "sentence",
+            Chunker::Fixed => "fixed",
         }
     }
 }
 
+impl TryFrom<String> for Chunker {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "auto" => Ok(Chunker::Auto),
+            "code" => Ok(Chunker::Code),
+            "markdown" => Ok(Chunker::Markdown),
+            "sentence" => Ok(Chunker::Sentence),
+            "fixed" => Ok(Chunker::Fixed),
+            other => Err(format!("Unknown chunker: {other}")),
+        }
+    }
+}
+
+/// Body of `POST /api/collections`.
 #[derive(Debug, Deserialize)]
-pub struct ChatRequest {
-    pub conversation_id: Option<String>,
-    pub message: String,
+pub struct CreateCollectionRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub chunker: Chunker,
+    #[serde(default)]
+    pub chunk_max_lines: Option<i32>,
+    #[serde(default)]
+    pub chunk_overlap: Option<i32>,
+    #[serde(default)]
+    pub global: bool,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ChatResponse {
-    pub conversation_id: String,
-    pub message: Message,
+/// Body of `POST /api/collections/{name}/rechunk`: chunking settings to
+/// give the collection before its documents are split again. Those left
+/// out stay as they are.
+#[derive(Debug, Default, Deserialize)]
+pub struct RechunkRequest {
+    #[serde(default)]
+    pub chunker: Option<Chunker>,
+    #[serde(default)]
+    pub chunk_max_lines: Option<i32>,
+    #[serde(default)]
+    pub chunk_overlap: Option<i32>,
 }
 
-// ── WebSocket message types ──────────────────────────────────────────────────
+/// Body of `POST /api/collections/{name}/preview`: a sample document to split
+/// as the collection would, or with the settings given instead.
+#[derive(Debug, Deserialize)]
+pub struct ChunkPreviewRequest {
+    pub content: String,
+    /// Names the document's language by its extension.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub chunker: Option<Chunker>,
+    #[serde(default)]
+    pub chunk_max_lines: Option<i32>,
+    #[serde(default)]
+    pub chunk_overlap: Option<i32>,
+}
 
-/// Incoming WebSocket message from the client.
+/// One chunk of a [`ChunkPreviewRequest`]'s document.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkPreview {
+    pub content: String,
+    /// 1-based lines of the document the chunk spans, inclusive.
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Query string of `GET /api/collections/{name}/chunks`.
+#[derive(Debug, Deserialize)]
+pub struct ChunksQuery {
+    /// Only the chunks of this document.
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// An indexed chunk as listed for inspection, without its embedding.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CollectionChunk {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub source: String,
+    pub path: String,
+    pub language: Option<String>,
+    pub position: i32,
+    pub content: String,
+    pub start_line: i32,
+    pub end_line: i32,
+    /// Length of its embedding.
+    pub dimensions: Option<i32>,
+}
+
+/// Body of `POST /api/collections/{name}/search`.
 #[derive(Debug, Deserialize)]
-pub struct WsChatRequest {
-    pub conversation_id: Option<String>,
-    pub message: String,
+pub struct CollectionSearchRequest {
+    pub query: String,
+    /// Hits returned; the configured `retrieval_top_k` by default.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Override the configured `retrieval_vector_weight` and
+    /// `retrieval_text_weight` for this search, to try other weightings.
+    #[serde(default)]
+    pub vector_weight: Option<f32>,
+    #[serde(default)]
+    pub text_weight: Option<f32>,
 }
 
-/// Outgoing WebSocket events sent to the client.
+/// Share of the retrieved chunks users rated that they found relevant, in
+/// one group of [`RetrievalMetrics`].
 #[derive(Debug, Clone, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum WsEvent {
-    /// Stream is starting — includes the (possibly new) conversation id.
-    StreamStart {
-        conversation_id: String,
-    },
-    /// A single content chunk from the LLM.
-    StreamChunk {
-        content: String,
-    },
-    /// Stream finished — full message has been persisted.
-    StreamEnd {
-        message_id: String,
-        full_content: String,
-    },
-    /// Something went wrong.
-    Error {
-        message: String,
-    },
+pub struct RetrievalPrecision {
+    pub group: String,
+    pub rated: i64,
+    pub relevant: i64,
+    /// `relevant / rated`; unset before any rating.
+    pub precision: Option<f64>,
+}
+
+impl RetrievalPrecision {
+    pub fn new(group: String, rated: i64, relevant: i64) -> Self {
+        let precision = (rated > 0).then(|| relevant as f64 / rated as f64);
+        Self { group, rated, relevant, precision }
+    }
+}
+
+/// Response of `GET /api/retrieval/metrics`: retrieval precision from users'
+/// verdicts on retrieved chunks, overall and broken down.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievalMetrics {
+    pub overall: RetrievalPrecision,
+    pub by_collection: Vec<RetrievalPrecision>,
+    /// By the rankings that found the chunk: `vector`, `text` or `both`.
+    pub by_ranking: Vec<RetrievalPrecision>,
+    /// By the chunk's place in what its turn retrieved, from 1.
+    pub by_position: Vec<RetrievalPrecision>,
+}
+
+/// Progress of an [`Ingestion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestionStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl IngestionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IngestionStatus::Running => "running",
+            IngestionStatus::Completed => "completed",
+            IngestionStatus::Failed => "failed",
+        }
+    }
+}
+
+impl TryFrom<String> for IngestionStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "running" => Ok(IngestionStatus::Running),
+            "completed" => Ok(IngestionStatus::Completed),
+            "failed" => Ok(IngestionStatus::Failed),
+            other => Err(format!("Unknown ingestion status: {other}")),
+        }
+    }
+}
+
+/// A background run indexing a source into a collection, returned by
+/// `POST /api/documents/git` and `GET /api/documents/ingestions/:id`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Ingestion {
+    pub id: Uuid,
+    pub collection: String,
+    pub source: String,
+    #[sqlx(try_from = "String")]
+    pub status: IngestionStatus,
+    /// What was indexed, once completed.
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub report: IngestionReport,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl Ingestion {
+    /// A new ingestion of `source` into `collection`, just started.
+    pub fn running(collection: String, source: String) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            collection,
+            source,
+            status: IngestionStatus::Running,
+            report: IngestionReport::default(),
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+}
+
+/// What an [`Ingestion`] indexed. Files whose text and chunks had not
+/// changed since they were last indexed are skipped, and chunks whose text
+/// was embedded before keep their embedding: only `embedded` chunks were
+/// sent to the embedding model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct IngestionReport {
+    /// Files indexed, skipped ones included.
+    pub files: i32,
+    pub chunks: i32,
+    /// Files indexed for the first time.
+    pub added: i32,
+    /// Files indexed again because they changed.
+    pub updated: i32,
+    /// Files left as they were indexed.
+    pub skipped: i32,
+    /// Files no longer in the source, dropped.
+    pub removed: i32,
+    pub embedded: i32,
+}
+
+/// A file indexed for retrieval.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Document {
+    pub id: Uuid,
+    pub collection: String,
+    /// The repository (or other origin) it was indexed from.
+    pub source: String,
+    pub path: String,
+    pub language: Option<String>,
+    /// The text it was split from; unset for documents indexed before it was
+    /// kept.
+    #[serde(skip)]
+    pub content: Option<String>,
+    /// Hex SHA-256 of `content`.
+    pub content_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An indexed [`Document`] with the hashes of its chunks, in order, to tell
+/// whether indexing it again would change anything.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct IndexedDocument {
+    #[sqlx(flatten)]
+    pub document: Document,
+    /// Empty strings for chunks indexed before hashes were kept.
+    pub chunk_hashes: Vec<String>,
+}
+
+/// A piece of a [`Document`] embedded on its own, numbered from 0.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DocumentChunk {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub position: i32,
+    pub content: String,
+    /// 1-based lines of the document the chunk spans, inclusive.
+    pub start_line: i32,
+    pub end_line: i32,
+    #[serde(skip)]
+    pub embedding: Vec<f32>,
+    /// Hex SHA-256 of the embedding model and the text embedded; chunks with
+    /// the same hash share an embedding.
+    #[serde(skip)]
+    pub content_hash: Option<String>,
+    /// The model that made `embedding`; unset for chunks embedded before it
+    /// was recorded.
+    #[serde(skip)]
+    pub embedding_model: Option<String>,
 }
 
 /// Context prepared by ChatService before streaming begins.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChatContext {
-    pub conversation_id: String,
+    pub conversation_id: Uuid,
+    pub conversation_title: String,
+    /// Display name used for `{{user_name}}` in the preamble, when known.
+    pub user_name: Option<String>,
+    /// ISO 639-3 code of the conversation's language, when detected.
+    pub language: Option<String>,
     pub history: Vec<Message>,
+    /// Newest message dropped from `history` by the history limit, if any.
+    pub history_cutoff: Option<Uuid>,
     pub user_message: String,
+    pub preferences: TurnPreferences,
+    /// Webhook tools the model may call this turn, besides the built-in ones.
+    pub webhook_tools: Vec<WebhookTool>,
+    /// Indexed chunks most similar to the user message, best first.
+    pub retrieved: Vec<RetrievedChunk>,
+}
+
+/// Per-user overrides applied to a single turn.
+#[derive(Debug, Clone, Default)]
+pub struct TurnPreferences {
+    /// Replaces the configured model.
+    pub model: Option<String>,
+    /// Replaces the configured system prompt template.
+    pub system_prompt: Option<String>,
+    pub temperature: Option<f64>,
+    /// Added to the system prompt as standing instructions.
+    pub custom_instructions: Option<String>,
+    pub response_format: ResponseFormat,
+    pub verbosity: Verbosity,
 }
@@ -0,0 +1,52 @@
+//! Conversation access audit trail: every successful read of a
+//! conversation's messages or export is logged by [`record_access`], and
+//! listed by [`access_log_handler`].
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use uuid::Uuid;
+
+use crate::models::{AccessAction, AccessLogQuery};
+use crate::routes::api_routes::error_response;
+use crate::service::chat_service::ChatService;
+
+/// GET `/api/conversations/:id/access-log?limit=...` — who read or exported
+/// a conversation, newest first
+pub async fn access_log_handler(
+    Path(id): Path<Uuid>,
+    Query(query): Query<AccessLogQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_access_log(id, query.limit).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// Logs an access to the conversation in the path once the route it wraps
+/// answered successfully: an export for `/export`, a view otherwise.
+pub async fn record_access(
+    State(svc): State<ChatService>,
+    Path(id): Path<Uuid>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let action = if request.uri().path().ends_with("/export") {
+        AccessAction::Export
+    } else {
+        AccessAction::View
+    };
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let response = next.run(request).await;
+    if response.status().is_success() {
+        svc.record_access(id, action, user_agent).await;
+    }
+    response
+}
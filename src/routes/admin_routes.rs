@@ -0,0 +1,88 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::config::ConfigStore;
+use crate::db::maintenance_repository::MaintenanceRepository;
+use crate::db::workspace_read_repository::WorkspaceReadRepository;
+use crate::models::{AccessLogQuery, ErrorBody, SetMaintenanceRequest, TranscriptQuery};
+use crate::routes::api_routes::error_response;
+use crate::service::chat_service::ChatService;
+
+const DEFAULT_TRANSCRIPT_TAIL: usize = 20;
+const MAX_TRANSCRIPT_TAIL: usize = 500;
+const DEFAULT_WORKSPACE_READS: i64 = 50;
+const MAX_WORKSPACE_READS: i64 = 500;
+
+/// POST `/api/admin/config/reload` — re-read the config file and apply the
+/// reloadable settings, returning the configuration now in effect.
+pub async fn reload_config_handler(State(config): State<ConfigStore>) -> impl IntoResponse {
+    match config.reload() {
+        Ok(config) => Json(config.as_ref().clone()).into_response(),
+        Err(e) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ErrorBody { error: e.to_string(), code: e.code() }),
+        )
+            .into_response(),
+    }
+}
+
+/// PUT `/api/admin/maintenance` — switch maintenance mode on or off. While
+/// on, chat and other writes get 503 and reads keep working.
+pub async fn set_maintenance_handler(
+    State(repo): State<MaintenanceRepository>,
+    Json(request): Json<SetMaintenanceRequest>,
+) -> impl IntoResponse {
+    let message = request.message.as_deref().map(str::trim).filter(|m| !m.is_empty());
+    match repo.set(request.enabled, message).await {
+        Ok(status) => Json(status).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/admin/streams` — generations running against the model:
+/// conversation, model, elapsed time and tokens so far
+pub async fn list_streams_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    Json(svc.generations())
+}
+
+/// DELETE `/api/admin/streams/:id` — cancel a running generation. A streamed
+/// reply keeps what was generated before the cancellation.
+pub async fn cancel_stream_handler(
+    Path(id): Path<String>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.cancel_generation(&id) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/admin/transcripts/:id?limit=20` — the most recent transcript
+/// entries logged for a conversation
+pub async fn tail_transcript_handler(
+    Path(id): Path<Uuid>,
+    Query(query): Query<TranscriptQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_TRANSCRIPT_TAIL).min(MAX_TRANSCRIPT_TAIL);
+    match svc.tail_transcript(id, limit).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/admin/workspace/reads?limit=50` — files of the workspace the
+/// agent read, newest first
+pub async fn workspace_reads_handler(
+    Query(query): Query<AccessLogQuery>,
+    State(repo): State<WorkspaceReadRepository>,
+) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_WORKSPACE_READS).clamp(1, MAX_WORKSPACE_READS);
+    match repo.find_recent(limit).await {
+        Ok(reads) => Json(reads).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
@@ -1,60 +1,707 @@
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::Json;
+use chrono::Utc;
+use futures_util::stream::{self, StreamExt};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+use uuid::Uuid;
 
 use crate::errors::AppError;
-use crate::models::ChatRequest;
-use crate::service::chat_service::ChatService;
+use crate::models::{
+    ChatRequest, ConversationQuery, CreateBatchRequest, CreateEvalRequest, CreateScheduleRequest,
+    CreateWebhookRequest, CreateWebhookToolRequest, ErrorBody, ErrorCode, ExportFormat,
+    ExportQuery, MessagesQuery, NotificationQuery, PassphraseRequest, PromptHistoryQuery,
+    PullModelRequest, PullProgress, ScheduleQuery, SearchQuery, SetActiveVersionRequest,
+    TokenizeRequest, UpdateConversationRequest, UpdateProfileRequest, WebhookDeliveryQuery,
+    WebhookQuery, WebhookToolQuery,
+};
+use crate::service::chat_service::{server_version, ChatService};
+use crate::service::export::{self, ExportEntry};
+use crate::storage::{BlobStore, BlobStream};
+
+/// How long a signed export download link stays valid.
+const EXPORT_URL_TTL: Duration = Duration::from_secs(15 * 60);
+/// Extractor rejections are a line of text; a longer body is left out.
+const MAX_REJECTION_BYTES: usize = 64 * 1024;
 
 // ── Handlers ─────────────────────────────────────────────────────────────────
 
 /// POST `/api/chat` — accepts JSON, returns JSON (non-streaming fallback)
+///
+/// Hyper drops this future when the client disconnects (or the route times
+/// out). The turn runs in its own task so it is not cut off midway; the drop
+/// guard cancels it instead, which stops generation and records the turn as
+/// failed.
 pub async fn chat_handler(
     State(svc): State<ChatService>,
     Json(request): Json<ChatRequest>,
 ) -> impl IntoResponse {
-    match svc.chat(request).await {
-        Ok(response) => Json(response).into_response(),
-        Err(err) => error_response(&err),
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+    let turn = tokio::spawn(async move { svc.chat_until(request, &cancel).await });
+    match turn.await {
+        Ok(Ok(response)) => Json(response).into_response(),
+        Ok(Err(err)) => error_response(&err),
+        Err(e) => error_response(&AppError::Unexpected(format!("Chat turn failed: {e}"))),
     }
 }
 
-/// GET `/api/conversations` — list conversations as JSON
+/// GET `/api/conversations?sort=...` — list conversations as JSON, in the
+/// order asked for or saved in the profile
 pub async fn list_conversations_handler(
+    Query(query): Query<ConversationQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_conversations(query.sort).await {
+        Ok(convs) => Json(convs).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/search?q=...&limit=...` — conversations whose title or messages match
+pub async fn search_handler(
+    Query(query): Query<SearchQuery>,
     State(svc): State<ChatService>,
 ) -> impl IntoResponse {
-    match svc.get_conversations().await {
+    match svc.search_conversations(&query.q, query.limit).await {
         Ok(convs) => Json(convs).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
-/// GET `/api/conversations/:id/messages` — messages for a conversation
+/// GET `/api/conversations/:id/messages?summaries=...` — messages for a
+/// conversation; with `summaries`, a timeline whose oldest messages may be
+/// condensed into a summary block
 pub async fn list_messages_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(query): Query<MessagesQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    let listed = if query.summaries {
+        svc.get_timeline(id).await.map(|entries| Json(entries).into_response())
+    } else {
+        svc.get_messages(id).await.map(|msgs| Json(msgs).into_response())
+    };
+    match listed {
+        Ok(response) => response,
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/conversations/:id/stats` — token usage and cost of a conversation
+pub async fn conversation_stats_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_conversation_stats(id).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/conversations/:id/export?format=pdf` — the conversation as a
+/// document download
+pub async fn export_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    let rendered = match query.format {
+        ExportFormat::Pdf => svc.export_pdf(id).await,
+    };
+    match rendered {
+        Ok((conversation, pdf)) => {
+            let filename = format!("{}.pdf", download_name(&conversation.title));
+            (
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+                ],
+                pdf,
+            )
+                .into_response()
+        }
+        Err(err) => error_response(&err),
+    }
+}
+
+/// A file name for a download, from a conversation title: ASCII letters and
+/// digits, the rest collapsed into dashes.
+fn download_name(title: &str) -> String {
+    let mut name = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name = name.trim_end_matches('-');
+    if name.is_empty() {
+        "conversation".to_string()
+    } else {
+        name.chars().take(60).collect()
+    }
+}
+
+/// DELETE `/api/conversations/:id` — delete a conversation and its messages
+pub async fn delete_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.delete_conversation(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PATCH `/api/conversations/{id}` — change a conversation's settings
+pub async fn update_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+    Json(update): Json<UpdateConversationRequest>,
+) -> impl IntoResponse {
+    match svc.update_conversation(id, update).await {
+        Ok(conversation) => Json(conversation).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/conversations/{id}/encrypt` — encrypt a conversation with a passphrase
+pub async fn encrypt_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+    Json(request): Json<PassphraseRequest>,
+) -> impl IntoResponse {
+    match svc.encrypt_conversation(id, request).await {
+        Ok(conversation) => Json(conversation).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/conversations/{id}/unlock` — supply an encrypted conversation's passphrase
+pub async fn unlock_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+    Json(request): Json<PassphraseRequest>,
+) -> impl IntoResponse {
+    match svc.unlock_conversation(id, request).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/conversations/{id}/lock` — forget an unlocked conversation's key
+pub async fn lock_conversation_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    svc.lock_conversation(id);
+    StatusCode::NO_CONTENT
+}
+
+/// GET `/api/me` — the current user's profile and preferences
+pub async fn get_profile_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.get_profile().await {
+        Ok(profile) => Json(profile).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PATCH `/api/me` — update some of the current user's preferences
+pub async fn update_profile_handler(
+    State(svc): State<ChatService>,
+    Json(update): Json<UpdateProfileRequest>,
+) -> impl IntoResponse {
+    match svc.update_profile(update).await {
+        Ok(profile) => Json(profile).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/export/all` — every stored conversation and the profile as a
+/// zip archive, streamed while it is built
+pub async fn export_all_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    zip_download(export_archive(svc))
+}
+
+/// POST `/api/exports` — build the same archive as `/api/export/all` and keep
+/// it in the blob store, returning where to download it
+pub async fn create_export_handler(
+    State(svc): State<ChatService>,
+    State(blobs): State<Arc<dyn BlobStore>>,
+) -> impl IntoResponse {
+    let id = uuid::Uuid::new_v4().to_string();
+    match blobs.put(&export_key(&id), export_archive(svc)).await {
+        Ok(()) => {
+            let url = format!("/api/exports/{id}");
+            (StatusCode::CREATED, Json(json!({ "id": id, "url": url }))).into_response()
+        }
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/exports/:id` — download a stored export: a redirect to a signed
+/// URL when the blob store supports them, otherwise streamed from the store
+pub async fn export_handler(
     axum::extract::Path(id): axum::extract::Path<String>,
+    State(blobs): State<Arc<dyn BlobStore>>,
+) -> impl IntoResponse {
+    let key = export_key(&id);
+    match blobs.signed_url(&key, EXPORT_URL_TTL).await {
+        Ok(Some(url)) => return Redirect::temporary(&url).into_response(),
+        Ok(None) => {}
+        Err(err) => return error_response(&err),
+    }
+    match blobs.get(&key).await {
+        Ok(blob) => zip_download(blob).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/exports/:id` — remove a stored export
+pub async fn delete_export_handler(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    State(blobs): State<Arc<dyn BlobStore>>,
+) -> impl IntoResponse {
+    match blobs.delete(&export_key(&id)).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+fn export_key(id: &str) -> String {
+    format!("exports/{id}.zip")
+}
+
+/// The export archive, zipped on a blocking thread as conversations load.
+fn export_archive(svc: ChatService) -> BlobStream {
+    let (entry_tx, entry_rx) = mpsc::channel::<Result<ExportEntry, AppError>>(4);
+    let (chunk_tx, chunk_rx) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        if let Err(e) = svc.export_all(&entry_tx).await {
+            error!("Data export failed: {e}");
+            let _ = entry_tx.send(Err(e)).await;
+        }
+    });
+    tokio::task::spawn_blocking(move || export::write_zip(entry_rx, chunk_tx));
+
+    stream::unfold(chunk_rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((chunk, rx))
+    })
+    .boxed()
+}
+
+fn zip_download(archive: BlobStream) -> impl IntoResponse {
+    let filename = format!("rust-ai-export-{}.zip", Utc::now().format("%Y%m%d"));
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        Body::from_stream(archive),
+    )
+}
+
+/// GET `/api/models` — models installed in Ollama
+pub async fn list_models_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.list_models().await {
+        Ok(models) => Json(models).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/system/status` — models Ollama has loaded (with VRAM use) and
+/// host memory and load
+pub async fn system_status_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.system_status().await {
+        Ok(status) => Json(status).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/config` — protocol version, build, tools and enabled features,
+/// for the frontend to render only what the server supports
+pub async fn server_config_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.server_config().await {
+        Ok(config) => Json(config).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/version` — protocol version and build (crate version and git
+/// commit), for the frontend to notice a deploy
+pub async fn version_handler() -> impl IntoResponse {
+    Json(server_version())
+}
+
+/// GET `/api/presets` — assistant presets conversations can switch to
+pub async fn list_presets_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    Json(svc.presets())
+}
+
+/// POST `/api/tokenize` — token count (and optionally tokens) of a text for a model
+pub async fn tokenize_handler(
     State(svc): State<ChatService>,
+    Json(request): Json<TokenizeRequest>,
 ) -> impl IntoResponse {
-    match svc.get_messages(&id).await {
-        Ok(msgs) => Json(msgs).into_response(),
-        Err(e) if e.is_not_found() => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    match svc.tokenize(request).await {
+        Ok(tokens) => Json(tokens).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/models/pull` — download a model into Ollama (the configured one
+/// by default). Progress streams back as server-sent events: `progress` with
+/// Ollama's status line, then a final `done` or `error`.
+pub async fn pull_model_handler(
+    State(svc): State<ChatService>,
+    body: Option<Json<PullModelRequest>>,
+) -> impl IntoResponse {
+    let model = body
+        .and_then(|Json(req)| req.model)
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or_else(|| svc.config().get().model.clone());
+
+    let (tx, rx) = mpsc::channel::<PullProgress>(32);
+    let agent = svc.agent().clone();
+    let name = model.clone();
+    let pull = tokio::spawn(async move { agent.pull_model(&name, tx).await });
+
+    let progress = stream::unfold(rx, |mut rx| async move {
+        let update = rx.recv().await?;
+        let event = Event::default().event("progress").json_data(update);
+        Some((event, rx))
+    });
+    let outcome = stream::once(async move {
+        let err = match pull.await {
+            Ok(Ok(())) => return Event::default().event("done").json_data(json!({ "model": model })),
+            Ok(Err(e)) => e,
+            Err(e) => AppError::Unexpected(format!("Pull task failed: {e}")),
+        };
+        let body = ErrorBody { error: err.to_string(), code: err.code() };
+        Event::default().event("error").json_data(body)
+    });
+
+    Sse::new(progress.chain(outcome)).keep_alive(KeepAlive::default())
+}
+
+/// POST `/api/messages/:id/regenerate` — new answer for an assistant message;
+/// the previous answer is kept as a version
+pub async fn regenerate_message_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.regenerate(id).await {
+        Ok(msg) => Json(msg).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/messages/:id/versions` — every generation of a message
+pub async fn list_versions_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_versions(id).await {
+        Ok(versions) => Json(versions).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/messages/:id/versions/:version/diff` — what changed since the previous version
+pub async fn version_diff_handler(
+    axum::extract::Path((id, version)): axum::extract::Path<(Uuid, i32)>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.version_diff(id, version).await {
+        Ok(diff) => Json(diff).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/conversations/:id/retry-last` — re-run the last user message after a failed turn
+pub async fn retry_last_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.retry_last(id).await {
+        Ok(response) => Json(response).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PUT `/api/messages/:id/star` — star a message
+pub async fn star_message_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.set_starred(id, true).await {
+        Ok(message) => Json(message).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/messages/:id/star` — unstar a message
+pub async fn unstar_message_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.set_starred(id, false).await {
+        Ok(message) => Json(message).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/me/prompts?q=...&limit=...` — prompts the user has sent, most
+/// recently used first
+pub async fn prompt_history_handler(
+    Query(query): Query<PromptHistoryQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.prompt_history(&query.q, query.limit).await {
+        Ok(prompts) => Json(prompts).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/starred` — starred messages from every conversation, newest star first
+pub async fn list_starred_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.starred_messages().await {
+        Ok(starred) => Json(starred).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/tools` — register a webhook tool the model may call
+pub async fn create_tool_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateWebhookToolRequest>,
+) -> impl IntoResponse {
+    match svc.create_webhook_tool(request).await {
+        Ok(tool) => (StatusCode::CREATED, Json(tool)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/tools?conversation_id=...` — the global webhook tools, or all
+/// those a turn in the conversation may call
+pub async fn list_tools_handler(
+    Query(query): Query<WebhookToolQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_webhook_tools(query.conversation_id).await {
+        Ok(tools) => Json(tools).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/tools/:id` — remove a webhook tool
+pub async fn delete_tool_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.delete_webhook_tool(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/schedules` — run a prompt on a cron schedule, posting each
+/// reply to a conversation
+pub async fn create_schedule_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateScheduleRequest>,
+) -> impl IntoResponse {
+    match svc.create_schedule(request).await {
+        Ok(schedule) => (StatusCode::CREATED, Json(schedule)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/schedules?conversation_id=...` — scheduled prompts, soonest first
+pub async fn list_schedules_handler(
+    Query(query): Query<ScheduleQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_schedules(query.conversation_id).await {
+        Ok(schedules) => Json(schedules).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/schedules/:id` — stop running a scheduled prompt
+pub async fn delete_schedule_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.delete_schedule(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/webhooks` — send every assistant message of a conversation to a
+/// URL; the response holds the signing secret, which is not shown again
+pub async fn create_webhook_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> impl IntoResponse {
+    match svc.create_webhook(request).await {
+        Ok(webhook) => (StatusCode::CREATED, Json(webhook)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/webhooks?conversation_id=...` — registered webhooks, oldest first
+pub async fn list_webhooks_handler(
+    Query(query): Query<WebhookQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_webhooks(query.conversation_id).await {
+        Ok(webhooks) => Json(webhooks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/webhooks/:id` — stop sending messages to a webhook
+pub async fn delete_webhook_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.delete_webhook(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/webhooks/:id/deliveries?limit=...` — messages sent to a
+/// webhook, newest first, with how each delivery went
+pub async fn list_webhook_deliveries_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    Query(query): Query<WebhookDeliveryQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_webhook_deliveries(id, query.limit).await {
+        Ok(deliveries) => Json(deliveries).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/notifications?limit=...` — notifications sent about finished
+/// batch jobs and scheduled prompts, newest first
+pub async fn list_notifications_handler(
+    Query(query): Query<NotificationQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_notifications(query.limit).await {
+        Ok(notifications) => Json(notifications).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PUT `/api/messages/:id/active-version` — choose which version is shown
+pub async fn set_active_version_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+    Json(request): Json<SetActiveVersionRequest>,
+) -> impl IntoResponse {
+    match svc.set_active_version(id, request.version).await {
+        Ok(msg) => Json(msg).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/evals` — replay conversations against another model in the
+/// background; returns the new run
+pub async fn create_eval_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateEvalRequest>,
+) -> impl IntoResponse {
+    match svc.start_eval(request).await {
+        Ok(run) => (StatusCode::ACCEPTED, Json(run)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/evals` — every eval run, newest first
+pub async fn list_evals_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.list_evals().await {
+        Ok(runs) => Json(runs).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/evals/:id` — a run's replies next to the originals, with word diffs
+pub async fn eval_report_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_eval_report(id).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/batch` — answer a list of prompts in the background; returns
+/// the new job with its items queued
+pub async fn create_batch_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateBatchRequest>,
+) -> impl IntoResponse {
+    match svc.start_batch(request).await {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/batch/:id` — a batch job with each item's status and reply
+pub async fn batch_handler(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_batch(id).await {
+        Ok(job) => Json(job).into_response(),
+        Err(err) => error_response(&err),
     }
 }
 
 // ── Helper ────────────────────────────────────────────────────────────────────
 
-fn error_response(err: &AppError) -> axum::response::Response {
-    let status = if err.is_validation() {
-        StatusCode::BAD_REQUEST
-    } else if err.is_not_found() {
-        StatusCode::NOT_FOUND
-    } else if err.is_agent_unavailable() {
-        StatusCode::SERVICE_UNAVAILABLE
+pub(crate) fn error_response(err: &AppError) -> axum::response::Response {
+    let body = ErrorBody { error: err.to_string(), code: err.code() };
+    (err.status_code(), Json(body)).into_response()
+}
+
+/// Turns the plain-text rejections of axum's extractors (a body that is not
+/// valid JSON, a malformed id in the path, a bad query string) into the same
+/// [`ErrorBody`] handlers answer with, so every error carries a `code`.
+pub(crate) async fn coded_rejections(response: Response) -> Response {
+    let plain_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"text/plain"));
+    let status = response.status();
+    if !status.is_client_error() || !plain_text {
+        return response;
+    }
+    let body = axum::body::to_bytes(response.into_body(), MAX_REJECTION_BYTES).await;
+    let error = body.map(|b| String::from_utf8_lossy(&b).into_owned()).unwrap_or_default();
+    let code = if status == StatusCode::NOT_FOUND {
+        ErrorCode::NotFound
     } else {
-        StatusCode::INTERNAL_SERVER_ERROR
+        ErrorCode::InvalidRequest
     };
-
-    let body = serde_json::json!({ "error": err.to_string() });
-    (status, Json(body)).into_response()
+    (status, Json(ErrorBody { error, code })).into_response()
 }
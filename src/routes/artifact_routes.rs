@@ -0,0 +1,70 @@
+//! Artifacts of assistant replies: long code blocks and documents, listed
+//! per conversation, downloadable one by one and, for code, diffed against
+//! and applied to the user's files.
+
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::models::ArtifactDiffRequest;
+use crate::routes::api_routes::error_response;
+use crate::service::chat_service::ChatService;
+
+/// GET `/api/conversations/:id/artifacts` — the artifacts of a conversation's
+/// replies, oldest first
+pub async fn list_artifacts_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_artifacts(id).await {
+        Ok(artifacts) => Json(artifacts).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/artifacts/:id` — one artifact
+pub async fn artifact_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_artifact(id).await {
+        Ok(artifact) => Json(artifact).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/artifacts/:id/download` — the artifact's content as a file
+pub async fn download_artifact_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_artifact(id).await {
+        Ok(artifact) => (
+            [
+                (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", artifact.file_name()),
+                ),
+            ],
+            artifact.content,
+        )
+            .into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/artifacts/:id/diff` — unified diff of a file against the
+/// artifact, optionally applying it to the workspace
+pub async fn diff_artifact_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+    Json(req): Json<ArtifactDiffRequest>,
+) -> impl IntoResponse {
+    match svc.diff_artifact(id, req).await {
+        Ok(diff) => Json(diff).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
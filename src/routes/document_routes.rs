@@ -0,0 +1,200 @@
+//! Documents indexed for retrieval: named collections, attached to
+//! conversations and inspected chunk by chunk, and git repositories ingested
+//! into them in the background.
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::models::{
+    ChunkPreviewRequest, ChunksQuery, CollectionSearchRequest, CreateCollectionRequest,
+    IngestGitRequest, RechunkRequest, RetrievalFeedback,
+};
+use crate::routes::api_routes::error_response;
+use crate::service::chat_service::ChatService;
+
+/// POST `/api/documents/git` — start indexing a repository; answers 202 with
+/// the ingestion to poll
+pub async fn ingest_git_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<IngestGitRequest>,
+) -> impl IntoResponse {
+    match svc.ingest_git(request).await {
+        Ok(ingestion) => (StatusCode::ACCEPTED, Json(ingestion)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/documents/ingestions/:id` — an ingestion's status and counts
+pub async fn ingestion_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_ingestion(id).await {
+        Ok(ingestion) => Json(ingestion).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/collections` — create a collection
+pub async fn create_collection_handler(
+    State(svc): State<ChatService>,
+    Json(request): Json<CreateCollectionRequest>,
+) -> impl IntoResponse {
+    match svc.create_collection(request).await {
+        Ok(collection) => (StatusCode::CREATED, Json(collection)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/collections` — every collection with its document and chunk counts
+pub async fn list_collections_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.list_collections().await {
+        Ok(collections) => Json(collections).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/collections/:name` — one collection
+pub async fn collection_handler(
+    Path(name): Path<String>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_collection(&name).await {
+        Ok(collection) => Json(collection).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/collections/:name` — delete a collection and its documents
+pub async fn delete_collection_handler(
+    Path(name): Path<String>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.delete_collection(&name).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/collections/:name/chunks?path=...&limit=50&offset=0` — the
+/// collection's chunks as indexed
+pub async fn collection_chunks_handler(
+    Path(name): Path<String>,
+    Query(query): Query<ChunksQuery>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_collection_chunks(&name, query).await {
+        Ok(chunks) => Json(chunks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/collections/:name/search` — the chunks retrieved for a query,
+/// with their scores
+pub async fn search_collection_handler(
+    Path(name): Path<String>,
+    State(svc): State<ChatService>,
+    Json(request): Json<CollectionSearchRequest>,
+) -> impl IntoResponse {
+    match svc.search_collection(&name, request).await {
+        Ok(hits) => Json(hits).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/collections/:name/rechunk` — split the collection's documents
+/// again with new chunking settings; answers 202 with the ingestion to poll
+pub async fn rechunk_collection_handler(
+    Path(name): Path<String>,
+    State(svc): State<ChatService>,
+    Json(request): Json<RechunkRequest>,
+) -> impl IntoResponse {
+    match svc.rechunk_collection(&name, request).await {
+        Ok(ingestion) => (StatusCode::ACCEPTED, Json(ingestion)).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// POST `/api/collections/:name/preview` — how a sample document would be
+/// split into chunks
+pub async fn preview_chunks_handler(
+    Path(name): Path<String>,
+    State(svc): State<ChatService>,
+    Json(request): Json<ChunkPreviewRequest>,
+) -> impl IntoResponse {
+    match svc.preview_chunks(&name, request).await {
+        Ok(chunks) => Json(chunks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/conversations/:id/collections` — the collections attached to a
+/// conversation
+pub async fn conversation_collections_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.list_conversation_collections(id).await {
+        Ok(collections) => Json(collections).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PUT `/api/conversations/:id/collections/:name` — retrieve from a
+/// collection in the conversation's turns
+pub async fn attach_collection_handler(
+    Path((id, name)): Path<(Uuid, String)>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.attach_collection(id, &name).await {
+        Ok(collections) => Json(collections).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// DELETE `/api/conversations/:id/collections/:name` — stop retrieving from it
+pub async fn detach_collection_handler(
+    Path((id, name)): Path<(Uuid, String)>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.detach_collection(id, &name).await {
+        Ok(collections) => Json(collections).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/messages/:id/retrieval` — the chunks retrieved for a reply's
+/// active version, with their scores
+pub async fn message_retrieval_handler(
+    Path(id): Path<Uuid>,
+    State(svc): State<ChatService>,
+) -> impl IntoResponse {
+    match svc.get_message_retrieval(id).await {
+        Ok(chunks) => Json(chunks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// PUT `/api/messages/:id/retrieval/:chunk_id` — record whether a retrieved
+/// chunk was relevant; answers with the reply's retrieved chunks
+pub async fn rate_retrieved_chunk_handler(
+    Path((id, chunk_id)): Path<(Uuid, Uuid)>,
+    State(svc): State<ChatService>,
+    Json(feedback): Json<RetrievalFeedback>,
+) -> impl IntoResponse {
+    match svc.rate_retrieved_chunk(id, chunk_id, feedback).await {
+        Ok(chunks) => Json(chunks).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// GET `/api/retrieval/metrics` — retrieval precision from users' verdicts
+pub async fn retrieval_metrics_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    match svc.retrieval_metrics().await {
+        Ok(metrics) => Json(metrics).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
@@ -0,0 +1,12 @@
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+
+use crate::service::chat_service::ChatService;
+
+/// GET `/readyz` — whether the service is answering normally. Always 200
+/// while it serves; `degraded` flags the model answering more slowly than
+/// `first_token_sla_ms`, which a replica restart would not fix.
+pub async fn readiness_handler(State(svc): State<ChatService>) -> impl IntoResponse {
+    Json(svc.readiness())
+}
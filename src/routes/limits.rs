@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use axum::error_handling::HandleErrorLayer;
+use axum::response::Response;
+use axum::{BoxError, Router};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::error::Overloaded;
+use tower::load_shed::LoadShedLayer;
+use tower::timeout::error::Elapsed;
+use tower::timeout::TimeoutLayer;
+use tower::ServiceBuilder;
+
+use crate::app::AppState;
+use crate::config::RouteLimit;
+use crate::errors::AppError;
+use crate::routes::api_routes::error_response;
+
+/// Applies `limit` to every route of `router`. The concurrency limit is shared
+/// by the whole group; requests over it are turned away at once instead of
+/// queueing behind slow ones.
+pub fn limited(
+    router: Router<AppState>,
+    route_group: &'static str,
+    limit: RouteLimit,
+) -> Router<AppState> {
+    let timeout = (limit.timeout_secs > 0)
+        .then(|| TimeoutLayer::new(Duration::from_secs(limit.timeout_secs)));
+    let concurrency =
+        (limit.max_concurrent > 0).then(|| GlobalConcurrencyLimitLayer::new(limit.max_concurrent));
+
+    router.route_layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(move |err: BoxError| async move {
+                limit_error(route_group, limit, err)
+            }))
+            .option_layer(timeout)
+            .layer(LoadShedLayer::new())
+            .option_layer(concurrency),
+    )
+}
+
+fn limit_error(route_group: &str, limit: RouteLimit, err: BoxError) -> Response {
+    let err = if err.is::<Elapsed>() {
+        AppError::RequestTimeout { timeout_secs: limit.timeout_secs }
+    } else if err.is::<Overloaded>() {
+        AppError::TooManyRequests {
+            route_group: route_group.to_string(),
+            max_concurrent: limit.max_concurrent,
+        }
+    } else {
+        AppError::Unexpected(err.to_string())
+    };
+    error_response(&err)
+}
@@ -0,0 +1,80 @@
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use tracing::warn;
+
+use crate::db::maintenance_repository::MaintenanceRepository;
+use crate::models::ErrorCode;
+use crate::routes::api_routes::error_response;
+
+const DEFAULT_NOTICE: &str =
+    "The service is under maintenance. Chat is unavailable for now; your history can still be read.";
+
+/// GET `/api/maintenance` — whether maintenance mode is on, for the frontend's banner
+pub async fn maintenance_status_handler(
+    State(repo): State<MaintenanceRepository>,
+) -> impl IntoResponse {
+    match repo.get().await {
+        Ok(status) => Json(status).into_response(),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// Refuses chat and every other write with 503 while maintenance mode is on.
+/// Reads and the admin API pass through. If the flag cannot be read the
+/// request is let through rather than taking the service down.
+pub async fn maintenance_guard(
+    State(repo): State<MaintenanceRepository>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_guarded(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    match repo.get().await {
+        Ok(status) if status.enabled => {
+            let notice = status.message.as_deref().unwrap_or(DEFAULT_NOTICE);
+            unavailable(request.headers(), notice)
+        }
+        Ok(_) => next.run(request).await,
+        Err(e) => {
+            warn!("Skipping maintenance check: {e}");
+            next.run(request).await
+        }
+    }
+}
+
+/// Writes, plus the chat WebSocket, which is opened with a GET.
+fn is_guarded(method: &Method, path: &str) -> bool {
+    if path.starts_with("/api/admin/") {
+        return false;
+    }
+    let read = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS);
+    !read || path == "/ws/chat"
+}
+
+/// A 503 as HTML for browsers navigating directly, JSON for everything else.
+fn unavailable(headers: &HeaderMap, notice: &str) -> Response {
+    let wants_html = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if wants_html {
+        let notice = notice.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let page = format!(
+            "<!doctype html><title>Maintenance</title>\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+             <h1>Down for maintenance</h1><p>{notice}</p>"
+        );
+        (StatusCode::SERVICE_UNAVAILABLE, Html(page)).into_response()
+    } else {
+        let body = serde_json::json!({
+            "error": notice,
+            "code": ErrorCode::Maintenance,
+            "maintenance": true,
+        });
+        (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+    }
+}
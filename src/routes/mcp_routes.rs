@@ -0,0 +1,270 @@
+//! Model Context Protocol server over the SSE transport, so other agents and
+//! editors can read conversations as resources and chat through a
+//! `send_message` tool.
+//!
+//! A client opens `GET /mcp/sse`, whose first event (`endpoint`) names the URL
+//! to POST its JSON-RPC messages to. Each POST is answered with 202 once
+//! handled; the JSON-RPC response itself arrives as a `message` event on the
+//! client's SSE stream.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::models::ChatRequest;
+use crate::service::chat_service::ChatService;
+
+/// MCP revision that defined the SSE transport.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const RESOURCE_PREFIX: &str = "conversation://";
+const SEND_MESSAGE_TOOL: &str = "send_message";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Open SSE streams, by session id.
+#[derive(Clone, Default)]
+pub struct McpSessions {
+    streams: Arc<Mutex<HashMap<String, mpsc::Sender<Value>>>>,
+}
+
+impl McpSessions {
+    fn open(&self, id: &str, tx: mpsc::Sender<Value>) {
+        self.streams.lock().expect("mcp sessions poisoned").insert(id.to_string(), tx);
+    }
+
+    fn get(&self, id: &str) -> Option<mpsc::Sender<Value>> {
+        self.streams.lock().expect("mcp sessions poisoned").get(id).cloned()
+    }
+
+    fn close(&self, id: &str) {
+        self.streams.lock().expect("mcp sessions poisoned").remove(id);
+    }
+}
+
+/// Forgets the session once its SSE stream is dropped.
+struct SessionGuard {
+    sessions: McpSessions,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.close(&self.id);
+        info!("MCP session {} closed", self.id);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct McpSessionQuery {
+    session_id: String,
+}
+
+/// A JSON-RPC request, or a notification when `id` is absent.
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: INVALID_PARAMS, message: message.into() }
+    }
+}
+
+impl From<AppError> for RpcError {
+    fn from(err: AppError) -> Self {
+        // Errors a REST client would get a 4xx for are the caller's fault.
+        let code = if err.status_code().is_client_error() {
+            INVALID_PARAMS
+        } else {
+            INTERNAL_ERROR
+        };
+        Self { code, message: err.to_string() }
+    }
+}
+
+/// GET `/mcp/sse` — opens an MCP session
+pub async fn mcp_sse_handler(
+    State(sessions): State<McpSessions>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let (tx, rx) = mpsc::channel::<Value>(32);
+    sessions.open(&id, tx);
+    info!("MCP session {id} opened");
+
+    let endpoint =
+        Event::default().event("endpoint").data(format!("/mcp/messages?session_id={id}"));
+    let guard = SessionGuard { sessions, id };
+    let messages = stream::unfold((rx, guard), |(mut rx, guard)| async move {
+        let message = rx.recv().await?;
+        let event = Event::default().event("message").data(message.to_string());
+        Some((event, (rx, guard)))
+    });
+    Sse::new(stream::once(async { endpoint }).chain(messages).map(Ok))
+        .keep_alive(KeepAlive::default())
+}
+
+/// POST `/mcp/messages?session_id=...` — one JSON-RPC message from the client
+pub async fn mcp_message_handler(
+    Query(query): Query<McpSessionQuery>,
+    State(sessions): State<McpSessions>,
+    State(svc): State<ChatService>,
+    body: String,
+) -> impl IntoResponse {
+    let Some(tx) = sessions.get(&query.session_id) else {
+        return (StatusCode::NOT_FOUND, "Unknown MCP session").into_response();
+    };
+    let request: RpcRequest = match serde_json::from_str(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError { code: PARSE_ERROR, message: e.to_string() };
+            let _ = tx.send(response(Value::Null, Err(error))).await;
+            return StatusCode::ACCEPTED.into_response();
+        }
+    };
+
+    let result = dispatch(&svc, &request.method, request.params).await;
+    // Notifications get no response.
+    if let Some(id) = request.id {
+        if tx.send(response(id, result)).await.is_err() {
+            warn!("MCP session {} closed before its response was sent", query.session_id);
+        }
+    }
+    StatusCode::ACCEPTED.into_response()
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+async fn dispatch(svc: &ChatService, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "resources": {}, "tools": {} },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })),
+        "ping" | "notifications/initialized" => Ok(json!({})),
+        "resources/list" => {
+            let resources: Vec<Value> = svc
+                .get_conversations()
+                .await?
+                .into_iter()
+                .map(|conv| {
+                    json!({
+                        "uri": format!("{RESOURCE_PREFIX}{}", conv.id),
+                        "name": conv.title,
+                        "description": conv.summary,
+                        "mimeType": "application/json",
+                    })
+                })
+                .collect();
+            Ok(json!({ "resources": resources }))
+        }
+        "resources/read" => {
+            let uri = params["uri"].as_str().unwrap_or_default();
+            let id = uri
+                .strip_prefix(RESOURCE_PREFIX)
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .ok_or_else(|| RpcError::invalid_params(format!("Unknown resource '{uri}'")))?;
+            let messages = svc.get_messages(id).await?;
+            let text = serde_json::to_string(&messages)
+                .map_err(|e| RpcError { code: INTERNAL_ERROR, message: e.to_string() })?;
+            Ok(json!({
+                "contents": [{ "uri": uri, "mimeType": "application/json", "text": text }],
+            }))
+        }
+        "tools/list" => Ok(json!({
+            "tools": [{
+                "name": SEND_MESSAGE_TOOL,
+                "description": "Send a message to the assistant and get its reply. Omit \
+                                conversation_id to start a new conversation; the reply is \
+                                followed by the conversation's id.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "message": { "type": "string" },
+                        "conversation_id": { "type": "string", "format": "uuid" },
+                    },
+                    "required": ["message"],
+                },
+            }],
+        })),
+        "tools/call" => {
+            if params["name"] != SEND_MESSAGE_TOOL {
+                return Err(RpcError::invalid_params(format!("Unknown tool {}", params["name"])));
+            }
+            let arguments = &params["arguments"];
+            let conversation_id = match arguments["conversation_id"].as_str() {
+                Some(id) => Some(Uuid::parse_str(id).map_err(|_| {
+                    RpcError::invalid_params(format!("Invalid conversation_id '{id}'"))
+                })?),
+                None => None,
+            };
+            let request = ChatRequest {
+                conversation_id,
+                message: arguments["message"].as_str().unwrap_or_default().to_string(),
+                ephemeral: false,
+                verbosity: None,
+                stateless: None,
+                quoted_message_id: None,
+            };
+            // Failed turns are reported to the model as tool errors.
+            Ok(match svc.chat(request).await {
+                Ok(reply) => {
+                    let conversation = format!("conversation_id: {}", reply.conversation_id);
+                    json!({
+                        "content": [
+                            { "type": "text", "text": reply.message.content },
+                            { "type": "text", "text": conversation },
+                        ],
+                        "isError": false,
+                    })
+                }
+                Err(e) => json!({
+                    "content": [{ "type": "text", "text": e.to_string() }],
+                    "isError": true,
+                }),
+            })
+        }
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method '{method}'"),
+        }),
+    }
+}
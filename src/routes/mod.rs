@@ -1,2 +1,203 @@
+pub mod access_log;
+pub mod admin_routes;
 pub mod api_routes;
+pub mod artifact_routes;
+pub mod document_routes;
+pub mod health;
+pub mod limits;
+pub mod maintenance;
+pub mod mcp_routes;
+pub mod slack_routes;
+pub mod tenants;
 pub mod ws_routes;
+
+use axum::middleware;
+use axum::routing::{delete, get, post, put};
+use axum::Router;
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::services::{ServeDir, ServeFile};
+use tower_http::trace::TraceLayer;
+
+use crate::app::AppState;
+use crate::routes::access_log::{access_log_handler, record_access};
+use crate::routes::admin_routes::{
+    cancel_stream_handler, list_streams_handler, reload_config_handler, set_maintenance_handler,
+    tail_transcript_handler, workspace_reads_handler,
+};
+use crate::routes::api_routes::{
+    batch_handler, chat_handler, coded_rejections, conversation_stats_handler, create_batch_handler,
+    create_eval_handler, create_export_handler, create_schedule_handler, create_tool_handler,
+    create_webhook_handler, delete_conversation_handler, delete_export_handler,
+    delete_schedule_handler, delete_tool_handler, delete_webhook_handler,
+    encrypt_conversation_handler, eval_report_handler, export_all_handler,
+    export_conversation_handler, export_handler, get_profile_handler, list_conversations_handler,
+    list_evals_handler, list_messages_handler, list_models_handler, list_notifications_handler,
+    list_presets_handler, list_schedules_handler, list_starred_handler, list_tools_handler,
+    list_versions_handler, list_webhook_deliveries_handler, list_webhooks_handler,
+    lock_conversation_handler,
+    prompt_history_handler, pull_model_handler, regenerate_message_handler, retry_last_handler,
+    search_handler, server_config_handler, set_active_version_handler, star_message_handler,
+    system_status_handler, tokenize_handler, unlock_conversation_handler, unstar_message_handler,
+    update_conversation_handler, update_profile_handler, version_diff_handler, version_handler,
+};
+use crate::routes::artifact_routes::{
+    artifact_handler, diff_artifact_handler, download_artifact_handler, list_artifacts_handler,
+};
+use crate::routes::document_routes::{
+    attach_collection_handler, collection_chunks_handler, collection_handler,
+    conversation_collections_handler, create_collection_handler, delete_collection_handler,
+    detach_collection_handler, ingest_git_handler, ingestion_handler, list_collections_handler,
+    message_retrieval_handler, preview_chunks_handler, rate_retrieved_chunk_handler,
+    rechunk_collection_handler, retrieval_metrics_handler, search_collection_handler,
+};
+use crate::routes::health::readiness_handler;
+use crate::routes::limits::limited;
+use crate::routes::maintenance::{maintenance_guard, maintenance_status_handler};
+use crate::routes::mcp_routes::{mcp_message_handler, mcp_sse_handler};
+use crate::routes::slack_routes::{slack_command_handler, slack_events_handler};
+use crate::routes::ws_routes::{ws_chat_handler, ws_events_handler};
+
+/// Builds the full HTTP + WebSocket router over `state`.
+pub fn router(state: AppState) -> Router {
+    let frontend_dir = state.config.get().frontend_dir.clone();
+
+    let limits = state.config.get().route_limits.clone();
+
+    // Routes reading a conversation out, whose accesses are logged
+    let audited = middleware::from_fn_with_state(state.clone(), record_access);
+
+    // REST JSON API, one group per set of limits
+    let chat = Router::new()
+        .route("/api/chat", post(chat_handler))
+        .route("/api/conversations/{id}/retry-last", post(retry_last_handler))
+        .route("/api/messages/{id}/regenerate", post(regenerate_message_handler))
+        .route("/api/models/pull", post(pull_model_handler));
+    let lists = Router::new()
+        .route("/api/conversations", get(list_conversations_handler))
+        .route(
+            "/api/conversations/{id}/messages",
+            get(list_messages_handler).route_layer(audited.clone()),
+        )
+        .route("/api/conversations/{id}/access-log", get(access_log_handler))
+        .route("/api/conversations/{id}/artifacts", get(list_artifacts_handler))
+        .route("/api/conversations/{id}/collections", get(conversation_collections_handler))
+        .route("/api/collections", get(list_collections_handler))
+        .route("/api/collections/{name}/chunks", get(collection_chunks_handler))
+        .route("/api/search", get(search_handler))
+        .route("/api/evals", get(list_evals_handler))
+        .route("/api/models", get(list_models_handler))
+        .route("/api/presets", get(list_presets_handler))
+        .route("/api/messages/{id}/versions", get(list_versions_handler))
+        .route("/api/messages/{id}/retrieval", get(message_retrieval_handler))
+        .route("/api/retrieval/metrics", get(retrieval_metrics_handler))
+        .route("/api/messages/{id}/versions/{version}/diff", get(version_diff_handler))
+        .route("/api/starred", get(list_starred_handler))
+        .route("/api/me/prompts", get(prompt_history_handler))
+        .route("/api/tokenize", post(tokenize_handler))
+        .route("/api/tools", get(list_tools_handler))
+        .route("/api/schedules", get(list_schedules_handler))
+        .route("/api/webhooks", get(list_webhooks_handler))
+        .route("/api/webhooks/{id}/deliveries", get(list_webhook_deliveries_handler))
+        .route("/api/notifications", get(list_notifications_handler));
+    let other = Router::new()
+        .route(
+            "/api/conversations/{id}",
+            delete(delete_conversation_handler).patch(update_conversation_handler),
+        )
+        .route("/api/conversations/{id}/stats", get(conversation_stats_handler))
+        .route(
+            "/api/conversations/{id}/export",
+            get(export_conversation_handler).route_layer(audited),
+        )
+        .route("/api/conversations/{id}/encrypt", post(encrypt_conversation_handler))
+        .route("/api/conversations/{id}/unlock", post(unlock_conversation_handler))
+        .route("/api/conversations/{id}/lock", post(lock_conversation_handler))
+        .route("/api/me", get(get_profile_handler).patch(update_profile_handler))
+        .route("/api/system/status", get(system_status_handler))
+        .route("/api/config", get(server_config_handler))
+        .route("/api/version", get(version_handler))
+        .route("/api/evals", post(create_eval_handler))
+        .route("/api/evals/{id}", get(eval_report_handler))
+        .route("/api/batch", post(create_batch_handler))
+        .route("/api/batch/{id}", get(batch_handler))
+        .route("/api/export/all", get(export_all_handler))
+        .route("/api/exports", post(create_export_handler))
+        .route("/api/exports/{id}", get(export_handler).delete(delete_export_handler))
+        .route("/api/messages/{id}/active-version", put(set_active_version_handler))
+        .route("/api/artifacts/{id}", get(artifact_handler))
+        .route("/api/artifacts/{id}/download", get(download_artifact_handler))
+        .route("/api/artifacts/{id}/diff", post(diff_artifact_handler))
+        .route("/api/documents/git", post(ingest_git_handler))
+        .route("/api/documents/ingestions/{id}", get(ingestion_handler))
+        .route("/api/collections", post(create_collection_handler))
+        .route(
+            "/api/collections/{name}",
+            get(collection_handler).delete(delete_collection_handler),
+        )
+        .route("/api/collections/{name}/search", post(search_collection_handler))
+        .route("/api/collections/{name}/rechunk", post(rechunk_collection_handler))
+        .route("/api/collections/{name}/preview", post(preview_chunks_handler))
+        .route("/api/messages/{id}/retrieval/{chunk_id}", put(rate_retrieved_chunk_handler))
+        .route(
+            "/api/conversations/{id}/collections/{name}",
+            put(attach_collection_handler).delete(detach_collection_handler),
+        )
+        .route(
+            "/api/messages/{id}/star",
+            put(star_message_handler).delete(unstar_message_handler),
+        )
+        .route("/api/tools", post(create_tool_handler))
+        .route("/api/tools/{id}", delete(delete_tool_handler))
+        .route("/api/schedules", post(create_schedule_handler))
+        .route("/api/schedules/{id}", delete(delete_schedule_handler))
+        .route("/api/webhooks", post(create_webhook_handler))
+        .route("/api/webhooks/{id}", delete(delete_webhook_handler))
+        .route("/api/maintenance", get(maintenance_status_handler));
+    // MCP server (SSE transport); `send_message` runs chat turns, so it
+    // shares the chat limits.
+    let mcp = Router::new()
+        .route("/mcp/sse", get(mcp_sse_handler))
+        .route("/mcp/messages", post(mcp_message_handler));
+    // Slack app; turns run in the background, so these answer quickly.
+    let slack = Router::new()
+        .route("/slack/events", post(slack_events_handler))
+        .route("/slack/commands", post(slack_command_handler));
+
+    let router = Router::new()
+        .merge(limited(chat, "chat", limits.chat))
+        .merge(limited(lists, "lists", limits.lists))
+        .merge(limited(other, "other", limits.other))
+        .merge(limited(mcp, "mcp", limits.chat))
+        .merge(limited(slack, "slack", limits.other))
+        // Admin
+        .route("/api/admin/config/reload", post(reload_config_handler))
+        .route("/api/admin/transcripts/{id}", get(tail_transcript_handler))
+        .route("/api/admin/maintenance", put(set_maintenance_handler))
+        .route("/api/admin/streams", get(list_streams_handler))
+        .route("/api/admin/streams/{id}", delete(cancel_stream_handler))
+        .route("/api/admin/workspace/reads", get(workspace_reads_handler))
+        .route("/readyz", get(readiness_handler))
+        // WebSocket — streaming chat
+        .route("/ws/chat", get(ws_chat_handler))
+        .route("/ws/conversations/{id}/events", get(ws_events_handler))
+        .layer(middleware::from_fn_with_state(state.clone(), maintenance_guard))
+        .layer(middleware::map_response(coded_rejections));
+
+    let router = match frontend_dir {
+        // Same-origin build: serve the SPA, falling back to index.html so
+        // client-side routes load too.
+        Some(dir) => {
+            let index = ServeFile::new(dir.join("index.html"));
+            router.fallback_service(ServeDir::new(dir).fallback(index))
+        }
+        // ── CORS (allow a frontend served from another origin) ───────────────
+        None => router.layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Any)
+                .allow_headers(Any),
+        ),
+    };
+
+    router.layer(TraceLayer::new_for_http()).with_state(state)
+}
@@ -0,0 +1,88 @@
+//! Endpoints the Slack app is pointed at: the Events API request URL and the
+//! slash command URL. Both check Slack's signature, answer right away, and
+//! leave the turn to [`SlackBot`] in the background, as Slack gives up on
+//! responses slower than three seconds.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use serde_json::json;
+use tracing::{warn, Instrument};
+
+use crate::config::SlackConfig;
+use crate::service::slack::{self, SlackBot, SlackPayload, SlashCommand};
+
+/// POST `/slack/events` — Events API callbacks
+pub async fn slack_events_handler(
+    State(bot): State<SlackBot>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let slack = match verified(&bot, &headers, &body) {
+        Ok(slack) => slack,
+        Err(status) => return status.into_response(),
+    };
+    let payload: SlackPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    match payload {
+        SlackPayload::UrlVerification { challenge } => {
+            Json(json!({ "challenge": challenge })).into_response()
+        }
+        // Slack redelivers events it thinks went unanswered; the first
+        // delivery is already being answered.
+        SlackPayload::EventCallback { .. } if headers.contains_key("x-slack-retry-num") => {
+            StatusCode::OK.into_response()
+        }
+        SlackPayload::EventCallback { event, authorizations } => {
+            let bot_user = authorizations.into_iter().next().map(|a| a.user_id);
+            tokio::spawn(
+                async move { bot.handle_event(&slack, event, bot_user).await }.in_current_span(),
+            );
+            StatusCode::OK.into_response()
+        }
+        SlackPayload::Other => StatusCode::OK.into_response(),
+    }
+}
+
+/// POST `/slack/commands` — a slash command; its text is asked in a new
+/// thread
+pub async fn slack_command_handler(
+    State(bot): State<SlackBot>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let slack = match verified(&bot, &headers, &body) {
+        Ok(slack) => slack,
+        Err(status) => return status.into_response(),
+    };
+    let command: SlashCommand = match serde_urlencoded::from_bytes(&body) {
+        Ok(command) => command,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if command.text.trim().is_empty() {
+        // Shown only to the user who ran the command.
+        return "Ask a question after the command.".into_response();
+    }
+    tokio::spawn(async move { bot.handle_command(&slack, command).await }.in_current_span());
+    StatusCode::OK.into_response()
+}
+
+/// The Slack settings if the request is signed with the app's signing
+/// secret; 404 while the integration is off, 401 for a bad signature.
+fn verified(bot: &SlackBot, headers: &HeaderMap, body: &[u8]) -> Result<SlackConfig, StatusCode> {
+    let slack = bot.config().ok_or(StatusCode::NOT_FOUND)?;
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let timestamp = header("x-slack-request-timestamp");
+    let signature = header("x-slack-signature");
+    let now = Utc::now().timestamp();
+    if !slack::verify_signature(&slack.signing_secret, timestamp, body, signature, now) {
+        warn!("Refused a Slack request with a bad or stale signature");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(slack)
+}
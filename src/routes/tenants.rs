@@ -0,0 +1,83 @@
+//! Multi-tenant mode: each tenant's application is a router of its own, over
+//! its own schema; this one hands every request to the tenant it names.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::extract::Request;
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use axum::Router;
+use tower::ServiceExt;
+
+use crate::config::TenantsConfig;
+use crate::errors::AppError;
+use crate::routes::api_routes::error_response;
+
+/// Serves each request from `tenants[tenant]`, the tenant being named as
+/// [`tenant_of`] reads it. Requests naming no known tenant get 404.
+pub fn router(config: &TenantsConfig, tenants: HashMap<String, Router>) -> Router {
+    let config = config.clone();
+    let tenants = Arc::new(tenants);
+    Router::new().fallback(move |request: Request| {
+        let tenant = tenant_of(request.headers(), &config)
+            .map(|tenant| tenant.unwrap_or_default().to_string());
+        let router = tenant.as_ref().ok().and_then(|tenant| tenants.get(tenant).cloned());
+        async move {
+            match (tenant, router) {
+                (Err(e), _) => error_response(&e),
+                (Ok(_), Some(router)) => router.oneshot(request).await.into_response(),
+                (Ok(tenant), None) => error_response(&AppError::RecordNotFound {
+                    entity_type: "Tenant".to_string(),
+                    id: tenant,
+                }),
+            }
+        }
+    })
+}
+
+/// The tenant a request names. With `trust_header`, the value of `header`
+/// names it, as set by a proxy in front; otherwise, and without the header,
+/// the first label of a `Host` under `base_domain` does (`acme` for
+/// `acme.chat.example.com` under `chat.example.com`), and an untrusted header
+/// naming another tenant is rejected. IP addresses and `base_domain` itself
+/// name none.
+fn tenant_of<'a>(
+    headers: &'a HeaderMap,
+    config: &TenantsConfig,
+) -> Result<Option<&'a str>, AppError> {
+    let value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let named = value(config.header.as_str());
+    if let Some(tenant) = named.filter(|_| config.trust_header) {
+        return Ok(Some(tenant));
+    }
+    let tenant = value(header::HOST.as_str())
+        .and_then(|host| subdomain_of(host, config.base_domain.as_deref()?));
+    match named {
+        Some(named) if Some(named) != tenant => Err(AppError::InvalidField {
+            field_name: config.header.clone(),
+            message: "names another tenant than the Host".to_string(),
+        }),
+        _ => Ok(tenant),
+    }
+}
+
+/// The first label of `host` when it is a subdomain of `base_domain`.
+fn subdomain_of<'a>(host: &'a str, base_domain: &str) -> Option<&'a str> {
+    // A bracketed IPv6 address, with or without a port.
+    if host.starts_with('[') {
+        return None;
+    }
+    let host = host.split(':').next().unwrap_or(host);
+    if host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+    let base_domain = base_domain.trim_matches('.');
+    let cut = host.len().checked_sub(base_domain.len() + 1)?;
+    let (subdomain, base) = (host.get(..cut)?, host.get(cut..)?);
+    if !base.strip_prefix('.')?.eq_ignore_ascii_case(base_domain) {
+        return None;
+    }
+    subdomain.split('.').next().filter(|label| !label.is_empty())
+}
@@ -1,32 +1,549 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::State;
+use axum::extract::{Path, Query, State};
 use axum::response::IntoResponse;
-use tracing::{error, info, warn};
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, field, info, instrument, warn, Instrument, Span};
+use uuid::Uuid;
 
-use crate::models::{ChatRequest, WsChatRequest, WsEvent};
+use crate::agent::StreamUpdate;
+use crate::models::{
+    ActiveStream, ChatRequest, CompletionStats, ErrorCode, FinishReason, JsonValidation,
+    ResponseFormat, WsChatRequest, WsControl, WsEncoding, WsEvent, WsFrame,
+};
+use crate::errors::AppError;
+use crate::db::stream_repository::StreamRepository;
+use crate::events::{EventBus, MAX_EVENT_BYTES};
 use crate::service::chat_service::ChatService;
+use crate::service::chunking::Rechunker;
+use crate::service::json_mode::JsonStreamValidator;
+
+/// Turns a single socket may have in flight at once.
+const MAX_STREAMS_PER_SOCKET: usize = 4;
+/// Frames a slow watcher may fall behind by before it misses some.
+const WATCH_BUFFER: usize = 256;
+
+/// Bus channel carrying the frames of every turn, for watchers.
+const LIVE_CHANNEL: &str = "live_streams";
+
+/// Socket id of the turns [`LiveStreams::announce`] makes up.
+const ANNOUNCED_SOCKET: &str = "server";
+
+/// A turn: the socket it streams to and its stream id there.
+type TurnKey = (String, String);
+
+/// Turns streaming right now, by conversation, so sockets other than the one
+/// that started a turn can watch it (`GET /ws/conversations/{id}/events`).
+///
+/// Frames travel through the [`EventBus`], so a turn streamed by one server
+/// reaches watchers connected to any other server on the same bus. Each turn
+/// and its reply so far are also kept in `active_streams`, for watchers that
+/// connect to a server which has not heard of it from the start.
+#[derive(Clone)]
+pub struct LiveStreams {
+    registry: Arc<Registry>,
+    streams: StreamRepository,
+    /// Frames waiting to be stored and published, in order.
+    outbox: mpsc::UnboundedSender<Outgoing>,
+}
+
+/// What the bus has carried so far, by conversation.
+#[derive(Default)]
+struct Registry {
+    conversations: Mutex<HashMap<Uuid, LiveConversation>>,
+}
+
+struct LiveConversation {
+    tx: broadcast::Sender<LiveFrame>,
+    /// Turns in progress: their `stream_start` and the text streamed so far,
+    /// replayed to watchers that join midway.
+    turns: HashMap<TurnKey, (WsEvent, String)>,
+}
+
+/// One frame of a turn, as published on the bus.
+#[derive(Clone, Serialize, Deserialize)]
+struct LiveFrame {
+    conversation_id: Uuid,
+    /// The socket the turn streams to; stream ids are only unique per socket.
+    socket_id: String,
+    /// Bytes of the reply streamed before this frame.
+    #[serde(default)]
+    offset: usize,
+    frame: WsFrame,
+}
+
+impl LiveFrame {
+    fn turn(&self) -> Option<TurnKey> {
+        Some((self.socket_id.clone(), self.frame.stream_id.clone()?))
+    }
+}
+
+/// A frame on its way out, and whether its turn may be stored.
+struct Outgoing {
+    live: LiveFrame,
+    store: bool,
+}
+
+/// Where a watcher starts: catch-up frames, the reply length each turn has
+/// reached in them, and the frames that follow.
+struct Watch {
+    catch_up: Vec<WsFrame>,
+    seen: HashMap<TurnKey, usize>,
+    rx: broadcast::Receiver<LiveFrame>,
+}
+
+impl LiveStreams {
+    /// Publishes turns on `bus` and follows the ones every server publishes
+    /// there. Spawns tasks, so it must be called within a Tokio runtime.
+    pub fn new(bus: Arc<dyn EventBus>, streams: StreamRepository) -> Self {
+        let (outbox, pending) = mpsc::unbounded_channel();
+        tokio::spawn(publish_frames(pending, bus.clone(), streams.clone()));
+
+        let registry = Arc::new(Registry::default());
+        let following = registry.clone();
+        tokio::spawn(async move {
+            let mut frames = match bus.subscribe(LIVE_CHANNEL).await {
+                Ok(frames) => frames,
+                Err(e) => {
+                    error!("Live turns cannot be watched: {e}");
+                    return;
+                }
+            };
+            while let Some(payload) = frames.next().await {
+                match serde_json::from_str::<LiveFrame>(&payload) {
+                    Ok(live) => following.record(live),
+                    Err(e) => warn!("Unreadable live frame: {e}"),
+                }
+            }
+        });
+        Self { registry, streams, outbox }
+    }
+
+    /// Subscribes a watcher to the conversation's turns. Catch-up frames hold
+    /// each running turn's `stream_start` and its text so far, after `resume`
+    /// (a stream id and the bytes of it the watcher already has).
+    async fn watch(&self, conversation_id: Uuid, resume: Option<(&str, usize)>) -> Watch {
+        let (mut turns, rx) = {
+            let mut conversations = self.registry.lock();
+            let live = conversations.entry(conversation_id).or_insert_with(|| {
+                LiveConversation { tx: broadcast::channel(WATCH_BUFFER).0, turns: HashMap::new() }
+            });
+            (live.turns.clone(), live.tx.subscribe())
+        };
+        // Turns this server heard of too late, or with frames still on their
+        // way, are further along in the database.
+        match self.streams.find_by_conversation(conversation_id).await {
+            Ok(stored) => {
+                for stream in stored {
+                    let Ok(start) = serde_json::from_str::<WsEvent>(&stream.start_event) else {
+                        continue;
+                    };
+                    let turn = (stream.socket_id, stream.stream_id);
+                    let known = turns.get(&turn).map_or(0, |(_, text)| text.len());
+                    if !turns.contains_key(&turn) || stream.content.len() > known {
+                        turns.insert(turn, (start, stream.content));
+                    }
+                }
+            }
+            Err(e) => warn!("Catching up from memory only: {e}"),
+        }
+
+        let mut catch_up = Vec::new();
+        let mut seen = HashMap::new();
+        for (turn, (start, text)) in turns {
+            let stream_id = Some(turn.1.clone());
+            let from = match resume {
+                Some((id, offset)) if id == turn.1 && text.is_char_boundary(offset) => offset,
+                _ => 0,
+            };
+            catch_up.push(WsFrame { stream_id: stream_id.clone(), event: start });
+            if text.len() > from {
+                let event = WsEvent::StreamChunk { content: text[from..].to_string() };
+                catch_up.push(WsFrame { stream_id, event });
+            }
+            seen.insert(turn, text.len());
+        }
+        Watch { catch_up, seen, rx }
+    }
+
+    /// Sends a reply saved outside of any socket's turn, such as a scheduled
+    /// prompt's, to its conversation's watchers as a turn of its own:
+    /// `stream_start`, the whole reply as one `stream_chunk`, then `stream_end`.
+    pub fn announce(&self, message: &crate::models::Message, stats: CompletionStats) {
+        let stream_id = Some(message.id.to_string());
+        let events = [
+            WsEvent::StreamStart { conversation_id: message.conversation_id, history_cutoff: None },
+            WsEvent::StreamChunk { content: message.content.clone() },
+            WsEvent::StreamEnd {
+                message_id: message.id,
+                full_content: message.content.clone(),
+                stats,
+                json_validation: None,
+            },
+        ];
+        for event in events {
+            let frame = WsFrame { stream_id: stream_id.clone(), event };
+            self.publish(message.conversation_id, ANNOUNCED_SOCKET, &frame, false);
+        }
+    }
+
+    /// Queues `frame`, sent to socket `socket_id`, for the conversation's
+    /// watchers. Only turns that may be `stored` are kept in the database.
+    fn publish(&self, conversation_id: Uuid, socket_id: &str, frame: &WsFrame, store: bool) {
+        let live = LiveFrame {
+            conversation_id,
+            socket_id: socket_id.to_string(),
+            offset: 0,
+            frame: frame.clone(),
+        };
+        // Fails only once the publisher is gone, with the runtime.
+        let _ = self.outbox.send(Outgoing { live, store });
+    }
+}
+
+/// Stores and publishes queued frames in order. Each batch is stored before
+/// it is published, so a frame a watcher's catch-up lacks is still ahead of
+/// it on the bus.
+async fn publish_frames(
+    mut pending: mpsc::UnboundedReceiver<Outgoing>,
+    bus: Arc<dyn EventBus>,
+    streams: StreamRepository,
+) {
+    let node_id = uuid::Uuid::new_v4().to_string();
+    let mut offsets: HashMap<TurnKey, usize> = HashMap::new();
+    while let Some(first) = pending.recv().await {
+        let mut batch = vec![first];
+        while let Ok(next) = pending.try_recv() {
+            batch.push(next);
+        }
+
+        // Chunks of one turn in a row are stored with a single update.
+        let mut appended: Option<(TurnKey, String)> = None;
+        for Outgoing { live, store } in &mut batch {
+            let Some(turn) = live.turn() else { continue };
+            let offset = offsets.entry(turn.clone()).or_default();
+            live.offset = *offset;
+            let chunk = match &live.frame.event {
+                WsEvent::StreamChunk { content } => Some(content.as_str()),
+                _ => None,
+            };
+            *offset += chunk.map_or(0, str::len);
+            if let Some((key, text)) = appended.take_if(|(key, _)| chunk.is_none() || *key != turn)
+            {
+                store_chunks(&streams, &key, &text).await;
+            }
+            if !*store {
+                continue;
+            }
+            let stored = match &live.frame.event {
+                WsEvent::StreamStart { .. } => {
+                    let stream = ActiveStream {
+                        socket_id: turn.0.clone(),
+                        stream_id: turn.1.clone(),
+                        conversation_id: live.conversation_id,
+                        node_id: node_id.clone(),
+                        start_event: serde_json::to_string(&live.frame.event).unwrap_or_default(),
+                        content: String::new(),
+                    };
+                    streams.start(&stream).await
+                }
+                WsEvent::StreamChunk { content } => {
+                    appended.get_or_insert_with(|| (turn, String::new())).1.push_str(content);
+                    Ok(())
+                }
+                WsEvent::StreamEnd { .. }
+                | WsEvent::ModelMissing { .. }
+                | WsEvent::ModerationBlocked { .. }
+                | WsEvent::Error { .. } => {
+                    offsets.remove(&turn);
+                    streams.finish(&turn.0, &turn.1).await
+                }
+                WsEvent::Queued { .. }
+                | WsEvent::ToolCall { .. }
+                | WsEvent::StreamSources { .. }
+                | WsEvent::Artifact { .. }
+                | WsEvent::Busy { .. }
+                | WsEvent::Hello { .. }
+                | WsEvent::FollowUpSuggestions { .. } => Ok(()),
+            };
+            if let Err(e) = stored {
+                warn!("Failed to store a live frame: {e}");
+            }
+        }
+        if let Some((key, text)) = appended {
+            store_chunks(&streams, &key, &text).await;
+        }
+
+        for Outgoing { mut live, .. } in batch {
+            let mut payload = serde_json::to_string(&live).unwrap_or_default();
+            // Long replies do not fit in one event; watchers rebuild them
+            // from the chunks they saw.
+            if let WsEvent::StreamEnd { full_content, .. } = &mut live.frame.event {
+                if payload.len() > MAX_EVENT_BYTES {
+                    full_content.clear();
+                    payload = serde_json::to_string(&live).unwrap_or_default();
+                }
+            }
+            if let Err(e) = bus.publish(LIVE_CHANNEL, &payload).await {
+                warn!("Failed to publish a live frame: {e}");
+            }
+        }
+    }
+}
+
+async fn store_chunks(streams: &StreamRepository, (socket_id, stream_id): &TurnKey, text: &str) {
+    if let Err(e) = streams.append(socket_id, stream_id, text).await {
+        warn!("Failed to store a live frame: {e}");
+    }
+}
+
+impl Registry {
+    /// Passes a frame from the bus on to the conversation's watchers and
+    /// tracks the turn's progress for later ones.
+    fn record(&self, mut live: LiveFrame) {
+        let Some(turn) = live.turn() else { return };
+        let mut conversations = self.lock();
+        let conversation = conversations.entry(live.conversation_id).or_insert_with(|| {
+            LiveConversation { tx: broadcast::channel(WATCH_BUFFER).0, turns: HashMap::new() }
+        });
+        match &mut live.frame.event {
+            WsEvent::StreamStart { .. } => {
+                conversation.turns.insert(turn, (live.frame.event.clone(), String::new()));
+            }
+            WsEvent::StreamChunk { content } => {
+                if let Some((_, text)) = conversation.turns.get_mut(&turn) {
+                    text.push_str(content);
+                }
+            }
+            WsEvent::StreamEnd { full_content, .. } => {
+                if let Some((_, text)) = conversation.turns.remove(&turn) {
+                    if full_content.is_empty() {
+                        *full_content = text;
+                    }
+                }
+            }
+            WsEvent::ModelMissing { .. }
+            | WsEvent::ModerationBlocked { .. }
+            | WsEvent::Error { .. } => {
+                conversation.turns.remove(&turn);
+            }
+            WsEvent::Queued { .. }
+            | WsEvent::ToolCall { .. }
+            | WsEvent::StreamSources { .. }
+            | WsEvent::Artifact { .. }
+            | WsEvent::Busy { .. }
+            | WsEvent::Hello { .. }
+            | WsEvent::FollowUpSuggestions { .. } => {}
+        }
+        let empty = conversation.turns.is_empty();
+        // Fails only when nobody is watching.
+        if conversation.tx.send(live.clone()).is_err() && empty {
+            conversations.remove(&live.conversation_id);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, LiveConversation>> {
+        self.conversations.lock().expect("live streams poisoned")
+    }
+}
+
+/// Where `GET /ws/conversations/{id}/events` picks up a turn the watcher
+/// already has part of.
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    stream_id: Option<String>,
+    /// Bytes of that turn's reply already received.
+    offset: Option<usize>,
+}
 
 /// GET `/ws/chat` — upgrades to a WebSocket for streaming chat.
 pub async fn ws_chat_handler(
     ws: WebSocketUpgrade,
     State(svc): State<ChatService>,
+    State(live): State<LiveStreams>,
+) -> impl IntoResponse {
+    ws.on_upgrade(|socket| handle_socket(socket, svc, live))
+}
+
+/// GET `/ws/conversations/{id}/events` — upgrades to a WebSocket that
+/// carries every turn streaming in a conversation, whichever socket (and
+/// whichever server) started it.
+///
+/// Frames are the ones `/ws/chat` sends, with the stream id of the turn they
+/// belong to. A watcher that joins midway first gets the turn's
+/// `stream_start` and one `stream_chunk` with the text so far; reconnecting
+/// with `?stream_id=...&offset=n` skips the first `n` bytes of that turn's
+/// text. Messages sent by the client are ignored.
+pub async fn ws_events_handler(
+    ws: WebSocketUpgrade,
+    Path(conversation_id): Path<Uuid>,
+    Query(query): Query<WatchQuery>,
+    State(svc): State<ChatService>,
+    State(live): State<LiveStreams>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, svc))
+    ws.on_upgrade(move |socket| watch_conversation(socket, conversation_id, query, svc, live))
+}
+
+/// Forwards a conversation's live frames until either side goes away.
+async fn watch_conversation(
+    socket: WebSocket,
+    conversation_id: Uuid,
+    query: WatchQuery,
+    svc: ChatService,
+    live: LiveStreams,
+) {
+    let (mut sink, mut incoming) = socket.split();
+    let send = |frame: &WsFrame| {
+        let json = serde_json::to_string(frame).unwrap_or_default();
+        Message::Text(json.into())
+    };
+    // Watchers must be able to read the conversation.
+    if let Err(e) = svc.check_readable(conversation_id).await {
+        let event = WsEvent::Error { code: e.code(), message: e.to_string() };
+        let frame = WsFrame { stream_id: None, event };
+        let _ = sink.send(send(&frame)).await;
+        return;
+    }
+
+    let resume = query.stream_id.as_deref().map(|id| (id, query.offset.unwrap_or(0)));
+    let Watch { catch_up, mut seen, mut rx } = live.watch(conversation_id, resume).await;
+    info!("Watching conversation {conversation_id}");
+    for frame in &catch_up {
+        if sink.send(send(frame)).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            frame = rx.recv() => match frame {
+                Ok(live) => {
+                    let Some(frame) = unseen(&mut seen, live) else { continue };
+                    if sink.send(send(&frame)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Watcher of conversation {conversation_id} missed {skipped} frames");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = incoming.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    info!("Stopped watching conversation {conversation_id}");
+}
+
+/// The part of `live` a watcher has not had yet, given the reply length it
+/// has reached for each turn.
+fn unseen(seen: &mut HashMap<TurnKey, usize>, live: LiveFrame) -> Option<WsFrame> {
+    let turn = live.turn()?;
+    let LiveFrame { offset, mut frame, .. } = live;
+    match &mut frame.event {
+        WsEvent::StreamStart { .. } => {
+            // Already sent while catching up.
+            if seen.contains_key(&turn) {
+                return None;
+            }
+            seen.insert(turn, 0);
+        }
+        WsEvent::StreamChunk { content } => {
+            let reached = seen.entry(turn).or_insert(offset);
+            let end = offset + content.len();
+            if end <= *reached {
+                return None;
+            }
+            let skip = *reached - offset.min(*reached);
+            if content.is_char_boundary(skip) {
+                content.drain(..skip);
+            }
+            *reached = end;
+        }
+        WsEvent::StreamEnd { .. }
+        | WsEvent::ModelMissing { .. }
+        | WsEvent::ModerationBlocked { .. }
+        | WsEvent::Error { .. } => {
+            seen.remove(&turn);
+        }
+        WsEvent::Queued { .. }
+        | WsEvent::ToolCall { .. }
+        | WsEvent::StreamSources { .. }
+        | WsEvent::Artifact { .. }
+        | WsEvent::Busy { .. }
+        | WsEvent::Hello { .. }
+        | WsEvent::FollowUpSuggestions { .. } => {}
+    }
+    Some(frame)
 }
 
 /// Handles a single WebSocket connection.
 ///
 /// Protocol:
-/// - Client sends JSON `{ "conversation_id": "...|null", "message": "..." }`
-/// - Server streams back:
+/// - Client sends JSON `{ "conversation_id": "...|null", "message": "...", "ephemeral": false, "stream_id": "..." }`,
+///   or `{ "conversation_id": "...", "retry": true }` to answer the last unanswered message again;
+///   either may add `"response_format": "json"` to ask for a JSON reply and
+///   `"verbosity": "concise|normal|detailed"` to set the reply length; a new message may add
+///   `"quoted_message_id": "..."` when it opens by quoting an earlier one and `"stateless": true`
+///   to stop replaying the conversation's history (`false` resumes it), and any request
+///   `"chunking": "token|word|sentence"` to have chunks grouped into whole words or sentences,
+///   and `"replace": true` to cancel a turn it would otherwise be refused for (see below)
+/// - Server streams back, every event carrying the request's `stream_id`:
 ///   1. `{ "type": "stream_start", "conversation_id": "..." }`
-///   2. `{ "type": "stream_chunk", "content": "..." }` (repeated)
-///   3. `{ "type": "stream_end",   "message_id": "..." }`
-///   or `{ "type": "error", "message": "..." }` on failure.
-async fn handle_socket(mut socket: WebSocket, svc: ChatService) {
+///   2. `{ "type": "queued", "position": n }` (only while waiting for Ollama)
+///   3. `{ "type": "stream_chunk", "content": "..." }` (repeated)
+///   4. `{ "type": "stream_sources", "sources": [...] }` (only if any were cited)
+///   5. `{ "type": "stream_end", "message_id": "...", "model": "...", "prompt_tokens": n,
+///      "completion_tokens": n, "finish_reason": "stop|length|cancelled|error", "duration_ms": n,
+///      "json_validation": { "status": "valid|repaired|invalid", "error": "..." } }`
+///      or `{ "type": "model_missing", "model": "..." }` when the model must be pulled first,
+///      or `{ "type": "error", "code": "...", "message": "..." }` on any other failure,
+///      `code` being one of the `ErrorCode`s.
+///   6. `{ "type": "follow_up_suggestions", "message_id": "...", "suggestions": [...] }`
+///      some time after `stream_end`, unless suggestions are off.
+/// - Frames are JSON text until the client sends `{ "type": "hello", "encoding": "msgpack" }`;
+///   the server answers `{ "type": "hello", "encoding": "msgpack" }` and sends every later
+///   frame as MessagePack (with field names) in a binary message. Requests may be sent in
+///   either encoding at any time: text messages are read as JSON, binary ones as MessagePack.
+///
+/// Requests are handled concurrently: a client may start another turn before
+/// the previous one has finished, and events of different turns interleave.
+/// Only one turn per conversation streams at a time, though; see
+/// [`SocketTurns`] for what happens to a request that collides with one.
+async fn handle_socket(socket: WebSocket, svc: ChatService, live: LiveStreams) {
     info!("WebSocket client connected");
 
-    while let Some(msg) = socket.recv().await {
+    let (mut sink, mut incoming) = socket.split();
+    let (out_tx, mut out_rx) = mpsc::channel::<WsFrame>(256);
+
+    // A single writer owns the sink; turns send it frames through `out_tx`.
+    let writer = tokio::spawn(async move {
+        let mut encoding = WsEncoding::Json;
+        while let Some(frame) = out_rx.recv().await {
+            let Some(msg) = encode(&frame, encoding) else { continue };
+            // The acknowledgement itself goes out in the old encoding.
+            if let WsEvent::Hello { encoding: chosen } = frame.event {
+                encoding = chosen;
+            }
+            if sink.send(msg).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let turns = SocketTurns::default();
+    let socket_id = uuid::Uuid::new_v4().to_string();
+
+    while let Some(msg) = incoming.next().await {
         let msg = match msg {
             Ok(m) => m,
             Err(e) => {
@@ -35,106 +552,474 @@ async fn handle_socket(mut socket: WebSocket, svc: ChatService) {
             }
         };
 
-        // Only handle text messages
-        let text = match &msg {
-            Message::Text(t) => t.to_string(),
+        match &msg {
+            Message::Text(_) | Message::Binary(_) => {}
             Message::Close(_) => break,
             _ => continue,
-        };
+        }
+
+        if let Ok(WsControl::Hello { encoding }) = decode::<WsControl>(&msg) {
+            let event = WsEvent::Hello { encoding };
+            let _ = out_tx.send(WsFrame { stream_id: None, event }).await;
+            continue;
+        }
 
         // Parse the incoming request
-        let ws_req: WsChatRequest = match serde_json::from_str(&text) {
+        let ws_req: WsChatRequest = match decode(&msg) {
             Ok(r) => r,
             Err(e) => {
-                send_event(&mut socket, &WsEvent::Error {
-                    message: format!("Invalid request: {e}"),
-                }).await;
+                // Tag the error with the stream id if the frame had one.
+                let stream_id = decode::<Tagged>(&msg).ok().and_then(|t| t.stream_id);
+                let _ = out_tx
+                    .send(WsFrame {
+                        stream_id,
+                        event: WsEvent::Error {
+                            code: ErrorCode::InvalidRequest,
+                            message: format!("Invalid request: {e}"),
+                        },
+                    })
+                    .await;
                 continue;
             }
         };
 
-        // Build a ChatRequest for the service layer
-        let chat_request = ChatRequest {
-            conversation_id: ws_req.conversation_id,
-            message: ws_req.message,
+        let stream_id = ws_req
+            .stream_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let out = StreamOut {
+            stream_id: stream_id.clone(),
+            tx: out_tx.clone(),
+            live: live.clone(),
+            socket_id: socket_id.clone(),
+            conversation_id: None,
+            store: false,
         };
 
-        // ── Prepare: validate, resolve conversation, save user message ────
-        let ctx = match svc.prepare_chat(chat_request).await {
-            Ok(ctx) => ctx,
-            Err(e) => {
-                send_event(&mut socket, &WsEvent::Error {
-                    message: e.to_string(),
-                }).await;
+        let turn = match turns.admit(&stream_id, ws_req.conversation_id, ws_req.replace) {
+            Ok(turn) => turn,
+            Err(refusal) => {
+                out.send(*refusal).await;
                 continue;
             }
         };
 
-        // ── Notify client: streaming is starting ─────────────────────────
-        send_event(&mut socket, &WsEvent::StreamStart {
-            conversation_id: ctx.conversation_id.clone(),
-        }).await;
+        let svc = svc.clone();
+        tokio::spawn(async move {
+            // A replaced turn saves what it streamed before this one starts.
+            if let Some(replaced) = &turn.replacing {
+                replaced.cancelled().await;
+            }
+            run_turn(&svc, ws_req, out, &turn).await;
+        });
+    }
 
-        // ── Stream tokens from Ollama via a channel ──────────────────────
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(64);
-        let agent = svc.agent().clone();
-        let conv_id = ctx.conversation_id.clone();
-        let history = ctx.history.clone();
-        let user_msg = ctx.user_message.clone();
+    // In-flight turns keep running until they notice the writer is gone, so
+    // whatever was generated so far is still persisted.
+    drop(out_tx);
+    let _ = writer.await;
+    info!("WebSocket client disconnected");
+}
 
-        let stream_handle = tokio::spawn(async move {
-            agent.stream_chat(&conv_id, &history, &user_msg, tx).await
-        });
+/// Just the stream id of a client message, for tagging the error about a
+/// request that could not be read as a whole.
+#[derive(Deserialize)]
+struct Tagged {
+    stream_id: Option<String>,
+}
 
-        // Forward each chunk to the WebSocket client
-        let mut full_content = String::new();
-        while let Some(chunk) = rx.recv().await {
-            full_content.push_str(&chunk);
-            send_event(&mut socket, &WsEvent::StreamChunk {
-                content: chunk,
-            }).await;
-        }
-
-        // Wait for the agent task to finish
-        match stream_handle.await {
-            Ok(Ok(())) => {
-                // Persist the complete assistant message
-                match svc.save_assistant_message(&ctx.conversation_id, &full_content).await {
-                    Ok(msg) => {
-                        send_event(&mut socket, &WsEvent::StreamEnd {
-                            message_id: msg.id,
-                            full_content: full_content.clone(),
-                        }).await;
-                    }
-                    Err(e) => {
-                        error!("Failed to save assistant message: {e}");
-                        send_event(&mut socket, &WsEvent::Error {
-                            message: format!("Failed to save response: {e}"),
-                        }).await;
-                    }
+/// Reads a client message: JSON from text messages, MessagePack from binary ones.
+fn decode<T: DeserializeOwned>(msg: &Message) -> Result<T, String> {
+    match msg {
+        Message::Text(text) => serde_json::from_str(text).map_err(|e| e.to_string()),
+        Message::Binary(bytes) => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+        _ => Err("not a data message".to_string()),
+    }
+}
+
+/// Writes `frame` in the socket's current encoding.
+fn encode(frame: &WsFrame, encoding: WsEncoding) -> Option<Message> {
+    match encoding {
+        WsEncoding::Json => {
+            serde_json::to_string(frame).ok().map(|json| Message::Text(json.into()))
+        }
+        WsEncoding::Msgpack => {
+            rmp_serde::to_vec_named(frame).ok().map(|bytes| Message::Binary(bytes.into()))
+        }
+    }
+}
+
+/// Generates follow-up suggestions for a reply in the background, so the
+/// turn's stream slot is free meanwhile, and sends them as
+/// `follow_up_suggestions`.
+fn send_suggestions(svc: &ChatService, conversation_id: Uuid, message_id: Uuid, out: &StreamOut) {
+    let (svc, out) = (svc.clone(), out.clone());
+    tokio::spawn(
+        async move {
+            match svc.suggest_follow_ups(conversation_id, message_id).await {
+                Ok(suggestions) if suggestions.is_empty() => {}
+                Ok(suggestions) => {
+                    out.send(WsEvent::FollowUpSuggestions { message_id, suggestions }).await;
                 }
+                Err(e) => warn!("Failed to suggest follow-ups for message {message_id}: {e}"),
             }
-            Ok(Err(e)) => {
-                error!("Agent streaming failed: {e}");
-                send_event(&mut socket, &WsEvent::Error {
-                    message: e.to_string(),
-                }).await;
+        }
+        .in_current_span(),
+    );
+}
+
+/// Sends events for one turn, tagged with its stream id. Once the turn's
+/// conversation is known, they also go to the conversation's watchers.
+#[derive(Clone)]
+struct StreamOut {
+    stream_id: String,
+    tx: mpsc::Sender<WsFrame>,
+    live: LiveStreams,
+    socket_id: String,
+    conversation_id: Option<Uuid>,
+    /// Whether the turn may be kept in the database for watchers.
+    store: bool,
+}
+
+impl StreamOut {
+    /// Returns `false` once the socket is gone; watchers keep getting events.
+    async fn send(&self, event: WsEvent) -> bool {
+        let frame = WsFrame { stream_id: Some(self.stream_id.clone()), event };
+        if let Some(conversation_id) = self.conversation_id {
+            self.live.publish(conversation_id, &self.socket_id, &frame, self.store);
+        }
+        self.tx.send(frame).await.is_ok()
+    }
+}
+
+/// The turns one socket has in flight.
+///
+/// Each conversation streams at most one turn per socket. A request for a
+/// conversation, or under a stream id, that already has a turn running is:
+/// - refused with `busy` by default, the running turn carrying on;
+/// - with `replace`, admitted in its place: the running turn is cancelled,
+///   ends with what it streamed so far, and the new one starts after that.
+///
+/// A cancelled turn stays listed until it has ended, but no longer collides.
+#[derive(Clone, Default)]
+struct SocketTurns {
+    inner: Arc<Mutex<TurnTable>>,
+}
+
+#[derive(Default)]
+struct TurnTable {
+    next_id: u64,
+    running: HashMap<u64, RunningTurn>,
+}
+
+struct RunningTurn {
+    stream_id: String,
+    /// Unknown until prepared, for a turn that starts a new conversation.
+    conversation_id: Option<Uuid>,
+    /// Cancelled when another request replaces the turn.
+    cancel: CancellationToken,
+    /// Cancelled once the turn has ended.
+    ended: CancellationToken,
+}
+
+/// A turn admitted by [`SocketTurns::admit`]; it ends when dropped.
+struct Turn {
+    id: u64,
+    turns: SocketTurns,
+    cancel: CancellationToken,
+    ended: CancellationToken,
+    /// Ends the turn this one replaces, which must finish first.
+    replacing: Option<CancellationToken>,
+}
+
+impl SocketTurns {
+    /// Lists a new turn, or returns the event refusing it.
+    fn admit(
+        &self,
+        stream_id: &str,
+        conversation_id: Option<Uuid>,
+        replace: bool,
+    ) -> Result<Turn, Box<WsEvent>> {
+        let mut table = self.inner.lock().expect("socket turns poisoned");
+        let in_the_way = table.running.values().find(|running| {
+            !running.cancel.is_cancelled()
+                && (running.stream_id == stream_id
+                    || conversation_id.is_some() && running.conversation_id == conversation_id)
+        });
+        let replacing = match in_the_way {
+            Some(running) if !replace => {
+                return Err(Box::new(WsEvent::Busy {
+                    active_stream_id: running.stream_id.clone(),
+                    conversation_id: running.conversation_id,
+                }));
             }
-            Err(e) => {
-                error!("Agent task panicked: {e}");
-                send_event(&mut socket, &WsEvent::Error {
-                    message: "Internal error during streaming".to_string(),
-                }).await;
+            Some(running) => {
+                info!("Stream '{}' replaced by '{stream_id}'", running.stream_id);
+                running.cancel.cancel();
+                Some(running.ended.clone())
+            }
+            None if table.running.len() >= MAX_STREAMS_PER_SOCKET => {
+                return Err(Box::new(WsEvent::Error {
+                    code: ErrorCode::Overloaded,
+                    message: format!("Too many concurrent streams (max {MAX_STREAMS_PER_SOCKET})"),
+                }));
             }
+            None => None,
+        };
+
+        let id = table.next_id;
+        table.next_id += 1;
+        let (cancel, ended) = (CancellationToken::new(), CancellationToken::new());
+        table.running.insert(id, RunningTurn {
+            stream_id: stream_id.to_string(),
+            conversation_id,
+            cancel: cancel.clone(),
+            ended: ended.clone(),
+        });
+        Ok(Turn { id, turns: self.clone(), cancel, ended, replacing })
+    }
+}
+
+impl Turn {
+    /// Records the conversation of a turn that started a new one, so later
+    /// requests for it collide with the turn.
+    fn set_conversation(&self, conversation_id: Uuid) {
+        let mut table = self.turns.inner.lock().expect("socket turns poisoned");
+        if let Some(running) = table.running.get_mut(&self.id) {
+            running.conversation_id = Some(conversation_id);
         }
     }
 
-    info!("WebSocket client disconnected");
+    /// Sent instead of a reply when the turn was replaced before any of it
+    /// streamed.
+    fn replaced_event() -> WsEvent {
+        WsEvent::Error {
+            code: ErrorCode::Cancelled,
+            message: "Replaced by a newer request".to_string(),
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.turns.inner.lock().expect("socket turns poisoned").running.remove(&self.id);
+        self.ended.cancel();
+    }
 }
 
-/// Helper: serialize a `WsEvent` and send it over the socket.
-async fn send_event(socket: &mut WebSocket, event: &WsEvent) {
-    if let Ok(json) = serde_json::to_string(event) {
-        let _ = socket.send(Message::Text(json.into())).await;
+/// Runs one chat turn: prepare, stream from the agent, persist, report.
+#[instrument(name = "ws_turn", skip_all, fields(
+    stream_id = %out.stream_id,
+    conversation_id = field::Empty,
+    retry = ws_req.retry,
+))]
+async fn run_turn(svc: &ChatService, ws_req: WsChatRequest, mut out: StreamOut, turn: &Turn) {
+    // Replaced while still waiting for the turn before it.
+    if turn.cancel.is_cancelled() {
+        out.send(Turn::replaced_event()).await;
+        return;
+    }
+    let response_format = ws_req.response_format;
+    let verbosity = ws_req.verbosity;
+    let chunking = ws_req.chunking;
+    // ── Prepare: validate, resolve conversation, save user message ────────
+    let prepared = if ws_req.retry {
+        match ws_req.conversation_id {
+            Some(id) => svc.prepare_retry(id).await,
+            None => Err(AppError::EmptyField { field_name: "conversation_id".to_string() }),
+        }
+    } else {
+        svc.prepare_chat(ChatRequest {
+            conversation_id: ws_req.conversation_id,
+            message: ws_req.message,
+            ephemeral: ws_req.ephemeral,
+            verbosity,
+            stateless: ws_req.stateless,
+            quoted_message_id: ws_req.quoted_message_id,
+        })
+        .await
+    };
+    let mut ctx = match prepared {
+        Ok(ctx) => ctx,
+        Err(AppError::ModerationBlocked { reason }) => {
+            out.send(WsEvent::ModerationBlocked { reason }).await;
+            return;
+        }
+        Err(e) => {
+            out.send(WsEvent::Error { code: e.code(), message: e.to_string() }).await;
+            return;
+        }
+    };
+
+    Span::current().record("conversation_id", field::display(ctx.conversation_id));
+    turn.set_conversation(ctx.conversation_id);
+    ctx.preferences.response_format = response_format;
+    if let Some(verbosity) = verbosity {
+        ctx.preferences.verbosity = verbosity;
+    }
+    out.conversation_id = Some(ctx.conversation_id);
+    out.store = !svc.is_confidential(ctx.conversation_id).await;
+
+    // ── Notify client: streaming is starting ─────────────────────────────
+    out.send(WsEvent::StreamStart {
+        conversation_id: ctx.conversation_id,
+        history_cutoff: ctx.history_cutoff,
+    })
+    .await;
+
+    // ── Stream tokens from Ollama via a channel ──────────────────────────
+    let started = Instant::now();
+    let (tx, mut rx) = mpsc::channel::<StreamUpdate>(64);
+    let stream_svc = svc.clone();
+    let stream_ctx = ctx.clone();
+
+    let stream_handle = tokio::spawn(
+        async move { stream_svc.stream_reply(stream_ctx, tx).await }.in_current_span(),
+    );
+
+    // Forward queue updates, tool calls and chunks to the WebSocket client. If the client
+    // goes away, the turn is replaced, or a JSON reply turns out malformed,
+    // dropping `rx` makes the agent stop early.
+    let mut validator =
+        (response_format == ResponseFormat::Json).then(JsonStreamValidator::default);
+    let mut malformed = None;
+    let mut replaced = false;
+    let mut full_content = String::new();
+    let mut rechunker = Rechunker::new(chunking);
+    loop {
+        let update = tokio::select! {
+            biased;
+            () = turn.cancel.cancelled() => {
+                replaced = true;
+                break;
+            }
+            update = rx.recv() => match update {
+                Some(update) => update,
+                None => break,
+            },
+        };
+        let delivered = match update {
+            StreamUpdate::Queued { position } => out.send(WsEvent::Queued { position }).await,
+            StreamUpdate::Tool { call_id, name, status, duration_ms } => {
+                out.send(WsEvent::ToolCall { call_id, name, status, duration_ms }).await
+            }
+            StreamUpdate::Chunk(chunk) => {
+                full_content.push_str(&chunk);
+                if let Some(Err(error)) = validator.as_mut().map(|v| v.push(&chunk)) {
+                    warn!("Streamed JSON reply is malformed: {error}");
+                    malformed = Some(error);
+                }
+                match rechunker.push(&chunk) {
+                    Some(content) => out.send(WsEvent::StreamChunk { content }).await,
+                    None => true,
+                }
+            }
+        };
+        if !delivered || malformed.is_some() {
+            break;
+        }
+    }
+    drop(rx);
+    if replaced && full_content.is_empty() {
+        // Nothing to keep, so no need to wait for the agent to notice.
+        info!("Turn replaced before it streamed");
+        stream_handle.abort();
+        out.send(Turn::replaced_event()).await;
+        return;
+    }
+    // The last word or sentence has no boundary after it.
+    if malformed.is_none() {
+        if let Some(content) = rechunker.finish() {
+            out.send(WsEvent::StreamChunk { content }).await;
+        }
+    }
+
+    // Wait for the agent task to finish
+    match stream_handle.await {
+        Ok(Ok(outcome)) => {
+            if !outcome.sources.is_empty() {
+                out.send(WsEvent::StreamSources { sources: outcome.sources.clone() }).await;
+            }
+            // Chunks still buffered when the turn was replaced were dropped,
+            // even if the agent had finished.
+            let mut finish_reason =
+                if replaced { FinishReason::Cancelled } else { outcome.finish_reason };
+            // A replaced JSON reply is cut short; repairing it would be wasted.
+            let json_validation = if validator.is_some() && !replaced {
+                let (content, validation) =
+                    svc.settle_json_reply(&ctx, full_content, malformed).await;
+                if matches!(validation, JsonValidation::Repaired { .. }) {
+                    finish_reason = FinishReason::Stop;
+                }
+                full_content = content;
+                Some(validation)
+            } else {
+                None
+            };
+            let cost = svc.price(&outcome.model, outcome.prompt_tokens, outcome.completion_tokens);
+            let stats = CompletionStats {
+                model: outcome.model,
+                prompt_tokens: outcome.prompt_tokens,
+                completion_tokens: outcome.completion_tokens,
+                finish_reason,
+                duration_ms: started.elapsed().as_millis() as u64,
+                cost,
+            };
+
+            // Persist the tool calls the model made, then the complete reply
+            if let Err(e) = svc.save_tool_messages(&outcome.tool_messages).await {
+                error!("Failed to save tool messages: {e}");
+            }
+            let streamed_sources = outcome.sources.clone();
+            match svc
+                .save_assistant_message(&ctx, &full_content, outcome.sources, &stats)
+                .await
+            {
+                Ok(msg) => {
+                    let message_id = msg.id;
+                    // Post-processing may have renumbered the citations.
+                    if msg.sources != streamed_sources {
+                        out.send(WsEvent::StreamSources { sources: msg.sources.clone() }).await;
+                    }
+                    for artifact in svc.save_artifacts(&msg).await {
+                        out.send(WsEvent::Artifact { artifact }).await;
+                    }
+                    out.send(WsEvent::StreamEnd {
+                        message_id: msg.id,
+                        full_content: msg.content,
+                        stats,
+                        json_validation,
+                    })
+                    .await;
+                    send_suggestions(svc, ctx.conversation_id, message_id, &out);
+                }
+                Err(e) => {
+                    error!("Failed to save assistant message: {e}");
+                    out.send(WsEvent::Error {
+                        code: e.code(),
+                        message: format!("Failed to save response: {e}"),
+                    })
+                    .await;
+                }
+            }
+        }
+        Ok(Err(AppError::ModelNotFound { model_name })) => {
+            warn!("Model {model_name} is not installed");
+            let e = AppError::ModelNotFound { model_name: model_name.clone() };
+            svc.record_failed_turn(ctx.conversation_id, &e).await;
+            out.send(WsEvent::ModelMissing { model: model_name }).await;
+        }
+        Ok(Err(e)) => {
+            error!("Agent streaming failed: {e}");
+            svc.record_failed_turn(ctx.conversation_id, &e).await;
+            out.send(WsEvent::Error { code: e.code(), message: e.to_string() }).await;
+        }
+        Err(e) => {
+            error!("Agent task panicked: {e}");
+            let message = "Internal error during streaming".to_string();
+            svc.record_failed_turn(ctx.conversation_id, &AppError::Unexpected(message.clone()))
+                .await;
+            out.send(WsEvent::Error { code: ErrorCode::Internal, message }).await;
+        }
     }
 }
@@ -0,0 +1,170 @@
+//! Artifacts: long code blocks and documents of assistant replies, kept apart
+//! so clients can show them in a side panel and offer to copy or download
+//! them. The reply itself is saved unchanged.
+
+use chrono::Utc;
+use shared_models::markdown::{self, Block};
+use uuid::Uuid;
+
+use crate::models::{Artifact, ArtifactKind, DiffOp};
+use crate::service::evals;
+
+/// Fence languages whose blocks are prose rather than code.
+const DOCUMENT_LANGUAGES: &[&str] = &["markdown", "md", "text", "txt"];
+/// Line comment markers a code block may name its file after.
+const COMMENT_MARKERS: &[&str] = &["//", "#", "--", ";", "<!--", "/*"];
+/// Unchanged lines shown around each change of a unified diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// The artifacts of `content`, a reply of message `message_id`: every fenced
+/// block of at least `min_lines` lines, or the whole reply when it is a
+/// document of that length opening with a top-level heading. A `min_lines` of
+/// 0 finds none.
+pub fn extract(message_id: Uuid, content: &str, min_lines: usize) -> Vec<Artifact> {
+    if min_lines == 0 {
+        return Vec::new();
+    }
+    let artifact = |kind, language: Option<String>, title: String, content: String| Artifact {
+        id: Uuid::new_v4(),
+        message_id,
+        kind,
+        language,
+        title,
+        content,
+        created_at: Utc::now(),
+    };
+
+    let first_line = content.trim_start().lines().next().unwrap_or_default();
+    if let Some(title) = first_line.strip_prefix("# ") {
+        if content.lines().filter(|l| !l.trim().is_empty()).count() >= min_lines {
+            let title = title.trim().to_string();
+            return vec![artifact(ArtifactKind::Document, None, title, content.trim().to_string())];
+        }
+    }
+
+    markdown::split(content)
+        .iter()
+        .filter_map(|source| match markdown::parse_block(source, false) {
+            Block::Code { language, code } if code.lines().count() >= min_lines => {
+                let language = language
+                    .split_whitespace()
+                    .next()
+                    .map(str::to_lowercase)
+                    .filter(|l| !l.is_empty());
+                let kind = match language.as_deref() {
+                    Some(l) if DOCUMENT_LANGUAGES.contains(&l) => ArtifactKind::Document,
+                    _ => ArtifactKind::Code,
+                };
+                let title = title(kind, language.as_deref(), &code);
+                Some(artifact(kind, language, title, code))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// A document's first heading, or the file a code block names in a comment
+/// on its first line (`// src/main.rs`); otherwise a generic title.
+fn title(kind: ArtifactKind, language: Option<&str>, code: &str) -> String {
+    let first = code.lines().next().unwrap_or_default().trim();
+    match kind {
+        ArtifactKind::Document => {
+            let heading = first.trim_start_matches('#');
+            if heading.len() < first.len() && !heading.trim().is_empty() {
+                return heading.trim().to_string();
+            }
+            "Document".to_string()
+        }
+        ArtifactKind::Code => {
+            let named = COMMENT_MARKERS
+                .iter()
+                .find_map(|marker| first.strip_prefix(marker))
+                .map(|rest| rest.trim().trim_end_matches("-->").trim_end_matches("*/").trim())
+                .filter(|name| is_file_path(name));
+            match (named, language) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(language)) => format!("{language} snippet"),
+                (None, None) => "Code snippet".to_string(),
+            }
+        }
+    }
+}
+
+/// `src/main.rs` or `app.py`, not prose: one word with an extension.
+fn is_file_path(text: &str) -> bool {
+    let Some(name) = text.rsplit('/').next() else { return false };
+    let Some((stem, extension)) = name.rsplit_once('.') else { return false };
+    !stem.is_empty()
+        && (1..=8).contains(&extension.len())
+        && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '-' | '_'))
+}
+
+/// Unified diff (as `diff -u` or `git diff` print it) turning `old` into
+/// `new`, both named `path`. Empty when they are the same.
+pub fn unified_diff(old: &str, new: &str, path: &str) -> String {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = evals::diff_ops(&a, &b);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != DiffOp::Equal).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Lines of `old` and `new` before each operation.
+    let mut starts = Vec::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+    for (op, _) in &ops {
+        starts.push((i, j));
+        match op {
+            DiffOp::Equal => (i, j) = (i + 1, j + 1),
+            DiffOp::Delete => i += 1,
+            DiffOp::Insert => j += 1,
+        }
+    }
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    let mut k = 0;
+    while k < changes.len() {
+        // Changes closer than twice the context share a hunk.
+        let first = changes[k];
+        while k + 1 < changes.len() && changes[k + 1] - changes[k] <= 2 * DIFF_CONTEXT + 1 {
+            k += 1;
+        }
+        let last = changes[k];
+        k += 1;
+
+        let from = first.saturating_sub(DIFF_CONTEXT);
+        let hunk = &ops[from..(last + DIFF_CONTEXT + 1).min(ops.len())];
+        let (old_start, new_start) = starts[from];
+        let old_len = hunk.iter().filter(|(op, _)| *op != DiffOp::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != DiffOp::Delete).count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        ));
+        for (op, line) in hunk {
+            out.push(match op {
+                DiffOp::Equal => ' ',
+                DiffOp::Delete => '-',
+                DiffOp::Insert => '+',
+            });
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// `start,len` of a hunk header, 1-based; an empty range names the line
+/// before it.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
@@ -1,150 +1,3186 @@
-use tracing::error;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, field, info, instrument, warn, Instrument, Span};
 use uuid::Uuid;
 
-use crate::agent::OllamaAgentService;
+use crate::agent::{AgentService, StreamOutcome, StreamUpdate};
+use crate::config::{ConfigStore, ModerationAction};
+use crate::db::access_log_repository::AccessLogRepository;
+use crate::db::artifact_repository::ArtifactRepository;
+use crate::db::batch_repository::BatchRepository;
+use crate::db::collection_repository::CollectionRepository;
 use crate::db::conversation_repository::ConversationRepository;
+use crate::db::conversation_webhook_repository::ConversationWebhookRepository;
+use crate::db::document_repository::{
+    DocumentRepository, BY_COLLECTION, BY_POSITION, BY_RANKING,
+};
+use crate::db::ephemeral_store::EphemeralStore;
+use crate::db::eval_repository::EvalRepository;
 use crate::db::message_repository::MessageRepository;
+use crate::db::notification_repository::NotificationRepository;
+use crate::db::profile_repository::ProfileRepository;
+use crate::db::schedule_repository::ScheduleRepository;
+use crate::db::webhook_tool_repository::WebhookToolRepository;
 use crate::errors::AppError;
-use crate::models::{ChatContext, ChatRequest, ChatResponse, Conversation, Message, MessageRole};
+use crate::service::encryption::{self, ConversationKey, KeyRing};
+use crate::service::evals;
+use crate::service::export::{ExportEntry, EXPORT_BATCH_SIZE};
+use crate::service::ingest::{self, IngestSettings, Origin, CODEBASE_COLLECTION};
+use crate::service::notify::{self, Notifier};
+use crate::service::pdf;
+use crate::service::retrieval::Weights;
+use crate::service::splitter::{self, ChunkSettings};
+use crate::service::post_process::{PostProcessor, PostProcessorRegistry, Reply};
+use crate::service::transcript::{TranscriptEntry, TranscriptLogger};
+use crate::service::webhooks::{self, WebhookDispatcher};
+use crate::service::workspace::Workspace;
+use crate::service::{artifacts, cron, host, json_mode, language, moderation, retrieval, tokenize};
+use crate::models::{
+    AccessAction, AccessLogEntry, Artifact, ArtifactDiff, ArtifactDiffRequest, AssistantPreset, BatchItem, BatchJob, BatchStatus, ChatContext, ChatRequest, ChatResponse, CompletionStats, Conversation, ConversationExport, ConversationSort,
+    ConversationStats, CreateBatchRequest, CreateEvalRequest, EvalReport, EvalResult, EvalRun, EvalStatus, Message,
+    CreateScheduleRequest, CreateWebhookToolRequest, FinishReason, GenerationInfo, JsonValidation, MessageRole, PromptHistoryEntry,
+    ConversationWebhook, CreateWebhookRequest, CreatedWebhook, Notification, NotificationEvent,
+    Readiness, MessageVersion, ModelsResponse, BuildInfo, Features, ServerConfig, ServerVersion,
+    BUILD_COMMIT, PROTOCOL_VERSION,
+    PassphraseRequest, ResponseFormat, Schedule, Source, TokenizeRequest, TokenizeResponse,
+    StarredMessage, SummaryBlock, SystemStatus, TimelineEntry, TurnPreferences, UpdateConversationRequest, UpdateProfileRequest, UserProfile,
+    VersionDiff, WebhookDelivery, WebhookTool, IngestGitRequest, Ingestion, IngestionReport,
+    IngestionStatus, RetrievedChunk, Collection, CollectionChunk, CollectionSearchRequest,
+    ChunksQuery,
+    CreateCollectionRequest, RetrievalFeedback, RetrievalMetrics, RetrievalPrecision,
+    ChunkPreview, ChunkPreviewRequest, RechunkRequest,
+};
+use crate::tools::BUILTIN_TOOL_NAMES;
+
+/// The user every request acts as until authentication exists.
+pub const LOCAL_USER_ID: &str = "local";
 
-const MAX_MESSAGE_LENGTH: usize = 8000;
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+const MAX_SEARCH_LIMIT: i64 = 100;
+const MAX_SEARCH_QUERY_LENGTH: usize = 200;
+const DEFAULT_PROMPT_HISTORY_LIMIT: i64 = 50;
+const MAX_PROMPT_HISTORY_LIMIT: i64 = 200;
+const DEFAULT_NOTIFICATION_LIMIT: i64 = 50;
+const MAX_NOTIFICATION_LIMIT: i64 = 200;
+const MAX_NOTIFY_TARGET_LENGTH: usize = 2000;
+const DEFAULT_ACCESS_LOG_LIMIT: i64 = 50;
+const MAX_ACCESS_LOG_LIMIT: i64 = 200;
+const DEFAULT_DELIVERY_LIMIT: i64 = 50;
+const MAX_DELIVERY_LIMIT: i64 = 200;
+const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+const MAX_MODEL_NAME_LENGTH: usize = 200;
+const MAX_CUSTOM_INSTRUCTIONS_LENGTH: usize = 4000;
+/// Range Ollama accepts for `temperature`.
+const TEMPERATURE_RANGE: std::ops::RangeInclusive<f64> = 0.0..=2.0;
+const THEMES: [&str; 2] = ["light", "dark"];
+/// Bounds on what the summarizer is shown of a conversation.
+const MAX_SUMMARY_MESSAGE_CHARS: usize = 1000;
+const MAX_SUMMARY_TRANSCRIPT_BYTES: usize = 12_000;
+/// Newest messages a timeline always shows in full, however much of the
+/// conversation its summary covers.
+const SUMMARY_KEEPS_RECENT: usize = 10;
+/// Latest messages shown to the model when suggesting follow-ups.
+const SUGGESTION_CONTEXT_MESSAGES: usize = 6;
+const MAX_EVAL_CONVERSATIONS: usize = 100;
+const MAX_BATCH_PROMPTS: usize = 100;
+const MAX_HISTORY_MESSAGES: i32 = 10_000;
+/// Room for an emoji built from several code points (flags, ZWJ sequences).
+const MAX_ICON_CHARS: usize = 16;
+/// Limits on registered webhook tools; names follow Ollama's function names.
+const MAX_TOOL_NAME_LENGTH: usize = 64;
+const MAX_TOOL_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_TOOL_SCHEMA_LENGTH: usize = 8000;
+const MAX_AUTH_HEADER_LENGTH: usize = 1000;
+const MIN_PASSPHRASE_LENGTH: usize = 8;
+const MAX_PASSPHRASE_LENGTH: usize = 1000;
+/// Enough for a long conversation's whole history.
+const MAX_TOKENIZE_LENGTH: usize = 1_000_000;
+/// Limits on retrieval collections and their inspection.
+const MAX_COLLECTION_NAME_LENGTH: usize = 64;
+const MAX_COLLECTION_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_CHUNK_LINES: i32 = 1000;
+const DEFAULT_CHUNK_LIMIT: i64 = 50;
+const MAX_CHUNK_LIMIT: i64 = 500;
+const MAX_SEARCH_HITS: usize = 100;
+/// Source of the ingestions that re-chunk a collection.
+const RECHUNK_SOURCE: &str = "rechunk";
+/// Named in the note left when a conversation switches back from a preset.
+const DEFAULT_ASSISTANT_NAME: &str = "the default assistant";
 
 #[derive(Clone)]
 pub struct ChatService {
     conversation_repo: ConversationRepository,
     message_repo: MessageRepository,
-    agent: OllamaAgentService,
+    profile_repo: ProfileRepository,
+    eval_repo: EvalRepository,
+    batch_repo: BatchRepository,
+    webhook_tool_repo: WebhookToolRepository,
+    schedule_repo: ScheduleRepository,
+    webhook_repo: ConversationWebhookRepository,
+    access_repo: AccessLogRepository,
+    artifact_repo: ArtifactRepository,
+    document_repo: DocumentRepository,
+    collection_repo: CollectionRepository,
+    agent: Arc<dyn AgentService>,
+    config: ConfigStore,
+    notifier: Notifier,
+    webhooks: WebhookDispatcher,
+    ephemeral: EphemeralStore,
+    keys: KeyRing,
+    transcripts: TranscriptLogger,
+    post_processors: PostProcessorRegistry,
 }
 
 impl ChatService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         conversation_repo: ConversationRepository,
         message_repo: MessageRepository,
-        agent: OllamaAgentService,
+        profile_repo: ProfileRepository,
+        eval_repo: EvalRepository,
+        batch_repo: BatchRepository,
+        webhook_tool_repo: WebhookToolRepository,
+        schedule_repo: ScheduleRepository,
+        notification_repo: NotificationRepository,
+        webhook_repo: ConversationWebhookRepository,
+        access_repo: AccessLogRepository,
+        artifact_repo: ArtifactRepository,
+        document_repo: DocumentRepository,
+        collection_repo: CollectionRepository,
+        agent: Arc<dyn AgentService>,
+        config: ConfigStore,
     ) -> Self {
-        Self { conversation_repo, message_repo, agent }
+        Self {
+            conversation_repo,
+            message_repo,
+            profile_repo,
+            eval_repo,
+            batch_repo,
+            webhook_tool_repo,
+            schedule_repo,
+            agent,
+            transcripts: TranscriptLogger::new(config.clone()),
+            notifier: Notifier::new(notification_repo, config.clone()),
+            webhooks: WebhookDispatcher::new(webhook_repo.clone()),
+            webhook_repo,
+            access_repo,
+            artifact_repo,
+            document_repo,
+            collection_repo,
+            config,
+            ephemeral: EphemeralStore::default(),
+            keys: KeyRing::default(),
+            post_processors: PostProcessorRegistry::default(),
+        }
+    }
+
+    /// Makes `processor` available to `post_processors` in the configuration,
+    /// alongside the built-in ones.
+    pub fn with_post_processor(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.post_processors = self.post_processors.with(processor);
+        self
+    }
+
+    /// Expose the live configuration.
+    pub fn config(&self) -> &ConfigStore {
+        &self.config
     }
 
     /// Expose the agent for direct streaming calls from WebSocket handlers.
-    pub fn agent(&self) -> &OllamaAgentService {
+    pub fn agent(&self) -> &Arc<dyn AgentService> {
         &self.agent
     }
 
+    /// Every stored conversation, most recently updated first.
     pub async fn get_conversations(&self) -> Result<Vec<Conversation>, AppError> {
-        self.conversation_repo.find_all().await
+        self.conversation_repo.find_all(ConversationSort::Updated).await
+    }
+
+    /// Every stored conversation in the order `sort` asks for, by default the
+    /// one saved in the user's profile.
+    pub async fn list_conversations(
+        &self,
+        sort: Option<ConversationSort>,
+    ) -> Result<Vec<Conversation>, AppError> {
+        let sort = match sort {
+            Some(sort) => sort,
+            None => self.get_profile().await?.conversation_sort,
+        };
+        self.conversation_repo.find_all(sort).await
+    }
+
+    /// Conversations whose title or messages contain `query`. Ephemeral
+    /// conversations are never searched.
+    pub async fn search_conversations(
+        &self,
+        query: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<Conversation>, AppError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        if query.len() > MAX_SEARCH_QUERY_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "q".to_string(),
+                max_length: MAX_SEARCH_QUERY_LENGTH,
+                actual_length: query.len(),
+            });
+        }
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+        self.conversation_repo.search(query, limit).await
+    }
+
+    pub async fn delete_conversation(&self, id: Uuid) -> Result<(), AppError> {
+        if self.ephemeral.remove(id) {
+            return Ok(());
+        }
+        self.keys.remove(id);
+        if !self.conversation_repo.delete(id).await? {
+            return Err(AppError::ConversationNotFound { id });
+        }
+        Ok(())
+    }
+
+    /// Applies the settings in `request` to a conversation, ephemeral or not.
+    pub async fn update_conversation(
+        &self,
+        id: Uuid,
+        request: UpdateConversationRequest,
+    ) -> Result<Conversation, AppError> {
+        let ephemeral = self.ephemeral.find_conversation(id);
+        let is_ephemeral = ephemeral.is_some();
+        let mut conversation = match ephemeral {
+            Some(conv) => conv,
+            None => self
+                .conversation_repo
+                .find_by_id(id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound { id })?,
+        };
+
+        if let Some(limit) = request.max_history_messages {
+            if limit.is_some_and(|n| !(0..=MAX_HISTORY_MESSAGES).contains(&n)) {
+                return Err(AppError::InvalidField {
+                    field_name: "max_history_messages".to_string(),
+                    message: format!("must be between 0 and {MAX_HISTORY_MESSAGES}"),
+                });
+            }
+            if is_ephemeral {
+                self.ephemeral.set_max_history_messages(id, limit);
+            } else {
+                self.conversation_repo.update_max_history_messages(id, limit).await?;
+            }
+            conversation.max_history_messages = limit;
+        }
+        if request.icon.is_some() || request.color.is_some() {
+            let icon = match request.icon {
+                Some(icon) => validate_icon(icon)?,
+                None => conversation.icon.clone(),
+            };
+            let color = match request.color {
+                Some(color) => validate_color(color)?,
+                None => conversation.color.clone(),
+            };
+            if is_ephemeral {
+                self.ephemeral.set_appearance(id, icon.clone(), color.clone());
+            } else {
+                self.conversation_repo
+                    .update_appearance(id, icon.as_deref(), color.as_deref())
+                    .await?;
+            }
+            conversation.icon = icon;
+            conversation.color = color;
+        }
+        if let Some(verbosity) = request.verbosity {
+            if is_ephemeral {
+                self.ephemeral.set_verbosity(id, verbosity);
+            } else {
+                self.conversation_repo.update_verbosity(id, verbosity).await?;
+            }
+            conversation.verbosity = verbosity;
+        }
+        if let Some(stateless) = request.stateless {
+            if is_ephemeral {
+                self.ephemeral.set_stateless(id, stateless);
+            } else {
+                self.conversation_repo.update_stateless(id, stateless).await?;
+            }
+            conversation.stateless = stateless;
+        }
+        if let Some(preset) = request.preset {
+            let preset = preset.map(|id| self.find_preset(&id)).transpose()?;
+            let preset_id = preset.as_ref().map(|p| p.id.clone());
+            let model = preset.as_ref().and_then(|p| p.model.clone());
+            if preset_id != conversation.preset {
+                // Recorded first, so a locked conversation is left as it was.
+                let name = preset.map_or_else(|| DEFAULT_ASSISTANT_NAME.to_string(), |p| p.name);
+                let switch = Message::new(id, MessageRole::System, format!("Switched to {name}"));
+                self.store_message(&switch).await?;
+                if is_ephemeral {
+                    self.ephemeral.set_preset(id, preset_id.clone());
+                } else {
+                    self.conversation_repo.update_preset(id, preset_id.as_deref()).await?;
+                }
+                info!("Conversation {id} switched to {name}");
+                conversation.preset = preset_id;
+                self.warm_up(model);
+            }
+        }
+        Ok(conversation)
+    }
+
+    /// The assistant presets conversations can switch to.
+    pub fn presets(&self) -> Vec<AssistantPreset> {
+        self.config.get().presets.clone()
+    }
+
+    fn find_preset(&self, id: &str) -> Result<AssistantPreset, AppError> {
+        let config = self.config.get();
+        config.presets.iter().find(|p| p.id == id).cloned().ok_or_else(|| {
+            AppError::InvalidField {
+                field_name: "preset".to_string(),
+                message: format!("no preset '{id}' is configured"),
+            }
+        })
+    }
+
+    /// Earlier messages sent with each turn of `conversation`; `None` is
+    /// unlimited, and stateless conversations send none.
+    fn history_limit(&self, conversation: &Conversation) -> Option<usize> {
+        if conversation.stateless {
+            return Some(0);
+        }
+        let limit = match conversation.max_history_messages {
+            Some(limit) => limit.max(0) as usize,
+            None => self.config.get().max_history_messages,
+        };
+        (limit > 0).then_some(limit)
+    }
+
+    /// Encrypts a stored conversation's messages with `passphrase` and leaves
+    /// it unlocked. Its summary is dropped and no more are generated.
+    #[instrument(skip(self, request))]
+    pub async fn encrypt_conversation(
+        &self,
+        id: Uuid,
+        request: PassphraseRequest,
+    ) -> Result<Conversation, AppError> {
+        if self.ephemeral.contains(id) {
+            return Err(AppError::InvalidField {
+                field_name: "passphrase".to_string(),
+                message: "ephemeral conversations are never stored, so need no encryption"
+                    .to_string(),
+            });
+        }
+        let passphrase = request.passphrase;
+        if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+            return Err(AppError::InvalidField {
+                field_name: "passphrase".to_string(),
+                message: format!("must be at least {MIN_PASSPHRASE_LENGTH} characters"),
+            });
+        }
+        if passphrase.len() > MAX_PASSPHRASE_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "passphrase".to_string(),
+                max_length: MAX_PASSPHRASE_LENGTH,
+                actual_length: passphrase.len(),
+            });
+        }
+        let conversation = self
+            .conversation_repo
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::ConversationNotFound { id })?;
+        let already_encrypted = || AppError::InvalidField {
+            field_name: "passphrase".to_string(),
+            message: format!("conversation '{id}' is already encrypted"),
+        };
+        if conversation.encrypted {
+            return Err(already_encrypted());
+        }
+
+        let salt = encryption::new_salt()?;
+        let key = derive_key(passphrase, salt.clone()).await?;
+        let check = key.check_value()?;
+        if !self.message_repo.encrypt_conversation(id, &salt, &check, |c| key.seal(c)).await? {
+            return Err(already_encrypted());
+        }
+        self.keys.insert(id, key);
+        Ok(Conversation {
+            encrypted: true,
+            summary: None,
+            summary_message_count: 0,
+            ..conversation
+        })
+    }
+
+    /// Checks `passphrase` against an encrypted conversation and keeps its key
+    /// in memory, so its messages can be read and added until it is locked
+    /// again or goes unused for [`encryption::UNLOCK_IDLE_TIMEOUT`].
+    #[instrument(skip(self, request))]
+    pub async fn unlock_conversation(
+        &self,
+        id: Uuid,
+        request: PassphraseRequest,
+    ) -> Result<(), AppError> {
+        let Some((salt, check)) = self.conversation_repo.find_key_params(id).await? else {
+            self.conversation_repo
+                .find_by_id(id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound { id })?;
+            return Err(AppError::InvalidField {
+                field_name: "passphrase".to_string(),
+                message: format!("conversation '{id}' is not encrypted"),
+            });
+        };
+        let key = derive_key(request.passphrase, salt).await?;
+        if !key.matches(&check) {
+            return Err(AppError::WrongPassphrase { id });
+        }
+        self.keys.insert(id, key);
+        Ok(())
+    }
+
+    /// Forgets the key of an unlocked conversation.
+    pub fn lock_conversation(&self, id: Uuid) {
+        self.keys.remove(id);
+    }
+
+    /// The key `conversation`'s messages are sealed with: `None` unless it is
+    /// encrypted, and an error while it is locked.
+    fn unlocked_key(
+        &self,
+        conversation: &Conversation,
+    ) -> Result<Option<Arc<ConversationKey>>, AppError> {
+        if !conversation.encrypted {
+            return Ok(None);
+        }
+        self.keys
+            .get(conversation.id)
+            .map(Some)
+            .ok_or_else(|| AppError::ConversationLocked { id: conversation.id })
+    }
+
+    /// [`ChatService::unlocked_key`] for a stored conversation known by id.
+    async fn key_for(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Option<Arc<ConversationKey>>, AppError> {
+        if let Some(key) = self.keys.get(conversation_id) {
+            return Ok(Some(key));
+        }
+        match self.conversation_repo.find_by_id(conversation_id).await? {
+            Some(conversation) => self.unlocked_key(&conversation),
+            None => Ok(None),
+        }
+    }
+
+    /// The model new turns use: the user's preferred one, else the configured one.
+    async fn current_model(&self) -> Result<String, AppError> {
+        Ok(match self.get_profile().await?.preferred_model {
+            Some(model) => model,
+            None => self.config.get().model.clone(),
+        })
+    }
+
+    /// What this server offers, for clients to show only what works.
+    pub async fn server_config(&self) -> Result<ServerConfig, AppError> {
+        let model = self.current_model().await?;
+        let config = self.config.get();
+        Ok(ServerConfig {
+            version: server_version(),
+            providers: vec!["ollama".to_string()],
+            model,
+            tools: self.agent.tools(),
+            max_message_length: config.max_message_length,
+            features: Features {
+                follow_up_suggestions: config.follow_up_suggestions > 0,
+                presets: !config.presets.is_empty(),
+                email_notifications: config.smtp_url.is_some(),
+                slack: config.slack.is_some(),
+                email_gateway: config.email_gateway.is_some() && config.tenants.is_none(),
+                artifacts: config.artifact_min_lines > 0,
+                workspace: config.workspace_dir.is_some(),
+                retrieval: config.retrieval_top_k > 0,
+            },
+        })
+    }
+
+    /// Installed models, the one new turns will use and the context size.
+    pub async fn list_models(&self) -> Result<ModelsResponse, AppError> {
+        let current = self.current_model().await?;
+        Ok(ModelsResponse {
+            current,
+            models: self.agent.list_models().await?,
+            context_window: self.config.get().context_window,
+        })
+    }
+
+    /// The models Ollama has loaded and the host's memory and load. An
+    /// unreachable Ollama is reported in the status rather than failing it.
+    pub async fn system_status(&self) -> Result<SystemStatus, AppError> {
+        let current_model = self.current_model().await?;
+        let (ollama_reachable, loaded_models) = match self.agent.loaded_models().await {
+            Ok(models) => (true, models),
+            Err(e) => {
+                warn!("Ollama did not report its loaded models: {e}");
+                (false, Vec::new())
+            }
+        };
+        Ok(SystemStatus { current_model, ollama_reachable, loaded_models, host: host::host_stats() })
+    }
+
+    /// Counts the tokens of `request.text` for a model, by default the one new
+    /// turns use.
+    pub async fn tokenize(&self, request: TokenizeRequest) -> Result<TokenizeResponse, AppError> {
+        if request.text.len() > MAX_TOKENIZE_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "text".to_string(),
+                max_length: MAX_TOKENIZE_LENGTH,
+                actual_length: request.text.len(),
+            });
+        }
+        let model = match request.model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()) {
+            Some(model) => model,
+            None => self.current_model().await?,
+        };
+        tokio::task::spawn_blocking(move || {
+            tokenize::tokenize(&model, &request.text, request.boundaries)
+        })
+        .await
+        .map_err(|e| AppError::Unexpected(format!("Tokenizing failed: {e}")))
+    }
+
+    /// The current user's profile; a blank one until it is first updated.
+    pub async fn get_profile(&self) -> Result<UserProfile, AppError> {
+        Ok(self
+            .profile_repo
+            .find(LOCAL_USER_ID)
+            .await?
+            .unwrap_or_else(|| UserProfile::new(LOCAL_USER_ID.to_string())))
+    }
+
+    /// Applies the fields present in `update`. Blank text clears a field.
+    pub async fn update_profile(
+        &self,
+        update: UpdateProfileRequest,
+    ) -> Result<UserProfile, AppError> {
+        let mut profile = self.get_profile().await?;
+        let previous_model = profile.preferred_model.clone();
+        if let Some(value) = update.display_name {
+            profile.display_name = text_field("display_name", value, MAX_DISPLAY_NAME_LENGTH)?;
+        }
+        if let Some(value) = update.preferred_model {
+            profile.preferred_model = text_field("preferred_model", value, MAX_MODEL_NAME_LENGTH)?;
+        }
+        if let Some(value) = update.temperature {
+            if value.is_some_and(|t| !TEMPERATURE_RANGE.contains(&t)) {
+                return Err(AppError::InvalidField {
+                    field_name: "temperature".to_string(),
+                    message: format!(
+                        "must be between {} and {}",
+                        TEMPERATURE_RANGE.start(),
+                        TEMPERATURE_RANGE.end()
+                    ),
+                });
+            }
+            profile.temperature = value;
+        }
+        if let Some(value) = update.theme {
+            let theme = value.map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty());
+            if theme.as_deref().is_some_and(|t| !THEMES.contains(&t)) {
+                return Err(AppError::InvalidField {
+                    field_name: "theme".to_string(),
+                    message: format!("must be one of {}", THEMES.join(", ")),
+                });
+            }
+            profile.theme = theme;
+        }
+        if let Some(value) = update.custom_instructions {
+            profile.custom_instructions =
+                text_field("custom_instructions", value, MAX_CUSTOM_INSTRUCTIONS_LENGTH)?;
+        }
+        if let Some(sort) = update.conversation_sort {
+            profile.conversation_sort = sort;
+        }
+        if let Some(value) = update.notify_webhook_url {
+            let url = text_field("notify_webhook_url", value, MAX_NOTIFY_TARGET_LENGTH)?;
+            let is_http = |u: &str| {
+                reqwest::Url::parse(u).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+            };
+            if url.as_deref().is_some_and(|u| !is_http(u)) {
+                return Err(AppError::InvalidField {
+                    field_name: "notify_webhook_url".to_string(),
+                    message: "must be an http or https URL".to_string(),
+                });
+            }
+            profile.notify_webhook_url = url;
+        }
+        if let Some(value) = update.notify_email {
+            let email = text_field("notify_email", value, MAX_NOTIFY_TARGET_LENGTH)?;
+            if email.as_deref().is_some_and(|e| !notify::is_valid_email(e)) {
+                return Err(AppError::InvalidField {
+                    field_name: "notify_email".to_string(),
+                    message: "must be an email address".to_string(),
+                });
+            }
+            profile.notify_email = email;
+        }
+        profile.updated_at = Utc::now();
+        let profile = self.profile_repo.save(&profile).await?;
+        if profile.preferred_model != previous_model {
+            self.warm_up(None);
+        }
+        Ok(profile)
+    }
+
+    /// Loads `model`, by default the one new turns use, into Ollama in the
+    /// background so the next turn with it need not wait for the load. Does
+    /// nothing unless `warm_up` is on; failures are only logged.
+    pub fn warm_up(&self, model: Option<String>) {
+        if !self.config.get().warm_up {
+            return;
+        }
+        let svc = self.clone();
+        tokio::spawn(
+            async move {
+                let model = match model {
+                    Some(model) => model,
+                    None => match svc.current_model().await {
+                        Ok(model) => model,
+                        Err(e) => {
+                            warn!("Failed to find the model to warm up: {e}");
+                            return;
+                        }
+                    },
+                };
+                match svc.agent.warm_up(&model).await {
+                    Ok(()) => info!("Warmed up {model}"),
+                    Err(e) => warn!("Failed to warm up {model}: {e}"),
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Prompts the user has sent that contain `query`, most recently used
+    /// first.
+    pub async fn prompt_history(
+        &self,
+        query: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<PromptHistoryEntry>, AppError> {
+        let query = query.trim();
+        if query.len() > MAX_SEARCH_QUERY_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "q".to_string(),
+                max_length: MAX_SEARCH_QUERY_LENGTH,
+                actual_length: query.len(),
+            });
+        }
+        let limit =
+            limit.unwrap_or(DEFAULT_PROMPT_HISTORY_LIMIT).clamp(1, MAX_PROMPT_HISTORY_LIMIT);
+        self.profile_repo.find_prompts(LOCAL_USER_ID, query, limit).await
+    }
+
+    /// Preferences a conversation's turns start from: its reply length and,
+    /// once it has switched to a preset, that preset's system prompt and model.
+    fn turn_preferences(&self, conversation: &Conversation) -> TurnPreferences {
+        let config = self.config.get();
+        let preset = conversation.preset.as_deref().and_then(|id| {
+            let preset = config.presets.iter().find(|p| p.id == id);
+            if preset.is_none() {
+                warn!("Conversation {} uses unconfigured preset '{id}'", conversation.id);
+            }
+            preset
+        });
+        TurnPreferences {
+            model: preset.and_then(|p| p.model.clone()),
+            system_prompt: preset.and_then(|p| p.system_prompt.clone()),
+            verbosity: conversation.verbosity,
+            ..TurnPreferences::default()
+        }
+    }
+
+    /// Fills in the user's display name, per-turn preferences and the webhook
+    /// tools registered for the conversation.
+    async fn personalize(&self, mut ctx: ChatContext) -> Result<ChatContext, AppError> {
+        let profile = self.get_profile().await?;
+        ctx.webhook_tools =
+            self.webhook_tool_repo.find_visible(Some(ctx.conversation_id)).await?;
+        ctx.user_name = profile.display_name;
+        ctx.preferences = TurnPreferences {
+            // A preset's model wins over the user's preferred one.
+            model: ctx.preferences.model.or(profile.preferred_model),
+            temperature: profile.temperature,
+            custom_instructions: profile.custom_instructions,
+            ..ctx.preferences
+        };
+        ctx.retrieved = self.retrieve(ctx.conversation_id, &ctx.user_message).await;
+        Ok(ctx)
+    }
+
+    /// The indexed chunks closest to `query` in the collections the
+    /// conversation searches. Retrieval only adds to a turn: when the
+    /// embedding model fails, the turn goes ahead without it.
+    async fn retrieve(&self, conversation_id: Uuid, query: &str) -> Vec<RetrievedChunk> {
+        let top_k = self.config.get().retrieval_top_k;
+        if top_k == 0 || query.trim().is_empty() {
+            return Vec::new();
+        }
+        let retrieved = async {
+            let collections = self.collection_repo.find_searched_by(conversation_id).await?;
+            self.rank(query, &collections, top_k, self.retrieval_weights()).await
+        };
+        retrieved.await.unwrap_or_else(|e| {
+            warn!("Retrieval skipped: {e}");
+            Vec::new()
+        })
+    }
+
+    fn retrieval_weights(&self) -> Weights {
+        let config = self.config.get();
+        Weights { vector: config.retrieval_vector_weight, text: config.retrieval_text_weight }
+    }
+
+    /// The `limit` chunks of `collections` that best match `query`, best
+    /// first: the similarity and full-text rankings fused by `weights`, then
+    /// rescored by the reranker if there is one. The query is embedded once
+    /// per embedding model the collections use, and both rankings are done
+    /// in the database.
+    async fn rank(
+        &self,
+        query: &str,
+        collections: &[Collection],
+        limit: usize,
+        weights: Weights,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        let config = self.config.get();
+        let candidates = config.retrieval_candidates.max(limit);
+        let collections: Vec<&Collection> = collections.iter().filter(|c| c.chunks > 0).collect();
+
+        let mut by_vector = Vec::new();
+        if weights.vector > 0.0 {
+            let mut by_model: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+            for collection in &collections {
+                let model =
+                    collection.embedding_model.as_deref().unwrap_or(&config.embedding_model);
+                by_model.entry(model).or_default().push(collection.name.clone());
+            }
+            for (model, names) in by_model {
+                let embedded = self.agent.embed(model, &[query.to_string()]).await?;
+                let embedding = embedded.into_iter().next().unwrap_or_default();
+                let limit = candidates as i64;
+                let similar = self.document_repo.search_similar(&names, &embedding, limit).await?;
+                by_vector.extend(similar);
+            }
+            by_vector.sort_by(|a, b| b.score.total_cmp(&a.score));
+            by_vector.truncate(candidates);
+        }
+        let mut by_text = Vec::new();
+        if weights.text > 0.0 && !collections.is_empty() {
+            let names: Vec<String> = collections.iter().map(|c| c.name.clone()).collect();
+            let words = retrieval::any_word(query);
+            by_text = self.document_repo.search_text(&names, &words, candidates as i64).await?;
+        }
+
+        let mut hits = retrieval::fuse(by_vector, by_text, weights);
+        hits.truncate(candidates);
+        if let Some(model) = config.reranker_model.as_deref().filter(|_| hits.len() > 1) {
+            self.rerank(model, query, &mut hits).await;
+        }
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Rescores `hits` with the reranker `model`, best first. When it fails
+    /// they keep their fused order.
+    async fn rerank(&self, model: &str, query: &str, hits: &mut [RetrievedChunk]) {
+        let documents: Vec<String> =
+            hits.iter().map(|hit| format!("{}\n{}", hit.path, hit.content)).collect();
+        match self.agent.rerank(model, query, &documents).await {
+            Ok(scores) if scores.len() == hits.len() => {
+                for (hit, score) in hits.iter_mut().zip(scores) {
+                    hit.score = score;
+                }
+                hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+            }
+            Ok(scores) => warn!("Reranker scored {} of {} chunks", scores.len(), hits.len()),
+            Err(e) => warn!("Reranking skipped: {e}"),
+        }
     }
 
     pub async fn get_messages(
         &self,
-        conversation_id: &str,
+        conversation_id: Uuid,
     ) -> Result<Vec<Message>, AppError> {
-        self.conversation_repo
+        self.sweep_ephemeral();
+        if let Some(messages) = self.ephemeral.messages(conversation_id) {
+            return Ok(messages);
+        }
+        let conversation = self
+            .conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or_else(|| AppError::ConversationNotFound {
+                id: conversation_id,
+            })?;
+        let key = self.unlocked_key(&conversation)?;
+        let messages = self.message_repo.find_by_conversation_id(conversation_id).await?;
+        open_messages(key.as_deref(), messages)
+    }
+
+    /// The conversation's messages, with the oldest ones its summary covers
+    /// condensed into a summary block in front of the rest. Long
+    /// conversations only: the newest [`SUMMARY_KEEPS_RECENT`] messages are
+    /// never condensed.
+    pub async fn get_timeline(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<TimelineEntry>, AppError> {
+        let messages = self.get_messages(conversation_id).await?;
+        // Ephemeral and encrypted conversations are never summarized.
+        let summary = if self.ephemeral.contains(conversation_id) {
+            None
+        } else {
+            self.conversation_repo
+                .find_by_id(conversation_id)
+                .await?
+                .and_then(|c| Some((c.summary?, c.summary_message_count.max(0) as usize)))
+        };
+        Ok(timeline(messages, summary))
+    }
+
+    /// Fails unless the conversation exists and, if encrypted, is unlocked.
+    pub async fn check_readable(&self, conversation_id: Uuid) -> Result<(), AppError> {
+        if self.ephemeral.contains(conversation_id) {
+            return Ok(());
+        }
+        let conversation = self
+            .conversation_repo
             .find_by_id(conversation_id)
             .await?
             .ok_or_else(|| AppError::ConversationNotFound {
-                id: conversation_id.to_string(),
+                id: conversation_id,
             })?;
-        self.message_repo.find_by_conversation_id(conversation_id).await
+        self.unlocked_key(&conversation).map(|_| ())
+    }
+
+    /// Whether the conversation's text must stay out of the database outside
+    /// its (sealed) messages: it is ephemeral or encrypted. Unknown
+    /// conversations count as confidential.
+    pub async fn is_confidential(&self, conversation_id: Uuid) -> bool {
+        if self.ephemeral.contains(conversation_id) {
+            return true;
+        }
+        match self.conversation_repo.find_by_id(conversation_id).await {
+            Ok(Some(conversation)) => conversation.encrypted,
+            Ok(None) | Err(_) => true,
+        }
     }
 
     /// Non-streaming chat (POST /api/chat fallback).
     pub async fn chat(&self, request: ChatRequest) -> Result<ChatResponse, AppError> {
+        self.chat_until(request, &CancellationToken::new()).await
+    }
+
+    /// Non-streaming chat that stops generating once `cancel` fires, e.g. when
+    /// the client that asked has gone away. The turn is then recorded as
+    /// failed, so it can be retried, and no reply is saved.
+    #[instrument(skip_all, fields(conversation_id = field::Empty))]
+    pub async fn chat_until(
+        &self,
+        request: ChatRequest,
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, AppError> {
         let ctx = self.prepare_chat(request).await?;
+        Span::current().record("conversation_id", field::display(ctx.conversation_id));
+        self.answer(ctx, cancel).await
+    }
 
-        let assistant_message = self
-            .agent
-            .chat(&ctx.conversation_id, &ctx.history, &ctx.user_message)
-            .await?;
+    /// Answers a conversation's last user message again after its turn
+    /// failed (POST /api/conversations/:id/retry-last).
+    #[instrument(skip(self))]
+    pub async fn retry_last(&self, conversation_id: Uuid) -> Result<ChatResponse, AppError> {
+        let ctx = self.prepare_retry(conversation_id).await?;
+        self.answer(ctx, &CancellationToken::new()).await
+    }
 
-        self.message_repo.save(&assistant_message).await?;
-        if let Err(e) = self.conversation_repo.update_timestamp(&ctx.conversation_id).await {
-            error!("Failed to update conversation timestamp: {e}");
-        }
+    /// Runs a prepared turn without streaming and saves the reply. A failure,
+    /// or `cancel` firing first, is recorded on the conversation so the turn
+    /// can be retried.
+    async fn answer(
+        &self,
+        ctx: ChatContext,
+        cancel: &CancellationToken,
+    ) -> Result<ChatResponse, AppError> {
+        let mut ctx = ctx;
+        let turn = self.with_shrinking_history(&mut ctx, || true, |ctx| {
+            let agent = self.agent.clone();
+            async move { agent.chat(&ctx).await }
+        });
+        // Dropping the agent's future ends its Ollama request and frees the
+        // generation slot.
+        let reply = tokio::select! {
+            reply = turn => reply,
+            () = cancel.cancelled() => {
+                info!("Client left before the reply to {} was ready", ctx.conversation_id);
+                Err(AppError::ClientDisconnected)
+            }
+        };
+        let assistant_message = match reply {
+            Ok(message) => {
+                let reply =
+                    self.post_process(&ctx, message.content.clone(), message.sources.clone());
+                Message { content: reply.content, sources: reply.sources, ..message }
+            }
+            Err(e) => {
+                self.record_failed_turn(ctx.conversation_id, &e).await;
+                return Err(e);
+            }
+        };
+
+        self.store_message(&assistant_message).await?;
+        self.log_turn(&ctx, &assistant_message, None).await;
+        self.schedule_suggestions(ctx.conversation_id, assistant_message.id);
+        let artifacts = self.save_artifacts(&assistant_message).await;
+        self.save_retrieval(&ctx, &assistant_message).await;
 
         Ok(ChatResponse {
             conversation_id: ctx.conversation_id,
+            sources: assistant_message.sources.clone(),
             message: assistant_message,
+            history_cutoff: ctx.history_cutoff,
+            artifacts,
+        })
+    }
+
+    /// Streams the reply to a prepared turn through `tx`, with less history
+    /// if the model runs out of context or memory before it starts. Once the
+    /// client has been sent part of the reply, the turn fails instead: a
+    /// retry would stream it again after what was already shown.
+    pub async fn stream_reply(
+        &self,
+        mut ctx: ChatContext,
+        tx: mpsc::Sender<StreamUpdate>,
+    ) -> Result<StreamOutcome, AppError> {
+        let started = AtomicBool::new(false);
+        let retryable = || !started.load(Ordering::Relaxed);
+        self.with_shrinking_history(&mut ctx, retryable, |ctx| {
+            let (agent, tx, started) = (self.agent.clone(), tx.clone(), &started);
+            async move {
+                // Updates pass through here so the ones the client sees are
+                // noticed; a place in the queue is not part of the reply.
+                let (relay, mut updates) = mpsc::channel(tx.max_capacity());
+                let forward = async {
+                    while let Some(update) = updates.recv().await {
+                        if !matches!(update, StreamUpdate::Queued { .. }) {
+                            started.store(true, Ordering::Relaxed);
+                        }
+                        if tx.send(update).await.is_err() {
+                            break;
+                        }
+                    }
+                };
+                let (outcome, ()) = tokio::join!(agent.stream_chat(&ctx, relay), forward);
+                outcome
+            }
         })
+        .await
+    }
+
+    /// Runs `attempt` on the turn, retrying with half of its history each
+    /// time the model runs out of context or memory, until there is none
+    /// left to drop or `retryable` says the failed attempt cannot be redone.
+    /// When a shortened turn succeeds, a SYSTEM message before the reply
+    /// notes how much history the model was given.
+    async fn with_shrinking_history<T, F, Fut>(
+        &self,
+        ctx: &mut ChatContext,
+        retryable: impl Fn() -> bool,
+        mut attempt: F,
+    ) -> Result<T, AppError>
+    where
+        F: FnMut(ChatContext) -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let mut shortened_at = None;
+        loop {
+            match attempt(ctx.clone()).await {
+                Err(AppError::ContextTooLarge { model_name, message })
+                    if !ctx.history.is_empty() && retryable() =>
+                {
+                    let keep = ctx.history.len() / 2;
+                    warn!(
+                        "{model_name} ran out of context or memory ({message}); \
+                         retrying with {keep} earlier messages"
+                    );
+                    ctx.history_cutoff = limit_history(&mut ctx.history, Some(keep));
+                    shortened_at.get_or_insert_with(Utc::now);
+                }
+                Ok(reply) => {
+                    if let Some(created_at) = shortened_at {
+                        let content = match ctx.history.len() {
+                            0 => "No earlier messages were sent with this turn: the model ran \
+                                  out of context or memory"
+                                .to_string(),
+                            kept => format!(
+                                "Only the last {kept} earlier messages were sent with this \
+                                 turn: the model ran out of context or memory"
+                            ),
+                        };
+                        let note = Message {
+                            created_at,
+                            ..Message::new(ctx.conversation_id, MessageRole::System, content)
+                        };
+                        if let Err(e) = self.store_message(&note).await {
+                            error!("Failed to note the shortened history: {e}");
+                        }
+                    }
+                    return Ok(reply);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Marks the conversation's last turn as failed with `error`, until a
+    /// reply is saved.
+    pub async fn record_failed_turn(&self, conversation_id: Uuid, error: &AppError) {
+        let message = error.to_string();
+        if self.ephemeral.contains(conversation_id) {
+            self.ephemeral.set_failed_turn(conversation_id, Some(message));
+        } else if let Err(e) =
+            self.conversation_repo.update_failed_turn(conversation_id, Some(&message)).await
+        {
+            error!("Failed to record failed turn: {e}");
+        }
     }
 
     /// Validate the request, resolve/create the conversation, persist the user
     /// message, and return a [`ChatContext`] ready for the agent to process.
     ///
     /// Used by both the REST handler and the WebSocket streaming handler.
+    #[instrument(skip_all, fields(conversation_id = field::Empty, ephemeral = request.ephemeral))]
     pub async fn prepare_chat(&self, request: ChatRequest) -> Result<ChatContext, AppError> {
         // ── Validation ────────────────────────────────────────────────────────
+        let max_length = self.config.get().max_message_length;
         if request.message.trim().is_empty() {
             return Err(AppError::EmptyField { field_name: "message".to_string() });
         }
-        if request.message.len() > MAX_MESSAGE_LENGTH {
+        if request.message.len() > max_length {
             return Err(AppError::FieldTooLong {
                 field_name: "message".to_string(),
-                max_length: MAX_MESSAGE_LENGTH,
+                max_length,
                 actual_length: request.message.len(),
             });
         }
+        if request.quoted_message_id.is_some() && request.conversation_id.is_none() {
+            return Err(AppError::InvalidField {
+                field_name: "quoted_message_id".to_string(),
+                message: "quotes need the conversation_id of the quoted message".to_string(),
+            });
+        }
+
+        self.moderate(&request.message).await?;
 
         // ── Resolve or create conversation ────────────────────────────────────
-        let conversation_id = request
-            .conversation_id
-            .unwrap_or_else(|| Uuid::new_v4().to_string());
-
-        match self.conversation_repo.find_by_id(&conversation_id).await? {
-            Some(_) => {}
-            None => {
-                let title = {
-                    let t = request.message.trim();
-                    if t.chars().count() > 60 {
-                        format!("{}…", t.chars().take(60).collect::<String>())
+        self.sweep_ephemeral();
+        let conversation_id = request.conversation_id.unwrap_or_else(Uuid::new_v4);
+        Span::current().record("conversation_id", field::display(conversation_id));
+
+        let conversation = if let Some(conv) = self.ephemeral.find_conversation(conversation_id) {
+            conv
+        } else {
+            match self.conversation_repo.find_by_id(conversation_id).await? {
+                Some(conv) => conv,
+                None => {
+                    let conv = Conversation::new(conversation_id, title_for(&request.message));
+                    if request.ephemeral {
+                        self.ephemeral.create(conv.clone());
+                        conv
                     } else {
-                        t.to_string()
+                        self.conversation_repo.save(&conv).await?
                     }
-                };
-                let conv = Conversation::new(conversation_id.clone(), title);
-                self.conversation_repo.save(&conv).await?;
+                }
             }
         };
+        let change = UpdateConversationRequest {
+            verbosity: request.verbosity.filter(|v| *v != conversation.verbosity),
+            stateless: request.stateless.filter(|s| *s != conversation.stateless),
+            ..UpdateConversationRequest::default()
+        };
+        let conversation = if change.verbosity.is_some() || change.stateless.is_some() {
+            self.update_conversation(conversation_id, change).await?
+        } else {
+            conversation
+        };
+        let key = self.unlocked_key(&conversation)?;
+        let language = self.track_language(&conversation, &request.message).await;
 
-        // ── Persist user message ──────────────────────────────────────────────
-        let user_message = Message::new(
-            conversation_id.clone(),
-            MessageRole::User,
-            request.message.clone(),
-        );
-        self.message_repo.save(&user_message).await?;
-
-        // ── Fetch history (excludes the just-saved user message) ──────────────
-        let all_messages = self
-            .message_repo
-            .find_by_conversation_id(&conversation_id)
-            .await?;
-        let history: Vec<Message> = all_messages
-            .into_iter()
-            .filter(|m| m.id != user_message.id)
-            .collect();
+        // ── Fetch history, then persist the user message ──────────────────────
+        let mut history = match self.ephemeral.messages(conversation_id) {
+            Some(messages) => messages,
+            None => open_messages(
+                key.as_deref(),
+                self.message_repo.find_by_conversation_id(conversation_id).await?,
+            )?,
+        };
+        // Quotes are of messages already in this conversation.
+        if let Some(quoted) = &request.quoted_message_id {
+            if !history.iter().any(|m| &m.id == quoted) {
+                return Err(AppError::InvalidField {
+                    field_name: "quoted_message_id".to_string(),
+                    message: format!("'{quoted}' is not a message of this conversation"),
+                });
+            }
+        }
+        let history_cutoff = limit_history(&mut history, self.history_limit(&conversation));
+        let user_message = Message {
+            quoted_message_id: request.quoted_message_id,
+            ..Message::new(conversation_id, MessageRole::User, request.message.clone())
+        };
+        if !self.ephemeral.push_message(user_message.clone()) {
+            self.message_repo.save(&seal_message(key.as_deref(), &user_message)?).await?;
+            // Encrypted conversations' prompts stay out of the plaintext history.
+            if key.is_none() {
+                let recorded = self.profile_repo.record_prompt(LOCAL_USER_ID, &request.message);
+                if let Err(e) = recorded.await {
+                    error!("Failed to record prompt history: {e}");
+                }
+            }
+        }
 
-        Ok(ChatContext {
+        let preferences = self.turn_preferences(&conversation);
+        self.personalize(ChatContext {
             conversation_id,
+            conversation_title: conversation.title,
+            user_name: None,
+            language,
             history,
+            history_cutoff,
             user_message: request.message,
+            preferences,
+            webhook_tools: Vec::new(),
+            retrieved: Vec::new(),
         })
+        .await
     }
 
-    /// Persist a complete assistant response and update the conversation timestamp.
-    pub async fn save_assistant_message(
+    /// Checks a user message against the blocked terms, then the moderation
+    /// classifier when one is configured. Depending on `moderation_action`, a
+    /// hit either rejects the message or is only logged.
+    #[instrument(skip_all)]
+    async fn moderate(&self, message: &str) -> Result<(), AppError> {
+        let config = self.config.get();
+        let blocked = moderation::blocked_term(message, &config.moderation_blocked_terms);
+        let reason = if blocked.is_some() {
+            Some("it contains a blocked term".to_string())
+        } else if let Some(model) = &config.moderation_model {
+            let verdict = self.agent.moderate(model, message).await.map_err(|e| match e {
+                AppError::ModelNotFound { model_name } => AppError::InvalidConfig {
+                    message: format!("moderation_model '{model_name}' is not installed in Ollama"),
+                },
+                e => e,
+            })?;
+            verdict.map(|codes| {
+                format!("it was classified as {}", moderation::describe_categories(&codes))
+            })
+        } else {
+            None
+        };
+
+        let Some(reason) = reason else { return Ok(()) };
+        match config.moderation_action {
+            ModerationAction::Block => Err(AppError::ModerationBlocked { reason }),
+            ModerationAction::Flag => {
+                warn!("Flagged user message: {reason}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Detects the language of `message` and records it on the conversation
+    /// when it differs from what is stored. Messages too short to classify
+    /// keep the stored language. Returns the language to reply in.
+    async fn track_language(&self, conversation: &Conversation, message: &str) -> Option<String> {
+        let Some(detected) = language::detect(message) else {
+            return conversation.language.clone();
+        };
+        if conversation.language.as_deref() != Some(detected) {
+            if self.ephemeral.contains(conversation.id) {
+                self.ephemeral.set_language(conversation.id, detected);
+            } else if let Err(e) =
+                self.conversation_repo.update_language(conversation.id, detected).await
+            {
+                error!("Failed to store conversation language: {e}");
+            }
+        }
+        Some(detected.to_string())
+    }
+
+    /// Context for answering a conversation's last user message again, after
+    /// a turn failed before a reply was saved (e.g. while the model was being
+    /// pulled). Nothing new is persisted.
+    #[instrument(skip(self))]
+    pub async fn prepare_retry(&self, conversation_id: Uuid) -> Result<ChatContext, AppError> {
+        let conversation = match self.ephemeral.find_conversation(conversation_id) {
+            Some(conv) => conv,
+            None => self
+                .conversation_repo
+                .find_by_id(conversation_id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound {
+                    id: conversation_id,
+                })?,
+        };
+        let mut history = self.get_messages(conversation_id).await?;
+        // A preset switch since the failure applies to the retry.
+        let user_message = match history.iter().rposition(|m| m.role != MessageRole::System) {
+            Some(last) if history[last].role == MessageRole::User => history.remove(last).content,
+            _ => return Err(AppError::NothingToRetry { id: conversation_id }),
+        };
+        let history_cutoff = limit_history(&mut history, self.history_limit(&conversation));
+
+        let preferences = self.turn_preferences(&conversation);
+        self.personalize(ChatContext {
+            conversation_id,
+            conversation_title: conversation.title,
+            user_name: None,
+            language: conversation.language,
+            history,
+            history_cutoff,
+            user_message,
+            preferences,
+            webhook_tools: Vec::new(),
+            retrieved: Vec::new(),
+        })
+        .await
+    }
+
+    /// Persists the `FUNCTION` and `TOOL` messages of a turn, so tool calls
+    /// are replayed with the rest of the history.
+    pub async fn save_tool_messages(&self, messages: &[Message]) -> Result<(), AppError> {
+        for message in messages {
+            self.store_message(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Final check of a `json` turn's streamed reply. A reply that is not
+    /// valid JSON (or was already found malformed while streaming, as
+    /// `early_error`) gets one non-streamed repair attempt. Returns the content
+    /// to save and the validation outcome.
+    pub async fn settle_json_reply(
         &self,
-        conversation_id: &str,
-        content: &str,
-    ) -> Result<Message, AppError> {
-        let msg = Message::new(
-            conversation_id.to_string(),
+        ctx: &ChatContext,
+        content: String,
+        early_error: Option<String>,
+    ) -> (String, JsonValidation) {
+        let error = match early_error.map_or_else(|| json_mode::validate(&content), Err) {
+            Ok(()) => return (content, JsonValidation::Valid),
+            Err(error) => error,
+        };
+        let mut repair = ctx.clone();
+        repair.history.push(Message::new(
+            ctx.conversation_id,
+            MessageRole::User,
+            ctx.user_message.clone(),
+        ));
+        repair.history.push(Message::new(
+            ctx.conversation_id,
             MessageRole::Assistant,
-            content.to_string(),
-        );
-        self.message_repo.save(&msg).await?;
-        if let Err(e) = self.conversation_repo.update_timestamp(conversation_id).await {
-            error!("Failed to update conversation timestamp: {e}");
+            content.clone(),
+        ));
+        repair.user_message = json_mode::repair_prompt(&error);
+        match self.agent.chat(&repair).await {
+            Ok(reply) if json_mode::validate(&reply.content).is_ok() => {
+                (reply.content, JsonValidation::Repaired { error })
+            }
+            Ok(_) => (content, JsonValidation::Invalid { error }),
+            Err(e) => {
+                warn!("JSON repair attempt failed: {e}");
+                (content, JsonValidation::Invalid { error })
+            }
         }
+    }
+
+    /// Persist a complete assistant response and its token usage, and update
+    /// the conversation timestamp.
+    #[instrument(skip_all, fields(
+        conversation_id = %ctx.conversation_id,
+        model = %stats.model,
+        prompt_tokens = stats.prompt_tokens,
+        completion_tokens = stats.completion_tokens,
+    ))]
+    pub async fn save_assistant_message(
+        &self,
+        ctx: &ChatContext,
+        content: &str,
+        sources: Vec<Source>,
+        stats: &CompletionStats,
+    ) -> Result<Message, AppError> {
+        let conversation_id = ctx.conversation_id;
+        let reply = self.post_process(ctx, content.to_string(), sources);
+        let msg = Message::new(conversation_id, MessageRole::Assistant, reply.content)
+            .with_sources(reply.sources);
+        self.store_message(&msg).await?;
+        self.save_retrieval(ctx, &msg).await;
+        if !self.ephemeral.contains(conversation_id) {
+            let usage = self
+                .message_repo
+                .record_usage(msg.id, &stats.model, stats.prompt_tokens, stats.completion_tokens)
+                .await;
+            if let Err(e) = usage {
+                error!("Failed to record message usage: {e}");
+            }
+        }
+        self.log_turn(ctx, &msg, Some(stats)).await;
         Ok(msg)
     }
+
+    /// Runs a complete reply through the configured post-processors. JSON
+    /// replies are left alone, as the steps could make them invalid.
+    fn post_process(&self, ctx: &ChatContext, content: String, sources: Vec<Source>) -> Reply {
+        let mut reply = Reply { content, sources };
+        if ctx.preferences.response_format != ResponseFormat::Json {
+            self.post_processors.run(&mut reply, &self.config.get());
+        }
+        reply
+    }
+
+    /// Appends a completed turn to the transcript log, if one is configured.
+    /// Incognito turns, and those of encrypted conversations, are never written
+    /// to disk.
+    async fn log_turn(&self, ctx: &ChatContext, reply: &Message, stats: Option<&CompletionStats>) {
+        if self.ephemeral.contains(ctx.conversation_id)
+            || self.keys.get(ctx.conversation_id).is_some()
+        {
+            return;
+        }
+        let entry = TranscriptEntry {
+            timestamp: reply.created_at,
+            conversation_id: ctx.conversation_id,
+            message_id: reply.id,
+            user: ctx.user_message.clone(),
+            assistant: reply.content.clone(),
+            sources: reply.sources.clone(),
+            stats: stats.cloned(),
+        };
+        if let Err(e) = self.transcripts.append(&entry).await {
+            warn!("Failed to log transcript for {}: {e}", ctx.conversation_id);
+        }
+    }
+
+    /// The last `limit` transcript entries of a conversation.
+    pub async fn tail_transcript(
+        &self,
+        conversation_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<TranscriptEntry>, AppError> {
+        self.transcripts.tail(conversation_id, limit).await
+    }
+
+    /// Generations running against the model right now.
+    pub fn generations(&self) -> Vec<GenerationInfo> {
+        self.agent.generations()
+    }
+
+    /// Degraded while the model is slower to start answering than its SLA.
+    pub fn readiness(&self) -> Readiness {
+        let first_token = self.agent.first_token_latency();
+        let status = if first_token.degraded { "degraded" } else { "ready" };
+        Readiness { status: status.to_string(), degraded: first_token.degraded, first_token }
+    }
+
+    /// Stops a running generation; see [`AgentService::cancel_generation`].
+    pub fn cancel_generation(&self, id: &str) -> Result<(), AppError> {
+        if !self.agent.cancel_generation(id) {
+            return Err(AppError::RecordNotFound {
+                entity_type: "generation".to_string(),
+                id: id.to_string(),
+            });
+        }
+        info!("Generation {id} cancelled by an administrator");
+        Ok(())
+    }
+
+    /// Cost of a reply under the configured pricing, when the model is priced
+    /// and reported its token counts.
+    pub fn price(
+        &self,
+        model: &str,
+        prompt_tokens: Option<u64>,
+        completion_tokens: Option<u64>,
+    ) -> Option<f64> {
+        let price = *self.config.get().pricing.get(model)?;
+        Some(price.cost(prompt_tokens?, completion_tokens?))
+    }
+
+    /// Token totals and cost of a conversation's streamed replies. Ephemeral
+    /// conversations keep no usage.
+    pub async fn get_conversation_stats(&self, id: Uuid) -> Result<ConversationStats, AppError> {
+        if self.ephemeral.contains(id) {
+            return Ok(ConversationStats::default());
+        }
+        self.conversation_repo
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::ConversationNotFound { id })?;
+
+        let pricing = self.config.get().pricing.clone();
+        let mut stats = ConversationStats::default();
+        for mut usage in self.message_repo.usage_by_model(id).await? {
+            usage.cost = pricing
+                .get(&usage.model)
+                .map(|p| p.cost(usage.prompt_tokens as u64, usage.completion_tokens as u64));
+            stats.prompt_tokens += usage.prompt_tokens;
+            stats.completion_tokens += usage.completion_tokens;
+            if let Some(cost) = usage.cost {
+                stats.cost = Some(stats.cost.unwrap_or(0.0) + cost);
+            }
+            stats.models.push(usage);
+        }
+        Ok(stats)
+    }
+
+    /// Renders a conversation's user and assistant messages as a PDF, on a
+    /// blocking thread. Returns the conversation too, for naming the file.
+    pub async fn export_pdf(&self, id: Uuid) -> Result<(Conversation, Vec<u8>), AppError> {
+        let conversation = match self.ephemeral.find_conversation(id) {
+            Some(conversation) => conversation,
+            None => self
+                .conversation_repo
+                .find_by_id(id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound { id })?,
+        };
+        let messages = self.get_messages(id).await?;
+        tokio::task::spawn_blocking(move || {
+            pdf::render(&conversation, &messages).map(|pdf| (conversation, pdf))
+        })
+        .await
+        .map_err(|e| AppError::Unexpected(format!("PDF rendering failed: {e}")))?
+    }
+
+    /// Sends everything stored for the current user through `tx`, one archive
+    /// entry at a time: the profile, then each saved conversation with its
+    /// messages. Stops early if the receiver goes away.
+    #[instrument(skip_all)]
+    pub async fn export_all(
+        &self,
+        tx: &mpsc::Sender<Result<ExportEntry, AppError>>,
+    ) -> Result<(), AppError> {
+        let profile = self.get_profile().await?;
+        if !send_entry(tx, "profile.json", &profile).await? {
+            return Ok(());
+        }
+
+        let mut exported = 0usize;
+        let mut after: Option<Uuid> = None;
+        loop {
+            let batch = self
+                .conversation_repo
+                .find_batch(after, EXPORT_BATCH_SIZE)
+                .await?;
+            let Some(last) = batch.last() else { break };
+            after = Some(last.id);
+
+            for conversation in batch {
+                let messages = self.message_repo.find_by_conversation_id(conversation.id).await?;
+                let mut versions = BTreeMap::new();
+                for message in messages.iter().filter(|m| m.version_count > 1) {
+                    versions.insert(
+                        message.id,
+                        self.message_repo.find_versions(message.id).await?,
+                    );
+                }
+                let path = format!("conversations/{}.json", conversation.id);
+                let export = ConversationExport { conversation, messages, versions };
+                if !send_entry(tx, &path, &export).await? {
+                    return Ok(());
+                }
+                exported += 1;
+            }
+        }
+
+        let manifest = serde_json::json!({
+            "exported_at": Utc::now(),
+            "conversations": exported,
+        });
+        send_entry(tx, "export.json", &manifest).await?;
+        Ok(())
+    }
+
+    /// Starts replaying the user turns of the given conversations against
+    /// `request.model`. The replay runs in the background; poll
+    /// [`ChatService::get_eval_report`] for its results.
+    #[instrument(skip_all, fields(model = %request.model))]
+    pub async fn start_eval(&self, request: CreateEvalRequest) -> Result<EvalRun, AppError> {
+        let model = request.model.trim().to_string();
+        if model.is_empty() {
+            return Err(AppError::EmptyField { field_name: "model".to_string() });
+        }
+        if model.len() > MAX_MODEL_NAME_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "model".to_string(),
+                max_length: MAX_MODEL_NAME_LENGTH,
+                actual_length: model.len(),
+            });
+        }
+        if request.conversation_ids.is_empty() {
+            return Err(AppError::EmptyField { field_name: "conversation_ids".to_string() });
+        }
+        if request.conversation_ids.len() > MAX_EVAL_CONVERSATIONS {
+            return Err(AppError::InvalidField {
+                field_name: "conversation_ids".to_string(),
+                message: format!("at most {MAX_EVAL_CONVERSATIONS} conversations per run"),
+            });
+        }
+        let mut conversations = Vec::with_capacity(request.conversation_ids.len());
+        for &id in &request.conversation_ids {
+            let conversation = self
+                .conversation_repo
+                .find_by_id(id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound { id })?;
+            // Results are stored in the clear.
+            if conversation.encrypted {
+                return Err(AppError::InvalidField {
+                    field_name: "conversation_ids".to_string(),
+                    message: format!("conversation '{id}' is encrypted"),
+                });
+            }
+            conversations.push(conversation);
+        }
+
+        let run = EvalRun {
+            id: Uuid::new_v4(),
+            model,
+            status: EvalStatus::Running,
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+        };
+        self.eval_repo.save_run(&run).await?;
+
+        let svc = self.clone();
+        let (run_id, model) = (run.id, run.model.clone());
+        tokio::spawn(async move {
+            let (status, error) = match svc.replay(run_id, &model, conversations).await {
+                Ok(()) => (EvalStatus::Completed, None),
+                Err(e) => {
+                    error!("Eval run {run_id} failed: {e}");
+                    (EvalStatus::Failed, Some(e.to_string()))
+                }
+            };
+            if let Err(e) = svc.eval_repo.finish_run(run_id, status, error.as_deref()).await {
+                error!("Failed to record the end of eval run {run_id}: {e}");
+            }
+        });
+        Ok(run)
+    }
+
+    /// Answers every stored user turn again with `model`, replaying the
+    /// original history before it. A turn the model fails is recorded with
+    /// its error and the run carries on.
+    #[instrument(skip(self, conversations), fields(conversations = conversations.len()))]
+    async fn replay(
+        &self,
+        run_id: Uuid,
+        model: &str,
+        conversations: Vec<Conversation>,
+    ) -> Result<(), AppError> {
+        for conversation in conversations {
+            let messages = self.message_repo.find_by_conversation_id(conversation.id).await?;
+            let limit = self.history_limit(&conversation);
+            let mut turn = 0;
+            for (i, message) in messages.iter().enumerate() {
+                if message.role != MessageRole::User {
+                    continue;
+                }
+                turn += 1;
+                let original = messages[i + 1..]
+                    .iter()
+                    .find(|m| !m.role.is_tool_traffic())
+                    .filter(|m| m.role == MessageRole::Assistant)
+                    .map(|m| m.content.clone());
+                let mut history = messages[..i].to_vec();
+                let history_cutoff = limit_history(&mut history, limit);
+
+                let mut ctx = self
+                    .personalize(ChatContext {
+                        conversation_id: conversation.id,
+                        conversation_title: conversation.title.clone(),
+                        user_name: None,
+                        language: conversation.language.clone(),
+                        history,
+                        history_cutoff,
+                        user_message: message.content.clone(),
+                        preferences: self.turn_preferences(&conversation),
+                        webhook_tools: Vec::new(),
+                        retrieved: Vec::new(),
+                    })
+                    .await?;
+                ctx.preferences.model = Some(model.to_string());
+
+                let (new_content, error) = match self.agent.chat(&ctx).await {
+                    Ok(reply) => (Some(reply.content), None),
+                    Err(e) => (None, Some(e.to_string())),
+                };
+                self.eval_repo
+                    .save_result(&EvalResult {
+                        id: Uuid::new_v4(),
+                        run_id,
+                        conversation_id: conversation.id,
+                        turn,
+                        user_message: message.content.clone(),
+                        original_content: original,
+                        new_content,
+                        error,
+                        created_at: Utc::now(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every eval run, newest first.
+    pub async fn list_evals(&self) -> Result<Vec<EvalRun>, AppError> {
+        self.eval_repo.find_runs().await
+    }
+
+    /// An eval run's results so far, each diffed against the original reply.
+    pub async fn get_eval_report(&self, id: Uuid) -> Result<EvalReport, AppError> {
+        let run = self.eval_repo.find_run(id).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "eval run".to_string(),
+            id: id.to_string(),
+        })?;
+        let results = self.eval_repo.find_results(id).await?;
+        Ok(evals::build_report(run, results))
+    }
+
+    /// Queues standalone prompts to be answered in the background, at most
+    /// `max_concurrent_generations` at a time so interactive turns can still
+    /// get a slot. Returns the new job with its items queued.
+    pub async fn start_batch(&self, request: CreateBatchRequest) -> Result<BatchJob, AppError> {
+        let config = self.config.get();
+        if request.prompts.is_empty() {
+            return Err(AppError::EmptyField { field_name: "prompts".to_string() });
+        }
+        if request.prompts.len() > MAX_BATCH_PROMPTS {
+            return Err(AppError::InvalidField {
+                field_name: "prompts".to_string(),
+                message: format!("at most {MAX_BATCH_PROMPTS} prompts per batch"),
+            });
+        }
+        let default_model = batch_model(request.model, "model")?.unwrap_or(config.model.clone());
+
+        let mut items = Vec::with_capacity(request.prompts.len());
+        for (position, item) in request.prompts.into_iter().enumerate() {
+            let field_name = format!("prompts[{position}].prompt");
+            if item.prompt.trim().is_empty() {
+                return Err(AppError::EmptyField { field_name });
+            }
+            if item.prompt.len() > config.max_message_length {
+                return Err(AppError::FieldTooLong {
+                    field_name,
+                    max_length: config.max_message_length,
+                    actual_length: item.prompt.len(),
+                });
+            }
+            let model = batch_model(item.model, &format!("prompts[{position}].model"))?;
+            items.push(BatchItem {
+                position: position as i32,
+                prompt: item.prompt,
+                model: model.unwrap_or_else(|| default_model.clone()),
+                status: BatchStatus::Queued,
+                content: None,
+                error: None,
+                started_at: None,
+                completed_at: None,
+            });
+        }
+
+        let job = BatchJob {
+            id: Uuid::new_v4(),
+            status: BatchStatus::Running,
+            created_at: Utc::now(),
+            completed_at: None,
+            items,
+        };
+        self.batch_repo.save_job(&job).await?;
+
+        let svc = self.clone();
+        let (job_id, items) = (job.id, job.items.clone());
+        let concurrency = config.max_concurrent_generations.max(1);
+        tokio::spawn(async move {
+            let answered: Vec<bool> = futures_util::stream::iter(items)
+                .map(|item| svc.run_batch_item(job_id, item))
+                .buffer_unordered(concurrency)
+                .collect()
+                .await;
+            if let Err(e) = svc.batch_repo.finish_job(job_id, BatchStatus::Completed).await {
+                error!("Failed to record the end of batch job {job_id}: {e}");
+            }
+            let succeeded = answered.iter().filter(|&&a| a).count();
+            let event = if succeeded == answered.len() {
+                NotificationEvent::BatchCompleted
+            } else {
+                NotificationEvent::BatchFailed
+            };
+            let summary = format!("{succeeded} of {} prompts answered", answered.len());
+            svc.notify(event, job_id, summary).await;
+        });
+        Ok(job)
+    }
+
+    /// Answers one batch prompt on its own, without history or the user's
+    /// preferences, and records the reply or the error. Returns whether it
+    /// was answered.
+    #[instrument(skip(self, item), fields(position = item.position, model = %item.model))]
+    async fn run_batch_item(&self, job_id: Uuid, item: BatchItem) -> bool {
+        if let Err(e) = self.batch_repo.start_item(job_id, item.position).await {
+            error!("Failed to start batch item: {e}");
+        }
+        let reply = match self.moderate(&item.prompt).await {
+            Ok(()) => {
+                let ctx = ChatContext {
+                    conversation_id: job_id,
+                    conversation_title: "Batch".to_string(),
+                    user_name: None,
+                    language: None,
+                    history: Vec::new(),
+                    history_cutoff: None,
+                    user_message: item.prompt,
+                    preferences: TurnPreferences {
+                        model: Some(item.model),
+                        ..TurnPreferences::default()
+                    },
+                    webhook_tools: Vec::new(),
+                    retrieved: Vec::new(),
+                };
+                self.agent.chat(&ctx).await.map(|reply| reply.content)
+            }
+            Err(e) => Err(e),
+        };
+        let (content, error) = match reply {
+            Ok(content) => (Some(content), None),
+            Err(e) => {
+                warn!("Batch item {} of job {job_id} failed: {e}", item.position);
+                (None, Some(e.to_string()))
+            }
+        };
+        let finished = self
+            .batch_repo
+            .finish_item(job_id, item.position, content.as_deref(), error.as_deref())
+            .await;
+        if let Err(e) = finished {
+            error!("Failed to record batch item: {e}");
+        }
+        error.is_none()
+    }
+
+    /// Starts indexing a git repository into a collection (`codebase` unless
+    /// named) in the background. A local repository must be under
+    /// `workspace_dir`; anything else is cloned over https.
+    pub async fn ingest_git(&self, request: IngestGitRequest) -> Result<Ingestion, AppError> {
+        let config = self.config.get();
+        let repository = request.repository.trim().to_string();
+        if repository.is_empty() {
+            return Err(AppError::EmptyField { field_name: "repository".to_string() });
+        }
+        let origin = if repository.starts_with("https://") {
+            Origin::Remote(repository.clone())
+        } else if repository.contains("://") || repository.starts_with("git@") {
+            return Err(AppError::InvalidField {
+                field_name: "repository".to_string(),
+                message: "must be an https URL or a path inside the workspace".to_string(),
+            });
+        } else {
+            let Some(dir) = config.workspace_dir.clone() else {
+                return Err(AppError::InvalidField {
+                    field_name: "repository".to_string(),
+                    message: "no workspace_dir is configured".to_string(),
+                });
+            };
+            Origin::Local(Workspace::new(dir).resolve(&repository).await?)
+        };
+        let branch = request.branch.map(|b| b.trim().to_string()).filter(|b| !b.is_empty());
+        let collection = request.collection.as_deref().map(str::trim).filter(|c| !c.is_empty());
+        let collection = self.get_collection(collection.unwrap_or(CODEBASE_COLLECTION)).await?;
+
+        let ingestion = Ingestion::running(collection.name.clone(), repository);
+        self.document_repo.save_ingestion(&ingestion).await?;
+
+        let svc = self.clone();
+        let (id, source) = (ingestion.id, ingestion.source.clone());
+        tokio::spawn(async move {
+            let config = svc.config.get();
+            let embedding_model =
+                collection.embedding_model.as_deref().unwrap_or(&config.embedding_model);
+            let settings = IngestSettings {
+                collection: &collection.name,
+                embedding_model,
+                max_file_bytes: config.ingest_max_file_bytes,
+                chunks: svc.chunk_settings(&collection),
+            };
+            let outcome = ingest::ingest(
+                svc.agent.as_ref(),
+                &svc.document_repo,
+                &source,
+                origin,
+                branch,
+                settings,
+            )
+            .await;
+            svc.finish_ingestion(id, &source, outcome).await;
+        });
+        Ok(ingestion)
+    }
+
+    /// Starts splitting every document of a collection again in the
+    /// background, after giving it the chunking settings of `request`.
+    pub async fn rechunk_collection(
+        &self,
+        name: &str,
+        request: RechunkRequest,
+    ) -> Result<Ingestion, AppError> {
+        let mut collection = self.get_collection(name).await?;
+        collection.chunker = request.chunker.unwrap_or(collection.chunker);
+        collection.chunk_max_lines = request.chunk_max_lines.or(collection.chunk_max_lines);
+        collection.chunk_overlap = request.chunk_overlap.or(collection.chunk_overlap);
+        validate_chunking(&collection, self.config.get().chunk_max_lines)?;
+        self.collection_repo
+            .update_chunking(
+                &collection.name,
+                collection.chunker,
+                collection.chunk_max_lines,
+                collection.chunk_overlap,
+            )
+            .await?;
+
+        let ingestion = Ingestion::running(collection.name.clone(), RECHUNK_SOURCE.to_string());
+        self.document_repo.save_ingestion(&ingestion).await?;
+
+        let svc = self.clone();
+        let id = ingestion.id;
+        tokio::spawn(async move {
+            let config = svc.config.get();
+            let embedding_model =
+                collection.embedding_model.as_deref().unwrap_or(&config.embedding_model);
+            let outcome = ingest::rechunk(
+                svc.agent.as_ref(),
+                &svc.document_repo,
+                &collection.name,
+                embedding_model,
+                svc.chunk_settings(&collection),
+            )
+            .await;
+            svc.finish_ingestion(id, RECHUNK_SOURCE, outcome).await;
+        });
+        Ok(ingestion)
+    }
+
+    /// How a sample document would be split in a collection, with the
+    /// collection's chunking settings or those of `request`. Nothing is
+    /// embedded or saved.
+    pub async fn preview_chunks(
+        &self,
+        name: &str,
+        request: ChunkPreviewRequest,
+    ) -> Result<Vec<ChunkPreview>, AppError> {
+        let mut collection = self.get_collection(name).await?;
+        let config = self.config.get();
+        if request.content.len() as u64 > config.ingest_max_file_bytes {
+            return Err(AppError::FieldTooLong {
+                field_name: "content".to_string(),
+                max_length: config.ingest_max_file_bytes as usize,
+                actual_length: request.content.len(),
+            });
+        }
+        collection.chunker = request.chunker.unwrap_or(collection.chunker);
+        collection.chunk_max_lines = request.chunk_max_lines.or(collection.chunk_max_lines);
+        collection.chunk_overlap = request.chunk_overlap.or(collection.chunk_overlap);
+        validate_chunking(&collection, config.chunk_max_lines)?;
+        let language = request.path.as_deref().and_then(splitter::language).unwrap_or("text");
+        let settings = self.chunk_settings(&collection);
+        let sections = splitter::split(&request.content, language, settings);
+        let preview = sections.into_iter().map(|section| ChunkPreview {
+            content: section.content,
+            start_line: section.start_line,
+            end_line: section.end_line,
+        });
+        Ok(preview.collect())
+    }
+
+    /// How the documents of `collection` are split, its own settings first.
+    fn chunk_settings(&self, collection: &Collection) -> ChunkSettings {
+        let config = self.config.get();
+        ChunkSettings {
+            chunker: collection.chunker,
+            max_lines: collection
+                .chunk_max_lines
+                .map_or(config.chunk_max_lines, |lines| lines as usize),
+            overlap: collection.chunk_overlap.map_or(config.chunk_overlap, |lines| lines as usize),
+        }
+    }
+
+    /// Records how a background ingestion ended.
+    async fn finish_ingestion(
+        &self,
+        id: Uuid,
+        source: &str,
+        outcome: Result<IngestionReport, AppError>,
+    ) {
+        let (status, report, message) = match outcome {
+            Ok(report) => (IngestionStatus::Completed, report, None),
+            Err(e) => {
+                error!("Ingestion {id} of {source} failed: {e}");
+                (IngestionStatus::Failed, IngestionReport::default(), Some(e.to_string()))
+            }
+        };
+        let finished =
+            self.document_repo.finish_ingestion(id, status, &report, message.as_deref()).await;
+        if let Err(e) = finished {
+            error!("Failed to record the end of ingestion {id}: {e}");
+        }
+    }
+
+    /// Creates a collection; its name must be free and fit in a URL.
+    pub async fn create_collection(
+        &self,
+        request: CreateCollectionRequest,
+    ) -> Result<Collection, AppError> {
+        let name = request.name.trim().to_string();
+        let valid_name = name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+        if name.is_empty() || name.len() > MAX_COLLECTION_NAME_LENGTH || !valid_name {
+            return Err(AppError::InvalidField {
+                field_name: "name".to_string(),
+                message: format!(
+                    "must be 1 to {MAX_COLLECTION_NAME_LENGTH} lowercase letters, digits, \
+                     '_' or '-'"
+                ),
+            });
+        }
+        let collection = Collection {
+            name,
+            description: text_field(
+                "description",
+                request.description,
+                MAX_COLLECTION_DESCRIPTION_LENGTH,
+            )?,
+            embedding_model: text_field(
+                "embedding_model",
+                request.embedding_model,
+                MAX_MODEL_NAME_LENGTH,
+            )?,
+            chunker: request.chunker,
+            chunk_max_lines: request.chunk_max_lines,
+            chunk_overlap: request.chunk_overlap,
+            global: request.global,
+            created_at: Utc::now(),
+            documents: 0,
+            chunks: 0,
+        };
+        validate_chunking(&collection, self.config.get().chunk_max_lines)?;
+        if !self.collection_repo.save(&collection).await? {
+            return Err(AppError::InvalidField {
+                field_name: "name".to_string(),
+                message: format!("a collection named '{}' already exists", collection.name),
+            });
+        }
+        Ok(collection)
+    }
+
+    pub async fn list_collections(&self) -> Result<Vec<Collection>, AppError> {
+        self.collection_repo.find_all().await
+    }
+
+    pub async fn get_collection(&self, name: &str) -> Result<Collection, AppError> {
+        self.collection_repo.find_by_name(name).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "collection".to_string(),
+            id: name.to_string(),
+        })
+    }
+
+    /// Deletes a collection along with everything indexed in it.
+    pub async fn delete_collection(&self, name: &str) -> Result<(), AppError> {
+        if !self.collection_repo.delete(name).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "collection".to_string(),
+                id: name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// A page of a collection's chunks as indexed, to check how its
+    /// documents were split.
+    pub async fn list_collection_chunks(
+        &self,
+        name: &str,
+        query: ChunksQuery,
+    ) -> Result<Vec<CollectionChunk>, AppError> {
+        let collection = self.get_collection(name).await?;
+        let limit = query.limit.unwrap_or(DEFAULT_CHUNK_LIMIT).clamp(1, MAX_CHUNK_LIMIT);
+        let path = query.path.as_deref().map(str::trim).filter(|p| !p.is_empty());
+        self.document_repo
+            .find_chunk_page(&collection.name, path, limit, query.offset.unwrap_or(0).max(0))
+            .await
+    }
+
+    /// The chunks of one collection a turn asking `request.query` would be
+    /// given, with their scores. Unlike a turn's retrieval, embedding
+    /// failures are reported.
+    pub async fn search_collection(
+        &self,
+        name: &str,
+        request: CollectionSearchRequest,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        let collection = self.get_collection(name).await?;
+        let query = text_field("query", Some(request.query), self.config.get().max_message_length)?
+            .ok_or_else(|| AppError::EmptyField { field_name: "query".to_string() })?;
+        let limit = request
+            .limit
+            .unwrap_or(self.config.get().retrieval_top_k)
+            .clamp(1, MAX_SEARCH_HITS);
+        let configured = self.retrieval_weights();
+        let weights = Weights {
+            vector: search_weight("vector_weight", request.vector_weight, configured.vector)?,
+            text: search_weight("text_weight", request.text_weight, configured.text)?,
+        };
+        if weights.vector == 0.0 && weights.text == 0.0 {
+            return Err(AppError::InvalidField {
+                field_name: "vector_weight".to_string(),
+                message: "vector_weight and text_weight cannot both be 0".to_string(),
+            });
+        }
+        self.rank(&query, &[collection], limit, weights).await
+    }
+
+    /// The collections attached to a conversation, besides the global ones
+    /// every conversation searches.
+    pub async fn list_conversation_collections(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<Collection>, AppError> {
+        self.conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: conversation_id })?;
+        self.collection_repo.find_attached(conversation_id).await
+    }
+
+    /// Lets a conversation's turns retrieve from a collection. Returns the
+    /// collections now attached.
+    pub async fn attach_collection(
+        &self,
+        conversation_id: Uuid,
+        name: &str,
+    ) -> Result<Vec<Collection>, AppError> {
+        self.conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: conversation_id })?;
+        let collection = self.get_collection(name).await?;
+        self.collection_repo.attach(conversation_id, &collection.name).await?;
+        self.collection_repo.find_attached(conversation_id).await
+    }
+
+    pub async fn detach_collection(
+        &self,
+        conversation_id: Uuid,
+        name: &str,
+    ) -> Result<Vec<Collection>, AppError> {
+        if !self.collection_repo.detach(conversation_id, name).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "attached collection".to_string(),
+                id: name.to_string(),
+            });
+        }
+        self.collection_repo.find_attached(conversation_id).await
+    }
+
+    pub async fn get_ingestion(&self, id: Uuid) -> Result<Ingestion, AppError> {
+        self.document_repo.find_ingestion(id).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "ingestion".to_string(),
+            id: id.to_string(),
+        })
+    }
+
+    pub async fn get_batch(&self, id: Uuid) -> Result<BatchJob, AppError> {
+        self.batch_repo.find_job(id).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "batch job".to_string(),
+            id: id.to_string(),
+        })
+    }
+
+    /// Saves a reply to wherever its conversation lives, sealed if the
+    /// conversation is encrypted, and bumps the conversation's timestamp.
+    async fn store_message(&self, message: &Message) -> Result<(), AppError> {
+        let answered = message.role == MessageRole::Assistant;
+        if self.ephemeral.push_message(message.clone()) {
+            if answered {
+                self.ephemeral.set_failed_turn(message.conversation_id, None);
+            }
+            return Ok(());
+        }
+        let key = self.key_for(message.conversation_id).await?;
+        self.message_repo.save(&seal_message(key.as_deref(), message)?).await?;
+        if let Err(e) = self.conversation_repo.update_timestamp(message.conversation_id).await {
+            error!("Failed to update conversation timestamp: {e}");
+        }
+        if answered {
+            if let Err(e) =
+                self.conversation_repo.update_failed_turn(message.conversation_id, None).await
+            {
+                error!("Failed to clear failed turn: {e}");
+            }
+            // Webhooks would carry the plaintext of an encrypted conversation
+            if key.is_none() {
+                self.webhooks.message_created(message).await;
+            }
+        }
+        self.schedule_summary(message.conversation_id);
+        Ok(())
+    }
+
+    /// Refreshes the conversation's summary in the background once enough
+    /// messages have arrived since the last one.
+    fn schedule_summary(&self, conversation_id: Uuid) {
+        let interval = self.config.get().summary_interval;
+        if interval == 0 {
+            return;
+        }
+        let svc = self.clone();
+        tokio::spawn(
+            async move {
+                if let Err(e) = svc.refresh_summary(conversation_id, interval).await {
+                    warn!("Failed to summarize conversation {conversation_id}: {e}");
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    #[instrument(skip(self))]
+    async fn refresh_summary(&self, id: Uuid, interval: usize) -> Result<(), AppError> {
+        // A summary would be stored in the clear.
+        let Some(conversation) = self.conversation_repo.find_by_id(id).await? else {
+            return Ok(());
+        };
+        if conversation.encrypted {
+            return Ok(());
+        }
+        let messages = self.message_repo.find_by_conversation_id(id).await?;
+        if messages.len() < conversation.summary_message_count as usize + interval {
+            return Ok(());
+        }
+
+        let config = self.config.get();
+        let model = config.summary_model.as_deref().unwrap_or(&config.model);
+        let summary = self.agent.summarize(model, &summary_transcript(&messages)).await?;
+        if summary.is_empty() {
+            return Ok(());
+        }
+        self.conversation_repo.update_summary(id, &summary, messages.len() as i32).await
+    }
+
+    /// Asks for follow-up prompts for `message_id` and stores them on it.
+    /// Empty when suggestions are off, the conversation is encrypted (they
+    /// would be stored in the clear), or the message is no longer the newest.
+    #[instrument(skip(self))]
+    pub async fn suggest_follow_ups(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<String>, AppError> {
+        let config = self.config.get();
+        if config.follow_up_suggestions == 0 {
+            return Ok(Vec::new());
+        }
+        let messages = match self.ephemeral.messages(conversation_id) {
+            Some(messages) => messages,
+            None => match self.conversation_repo.find_by_id(conversation_id).await? {
+                Some(conversation) if !conversation.encrypted => {
+                    self.message_repo.find_by_conversation_id(conversation_id).await?
+                }
+                _ => return Ok(Vec::new()),
+            },
+        };
+        if messages.last().map(|m| m.id) != Some(message_id) {
+            return Ok(Vec::new());
+        }
+
+        let recent = &messages[messages.len().saturating_sub(SUGGESTION_CONTEXT_MESSAGES)..];
+        let model = config.summary_model.as_deref().unwrap_or(&config.model);
+        let suggestions = self
+            .agent
+            .suggest_follow_ups(model, &summary_transcript(recent), config.follow_up_suggestions)
+            .await?;
+        if !self.ephemeral.set_suggestions(conversation_id, message_id, suggestions.clone()) {
+            self.message_repo.update_suggestions(message_id, &suggestions).await?;
+        }
+        Ok(suggestions)
+    }
+
+    /// Runs [`ChatService::suggest_follow_ups`] in the background, for replies
+    /// not streamed over the WebSocket.
+    fn schedule_suggestions(&self, conversation_id: Uuid, message_id: Uuid) {
+        let svc = self.clone();
+        tokio::spawn(
+            async move {
+                if let Err(e) = svc.suggest_follow_ups(conversation_id, message_id).await {
+                    warn!("Failed to suggest follow-ups for message {message_id}: {e}");
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    fn sweep_ephemeral(&self) {
+        let ttl_minutes = self.config.get().ephemeral_ttl_minutes;
+        self.ephemeral.sweep(Duration::from_secs(ttl_minutes * 60));
+    }
+
+    /// Generates a new answer for an assistant message, keeping the previous
+    /// answer as an earlier version.
+    #[instrument(skip(self))]
+    pub async fn regenerate(&self, message_id: Uuid) -> Result<Message, AppError> {
+        let ctx = self.prepare_regeneration(message_id).await?;
+        let reply = self.agent.chat(&ctx).await?;
+        let reply = self.post_process(&ctx, reply.content, reply.sources);
+
+        let key = self.key_for(ctx.conversation_id).await?;
+        let content = seal_content(key.as_deref(), reply.content)?;
+        let message = self.message_repo.add_version(message_id, &content, &reply.sources).await?;
+        if let Err(e) = self.conversation_repo.update_timestamp(ctx.conversation_id).await {
+            error!("Failed to update conversation timestamp: {e}");
+        }
+        self.schedule_suggestions(ctx.conversation_id, message_id);
+        let message = open_message(key.as_deref(), message)?;
+        self.save_artifacts(&message).await;
+        self.save_retrieval(&ctx, &message).await;
+        Ok(message)
+    }
+
+    /// Rebuilds the context an assistant message was originally generated from:
+    /// the user message it answered and everything before that.
+    async fn prepare_regeneration(&self, message_id: Uuid) -> Result<ChatContext, AppError> {
+        let target = self.find_message(message_id).await?;
+        if target.role != MessageRole::Assistant {
+            return Err(AppError::NotAnAssistantMessage { id: message_id });
+        }
+
+        let conversation = self
+            .conversation_repo
+            .find_by_id(target.conversation_id)
+            .await?
+            .ok_or_else(|| AppError::ConversationNotFound {
+                id: target.conversation_id,
+            })?;
+        let key = self.unlocked_key(&conversation)?;
+        let mut history = open_messages(
+            key.as_deref(),
+            self.message_repo.find_by_conversation_id(target.conversation_id).await?,
+        )?;
+
+        let target_index = history
+            .iter()
+            .position(|m| m.id == target.id)
+            .unwrap_or(history.len());
+        history.truncate(target_index);
+        let prompt_index = history
+            .iter()
+            .rposition(|m| m.role == MessageRole::User)
+            .ok_or_else(|| AppError::Unexpected(format!(
+                "No user message precedes assistant message {message_id}"
+            )))?;
+        let user_message = history.remove(prompt_index).content;
+        history.truncate(prompt_index);
+        let history_cutoff = limit_history(&mut history, self.history_limit(&conversation));
+
+        let preferences = self.turn_preferences(&conversation);
+        self.personalize(ChatContext {
+            conversation_id: conversation.id,
+            conversation_title: conversation.title,
+            user_name: None,
+            language: conversation.language,
+            history,
+            history_cutoff,
+            user_message,
+            preferences,
+            webhook_tools: Vec::new(),
+            retrieved: Vec::new(),
+        })
+        .await
+    }
+
+    /// Lists every generation of a message, oldest first.
+    pub async fn get_versions(&self, message_id: Uuid) -> Result<Vec<MessageVersion>, AppError> {
+        let message = self.find_message(message_id).await?;
+        let mut versions = self.message_repo.find_versions(message_id).await?;
+        if !versions.is_empty() {
+            if let Some(key) = self.key_for(message.conversation_id).await? {
+                for version in &mut versions {
+                    version.content = key.open_stored(&version.content)?;
+                }
+            }
+            return Ok(versions);
+        }
+        // Never regenerated: the message itself is the only version.
+        Ok(vec![MessageVersion {
+            version: 1,
+            content: message.content,
+            created_at: message.created_at,
+            is_active: true,
+        }])
+    }
+
+    /// Word diff between `version` of a message and the version generated
+    /// just before it.
+    pub async fn version_diff(
+        &self,
+        message_id: Uuid,
+        version: i32,
+    ) -> Result<VersionDiff, AppError> {
+        if version <= 1 {
+            return Err(AppError::InvalidField {
+                field_name: "version".to_string(),
+                message: "the first version has nothing to compare against".to_string(),
+            });
+        }
+        let versions = self.get_versions(message_id).await?;
+        let content = |v: i32| versions.iter().find(|m| m.version == v).map(|m| &m.content);
+        let (Some(previous), Some(current)) = (content(version - 1), content(version)) else {
+            return Err(AppError::RecordNotFound {
+                entity_type: "message version".to_string(),
+                id: format!("{message_id}/{version}"),
+            });
+        };
+        Ok(VersionDiff {
+            message_id,
+            from_version: version - 1,
+            to_version: version,
+            diff: evals::word_diff(previous, current),
+        })
+    }
+
+    /// Switches which version of a message is displayed and replayed as history.
+    pub async fn set_active_version(
+        &self,
+        message_id: Uuid,
+        version: i32,
+    ) -> Result<Message, AppError> {
+        let message = self.find_message(message_id).await?;
+        if message.active_version == version {
+            return Ok(message);
+        }
+        self.message_repo.set_active_version(message_id, version).await?;
+        self.find_message(message_id).await
+    }
+
+    /// Stars or unstars a stored message and returns it.
+    pub async fn set_starred(&self, message_id: Uuid, starred: bool) -> Result<Message, AppError> {
+        if !self.message_repo.set_starred(message_id, starred).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "message".to_string(),
+                id: message_id.to_string(),
+            });
+        }
+        self.find_message(message_id).await
+    }
+
+    /// Starred messages across all conversations, most recently starred first.
+    /// Those of locked conversations are left out until they are unlocked.
+    pub async fn starred_messages(&self) -> Result<Vec<StarredMessage>, AppError> {
+        let mut starred = Vec::new();
+        for mut entry in self.message_repo.find_starred().await? {
+            let key = match self.key_for(entry.message.conversation_id).await {
+                Ok(key) => key,
+                Err(e) if e.is_locked() => continue,
+                Err(e) => return Err(e),
+            };
+            entry.message = open_message(key.as_deref(), entry.message)?;
+            starred.push(entry);
+        }
+        Ok(starred)
+    }
+
+    /// Registers a webhook tool, for one conversation or for all of them.
+    pub async fn create_webhook_tool(
+        &self,
+        request: CreateWebhookToolRequest,
+    ) -> Result<WebhookTool, AppError> {
+        let name = request.name.trim().to_string();
+        let valid_name = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if name.is_empty() || name.len() > MAX_TOOL_NAME_LENGTH || !valid_name {
+            return Err(AppError::InvalidField {
+                field_name: "name".to_string(),
+                message: format!(
+                    "must be 1 to {MAX_TOOL_NAME_LENGTH} letters, digits, '_' or '-'"
+                ),
+            });
+        }
+        if BUILTIN_TOOL_NAMES.contains(&name.as_str()) {
+            return Err(AppError::InvalidField {
+                field_name: "name".to_string(),
+                message: format!("'{name}' is a built-in tool"),
+            });
+        }
+        let description = text_field(
+            "description",
+            Some(request.description),
+            MAX_TOOL_DESCRIPTION_LENGTH,
+        )?
+        .ok_or_else(|| AppError::EmptyField { field_name: "description".to_string() })?;
+        if request.parameters.get("type").and_then(|t| t.as_str()) != Some("object") {
+            return Err(AppError::InvalidField {
+                field_name: "parameters".to_string(),
+                message: "must be a JSON schema of \"type\": \"object\"".to_string(),
+            });
+        }
+        let schema_length = request.parameters.to_string().len();
+        if schema_length > MAX_TOOL_SCHEMA_LENGTH {
+            return Err(AppError::FieldTooLong {
+                field_name: "parameters".to_string(),
+                max_length: MAX_TOOL_SCHEMA_LENGTH,
+                actual_length: schema_length,
+            });
+        }
+        let url = request.url.trim().to_string();
+        if !reqwest::Url::parse(&url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+            return Err(AppError::InvalidField {
+                field_name: "url".to_string(),
+                message: "must be an http or https URL".to_string(),
+            });
+        }
+        if let Some(id) = request.conversation_id {
+            self.conversation_repo
+                .find_by_id(id)
+                .await?
+                .ok_or_else(|| AppError::ConversationNotFound { id })?;
+        }
+
+        let tool = WebhookTool {
+            id: Uuid::new_v4(),
+            conversation_id: request.conversation_id,
+            name,
+            description,
+            parameters: request.parameters,
+            url,
+            auth_header: text_field("auth_header", request.auth_header, MAX_AUTH_HEADER_LENGTH)?,
+            created_at: Utc::now(),
+        };
+        if !self.webhook_tool_repo.save(&tool).await? {
+            return Err(AppError::InvalidField {
+                field_name: "name".to_string(),
+                message: format!("a tool named '{}' already exists there", tool.name),
+            });
+        }
+        Ok(tool)
+    }
+
+    /// The global webhook tools, or every tool a turn in `conversation_id`
+    /// may call.
+    pub async fn list_webhook_tools(
+        &self,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<WebhookTool>, AppError> {
+        self.webhook_tool_repo.find_visible(conversation_id).await
+    }
+
+    pub async fn delete_webhook_tool(&self, id: Uuid) -> Result<(), AppError> {
+        if !self.webhook_tool_repo.delete(id).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "tool".to_string(),
+                id: id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Schedules `request.prompt` to run whenever `request.cron` fires, each
+    /// reply going to the conversation. Ephemeral conversations cannot have
+    /// schedules.
+    pub async fn create_schedule(
+        &self,
+        request: CreateScheduleRequest,
+    ) -> Result<Schedule, AppError> {
+        let max_length = self.config.get().max_message_length;
+        let prompt = text_field("prompt", Some(request.prompt), max_length)?
+            .ok_or_else(|| AppError::EmptyField { field_name: "prompt".to_string() })?;
+        let expression = request.cron.trim().to_string();
+        let next_run_at = cron::next_run(&cron::parse(&expression)?, Utc::now())?;
+        let conversation_id = request.conversation_id;
+        self.conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: conversation_id })?;
+
+        let schedule = Schedule {
+            id: Uuid::new_v4(),
+            conversation_id,
+            cron: expression,
+            prompt,
+            model: text_field("model", request.model, MAX_MODEL_NAME_LENGTH)?,
+            next_run_at,
+            last_run_at: None,
+            last_error: None,
+            created_at: Utc::now(),
+        };
+        self.schedule_repo.save(&schedule).await?;
+        Ok(schedule)
+    }
+
+    /// Every schedule, or only those of `conversation_id`, soonest first.
+    pub async fn list_schedules(
+        &self,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<Schedule>, AppError> {
+        self.schedule_repo.find_all(conversation_id).await
+    }
+
+    pub async fn delete_schedule(&self, id: Uuid) -> Result<(), AppError> {
+        if !self.schedule_repo.delete(id).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "schedule".to_string(),
+                id: id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Notes in the access log of `conversation_id` that the current user
+    /// read or exported it. Ephemeral conversations keep no log; failures are
+    /// only logged, as the read itself went through.
+    pub async fn record_access(
+        &self,
+        conversation_id: Uuid,
+        action: AccessAction,
+        user_agent: Option<String>,
+    ) {
+        if self.ephemeral.contains(conversation_id) {
+            return;
+        }
+        let entry = AccessLogEntry {
+            id: Uuid::new_v4(),
+            conversation_id,
+            user_id: LOCAL_USER_ID.to_string(),
+            action,
+            user_agent,
+            created_at: Utc::now(),
+        };
+        if let Err(e) = self.access_repo.save(&entry).await {
+            error!("Failed to record access to {conversation_id}: {e}");
+        }
+    }
+
+    /// The latest reads and exports of a stored conversation, newest first.
+    pub async fn get_access_log(
+        &self,
+        id: Uuid,
+        limit: Option<i64>,
+    ) -> Result<Vec<AccessLogEntry>, AppError> {
+        self.conversation_repo
+            .find_by_id(id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id })?;
+        let limit = limit.unwrap_or(DEFAULT_ACCESS_LOG_LIMIT).clamp(1, MAX_ACCESS_LOG_LIMIT);
+        self.access_repo.find_recent(id, limit).await
+    }
+
+    /// Keeps the long code blocks and documents of a saved reply as artifacts
+    /// of its active version, and returns them. Ephemeral and encrypted
+    /// conversations keep none, as they would be stored in the clear;
+    /// failures are only logged, as the reply itself was saved.
+    pub async fn save_artifacts(&self, message: &Message) -> Vec<Artifact> {
+        let min_lines = self.config.get().artifact_min_lines;
+        let found = artifacts::extract(message.id, &message.content, min_lines);
+        if found.is_empty() || self.is_confidential(message.conversation_id).await {
+            return Vec::new();
+        }
+        match self.artifact_repo.save_all(message.id, message.active_version, &found).await {
+            Ok(()) => found,
+            Err(e) => {
+                error!("Failed to save artifacts of message {}: {e}", message.id);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Keeps the chunks retrieved for a turn against the reply's active
+    /// version, so what the model was given can be inspected later. Like
+    /// artifacts, none are kept for ephemeral and encrypted conversations.
+    async fn save_retrieval(&self, ctx: &ChatContext, message: &Message) {
+        if ctx.retrieved.is_empty() || self.is_confidential(message.conversation_id).await {
+            return;
+        }
+        let saved = self
+            .document_repo
+            .save_retrieved(message.id, message.active_version, &ctx.retrieved)
+            .await;
+        if let Err(e) = saved {
+            error!("Failed to save retrieved chunks of message {}: {e}", message.id);
+        }
+    }
+
+    /// The chunks retrieved for a reply's active version, best first.
+    pub async fn get_message_retrieval(
+        &self,
+        message_id: Uuid,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        self.find_message(message_id).await?;
+        self.document_repo.find_retrieved(message_id).await
+    }
+
+    /// Records the user's verdict on a chunk retrieved for a reply's active
+    /// version. Returns the reply's retrieved chunks.
+    pub async fn rate_retrieved_chunk(
+        &self,
+        message_id: Uuid,
+        chunk_id: Uuid,
+        feedback: RetrievalFeedback,
+    ) -> Result<Vec<RetrievedChunk>, AppError> {
+        self.find_message(message_id).await?;
+        if !self.document_repo.rate_retrieved(message_id, chunk_id, feedback.relevant).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "retrieved chunk".to_string(),
+                id: chunk_id.to_string(),
+            });
+        }
+        self.document_repo.find_retrieved(message_id).await
+    }
+
+    /// Retrieval precision from users' verdicts on retrieved chunks.
+    pub async fn retrieval_metrics(&self) -> Result<RetrievalMetrics, AppError> {
+        let by_collection = self.document_repo.find_precision(BY_COLLECTION).await?;
+        let (rated, relevant) = by_collection
+            .iter()
+            .fold((0, 0), |(rated, relevant), p| (rated + p.rated, relevant + p.relevant));
+        Ok(RetrievalMetrics {
+            overall: RetrievalPrecision::new("all".to_string(), rated, relevant),
+            by_collection,
+            by_ranking: self.document_repo.find_precision(BY_RANKING).await?,
+            by_position: self.document_repo.find_precision(BY_POSITION).await?,
+        })
+    }
+
+    /// The artifacts of a conversation's replies, oldest first.
+    pub async fn list_artifacts(&self, conversation_id: Uuid) -> Result<Vec<Artifact>, AppError> {
+        if self.ephemeral.contains(conversation_id) {
+            return Ok(Vec::new());
+        }
+        self.conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: conversation_id })?;
+        self.artifact_repo.find_by_conversation_id(conversation_id).await
+    }
+
+    pub async fn get_artifact(&self, id: Uuid) -> Result<Artifact, AppError> {
+        self.artifact_repo.find_by_id(id).await?.ok_or_else(|| AppError::RecordNotFound {
+            entity_type: "artifact".to_string(),
+            id: id.to_string(),
+        })
+    }
+
+    /// Compares an artifact with the file it is meant to replace, uploaded or
+    /// read from the workspace, and optionally writes it there.
+    pub async fn diff_artifact(
+        &self,
+        id: Uuid,
+        request: ArtifactDiffRequest,
+    ) -> Result<ArtifactDiff, AppError> {
+        let artifact = self.get_artifact(id).await?;
+        let workspace = self.config.get().workspace_dir.clone().map(Workspace::new);
+        let disabled = |field: &str| AppError::InvalidField {
+            field_name: field.to_string(),
+            message: "no workspace_dir is configured".to_string(),
+        };
+        let path = request.path.as_deref().map(str::trim).filter(|p| !p.is_empty());
+
+        let current = match (&workspace, path) {
+            (Some(workspace), Some(path)) => workspace.read(path).await?,
+            _ => None,
+        };
+        let old = match (&request.content, path) {
+            (Some(content), _) => content.clone(),
+            (None, Some(_)) if workspace.is_none() => return Err(disabled("path")),
+            (None, Some(_)) => current.clone().unwrap_or_default(),
+            (None, None) => return Err(AppError::EmptyField { field_name: "content".to_string() }),
+        };
+        let name = path.map(str::to_string).unwrap_or_else(|| artifact.file_name());
+        let diff = artifacts::unified_diff(&old, &artifact.content, &name);
+
+        if request.apply {
+            let Some(workspace) = workspace else { return Err(disabled("apply")) };
+            let Some(path) = path else {
+                return Err(AppError::EmptyField { field_name: "path".to_string() });
+            };
+            if request.content.is_some() && current.as_deref().unwrap_or_default() != old {
+                return Err(AppError::InvalidField {
+                    field_name: "content".to_string(),
+                    message: format!("{path} in the workspace differs from the uploaded file"),
+                });
+            }
+            workspace.write(path, &artifact.content).await?;
+            info!(artifact_id = %id, path, "Applied artifact to the workspace");
+        }
+        Ok(ArtifactDiff { artifact_id: id, path: name, diff, applied: request.apply })
+    }
+
+    /// Registers `request.url` to receive every assistant message saved to the
+    /// conversation. The returned secret, which signs the payloads, is not
+    /// shown again. Ephemeral and encrypted conversations cannot have
+    /// webhooks.
+    pub async fn create_webhook(
+        &self,
+        request: CreateWebhookRequest,
+    ) -> Result<CreatedWebhook, AppError> {
+        let url = request.url.trim().to_string();
+        if !reqwest::Url::parse(&url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+            return Err(AppError::InvalidField {
+                field_name: "url".to_string(),
+                message: "must be an http or https URL".to_string(),
+            });
+        }
+        let conversation_id = request.conversation_id;
+        let conversation = self
+            .conversation_repo
+            .find_by_id(conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: conversation_id })?;
+        if conversation.encrypted {
+            return Err(AppError::InvalidField {
+                field_name: "conversation_id".to_string(),
+                message: "encrypted conversations cannot have webhooks".to_string(),
+            });
+        }
+
+        let webhook = ConversationWebhook {
+            id: Uuid::new_v4(),
+            conversation_id,
+            url,
+            secret: webhooks::new_secret()?,
+            created_at: Utc::now(),
+        };
+        self.webhook_repo.save(&webhook).await?;
+        let secret = webhook.secret.clone();
+        Ok(CreatedWebhook { webhook, secret })
+    }
+
+    /// Every webhook, or only those of `conversation_id`, oldest first.
+    pub async fn list_webhooks(
+        &self,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<ConversationWebhook>, AppError> {
+        self.webhook_repo.find_all(conversation_id).await
+    }
+
+    pub async fn delete_webhook(&self, id: Uuid) -> Result<(), AppError> {
+        if !self.webhook_repo.delete(id).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "webhook".to_string(),
+                id: id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The latest deliveries of webhook `id`, newest first, with how they
+    /// went.
+    pub async fn list_webhook_deliveries(
+        &self,
+        id: Uuid,
+        limit: Option<i64>,
+    ) -> Result<Vec<WebhookDelivery>, AppError> {
+        if !self.webhook_repo.exists(id).await? {
+            return Err(AppError::RecordNotFound {
+                entity_type: "webhook".to_string(),
+                id: id.to_string(),
+            });
+        }
+        let limit = limit.unwrap_or(DEFAULT_DELIVERY_LIMIT).clamp(1, MAX_DELIVERY_LIMIT);
+        self.webhook_repo.find_deliveries(id, limit).await
+    }
+
+    /// The current user's latest notifications, newest first, with how their
+    /// delivery went.
+    pub async fn list_notifications(
+        &self,
+        limit: Option<i64>,
+    ) -> Result<Vec<Notification>, AppError> {
+        let limit = limit.unwrap_or(DEFAULT_NOTIFICATION_LIMIT).clamp(1, MAX_NOTIFICATION_LIMIT);
+        self.notifier.recent(LOCAL_USER_ID, limit).await
+    }
+
+    /// Tells the current user that the batch job or schedule `subject_id`
+    /// finished, through whichever channels their profile sets up.
+    async fn notify(&self, event: NotificationEvent, subject_id: Uuid, summary: String) {
+        match self.get_profile().await {
+            Ok(profile) => self.notifier.notify(&profile, event, subject_id, summary).await,
+            Err(e) => error!("Failed to load the profile to notify: {e}"),
+        }
+    }
+
+    /// Runs every schedule that is due, one after another, and returns the
+    /// replies it posted. A run missed while no server was up happens once,
+    /// late. Failures are recorded on the schedule.
+    pub async fn run_due_schedules(&self) -> Vec<(Message, CompletionStats)> {
+        let now = Utc::now();
+        let due = match self.schedule_repo.find_due(now).await {
+            Ok(due) => due,
+            Err(e) => {
+                error!("Failed to find due schedules: {e}");
+                return Vec::new();
+            }
+        };
+
+        let mut replies = Vec::new();
+        for schedule in due {
+            let next_run = cron::parse(&schedule.cron).and_then(|c| cron::next_run(&c, now));
+            let next_run_at = match next_run {
+                Ok(next) => next,
+                Err(e) => {
+                    warn!("Schedule {} can no longer run: {e}", schedule.id);
+                    continue;
+                }
+            };
+            match self.schedule_repo.claim(&schedule, next_run_at).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Failed to claim schedule {}: {e}", schedule.id);
+                    continue;
+                }
+            }
+
+            let outcome = self.run_schedule(&schedule).await;
+            let error = outcome.as_ref().err().map(ToString::to_string);
+            if let Err(e) = &outcome {
+                warn!("Schedule {} failed: {e}", schedule.id);
+            }
+            let recorded = self.schedule_repo.update_last_error(schedule.id, error.as_deref()).await;
+            if let Err(e) = recorded {
+                error!("Failed to record outcome of schedule {}: {e}", schedule.id);
+            }
+            let (event, summary) = match &error {
+                None => (NotificationEvent::ScheduleCompleted, schedule.prompt.clone()),
+                Some(e) => (NotificationEvent::ScheduleFailed, format!("{}: {e}", schedule.prompt)),
+            };
+            self.notify(event, schedule.id, summary).await;
+            replies.extend(outcome.ok());
+        }
+        replies
+    }
+
+    /// Answers a schedule's prompt in its conversation, with the history a
+    /// turn there would have, and saves the reply. The prompt itself is not
+    /// saved.
+    #[instrument(skip_all, fields(schedule_id = %schedule.id))]
+    async fn run_schedule(
+        &self,
+        schedule: &Schedule,
+    ) -> Result<(Message, CompletionStats), AppError> {
+        let started = std::time::Instant::now();
+        let conversation = self
+            .conversation_repo
+            .find_by_id(schedule.conversation_id)
+            .await?
+            .ok_or(AppError::ConversationNotFound { id: schedule.conversation_id })?;
+        let key = self.unlocked_key(&conversation)?;
+        let mut history = open_messages(
+            key.as_deref(),
+            self.message_repo.find_by_conversation_id(conversation.id).await?,
+        )?;
+        let history_cutoff = limit_history(&mut history, self.history_limit(&conversation));
+
+        let preferences = self.turn_preferences(&conversation);
+        let mut ctx = self
+            .personalize(ChatContext {
+                conversation_id: conversation.id,
+                conversation_title: conversation.title,
+                user_name: None,
+                language: conversation.language,
+                history,
+                history_cutoff,
+                user_message: schedule.prompt.clone(),
+                preferences,
+                webhook_tools: Vec::new(),
+                retrieved: Vec::new(),
+            })
+            .await?;
+        if let Some(model) = &schedule.model {
+            ctx.preferences.model = Some(model.clone());
+        }
+        let model = match &ctx.preferences.model {
+            Some(model) => model.clone(),
+            None => self.config.get().model.clone(),
+        };
+
+        let reply = match self.agent.chat(&ctx).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                self.record_failed_turn(ctx.conversation_id, &e).await;
+                return Err(e);
+            }
+        };
+        let reply = self.post_process(&ctx, reply.content, reply.sources);
+        let message = Message::new(ctx.conversation_id, MessageRole::Assistant, reply.content)
+            .with_sources(reply.sources);
+        self.store_message(&message).await?;
+        self.log_turn(&ctx, &message, None).await;
+        let stats = CompletionStats {
+            model,
+            prompt_tokens: None,
+            completion_tokens: None,
+            finish_reason: FinishReason::Stop,
+            duration_ms: started.elapsed().as_millis() as u64,
+            cost: None,
+        };
+        Ok((message, stats))
+    }
+
+    async fn find_message(&self, message_id: Uuid) -> Result<Message, AppError> {
+        let message = self
+            .message_repo
+            .find_by_id(message_id)
+            .await?
+            .ok_or_else(|| AppError::RecordNotFound {
+                entity_type: "message".to_string(),
+                id: message_id.to_string(),
+            })?;
+        let key = self.key_for(message.conversation_id).await?;
+        open_message(key.as_deref(), message)
+    }
+}
+
+/// Derives a conversation key on the blocking pool; it takes a while on purpose.
+async fn derive_key(passphrase: String, salt: String) -> Result<ConversationKey, AppError> {
+    tokio::task::spawn_blocking(move || ConversationKey::derive(&passphrase, &salt))
+        .await
+        .map_err(|e| AppError::Unexpected(format!("Key derivation failed: {e}")))?
+}
+
+/// `content` as stored: sealed with `key` when its conversation is encrypted.
+fn seal_content(key: Option<&ConversationKey>, content: String) -> Result<String, AppError> {
+    match key {
+        Some(key) => key.seal(&content),
+        None => Ok(content),
+    }
+}
+
+fn seal_message(key: Option<&ConversationKey>, message: &Message) -> Result<Message, AppError> {
+    Ok(Message { content: seal_content(key, message.content.clone())?, ..message.clone() })
+}
+
+/// Reverses [`seal_message`] for a message loaded from storage.
+fn open_message(key: Option<&ConversationKey>, mut message: Message) -> Result<Message, AppError> {
+    if let Some(key) = key {
+        message.content = key.open_stored(&message.content)?;
+    }
+    Ok(message)
+}
+
+fn open_messages(
+    key: Option<&ConversationKey>,
+    messages: Vec<Message>,
+) -> Result<Vec<Message>, AppError> {
+    messages.into_iter().map(|m| open_message(key, m)).collect()
+}
+
+/// Serializes `value` as pretty JSON and queues it as the archive entry
+/// `path`. `false` means the download was abandoned.
+async fn send_entry<T: serde::Serialize>(
+    tx: &mpsc::Sender<Result<ExportEntry, AppError>>,
+    path: &str,
+    value: &T,
+) -> Result<bool, AppError> {
+    let contents = serde_json::to_vec_pretty(value)
+        .map_err(|e| AppError::Unexpected(format!("Failed to serialize {path}: {e}")))?;
+    let entry = ExportEntry { path: path.to_string(), contents };
+    Ok(tx.send(Ok(entry)).await.is_ok())
+}
+
+/// Keeps the last `limit` messages of `history` (all of them when `limit` is
+/// `None`) and returns the id of the newest one dropped.
+fn limit_history(history: &mut Vec<Message>, limit: Option<usize>) -> Option<Uuid> {
+    let limit = limit?;
+    if history.len() <= limit {
+        return None;
+    }
+    let dropped = history.len() - limit;
+    history.drain(..dropped).next_back().map(|m| m.id)
+}
+
+/// `messages` with the first `covered` of them replaced by a block holding
+/// `summary`, keeping the newest [`SUMMARY_KEEPS_RECENT`] out of it.
+fn timeline(mut messages: Vec<Message>, summary: Option<(String, usize)>) -> Vec<TimelineEntry> {
+    let mut entries = Vec::with_capacity(messages.len() + 1);
+    if let Some((summary, covered)) = summary {
+        let condensed = covered.min(messages.len().saturating_sub(SUMMARY_KEEPS_RECENT));
+        if condensed > 0 {
+            let rest = messages.split_off(condensed);
+            entries.push(TimelineEntry::Summary(SummaryBlock {
+                summary,
+                message_count: condensed,
+                last_message_id: messages[condensed - 1].id,
+            }));
+            messages = rest;
+        }
+    }
+    entries.extend(messages.into_iter().map(TimelineEntry::Message));
+    entries
+}
+
+/// `role: content` lines for the summarizer, oldest first. Long messages are
+/// clipped and the transcript stops at a fixed budget; the opening of a
+/// conversation says the most about what it is for.
+fn summary_transcript(messages: &[Message]) -> String {
+    let mut transcript = String::new();
+    for message in messages.iter().filter(|m| !m.role.is_tool_traffic()) {
+        let content: String =
+            message.content.trim().chars().take(MAX_SUMMARY_MESSAGE_CHARS).collect();
+        let line = format!("{}: {}\n", message.role.as_str().to_lowercase(), content);
+        if transcript.len() + line.len() > MAX_SUMMARY_TRANSCRIPT_BYTES {
+            break;
+        }
+        transcript.push_str(&line);
+    }
+    transcript
+}
+
+/// A trimmed model name from a batch request; blank means unset.
+fn batch_model(model: Option<String>, field_name: &str) -> Result<Option<String>, AppError> {
+    let Some(model) = model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()) else {
+        return Ok(None);
+    };
+    if model.len() > MAX_MODEL_NAME_LENGTH {
+        return Err(AppError::FieldTooLong {
+            field_name: field_name.to_string(),
+            max_length: MAX_MODEL_NAME_LENGTH,
+            actual_length: model.len(),
+        });
+    }
+    Ok(Some(model))
+}
+
+/// Conversation title derived from its first message.
+fn title_for(message: &str) -> String {
+    let t = message.trim();
+    if t.chars().count() > 60 {
+        format!("{}…", t.chars().take(60).collect::<String>())
+    } else {
+        t.to_string()
+    }
+}
+
+/// Trims a profile text field, treating blank as unset.
+fn text_field(
+    field_name: &str,
+    value: Option<String>,
+    max_length: usize,
+) -> Result<Option<String>, AppError> {
+    let Some(value) = value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) else {
+        return Ok(None);
+    };
+    if value.len() > max_length {
+        return Err(AppError::FieldTooLong {
+            field_name: field_name.to_string(),
+            max_length,
+            actual_length: value.len(),
+        });
+    }
+    Ok(Some(value))
+}
+
+/// A ranking weight given to a collection search, or the configured one.
+fn search_weight(field_name: &str, value: Option<f32>, configured: f32) -> Result<f32, AppError> {
+    match value {
+        Some(weight) if !weight.is_finite() || weight < 0.0 => Err(AppError::InvalidField {
+            field_name: field_name.to_string(),
+            message: "must be a number of at least 0".to_string(),
+        }),
+        Some(weight) => Ok(weight),
+        None => Ok(configured),
+    }
+}
+
+/// Checks the chunk size and overlap of `collection`: the overlap must be
+/// shorter than a chunk, `configured_max_lines` long when it sets none.
+fn validate_chunking(collection: &Collection, configured_max_lines: usize) -> Result<(), AppError> {
+    if let Some(lines) = collection.chunk_max_lines {
+        if !(1..=MAX_CHUNK_LINES).contains(&lines) {
+            return Err(AppError::InvalidField {
+                field_name: "chunk_max_lines".to_string(),
+                message: format!("must be between 1 and {MAX_CHUNK_LINES}"),
+            });
+        }
+    }
+    let max_lines = collection.chunk_max_lines.unwrap_or(configured_max_lines as i32);
+    if let Some(overlap) = collection.chunk_overlap {
+        if overlap < 0 || overlap >= max_lines {
+            return Err(AppError::InvalidField {
+                field_name: "chunk_overlap".to_string(),
+                message: format!("must be at least 0 and less than chunk_max_lines ({max_lines})"),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A trimmed conversation icon; blank means unset.
+fn validate_icon(icon: Option<String>) -> Result<Option<String>, AppError> {
+    let Some(icon) = icon.map(|i| i.trim().to_string()).filter(|i| !i.is_empty()) else {
+        return Ok(None);
+    };
+    if icon.chars().count() > MAX_ICON_CHARS || icon.chars().any(char::is_control) {
+        return Err(AppError::InvalidField {
+            field_name: "icon".to_string(),
+            message: format!("must be an emoji or up to {MAX_ICON_CHARS} characters"),
+        });
+    }
+    Ok(Some(icon))
+}
+
+/// A conversation color as lowercase `#rrggbb`; blank means unset.
+fn validate_color(color: Option<String>) -> Result<Option<String>, AppError> {
+    let Some(color) = color.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty()) else {
+        return Ok(None);
+    };
+    let is_hex = color
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if !is_hex {
+        return Err(AppError::InvalidField {
+            field_name: "color".to_string(),
+            message: "must be a #rrggbb hex color".to_string(),
+        });
+    }
+    Ok(Some(color))
+}
+
+/// The protocol and build of this server.
+pub fn server_version() -> ServerVersion {
+    ServerVersion {
+        protocol_version: PROTOCOL_VERSION,
+        build: BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: BUILD_COMMIT.map(str::to_string),
+        },
+    }
 }
@@ -0,0 +1,82 @@
+//! Regrouping of streamed reply chunks into whole words or sentences, for
+//! clients that would rather re-render less often than once per token.
+
+use crate::models::ChunkMode;
+
+/// Abbreviations whose period does not end a sentence.
+const ABBREVIATIONS: &[&str] = &["e.g.", "i.e.", "etc.", "vs.", "Mr.", "Mrs.", "Ms.", "Dr."];
+
+/// Buffers chunks from the model and releases them at word or sentence
+/// boundaries, depending on the [`ChunkMode`]. `token` passes them through.
+#[derive(Debug)]
+pub struct Rechunker {
+    mode: ChunkMode,
+    pending: String,
+}
+
+impl Rechunker {
+    pub fn new(mode: ChunkMode) -> Self {
+        Self { mode, pending: String::new() }
+    }
+
+    /// Adds a chunk; returns the text now ready to send, if any.
+    pub fn push(&mut self, chunk: &str) -> Option<String> {
+        self.pending.push_str(chunk);
+        let end = match self.mode {
+            ChunkMode::Token => self.pending.len(),
+            ChunkMode::Word => word_end(&self.pending),
+            ChunkMode::Sentence => sentence_end(&self.pending),
+        };
+        if end == 0 {
+            return None;
+        }
+        let rest = self.pending.split_off(end);
+        Some(std::mem::replace(&mut self.pending, rest))
+    }
+
+    /// Whatever is still held back, once the reply is complete.
+    pub fn finish(&mut self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+/// Bytes of `text` up to and including its last whitespace.
+fn word_end(text: &str) -> usize {
+    text.rfind(char::is_whitespace)
+        .map_or(0, |i| i + text[i..].chars().next().map_or(0, char::len_utf8))
+}
+
+/// Bytes of `text` up to the end of its last complete sentence: terminal
+/// punctuation (and any closing quotes or brackets) followed by whitespace,
+/// or a line break. The whitespace after the sentence stays with it.
+fn sentence_end(text: &str) -> usize {
+    let mut end = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\n' {
+            end = i + 1;
+            continue;
+        }
+        if !matches!(c, '.' | '!' | '?' | '…') {
+            continue;
+        }
+        let mut after = i + c.len_utf8();
+        while let Some(&(j, closing)) = chars.peek() {
+            if !matches!(closing, '"' | '\'' | ')' | ']' | '”' | '’' | '.' | '!' | '?') {
+                break;
+            }
+            after = j + closing.len_utf8();
+            chars.next();
+        }
+        let Some(&(j, space)) = chars.peek() else { break };
+        if space.is_whitespace() && !ends_with_abbreviation(&text[..after]) {
+            end = j + space.len_utf8();
+        }
+    }
+    end
+}
+
+fn ends_with_abbreviation(text: &str) -> bool {
+    let word = text.rsplit(char::is_whitespace).next().unwrap_or(text);
+    ABBREVIATIONS.contains(&word)
+}
@@ -0,0 +1,23 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use croner::Cron;
+
+use crate::errors::AppError;
+
+/// Parses the cron expression of a schedule: minute, hour, day of month,
+/// month and day of week, optionally preceded by seconds. Times are UTC.
+pub fn parse(expression: &str) -> Result<Cron, AppError> {
+    Cron::from_str(expression.trim()).map_err(|e| AppError::InvalidField {
+        field_name: "cron".to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// The first time `cron` fires after `after`.
+pub fn next_run(cron: &Cron, after: DateTime<Utc>) -> Result<DateTime<Utc>, AppError> {
+    cron.find_next_occurrence(&after, false).map_err(|e| AppError::InvalidField {
+        field_name: "cron".to_string(),
+        message: format!("never fires: {e}"),
+    })
+}
@@ -0,0 +1,346 @@
+//! Email gateway: emails to a mailbox become chat turns, answered by email.
+//!
+//! The mailbox is polled over IMAP for unread emails. Each one is asked as a
+//! turn and the reply sent back through `smtp_url`, threaded under the email.
+//! An email whose `References` (or `In-Reply-To`) name an earlier email or
+//! reply continues that conversation; any other starts a new one.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{StreamExt, TryStreamExt};
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use mail_parser::MessageParser;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tracing::{error, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::config::EmailGatewayConfig;
+use crate::db::email_message_repository::EmailMessageRepository;
+use crate::models::ChatRequest;
+use crate::service::chat_service::ChatService;
+
+/// How often a server without a gateway checks whether one was configured.
+const DISABLED_POLL: Duration = Duration::from_secs(60);
+
+/// An email to answer, as far as the gateway cares.
+struct Incoming {
+    message_id: String,
+    from: String,
+    subject: String,
+    /// The `References` of the email, then its `In-Reply-To`.
+    references: Vec<String>,
+    /// The body, without the quoted email it replies to.
+    text: String,
+}
+
+impl Incoming {
+    /// Subject of the reply: the email's, marked `Re:` once.
+    fn reply_subject(&self) -> String {
+        if self.subject.to_ascii_lowercase().starts_with("re:") {
+            self.subject.clone()
+        } else {
+            format!("Re: {}", self.subject)
+        }
+    }
+
+    /// `References` of the reply, which threads it under the email: the
+    /// email's own references, then the email.
+    fn reply_references(&self) -> String {
+        self.references
+            .iter()
+            .chain([&self.message_id])
+            .map(|id| format!("<{id}>"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Polls the configured mailbox and answers what arrives.
+#[derive(Clone)]
+pub struct EmailGateway {
+    svc: ChatService,
+    emails: EmailMessageRepository,
+}
+
+impl EmailGateway {
+    pub fn new(svc: ChatService, emails: EmailMessageRepository) -> Self {
+        Self { svc, emails }
+    }
+
+    /// Checks the mailbox every `poll_secs`, for as long as the server runs.
+    /// Picks up a gateway configured, changed or removed by a reload. Off in
+    /// multi-tenant mode, as an email does not say which tenant it is for.
+    pub async fn run(self) {
+        loop {
+            let config = self.svc.config().get();
+            let gateway = config.email_gateway.clone().filter(|_| config.tenants.is_none());
+            let Some(gateway) = gateway else {
+                tokio::time::sleep(DISABLED_POLL).await;
+                continue;
+            };
+            if let Err(e) = self.poll(&gateway).await {
+                warn!("Failed to check {} for emails: {e}", gateway.imap_host);
+            }
+            tokio::time::sleep(Duration::from_secs(gateway.poll_secs.max(1))).await;
+        }
+    }
+
+    /// Answers every unread email in the mailbox, marking each one read.
+    #[instrument(skip_all, fields(mailbox = %gateway.mailbox))]
+    async fn poll(&self, gateway: &EmailGatewayConfig) -> Result<(), String> {
+        let host = gateway.imap_host.as_str();
+        let server_name = ServerName::try_from(host.to_string()).map_err(|e| e.to_string())?;
+        let tcp = TcpStream::connect((host, gateway.imap_port)).await.map_err(|e| e.to_string())?;
+        let tls = tls_connector()?.connect(server_name, tcp).await.map_err(|e| e.to_string())?;
+        let mut client = async_imap::Client::new(tls);
+        client
+            .read_response()
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("the server closed the connection")?;
+        let mut session = client
+            .login(&gateway.username, &gateway.password)
+            .await
+            .map_err(|(e, _)| e.to_string())?;
+        session.select(&gateway.mailbox).await.map_err(|e| e.to_string())?;
+
+        let mut unread: Vec<u32> =
+            session.uid_search("UNSEEN").await.map_err(|e| e.to_string())?.into_iter().collect();
+        unread.sort_unstable();
+        for uid in unread {
+            let fetches: Vec<_> = session
+                .uid_fetch(uid.to_string(), "BODY.PEEK[]")
+                .await
+                .map_err(|e| e.to_string())?
+                .try_collect()
+                .await
+                .map_err(|e| e.to_string())?;
+            for raw in fetches.iter().filter_map(|f| f.body()) {
+                match parse(raw) {
+                    Some(email) => self.answer(gateway, email).await,
+                    None => warn!("Skipped email {uid}, which could not be parsed"),
+                }
+            }
+            session
+                .uid_store(uid.to_string(), "+FLAGS (\\Seen)")
+                .await
+                .map_err(|e| e.to_string())?
+                .for_each(|_| async {})
+                .await;
+        }
+        session.logout().await.map_err(|e| e.to_string())
+    }
+
+    /// Runs `email` as a turn and replies with the answer, or with why there
+    /// is none. Emails from the gateway itself, automatic ones, and those
+    /// already answered (here or by another server) are left alone.
+    #[instrument(skip_all, fields(message_id = %email.message_id))]
+    async fn answer(&self, gateway: &EmailGatewayConfig, email: Incoming) {
+        if email.from.eq_ignore_ascii_case(&gateway.address) {
+            return;
+        }
+        match self.emails.claim(&email.message_id).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                error!("Failed to claim email: {e}");
+                return;
+            }
+        }
+        let conversation_id = match self.emails.find_conversation(&email.references).await {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to look up the email's thread: {e}");
+                None
+            }
+        };
+        let request = ChatRequest {
+            conversation_id,
+            message: email.text.clone(),
+            ephemeral: false,
+            verbosity: None,
+            stateless: None,
+            quoted_message_id: None,
+        };
+        let (body, conversation_id) = match self.svc.chat(request).await {
+            Ok(reply) => (reply.message.content, Some(reply.conversation_id)),
+            Err(e) => {
+                warn!("Email was not answered: {e}");
+                (format!("Sorry, your email could not be answered: {e}"), conversation_id)
+            }
+        };
+
+        let domain = gateway.address.rsplit_once('@').map_or("localhost", |(_, d)| d);
+        let reply_id = format!("{}@{domain}", Uuid::new_v4());
+        match self.send_reply(gateway, &email, &reply_id, body).await {
+            Ok(()) => info!("Answered an email from {}", email.from),
+            Err(e) => error!("Failed to reply to {}: {e}", email.from),
+        }
+        if let Some(id) = conversation_id {
+            if let Err(e) = self.emails.link(&[email.message_id, reply_id], id).await {
+                error!("Failed to file the email under its conversation: {e}");
+            }
+        }
+    }
+
+    async fn send_reply(
+        &self,
+        gateway: &EmailGatewayConfig,
+        email: &Incoming,
+        reply_id: &str,
+        body: String,
+    ) -> Result<(), String> {
+        let config = self.svc.config().get();
+        let Some(url) = &config.smtp_url else {
+            return Err("smtp_url is not configured".to_string());
+        };
+        let from: Mailbox =
+            gateway.address.parse().map_err(|e| format!("invalid gateway address: {e}"))?;
+        let to: Mailbox = email.from.parse().map_err(|e| format!("{e}"))?;
+        let message = lettre::Message::builder()
+            .from(from)
+            .to(to)
+            .subject(email.reply_subject())
+            .message_id(Some(format!("<{reply_id}>")))
+            .in_reply_to(format!("<{}>", email.message_id))
+            .references(email.reply_references())
+            .body(body)
+            .map_err(|e| e.to_string())?;
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| format!("invalid smtp_url: {e}"))?
+            .build();
+        transport.send(message).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+/// TLS to the IMAP server, trusting the Mozilla root certificates.
+fn tls_connector() -> Result<TlsConnector, String> {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// The parts of the raw email `raw` the gateway needs. `None` for emails
+/// without a sender, or sent automatically (`Auto-Submitted`), such as
+/// out-of-office replies, which must not be answered.
+fn parse(raw: &[u8]) -> Option<Incoming> {
+    let email = MessageParser::default().parse(raw)?;
+    let automatic = email
+        .header_raw("Auto-Submitted")
+        .is_some_and(|v| !v.trim().eq_ignore_ascii_case("no"));
+    if automatic {
+        return None;
+    }
+    let from = email.from()?.first()?.address()?.to_string();
+    let ids = |value: &mail_parser::HeaderValue| -> Vec<String> {
+        value.as_text_list().unwrap_or_default().iter().map(|id| id.to_string()).collect()
+    };
+    let mut references = ids(email.references());
+    references.extend(ids(email.in_reply_to()));
+    Some(Incoming {
+        // One is made up for an email without it, which then cannot be told
+        // apart from a redelivery of it.
+        message_id: email
+            .message_id()
+            .map_or_else(|| format!("{}@gateway.invalid", Uuid::new_v4()), str::to_string),
+        from,
+        subject: email.subject().unwrap_or_default().to_string(),
+        references,
+        text: strip_quoted(&email.body_text(0).unwrap_or_default()),
+    })
+}
+
+/// `text` without the lines quoting the email it replies to, nor the
+/// "On ..., ... wrote:" line introducing them.
+fn strip_quoted(text: &str) -> String {
+    let quoted = |line: &str| line.trim_start().starts_with('>');
+    let lines: Vec<&str> = text.lines().collect();
+    let kept: Vec<&str> = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| {
+            let introduces_quote = line.trim_end().ends_with("wrote:")
+                && lines[i + 1..].iter().find(|l| !l.trim().is_empty()).is_some_and(|l| quoted(l));
+            !quoted(line) && !introduces_quote
+        })
+        .map(|(_, line)| *line)
+        .collect();
+    kept.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(headers: &str, body: &str) -> Option<Incoming> {
+        parse(format!("From: Ann <ann@example.com>\r\n{headers}\r\n{body}").as_bytes())
+    }
+
+    #[test]
+    fn references_then_in_reply_to_name_the_thread() {
+        let incoming = email(
+            "Message-ID: <3@example.com>\r\n\
+             References: <1@example.com> <2@example.com>\r\n\
+             In-Reply-To: <2@example.com>\r\n\
+             Subject: Re: Lunch\r\n",
+            "Thursday works.",
+        )
+        .unwrap();
+
+        assert_eq!(incoming.message_id, "3@example.com");
+        assert_eq!(incoming.from, "ann@example.com");
+        assert_eq!(incoming.references, ["1@example.com", "2@example.com", "2@example.com"]);
+
+        let incoming = email("In-Reply-To: <2@example.com>\r\n", "Thursday works.").unwrap();
+        assert_eq!(incoming.references, ["2@example.com"]);
+        assert!(incoming.message_id.ends_with("@gateway.invalid"), "{}", incoming.message_id);
+
+        let incoming = email("Subject: Lunch\r\n", "Are you free?").unwrap();
+        assert!(incoming.references.is_empty());
+    }
+
+    #[test]
+    fn automatic_emails_are_not_answered() {
+        assert!(email("Auto-Submitted: auto-replied\r\n", "I am away.").is_none());
+        assert!(email("Auto-Submitted: no\r\n", "I am back.").is_some());
+        assert!(parse(b"Subject: No sender\r\n\r\nHello").is_none());
+    }
+
+    #[test]
+    fn replies_are_threaded_under_the_email() {
+        let incoming = email(
+            "Message-ID: <3@example.com>\r\n\
+             References: <1@example.com> <2@example.com>\r\n\
+             Subject: Lunch\r\n",
+            "Thursday?",
+        )
+        .unwrap();
+        assert_eq!(incoming.reply_subject(), "Re: Lunch");
+        assert_eq!(
+            incoming.reply_references(),
+            "<1@example.com> <2@example.com> <3@example.com>"
+        );
+
+        let incoming = email("Message-ID: <1@example.com>\r\nSubject: RE: Lunch\r\n", "Ok")
+            .unwrap();
+        assert_eq!(incoming.reply_subject(), "RE: Lunch");
+        assert_eq!(incoming.reply_references(), "<1@example.com>");
+    }
+
+    #[test]
+    fn quoted_replies_are_left_out_of_the_turn() {
+        let text = "Thursday works.\n\nOn Mon, Bob wrote:\n\n> Lunch?\n> Bob\n";
+        assert_eq!(strip_quoted(text), "Thursday works.");
+        assert_eq!(strip_quoted("He wrote: fine\nThanks"), "He wrote: fine\nThanks");
+    }
+}
@@ -0,0 +1,177 @@
+//! Passphrase encryption of the messages of conversations that opt in.
+//!
+//! A conversation's key is derived from its passphrase and a random salt with
+//! Argon2id, and message bodies are sealed with AES-256-GCM under a fresh
+//! nonce each. Conversations encrypted before Argon2id keep their
+//! PBKDF2-HMAC-SHA256 keys, told apart by their salts. Neither the passphrase
+//! nor the key is stored: a conversation stays locked until its passphrase is
+//! supplied again, after which the key is held in memory by the [`KeyRing`].
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Argon2id costs: 19 MiB, two passes, one lane. Keys are only recognised
+/// by their salt's prefix, so changing them takes a new prefix.
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_PASSES: u32 = 2;
+const ARGON2_LANES: u32 = 1;
+/// Marks (and versions) the salts of keys derived with Argon2id. Salts
+/// without it are of keys derived with PBKDF2.
+const ARGON2_SALT_PREFIX: &str = "argon2id:";
+const PBKDF2_ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+const SALT_LEN: usize = 16;
+/// Marks (and versions) sealed content.
+const SEALED_PREFIX: &str = "enc1:";
+/// Sealed with a conversation's key so a passphrase can be checked.
+const KEY_CHECK: &str = "conversation-key-check";
+/// Unlocked keys are forgotten after this long without use.
+pub const UNLOCK_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The AES-256-GCM key of one encrypted conversation.
+pub struct ConversationKey {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl ConversationKey {
+    /// Derives the key for `passphrase` and a `salt` from [`new_salt`], or a
+    /// bare base64 one of a key derived with PBKDF2. Slow on purpose; call it
+    /// off the async runtime.
+    pub fn derive(passphrase: &str, salt: &str) -> Result<Self, AppError> {
+        let decode = |salt| {
+            BASE64
+                .decode(salt)
+                .map_err(|e| AppError::Unexpected(format!("Unreadable key salt: {e}")))
+        };
+        let derive_failed =
+            |e: argon2::Error| AppError::Unexpected(format!("Failed to derive the key: {e}"));
+        let mut bytes = [0u8; 32];
+        match salt.strip_prefix(ARGON2_SALT_PREFIX) {
+            Some(salt) => {
+                let params =
+                    Params::new(ARGON2_MEMORY_KIB, ARGON2_PASSES, ARGON2_LANES, Some(bytes.len()))
+                        .map_err(derive_failed)?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), &decode(salt)?, &mut bytes)
+                    .map_err(derive_failed)?;
+            }
+            None => pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                PBKDF2_ITERATIONS,
+                &decode(salt)?,
+                passphrase.as_bytes(),
+                &mut bytes,
+            ),
+        }
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| AppError::Unexpected("Failed to create encryption key".to_string()))?;
+        Ok(Self { key: LessSafeKey::new(key), rng: SystemRandom::new() })
+    }
+
+    /// Encrypts `plaintext` as `enc1:` followed by the base64 nonce, ciphertext
+    /// and tag.
+    pub fn seal(&self, plaintext: &str) -> Result<String, AppError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| AppError::Unexpected("Failed to generate a nonce".to_string()))?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        let unique = Nonce::assume_unique_for_key(nonce);
+        self.key
+            .seal_in_place_append_tag(unique, Aad::empty(), &mut sealed)
+            .map_err(|_| AppError::Unexpected("Failed to encrypt message".to_string()))?;
+        sealed.splice(0..0, nonce);
+        Ok(format!("{SEALED_PREFIX}{}", BASE64.encode(sealed)))
+    }
+
+    /// Reverses [`ConversationKey::seal`]. `None` if `sealed` is malformed or
+    /// was sealed with another key.
+    pub fn open(&self, sealed: &str) -> Option<String> {
+        let mut bytes = BASE64.decode(sealed.strip_prefix(SEALED_PREFIX)?).ok()?;
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let mut ciphertext = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).ok()?;
+        let plaintext = self.key.open_in_place(nonce, Aad::empty(), &mut ciphertext).ok()?;
+        String::from_utf8(plaintext.to_vec()).ok()
+    }
+
+    /// [`ConversationKey::open`] for stored content, which must have been
+    /// sealed with this key.
+    pub fn open_stored(&self, sealed: &str) -> Result<String, AppError> {
+        self.open(sealed)
+            .ok_or_else(|| AppError::Unexpected("Failed to decrypt a stored message".to_string()))
+    }
+
+    /// The value stored to recognise this key by.
+    pub fn check_value(&self) -> Result<String, AppError> {
+        self.seal(KEY_CHECK)
+    }
+
+    /// Whether `check` was made by [`ConversationKey::check_value`] with the
+    /// same key, i.e. the passphrase was right.
+    pub fn matches(&self, check: &str) -> bool {
+        self.open(check).as_deref() == Some(KEY_CHECK)
+    }
+}
+
+/// A random salt for a newly encrypted conversation, whose key is derived
+/// with Argon2id: base64 after `argon2id:`.
+pub fn new_salt() -> Result<String, AppError> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| AppError::Unexpected("Failed to generate a key salt".to_string()))?;
+    Ok(format!("{ARGON2_SALT_PREFIX}{}", BASE64.encode(salt)))
+}
+
+/// Keys of the conversations unlocked since the server started.
+///
+/// Keys live only here: they are dropped when their conversation is locked,
+/// after [`UNLOCK_IDLE_TIMEOUT`] without use, or when the server restarts.
+#[derive(Clone, Default)]
+pub struct KeyRing {
+    keys: Arc<Mutex<HashMap<Uuid, Unlocked>>>,
+}
+
+struct Unlocked {
+    key: Arc<ConversationKey>,
+    last_used: Instant,
+}
+
+impl KeyRing {
+    pub fn insert(&self, conversation_id: Uuid, key: ConversationKey) {
+        let unlocked = Unlocked { key: Arc::new(key), last_used: Instant::now() };
+        self.lock().insert(conversation_id, unlocked);
+    }
+
+    /// The key of an unlocked conversation, counting as a use of it.
+    pub fn get(&self, conversation_id: Uuid) -> Option<Arc<ConversationKey>> {
+        let mut keys = self.lock();
+        keys.retain(|_, unlocked| unlocked.last_used.elapsed() < UNLOCK_IDLE_TIMEOUT);
+        let unlocked = keys.get_mut(&conversation_id)?;
+        unlocked.last_used = Instant::now();
+        Some(unlocked.key.clone())
+    }
+
+    pub fn remove(&self, conversation_id: Uuid) {
+        self.lock().remove(&conversation_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Unlocked>> {
+        self.keys.lock().expect("key ring poisoned")
+    }
+}
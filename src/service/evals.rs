@@ -0,0 +1,113 @@
+//! Reports for eval runs: how a model's replays differ from the replies
+//! originally stored.
+
+use crate::models::{DiffOp, DiffSpan, EvalReport, EvalResult, EvalRun, EvalSummary, EvalTurnReport};
+
+/// Texts whose word (or line) counts multiply to more than this are reported
+/// as replaced wholesale rather than diffed piece by piece; the diff is
+/// quadratic.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Pairs each result with a word diff and totals them up.
+pub fn build_report(run: EvalRun, results: Vec<EvalResult>) -> EvalReport {
+    let mut summary = EvalSummary { turns: results.len(), ..EvalSummary::default() };
+    let turns = results
+        .into_iter()
+        .map(|result| {
+            let original = result.original_content.as_deref().unwrap_or_default();
+            let new = result.new_content.as_deref().unwrap_or_default();
+            summary.original_words += original.split_whitespace().count();
+            summary.new_words += new.split_whitespace().count();
+            if result.error.is_some() {
+                summary.errors += 1;
+            } else if original.trim() != new.trim() {
+                summary.changed += 1;
+            }
+            let diff = word_diff(original, new);
+            EvalTurnReport { result, diff }
+        })
+        .collect();
+    EvalReport { run, summary, turns }
+}
+
+/// Word-level diff turning `old` into `new`. Each word keeps its trailing
+/// whitespace, so concatenating the `Equal` and `Delete` spans gives `old`
+/// back, and `Equal` plus `Insert` gives `new`.
+pub fn word_diff(old: &str, new: &str) -> Vec<DiffSpan> {
+    merge(diff_ops(&words(old), &words(new)))
+}
+
+/// The operations turning the pieces `a` into `b`, one per piece: a longest
+/// common subsequence, or everything replaced when that would be too costly.
+pub(crate) fn diff_ops<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(DiffOp, &'a str)> {
+    let (n, m) = (a.len(), b.len());
+
+    let mut ops = Vec::with_capacity(n + m);
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        ops.extend(a.iter().map(|w| (DiffOp::Delete, *w)));
+        ops.extend(b.iter().map(|w| (DiffOp::Insert, *w)));
+        return ops;
+    }
+
+    // lcs[i][j]: longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            ops.push((DiffOp::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((DiffOp::Delete, a[i]));
+            i += 1;
+        } else {
+            ops.push((DiffOp::Insert, b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[i..].iter().map(|w| (DiffOp::Delete, *w)));
+    ops.extend(b[j..].iter().map(|w| (DiffOp::Insert, *w)));
+    ops
+}
+
+/// Splits after each run of whitespace, keeping it with the preceding word.
+fn words(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut in_space = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_space = true;
+        } else if in_space {
+            out.push(&text[start..i]);
+            start = i;
+            in_space = false;
+        }
+    }
+    if start < text.len() {
+        out.push(&text[start..]);
+    }
+    out
+}
+
+/// Joins consecutive words with the same operation into one span.
+fn merge(ops: Vec<(DiffOp, &str)>) -> Vec<DiffSpan> {
+    let mut spans: Vec<DiffSpan> = Vec::new();
+    for (op, word) in ops {
+        match spans.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(word),
+            _ => spans.push(DiffSpan { op, text: word.to_string() }),
+        }
+    }
+    spans
+}
@@ -0,0 +1,92 @@
+//! "Download my data": the profile and every stored conversation as a zip
+//! archive, sent to the client while it is still being written.
+
+use std::io::{self, Write};
+
+use axum::body::Bytes;
+use chrono::{Datelike, Timelike, Utc};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::errors::AppError;
+
+/// Conversations loaded per database round trip.
+pub const EXPORT_BATCH_SIZE: i64 = 50;
+/// Archive bytes are handed to the response in chunks of about this size.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// One file in the archive.
+pub struct ExportEntry {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+/// Zips `entries` as they arrive and sends the archive through `out`. Blocks,
+/// so run it with `spawn_blocking`. An `Err` entry aborts the archive, and the
+/// error is passed on so the download fails instead of ending truncated.
+pub fn write_zip(
+    mut entries: mpsc::Receiver<Result<ExportEntry, AppError>>,
+    out: mpsc::Sender<io::Result<Bytes>>,
+) {
+    let writer = ChannelWriter {
+        buffer: Vec::with_capacity(CHUNK_SIZE),
+        out: out.clone(),
+    };
+    if let Err(e) = zip_entries(&mut entries, writer) {
+        let _ = out.blocking_send(Err(e));
+    }
+}
+
+fn zip_entries(
+    entries: &mut mpsc::Receiver<Result<ExportEntry, AppError>>,
+    writer: ChannelWriter,
+) -> io::Result<()> {
+    let now = Utc::now();
+    let modified = zip::DateTime::from_date_and_time(
+        now.year() as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    )
+    .unwrap_or_default();
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(modified);
+
+    let mut zip = ZipWriter::new_stream(writer);
+    while let Some(entry) = entries.blocking_recv() {
+        let entry = entry.map_err(io::Error::other)?;
+        zip.start_file(entry.path, options).map_err(io::Error::other)?;
+        zip.write_all(&entry.contents)?;
+    }
+    zip.finish().map_err(io::Error::other)?.into_inner().flush()
+}
+
+/// Buffers writes and forwards them to the response body in chunks.
+struct ChannelWriter {
+    buffer: Vec<u8>,
+    out: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+        self.out
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download cancelled"))
+    }
+}
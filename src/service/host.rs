@@ -0,0 +1,17 @@
+use sysinfo::System;
+
+use crate::models::HostStats;
+
+/// Memory use and load average of the host right now. Reads a couple of
+/// files under `/proc` on Linux; cheap enough to call per request.
+pub fn host_stats() -> HostStats {
+    let mut system = System::new();
+    system.refresh_memory();
+    let load = System::load_average();
+    HostStats {
+        memory_total_bytes: system.total_memory(),
+        memory_used_bytes: system.used_memory(),
+        cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        load_average: [load.one, load.five, load.fifteen],
+    }
+}
@@ -0,0 +1,326 @@
+//! Ingestion of git repositories for retrieval: the text files committed at
+//! a branch are split into chunks ([`splitter`]), embedded and indexed in a
+//! collection, replacing what was indexed there from the same repository
+//! before. Collections are also split again in place ([`rechunk`]) when
+//! their chunking settings change.
+//!
+//! Documents and chunks are indexed with the SHA-256 of their text, so files
+//! that did not change are skipped and chunks whose text was embedded before
+//! reuse that embedding: indexing again only embeds what changed.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use git2::build::RepoBuilder;
+use git2::{FetchOptions, ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use ring::digest::{digest, SHA256};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::agent::AgentService;
+use crate::db::document_repository::DocumentRepository;
+use crate::errors::AppError;
+use crate::models::{Document, DocumentChunk, IndexedDocument, IngestionReport};
+use crate::service::splitter::{self, ChunkSettings, Section};
+
+/// The collection repositories are indexed in unless another is named.
+pub const CODEBASE_COLLECTION: &str = "codebase";
+/// Chunks embedded per request to the embedding model.
+const EMBED_BATCH: usize = 32;
+/// Git file mode of a symbolic link.
+const SYMLINK_MODE: i32 = 0o120000;
+
+/// Where a repository is read from.
+#[derive(Debug, Clone)]
+pub enum Origin {
+    /// Cloned over https for the ingestion and removed afterwards.
+    Remote(String),
+    /// A repository already on the server, read in place.
+    Local(PathBuf),
+}
+
+/// A committed file worth indexing.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: String,
+    pub language: &'static str,
+    pub text: String,
+}
+
+/// Where an ingestion indexes and how.
+pub struct IngestSettings<'a> {
+    pub collection: &'a str,
+    pub embedding_model: &'a str,
+    pub max_file_bytes: u64,
+    pub chunks: ChunkSettings,
+}
+
+/// Reads, splits, embeds and indexes `origin` at `branch` (its default branch
+/// when unset) as `source`. Files indexed from it before are skipped when
+/// unchanged, and dropped when no longer there.
+pub async fn ingest(
+    agent: &dyn AgentService,
+    documents: &DocumentRepository,
+    source: &str,
+    origin: Origin,
+    branch: Option<String>,
+    settings: IngestSettings<'_>,
+) -> Result<IngestionReport, AppError> {
+    let max_file_bytes = settings.max_file_bytes;
+    let files =
+        tokio::task::spawn_blocking(move || read_files(&origin, branch.as_deref(), max_file_bytes))
+            .await
+            .map_err(|e| AppError::Unexpected(format!("Repository reader stopped: {e}")))??;
+
+    let collection = settings.collection;
+    let mut previous: HashMap<String, IndexedDocument> = documents
+        .find_documents(collection, Some(source))
+        .await?
+        .into_iter()
+        .map(|indexed| (indexed.document.path.clone(), indexed))
+        .collect();
+    let embedder = Embedder { agent, documents, collection, model: settings.embedding_model };
+    let paths: Vec<String> = files.iter().map(|file| file.path.clone()).collect();
+    let mut report = IngestionReport::default();
+    let mut changed = Vec::new();
+    for file in files {
+        let text_hash = content_hash(&file.text);
+        let sections = splitter::split(&file.text, file.language, settings.chunks);
+        let hashes = embedder.hashes(&file.path, &sections);
+        report.files += 1;
+        report.chunks += sections.len() as i32;
+        match previous.remove(&file.path) {
+            Some(indexed)
+                if indexed.document.content_hash.as_ref() == Some(&text_hash)
+                    && indexed.chunk_hashes == hashes =>
+            {
+                report.skipped += 1;
+                continue;
+            }
+            Some(_) => report.updated += 1,
+            None => report.added += 1,
+        }
+        let document = Document {
+            id: Uuid::new_v4(),
+            collection: collection.to_string(),
+            source: source.to_string(),
+            path: file.path,
+            language: Some(file.language.to_string()),
+            content: Some(file.text),
+            content_hash: Some(text_hash),
+            created_at: Utc::now(),
+        };
+        let chunks = embedder.chunks(&document, &sections, hashes, &mut report).await?;
+        changed.push((document, chunks));
+    }
+    report.removed = previous.len() as i32;
+
+    documents.sync_source(collection, source, &changed, &paths).await?;
+    info!("Indexed {source} in {collection}: {report:?}");
+    Ok(report)
+}
+
+/// Splits every document of `collection` again with `settings` and embeds
+/// the chunks that changed, replacing the old ones. Documents indexed
+/// before their text was kept are put back together from their chunks.
+pub async fn rechunk(
+    agent: &dyn AgentService,
+    documents: &DocumentRepository,
+    collection: &str,
+    embedding_model: &str,
+    settings: ChunkSettings,
+) -> Result<IngestionReport, AppError> {
+    let embedder = Embedder { agent, documents, collection, model: embedding_model };
+    let mut report = IngestionReport::default();
+    let mut changed = Vec::new();
+    for IndexedDocument { mut document, chunk_hashes } in
+        documents.find_documents(collection, None).await?
+    {
+        let text = match document.content.take() {
+            Some(text) => text,
+            None => reassemble(&documents.find_document_chunks(document.id).await?),
+        };
+        let language = document.language.as_deref().unwrap_or("text");
+        let sections = splitter::split(&text, language, settings);
+        let hashes = embedder.hashes(&document.path, &sections);
+        report.files += 1;
+        report.chunks += sections.len() as i32;
+        if chunk_hashes == hashes {
+            report.skipped += 1;
+            continue;
+        }
+        report.updated += 1;
+        document.content_hash = Some(content_hash(&text));
+        document.content = Some(text);
+        let chunks = embedder.chunks(&document, &sections, hashes, &mut report).await?;
+        changed.push((document, chunks));
+    }
+
+    documents.replace_chunks(&changed).await?;
+    info!("Re-chunked {collection}: {report:?}");
+    Ok(report)
+}
+
+/// Embeds chunks for a collection, reusing the embeddings of chunks indexed
+/// there before with the same text by the same model.
+struct Embedder<'a> {
+    agent: &'a dyn AgentService,
+    documents: &'a DocumentRepository,
+    collection: &'a str,
+    model: &'a str,
+}
+
+impl Embedder<'_> {
+    /// The hashes of `sections` of the document at `path` as embedded.
+    fn hashes(&self, path: &str, sections: &[Section]) -> Vec<String> {
+        let text = |section: &Section| embedded_text(path, &section.content);
+        sections.iter().map(|s| content_hash(&format!("{}\n{}", self.model, text(s)))).collect()
+    }
+
+    /// `sections` of `document` as its chunks, in order, embedding those
+    /// whose `hashes` are not indexed yet.
+    async fn chunks(
+        &self,
+        document: &Document,
+        sections: &[Section],
+        hashes: Vec<String>,
+        report: &mut IngestionReport,
+    ) -> Result<Vec<DocumentChunk>, AppError> {
+        let mut embeddings =
+            self.documents.find_embeddings(self.collection, self.model, &hashes).await?;
+        let mut queued = HashSet::new();
+        let missing: Vec<usize> = (0..sections.len())
+            .filter(|&i| !embeddings.contains_key(&hashes[i]) && queued.insert(&hashes[i]))
+            .collect();
+        for batch in missing.chunks(EMBED_BATCH) {
+            let texts: Vec<String> = batch
+                .iter()
+                .map(|&i| embedded_text(&document.path, &sections[i].content))
+                .collect();
+            let embedded = self.agent.embed(self.model, &texts).await?;
+            for (&i, embedding) in batch.iter().zip(embedded) {
+                embeddings.insert(hashes[i].clone(), embedding);
+            }
+            report.embedded += batch.len() as i32;
+        }
+
+        let chunks = sections.iter().zip(hashes).enumerate().map(|(position, (section, hash))| {
+            DocumentChunk {
+                id: Uuid::new_v4(),
+                document_id: document.id,
+                position: position as i32,
+                content: section.content.clone(),
+                start_line: section.start_line as i32,
+                end_line: section.end_line as i32,
+                embedding: embeddings.get(&hash).cloned().unwrap_or_default(),
+                content_hash: Some(hash),
+                embedding_model: Some(self.model.to_string()),
+            }
+        });
+        Ok(chunks.collect())
+    }
+}
+
+/// What is embedded for a chunk: the path along with the code, as it often
+/// names what the code is about.
+fn embedded_text(path: &str, content: &str) -> String {
+    format!("{path}\n{content}")
+}
+
+/// Hex SHA-256 of `text`.
+fn content_hash(text: &str) -> String {
+    digest(&SHA256, text.as_bytes()).as_ref().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The text of a document rebuilt from its `chunks`, each put back at its
+/// lines. Blank lines the splitter trimmed off come back as blank lines;
+/// overlapping chunks agree on the lines they share.
+fn reassemble(chunks: &[DocumentChunk]) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for chunk in chunks {
+        let start = chunk.start_line.max(1) as usize - 1;
+        for (i, line) in chunk.content.lines().enumerate() {
+            if lines.len() <= start + i {
+                lines.resize(start + i + 1, "");
+            }
+            lines[start + i] = line;
+        }
+    }
+    lines.join("\n")
+}
+
+/// The indexable files committed at `branch` of `origin`: tracked, not
+/// hidden, not symlinks, in a known language, at most `max_file_bytes`
+/// and valid UTF-8. Blocking.
+pub fn read_files(
+    origin: &Origin,
+    branch: Option<&str>,
+    max_file_bytes: u64,
+) -> Result<Vec<SourceFile>, AppError> {
+    let failed = |e: git2::Error| AppError::InvalidField {
+        field_name: "repository".to_string(),
+        message: e.message().to_string(),
+    };
+    match origin {
+        Origin::Local(path) => {
+            let repo = Repository::open(path).map_err(failed)?;
+            read_tree(&repo, branch, max_file_bytes).map_err(failed)
+        }
+        Origin::Remote(url) => {
+            let scratch = std::env::temp_dir().join(format!("ingest-{}", Uuid::new_v4()));
+            let files = clone(url, branch, &scratch)
+                .and_then(|repo| read_tree(&repo, None, max_file_bytes))
+                .map_err(failed);
+            let _ = std::fs::remove_dir_all(&scratch);
+            files
+        }
+    }
+}
+
+/// A bare, shallow clone of `url` at `branch`: only the files' latest
+/// version is fetched, and none is checked out.
+fn clone(url: &str, branch: Option<&str>, into: &Path) -> Result<Repository, git2::Error> {
+    let mut fetch = FetchOptions::new();
+    fetch.depth(1);
+    let mut builder = RepoBuilder::new();
+    builder.bare(true).fetch_options(fetch);
+    if let Some(branch) = branch {
+        builder.branch(branch);
+    }
+    builder.clone(url, into)
+}
+
+fn read_tree(
+    repo: &Repository,
+    branch: Option<&str>,
+    max_file_bytes: u64,
+) -> Result<Vec<SourceFile>, git2::Error> {
+    let tree = match branch {
+        Some(branch) => repo.revparse_single(branch)?.peel_to_tree()?,
+        None => repo.head()?.peel_to_tree()?,
+    };
+    let mut files = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        let Some(name) = entry.name() else { return TreeWalkResult::Skip };
+        if name.starts_with('.') {
+            return TreeWalkResult::Skip;
+        }
+        if entry.kind() != Some(ObjectType::Blob) || entry.filemode() == SYMLINK_MODE {
+            return TreeWalkResult::Ok;
+        }
+        let path = format!("{dir}{name}");
+        let Some(language) = splitter::language(&path) else { return TreeWalkResult::Ok };
+        let Ok(blob) = entry.to_object(repo).and_then(|o| o.peel_to_blob()) else {
+            return TreeWalkResult::Ok;
+        };
+        if blob.size() as u64 > max_file_bytes || blob.is_binary() {
+            return TreeWalkResult::Ok;
+        }
+        if let Ok(text) = std::str::from_utf8(blob.content()) {
+            files.push(SourceFile { path, language, text: text.to_string() });
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(files)
+}
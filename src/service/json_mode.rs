@@ -0,0 +1,207 @@
+//! Validation of replies to turns that asked for `response_format: json`:
+//! incrementally while they stream, so a reply that has clearly gone wrong
+//! can be stopped early, and once more in full before it is saved.
+
+/// What may come next at the current nesting level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    /// Right after `[`.
+    ValueOrClose,
+    /// Right after `{`.
+    KeyOrClose,
+    /// After a `,` inside an object.
+    Key,
+    Colon,
+    CommaOrClose,
+    /// The top-level value is complete; only whitespace may follow.
+    Nothing,
+}
+
+/// Position inside a string literal.
+#[derive(Debug, Clone, Copy)]
+struct InString {
+    is_key: bool,
+    escaped: bool,
+    /// Hex digits still owed to a `\u` escape.
+    unicode_digits: u8,
+}
+
+/// Checks a JSON document fed to it a chunk at a time, failing as soon as
+/// the text so far cannot be the start of a valid document.
+#[derive(Debug)]
+pub struct JsonStreamValidator {
+    /// Open `{` and `[`, innermost last.
+    stack: Vec<char>,
+    expect: Expect,
+    string: Option<InString>,
+    /// Number or `true`/`false`/`null` being read.
+    token: String,
+    /// Characters consumed so far, for error positions.
+    offset: usize,
+    error: Option<String>,
+}
+
+impl Default for JsonStreamValidator {
+    fn default() -> Self {
+        Self {
+            stack: Vec::new(),
+            expect: Expect::Value,
+            string: None,
+            token: String::new(),
+            offset: 0,
+            error: None,
+        }
+    }
+}
+
+impl JsonStreamValidator {
+    /// Feeds the next chunk. Once an error is reported, every later call
+    /// reports it again.
+    pub fn push(&mut self, chunk: &str) -> Result<(), String> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        for c in chunk.chars() {
+            if let Err(error) = self.step(c) {
+                let error = format!("{error} at character {}", self.offset);
+                self.error = Some(error.clone());
+                return Err(error);
+            }
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, c: char) -> Result<(), String> {
+        if let Some(string) = &mut self.string {
+            if string.escaped {
+                string.escaped = false;
+                match c {
+                    'u' => string.unicode_digits = 4,
+                    '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' => {}
+                    _ => return Err(format!("invalid escape '\\{c}'")),
+                }
+            } else if string.unicode_digits > 0 {
+                if !c.is_ascii_hexdigit() {
+                    return Err(format!("invalid unicode escape digit '{c}'"));
+                }
+                string.unicode_digits -= 1;
+            } else if c == '\\' {
+                string.escaped = true;
+            } else if c == '"' {
+                let is_key = string.is_key;
+                self.string = None;
+                self.after_value(is_key);
+            } else if c.is_control() {
+                return Err("unescaped control character in a string".to_string());
+            }
+            return Ok(());
+        }
+
+        if !self.token.is_empty() {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-') {
+                self.token.push(c);
+                return self.check_token_prefix();
+            }
+            self.end_token()?;
+        }
+        if matches!(c, ' ' | '\t' | '\n' | '\r') {
+            return Ok(());
+        }
+
+        match (self.expect, c) {
+            (Expect::Value | Expect::ValueOrClose, '{') => {
+                self.stack.push('{');
+                self.expect = Expect::KeyOrClose;
+            }
+            (Expect::Value | Expect::ValueOrClose, '[') => {
+                self.stack.push('[');
+                self.expect = Expect::ValueOrClose;
+            }
+            (Expect::Value | Expect::ValueOrClose, '"') => self.open_string(false),
+            (Expect::Value | Expect::ValueOrClose, '-' | '0'..='9' | 't' | 'f' | 'n') => {
+                self.token.push(c);
+            }
+            (Expect::KeyOrClose | Expect::Key, '"') => self.open_string(true),
+            (Expect::Colon, ':') => self.expect = Expect::Value,
+            (Expect::CommaOrClose, ',') => {
+                self.expect =
+                    if self.stack.last() == Some(&'{') { Expect::Key } else { Expect::Value };
+            }
+            (Expect::ValueOrClose | Expect::CommaOrClose, ']')
+                if self.stack.last() == Some(&'[') =>
+            {
+                self.close();
+            }
+            (Expect::KeyOrClose | Expect::CommaOrClose, '}')
+                if self.stack.last() == Some(&'{') =>
+            {
+                self.close();
+            }
+            (Expect::Nothing, _) => {
+                return Err(format!("unexpected '{c}' after the JSON document"));
+            }
+            _ => return Err(format!("unexpected '{c}'")),
+        }
+        Ok(())
+    }
+
+    fn open_string(&mut self, is_key: bool) {
+        self.string = Some(InString { is_key, escaped: false, unicode_digits: 0 });
+    }
+
+    fn close(&mut self) {
+        self.stack.pop();
+        self.after_value(false);
+    }
+
+    fn after_value(&mut self, is_key: bool) {
+        self.expect = if is_key {
+            Expect::Colon
+        } else if self.stack.is_empty() {
+            Expect::Nothing
+        } else {
+            Expect::CommaOrClose
+        };
+    }
+
+    /// Fails once a literal can no longer become `true`, `false` or `null`,
+    /// or a number picks up a character numbers never contain.
+    fn check_token_prefix(&self) -> Result<(), String> {
+        let valid = if self.token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            ["true", "false", "null"].iter().any(|literal| literal.starts_with(&self.token))
+        } else {
+            self.token
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | '+' | '-' | 'e' | 'E'))
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("invalid literal '{}'", self.token))
+        }
+    }
+
+    fn end_token(&mut self) -> Result<(), String> {
+        let token = std::mem::take(&mut self.token);
+        if serde_json::from_str::<serde_json::Value>(&token).is_err() {
+            return Err(format!("invalid literal '{token}'"));
+        }
+        self.after_value(false);
+        Ok(())
+    }
+}
+
+/// Full check of a finished reply.
+pub fn validate(content: &str) -> Result<(), String> {
+    serde_json::from_str::<serde_json::Value>(content).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Follow-up message asking the model to fix its malformed reply.
+pub fn repair_prompt(error: &str) -> String {
+    format!(
+        "Your previous reply was not valid JSON ({error}). Reply again with the same content \
+         as a single valid JSON object and nothing else."
+    )
+}
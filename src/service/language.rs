@@ -0,0 +1,18 @@
+use whatlang::Lang;
+
+/// Below this, whatlang is effectively guessing (short or mixed-language text).
+/// Its own `is_reliable` is stricter than typical chat messages allow.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// ISO 639-3 code of the language `text` is written in, or `None` when it is
+/// too short or ambiguous to tell.
+pub fn detect(text: &str) -> Option<&'static str> {
+    whatlang::detect(text)
+        .filter(|info| info.confidence() >= MIN_CONFIDENCE)
+        .map(|info| info.lang().code())
+}
+
+/// English name of an ISO 639-3 language code, e.g. "Spanish" for "spa".
+pub fn english_name(code: &str) -> Option<&'static str> {
+    Lang::from_code(code).map(|lang| lang.eng_name())
+}
@@ -1 +1,23 @@
+pub mod artifacts;
 pub mod chat_service;
+pub mod chunking;
+pub mod cron;
+pub mod email_gateway;
+pub mod encryption;
+pub mod evals;
+pub mod json_mode;
+pub mod export;
+pub mod host;
+pub mod ingest;
+pub mod language;
+pub mod moderation;
+pub mod notify;
+pub mod pdf;
+pub mod post_process;
+pub mod retrieval;
+pub mod slack;
+pub mod splitter;
+pub mod tokenize;
+pub mod transcript;
+pub mod webhooks;
+pub mod workspace;
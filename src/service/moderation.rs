@@ -0,0 +1,62 @@
+/// Hazard categories of the Llama Guard 3 taxonomy, by code.
+const GUARD_CATEGORIES: [(&str, &str); 14] = [
+    ("S1", "violent crimes"),
+    ("S2", "non-violent crimes"),
+    ("S3", "sex-related crimes"),
+    ("S4", "child sexual exploitation"),
+    ("S5", "defamation"),
+    ("S6", "specialized advice"),
+    ("S7", "privacy"),
+    ("S8", "intellectual property"),
+    ("S9", "indiscriminate weapons"),
+    ("S10", "hate"),
+    ("S11", "suicide and self-harm"),
+    ("S12", "sexual content"),
+    ("S13", "elections"),
+    ("S14", "code interpreter abuse"),
+];
+
+/// The first of `terms` that appears in `message` as a whole word or phrase,
+/// ignoring case and punctuation.
+pub fn blocked_term<'a>(message: &str, terms: &'a [String]) -> Option<&'a str> {
+    let haystack = normalize(message);
+    terms
+        .iter()
+        .map(String::as_str)
+        .find(|term| {
+            let needle = normalize(term);
+            !needle.trim().is_empty() && haystack.contains(&needle)
+        })
+}
+
+/// Readable names for a classifier's comma-separated category codes, e.g.
+/// "violent crimes, hate" for "S1,S10". Unknown codes are kept as-is.
+pub fn describe_categories(codes: &str) -> String {
+    let names: Vec<&str> = codes
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|code| {
+            GUARD_CATEGORIES
+                .iter()
+                .find(|(c, _)| c.eq_ignore_ascii_case(code))
+                .map_or(code, |(_, name)| name)
+        })
+        .collect();
+    if names.is_empty() {
+        "unsafe content".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Lowercases `text` and reduces it to its words, each surrounded by single
+/// spaces, so a substring search only matches whole words.
+fn normalize(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!(" {} ", words.join(" "))
+}
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde_json::json;
+use tracing::{error, info, warn, Instrument};
+use uuid::Uuid;
+
+use crate::config::ConfigStore;
+use crate::db::notification_repository::NotificationRepository;
+use crate::errors::AppError;
+use crate::models::{
+    Notification, NotificationChannel, NotificationEvent, NotificationStatus, UserProfile,
+};
+
+/// Deliveries tried per notification before it is given up as failed.
+const DELIVERY_ATTEMPTS: i32 = 3;
+/// Wait before the second attempt; doubled before each one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How long a notification webhook may take to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tells users about finished background work through the channels in their
+/// profile: a JSON POST to `notify_webhook_url` and an email to
+/// `notify_email`. Every notification is logged, with how its delivery went.
+#[derive(Clone)]
+pub struct Notifier {
+    http: reqwest::Client,
+    repo: NotificationRepository,
+    config: ConfigStore,
+}
+
+impl Notifier {
+    pub fn new(repo: NotificationRepository, config: ConfigStore) -> Self {
+        Self { http: reqwest::Client::new(), repo, config }
+    }
+
+    /// Reports `event` about `subject_id` (a batch job or schedule) to every
+    /// channel `profile` has set up. Delivery, and its retries, happen in the
+    /// background.
+    pub async fn notify(
+        &self,
+        profile: &UserProfile,
+        event: NotificationEvent,
+        subject_id: Uuid,
+        summary: String,
+    ) {
+        let channels = [
+            (NotificationChannel::Webhook, &profile.notify_webhook_url),
+            (NotificationChannel::Email, &profile.notify_email),
+        ];
+        for (channel, target) in channels {
+            let Some(target) = target else { continue };
+            let notification = Notification {
+                id: Uuid::new_v4(),
+                user_id: profile.user_id.clone(),
+                event,
+                subject_id,
+                summary: summary.clone(),
+                channel,
+                target: target.clone(),
+                status: NotificationStatus::Pending,
+                attempts: 0,
+                last_error: None,
+                created_at: Utc::now(),
+                delivered_at: None,
+            };
+            if let Err(e) = self.repo.save(&notification).await {
+                error!("Failed to log notification: {e}");
+                continue;
+            }
+            let notifier = self.clone();
+            tokio::spawn(async move { notifier.deliver(notification).await }.in_current_span());
+        }
+    }
+
+    /// The latest `limit` notifications of `user_id`, newest first.
+    pub async fn recent(&self, user_id: &str, limit: i64) -> Result<Vec<Notification>, AppError> {
+        self.repo.find_recent(user_id, limit).await
+    }
+
+    /// Tries to deliver `notification` until it goes through or runs out of
+    /// attempts, recording each attempt.
+    async fn deliver(&self, notification: Notification) {
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=DELIVERY_ATTEMPTS {
+            let outcome = match notification.channel {
+                NotificationChannel::Webhook => self.post(&notification).await,
+                NotificationChannel::Email => self.email(&notification).await,
+            };
+            let status = match &outcome {
+                Ok(()) => NotificationStatus::Delivered,
+                Err(_) if attempt == DELIVERY_ATTEMPTS => NotificationStatus::Failed,
+                Err(_) => NotificationStatus::Pending,
+            };
+            let error = outcome.as_ref().err().map(String::as_str);
+            if let Err(e) = self.repo.record_attempt(notification.id, status, error).await {
+                error!("Failed to record delivery of notification {}: {e}", notification.id);
+            }
+            match outcome {
+                Ok(()) => {
+                    info!("Notified {} of {}", notification.target, notification.event.as_str());
+                    return;
+                }
+                Err(e) => warn!(
+                    "Attempt {attempt} to notify {} failed: {e}",
+                    notification.target
+                ),
+            }
+            if attempt < DELIVERY_ATTEMPTS {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+
+    async fn post(&self, notification: &Notification) -> Result<(), String> {
+        let body = json!({
+            "id": notification.id,
+            "event": notification.event,
+            "subject_id": notification.subject_id,
+            "summary": notification.summary,
+            "created_at": notification.created_at,
+        });
+        self.http
+            .post(&notification.target)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn email(&self, notification: &Notification) -> Result<(), String> {
+        let config = self.config.get();
+        let Some(url) = &config.smtp_url else {
+            return Err("smtp_url is not configured".to_string());
+        };
+        let from: Mailbox =
+            config.smtp_from.parse().map_err(|e| format!("invalid smtp_from: {e}"))?;
+        let to: Mailbox = notification.target.parse().map_err(|e| format!("{e}"))?;
+        let message = lettre::Message::builder()
+            .from(from)
+            .to(to)
+            .subject(subject(notification.event))
+            .body(notification.summary.clone())
+            .map_err(|e| e.to_string())?;
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|e| format!("invalid smtp_url: {e}"))?
+            .build();
+        transport.send(message).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+/// Subject line of the email for `event`.
+fn subject(event: NotificationEvent) -> &'static str {
+    match event {
+        NotificationEvent::BatchCompleted => "Batch job completed",
+        NotificationEvent::BatchFailed => "Batch job finished with failures",
+        NotificationEvent::ScheduleCompleted => "Scheduled prompt answered",
+        NotificationEvent::ScheduleFailed => "Scheduled prompt failed",
+    }
+}
+
+/// Whether `address` is an email address notifications can be sent to.
+pub fn is_valid_email(address: &str) -> bool {
+    address.parse::<Mailbox>().is_ok()
+}
@@ -0,0 +1,424 @@
+//! A conversation rendered to PDF, for `GET /api/conversations/{id}/export?format=pdf`.
+//!
+//! Replies are laid out from the same markdown blocks the UI renders. Text is
+//! set in the PDF standard fonts (Helvetica and Courier), so no font has to be
+//! embedded; they only cover Windows-1252, and characters outside it, such as
+//! emoji, are left out.
+
+use chrono::Utc;
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Rgb,
+};
+use shared_models::markdown::{self, Block, Inline};
+
+use crate::errors::AppError;
+use crate::models::{Conversation, Message, MessageRole};
+
+/// A4, in millimetres.
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+/// Every page has a single layer.
+const LAYER: &str = "Text";
+/// Millimetres per point.
+const PT: f32 = 25.4 / 72.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const BODY_SIZE: f32 = 10.5;
+const CODE_SIZE: f32 = 9.0;
+const SMALL_SIZE: f32 = 8.5;
+/// Line height, as a multiple of the font size.
+const LEADING: f32 = 1.4;
+/// How far lists, quotes and code blocks are indented.
+const INDENT: f32 = 6.0;
+/// Space between blocks of a message.
+const BLOCK_GAP: f32 = 2.0;
+/// Space between messages.
+const MESSAGE_GAP: f32 = 6.0;
+
+/// Advance widths of Helvetica's printable ASCII characters, from `' '` to
+/// `'~'`, in thousandths of an em.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // ' '..'/'
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // '0'..'?'
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // '@'..'O'
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // 'P'..'_'
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // '`'..'o'
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // 'p'..'~'
+];
+
+/// Renders the user and assistant messages of a conversation, in order,
+/// under its title.
+pub fn render(conversation: &Conversation, messages: &[Message]) -> Result<Vec<u8>, AppError> {
+    let mut pdf = Writer::new(&conversation.title)?;
+    pdf.paragraph(&[Run::new(&conversation.title, Style::Bold)], TITLE_SIZE, Frame::default());
+    let exported = format!("Exported {}", Utc::now().format("%Y-%m-%d %H:%M UTC"));
+    pdf.paragraph(&[Run::new(&exported, Style::Regular)], SMALL_SIZE, Frame::GREY);
+
+    let shown = messages
+        .iter()
+        .filter(|m| matches!(m.role, MessageRole::User | MessageRole::Assistant));
+    for message in shown {
+        pdf.gap(MESSAGE_GAP);
+        let author = if message.role == MessageRole::User { "You" } else { "Assistant" };
+        let header = format!("{author} · {}", message.created_at.format("%Y-%m-%d %H:%M"));
+        pdf.paragraph(&[Run::new(&header, Style::Bold)], SMALL_SIZE, Frame::GREY);
+        pdf.gap(BLOCK_GAP / 2.0);
+
+        // Users type plain text; replies are markdown.
+        if message.role == MessageRole::User {
+            let text = [Run::new(&message.content, Style::Regular)];
+            pdf.paragraph(&text, BODY_SIZE, Frame::default());
+            continue;
+        }
+        for (i, source) in markdown::split(&message.content).iter().enumerate() {
+            if i > 0 {
+                pdf.gap(BLOCK_GAP);
+            }
+            pdf.block(markdown::parse_block(source, false));
+        }
+        if !message.sources.is_empty() {
+            pdf.gap(BLOCK_GAP);
+            for (i, source) in message.sources.iter().enumerate() {
+                let title = if source.title.is_empty() { &source.url } else { &source.title };
+                let line = format!("[{}] {title} — {}", i + 1, source.url);
+                pdf.paragraph(&[Run::new(&line, Style::Regular)], SMALL_SIZE, Frame::GREY);
+            }
+        }
+    }
+    pdf.doc.save_to_bytes().map_err(pdf_error)
+}
+
+fn pdf_error(e: printpdf::Error) -> AppError {
+    AppError::Unexpected(format!("PDF rendering failed: {e}"))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Code,
+}
+
+impl Style {
+    fn bold(self) -> Self {
+        match self {
+            Style::Regular => Style::Bold,
+            Style::Italic => Style::BoldItalic,
+            other => other,
+        }
+    }
+
+    fn italic(self) -> Self {
+        match self {
+            Style::Regular => Style::Italic,
+            Style::Bold => Style::BoldItalic,
+            other => other,
+        }
+    }
+}
+
+/// Text in a single style.
+struct Run {
+    text: String,
+    style: Style,
+}
+
+impl Run {
+    fn new(text: &str, style: Style) -> Self {
+        Self { text: text.to_string(), style }
+    }
+}
+
+/// Where a paragraph sits and how it is set off from the text around it.
+#[derive(Clone, Copy, Default)]
+struct Frame {
+    /// From the left margin, in millimetres.
+    indent: f32,
+    /// A rule down the left edge, as for quotes and code.
+    bar: bool,
+    grey: bool,
+}
+
+impl Frame {
+    const GREY: Frame = Frame { indent: 0.0, bar: false, grey: true };
+}
+
+struct Fonts {
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    italic: IndirectFontRef,
+    bold_italic: IndirectFontRef,
+    code: IndirectFontRef,
+}
+
+/// Lays text out top to bottom, starting a page whenever one fills up.
+struct Writer {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    fonts: Fonts,
+    /// Top of the next line, in millimetres from the bottom of the page.
+    y: f32,
+}
+
+impl Writer {
+    fn new(title: &str) -> Result<Self, AppError> {
+        let (doc, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), LAYER);
+        let font = |font| doc.add_builtin_font(font).map_err(pdf_error);
+        let fonts = Fonts {
+            regular: font(BuiltinFont::Helvetica)?,
+            bold: font(BuiltinFont::HelveticaBold)?,
+            italic: font(BuiltinFont::HelveticaOblique)?,
+            bold_italic: font(BuiltinFont::HelveticaBoldOblique)?,
+            code: font(BuiltinFont::Courier)?,
+        };
+        let layer = doc.get_page(page).get_layer(layer);
+        Ok(Self { doc, layer, fonts, y: PAGE_HEIGHT - MARGIN })
+    }
+
+    fn font(&self, style: Style) -> &IndirectFontRef {
+        match style {
+            Style::Regular => &self.fonts.regular,
+            Style::Bold => &self.fonts.bold,
+            Style::Italic => &self.fonts.italic,
+            Style::BoldItalic => &self.fonts.bold_italic,
+            Style::Code => &self.fonts.code,
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// Moves to a new page unless `height` more millimetres fit on this one.
+    fn reserve(&mut self, height: f32) {
+        if self.y - height >= MARGIN {
+            return;
+        }
+        let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), LAYER);
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn block(&mut self, block: Block) {
+        let body = Frame::default();
+        match block {
+            Block::Paragraph(inlines) => {
+                self.paragraph(&runs(inlines, Style::Regular), BODY_SIZE, body);
+            }
+            Block::Heading(level, inlines) => {
+                let size = match level {
+                    1 => 16.0,
+                    2 => 14.0,
+                    3 => 12.5,
+                    _ => 11.0,
+                };
+                self.paragraph(&runs(inlines, Style::Bold), size, body);
+            }
+            Block::Code { code, .. } => self.code(&code),
+            Block::Quote(inlines) => {
+                let frame = Frame { indent: INDENT, bar: true, grey: true };
+                self.paragraph(&runs(inlines, Style::Italic), BODY_SIZE, frame);
+            }
+            Block::List { ordered, start, items } => {
+                let frame = Frame { indent: INDENT, ..body };
+                for (n, item) in (start..).zip(items) {
+                    let marker = if ordered { format!("{n}.") } else { "•".to_string() };
+                    self.list_item(&marker, &runs(item, Style::Regular), frame);
+                }
+            }
+            Block::Rule => {
+                self.reserve(BLOCK_GAP * 2.0);
+                let y = self.y - BLOCK_GAP;
+                self.rule((MARGIN, y), (PAGE_WIDTH - MARGIN, y));
+                self.gap(BLOCK_GAP * 2.0);
+            }
+        }
+    }
+
+    /// Wrapped text in `frame`.
+    fn paragraph(&mut self, runs: &[Run], size: f32, frame: Frame) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN - frame.indent;
+        for line in wrap(runs, size, width) {
+            self.line(&line, size, frame);
+        }
+    }
+
+    /// A list item, its marker hanging in `frame`'s indent.
+    fn list_item(&mut self, marker: &str, runs: &[Run], frame: Frame) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN - frame.indent;
+        for (i, line) in wrap(runs, BODY_SIZE, width).iter().enumerate() {
+            if i == 0 {
+                self.reserve(BODY_SIZE * LEADING * PT);
+                let baseline = self.y - BODY_SIZE * PT;
+                let x = MARGIN + frame.indent - INDENT;
+                let font = self.font(Style::Regular).clone();
+                self.layer.use_text(marker, BODY_SIZE, Mm(x), Mm(baseline), &font);
+            }
+            self.line(line, BODY_SIZE, frame);
+        }
+    }
+
+    /// A code block, monospaced, its lines broken wherever they run out of room.
+    fn code(&mut self, code: &str) {
+        let frame = Frame { indent: INDENT, bar: true, grey: false };
+        let width = PAGE_WIDTH - 2.0 * MARGIN - frame.indent;
+        let per_line = ((width / text_width("M", Style::Code, CODE_SIZE)) as usize).max(1);
+        for line in code.replace('\t', "    ").lines() {
+            let chars: Vec<char> = line.chars().collect();
+            // An empty line still takes up its height.
+            for chunk in chars.chunks(per_line).chain(chars.is_empty().then_some(&[][..])) {
+                let text: String = chunk.iter().collect();
+                self.line(&[(Style::Code, text)], CODE_SIZE, frame);
+            }
+        }
+    }
+
+    /// One line of text, already wrapped to fit.
+    fn line(&mut self, segments: &[(Style, String)], size: f32, frame: Frame) {
+        let height = size * LEADING * PT;
+        self.reserve(height);
+        let baseline = self.y - size * PT;
+        if frame.grey {
+            self.layer.set_fill_color(grey());
+        }
+        let mut x = MARGIN + frame.indent;
+        for (style, text) in segments {
+            let font = self.font(*style).clone();
+            self.layer.use_text(text.as_str(), size, Mm(x), Mm(baseline), &font);
+            x += text_width(text, *style, size);
+        }
+        if frame.grey {
+            self.layer.set_fill_color(black());
+        }
+        if frame.bar {
+            let x = MARGIN + frame.indent - INDENT / 2.0;
+            self.rule((x, self.y), (x, self.y - height));
+        }
+        self.y -= height;
+    }
+
+    fn rule(&self, from: (f32, f32), to: (f32, f32)) {
+        self.layer.set_outline_color(grey());
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(from.0), Mm(from.1)), false),
+                (Point::new(Mm(to.0), Mm(to.1)), false),
+            ],
+            is_closed: false,
+        });
+    }
+}
+
+fn grey() -> Color {
+    Color::Rgb(Rgb::new(0.4, 0.4, 0.4, None))
+}
+
+fn black() -> Color {
+    Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None))
+}
+
+/// Flattens inline markup into styled runs. Links keep their text, followed
+/// by the URL unless the text is the URL.
+fn runs(inlines: Vec<Inline>, style: Style) -> Vec<Run> {
+    let mut out = Vec::new();
+    push_runs(inlines, style, &mut out);
+    out
+}
+
+fn push_runs(inlines: Vec<Inline>, style: Style, out: &mut Vec<Run>) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => out.push(Run { text, style }),
+            Inline::Code(code) => out.push(Run { text: code, style: Style::Code }),
+            Inline::Strong(inner) => push_runs(inner, style.bold(), out),
+            Inline::Emphasis(inner) => push_runs(inner, style.italic(), out),
+            Inline::Link { text, url } => {
+                let bare = matches!(text.as_slice(), [Inline::Text(t)] if *t == url);
+                push_runs(text, style, out);
+                if !bare {
+                    out.push(Run { text: format!(" ({url})"), style });
+                }
+            }
+        }
+    }
+}
+
+/// Breaks `runs` into lines at most `width` millimetres wide: at spaces where
+/// possible, inside words longer than a whole line, and at every newline.
+fn wrap(runs: &[Run], size: f32, width: f32) -> Vec<Vec<(Style, String)>> {
+    let mut lines: Vec<Vec<(Style, String)>> = vec![Vec::new()];
+    let mut used = 0.0;
+    for run in runs {
+        for (i, part) in run.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Vec::new());
+                used = 0.0;
+            }
+            for word in part.split_inclusive(' ') {
+                let fits = used + text_width(word.trim_end(), run.style, size) <= width;
+                if !fits && used > 0.0 {
+                    lines.push(Vec::new());
+                    used = 0.0;
+                }
+                for piece in split_to_width(word, run.style, size, width - used, width) {
+                    if used > 0.0 && piece.as_ptr() != word.as_ptr() {
+                        lines.push(Vec::new());
+                        used = 0.0;
+                    }
+                    used += text_width(piece, run.style, size);
+                    let line = lines.last_mut().expect("there is always a line");
+                    match line.last_mut() {
+                        Some((style, text)) if *style == run.style => text.push_str(piece),
+                        _ => line.push((run.style, piece.to_string())),
+                    }
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Splits `word` so that its first piece is at most `first` millimetres wide
+/// and every other piece fits a line of `line` millimetres. Words that fit
+/// come back whole.
+fn split_to_width(word: &str, style: Style, size: f32, first: f32, line: f32) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let (mut start, mut used, mut room) = (0, 0.0, first);
+    for (i, c) in word.char_indices() {
+        let w = char_width(c, style) * size / 1000.0 * PT;
+        if used + w > room && i > start && c != ' ' {
+            pieces.push(&word[start..i]);
+            start = i;
+            used = 0.0;
+            room = line;
+        }
+        used += w;
+    }
+    pieces.push(&word[start..]);
+    pieces
+}
+
+/// Width in millimetres of `text` set in `style` at `size` points. Bold
+/// Helvetica is taken to be a little wider than regular.
+fn text_width(text: &str, style: Style, size: f32) -> f32 {
+    let em: f32 = text.chars().map(|c| char_width(c, style)).sum();
+    em * size / 1000.0 * PT
+}
+
+fn char_width(c: char, style: Style) -> f32 {
+    let regular = match c {
+        ' '..='~' => f32::from(HELVETICA_WIDTHS[c as usize - ' ' as usize]),
+        _ => 556.0,
+    };
+    match style {
+        Style::Code => 600.0,
+        Style::Bold | Style::BoldItalic => regular * 1.07,
+        Style::Regular | Style::Italic => regular,
+    }
+}
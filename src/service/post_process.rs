@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tracing::warn;
+
+use crate::config::AppConfig;
+use crate::models::Source;
+
+/// A complete assistant reply on its way to being saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub content: String,
+    pub sources: Vec<Source>,
+}
+
+/// One step of the pipeline run over every assistant reply before it is
+/// saved. Each step sees the reply as the previous one left it.
+pub trait PostProcessor: Send + Sync {
+    /// What `post_processors` in the configuration calls it.
+    fn name(&self) -> &'static str;
+
+    fn process(&self, reply: &mut Reply, config: &AppConfig);
+}
+
+/// The post-processors that can be enabled, by name. Which of them run, and
+/// in what order, is up to `post_processors` in the configuration.
+#[derive(Clone)]
+pub struct PostProcessorRegistry {
+    processors: HashMap<&'static str, Arc<dyn PostProcessor>>,
+}
+
+impl Default for PostProcessorRegistry {
+    /// The built-in processors: `whitespace`, `citations` and `profanity`.
+    fn default() -> Self {
+        Self { processors: HashMap::new() }
+            .with(Whitespace)
+            .with(Citations)
+            .with(Profanity)
+    }
+}
+
+impl PostProcessorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `processor`, replacing any registered under the same name.
+    pub fn with(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.processors.insert(processor.name(), Arc::new(processor));
+        self
+    }
+
+    /// Runs the processors `config.post_processors` names over `reply`, in
+    /// that order. Unknown names are skipped.
+    pub fn run(&self, reply: &mut Reply, config: &AppConfig) {
+        for name in &config.post_processors {
+            match self.processors.get(name.as_str()) {
+                Some(processor) => processor.process(reply, config),
+                None => warn!("Unknown post-processor '{name}' skipped"),
+            }
+        }
+    }
+}
+
+/// Strips trailing whitespace from lines, collapses runs of blank lines into
+/// one and trims the reply. Code blocks are left as they are.
+pub struct Whitespace;
+
+impl PostProcessor for Whitespace {
+    fn name(&self) -> &'static str {
+        "whitespace"
+    }
+
+    fn process(&self, reply: &mut Reply, _config: &AppConfig) {
+        let mut lines: Vec<&str> = Vec::new();
+        for (line, in_code) in markdown_lines(&reply.content) {
+            if in_code {
+                lines.push(line);
+                continue;
+            }
+            let line = line.trim_end();
+            if !(line.is_empty() && lines.last().is_some_and(|l| l.is_empty())) {
+                lines.push(line);
+            }
+        }
+        reply.content = lines.join("\n").trim().to_string();
+    }
+}
+
+/// Renumbers inline citations (`[3]`) in the order they first appear, and
+/// reorders the sources to match, so the first source cited is `[1]`.
+/// Sources the reply never cites keep their order after the cited ones.
+pub struct Citations;
+
+impl PostProcessor for Citations {
+    fn name(&self) -> &'static str {
+        "citations"
+    }
+
+    fn process(&self, reply: &mut Reply, _config: &AppConfig) {
+        let count = reply.sources.len();
+        let mut order: Vec<usize> = Vec::new();
+        for (line, in_code) in markdown_lines(&reply.content) {
+            if !in_code {
+                renumber_citations(line, count, |n| {
+                    if !order.contains(&n) {
+                        order.push(n);
+                    }
+                    n
+                });
+            }
+        }
+        if order.iter().enumerate().all(|(i, &n)| n == i + 1) {
+            return;
+        }
+
+        let lines: Vec<String> = markdown_lines(&reply.content)
+            .map(|(line, in_code)| {
+                if in_code {
+                    line.to_string()
+                } else {
+                    renumber_citations(line, count, |n| {
+                        order.iter().position(|&o| o == n).map_or(n, |i| i + 1)
+                    })
+                }
+            })
+            .collect();
+        reply.content = lines.join("\n");
+
+        let mut sources: Vec<Option<Source>> =
+            std::mem::take(&mut reply.sources).into_iter().map(Some).collect();
+        reply.sources = order.iter().filter_map(|&n| sources[n - 1].take()).collect();
+        reply.sources.extend(sources.into_iter().flatten());
+    }
+}
+
+/// Masks the words and phrases in `profanity_terms` (ignoring case) with
+/// asterisks wherever they appear as whole words.
+pub struct Profanity;
+
+impl PostProcessor for Profanity {
+    fn name(&self) -> &'static str {
+        "profanity"
+    }
+
+    fn process(&self, reply: &mut Reply, config: &AppConfig) {
+        let words: Vec<(usize, usize)> = word_spans(&reply.content);
+        let lowered: Vec<String> = words
+            .iter()
+            .map(|&(start, end)| reply.content[start..end].to_lowercase())
+            .collect();
+        let mut masked = vec![false; words.len()];
+        for term in &config.profanity_terms {
+            let term: Vec<String> = term
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            if term.is_empty() || term.len() > lowered.len() {
+                continue;
+            }
+            for start in 0..=lowered.len() - term.len() {
+                if lowered[start..start + term.len()] == term[..] {
+                    masked[start..start + term.len()].fill(true);
+                }
+            }
+        }
+        if !masked.contains(&true) {
+            return;
+        }
+
+        let mut content = String::with_capacity(reply.content.len());
+        let mut last = 0;
+        for (&(start, end), _) in words.iter().zip(&masked).filter(|(_, &m)| m) {
+            content.push_str(&reply.content[last..start]);
+            content.extend(reply.content[start..end].chars().map(|_| '*'));
+            last = end;
+        }
+        content.push_str(&reply.content[last..]);
+        reply.content = content;
+    }
+}
+
+/// The lines of `text`, each with whether it belongs to a fenced code block
+/// (fences included).
+fn markdown_lines(text: &str) -> impl Iterator<Item = (&str, bool)> {
+    let mut in_code = false;
+    text.split('\n').map(move |line| {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            return (line, true);
+        }
+        (line, in_code)
+    })
+}
+
+/// Rewrites each citation `[n]` in `line` (with `n` between 1 and `count`) as
+/// `[renumber(n)]`. Inline code and markdown links (`[1](url)`) are skipped.
+fn renumber_citations(
+    line: &str,
+    count: usize,
+    mut renumber: impl FnMut(usize) -> usize,
+) -> String {
+    let mut out = String::with_capacity(line.len());
+    for (i, segment) in line.split('`').enumerate() {
+        if i > 0 {
+            out.push('`');
+        }
+        if i % 2 == 1 {
+            out.push_str(segment);
+            continue;
+        }
+        let mut rest = segment;
+        while let Some(open) = rest.find('[') {
+            out.push_str(&rest[..open]);
+            rest = &rest[open..];
+            let citation = rest[1..].find(']').and_then(|close| {
+                let digits = &rest[1..=close];
+                if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                let n = digits.parse::<usize>().ok()?;
+                let is_link = rest[close + 2..].starts_with('(');
+                ((1..=count).contains(&n) && !is_link).then_some((n, close + 2))
+            });
+            match citation {
+                Some((n, len)) => {
+                    out.push_str(&format!("[{}]", renumber(n)));
+                    rest = &rest[len..];
+                }
+                None => {
+                    out.push('[');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Byte ranges of the alphanumeric runs in `text`.
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
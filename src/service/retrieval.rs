@@ -0,0 +1,60 @@
+//! Retrieval of indexed chunks for a turn. Chunks are ranked two ways, both
+//! in Postgres — by cosine similarity of their embeddings to the user's
+//! message, with pgvector, and by full-text match — and the rankings are
+//! fused by reciprocal rank, so a chunk both find comes first.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::models::RetrievedChunk;
+
+/// Constant of reciprocal-rank fusion: the larger it is, the less the first
+/// places of a ranking outweigh the next ones.
+const RRF_K: f32 = 60.0;
+
+/// How much each ranking counts in a fusion. 0 leaves a ranking out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    pub vector: f32,
+    pub text: f32,
+}
+
+/// Fuses the rankings `by_vector` and `by_text`, each best first: a chunk
+/// scores `weight / (RRF_K + rank)` for each ranking it is in. Returns the
+/// chunks best first, with their places in both rankings.
+pub fn fuse(
+    by_vector: Vec<RetrievedChunk>,
+    by_text: Vec<RetrievedChunk>,
+    weights: Weights,
+) -> Vec<RetrievedChunk> {
+    let mut fused: Vec<RetrievedChunk> = Vec::new();
+    let mut index: HashMap<Uuid, usize> = HashMap::new();
+    let rankings = [(by_vector, weights.vector, true), (by_text, weights.text, false)];
+    for (ranking, weight, is_vector) in rankings {
+        for (place, chunk) in ranking.into_iter().enumerate() {
+            let rank = place as i32 + 1;
+            let i = *index.entry(chunk.chunk_id).or_insert_with(|| {
+                fused.push(RetrievedChunk { score: 0.0, ..chunk });
+                fused.len() - 1
+            });
+            let hit = &mut fused[i];
+            hit.score += weight / (RRF_K + rank as f32);
+            if is_vector {
+                hit.vector_rank = Some(rank);
+            } else {
+                hit.text_rank = Some(rank);
+            }
+        }
+    }
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused
+}
+
+/// A `websearch_to_tsquery` query matching text with any of the words of
+/// `query`, rather than all of them.
+pub fn any_word(query: &str) -> String {
+    let words: Vec<&str> =
+        query.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+    words.join(" or ")
+}
@@ -0,0 +1,340 @@
+//! Slack integration: threads in Slack relayed to conversations.
+//!
+//! Mentioning the bot, messaging it directly, or its slash command starts a
+//! thread with a conversation of its own; later messages in the thread
+//! continue that conversation. Each reply is posted as soon as its turn
+//! starts, edited as it streams, and finally replaced by the saved message.
+
+use std::time::{Duration, Instant};
+
+use ring::hmac;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, warn, Instrument};
+use uuid::Uuid;
+
+use crate::agent::StreamUpdate;
+use crate::config::SlackConfig;
+use crate::db::slack_thread_repository::SlackThreadRepository;
+use crate::errors::AppError;
+use crate::models::{ChatRequest, CompletionStats};
+use crate::service::chat_service::ChatService;
+
+/// Slack allows about one `chat.update` a second per channel.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+/// Requests signed longer ago than this are refused, so captured ones cannot
+/// be replayed.
+const MAX_REQUEST_AGE_SECS: i64 = 5 * 60;
+/// How long a Web API call may take.
+const API_TIMEOUT: Duration = Duration::from_secs(10);
+/// Shown in place of a reply until its first words arrive.
+const PLACEHOLDER: &str = "_Thinking…_";
+
+/// Body of a request to `/slack/events`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SlackPayload {
+    /// Sent once when the events URL is set up; answered with `challenge`.
+    UrlVerification { challenge: String },
+    EventCallback {
+        event: SlackEvent,
+        /// The bot user, among others the event was delivered for.
+        #[serde(default)]
+        authorizations: Vec<SlackAuthorization>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// An `app_mention` or `message` event; other kinds are ignored.
+#[derive(Debug, Deserialize)]
+pub struct SlackEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub channel: Option<String>,
+    /// `im` for direct messages to the bot.
+    pub channel_type: Option<String>,
+    pub text: Option<String>,
+    pub ts: Option<String>,
+    /// First message of the thread, when the event is a reply in one.
+    pub thread_ts: Option<String>,
+    /// Set on messages posted by bots, the bot itself included.
+    pub bot_id: Option<String>,
+    /// Set on edits, joins and other messages that are not plain posts.
+    pub subtype: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlackAuthorization {
+    pub user_id: String,
+}
+
+/// Form body of a slash command.
+#[derive(Debug, Deserialize)]
+pub struct SlashCommand {
+    pub channel_id: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+/// Relays Slack threads to conversations and posts the replies back.
+#[derive(Clone)]
+pub struct SlackBot {
+    svc: ChatService,
+    threads: SlackThreadRepository,
+    http: reqwest::Client,
+}
+
+impl SlackBot {
+    pub fn new(svc: ChatService, threads: SlackThreadRepository) -> Self {
+        Self { svc, threads, http: reqwest::Client::new() }
+    }
+
+    /// The Slack settings, or `None` while the integration is off.
+    pub fn config(&self) -> Option<SlackConfig> {
+        self.svc.config().get().slack.clone()
+    }
+
+    /// Answers `event` if it asks something of the bot: a mention, a direct
+    /// message, or a reply in a thread the bot is answering in. `bot_user` is
+    /// the bot's own user id, whose mentions arrive as `app_mention` events
+    /// too and are only answered once.
+    pub async fn handle_event(
+        &self,
+        slack: &SlackConfig,
+        event: SlackEvent,
+        bot_user: Option<String>,
+    ) {
+        if event.bot_id.is_some() || event.subtype.is_some() {
+            return;
+        }
+        let (Some(channel), Some(ts), Some(text)) = (event.channel, event.ts, event.text) else {
+            return;
+        };
+        let thread_ts = event.thread_ts.unwrap_or(ts);
+        let conversation_id = match self.threads.find(&channel, &thread_ts).await {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to look up Slack thread: {e}");
+                return;
+            }
+        };
+        let relayed = match event.kind.as_str() {
+            "app_mention" => true,
+            "message" => {
+                let mentioned = bot_user.is_some_and(|u| text.contains(&format!("<@{u}>")));
+                let direct = event.channel_type.as_deref() == Some("im");
+                !mentioned && (direct || conversation_id.is_some())
+            }
+            _ => false,
+        };
+        if relayed {
+            self.relay(slack, &channel, &thread_ts, conversation_id, strip_mentions(&text)).await;
+        }
+    }
+
+    /// Posts the slash command's question to its channel and answers it in a
+    /// thread under it.
+    pub async fn handle_command(&self, slack: &SlackConfig, command: SlashCommand) {
+        let question = format!("<@{}>: {}", command.user_id, command.text);
+        match self.post_message(slack, &command.channel_id, None, &question).await {
+            Ok(ts) => self.relay(slack, &command.channel_id, &ts, None, command.text).await,
+            Err(e) => error!("Failed to post slash command to Slack: {e}"),
+        }
+    }
+
+    /// Runs a turn of `conversation_id` (a new one when `None`, which the
+    /// thread is then relayed to) and streams the reply into the thread.
+    #[instrument(name = "slack_turn", skip(self, slack, text))]
+    async fn relay(
+        &self,
+        slack: &SlackConfig,
+        channel: &str,
+        thread_ts: &str,
+        conversation_id: Option<Uuid>,
+        text: String,
+    ) {
+        let ts = match self.post_message(slack, channel, Some(thread_ts), PLACEHOLDER).await {
+            Ok(ts) => ts,
+            Err(e) => {
+                error!("Failed to post to Slack: {e}");
+                return;
+            }
+        };
+        let request = ChatRequest {
+            conversation_id,
+            message: text,
+            ephemeral: false,
+            verbosity: None,
+            stateless: None,
+            quoted_message_id: None,
+        };
+        let ctx = match self.svc.prepare_chat(request).await {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                warn!("Slack message was not answered: {e}");
+                self.edit(slack, channel, &ts, &format!("Could not answer: {e}")).await;
+                return;
+            }
+        };
+        if conversation_id.is_none() {
+            if let Err(e) = self.threads.save(channel, thread_ts, ctx.conversation_id).await {
+                error!("Failed to relay Slack thread: {e}");
+            }
+        }
+
+        let started = Instant::now();
+        let (tx, mut rx) = mpsc::channel::<StreamUpdate>(64);
+        let stream_svc = self.svc.clone();
+        let stream_ctx = ctx.clone();
+        let stream_handle = tokio::spawn(
+            async move { stream_svc.stream_reply(stream_ctx, tx).await }.in_current_span(),
+        );
+        let mut content = String::new();
+        let mut last_edit = Instant::now();
+        while let Some(update) = rx.recv().await {
+            if let StreamUpdate::Chunk(chunk) = update {
+                content.push_str(&chunk);
+                if last_edit.elapsed() >= UPDATE_INTERVAL {
+                    self.edit(slack, channel, &ts, &format!("{content} …")).await;
+                    last_edit = Instant::now();
+                }
+            }
+        }
+
+        let reply = match stream_handle.await {
+            Ok(Ok(outcome)) => {
+                let cost =
+                    self.svc.price(&outcome.model, outcome.prompt_tokens, outcome.completion_tokens);
+                let stats = CompletionStats {
+                    model: outcome.model,
+                    prompt_tokens: outcome.prompt_tokens,
+                    completion_tokens: outcome.completion_tokens,
+                    finish_reason: outcome.finish_reason,
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    cost,
+                };
+                if let Err(e) = self.svc.save_tool_messages(&outcome.tool_messages).await {
+                    error!("Failed to save tool messages: {e}");
+                }
+                match self.svc.save_assistant_message(&ctx, &content, outcome.sources, &stats).await
+                {
+                    Ok(msg) => {
+                        info!("Answered in Slack thread {channel}/{thread_ts}");
+                        msg.content
+                    }
+                    Err(e) => {
+                        error!("Failed to save assistant message: {e}");
+                        format!("Failed to save the reply: {e}")
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                warn!("Slack turn failed: {e}");
+                self.svc.record_failed_turn(ctx.conversation_id, &e).await;
+                format!("Could not answer: {e}")
+            }
+            Err(e) => {
+                error!("Agent task panicked: {e}");
+                let e = AppError::Unexpected("Internal error during streaming".to_string());
+                self.svc.record_failed_turn(ctx.conversation_id, &e).await;
+                format!("Could not answer: {e}")
+            }
+        };
+        self.edit(slack, channel, &ts, &reply).await;
+    }
+
+    /// Posts `text` to `channel`, in the thread of `thread_ts` if given, and
+    /// returns the new message's timestamp.
+    async fn post_message(
+        &self,
+        slack: &SlackConfig,
+        channel: &str,
+        thread_ts: Option<&str>,
+        text: &str,
+    ) -> Result<String, AppError> {
+        let body = json!({ "channel": channel, "thread_ts": thread_ts, "text": text });
+        let response = self.call(slack, "chat.postMessage", body).await?;
+        response["ts"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AppError::Unexpected("Slack chat.postMessage returned no ts".to_string()))
+    }
+
+    /// Replaces the text of the bot's message `ts`; failures are only logged,
+    /// as the next edit may well go through.
+    async fn edit(&self, slack: &SlackConfig, channel: &str, ts: &str, text: &str) {
+        let body = json!({ "channel": channel, "ts": ts, "text": text });
+        if let Err(e) = self.call(slack, "chat.update", body).await {
+            warn!("Failed to update Slack message: {e}");
+        }
+    }
+
+    async fn call(&self, slack: &SlackConfig, method: &str, body: Value) -> Result<Value, AppError> {
+        let failed = |e: String| AppError::Unexpected(format!("Slack {method} failed: {e}"));
+        let response: Value = self
+            .http
+            .post(format!("{}/{method}", slack.api_url.trim_end_matches('/')))
+            .timeout(API_TIMEOUT)
+            .bearer_auth(&slack.bot_token)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| failed(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        if response["ok"] != true {
+            return Err(failed(response["error"].as_str().unwrap_or("unknown error").to_string()));
+        }
+        Ok(response)
+    }
+}
+
+/// Whether `signature` (the `X-Slack-Signature` header, `v0=<hex>`) is the
+/// HMAC-SHA256 under `signing_secret` of `body` sent at `timestamp` (the
+/// `X-Slack-Request-Timestamp` header), and that was recent at `now`.
+pub fn verify_signature(
+    signing_secret: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: i64,
+) -> bool {
+    let Ok(sent_at) = timestamp.parse::<i64>() else { return false };
+    if (now - sent_at).abs() > MAX_REQUEST_AGE_SECS {
+        return false;
+    }
+    let Some(tag) = signature.strip_prefix("v0=").and_then(decode_hex) else { return false };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, signing_secret.as_bytes());
+    let signed = [format!("v0:{timestamp}:").as_bytes(), body].concat();
+    hmac::verify(&key, &signed, &tag).is_ok()
+}
+
+/// `text` without the `<@U123>` user mentions Slack writes into it.
+fn strip_mentions(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<@") {
+        stripped.push_str(&rest[..start]);
+        match rest[start..].find('>') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped.trim().to_string()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
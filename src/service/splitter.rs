@@ -0,0 +1,342 @@
+//! Splitting of documents into chunks for retrieval, by the [`Chunker`] of
+//! their collection. The structural chunkers follow the document's own
+//! shape: they break where a definition (or, in markdown, a heading) begins
+//! and group small neighbours up to a size limit, so a function is not cut
+//! in half when it fits.
+
+use std::ops::Range;
+
+use tree_sitter::{Language, Node, Parser};
+
+use crate::models::Chunker;
+
+/// Languages indexed for retrieval, by file extension. Files with any other
+/// extension are left out.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("jsx", "javascript"),
+    ("mjs", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("swift", "swift"),
+    ("scala", "scala"),
+    ("sh", "shell"),
+    ("sql", "sql"),
+    ("html", "html"),
+    ("css", "css"),
+    ("scss", "css"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("md", "markdown"),
+];
+
+/// Lines that close a definition rather than open one.
+const CLOSERS: &[&str] = &["}", ")", "]", "end"];
+
+/// Syntax nodes that belong to the definition after them.
+const LEADING_NODES: &[&str] = &[
+    "comment",
+    "line_comment",
+    "block_comment",
+    "attribute_item",
+    "inner_attribute_item",
+    "decorator",
+];
+
+/// Characters that count as one line when sizing chunks of sentences: prose
+/// often has a whole paragraph on a line.
+const LINE_WIDTH: usize = 100;
+
+/// A piece of a file: its text and the 1-based lines it spans, inclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// How documents are split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkSettings {
+    pub chunker: Chunker,
+    /// Most lines per chunk.
+    pub max_lines: usize,
+    /// Lines a `fixed` chunk repeats from the one before.
+    pub overlap: usize,
+}
+
+/// The language of `path`, from its extension; `None` when it is not one
+/// that is indexed.
+pub fn language(path: &str) -> Option<&'static str> {
+    let (_, extension) = path.rsplit('/').next()?.rsplit_once('.')?;
+    LANGUAGES
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(extension))
+        .map(|(_, language)| *language)
+}
+
+/// Splits `text`, written in `language`, as `settings` say. Blank sections
+/// are dropped.
+pub fn split(text: &str, language: &str, settings: ChunkSettings) -> Vec<Section> {
+    let lines: Vec<&str> = text.lines().collect();
+    let max_lines = settings.max_lines.max(1);
+    match settings.chunker {
+        Chunker::Auto if language == "markdown" => {
+            structured(&lines, headings(&lines), max_lines)
+        }
+        Chunker::Auto | Chunker::Code => {
+            let starts = symbols(text, language, max_lines).unwrap_or_else(|| {
+                (0..lines.len()).filter(|&i| is_boundary(&lines, i)).collect()
+            });
+            structured(&lines, starts, max_lines)
+        }
+        Chunker::Markdown => structured(&lines, headings(&lines), max_lines),
+        Chunker::Sentence => sentences(text, max_lines),
+        Chunker::Fixed => fixed(&lines, max_lines, settings.overlap),
+    }
+}
+
+/// Splits `lines` where a definition starts (at the 0-based `starts`),
+/// cutting definitions too long for one chunk and grouping neighbours that
+/// fit together.
+fn structured(lines: &[&str], mut starts: Vec<usize>, max_lines: usize) -> Vec<Section> {
+    starts.sort_unstable();
+    starts.dedup();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    starts.push(lines.len());
+    let definitions = starts.windows(2).map(|w| w[0]..w[1]);
+
+    // Definitions too long for one chunk are cut, preferably at a blank line.
+    let mut pieces = Vec::new();
+    for definition in definitions {
+        let mut start = definition.start;
+        while definition.end - start > max_lines {
+            let window = start + max_lines / 2..start + max_lines;
+            let cut = window
+                .rev()
+                .find(|&i| lines[i].trim().is_empty())
+                .map_or(start + max_lines, |i| i + 1);
+            pieces.push(start..cut);
+            start = cut;
+        }
+        pieces.push(start..definition.end);
+    }
+
+    // Neighbours are grouped as long as they fit together.
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    for piece in pieces {
+        match groups.last_mut() {
+            Some(last) if piece.end - last.start <= max_lines => last.end = piece.end,
+            _ => groups.push(piece),
+        }
+    }
+    sections(lines, groups)
+}
+
+/// The sections of `lines` in `ranges`, without their surrounding blank
+/// lines; blank ones are dropped.
+fn sections(lines: &[&str], ranges: Vec<Range<usize>>) -> Vec<Section> {
+    let blank = |i: &usize| lines[*i].trim().is_empty();
+    ranges
+        .into_iter()
+        .filter_map(|range| {
+            let start = range.clone().find(|i| !blank(i))?;
+            let end = range.rev().find(|i| !blank(i))?;
+            Some(Section {
+                content: lines[start..=end].join("\n"),
+                start_line: start + 1,
+                end_line: end + 1,
+            })
+        })
+        .collect()
+}
+
+/// Whether a definition starts at line `i`, judged without a parser: an
+/// unindented line after a blank one that does not close a block. Comments
+/// and attributes right above a definition start with it.
+fn is_boundary(lines: &[&str], i: usize) -> bool {
+    let line = lines[i];
+    let after_blank = i == 0 || lines[i - 1].trim().is_empty();
+    after_blank
+        && !line.trim().is_empty()
+        && !line.starts_with(char::is_whitespace)
+        && !CLOSERS.iter().any(|closer| line.trim_end().trim_end_matches(';') == *closer)
+}
+
+/// Lines where a markdown heading starts, skipping fenced code blocks, whose
+/// `#` lines are comments.
+fn headings(lines: &[&str]) -> Vec<usize> {
+    let mut in_fence = false;
+    let mut starts = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence && line.starts_with('#') {
+            starts.push(i);
+        }
+    }
+    starts
+}
+
+/// The tree-sitter grammar of `language`, if there is one.
+fn grammar(language: &str) -> Option<Language> {
+    let grammar = match language {
+        "rust" => tree_sitter_rust::LANGUAGE,
+        "python" => tree_sitter_python::LANGUAGE,
+        "javascript" => tree_sitter_javascript::LANGUAGE,
+        "typescript" => tree_sitter_typescript::LANGUAGE_TSX,
+        "go" => tree_sitter_go::LANGUAGE,
+        "java" => tree_sitter_java::LANGUAGE,
+        _ => return None,
+    };
+    Some(grammar.into())
+}
+
+/// Lines where a definition starts according to the syntax tree of `text`;
+/// `None` when there is no parser for `language`. Definitions longer than
+/// `max_lines` are broken down into their own definitions or statements.
+fn symbols(text: &str, language: &str, max_lines: usize) -> Option<Vec<usize>> {
+    let mut parser = Parser::new();
+    parser.set_language(&grammar(language)?).ok()?;
+    let tree = parser.parse(text, None)?;
+    let mut starts = Vec::new();
+    symbol_starts(tree.root_node(), max_lines, &mut starts);
+    Some(starts)
+}
+
+fn symbol_starts(node: Node, max_lines: usize, starts: &mut Vec<usize>) {
+    let mut cursor = node.walk();
+    // Comments and attributes go with the definition right below them.
+    let mut leading: Option<(usize, usize)> = None;
+    let mut previous_end = None;
+    for child in node.named_children(&mut cursor) {
+        let (start, end) = (child.start_position().row, child.end_position().row);
+        // Nodes starting on the line another ends on are not definitions of
+        // their own, like a comment after a statement.
+        let own_line = previous_end.is_none_or(|previous| start > previous);
+        previous_end = Some(end);
+        if LEADING_NODES.contains(&child.kind()) {
+            if own_line {
+                leading = match leading {
+                    Some((first, last)) if start <= last + 1 => Some((first, end)),
+                    Some((first, _)) => {
+                        starts.push(first);
+                        Some((start, end))
+                    }
+                    None => Some((start, end)),
+                };
+            }
+            continue;
+        }
+        match leading.take() {
+            Some((first, last)) if last + 1 >= start => starts.push(first),
+            Some((first, _)) => starts.extend([first, start]),
+            None if own_line => starts.push(start),
+            None => {}
+        }
+        if end - start + 1 > max_lines {
+            symbol_starts(child, max_lines, starts);
+        }
+    }
+    if let Some((first, _)) = leading {
+        starts.push(first);
+    }
+}
+
+/// Whole sentences grouped up to `max_lines` lines, a line being at most
+/// [`LINE_WIDTH`] characters. A sentence longer than that is a chunk of its
+/// own. Sentences end at `.`, `!` or `?` followed by a space, and at blank
+/// lines.
+fn sentences(text: &str, max_lines: usize) -> Vec<Section> {
+    let bytes = text.as_bytes();
+    let mut ends = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if matches!(bytes[i], b'.' | b'!' | b'?') {
+            let mut j = i + 1;
+            // Quotes and parentheses closed after the stop end with it.
+            while j < bytes.len() && b".!?\"')".contains(&bytes[j]) {
+                j += 1;
+            }
+            if j == bytes.len() || bytes[j].is_ascii_whitespace() {
+                ends.push(j);
+            }
+            i = j;
+            continue;
+        }
+        let blank_next = || text[i + 1..].lines().next().is_some_and(|l| l.trim().is_empty());
+        if bytes[i] == b'\n' && blank_next() {
+            ends.push(i);
+        }
+        i += 1;
+    }
+    ends.push(bytes.len());
+
+    let size = |range: Range<usize>| -> usize {
+        text[range].trim().lines().map(|line| line.len().div_ceil(LINE_WIDTH).max(1)).sum()
+    };
+    let mut groups: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+    for end in ends {
+        if text[start..end].trim().is_empty() {
+            continue;
+        }
+        match groups.last_mut() {
+            Some(last) if size(last.start..end) <= max_lines => last.end = end,
+            _ => groups.push(start..end),
+        }
+        start = end;
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|range| {
+            let piece = &text[range.clone()];
+            let content = piece.trim();
+            if content.is_empty() {
+                return None;
+            }
+            let offset = range.start + (piece.len() - piece.trim_start().len());
+            let line_at = |byte: usize| text[..byte].matches('\n').count() + 1;
+            Some(Section {
+                content: content.to_string(),
+                start_line: line_at(offset),
+                end_line: line_at(offset + content.len()),
+            })
+        })
+        .collect()
+}
+
+/// Every `max_lines` lines, each window starting `overlap` lines before the
+/// previous one ended.
+fn fixed(lines: &[&str], max_lines: usize, overlap: usize) -> Vec<Section> {
+    let overlap = overlap.min(max_lines - 1);
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = (start + max_lines).min(lines.len());
+        windows.push(start..end);
+        if end == lines.len() {
+            break;
+        }
+        start = end - overlap;
+    }
+    sections(lines, windows)
+}
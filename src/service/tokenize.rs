@@ -0,0 +1,55 @@
+//! Token counts with tiktoken vocabularies, for budgeting a turn against the
+//! model's context window.
+//!
+//! OpenAI model names map to their own vocabulary. Anything else, such as
+//! Ollama's Llama models, is split with `cl100k_base`: Llama 3's vocabulary
+//! extends it, so counts come out close without shipping every model's
+//! tokenizer.
+
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+use crate::models::{TokenSpan, TokenizeResponse};
+
+/// The vocabulary for `model`, its name, and whether it is the model's own.
+fn encoding_for(model: &str) -> (&'static CoreBPE, &'static str, bool) {
+    match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => (tiktoken_rs::o200k_base_singleton(), "o200k_base", true),
+        Some(Tokenizer::Cl100kBase) => (tiktoken_rs::cl100k_base_singleton(), "cl100k_base", true),
+        Some(Tokenizer::P50kBase) => (tiktoken_rs::p50k_base_singleton(), "p50k_base", true),
+        Some(Tokenizer::P50kEdit) => (tiktoken_rs::p50k_edit_singleton(), "p50k_edit", true),
+        Some(Tokenizer::R50kBase | Tokenizer::Gpt2) => {
+            (tiktoken_rs::r50k_base_singleton(), "r50k_base", true)
+        }
+        None => (tiktoken_rs::cl100k_base_singleton(), "cl100k_base", false),
+    }
+}
+
+/// Splits `text` into tokens for `model`. Special-token markup in `text` is
+/// counted as plain text. CPU-bound for long texts.
+pub fn tokenize(model: &str, text: &str, boundaries: bool) -> TokenizeResponse {
+    let (bpe, encoding, exact) = encoding_for(model);
+    let ranks = bpe.encode_ordinary(text);
+    let count = ranks.len();
+    let tokens = boundaries.then(|| {
+        let mut start = 0;
+        bpe._decode_native_and_split(ranks)
+            .map(|bytes| {
+                let span = TokenSpan {
+                    text: String::from_utf8_lossy(&bytes).into_owned(),
+                    start,
+                    end: start + bytes.len(),
+                };
+                start = span.end;
+                span
+            })
+            .collect()
+    });
+    TokenizeResponse {
+        model: model.to_string(),
+        encoding: encoding.to_string(),
+        exact,
+        count,
+        tokens,
+    }
+}
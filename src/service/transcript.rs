@@ -0,0 +1,136 @@
+//! Optional on-disk log of completed turns: one JSONL file per conversation
+//! under `transcript_dir`, for building evaluation datasets from real usage.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::ConfigStore;
+use crate::errors::AppError;
+use crate::models::{CompletionStats, Source};
+
+/// One line of a transcript file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub timestamp: DateTime<Utc>,
+    pub conversation_id: Uuid,
+    pub message_id: Uuid,
+    pub user: String,
+    pub assistant: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
+    /// Only streamed turns report stats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<CompletionStats>,
+}
+
+/// Appends turns to per-conversation files and rotates them by size.
+#[derive(Clone)]
+pub struct TranscriptLogger {
+    config: ConfigStore,
+    /// Serializes appends so rotation never races a write.
+    lock: Arc<Mutex<()>>,
+}
+
+impl TranscriptLogger {
+    pub fn new(config: ConfigStore) -> Self {
+        Self { config, lock: Arc::new(Mutex::new(())) }
+    }
+
+    /// Appends `entry` to its conversation's file. Does nothing while
+    /// `transcript_dir` is unset.
+    pub async fn append(&self, entry: &TranscriptEntry) -> Result<(), AppError> {
+        let config = self.config.get();
+        let Some(dir) = config.transcript_dir.clone() else {
+            return Ok(());
+        };
+        let (max_bytes, max_files) = (config.transcript_max_bytes, config.transcript_max_files);
+        let path = transcript_path(&dir, entry.conversation_id);
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| AppError::Unexpected(format!("Failed to serialize transcript: {e}")))?;
+        line.push(b'\n');
+
+        let lock = self.lock.clone();
+        tokio::task::spawn_blocking(move || {
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            fs::create_dir_all(&dir)?;
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if size > 0 && size + line.len() as u64 > max_bytes {
+                rotate(&path, max_files)?;
+            }
+            OpenOptions::new().create(true).append(true).open(&path)?.write_all(&line)
+        })
+        .await
+        .map_err(|e| AppError::Unexpected(format!("Transcript task failed: {e}")))?
+        .map_err(|e| AppError::Unexpected(format!("Failed to write transcript: {e}")))
+    }
+
+    /// The last `limit` entries logged for a conversation, oldest first.
+    /// Rotated files are not read.
+    pub async fn tail(
+        &self,
+        conversation_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<TranscriptEntry>, AppError> {
+        let Some(dir) = self.config.get().transcript_dir.clone() else {
+            return Err(AppError::InvalidField {
+                field_name: "transcript_dir".to_string(),
+                message: "transcript logging is disabled".to_string(),
+            });
+        };
+        let path = transcript_path(&dir, conversation_id);
+        let raw = match tokio::fs::read_to_string(&path).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(AppError::RecordNotFound {
+                    entity_type: "transcript".to_string(),
+                    id: conversation_id.to_string(),
+                })
+            }
+            Err(e) => {
+                return Err(AppError::Unexpected(format!("Failed to read transcript: {e}")))
+            }
+        };
+
+        let lines: Vec<&str> = raw.lines().filter(|l| !l.trim().is_empty()).collect();
+        lines[lines.len().saturating_sub(limit)..]
+            .iter()
+            .map(|line| {
+                serde_json::from_str(line).map_err(|e| {
+                    let path = path.display();
+                    AppError::Unexpected(format!("Corrupt transcript line in {path}: {e}"))
+                })
+            })
+            .collect()
+    }
+}
+
+/// `<dir>/<conversation_id>.jsonl`.
+fn transcript_path(dir: &Path, conversation_id: Uuid) -> PathBuf {
+    dir.join(format!("{conversation_id}.jsonl"))
+}
+
+/// Shifts `file.jsonl` to `file.jsonl.1`, `.1` to `.2` and so on, dropping
+/// whatever would land beyond `max_files`.
+fn rotate(path: &Path, max_files: usize) -> io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+    match fs::remove_file(numbered(max_files)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for n in (1..max_files).rev() {
+        match fs::rename(numbered(n), numbered(n + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::rename(path, numbered(1))
+}
@@ -0,0 +1,150 @@
+//! Conversation webhooks: a signed JSON `POST` for every assistant message.
+//!
+//! Each payload carries `X-Webhook-Signature: sha256=<hex>`, the HMAC-SHA256
+//! of the raw body under the webhook's secret, so receivers can check it came
+//! from this server.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::json;
+use tracing::{error, info, warn, Instrument};
+use uuid::Uuid;
+
+use crate::db::conversation_webhook_repository::ConversationWebhookRepository;
+use crate::errors::AppError;
+use crate::models::{ConversationWebhook, Message, NotificationStatus, WebhookDelivery};
+
+/// Header holding the signature of a payload.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Event name of every payload.
+const MESSAGE_CREATED: &str = "message.created";
+/// Deliveries tried per message before it is given up as failed.
+const DELIVERY_ATTEMPTS: i32 = 3;
+/// Wait before the second attempt; doubled before each one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How long a webhook may take to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Random bytes in a webhook secret.
+const SECRET_LEN: usize = 32;
+
+/// Sends the assistant messages of conversations to their webhooks, logging
+/// every delivery.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    http: reqwest::Client,
+    repo: ConversationWebhookRepository,
+}
+
+impl WebhookDispatcher {
+    pub fn new(repo: ConversationWebhookRepository) -> Self {
+        Self { http: reqwest::Client::new(), repo }
+    }
+
+    /// Sends `message` to every webhook of its conversation. Delivery, and its
+    /// retries, happen in the background.
+    pub async fn message_created(&self, message: &Message) {
+        let webhooks = match self.repo.find_all(Some(message.conversation_id)).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                error!("Failed to load the webhooks of {}: {e}", message.conversation_id);
+                return;
+            }
+        };
+        for webhook in webhooks {
+            let delivery = WebhookDelivery {
+                id: Uuid::new_v4(),
+                webhook_id: webhook.id,
+                message_id: message.id,
+                status: NotificationStatus::Pending,
+                attempts: 0,
+                last_error: None,
+                created_at: Utc::now(),
+                delivered_at: None,
+            };
+            if let Err(e) = self.repo.save_delivery(&delivery).await {
+                error!("Failed to log webhook delivery: {e}");
+                continue;
+            }
+            let body = json!({
+                "id": delivery.id,
+                "event": MESSAGE_CREATED,
+                "conversation_id": message.conversation_id,
+                "message": message,
+                "created_at": delivery.created_at,
+            })
+            .to_string();
+            let dispatcher = self.clone();
+            tokio::spawn(
+                async move { dispatcher.deliver(webhook, delivery.id, body).await }
+                    .in_current_span(),
+            );
+        }
+    }
+
+    /// Tries to deliver `body` until it goes through or runs out of attempts,
+    /// recording each attempt.
+    async fn deliver(&self, webhook: ConversationWebhook, delivery_id: Uuid, body: String) {
+        let signature = sign(&webhook.secret, &body);
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=DELIVERY_ATTEMPTS {
+            let outcome = self.post(&webhook.url, &signature, body.clone()).await;
+            let status = match &outcome {
+                Ok(()) => NotificationStatus::Delivered,
+                Err(_) if attempt == DELIVERY_ATTEMPTS => NotificationStatus::Failed,
+                Err(_) => NotificationStatus::Pending,
+            };
+            let error = outcome.as_ref().err().map(String::as_str);
+            if let Err(e) = self.repo.record_attempt(delivery_id, status, error).await {
+                error!("Failed to record webhook delivery {delivery_id}: {e}");
+            }
+            match outcome {
+                Ok(()) => {
+                    info!("Delivered message webhook {delivery_id} to {}", webhook.url);
+                    return;
+                }
+                Err(e) => warn!("Attempt {attempt} to call webhook {} failed: {e}", webhook.url),
+            }
+            if attempt < DELIVERY_ATTEMPTS {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+
+    async fn post(&self, url: &str, signature: &str, body: String) -> Result<(), String> {
+        self.http
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The signature header value of `body`: `sha256=` and the hex HMAC-SHA256
+/// of it under `secret`.
+pub fn sign(secret: &str, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!("sha256={}", hex(hmac::sign(&key, body.as_bytes()).as_ref()))
+}
+
+/// A random hex secret for a newly registered webhook.
+pub fn new_secret() -> Result<String, AppError> {
+    let mut secret = [0u8; SECRET_LEN];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .map_err(|_| AppError::Unexpected("Failed to generate a webhook secret".to_string()))?;
+    Ok(hex(&secret))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
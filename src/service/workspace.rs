@@ -0,0 +1,76 @@
+//! The workspace: a directory on the server, set by `workspace_dir`, that code
+//! artifacts can be written into. Every path is relative to it and may not
+//! lead outside, whether through `..`, an absolute path or a symlink.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::errors::AppError;
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Where `relative` is on disk. The file itself need not exist, but
+    /// whatever part of the path does must resolve inside the workspace.
+    pub async fn resolve(&self, relative: &str) -> Result<PathBuf, AppError> {
+        let outside = || AppError::InvalidField {
+            field_name: "path".to_string(),
+            message: "must be a relative path inside the workspace".to_string(),
+        };
+        if relative.trim().is_empty() {
+            return Err(AppError::EmptyField { field_name: "path".to_string() });
+        }
+        let relative = Path::new(relative);
+        if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(outside());
+        }
+
+        let root = tokio::fs::canonicalize(&self.root).await.map_err(|e| {
+            AppError::Unexpected(format!(
+                "Workspace directory {} is unavailable: {e}",
+                self.root.display()
+            ))
+        })?;
+        let path = root.join(relative);
+        // The deepest part of the path that exists, symlinks included (even
+        // dangling ones), must lead back inside the root.
+        let mut existing = path.as_path();
+        while tokio::fs::symlink_metadata(existing).await.is_err() {
+            existing = existing.parent().unwrap_or(&root);
+        }
+        match tokio::fs::canonicalize(existing).await {
+            Ok(real) if real.starts_with(&root) => Ok(path),
+            _ => Err(outside()),
+        }
+    }
+
+    /// The text of a file in the workspace, `None` if there is no such file.
+    pub async fn read(&self, relative: &str) -> Result<Option<String>, AppError> {
+        let path = self.resolve(relative).await?;
+        match tokio::fs::read_to_string(&path).await {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(AppError::Unexpected(format!("Failed to read {relative}: {e}"))),
+        }
+    }
+
+    /// Writes a file in the workspace, creating its directories as needed.
+    pub async fn write(&self, relative: &str, content: &str) -> Result<(), AppError> {
+        let path = self.resolve(relative).await?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Unexpected(format!("Failed to create {relative}: {e}")))?;
+        }
+        tokio::fs::write(&path, content)
+            .await
+            .map_err(|e| AppError::Unexpected(format!("Failed to write {relative}: {e}")))
+    }
+}
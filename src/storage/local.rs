@@ -0,0 +1,92 @@
+//! Blobs as files under a local directory.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::errors::AppError;
+use crate::storage::{not_found, validate_key, BlobStore, BlobStream};
+
+/// Stores each blob at `<root>/<key>`. Cannot sign URLs, so downloads are
+/// streamed through the server.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, AppError> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+fn io_error(action: &str, key: &str, e: io::Error) -> AppError {
+    AppError::Unexpected(format!("Failed to {action} blob '{key}': {e}"))
+}
+
+impl BlobStore for LocalBlobStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        mut data: BlobStream,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await.map_err(|e| io_error("store", key, e))?;
+            }
+            // Written next to the target and renamed into place when complete.
+            let partial = path.with_extension(format!("partial-{}", uuid::Uuid::new_v4()));
+            let written = async {
+                let mut file = tokio::fs::File::create(&partial).await?;
+                while let Some(chunk) = data.next().await {
+                    file.write_all(&chunk?).await?;
+                }
+                file.flush().await?;
+                tokio::fs::rename(&partial, &path).await
+            };
+            if let Err(e) = written.await {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(io_error("store", key, e));
+            }
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<BlobStream, AppError>> {
+        Box::pin(async move {
+            let file = match tokio::fs::File::open(self.path(key)?).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(not_found(key)),
+                Err(e) => return Err(io_error("read", key, e)),
+            };
+            Ok(ReaderStream::new(file).boxed())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)?).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error("delete", key, e)),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn signed_url<'a>(
+        &'a self,
+        _key: &'a str,
+        _expires_in: Duration,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(async { Ok(None) })
+    }
+}
@@ -0,0 +1,77 @@
+//! Storage for binary files too large for Postgres, such as data exports:
+//! a local directory or an S3-compatible bucket, picked by `blob_store` in
+//! the configuration.
+
+pub mod local;
+pub mod s3;
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+
+use crate::config::BlobStoreConfig;
+use crate::errors::AppError;
+use crate::storage::local::LocalBlobStore;
+use crate::storage::s3::S3BlobStore;
+
+/// Contents of a blob, read or written a chunk at a time.
+pub type BlobStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Keyed blob storage. Keys are `/`-separated paths such as
+/// `exports/<id>.zip`; see [`validate_key`].
+pub trait BlobStore: Send + Sync {
+    /// Writes `data` under `key`, replacing any previous blob. Readers never
+    /// see a partly written blob.
+    fn put<'a>(&'a self, key: &'a str, data: BlobStream) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Streams the blob stored under `key`.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<BlobStream, AppError>>;
+
+    /// Removes `key`. Removing a missing blob is not an error.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// A URL the client can download `key` from directly until `expires_in`
+    /// passes, or `None` when the store cannot sign URLs and the blob must be
+    /// streamed through the server instead.
+    fn signed_url<'a>(
+        &'a self,
+        key: &'a str,
+        expires_in: Duration,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>>;
+}
+
+/// Opens the store `config` describes.
+pub fn from_config(config: &BlobStoreConfig) -> Result<Arc<dyn BlobStore>, AppError> {
+    Ok(match config {
+        BlobStoreConfig::Local { path } => Arc::new(LocalBlobStore::new(path.clone())),
+        BlobStoreConfig::S3 { .. } => Arc::new(S3BlobStore::new(config)?),
+    })
+}
+
+/// Keys are relative paths of plain segments (letters, digits, `.`, `_`,
+/// `-`); nothing else could be mapped safely onto a local directory.
+pub fn validate_key(key: &str) -> Result<(), AppError> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidField {
+            field_name: "key".to_string(),
+            message: format!("'{key}' is not a valid blob key"),
+        })
+    }
+}
+
+fn not_found(key: &str) -> AppError {
+    AppError::RecordNotFound { entity_type: "blob".to_string(), id: key.to_string() }
+}
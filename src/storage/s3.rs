@@ -0,0 +1,119 @@
+//! Blobs in an S3-compatible bucket (AWS, MinIO, R2, ...) via `object_store`.
+
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use futures_util::{StreamExt, TryStreamExt};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path;
+use object_store::signer::Signer;
+use object_store::{ObjectStoreExt, WriteMultipart};
+use reqwest::Method;
+
+use crate::config::BlobStoreConfig;
+use crate::errors::AppError;
+use crate::storage::{not_found, validate_key, BlobStore, BlobStream};
+
+/// Uploads run as multipart uploads, so a blob appears only once complete.
+/// Credentials come from the usual `AWS_*` environment variables.
+pub struct S3BlobStore {
+    bucket: AmazonS3,
+}
+
+impl S3BlobStore {
+    pub fn new(config: &BlobStoreConfig) -> Result<Self, AppError> {
+        let BlobStoreConfig::S3 { bucket, endpoint, region, allow_http } = config else {
+            return Err(AppError::InvalidConfig {
+                message: "blob_store: not an S3 configuration".to_string(),
+            });
+        };
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_allow_http(*allow_http);
+        if let Some(endpoint) = endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+        if let Some(region) = region {
+            builder = builder.with_region(region);
+        }
+        let bucket = builder
+            .build()
+            .map_err(|e| AppError::InvalidConfig { message: format!("blob_store: {e}") })?;
+        Ok(Self { bucket })
+    }
+
+    fn path(key: &str) -> Result<Path, AppError> {
+        validate_key(key)?;
+        Ok(Path::from(key))
+    }
+}
+
+fn s3_error(action: &str, key: &str, e: object_store::Error) -> AppError {
+    match e {
+        object_store::Error::NotFound { .. } => not_found(key),
+        e => AppError::Unexpected(format!("Failed to {action} blob '{key}': {e}")),
+    }
+}
+
+impl BlobStore for S3BlobStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        mut data: BlobStream,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let path = Self::path(key)?;
+            let upload = self
+                .bucket
+                .put_multipart(&path)
+                .await
+                .map_err(|e| s3_error("store", key, e))?;
+            let mut writer = WriteMultipart::new(upload);
+            while let Some(chunk) = data.next().await {
+                match chunk {
+                    Ok(chunk) => writer.put(chunk),
+                    Err(e) => {
+                        let _ = writer.abort().await;
+                        return Err(AppError::Unexpected(format!(
+                            "Failed to store blob '{key}': {e}"
+                        )));
+                    }
+                }
+            }
+            writer.finish().await.map_err(|e| s3_error("store", key, e))?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<BlobStream, AppError>> {
+        Box::pin(async move {
+            let result =
+                self.bucket.get(&Self::path(key)?).await.map_err(|e| s3_error("read", key, e))?;
+            Ok(result.into_stream().map_err(std::io::Error::from).boxed())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            match self.bucket.delete(&Self::path(key)?).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                Err(e) => Err(s3_error("delete", key, e)),
+            }
+        })
+    }
+
+    fn signed_url<'a>(
+        &'a self,
+        key: &'a str,
+        expires_in: Duration,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(async move {
+            let url = self
+                .bucket
+                .signed_url(Method::GET, &Self::path(key)?, expires_in)
+                .await
+                .map_err(|e| s3_error("sign", key, e))?;
+            Ok(Some(url.to_string()))
+        })
+    }
+}
@@ -0,0 +1,99 @@
+//! Exports `tracing` spans as OpenTelemetry traces over OTLP/HTTP (JSON
+//! encoding), which Jaeger, Grafana Tempo and the OpenTelemetry Collector all
+//! accept on their OTLP HTTP port (usually 4318).
+//!
+//! Enabled by the standard environment variables, which the exporter reads:
+//!
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT` — collector base URL; spans go to `/v1/traces`
+//! - `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` — full traces URL, overriding the above
+//! - `OTEL_EXPORTER_OTLP_HEADERS` — extra headers, `key=value,key2=value2`
+//! - `OTEL_SERVICE_NAME` — defaults to `rust_ai_experiments`
+//!
+//! Spans are exported only if they pass the `log_filter`. Spans that close
+//! while the export queue is full are dropped.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::trace::{BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::errors::AppError;
+
+const DEFAULT_SERVICE_NAME: &str = "rust_ai_experiments";
+const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(2);
+/// The HTTP client's own spans would be exported by the export requests
+/// they describe, and so on forever.
+const IGNORED_TARGETS: [&str; 4] = ["reqwest", "hyper", "h2", "rustls"];
+
+/// Where and how to send traces.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Full URL of the collector's traces endpoint. Unset, the exporter
+    /// takes it from the `OTEL_EXPORTER_OTLP_*` variables.
+    pub traces_endpoint: Option<String>,
+    pub service_name: String,
+    /// Sent besides those in `OTEL_EXPORTER_OTLP_HEADERS`.
+    pub headers: HashMap<String, String>,
+    /// How long closed spans may wait before a batch is sent.
+    pub export_interval: Duration,
+}
+
+impl OtlpConfig {
+    /// Reads the `OTEL_*` variables; `None` when no endpoint is set.
+    pub fn from_env() -> Option<Self> {
+        let set = |name| std::env::var(name).is_ok_and(|url| !url.trim().is_empty());
+        if !set("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") && !set("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            return None;
+        }
+        Some(Self {
+            traces_endpoint: None,
+            service_name: std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string()),
+            headers: HashMap::new(),
+            export_interval: DEFAULT_EXPORT_INTERVAL,
+        })
+    }
+
+    /// A tracer provider that batches finished spans and sends them to the
+    /// collector from a thread of its own.
+    pub fn tracer_provider(&self) -> Result<SdkTracerProvider, AppError> {
+        let mut exporter = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_headers(self.headers.clone());
+        if let Some(endpoint) = &self.traces_endpoint {
+            exporter = exporter.with_endpoint(endpoint);
+        }
+        let exporter = exporter.build().map_err(|e| AppError::InvalidConfig {
+            message: format!("Failed to set up the OTLP exporter: {e}"),
+        })?;
+        let batches = BatchConfigBuilder::default()
+            .with_scheduled_delay(self.export_interval)
+            .build();
+        Ok(SdkTracerProvider::builder()
+            .with_resource(Resource::builder().with_service_name(self.service_name.clone()).build())
+            .with_span_processor(
+                BatchSpanProcessor::builder(exporter).with_batch_config(batches).build(),
+            )
+            .build())
+    }
+}
+
+/// A `tracing` layer recording spans into `provider`'s traces.
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+        .with_filter(filter_fn(|metadata| {
+            !IGNORED_TARGETS.iter().any(|t| metadata.target().starts_with(t))
+        }))
+}
@@ -0,0 +1,113 @@
+pub mod web_search;
+pub mod webhook;
+pub mod workspace;
+
+use std::sync::{Arc, Mutex};
+
+use rig::tool::{Tool, ToolDyn};
+use uuid::Uuid;
+
+use crate::models::{Source, WebhookTool};
+use crate::tools::web_search::{SearchProvider, WebSearchTool};
+use crate::tools::webhook::WebhookCaller;
+use crate::tools::workspace::{ListFilesTool, ReadFileTool, WorkspaceAccess};
+
+/// Names webhook tools may not take.
+pub const BUILTIN_TOOL_NAMES: [&str; 3] =
+    [WebSearchTool::NAME, ListFilesTool::NAME, ReadFileTool::NAME];
+
+/// Collects the sources returned by tools during a single agent run.
+///
+/// Sources are de-duplicated by URL; the 1-based position of a source in the
+/// collector is the footnote number the model is told to cite.
+#[derive(Clone, Default)]
+pub struct SourceCollector {
+    sources: Arc<Mutex<Vec<Source>>>,
+}
+
+impl SourceCollector {
+    /// Records `source` and returns its footnote number.
+    pub fn add(&self, source: Source) -> usize {
+        let mut sources = self.sources.lock().expect("source collector poisoned");
+        if let Some(pos) = sources.iter().position(|s| s.url == source.url) {
+            return pos + 1;
+        }
+        sources.push(source);
+        sources.len()
+    }
+
+    /// Returns everything collected so far, leaving the collector empty.
+    pub fn take(&self) -> Vec<Source> {
+        std::mem::take(&mut *self.sources.lock().expect("source collector poisoned"))
+    }
+}
+
+/// The set of built-in tools the agent may call.
+///
+/// Tools are instantiated per request by [`ToolRegistry::build`] so each run
+/// reports into its own [`SourceCollector`], alongside the turn's webhook tools.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    http: reqwest::Client,
+    web_search: Option<SearchProvider>,
+    workspace: Option<WorkspaceAccess>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables the `web_search` tool backed by `provider`.
+    pub fn with_web_search(mut self, provider: SearchProvider) -> Self {
+        self.web_search = Some(provider);
+        self
+    }
+
+    /// Enables the `list_files` and `read_file` tools over a workspace.
+    pub fn with_workspace(mut self, access: WorkspaceAccess) -> Self {
+        self.workspace = Some(access);
+        self
+    }
+
+    pub fn has_web_search(&self) -> bool {
+        self.web_search.is_some()
+    }
+
+    pub fn has_workspace(&self) -> bool {
+        self.workspace.is_some()
+    }
+
+    /// Names the enabled tools are called by.
+    pub fn names(&self) -> Vec<String> {
+        let web_search = self.web_search.iter().map(|_| WebSearchTool::NAME);
+        let workspace = self.workspace.iter().flat_map(|_| [ListFilesTool::NAME, ReadFileTool::NAME]);
+        web_search.chain(workspace).map(str::to_string).collect()
+    }
+
+    /// Builds fresh tool instances for one agent run of `conversation_id`,
+    /// including a caller for each of `webhooks`.
+    pub fn build(
+        &self,
+        conversation_id: Uuid,
+        sources: &SourceCollector,
+        webhooks: &[WebhookTool],
+    ) -> Vec<Box<dyn ToolDyn>> {
+        let mut tools: Vec<Box<dyn ToolDyn>> = Vec::new();
+        if let Some(provider) = &self.web_search {
+            tools.push(Box::new(WebSearchTool::new(
+                self.http.clone(),
+                provider.clone(),
+                sources.clone(),
+            )));
+        }
+        if let Some(access) = &self.workspace {
+            tools.push(Box::new(ListFilesTool::new(access.clone())));
+            tools.push(Box::new(ReadFileTool::new(access.clone(), conversation_id)));
+        }
+        for webhook in webhooks {
+            tools.push(Box::new(WebhookCaller::new(self.http.clone(), webhook.clone())));
+        }
+        tools
+    }
+}
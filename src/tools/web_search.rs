@@ -0,0 +1,196 @@
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::errors::AppError;
+use crate::models::Source;
+use crate::tools::SourceCollector;
+
+const MAX_RESULTS: usize = 5;
+const BRAVE_SEARCH_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+
+/// Backend used by the `web_search` tool.
+#[derive(Debug, Clone)]
+pub enum SearchProvider {
+    /// A self-hosted SearxNG instance with the JSON output format enabled.
+    SearxNg { base_url: String },
+    /// The Brave Search API.
+    Brave { api_key: String },
+}
+
+impl SearchProvider {
+    /// Reads `SEARCH_PROVIDER` (`searxng` or `brave`) plus the matching
+    /// `SEARXNG_BASE_URL` / `BRAVE_SEARCH_API_KEY`. Returns `None` when web
+    /// search is not configured.
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("SEARCH_PROVIDER").ok()?.to_lowercase().as_str() {
+            "searxng" => Some(SearchProvider::SearxNg {
+                base_url: std::env::var("SEARXNG_BASE_URL")
+                    .unwrap_or_else(|_| "http://localhost:8888".to_string()),
+            }),
+            "brave" => std::env::var("BRAVE_SEARCH_API_KEY")
+                .ok()
+                .map(|api_key| SearchProvider::Brave { api_key }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchProvider::SearxNg { .. } => "searxng",
+            SearchProvider::Brave { .. } => "brave",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebSearchArgs {
+    pub query: String,
+}
+
+/// One search hit as presented to the model. `id` is the footnote number to cite.
+#[derive(Debug, Serialize)]
+pub struct WebSearchResult {
+    pub id: usize,
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+// ── Provider response shapes ─────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct SearxNgResponse {
+    #[serde(default)]
+    results: Vec<SearxNgResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxNgResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    web: Option<BraveWeb>,
+}
+
+#[derive(Deserialize)]
+struct BraveWeb {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// rig [`Tool`] that searches the web and records every hit in a [`SourceCollector`].
+pub struct WebSearchTool {
+    http: reqwest::Client,
+    provider: SearchProvider,
+    sources: SourceCollector,
+}
+
+impl WebSearchTool {
+    pub fn new(http: reqwest::Client, provider: SearchProvider, sources: SourceCollector) -> Self {
+        Self { http, provider, sources }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Source>, reqwest::Error> {
+        let sources = match &self.provider {
+            SearchProvider::SearxNg { base_url } => self
+                .http
+                .get(format!("{}/search", base_url.trim_end_matches('/')))
+                .query(&[("q", query), ("format", "json")])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<SearxNgResponse>()
+                .await?
+                .results
+                .into_iter()
+                .map(|r| Source { url: r.url, title: r.title, snippet: r.content, score: None })
+                .collect(),
+            SearchProvider::Brave { api_key } => self
+                .http
+                .get(BRAVE_SEARCH_URL)
+                .header("X-Subscription-Token", api_key)
+                .query(&[("q", query), ("count", &MAX_RESULTS.to_string())])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<BraveResponse>()
+                .await?
+                .web
+                .map(|w| w.results)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|r| Source {
+                    url: r.url,
+                    title: r.title,
+                    snippet: r.description,
+                    score: None,
+                })
+                .collect(),
+        };
+        Ok(sources)
+    }
+}
+
+impl Tool for WebSearchTool {
+    const NAME: &'static str = "web_search";
+
+    type Error = AppError;
+    type Args = WebSearchArgs;
+    type Output = Vec<WebSearchResult>;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Search the web for current information. Returns numbered results; \
+                          cite them in your answer as [id]."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "The search query"
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        info!("web_search ({}): {}", self.provider.name(), args.query);
+
+        let hits = self.search(&args.query).await.map_err(|e| {
+            error!("web_search failed for query '{}': {e}", args.query);
+            AppError::ToolFailed { tool_name: Self::NAME.to_string(), message: e.to_string() }
+        })?;
+
+        Ok(hits
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|source| WebSearchResult {
+                id: self.sources.add(source.clone()),
+                title: source.title,
+                url: source.url,
+                snippet: source.snippet,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use reqwest::header::AUTHORIZATION;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde_json::Value;
+use tracing::{error, info};
+
+use crate::errors::AppError;
+use crate::models::WebhookTool;
+
+/// How long a webhook may take to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Larger responses are refused rather than handed to the model.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// rig [`Tool`] that POSTs the model's arguments to a registered
+/// [`WebhookTool`] and returns its JSON response.
+pub struct WebhookCaller {
+    http: reqwest::Client,
+    tool: WebhookTool,
+}
+
+impl WebhookCaller {
+    pub fn new(http: reqwest::Client, tool: WebhookTool) -> Self {
+        Self { http, tool }
+    }
+
+    fn failed(&self, message: String) -> AppError {
+        error!("Webhook tool {} failed: {message}", self.tool.name);
+        AppError::ToolFailed { tool_name: self.tool.name.clone(), message }
+    }
+
+    async fn post(&self, args: &Value) -> Result<Value, AppError> {
+        let mut request = self.http.post(&self.tool.url).timeout(WEBHOOK_TIMEOUT).json(args);
+        if let Some(auth) = &self.tool.auth_header {
+            request = request.header(AUTHORIZATION, auth);
+        }
+        let mut response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| self.failed(e.to_string()))?;
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| self.failed(e.to_string()))? {
+            if body.len() + chunk.len() > MAX_RESPONSE_BYTES {
+                return Err(self.failed(format!(
+                    "response is larger than {MAX_RESPONSE_BYTES} bytes"
+                )));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice(&body)
+            .map_err(|e| self.failed(format!("response is not JSON: {e}")))
+    }
+}
+
+impl Tool for WebhookCaller {
+    /// Unused: each caller is named after its webhook tool.
+    const NAME: &'static str = "webhook";
+
+    type Error = AppError;
+    type Args = Value;
+    type Output = Value;
+
+    fn name(&self) -> String {
+        self.tool.name.clone()
+    }
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.tool.name.clone(),
+            description: self.tool.description.clone(),
+            parameters: self.tool.parameters.clone(),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        info!("webhook tool {}: {args}", self.tool.name);
+        self.post(&args).await
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use chrono::Utc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::db::workspace_read_repository::WorkspaceReadRepository;
+use crate::errors::AppError;
+use crate::models::WorkspaceFileRead;
+use crate::service::workspace::Workspace;
+
+/// Entries listed per directory; the rest are counted but not named.
+const MAX_ENTRIES: usize = 500;
+
+/// What the workspace tools may see: files under the workspace with an
+/// allowed extension and size, none of them hidden. Shared by every run's
+/// tool instances, along with the log their reads go to.
+#[derive(Clone)]
+pub struct WorkspaceAccess {
+    workspace: Workspace,
+    max_file_bytes: u64,
+    extensions: Arc<[String]>,
+    reads: WorkspaceReadRepository,
+}
+
+impl WorkspaceAccess {
+    pub fn new(
+        workspace: Workspace,
+        max_file_bytes: u64,
+        extensions: Vec<String>,
+        reads: WorkspaceReadRepository,
+    ) -> Self {
+        let extensions = extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase());
+        Self { workspace, max_file_bytes, extensions: extensions.collect(), reads }
+    }
+
+    fn allows_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| self.extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(e)))
+    }
+}
+
+/// Whether any part of `path` is hidden (`.git`, `.env`).
+fn is_hidden(path: &str) -> bool {
+    Path::new(path).components().any(|c| match c {
+        Component::Normal(name) => name.to_str().is_some_and(|n| n.starts_with('.')),
+        _ => false,
+    })
+}
+
+fn failed(tool: &str, message: impl Into<String>) -> AppError {
+    let message = message.into();
+    error!("{tool} failed: {message}");
+    AppError::ToolFailed { tool_name: tool.to_string(), message }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListFilesArgs {
+    /// Directory to list, relative to the workspace; the workspace itself
+    /// when left out.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// One entry of a listed directory.
+#[derive(Debug, Serialize)]
+pub struct WorkspaceEntry {
+    pub path: String,
+    pub is_dir: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListFilesOutput {
+    pub entries: Vec<WorkspaceEntry>,
+    /// Entries left out past the listing limit.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub omitted: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// rig [`Tool`] listing one directory of the workspace: its subdirectories and
+/// the files the agent may read.
+pub struct ListFilesTool {
+    access: WorkspaceAccess,
+}
+
+impl ListFilesTool {
+    pub fn new(access: WorkspaceAccess) -> Self {
+        Self { access }
+    }
+
+    async fn list(&self, relative: &str) -> Result<ListFilesOutput, String> {
+        let dir = if relative.is_empty() {
+            self.access.workspace.resolve(".").await
+        } else {
+            self.access.workspace.resolve(relative).await
+        }
+        .map_err(|e| e.to_string())?;
+        let mut read_dir = tokio::fs::read_dir(&dir).await.map_err(|e| format!("{relative}: {e}"))?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir.next_entry().await.map_err(|e| e.to_string())? {
+            let Ok(name) = entry.file_name().into_string() else { continue };
+            // Symlinks are not followed, so their targets stay unlisted.
+            let Ok(kind) = entry.file_type().await else { continue };
+            if name.starts_with('.') || kind.is_symlink() {
+                continue;
+            }
+            let path = if relative.is_empty() { name } else { format!("{relative}/{name}") };
+            if kind.is_dir() {
+                entries.push(WorkspaceEntry { path, is_dir: true, bytes: None });
+            } else if kind.is_file() && self.access.allows_extension(Path::new(&path)) {
+                let bytes = entry.metadata().await.ok().map(|m| m.len());
+                entries.push(WorkspaceEntry { path, is_dir: false, bytes });
+            }
+        }
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path)));
+        let omitted = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.truncate(MAX_ENTRIES);
+        Ok(ListFilesOutput { entries, omitted })
+    }
+}
+
+impl Tool for ListFilesTool {
+    const NAME: &'static str = "list_files";
+
+    type Error = AppError;
+    type Args = ListFilesArgs;
+    type Output = ListFilesOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "List a directory of the user's project: its subdirectories and \
+                          the files you can read with read_file."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory relative to the project root; omit for the root"
+                    }
+                }
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let relative = args.path.as_deref().unwrap_or_default().trim().trim_matches('/');
+        let relative = if relative == "." { "" } else { relative };
+        info!("list_files: {relative:?}");
+        if is_hidden(relative) {
+            return Err(failed(Self::NAME, format!("{relative} is hidden")));
+        }
+        self.list(relative).await.map_err(|message| failed(Self::NAME, message))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadFileArgs {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadFileOutput {
+    pub path: String,
+    pub content: String,
+}
+
+/// rig [`Tool`] returning the text of a workspace file, logging each read
+/// against the turn's conversation.
+pub struct ReadFileTool {
+    access: WorkspaceAccess,
+    conversation_id: Uuid,
+}
+
+impl ReadFileTool {
+    pub fn new(access: WorkspaceAccess, conversation_id: Uuid) -> Self {
+        Self { access, conversation_id }
+    }
+
+    async fn read(&self, relative: &str) -> Result<String, String> {
+        if is_hidden(relative) {
+            return Err(format!("{relative} is hidden"));
+        }
+        if !self.access.allows_extension(Path::new(relative)) {
+            return Err(format!("{relative} does not have an allowed extension"));
+        }
+        let path = self.access.workspace.resolve(relative).await.map_err(|e| e.to_string())?;
+        let metadata = tokio::fs::metadata(&path).await.map_err(|e| format!("{relative}: {e}"))?;
+        if !metadata.is_file() {
+            return Err(format!("{relative} is not a file"));
+        }
+        if metadata.len() > self.access.max_file_bytes {
+            return Err(format!(
+                "{relative} is {} bytes, more than the {} allowed",
+                metadata.len(),
+                self.access.max_file_bytes
+            ));
+        }
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("{relative} is not readable as text: {e}"))?;
+        // Logged before the model sees it: a read that cannot be audited is
+        // not made.
+        let read = WorkspaceFileRead {
+            id: Uuid::new_v4(),
+            conversation_id: self.conversation_id,
+            path: relative.to_string(),
+            bytes: content.len() as i64,
+            created_at: Utc::now(),
+        };
+        self.access.reads.save(&read).await.map_err(|e| e.to_string())?;
+        Ok(content)
+    }
+}
+
+impl Tool for ReadFileTool {
+    const NAME: &'static str = "read_file";
+
+    type Error = AppError;
+    type Args = ReadFileArgs;
+    type Output = ReadFileOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Read a text file of the user's project, as listed by list_files."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File path relative to the project root"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let relative = args.path.trim().trim_start_matches("./").to_string();
+        info!("read_file ({}): {relative}", self.conversation_id);
+        let content = self.read(&relative).await.map_err(|message| failed(Self::NAME, message))?;
+        Ok(ReadFileOutput { path: relative, content })
+    }
+}
@@ -0,0 +1,2413 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::agent::ScriptedAgent;
+use common::{config_store, TestApp, TestDb};
+use reqwest::StatusCode;
+use rust_ai_experiments::app::{self, AppState};
+use rust_ai_experiments::config::{
+    AppConfig, BlobStoreConfig, RouteLimit, RouteLimits, SlackConfig, TenantsConfig,
+};
+use rust_ai_experiments::errors::AppError;
+use rust_ai_experiments::models::{ServerConfig, ServerVersion, BUILD_COMMIT, PROTOCOL_VERSION};
+use rust_ai_experiments::service::encryption::ConversationKey;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn spawn() -> (TestApp, reqwest::Client) {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hi!"]))).await;
+    (app, reqwest::Client::new())
+}
+
+#[tokio::test]
+async fn chat_then_list_conversations_and_messages() {
+    let (app, client) = spawn().await;
+
+    let resp = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["message"]["content"], "Hi!");
+    let conv_id = body["conversation_id"].as_str().unwrap().to_string();
+
+    let convs: Value = client
+        .get(app.url("/api/conversations"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(convs[0]["id"], conv_id.as_str());
+
+    let msgs: Value = client
+        .get(app.url(&format!("/api/conversations/{conv_id}/messages")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(msgs.as_array().unwrap().len(), 2);
+    assert_eq!(msgs[0]["role"], "USER");
+}
+
+#[tokio::test]
+async fn conversations_can_be_listed_in_the_order_the_profile_keeps() {
+    let (app, client) = spawn().await;
+    let chat = |message: &str, conversation_id: Option<&str>| {
+        client
+            .post(app.url("/api/chat"))
+            .json(&json!({ "message": message, "conversation_id": conversation_id }))
+            .send()
+    };
+    let banana: Value = chat("banana", None).await.unwrap().json().await.unwrap();
+    let banana = banana["conversation_id"].as_str().unwrap().to_string();
+    chat("apple", None).await.unwrap();
+    chat("And another?", Some(&banana)).await.unwrap();
+    chat("Cherry", None).await.unwrap();
+
+    let titles = |sort: &'static str| {
+        let url = if sort.is_empty() {
+            app.url("/api/conversations")
+        } else {
+            app.url(&format!("/api/conversations?sort={sort}"))
+        };
+        let client = client.clone();
+        async move {
+            let convs: Value = client.get(url).send().await.unwrap().json().await.unwrap();
+            convs
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["title"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(titles("").await, ["Cherry", "banana", "apple"]);
+    assert_eq!(titles("created").await, ["Cherry", "apple", "banana"]);
+    assert_eq!(titles("title").await, ["apple", "banana", "Cherry"]);
+    // Ties are broken by the latest update.
+    assert_eq!(titles("message_count").await, ["banana", "Cherry", "apple"]);
+    let res = client.get(app.url("/api/conversations?sort=size")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let profile: Value = client
+        .patch(app.url("/api/me"))
+        .json(&json!({ "conversation_sort": "title" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(profile["conversation_sort"], "title");
+    assert_eq!(titles("").await, ["apple", "banana", "Cherry"]);
+    assert_eq!(titles("updated").await, ["Cherry", "banana", "apple"]);
+}
+
+#[tokio::test]
+async fn summarized_messages_are_condensed_into_a_summary_block() {
+    // Summaries are written by hand below, not by the background job.
+    let config = AppConfig { summary_interval: 0, ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config_store(config))
+        .await;
+    let client = reqwest::Client::new();
+    let mut conv_id: Option<String> = None;
+    for turn in 0..7 {
+        let body: Value = client
+            .post(app.url("/api/chat"))
+            .json(&json!({ "message": format!("Turn {turn}"), "conversation_id": conv_id }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        conv_id = body["conversation_id"].as_str().map(str::to_string);
+    }
+    let conv_id = conv_id.unwrap();
+    let messages_url = app.url(&format!("/api/conversations/{conv_id}/messages"));
+    let timeline_url = format!("{messages_url}?summaries=true");
+    let get = |url: String| {
+        let client = client.clone();
+        async move { client.get(url).send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+
+    // Nothing summarized yet: the timeline is the messages.
+    let timeline = get(timeline_url.clone()).await;
+    assert_eq!(timeline.as_array().unwrap().len(), 14);
+    assert_eq!(timeline[0]["type"], "message");
+    assert_eq!(timeline[0]["content"], "Turn 0");
+
+    sqlx::query("UPDATE conversations SET summary = $1, summary_message_count = 12 WHERE id = $2")
+        .bind("Counting turns.")
+        .bind(conv_id.parse::<Uuid>().unwrap())
+        .execute(&app.db.pool)
+        .await
+        .unwrap();
+
+    // The newest ten stay out of the block, although the summary covers them.
+    let timeline = get(timeline_url).await;
+    let messages = get(messages_url).await;
+    assert_eq!(timeline.as_array().unwrap().len(), 11);
+    assert_eq!(timeline[0]["type"], "summary");
+    assert_eq!(timeline[0]["summary"], "Counting turns.");
+    assert_eq!(timeline[0]["message_count"], 4);
+    assert_eq!(timeline[0]["last_message_id"], messages[3]["id"]);
+    assert_eq!(timeline[1]["type"], "message");
+    assert_eq!(timeline[1]["content"], "Turn 2");
+    assert_eq!(messages.as_array().unwrap().len(), 14);
+}
+
+#[tokio::test]
+async fn errors_map_to_status_codes() {
+    let (app, client) = spawn().await;
+
+    let resp = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let missing = Uuid::new_v4();
+    let resp = client
+        .get(app.url(&format!("/api/conversations/{missing}/messages")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "not_found");
+
+    let resp = client
+        .post(app.url(&format!("/api/messages/{missing}/regenerate")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // Ids are UUIDs; anything else is rejected before reaching the database.
+    let resp = client
+        .get(app.url("/api/conversations/missing/messages"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_request");
+
+    // So are bodies that are not JSON, with a code like any other error.
+    let resp = client
+        .post(app.url("/api/chat"))
+        .header("content-type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_request");
+    assert!(!body["error"].as_str().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn regenerate_and_switch_versions() {
+    let (app, client) = spawn().await;
+
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let message_id = body["message"]["id"].as_str().unwrap().to_string();
+
+    let regenerated: Value = client
+        .post(app.url(&format!("/api/messages/{message_id}/regenerate")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(regenerated["active_version"], 2);
+    assert_eq!(regenerated["version_count"], 2);
+
+    let versions: Value = client
+        .get(app.url(&format!("/api/messages/{message_id}/versions")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(versions.as_array().unwrap().len(), 2);
+
+    let resp = client
+        .put(app.url(&format!("/api/messages/{message_id}/active-version")))
+        .json(&json!({ "version": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let switched: Value = resp.json().await.unwrap();
+    assert_eq!(switched["active_version"], 1);
+
+    let resp = client
+        .put(app.url(&format!("/api/messages/{message_id}/active-version")))
+        .json(&json!({ "version": 9 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn regenerated_versions_are_word_diffed_against_the_previous_one() {
+    let agent = ScriptedAgent::replying(&["The sky is blue."])
+        .answering_with("mistral", "The sky is grey today.");
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "What colour is the sky?" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let message_id = body["message"]["id"].as_str().unwrap().to_string();
+
+    client
+        .patch(app.url("/api/me"))
+        .json(&json!({ "preferred_model": "mistral" }))
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(app.url(&format!("/api/messages/{message_id}/regenerate")))
+        .send()
+        .await
+        .unwrap();
+
+    let diff_url =
+        |version: i32| app.url(&format!("/api/messages/{message_id}/versions/{version}/diff"));
+    let diff: Value = client.get(diff_url(2)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(diff["from_version"], 1);
+    assert_eq!(diff["to_version"], 2);
+    assert_eq!(
+        diff["diff"],
+        json!([
+            { "op": "equal", "text": "The sky is " },
+            { "op": "delete", "text": "blue." },
+            { "op": "insert", "text": "grey today." },
+        ])
+    );
+
+    let res = client.get(diff_url(1)).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = client.get(diff_url(3)).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn conversations_export_to_pdf_with_their_markdown_laid_out() {
+    let reply = "## Summary\n\nLifetimes are **checked** at compile time.\n\n\
+                 ```rust\nfn longest<'a>(x: &'a str) -> &'a str { x }\n```\n\n\
+                 - one\n- two";
+    let agent = ScriptedAgent::replying(&["unused"]).answering(reply);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Rust lifetimes: a report" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["conversation_id"].as_str().unwrap();
+
+    let res = client
+        .get(app.url(&format!("/api/conversations/{id}/export?format=pdf")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/pdf");
+    assert_eq!(
+        res.headers()["content-disposition"],
+        "attachment; filename=\"rust-lifetimes-a-report.pdf\""
+    );
+    let pdf = res.bytes().await.unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+
+    let res = client
+        .get(app.url(&format!("/api/conversations/{id}/export?format=docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = client
+        .get(app.url(&format!("/api/conversations/{}/export?format=pdf", Uuid::new_v4())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn conversation_views_and_exports_are_logged() {
+    let (app, client) = spawn().await;
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["conversation_id"].as_str().unwrap();
+
+    let res = client
+        .get(app.url(&format!("/api/conversations/{id}/messages")))
+        .header("user-agent", "audit-test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = client
+        .get(app.url(&format!("/api/conversations/{id}/export?format=pdf")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    // Failed exports are not accesses.
+    let res = client
+        .get(app.url(&format!("/api/conversations/{id}/export?format=docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let log: Vec<Value> = client
+        .get(app.url(&format!("/api/conversations/{id}/access-log")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let actions: Vec<&str> = log.iter().map(|e| e["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["export", "view"]);
+    assert_eq!(log[1]["user_id"], "local");
+    assert_eq!(log[1]["user_agent"], "audit-test");
+
+    let res = client
+        .get(app.url(&format!("/api/conversations/{}/access-log", Uuid::new_v4())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn long_code_blocks_are_kept_as_artifacts() {
+    let code: Vec<String> = (1..=25).map(|i| format!("let x{i} = {i};")).collect();
+    let reply = format!("Here it is:\n\n```rust\n// src/main.rs\n{}\n```", code.join("\n"));
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&[&reply]))).await;
+    let client = reqwest::Client::new();
+
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Write a program" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(chat["message"]["content"], reply.as_str());
+    assert_eq!(chat["artifacts"][0]["kind"], "code");
+    assert_eq!(chat["artifacts"][0]["language"], "rust");
+    assert_eq!(chat["artifacts"][0]["title"], "src/main.rs");
+    let id = chat["conversation_id"].as_str().unwrap();
+
+    let artifacts: Vec<Value> = client
+        .get(app.url(&format!("/api/conversations/{id}/artifacts")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(artifacts.len(), 1);
+    assert_eq!(artifacts[0]["message_id"], chat["message"]["id"]);
+    let artifact_id = artifacts[0]["id"].as_str().unwrap();
+
+    let res = client
+        .get(app.url(&format!("/api/artifacts/{artifact_id}/download")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-disposition"], "attachment; filename=\"main.rs\"");
+    let body = res.text().await.unwrap();
+    assert!(body.starts_with("// src/main.rs\nlet x1 = 1;"));
+
+    let res = client
+        .get(app.url(&format!("/api/artifacts/{}", Uuid::new_v4())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = client
+        .get(app.url(&format!("/api/conversations/{}/artifacts", Uuid::new_v4())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn code_artifacts_are_diffed_against_and_applied_to_workspace_files() {
+    let lines: Vec<String> = (1..=20).map(|i| format!("let x{i} = {i};")).collect();
+    let reply = format!("```rust\n{}\n```", lines.join("\n"));
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    let old = lines.join("\n").replace("let x10 = 10;", "let x10 = 0;") + "\n";
+    std::fs::write(dir.join("src/lib.rs"), &old).unwrap();
+    let config = AppConfig { workspace_dir: Some(dir.clone()), ..AppConfig::default() };
+    let app =
+        TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&[&reply])), config_store(config))
+            .await;
+    let client = reqwest::Client::new();
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Fix x10" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["artifacts"][0]["id"].as_str().unwrap();
+    let diff_url = app.url(&format!("/api/artifacts/{id}/diff"));
+
+    // Against an uploaded file: the hunk around the changed line.
+    let diff: Value = client
+        .post(&diff_url)
+        .json(&json!({ "content": old }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(diff["path"], "artifact.rs");
+    assert_eq!(diff["applied"], false);
+    assert_eq!(
+        diff["diff"],
+        "--- a/artifact.rs\n+++ b/artifact.rs\n@@ -7,7 +7,7 @@\n let x7 = 7;\n let x8 = 8;\n \
+         let x9 = 9;\n-let x10 = 0;\n+let x10 = 10;\n let x11 = 11;\n let x12 = 12;\n \
+         let x13 = 13;\n@@ -17,4 +17,4 @@\n let x17 = 17;\n let x18 = 18;\n let x19 = 19;\n\
+         -let x20 = 20;\n\
+         +let x20 = 20;\n\\ No newline at end of file\n"
+    );
+
+    // Stale uploads are not applied over the workspace's file.
+    let res = client
+        .post(&diff_url)
+        .json(&json!({ "content": "fn main() {}\n", "path": "src/lib.rs", "apply": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    for path in ["../outside.rs", "/etc/passwd", "src/../../outside.rs"] {
+        let res = client
+            .post(&diff_url)
+            .json(&json!({ "path": path, "apply": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+
+    let diff: Value = client
+        .post(&diff_url)
+        .json(&json!({ "path": "src/lib.rs", "apply": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(diff["applied"], true);
+    assert!(diff["diff"].as_str().unwrap().starts_with("--- a/src/lib.rs\n"));
+    assert_eq!(std::fs::read_to_string(dir.join("src/lib.rs")).unwrap(), lines.join("\n"));
+    let diff: Value = client
+        .post(&diff_url)
+        .json(&json!({ "path": "src/lib.rs" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(diff["diff"], "");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+const BILLING_RS: &str = "/// Reads an invoice line.\n\
+                          pub fn parse_invoice(line: &str) -> u32 {\n    line.len() as u32\n}\n\n\
+                          pub fn total(amounts: &[u32]) -> u32 {\n    amounts.iter().sum()\n}\n";
+
+/// A git repository at `dir` with `files` committed.
+fn commit_repository(dir: &std::path::Path, files: &[(&str, &str)]) {
+    commit_changes(dir, files, &[]);
+}
+
+/// Commits `files`, written over any there, and the removal of `removed` to
+/// the repository at `dir`, created if there is none.
+fn commit_changes(dir: &std::path::Path, files: &[(&str, &str)], removed: &[&str]) {
+    let repo = git2::Repository::init(dir).unwrap();
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        let file = dir.join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, content).unwrap();
+        index.add_path(std::path::Path::new(path)).unwrap();
+    }
+    for path in removed {
+        std::fs::remove_file(dir.join(path)).unwrap();
+        index.remove_path(std::path::Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let author = git2::Signature::now("Test", "test@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &author, &author, "Update", &tree, &parents).unwrap();
+}
+
+/// Polls the ingestion a `POST /api/documents/git` answered with until it ends.
+async fn finished_ingestion(app: &TestApp, client: &reqwest::Client, started: &Value) -> Value {
+    let url = app.url(&format!("/api/documents/ingestions/{}", started["id"].as_str().unwrap()));
+    let mut ingestion = Value::Null;
+    for _ in 0..50 {
+        ingestion = client.get(&url).send().await.unwrap().json().await.unwrap();
+        if ingestion["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    ingestion
+}
+
+#[tokio::test]
+async fn ingested_repositories_are_retrieved_from_on_every_turn() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    let project = dir.join("project");
+    commit_repository(
+        &project,
+        &[
+            ("src/billing.rs", BILLING_RS),
+            ("README.md", "# Shop\n\nSells things.\n"),
+            (".env", "SECRET=1\n"),
+        ],
+    );
+    // Uncommitted files are not indexed.
+    std::fs::write(project.join("src/draft.rs"), "fn parse_invoice_draft() {}\n").unwrap();
+
+    let agent = ScriptedAgent::replying(&["It counts bytes."]);
+    let config = AppConfig { workspace_dir: Some(dir.clone()), ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+    let client = reqwest::Client::new();
+
+    for repository in ["", "ssh://example.com/repo.git", "../project"] {
+        let res = client
+            .post(app.url("/api/documents/git"))
+            .json(&json!({ "repository": repository }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{repository}");
+    }
+
+    let res = client
+        .post(app.url("/api/documents/git"))
+        .json(&json!({ "repository": "project" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let ingestion: Value = res.json().await.unwrap();
+    assert_eq!(ingestion["collection"], "codebase");
+    let ingestion = finished_ingestion(&app, &client, &ingestion).await;
+    assert_eq!(ingestion["status"], "completed", "{ingestion}");
+    assert_eq!(ingestion["files"], 2);
+
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "What does parse_invoice return?" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let retrieved = &agent.seen()[0].retrieved;
+    assert_eq!(retrieved[0].path, "src/billing.rs");
+    assert_eq!(retrieved[0].start_line, 1);
+    assert!(retrieved[0].content.starts_with("/// Reads an invoice line."));
+    assert!(retrieved.iter().all(|chunk| chunk.path != ".env"));
+
+    // What the model was given is kept with the reply, scores included.
+    let message_id = chat["message"]["id"].as_str().unwrap();
+    let url = app.url(&format!("/api/messages/{message_id}/retrieval"));
+    let kept: Vec<Value> = client.get(url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(kept.len(), retrieved.len());
+    assert_eq!(kept[0]["path"], "src/billing.rs");
+    assert_eq!(kept[0]["collection"], "codebase");
+    assert!((kept[0]["score"].as_f64().unwrap() - f64::from(retrieved[0].score)).abs() < 1e-6);
+    let url = app.url(&format!("/api/messages/{}/retrieval", Uuid::new_v4()));
+    let res = client.get(url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // The chunk naming parse_invoice leads both rankings; the README only
+    // resembles the question.
+    assert_eq!(kept[0]["vector_rank"], 1);
+    assert_eq!(kept[0]["text_rank"], 1);
+    assert_eq!(kept[1]["path"], "README.md");
+    assert_eq!(kept[1]["text_rank"], Value::Null);
+
+    // Users' verdicts on retrieved chunks make up the precision metrics.
+    for (chunk, relevant) in [(&kept[0], true), (&kept[1], false)] {
+        let chunk_id = chunk["chunk_id"].as_str().unwrap();
+        let url = app.url(&format!("/api/messages/{message_id}/retrieval/{chunk_id}"));
+        let res = client.put(url).json(&json!({ "relevant": relevant })).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+    let url = app.url(&format!("/api/messages/{message_id}/retrieval/{}", Uuid::new_v4()));
+    let res = client.put(url).json(&json!({ "relevant": true })).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let url = app.url(&format!("/api/messages/{message_id}/retrieval"));
+    let kept: Vec<Value> = client.get(url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(kept[0]["relevant"], true);
+    assert_eq!(kept[1]["relevant"], false);
+
+    let metrics: Value =
+        client.get(app.url("/api/retrieval/metrics")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(metrics["overall"]["rated"], 2);
+    assert_eq!(metrics["overall"]["precision"], 0.5);
+    assert_eq!(metrics["by_ranking"][0]["group"], "both");
+    assert_eq!(metrics["by_ranking"][0]["precision"], 1.0);
+    assert_eq!(metrics["by_ranking"][1]["group"], "vector");
+    assert_eq!(metrics["by_ranking"][1]["precision"], 0.0);
+    assert_eq!(metrics["by_position"][0]["group"], "1");
+    assert_eq!(metrics["by_collection"][0]["group"], "codebase");
+
+    let url = app.url(&format!("/api/documents/ingestions/{}", Uuid::new_v4()));
+    let res = client.get(url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn collections_are_attached_to_conversations_and_inspected() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    commit_repository(&dir.join("project"), &[("src/billing.rs", BILLING_RS)]);
+    let agent = ScriptedAgent::replying(&["It counts bytes."]);
+    // The scripted reranker scores by the share of the query's words found.
+    let config = AppConfig {
+        workspace_dir: Some(dir.clone()),
+        reranker_model: Some("reranker".to_string()),
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+    let client = reqwest::Client::new();
+
+    for name in ["Billing", "bill ing", "", "codebase"] {
+        let res = client
+            .post(app.url("/api/collections"))
+            .json(&json!({ "name": name }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{name}");
+    }
+    let res = client
+        .post(app.url("/api/collections"))
+        .json(&json!({ "name": "billing", "description": "Invoices", "chunk_max_lines": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let collections: Value =
+        client.get(app.url("/api/collections")).send().await.unwrap().json().await.unwrap();
+    let names: Vec<&str> =
+        collections.as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["billing", "codebase"]);
+    assert_eq!(collections[1]["global"], true);
+
+    let started: Value = client
+        .post(app.url("/api/documents/git"))
+        .json(&json!({ "repository": "project", "collection": "billing" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ingestion = finished_ingestion(&app, &client, &started).await;
+    assert_eq!(ingestion["status"], "completed", "{ingestion}");
+    // Split with the collection's own chunk size: one chunk per function.
+    let chunks: Value = client
+        .get(app.url("/api/collections/billing/chunks?path=src/billing.rs"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(chunks.as_array().unwrap().len(), 2);
+    assert_eq!(chunks[0]["start_line"], 1);
+    assert_eq!(chunks[1]["start_line"], 6);
+    assert_eq!(chunks[0]["dimensions"], 1024);
+    let hits: Value = client
+        .post(app.url("/api/collections/billing/search"))
+        .json(&json!({ "query": "what is the total of the amounts", "limit": 1 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    assert_eq!(hits[0]["start_line"], 6);
+    assert!((hits[0]["score"].as_f64().unwrap() - 2.0 / 7.0).abs() < 1e-6, "{hits}");
+
+    // Searches can weigh the rankings differently, down to leaving one out.
+    let hits: Value = client
+        .post(app.url("/api/collections/billing/search"))
+        .json(&json!({ "query": "amounts", "vector_weight": 0 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    assert_eq!(hits[0]["start_line"], 6);
+    assert_eq!(hits[0]["vector_rank"], Value::Null);
+    assert_eq!(hits[0]["text_rank"], 1);
+    let weights = [json!({ "vector_weight": -1 }), json!({ "vector_weight": 0, "text_weight": 0 })];
+    for mut body in weights {
+        body["query"] = json!("amounts");
+        let res = client
+            .post(app.url("/api/collections/billing/search"))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    // Only conversations it is attached to retrieve from a collection.
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "How is the total computed?" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(agent.seen()[0].retrieved.is_empty());
+    let conversation_id = chat["conversation_id"].as_str().unwrap();
+    let attach_url = app.url(&format!("/api/conversations/{conversation_id}/collections/billing"));
+    let attached: Value = client.put(&attach_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(attached[0]["name"], "billing");
+    assert_eq!(attached[0]["chunks"], 2);
+    let turn =
+        json!({ "message": "How is the total computed?", "conversation_id": conversation_id });
+    client
+        .post(app.url("/api/chat"))
+        .json(&turn)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(agent.seen()[1].retrieved[0].collection, "billing");
+
+    let res = client.delete(app.url("/api/collections/billing")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = client.get(app.url("/api/collections/billing")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let url = app.url(&format!("/api/conversations/{conversation_id}/collections"));
+    let attached: Value = client.get(url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(attached, json!([]));
+    let res = client.delete(&attach_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn searches_are_reranked_only_with_a_reranker() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    commit_repository(&dir.join("project"), &[("src/billing.rs", BILLING_RS)]);
+    let agent: Arc<ScriptedAgent> = Arc::new(ScriptedAgent::replying(&[]));
+    let config = AppConfig { workspace_dir: Some(dir.clone()), ..AppConfig::default() };
+    let app = TestApp::spawn_with(agent.clone(), config_store(config.clone())).await;
+    let client = reqwest::Client::new();
+    client
+        .post(app.url("/api/collections"))
+        .json(&json!({ "name": "billing", "chunk_max_lines": 4 }))
+        .send()
+        .await
+        .unwrap();
+    let started: Value = client
+        .post(app.url("/api/documents/git"))
+        .json(&json!({ "repository": "project", "collection": "billing" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ingestion = finished_ingestion(&app, &client, &started).await;
+    assert_eq!(ingestion["status"], "completed", "{ingestion}");
+
+    // Both rankings only find `amounts`, in `total`; the scripted reranker
+    // also counts the words inside others, and `parse_invoice` has two.
+    let search = |app: &TestApp| {
+        client
+            .post(app.url("/api/collections/billing/search"))
+            .json(&json!({ "query": "amounts voice ads" }))
+            .send()
+    };
+    let hits: Value = search(&app).await.unwrap().json().await.unwrap();
+    let lines: Vec<&Value> = hits.as_array().unwrap().iter().map(|h| &h["start_line"]).collect();
+    assert_eq!(lines, [6, 1], "{hits}");
+    assert_eq!(hits[0]["text_rank"], 1);
+    assert!(hits[0]["score"].as_f64().unwrap() < 0.1, "{hits}");
+
+    let reranking = AppConfig { reranker_model: Some("reranker".to_string()), ..config };
+    let replica = app.replica(agent, config_store(reranking)).await;
+    let hits: Value = search(&replica).await.unwrap().json().await.unwrap();
+    let lines: Vec<&Value> = hits.as_array().unwrap().iter().map(|h| &h["start_line"]).collect();
+    assert_eq!(lines, [1, 6], "{hits}");
+    assert!((hits[0]["score"].as_f64().unwrap() - 2.0 / 3.0).abs() < 1e-6, "{hits}");
+    assert!((hits[1]["score"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-6, "{hits}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn collections_split_documents_with_their_chunker() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    commit_repository(&dir.join("project"), &[("src/billing.rs", BILLING_RS)]);
+    let config = AppConfig { workspace_dir: Some(dir.clone()), ..AppConfig::default() };
+    let agent = Arc::new(ScriptedAgent::replying(&[]));
+    let app = TestApp::spawn_with(agent, config_store(config)).await;
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(app.url("/api/collections"))
+        .json(&json!({ "name": "docs", "chunk_max_lines": 4, "chunk_overlap": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let created: Value = client
+        .post(app.url("/api/collections"))
+        .json(&json!({
+            "name": "docs", "chunker": "fixed", "chunk_max_lines": 4, "chunk_overlap": 1
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(created["chunker"], "fixed");
+
+    let spans = |chunks: &Value| -> Vec<(i64, i64)> {
+        let spans = chunks.as_array().unwrap().iter();
+        spans.map(|c| (c["start_line"].as_i64().unwrap(), c["end_line"].as_i64().unwrap())).collect()
+    };
+    let preview = |body: Value| {
+        let client = client.clone();
+        let url = app.url("/api/collections/docs/preview");
+        async move { client.post(url).json(&body).send().await.unwrap() }
+    };
+    // The collection's own settings: windows of 4 lines, each repeating one.
+    let lines: Vec<String> = (1..=10).map(|i| format!("line {i}")).collect();
+    let chunks: Value = preview(json!({ "content": lines.join("\n") })).await.json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 4), (4, 7), (7, 10)]);
+    assert_eq!(chunks[1]["content"], "line 4\nline 5\nline 6\nline 7");
+    // Sentences are never cut, even across lines.
+    let text = "First sentence here.\nSecond one, which\nwraps a line. Third.";
+    let body = json!({ "content": text, "chunker": "sentence", "chunk_max_lines": 2 });
+    let chunks: Value = preview(body).await.json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 1), (2, 3)]);
+    assert_eq!(chunks[1]["content"], "Second one, which\nwraps a line. Third.");
+    // A `#` line in a fenced block is not a heading.
+    let text = "intro\n# Setup\n```sh\n# install\n```\n# Usage\nrun";
+    let body = json!({ "content": text, "path": "README.md", "chunker": "auto" });
+    let chunks: Value = preview(body).await.json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 1), (2, 5), (6, 7)]);
+    // Code breaks between functions, found from the syntax tree.
+    let text = "fn a() {\n    1\n}\nfn b() {\n    2\n}";
+    let body = json!({ "content": text, "path": "src/lib.rs", "chunker": "code" });
+    let chunks: Value = preview(body).await.json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 3), (4, 6)]);
+    let body = json!({ "content": "x", "chunk_overlap": -1 });
+    assert_eq!(preview(body).await.status(), StatusCode::BAD_REQUEST);
+    let url = app.url("/api/collections/missing/preview");
+    let res = client.post(url).json(&json!({ "content": "x" })).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let started: Value = client
+        .post(app.url("/api/documents/git"))
+        .json(&json!({ "repository": "project", "collection": "docs" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    finished_ingestion(&app, &client, &started).await;
+    let chunks_url = app.url("/api/collections/docs/chunks");
+    let chunks: Value = client.get(&chunks_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 4), (4, 7), (7, 8)]);
+
+    // Re-chunking splits what is indexed again with the new settings.
+    let rechunk_url = app.url("/api/collections/docs/rechunk");
+    let res = client.post(&rechunk_url).json(&json!({ "chunk_overlap": 4 })).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = client.post(&rechunk_url).json(&json!({ "chunker": "code" })).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let started: Value = res.json().await.unwrap();
+    assert_eq!(started["source"], "rechunk");
+    let ingestion = finished_ingestion(&app, &client, &started).await;
+    assert_eq!(ingestion["status"], "completed", "{ingestion}");
+    assert_eq!(ingestion["files"], 1);
+    assert_eq!(ingestion["chunks"], 2);
+    assert_eq!(ingestion["updated"], 1);
+    let chunks: Value = client.get(&chunks_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(spans(&chunks), [(1, 4), (6, 8)]);
+    assert_eq!(chunks[0]["dimensions"], 1024);
+    let collection: Value = client
+        .get(app.url("/api/collections/docs"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(collection["chunker"], "code");
+    assert_eq!(collection["chunk_overlap"], 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn ingesting_a_repository_again_only_embeds_what_changed() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    let project = dir.join("project");
+    let util = "pub fn double(x: u32) -> u32 {\n    x * 2\n}\n";
+    commit_repository(&project, &[("src/billing.rs", BILLING_RS), ("src/util.rs", util)]);
+    let config = AppConfig { workspace_dir: Some(dir.clone()), ..AppConfig::default() };
+    let agent: Arc<ScriptedAgent> = Arc::new(ScriptedAgent::replying(&[]));
+    let app = TestApp::spawn_with(agent.clone(), config_store(config.clone())).await;
+    let client = reqwest::Client::new();
+    let res = client
+        .post(app.url("/api/collections"))
+        .json(&json!({ "name": "billing", "chunk_max_lines": 4 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let ingest = || async {
+        let started: Value = client
+            .post(app.url("/api/documents/git"))
+            .json(&json!({ "repository": "project", "collection": "billing" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let ingestion = finished_ingestion(&app, &client, &started).await;
+        assert_eq!(ingestion["status"], "completed", "{ingestion}");
+        ingestion
+    };
+    let report = |ingestion: &Value| {
+        ["files", "chunks", "added", "updated", "skipped", "removed", "embedded"]
+            .map(|field| ingestion[field].as_i64().unwrap())
+    };
+
+    // Two chunks of billing.rs, one of util.rs.
+    assert_eq!(report(&ingest().await), [2, 3, 2, 0, 0, 0, 3]);
+    assert_eq!(report(&ingest().await), [2, 3, 0, 0, 2, 0, 0]);
+
+    // Only the function that changed and the new file are embedded.
+    let billing = BILLING_RS.replace("amounts.iter().sum()", "amounts.iter().copied().sum()");
+    let changes = [("src/billing.rs", billing.as_str()), ("NOTES.md", "# Notes")];
+    commit_changes(&project, &changes, &["src/util.rs"]);
+    assert_eq!(report(&ingest().await), [2, 3, 1, 1, 0, 1, 2]);
+    let chunks: Value = client
+        .get(app.url("/api/collections/billing/chunks"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let paths: Vec<&str> =
+        chunks.as_array().unwrap().iter().map(|c| c["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["NOTES.md", "src/billing.rs", "src/billing.rs"]);
+    assert!(chunks[2]["content"].as_str().unwrap().contains("copied()"));
+    assert_eq!(chunks[2]["dimensions"], 1024);
+
+    // Re-chunking the same way leaves every file as it is.
+    let res = client
+        .post(app.url("/api/collections/billing/rechunk"))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    let started: Value = res.json().await.unwrap();
+    let ingestion = finished_ingestion(&app, &client, &started).await;
+    assert_eq!(report(&ingestion), [2, 3, 0, 0, 2, 0, 0]);
+
+    // Once the embedding model changes, nothing embedded before is reused.
+    let config = AppConfig { embedding_model: "other-embedder".to_string(), ..config };
+    let replica = app.replica(agent, config_store(config)).await;
+    let started: Value = client
+        .post(replica.url("/api/documents/git"))
+        .json(&json!({ "repository": "project", "collection": "billing" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ingestion = finished_ingestion(&replica, &client, &started).await;
+    assert_eq!(report(&ingestion), [2, 3, 0, 2, 0, 0, 3]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn starred_messages_are_listed_with_their_conversation() {
+    let (app, client) = spawn().await;
+
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let message_id = body["message"]["id"].as_str().unwrap().to_string();
+    let star_url = app.url(&format!("/api/messages/{message_id}/star"));
+
+    let starred: Value = client.put(&star_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(starred["starred"], true);
+    // Starring twice is harmless.
+    let resp = client.put(&star_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let list: Value =
+        client.get(app.url("/api/starred")).send().await.unwrap().json().await.unwrap();
+    let list = list.as_array().unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["message"]["id"], message_id.as_str());
+    assert_eq!(list[0]["conversation_title"], "Hello");
+
+    let unstarred: Value = client.delete(&star_url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(unstarred["starred"], false);
+    let list: Value =
+        client.get(app.url("/api/starred")).send().await.unwrap().json().await.unwrap();
+    assert!(list.as_array().unwrap().is_empty());
+
+    let url = app.url(&format!("/api/messages/{}/star", Uuid::new_v4()));
+    let resp = client.put(url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn sent_prompts_are_recalled_newest_first() {
+    let (app, client) = spawn().await;
+    let chat = |message: &'static str, ephemeral: bool| {
+        let body = json!({ "message": message, "ephemeral": ephemeral });
+        client.post(app.url("/api/chat")).json(&body).send()
+    };
+    assert_eq!(chat("Explain lifetimes", false).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(chat("Write a haiku", false).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(chat("Explain lifetimes", false).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(chat("Something private", true).await.unwrap().status(), StatusCode::OK);
+
+    let prompts: Value =
+        client.get(app.url("/api/me/prompts")).send().await.unwrap().json().await.unwrap();
+    let prompts = prompts.as_array().unwrap();
+    assert_eq!(prompts.len(), 2, "{prompts:?}");
+    assert_eq!(prompts[0]["prompt"], "Explain lifetimes");
+    assert_eq!(prompts[0]["uses"], 2);
+    assert_eq!(prompts[1]["prompt"], "Write a haiku");
+
+    let found: Value = client
+        .get(app.url("/api/me/prompts?q=HAIKU&limit=5"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(found.as_array().unwrap().len(), 1);
+    assert_eq!(found[0]["prompt"], "Write a haiku");
+}
+
+#[tokio::test]
+async fn webhook_tools_are_registered_and_offered_to_turns() {
+    let agent = ScriptedAgent::replying(&["Hi!"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let conv_id = body["conversation_id"].as_str().unwrap().to_string();
+
+    let tool = |conversation_id: Option<&str>, name: &str| {
+        json!({
+            "conversation_id": conversation_id,
+            "name": name,
+            "description": "Looks up an order",
+            "parameters": { "type": "object", "properties": { "id": { "type": "string" } } },
+            "url": "http://orders.example/lookup",
+            "auth_header": "Bearer secret",
+        })
+    };
+    let create = |body: Value| client.post(app.url("/api/tools")).json(&body).send();
+
+    let global = create(tool(None, "lookup")).await.unwrap();
+    assert_eq!(global.status(), StatusCode::CREATED);
+    let global: Value = global.json().await.unwrap();
+    assert!(global.get("auth_header").is_none());
+    let local: Value = create(tool(Some(&conv_id), "lookup")).await.unwrap().json().await.unwrap();
+
+    for bad in [tool(None, "lookup"), tool(None, "web_search"), tool(None, "no spaces")] {
+        assert_eq!(create(bad).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+    let resp = create(tool(Some(&Uuid::new_v4().to_string()), "lookup")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // A conversation's own tool hides the global one of the same name.
+    let listed: Value =
+        client.get(app.url("/api/tools")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed[0]["id"], global["id"]);
+    let url = app.url(&format!("/api/tools?conversation_id={conv_id}"));
+    let listed: Value = client.get(url).send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["id"], local["id"]);
+
+    client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Where is order 7?", "conversation_id": conv_id }))
+        .send()
+        .await
+        .unwrap();
+    let offered = agent.seen().pop().unwrap().webhook_tools;
+    assert_eq!(offered.len(), 1);
+    assert_eq!(offered[0].id.to_string(), local["id"].as_str().unwrap());
+    assert_eq!(offered[0].auth_header.as_deref(), Some("Bearer secret"));
+
+    let delete_url = app.url(&format!("/api/tools/{}", local["id"].as_str().unwrap()));
+    let resp = client.delete(&delete_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = client.delete(&delete_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn conversations_can_be_given_an_icon_and_color() {
+    let (app, client) = spawn().await;
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["conversation_id"].as_str().unwrap();
+    let url = app.url(&format!("/api/conversations/{id}"));
+    let patch = |body: Value| client.patch(&url).json(&body).send();
+
+    let updated: Value = patch(json!({ "icon": "📚", "color": "#0090FF" }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["icon"], "📚");
+    assert_eq!(updated["color"], "#0090ff");
+
+    let res = patch(json!({ "color": "blue" })).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // Omitted fields are kept, null clears.
+    let updated: Value = patch(json!({ "color": null })).await.unwrap().json().await.unwrap();
+    assert_eq!(updated["icon"], "📚");
+    assert_eq!(updated["color"], Value::Null);
+    let listed: Value =
+        client.get(app.url("/api/conversations")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed[0]["icon"], "📚");
+}
+
+#[tokio::test]
+async fn encrypted_conversations_are_sealed_at_rest_and_need_unlocking() {
+    let agent = ScriptedAgent::replying(&["Hi!"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+    let chat = |message: &str, conversation_id: Option<&str>| {
+        client
+            .post(app.url("/api/chat"))
+            .json(&json!({ "message": message, "conversation_id": conversation_id }))
+            .send()
+    };
+    let body: Value = chat("Hello", None).await.unwrap().json().await.unwrap();
+    let conv_id = body["conversation_id"].as_str().unwrap().to_string();
+    let passphrase = |action: &str, passphrase: &str| {
+        client
+            .post(app.url(&format!("/api/conversations/{conv_id}/{action}")))
+            .json(&json!({ "passphrase": passphrase }))
+            .send()
+    };
+
+    let resp = passphrase("encrypt", "short").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = passphrase("encrypt", "correct horse").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let conversation: Value = resp.json().await.unwrap();
+    assert_eq!(conversation["encrypted"], true);
+    let resp = passphrase("encrypt", "correct horse").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Unlocked right away: turns see the history in the clear.
+    let resp = chat("And again", Some(&conv_id)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(agent.seen().pop().unwrap().history[0].content, "Hello");
+    let stored: Vec<(String,)> =
+        sqlx::query_as("SELECT content FROM messages WHERE conversation_id = $1")
+            .bind(conv_id.parse::<Uuid>().unwrap())
+            .fetch_all(&app.db.pool)
+            .await
+            .unwrap();
+    assert_eq!(stored.len(), 4);
+    assert!(stored.iter().all(|(content,)| content.starts_with("enc1:")));
+    let (salt,): (String,) = sqlx::query_as("SELECT key_salt FROM conversations WHERE id = $1")
+        .bind(conv_id.parse::<Uuid>().unwrap())
+        .fetch_one(&app.db.pool)
+        .await
+        .unwrap();
+    assert!(salt.starts_with("argon2id:"), "{salt}");
+
+    let messages_url = app.url(&format!("/api/conversations/{conv_id}/messages"));
+    let lock_url = app.url(&format!("/api/conversations/{conv_id}/lock"));
+    let resp = client.post(&lock_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let resp = client.get(&messages_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+    let error: Value = resp.json().await.unwrap();
+    assert!(error["error"].as_str().unwrap().contains("unlock it with its passphrase"));
+    assert_eq!(error["code"], "conversation_locked");
+    let resp = chat("Still there?", Some(&conv_id)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::LOCKED);
+
+    let resp = passphrase("unlock", "wrong horse").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = passphrase("unlock", "correct horse").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    let messages: Value = client.get(&messages_url).send().await.unwrap().json().await.unwrap();
+    let contents: Vec<&str> =
+        messages.as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, ["Hello", "Hi!", "And again", "Hi!"]);
+}
+
+#[tokio::test]
+async fn conversations_encrypted_with_pbkdf2_keys_still_unlock() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hi!"]))).await;
+    let client = reqwest::Client::new();
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let conv_id: Uuid = body["conversation_id"].as_str().unwrap().parse().unwrap();
+
+    // Sealed as before Argon2id: a bare base64 salt, and a PBKDF2 key.
+    let salt = "c2FsdHNhbHRzYWx0c2FsdA==";
+    let key = ConversationKey::derive("correct horse", salt).unwrap();
+    let messages: Vec<(Uuid, String)> =
+        sqlx::query_as("SELECT id, content FROM messages WHERE conversation_id = $1")
+            .bind(conv_id)
+            .fetch_all(&app.db.pool)
+            .await
+            .unwrap();
+    for (id, content) in messages {
+        sqlx::query("UPDATE messages SET content = $2 WHERE id = $1")
+            .bind(id)
+            .bind(key.seal(&content).unwrap())
+            .execute(&app.db.pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query(
+        "UPDATE conversations SET encrypted = TRUE, key_salt = $2, key_check = $3 WHERE id = $1",
+    )
+    .bind(conv_id)
+    .bind(salt)
+    .bind(key.check_value().unwrap())
+    .execute(&app.db.pool)
+    .await
+    .unwrap();
+
+    let unlock_url = app.url(&format!("/api/conversations/{conv_id}/unlock"));
+    let unlock = |passphrase: &str| {
+        client.post(&unlock_url).json(&json!({ "passphrase": passphrase })).send()
+    };
+    assert_eq!(unlock("wrong horse").await.unwrap().status(), StatusCode::BAD_REQUEST);
+    assert_eq!(unlock("correct horse").await.unwrap().status(), StatusCode::NO_CONTENT);
+    let messages: Value = client
+        .get(app.url(&format!("/api/conversations/{conv_id}/messages")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let contents: Vec<&str> =
+        messages.as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, ["Hello", "Hi!"]);
+}
+
+#[tokio::test]
+async fn failed_turns_are_marked_and_can_be_retried() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hi!"]).without_model())).await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.status().is_server_error());
+    let conversations: Value =
+        client.get(app.url("/api/conversations")).send().await.unwrap().json().await.unwrap();
+    assert!(conversations[0]["failed_turn_error"].as_str().unwrap().contains("llama3.2"));
+    let id = conversations[0]["id"].as_str().unwrap();
+    let retry_url = app.url(&format!("/api/conversations/{id}/retry-last"));
+
+    client.post(app.url("/api/models/pull")).send().await.unwrap().text().await.unwrap();
+    let resp = client.post(&retry_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["message"]["content"], "Hi!");
+
+    let conversations: Value =
+        client.get(app.url("/api/conversations")).send().await.unwrap().json().await.unwrap();
+    assert!(conversations[0]["failed_turn_error"].is_null());
+    // The retry answered the saved message instead of adding another.
+    assert_eq!(app.service.get_messages(id.parse().unwrap()).await.unwrap().len(), 2);
+
+    let resp = client.post(&retry_url).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn chat_turns_stop_when_the_client_disconnects() {
+    let agent = ScriptedAgent::replying(&["Hi!"]).taking(std::time::Duration::from_secs(30));
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let client = reqwest::Client::new();
+
+    // The client gives up long before the reply would be ready.
+    let sent = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .timeout(std::time::Duration::from_millis(300))
+        .send()
+        .await;
+    assert!(sent.unwrap_err().is_timeout());
+
+    let mut conversations = Value::Null;
+    for _ in 0..50 {
+        conversations =
+            client.get(app.url("/api/conversations")).send().await.unwrap().json().await.unwrap();
+        if !conversations[0]["failed_turn_error"].is_null() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let error = conversations[0]["failed_turn_error"].as_str().expect("turn marked as failed");
+    assert!(error.contains("disconnected"));
+    // Only the question was kept; the turn can be retried.
+    let id = conversations[0]["id"].as_str().unwrap().parse().unwrap();
+    assert_eq!(app.service.get_messages(id).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn admin_reload_returns_the_active_config() {
+    let (app, client) = spawn().await;
+
+    let resp = client
+        .post(app.url("/api/admin/config/reload"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let config: Value = resp.json().await.unwrap();
+    assert!(config.get("database_url").is_none(), "secrets are not echoed back");
+    assert!(config["max_message_length"].as_u64().is_some());
+}
+
+#[tokio::test]
+async fn delete_conversation_removes_its_messages() {
+    let (app, client) = spawn().await;
+
+    let body: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let conv_id = body["conversation_id"].as_str().unwrap().to_string();
+
+    let resp = client
+        .delete(app.url(&format!("/api/conversations/{conv_id}")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+    let resp = client
+        .get(app.url(&format!("/api/conversations/{conv_id}/messages")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client
+        .delete(app.url(&format!("/api/conversations/{conv_id}")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn models_lists_the_agent_models() {
+    let (app, client) = spawn().await;
+
+    let body: Value = client
+        .get(app.url("/api/models"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["current"], "llama3.2");
+    assert_eq!(body["models"][0]["name"], "scripted");
+    assert_eq!(body["context_window"], 4096);
+}
+
+#[tokio::test]
+async fn tokenize_counts_tokens_for_the_current_or_a_named_model() {
+    let (app, client) = spawn().await;
+
+    let body: Value = client
+        .post(app.url("/api/tokenize"))
+        .json(&json!({ "text": "Hello world", "boundaries": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["model"], "llama3.2");
+    assert_eq!(body["encoding"], "cl100k_base");
+    assert_eq!(body["exact"], false);
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["tokens"][0], json!({ "text": "Hello", "start": 0, "end": 5 }));
+    assert_eq!(body["tokens"][1], json!({ "text": " world", "start": 5, "end": 11 }));
+
+    let body: Value = client
+        .post(app.url("/api/tokenize"))
+        .json(&json!({ "text": "Hello world", "model": "gpt-4o" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["encoding"], "o200k_base");
+    assert_eq!(body["exact"], true);
+    assert!(body.get("tokens").is_none());
+}
+
+#[tokio::test]
+async fn search_matches_titles_and_message_content() {
+    let (app, client) = spawn().await;
+    for message in ["Rust lifetimes", "Borrow checker"] {
+        client
+            .post(app.url("/api/chat"))
+            .json(&json!({ "message": message }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let search = |q: &str| {
+        let req = client.get(app.url("/api/search")).query(&[("q", q)]);
+        async move { req.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+
+    let by_title = search("LIFETIME").await;
+    assert_eq!(by_title.as_array().unwrap().len(), 1);
+    assert_eq!(by_title[0]["title"], "Rust lifetimes");
+
+    // Both replies are "Hi!", so a content match finds both conversations.
+    assert_eq!(search("hi!").await.as_array().unwrap().len(), 2);
+    // LIKE wildcards are matched literally.
+    assert!(search("%").await.as_array().unwrap().is_empty());
+    assert!(search("  ").await.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn profile_preferences_apply_to_every_turn() {
+    let agent = ScriptedAgent::replying(&["Hi!"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+
+    let blank: Value = client.get(app.url("/api/me")).send().await.unwrap().json().await.unwrap();
+    assert!(blank["display_name"].is_null());
+
+    let patch = |body: Value| client.patch(app.url("/api/me")).json(&body).send();
+    let updated: Value = patch(json!({
+        "display_name": "Ada",
+        "preferred_model": "mistral",
+        "temperature": 0.2,
+        "theme": "light",
+        "custom_instructions": "Answer in haiku.",
+    }))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(updated["theme"], "light");
+
+    // Omitted fields are kept; null clears.
+    let updated: Value =
+        patch(json!({ "theme": null })).await.unwrap().json().await.unwrap();
+    assert!(updated["theme"].is_null());
+    assert_eq!(updated["display_name"], "Ada");
+
+    let res = patch(json!({ "temperature": 5.0 })).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = patch(json!({ "theme": "neon" })).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap();
+    let ctx = &agent.seen()[0];
+    assert_eq!(ctx.user_name.as_deref(), Some("Ada"));
+    assert_eq!(ctx.preferences.model.as_deref(), Some("mistral"));
+    assert_eq!(ctx.preferences.temperature, Some(0.2));
+    assert_eq!(ctx.preferences.custom_instructions.as_deref(), Some("Answer in haiku."));
+
+    let models: Value =
+        client.get(app.url("/api/models")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(models["current"], "mistral");
+}
+
+#[tokio::test]
+async fn server_config_reports_protocol_limits_and_features() {
+    let config = config_store(AppConfig {
+        max_message_length: 500,
+        follow_up_suggestions: 0,
+        slack: Some(SlackConfig::default()),
+        retrieval_top_k: 0,
+        ..AppConfig::default()
+    });
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config).await;
+
+    let config: Value =
+        reqwest::get(app.url("/api/config")).await.unwrap().json().await.unwrap();
+    assert_eq!(config["protocol_version"], PROTOCOL_VERSION);
+    assert_eq!(config["build"]["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(config["providers"], json!(["ollama"]));
+    assert_eq!(config["model"], "llama3.2");
+    assert_eq!(config["tools"], json!([]));
+    assert_eq!(config["max_message_length"], 500);
+    assert_eq!(config["features"]["slack"], true);
+    assert_eq!(config["features"]["follow_up_suggestions"], false);
+    assert_eq!(config["features"]["email_gateway"], false);
+    assert_eq!(config["features"]["retrieval"], false);
+
+    let (app, client) = spawn().await;
+    let config: ServerConfig =
+        client.get(app.url("/api/config")).send().await.unwrap().json().await.unwrap();
+    assert!(config.features.retrieval);
+}
+
+#[tokio::test]
+async fn version_reports_the_build_the_frontend_checks_against() {
+    let (app, client) = spawn().await;
+    let version: ServerVersion =
+        client.get(app.url("/api/version")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(version.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(version.build.commit.as_deref(), BUILD_COMMIT);
+    assert!(version.matches_this_build());
+
+    let mut redeployed = version.clone();
+    redeployed.build.commit = Some("0123456789ab".to_string());
+    assert_eq!(redeployed.matches_this_build(), BUILD_COMMIT.is_none());
+    redeployed.protocol_version += 1;
+    assert!(!redeployed.matches_this_build());
+}
+
+#[tokio::test]
+async fn export_all_streams_a_zip_of_every_conversation() {
+    let (app, client) = spawn().await;
+    client.patch(app.url("/api/me")).json(&json!({ "display_name": "Ada" })).send().await.unwrap();
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["conversation_id"].as_str().unwrap();
+
+    let res = client.get(app.url("/api/export/all")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/zip");
+    let body = res.bytes().await.unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+    let mut read = |name: &str| -> Value {
+        let entry = archive.by_name(name).unwrap();
+        serde_json::from_reader(entry).unwrap()
+    };
+    assert_eq!(read("profile.json")["display_name"], "Ada");
+    let conversation = read(&format!("conversations/{id}.json"));
+    assert_eq!(conversation["conversation"]["id"], id);
+    let contents: Vec<&str> = conversation["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["content"].as_str().unwrap())
+        .collect();
+    assert_eq!(contents, ["Hello", "Hi!"]);
+    assert_eq!(read("export.json")["conversations"], 1);
+}
+
+#[tokio::test]
+async fn exports_are_kept_in_the_blob_store() {
+    let dir = std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()));
+    let config = AppConfig {
+        blob_store: BlobStoreConfig::Local { path: dir.clone() },
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config_store(config))
+        .await;
+    let client = reqwest::Client::new();
+    client.post(app.url("/api/chat")).json(&json!({ "message": "Hello" })).send().await.unwrap();
+
+    let res = client.post(app.url("/api/exports")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let created: Value = res.json().await.unwrap();
+    let url = created["url"].as_str().unwrap();
+    let id = created["id"].as_str().unwrap();
+    assert!(dir.join("exports").join(format!("{id}.zip")).exists());
+
+    // The local store cannot sign URLs, so the archive is streamed.
+    let res = client.get(app.url(url)).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/zip");
+    let body = res.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+    assert!(archive.by_name("export.json").is_ok());
+
+    let res = client.delete(app.url(url)).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    let res = client.get(app.url(url)).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = client.get(app.url("/api/exports/not%20an%20id")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn transcripts_log_completed_turns_and_can_be_tailed() {
+    let dir = std::env::temp_dir().join(format!("transcripts-{}", uuid::Uuid::new_v4()));
+    let config = AppConfig { transcript_dir: Some(dir.clone()), ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config_store(config))
+        .await;
+    let client = reqwest::Client::new();
+
+    let mut id: Option<String> = None;
+    for message in ["one", "two", "three"] {
+        let body: Value = client
+            .post(app.url("/api/chat"))
+            .json(&json!({ "message": message, "conversation_id": id }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        id = Some(body["conversation_id"].as_str().unwrap().to_string());
+    }
+    client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "secret", "ephemeral": true }))
+        .send()
+        .await
+        .unwrap();
+    let id = id.unwrap();
+
+    let tail: Value = client
+        .get(app.url(&format!("/api/admin/transcripts/{id}?limit=2")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let users: Vec<&str> =
+        tail.as_array().unwrap().iter().map(|e| e["user"].as_str().unwrap()).collect();
+    assert_eq!(users, ["two", "three"]);
+    assert_eq!(tail[1]["assistant"], "Hi!");
+
+    // Only the saved conversation was logged.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    let res = client.get(app.url("/api/admin/transcripts/..%2Fetc")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn evals_replay_user_turns_and_diff_the_replies() {
+    let agent = ScriptedAgent::replying(&["The answer is 4."])
+        .answering_with("mistral", "The answer is four.");
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+
+    let chat: Value = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "What is 2 + 2?" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = chat["conversation_id"].as_str().unwrap();
+
+    let res = client
+        .post(app.url("/api/evals"))
+        .json(&json!({ "model": "mistral", "conversation_ids": [id] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let run: Value = res.json().await.unwrap();
+    let run_id = run["id"].as_str().unwrap();
+
+    let mut report = Value::Null;
+    for _ in 0..50 {
+        report = client
+            .get(app.url(&format!("/api/evals/{run_id}")))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if report["run"]["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(report["run"]["status"], "completed");
+    assert_eq!(report["summary"]["turns"], 1);
+    assert_eq!(report["summary"]["changed"], 1);
+    let turn = &report["turns"][0];
+    assert_eq!(turn["user_message"], "What is 2 + 2?");
+    assert_eq!(turn["original_content"], "The answer is 4.");
+    assert_eq!(turn["new_content"], "The answer is four.");
+    assert_eq!(
+        turn["diff"],
+        json!([
+            { "op": "equal", "text": "The answer is " },
+            { "op": "delete", "text": "4." },
+            { "op": "insert", "text": "four." },
+        ])
+    );
+    // The replay saw the original history up to the turn, not the new replies.
+    let replay = agent.seen().pop().unwrap();
+    assert!(replay.history.is_empty());
+    assert_eq!(replay.preferences.model.as_deref(), Some("mistral"));
+
+    let res = client
+        .post(app.url("/api/evals"))
+        .json(&json!({ "model": "mistral", "conversation_ids": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn batch_prompts_are_answered_in_the_background() {
+    let agent =
+        ScriptedAgent::replying(&["Default reply"]).answering_with("mistral", "Mistral reply");
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(app.url("/api/batch"))
+        .json(&json!({ "prompts": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = client
+        .post(app.url("/api/batch"))
+        .json(&json!({
+            "prompts": [
+                { "prompt": "First" },
+                { "prompt": "Second", "model": "mistral" },
+            ],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let job: Value = res.json().await.unwrap();
+    let job_id = job["id"].as_str().unwrap();
+    assert_eq!(job["items"][0]["status"], "queued");
+
+    let mut job = Value::Null;
+    for _ in 0..50 {
+        job = client
+            .get(app.url(&format!("/api/batch/{job_id}")))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if job["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(job["status"], "completed");
+    let items = job["items"].as_array().unwrap();
+    assert_eq!(items[0]["model"], "llama3.2");
+    assert_eq!(items[0]["content"], "Default reply");
+    assert_eq!(items[1]["status"], "completed");
+    assert_eq!(items[1]["content"], "Mistral reply");
+    // Prompts are answered on their own, and nothing is stored as a conversation.
+    assert!(agent.seen().iter().all(|ctx| ctx.history.is_empty()));
+    assert!(app.service.get_conversations().await.unwrap().is_empty());
+
+    let url = app.url(&format!("/api/batch/{}", Uuid::new_v4()));
+    let res = client.get(url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn finished_batch_jobs_notify_the_user_and_retry_failed_deliveries() {
+    let (app, client) = spawn().await;
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&webhook)
+        .await;
+    Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).mount(&webhook).await;
+
+    let patch = |body: Value| client.patch(app.url("/api/me")).json(&body).send();
+    for bad in [json!({ "notify_webhook_url": "ftp://x" }), json!({ "notify_email": "nobody" })] {
+        assert_eq!(patch(bad).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+    let res = patch(json!({ "notify_webhook_url": format!("{}/hook", webhook.uri()) }))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let job: Value = client
+        .post(app.url("/api/batch"))
+        .json(&json!({ "prompts": [{ "prompt": "First" }, { "prompt": "Second" }] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let mut notifications = Value::Null;
+    for _ in 0..100 {
+        notifications = client
+            .get(app.url("/api/notifications"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if notifications[0]["status"] == "delivered" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let notification = &notifications[0];
+    assert_eq!(notification["status"], "delivered");
+    assert_eq!(notification["event"], "batch_completed");
+    assert_eq!(notification["channel"], "webhook");
+    assert_eq!(notification["subject_id"], job["id"]);
+    assert_eq!(notification["summary"], "2 of 2 prompts answered");
+    assert_eq!(notification["attempts"], 2);
+
+    let received = webhook.received_requests().await.unwrap();
+    assert_eq!(received.len(), 2);
+    let body: Value = received[1].body_json().unwrap();
+    assert_eq!(body["id"], notification["id"]);
+    assert_eq!(body["event"], "batch_completed");
+}
+
+#[tokio::test]
+async fn assistant_messages_are_sent_to_conversation_webhooks_signed() {
+    let (app, client) = spawn().await;
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).mount(&receiver).await;
+    let chat = |conversation_id: Value| {
+        let body = json!({ "message": "Hello", "conversation_id": conversation_id });
+        client.post(app.url("/api/chat")).json(&body).send()
+    };
+    let first: Value = chat(Value::Null).await.unwrap().json().await.unwrap();
+    let conversation_id = first["conversation_id"].clone();
+
+    let register = |url: String| {
+        let body = json!({ "conversation_id": conversation_id, "url": url });
+        client.post(app.url("/api/webhooks")).json(&body).send()
+    };
+    let res = register("ftp://x".to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = register(format!("{}/hook", receiver.uri())).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let webhook: Value = res.json().await.unwrap();
+    let secret = webhook["secret"].as_str().unwrap().to_string();
+    let query = format!("/api/webhooks?conversation_id={}", conversation_id.as_str().unwrap());
+    let listed: Value = client
+        .get(app.url(&query))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["id"], webhook["id"]);
+    assert!(listed[0].get("secret").is_none());
+
+    let reply: Value = chat(conversation_id.clone()).await.unwrap().json().await.unwrap();
+    let webhook_url = app.url(&format!("/api/webhooks/{}", webhook["id"].as_str().unwrap()));
+    let deliveries_url = format!("{webhook_url}/deliveries");
+    let mut deliveries = Value::Null;
+    for _ in 0..100 {
+        deliveries = client.get(&deliveries_url).send().await.unwrap().json().await.unwrap();
+        if deliveries[0]["status"] == "delivered" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(deliveries.as_array().unwrap().len(), 1);
+    assert_eq!(deliveries[0]["status"], "delivered");
+    assert_eq!(deliveries[0]["attempts"], 1);
+    assert_eq!(deliveries[0]["message_id"], reply["message"]["id"]);
+
+    let received = receiver.received_requests().await.unwrap();
+    assert_eq!(received.len(), 1);
+    let body: Value = received[0].body_json().unwrap();
+    assert_eq!(body["event"], "message.created");
+    assert_eq!(body["conversation_id"], conversation_id);
+    assert_eq!(body["message"]["content"], "Hi!");
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = ring::hmac::sign(&key, &received[0].body);
+    let hex: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    let signature = received[0].headers.get("x-webhook-signature").unwrap();
+    assert_eq!(signature.to_str().unwrap(), format!("sha256={hex}"));
+
+    let res = client.delete(&webhook_url).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(client.get(&deliveries_url).send().await.unwrap().status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn slack_threads_are_relayed_to_conversations() {
+    let slack_api = MockServer::start().await;
+    Mock::given(path("/chat.postMessage"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "ts": "200.1" })),
+        )
+        .mount(&slack_api)
+        .await;
+    Mock::given(path("/chat.update"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+        .mount(&slack_api)
+        .await;
+    let config = AppConfig {
+        slack: Some(SlackConfig {
+            bot_token: "xoxb-test".to_string(),
+            signing_secret: "shh".to_string(),
+            api_url: slack_api.uri(),
+        }),
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config_store(config))
+        .await;
+    let client = reqwest::Client::new();
+    let send = |body: Value, secret: &str| {
+        let body = body.to_string();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+        let tag = ring::hmac::sign(&key, format!("v0:{timestamp}:{body}").as_bytes());
+        let hex: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+        client
+            .post(app.url("/slack/events"))
+            .header("x-slack-request-timestamp", timestamp)
+            .header("x-slack-signature", format!("v0={hex}"))
+            .body(body)
+            .send()
+    };
+    // Waits for the reply to the `n`th relayed message to be posted in full.
+    let slack_api = &slack_api;
+    let replied = |n: usize| async move {
+        for _ in 0..100 {
+            let requests = slack_api.received_requests().await.unwrap();
+            let edits: Vec<Value> = requests
+                .iter()
+                .filter(|r| r.url.path() == "/chat.update")
+                .map(|r| r.body_json().unwrap())
+                .collect();
+            if edits.iter().filter(|e| e["text"] == "Hi!").count() == n {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("no reply was posted to Slack");
+    };
+
+    let verification = json!({ "type": "url_verification", "challenge": "abc" });
+    let res = send(verification.clone(), "wrong").await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res: Value = send(verification, "shh").await.unwrap().json().await.unwrap();
+    assert_eq!(res["challenge"], "abc");
+
+    let mention = json!({
+        "type": "event_callback",
+        "authorizations": [{ "user_id": "UBOT" }],
+        "event": { "type": "app_mention", "channel": "C1", "ts": "100.1", "text": "<@UBOT> Hello" },
+    });
+    assert_eq!(send(mention, "shh").await.unwrap().status(), StatusCode::OK);
+    let requests = replied(1).await;
+    let placeholder: Value = requests[0].body_json().unwrap();
+    assert_eq!(requests[0].url.path(), "/chat.postMessage");
+    assert_eq!(placeholder["channel"], "C1");
+    assert_eq!(placeholder["thread_ts"], "100.1");
+    let auth = requests[0].headers.get("authorization").unwrap();
+    assert_eq!(auth.to_str().unwrap(), "Bearer xoxb-test");
+
+    let reply_in_thread = json!({
+        "type": "event_callback",
+        "authorizations": [{ "user_id": "UBOT" }],
+        "event": {
+            "type": "message",
+            "channel": "C1",
+            "ts": "100.2",
+            "thread_ts": "100.1",
+            "text": "Again",
+        },
+    });
+    send(reply_in_thread, "shh").await.unwrap();
+    replied(2).await;
+
+    let conversations: Value =
+        client.get(app.url("/api/conversations")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(conversations.as_array().unwrap().len(), 1);
+    let id = conversations[0]["id"].as_str().unwrap();
+    let messages: Value = client
+        .get(app.url(&format!("/api/conversations/{id}/messages")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let contents: Vec<&str> =
+        messages.as_array().unwrap().iter().map(|m| m["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, ["Hello", "Hi!", "Again", "Hi!"]);
+}
+
+#[tokio::test]
+async fn maintenance_mode_refuses_chat_but_serves_history() {
+    let (app, client) = spawn().await;
+    let res = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap();
+    let conversation_id = res.json::<Value>().await.unwrap()["conversation_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let res = client
+        .put(app.url("/api/admin/maintenance"))
+        .json(&json!({ "enabled": true, "message": "Back at noon" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let status: Value = client
+        .get(app.url("/api/maintenance"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["enabled"], true);
+    assert_eq!(status["message"], "Back at noon");
+
+    let res = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Still there?" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = res.json().await.unwrap();
+    assert_eq!(body["error"], "Back at noon");
+    assert_eq!(body["maintenance"], true);
+
+    let res = client
+        .get(app.url("/ws/chat"))
+        .header("accept", "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(res.text().await.unwrap().contains("Back at noon"));
+
+    let res = client
+        .get(app.url(&format!("/api/conversations/{conversation_id}/messages")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.json::<Vec<Value>>().await.unwrap().len(), 2);
+
+    client
+        .put(app.url("/api/admin/maintenance"))
+        .json(&json!({ "enabled": false }))
+        .send()
+        .await
+        .unwrap();
+    let res = client
+        .post(app.url("/api/chat"))
+        .json(&json!({ "message": "Still there?", "conversation_id": conversation_id }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn frontend_dir_serves_the_app_on_the_api_origin() {
+    let dir = std::env::temp_dir().join(format!("frontend-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<html>app</html>").unwrap();
+    std::fs::write(dir.join("style.css"), "body {}").unwrap();
+    let config = AppConfig { frontend_dir: Some(dir.clone()), ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["Hi!"])), config_store(config))
+        .await;
+    let client = reqwest::Client::new();
+
+    let res = client.get(app.url("/style.css")).send().await.unwrap();
+    assert_eq!(res.text().await.unwrap(), "body {}");
+    // Client-side routes get the app itself.
+    let res = client.get(app.url("/conversations/abc")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "<html>app</html>");
+
+    let res = client
+        .get(app.url("/api/conversations"))
+        .header("origin", "http://localhost:8080")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("access-control-allow-origin").is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn chat_requests_over_the_route_limits_are_refused() {
+    let chat = RouteLimit { timeout_secs: 1, max_concurrent: 1 };
+    let config = AppConfig {
+        route_limits: RouteLimits { chat, ..RouteLimits::default() },
+        ..AppConfig::default()
+    };
+    let agent = ScriptedAgent::replying(&["Hi!"]).taking(std::time::Duration::from_secs(3));
+    let app = TestApp::spawn_with(Arc::new(agent), config_store(config)).await;
+    let client = reqwest::Client::new();
+
+    let send = || client.post(app.url("/api/chat")).json(&json!({ "message": "Hello" })).send();
+    let (first, second) = tokio::join!(send(), send());
+    let mut statuses = [first.unwrap().status(), second.unwrap().status()];
+    statuses.sort();
+    // One turn runs into the timeout; the other finds the group full.
+    assert_eq!(statuses, [StatusCode::REQUEST_TIMEOUT, StatusCode::SERVICE_UNAVAILABLE]);
+
+    // Other groups are unaffected.
+    let res = client.get(app.url("/api/conversations")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn tenants_only_see_their_own_conversations() {
+    let db = TestDb::new().await;
+    let config = config_store(AppConfig { database_url: db.url.clone(), ..AppConfig::default() });
+    // Tenants named like parts of hosts that are not subdomains of the base
+    // domain, which must not reach them.
+    let names = ["acme", "globex", "127", "chat", "localhost"];
+    let tenants = TenantsConfig {
+        names: names.map(str::to_string).to_vec(),
+        base_domain: Some("chat.example.com".to_string()),
+        ..TenantsConfig::default()
+    };
+    let agent = Arc::new(ScriptedAgent::replying(&["Hi!"]));
+    let state = |config, pool| AppState::with_agent(config, pool, agent.clone());
+    let router = app::build_tenant_router_with(config.clone(), &tenants, state).await.unwrap();
+    let server = app::spawn_server(router, "127.0.0.1:0").await.unwrap();
+    let url = |path: &str| format!("{}{path}", server.url());
+    let client = reqwest::Client::new();
+
+    let res = client
+        .post(url("/api/chat"))
+        .header("host", "acme.chat.example.com")
+        .json(&json!({ "message": "Hello" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let count = |server: &app::EmbeddedServer, tenant: &'static str, host: bool| {
+        let request = client.get(format!("{}/api/conversations", server.url()));
+        let request = if host {
+            request.header("host", format!("{tenant}.chat.example.com"))
+        } else {
+            request.header("x-tenant", tenant)
+        };
+        async move {
+            let conversations: Vec<Value> = request.send().await.unwrap().json().await.unwrap();
+            conversations.len()
+        }
+    };
+    assert_eq!(count(&server, "acme", true).await, 1);
+    assert_eq!(count(&server, "globex", true).await, 0);
+
+    // Untrusted, the header can only agree with the Host.
+    let request = |host: &str, tenant: &str| {
+        client.get(url("/api/conversations")).header("host", host).header("x-tenant", tenant)
+    };
+    let res = request("acme.chat.example.com", "acme").send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    for (host, tenant) in [("globex.chat.example.com", "acme"), ("127.0.0.1:3000", "acme")] {
+        let res = request(host, tenant).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{host}");
+    }
+
+    let res = client
+        .get(url("/api/conversations"))
+        .header("host", "initech.chat.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let hosts =
+        ["127.0.0.1:3000", "[::1]:3000", "chat.example.com", "acme.example.org", "localhost"];
+    for host in hosts {
+        let res = client.get(url("/api/conversations")).header("host", host).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{host}");
+    }
+
+    // Behind a proxy setting it, the header names the tenant.
+    let trusted = TenantsConfig { trust_header: true, base_domain: None, ..tenants.clone() };
+    let router = app::build_tenant_router_with(config.clone(), &trusted, state).await.unwrap();
+    let proxied = app::spawn_server(router, "127.0.0.1:0").await.unwrap();
+    assert_eq!(count(&proxied, "acme", false).await, 1);
+    assert_eq!(count(&proxied, "globex", false).await, 0);
+    let untrusted = TenantsConfig { base_domain: None, ..tenants };
+    let err = app::build_tenant_router_with(config, &untrusted, state).await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidConfig { .. }), "{err}");
+    proxied.shutdown();
+    server.shutdown();
+}
@@ -0,0 +1,435 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::agent::ScriptedAgent;
+use common::{config_store, TestApp};
+use rust_ai_experiments::config::{AppConfig, ModerationAction};
+use rust_ai_experiments::errors::AppError;
+use rust_ai_experiments::models::{
+    AssistantPreset, ChatRequest, MessageRole, Source, UpdateConversationRequest, Verbosity,
+};
+use uuid::Uuid;
+
+fn request(conversation_id: Option<Uuid>, message: &str) -> ChatRequest {
+    ChatRequest {
+        conversation_id,
+        message: message.to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    }
+}
+
+#[tokio::test]
+async fn chat_persists_both_turns_and_replays_history() {
+    let agent = ScriptedAgent::replying(&["Hello", " there"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let first = app.service.chat(request(None, "Hi")).await.unwrap();
+    assert_eq!(first.message.content, "Hello there");
+    let conv_id = first.conversation_id;
+
+    app.service.chat(request(Some(conv_id), "And again")).await.unwrap();
+
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let roles: Vec<_> = messages.iter().map(|m| m.role).collect();
+    assert_eq!(
+        roles,
+        [MessageRole::User, MessageRole::Assistant, MessageRole::User, MessageRole::Assistant]
+    );
+
+    let seen = agent.seen();
+    assert_eq!(seen.len(), 2);
+    assert!(seen[0].history.is_empty());
+    assert_eq!(seen[0].conversation_title, "Hi");
+    // The second turn sees the first exchange but not its own user message.
+    assert_eq!(seen[1].history.len(), 2);
+    assert_eq!(seen[1].user_message, "And again");
+}
+
+#[tokio::test]
+async fn ephemeral_conversations_stay_out_of_the_database() {
+    let agent = ScriptedAgent::replying(&["noted"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let first = app
+        .service
+        .chat(ChatRequest { ephemeral: true, ..request(None, "Secret") })
+        .await
+        .unwrap();
+    let conv_id = first.conversation_id;
+    // Follow-ups don't need the flag; the conversation is already ephemeral.
+    app.service.chat(request(Some(conv_id), "More")).await.unwrap();
+
+    assert_eq!(agent.seen()[1].history.len(), 2);
+    assert_eq!(app.service.get_messages(conv_id).await.unwrap().len(), 4);
+    assert!(app.service.get_conversations().await.unwrap().is_empty());
+    let (stored,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM messages")
+        .fetch_one(&app.db.pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+
+    app.service.delete_conversation(conv_id).await.unwrap();
+    let err = app.service.get_messages(conv_id).await.unwrap_err();
+    assert!(err.is_not_found());
+}
+
+#[tokio::test]
+async fn verbosity_is_kept_for_later_turns() {
+    let agent = ScriptedAgent::replying(&["ok"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let detailed = Some(Verbosity::Detailed);
+    let first = app
+        .service
+        .chat(ChatRequest { verbosity: detailed, ..request(None, "Explain") })
+        .await
+        .unwrap();
+    app.service.chat(request(Some(first.conversation_id), "More")).await.unwrap();
+    app.service.regenerate(first.message.id).await.unwrap();
+
+    let seen: Vec<_> = agent.seen().iter().map(|ctx| ctx.preferences.verbosity).collect();
+    assert_eq!(seen, [Verbosity::Detailed; 3]);
+    let conversation = app.service.get_conversations().await.unwrap().remove(0);
+    assert_eq!(conversation.verbosity, Verbosity::Detailed);
+}
+
+#[tokio::test]
+async fn switching_presets_is_noted_and_applies_to_later_turns() {
+    let agent = ScriptedAgent::replying(&["ok"]);
+    let reviewer = AssistantPreset {
+        id: "code_reviewer".to_string(),
+        name: "Code Reviewer".to_string(),
+        system_prompt: Some("Review the code.".to_string()),
+        model: Some("coder".to_string()),
+    };
+    let config = AppConfig { presets: vec![reviewer], ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+    let switch = |preset: Option<&str>| UpdateConversationRequest {
+        preset: Some(preset.map(str::to_string)),
+        ..UpdateConversationRequest::default()
+    };
+
+    let conv_id = app.service.chat(request(None, "Hi")).await.unwrap().conversation_id;
+    let conversation =
+        app.service.update_conversation(conv_id, switch(Some("code_reviewer"))).await.unwrap();
+    assert_eq!(conversation.preset.as_deref(), Some("code_reviewer"));
+    app.service.chat(request(Some(conv_id), "Look at this")).await.unwrap();
+    app.service.update_conversation(conv_id, switch(None)).await.unwrap();
+    app.service.chat(request(Some(conv_id), "Thanks")).await.unwrap();
+
+    let seen = agent.seen();
+    assert_eq!(seen[0].preferences.model, None);
+    assert_eq!(seen[1].preferences.model.as_deref(), Some("coder"));
+    assert_eq!(seen[1].preferences.system_prompt.as_deref(), Some("Review the code."));
+    assert_eq!(seen[2].preferences.system_prompt, None);
+
+    let notes: Vec<_> = app
+        .service
+        .get_messages(conv_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.role == MessageRole::System)
+        .map(|m| m.content)
+        .collect();
+    assert_eq!(notes, ["Switched to Code Reviewer", "Switched to the default assistant"]);
+
+    let err = app.service.update_conversation(conv_id, switch(Some("pirate"))).await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidField { .. }), "got {err:?}");
+}
+
+#[tokio::test]
+async fn quotes_are_linked_to_the_quoted_message() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Use a HashMap."]))).await;
+    let first = app.service.chat(request(None, "How do I count words?")).await.unwrap();
+    let conv_id = first.conversation_id;
+
+    let quote = ChatRequest {
+        quoted_message_id: Some(first.message.id),
+        ..request(Some(conv_id), "> assistant wrote:\n> Use a HashMap.\n\nWhich one?")
+    };
+    app.service.chat(quote).await.unwrap();
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    assert_eq!(messages[2].quoted_message_id, Some(first.message.id));
+
+    // Only messages of the same conversation can be quoted.
+    let other = app.service.chat(request(None, "Elsewhere")).await.unwrap();
+    let stray = ChatRequest {
+        quoted_message_id: Some(other.message.id),
+        ..request(Some(conv_id), "Quoting another conversation")
+    };
+    let err = app.service.chat(stray).await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidField { .. }));
+}
+
+#[tokio::test]
+async fn chat_rejects_invalid_messages() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["unused"]))).await;
+
+    let err = app.service.chat(request(None, "   ")).await.unwrap_err();
+    assert!(matches!(err, AppError::EmptyField { .. }));
+
+    let too_long = "x".repeat(app.service.config().get().max_message_length + 1);
+    let err = app.service.chat(request(None, &too_long)).await.unwrap_err();
+    assert!(matches!(err, AppError::FieldTooLong { .. }));
+}
+
+#[tokio::test]
+async fn agent_failures_are_surfaced() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::failing("boom"))).await;
+
+    let err = app.service.chat(request(None, "Hi")).await.unwrap_err();
+    assert!(matches!(err, AppError::InferenceError { .. }));
+}
+
+#[tokio::test]
+async fn regenerate_replays_the_original_prompt() {
+    let agent = ScriptedAgent::replying(&["answer"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let first = app.service.chat(request(None, "Question one")).await.unwrap();
+    app.service
+        .chat(request(Some(first.conversation_id), "Question two"))
+        .await
+        .unwrap();
+
+    let regenerated = app.service.regenerate(first.message.id).await.unwrap();
+    assert_eq!((regenerated.active_version, regenerated.version_count), (2, 2));
+
+    let ctx = agent.seen().pop().unwrap();
+    assert_eq!(ctx.user_message, "Question one");
+    assert!(ctx.history.is_empty());
+
+    let versions = app.service.get_versions(first.message.id).await.unwrap();
+    assert_eq!(versions.len(), 2);
+
+    let user_message = &app.service.get_messages(first.conversation_id).await.unwrap()[0];
+    let err = app.service.regenerate(user_message.id).await.unwrap_err();
+    assert!(err.is_validation());
+}
+
+#[tokio::test]
+async fn conversation_language_is_detected_and_kept() {
+    let agent = ScriptedAgent::replying(&["Claro"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let first = app
+        .service
+        .chat(request(None, "¿Puedes explicarme cómo funciona la memoria en Rust?"))
+        .await
+        .unwrap();
+    let conv_id = first.conversation_id;
+    // Too short to classify: the stored language carries over.
+    app.service.chat(request(Some(conv_id), "ok")).await.unwrap();
+
+    let languages: Vec<_> = agent.seen().iter().map(|c| c.language.clone()).collect();
+    assert_eq!(languages, [Some("spa".to_string()), Some("spa".to_string())]);
+    let conversations = app.service.get_conversations().await.unwrap();
+    assert_eq!(conversations[0].language.as_deref(), Some("spa"));
+}
+
+#[tokio::test]
+async fn moderation_blocks_before_anything_is_saved() {
+    let agent = ScriptedAgent::replying(&["fine"]).flagging("hateful");
+    let config = AppConfig {
+        moderation_blocked_terms: vec!["free crypto".to_string()],
+        moderation_model: Some("llama-guard3".to_string()),
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+
+    let err = app.service.chat(request(None, "Get FREE crypto now!")).await.unwrap_err();
+    assert!(matches!(err, AppError::ModerationBlocked { .. }), "got {err:?}");
+    let err = app.service.chat(request(None, "Something hateful")).await.unwrap_err();
+    assert!(err.to_string().contains("hate"), "{err}");
+    // Terms match whole words only.
+    app.service.chat(request(None, "Is cryptography free software?")).await.unwrap();
+
+    assert_eq!(agent.seen().len(), 1);
+    assert_eq!(app.service.get_conversations().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn flagged_messages_are_still_answered() {
+    let agent = ScriptedAgent::replying(&["fine"]).flagging("hateful");
+    let config = AppConfig {
+        moderation_model: Some("llama-guard3".to_string()),
+        moderation_action: ModerationAction::Flag,
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+
+    let reply = app.service.chat(request(None, "Something hateful")).await.unwrap();
+    assert_eq!(reply.message.content, "fine");
+}
+
+#[tokio::test]
+async fn replies_are_post_processed_before_they_are_saved() {
+    let source = |url: &str| Source {
+        url: url.to_string(),
+        title: url.to_string(),
+        snippet: String::new(),
+        score: None,
+    };
+    let sources = vec![source("https://a.example"), source("https://b.example")];
+    let chunks = ["Darn, see [2] and [1].  \n\n\n\n", "`x[2]` then [2]\n"];
+    let agent = ScriptedAgent::replying(&chunks).with_sources(sources.clone());
+    let config = AppConfig {
+        post_processors: vec!["whitespace".into(), "citations".into(), "profanity".into()],
+        profanity_terms: vec!["darn".to_string()],
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(agent), config_store(config)).await;
+
+    let reply = app.service.chat(request(None, "Sources?")).await.unwrap();
+    let expected = "****, see [1] and [2].\n\n`x[2]` then [1]";
+    assert_eq!(reply.message.content, expected);
+    assert_eq!(reply.sources, [sources[1].clone(), sources[0].clone()]);
+
+    let messages = app.service.get_messages(reply.conversation_id).await.unwrap();
+    assert_eq!(messages[1].content, expected);
+    assert_eq!(messages[1].sources, reply.sources);
+}
+
+#[tokio::test]
+async fn history_is_limited_globally_and_per_conversation() {
+    let agent = ScriptedAgent::replying(&["ok"]);
+    let config = AppConfig { max_history_messages: 2, ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config_store(config)).await;
+
+    let first = app.service.chat(request(None, "One")).await.unwrap();
+    let conv_id = first.conversation_id;
+    assert_eq!(first.history_cutoff, None);
+    app.service.chat(request(Some(conv_id), "Two")).await.unwrap();
+    let third = app.service.chat(request(Some(conv_id), "Three")).await.unwrap();
+
+    // Only the last exchange is sent; the cut point is the first reply.
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let seen = agent.seen();
+    assert_eq!(seen[2].history.len(), 2);
+    assert_eq!(seen[2].history[0].content, "Two");
+    assert_eq!(third.history_cutoff, Some(messages[1].id));
+
+    // An override of 0 sends the whole conversation again.
+    let update = UpdateConversationRequest {
+        max_history_messages: Some(Some(0)),
+        ..UpdateConversationRequest::default()
+    };
+    let conversation = app.service.update_conversation(conv_id, update).await.unwrap();
+    assert_eq!(conversation.max_history_messages, Some(0));
+    let fourth = app.service.chat(request(Some(conv_id), "Four")).await.unwrap();
+    assert_eq!(agent.seen()[3].history.len(), 6);
+    assert_eq!(fourth.history_cutoff, None);
+
+    let update = UpdateConversationRequest {
+        max_history_messages: Some(Some(-1)),
+        ..UpdateConversationRequest::default()
+    };
+    let err = app.service.update_conversation(conv_id, update).await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidField { .. }), "got {err:?}");
+}
+
+#[tokio::test]
+async fn stateless_conversations_answer_each_turn_on_its_own() {
+    let agent = ScriptedAgent::replying(&["ok"]);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+
+    let conv_id = app.service.chat(request(None, "One")).await.unwrap().conversation_id;
+    let stateless = ChatRequest { stateless: Some(true), ..request(Some(conv_id), "Two") };
+    let second = app.service.chat(stateless).await.unwrap();
+    // Kept for later turns, which send no history either.
+    app.service.chat(request(Some(conv_id), "Three")).await.unwrap();
+
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let seen = agent.seen();
+    assert!(seen[1].history.is_empty());
+    assert!(seen[2].history.is_empty());
+    assert_eq!(second.history_cutoff, Some(messages[1].id));
+    let conversation = app.service.get_conversations().await.unwrap().remove(0);
+    assert!(conversation.stateless);
+
+    let update = UpdateConversationRequest {
+        stateless: Some(false),
+        ..UpdateConversationRequest::default()
+    };
+    let conversation = app.service.update_conversation(conv_id, update).await.unwrap();
+    assert!(!conversation.stateless);
+    app.service.chat(request(Some(conv_id), "Four")).await.unwrap();
+    assert_eq!(agent.seen()[3].history.len(), 6);
+}
+
+#[tokio::test]
+async fn conversations_are_summarized_every_few_messages() {
+    let agent = ScriptedAgent::replying(&["Hello"]);
+    let config = AppConfig { summary_interval: 4, ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(agent), config_store(config)).await;
+
+    let first = app.service.chat(request(None, "Hi")).await.unwrap();
+    app.service.chat(request(Some(first.conversation_id), "Tell me more")).await.unwrap();
+
+    // Summaries are written by a background task.
+    let mut summary = None;
+    for _ in 0..50 {
+        summary = app.service.get_conversations().await.unwrap()[0].summary.clone();
+        if summary.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(summary.as_deref(), Some("Summary of user: Hi"));
+}
+
+#[tokio::test]
+async fn replies_get_follow_up_suggestions_in_the_background() {
+    let agent = ScriptedAgent::replying(&["Hello"]);
+    let config = AppConfig { follow_up_suggestions: 2, ..AppConfig::default() };
+    let app = TestApp::spawn_with(Arc::new(agent), config_store(config)).await;
+
+    let reply = app.service.chat(request(None, "Hi")).await.unwrap();
+    let mut suggestions = Vec::new();
+    for _ in 0..50 {
+        let messages = app.service.get_messages(reply.conversation_id).await.unwrap();
+        suggestions = messages[1].suggestions.clone();
+        if !suggestions.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(suggestions, ["Follow-up 1", "Follow-up 2"]);
+
+    // Only the newest reply gets them.
+    let first = reply.message.id;
+    let suggested = app.service.suggest_follow_ups(reply.conversation_id, first).await.unwrap();
+    assert_eq!(suggested.len(), 2);
+    app.service.chat(request(Some(reply.conversation_id), "More")).await.unwrap();
+    let suggested = app.service.suggest_follow_ups(reply.conversation_id, first).await.unwrap();
+    assert!(suggested.is_empty());
+}
+
+#[tokio::test]
+async fn transcripts_rotate_by_size() {
+    let dir = std::env::temp_dir().join(format!("transcripts-{}", uuid::Uuid::new_v4()));
+    let config = AppConfig {
+        transcript_dir: Some(dir.clone()),
+        transcript_max_bytes: 1,
+        transcript_max_files: 1,
+        ..AppConfig::default()
+    };
+    let app = TestApp::spawn_with(Arc::new(ScriptedAgent::replying(&["ok"])), config_store(config))
+        .await;
+
+    let first = app.service.chat(request(None, "one")).await.unwrap();
+    let id = first.conversation_id;
+    app.service.chat(request(Some(id), "two")).await.unwrap();
+    app.service.chat(request(Some(id), "three")).await.unwrap();
+
+    let lines = |name: String| std::fs::read_to_string(dir.join(name)).unwrap().lines().count();
+    assert_eq!(lines(format!("{id}.jsonl")), 1);
+    assert_eq!(lines(format!("{id}.jsonl.1")), 1);
+    assert!(!dir.join(format!("{id}.jsonl.2")).exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,349 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use rust_ai_experiments::agent::{AgentService, StreamOutcome, StreamUpdate};
+use rust_ai_experiments::errors::AppError;
+use rust_ai_experiments::models::{
+    ChatContext, FinishReason, FirstTokenLatency, GenerationInfo, LoadedModel, Message, MessageRole,
+    ModelInfo, PullProgress, Source, ToolCallStatus,
+};
+use tokio::sync::{mpsc, Barrier};
+
+/// An [`AgentService`] that answers every turn with the same chunks and
+/// records the contexts it was given.
+#[derive(Clone, Default)]
+pub struct ScriptedAgent {
+    chunks: Vec<String>,
+    sources: Vec<Source>,
+    fail_with: Option<String>,
+    /// Streams wait here before sending chunks, to prove turns overlap.
+    barrier: Option<Arc<Barrier>>,
+    /// Non-streamed turns sleep this long before answering.
+    delay: Option<Duration>,
+    /// Streamed turns sleep this long before each chunk.
+    pacing: Option<Duration>,
+    /// While set, turns fail with `ModelNotFound`; a pull clears it.
+    model_missing: Arc<AtomicBool>,
+    /// Moderation judges messages containing this word unsafe (category S10).
+    unsafe_word: Option<String>,
+    /// Replies used instead of `chunks` when a turn asks for a given model.
+    model_replies: Vec<(String, String)>,
+    /// Reply to non-streamed turns that ask for no particular model.
+    chat_reply: Option<String>,
+    /// Streamed turns report one call to this tool: name, arguments, result.
+    tool_call: Option<(String, String, String)>,
+    /// Streamed turns run out of context after sending this many chunks.
+    context_limit: Option<usize>,
+    seen: Arc<Mutex<Vec<ChatContext>>>,
+}
+
+impl ScriptedAgent {
+    pub fn replying(chunks: &[&str]) -> Self {
+        Self {
+            chunks: chunks.iter().map(|c| c.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn failing(message: &str) -> Self {
+        Self { fail_with: Some(message.to_string()), ..Self::default() }
+    }
+
+    /// Every streamed turn waits until `streams` turns are streaming at once.
+    pub fn concurrent(mut self, streams: usize) -> Self {
+        self.barrier = Some(Arc::new(Barrier::new(streams)));
+        self
+    }
+
+    /// Non-streamed turns take `delay` to answer.
+    pub fn taking(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Streamed turns send a chunk every `pacing`, so they can be caught midway.
+    pub fn pacing(mut self, pacing: Duration) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
+    /// Turns fail with `ModelNotFound` until the model is pulled.
+    pub fn without_model(self) -> Self {
+        self.model_missing.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Moderation flags messages containing `word`.
+    pub fn flagging(mut self, word: &str) -> Self {
+        self.unsafe_word = Some(word.to_string());
+        self
+    }
+
+    /// Non-streamed turns that ask for `model` are answered with `reply`.
+    pub fn answering_with(mut self, model: &str, reply: &str) -> Self {
+        self.model_replies.push((model.to_string(), reply.to_string()));
+        self
+    }
+
+    /// Non-streamed turns are answered with `reply` instead of the chunks.
+    pub fn answering(mut self, reply: &str) -> Self {
+        self.chat_reply = Some(reply.to_string());
+        self
+    }
+
+    /// Streamed turns report calling `tool` with `arguments`, returning `result`.
+    pub fn calling_tool(mut self, tool: &str, arguments: &str, result: &str) -> Self {
+        self.tool_call = Some((tool.to_string(), arguments.to_string(), result.to_string()));
+        self
+    }
+
+    /// Streamed turns fail with `ContextTooLarge` after sending `chunks`
+    /// chunks.
+    pub fn out_of_context_after(mut self, chunks: usize) -> Self {
+        self.context_limit = Some(chunks);
+        self
+    }
+
+    pub fn with_sources(mut self, sources: Vec<Source>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Contexts received so far, oldest first.
+    pub fn seen(&self) -> Vec<ChatContext> {
+        self.seen.lock().unwrap().clone()
+    }
+
+    fn record(&self, ctx: &ChatContext) -> Result<(), AppError> {
+        if self.model_missing.load(Ordering::SeqCst) {
+            return Err(AppError::ModelNotFound { model_name: "llama3.2".to_string() });
+        }
+        self.seen.lock().unwrap().push(ctx.clone());
+        match &self.fail_with {
+            Some(message) => Err(AppError::InferenceError { message: message.clone() }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AgentService for ScriptedAgent {
+    fn chat<'a>(&'a self, ctx: &'a ChatContext) -> BoxFuture<'a, Result<Message, AppError>> {
+        Box::pin(async move {
+            self.record(ctx)?;
+            if let Some(delay) = self.delay {
+                tokio::time::sleep(delay).await;
+            }
+            let reply = self
+                .model_replies
+                .iter()
+                .find(|(model, _)| ctx.preferences.model.as_deref() == Some(model.as_str()))
+                .map(|(_, reply)| reply.clone())
+                .or_else(|| self.chat_reply.clone())
+                .unwrap_or_else(|| self.chunks.concat());
+            Ok(Message::new(ctx.conversation_id, MessageRole::Assistant, reply)
+                .with_sources(self.sources.clone()))
+        })
+    }
+
+    fn stream_chat<'a>(
+        &'a self,
+        ctx: &'a ChatContext,
+        tx: mpsc::Sender<StreamUpdate>,
+    ) -> BoxFuture<'a, Result<StreamOutcome, AppError>> {
+        Box::pin(async move {
+            self.record(ctx)?;
+            if let Some(barrier) = &self.barrier {
+                barrier.wait().await;
+            }
+            if let Some((tool, _, _)) = &self.tool_call {
+                let progress = [(ToolCallStatus::Running, None), (ToolCallStatus::Done, Some(5))];
+                for (status, duration_ms) in progress {
+                    let call_id = "call-1".to_string();
+                    let update = StreamUpdate::Tool { call_id, name: tool.clone(), status, duration_ms };
+                    let _ = tx.send(update).await;
+                }
+            }
+            let mut finish_reason = FinishReason::Stop;
+            for (sent, chunk) in self.chunks.iter().enumerate() {
+                if self.context_limit == Some(sent) {
+                    return Err(AppError::ContextTooLarge {
+                        model_name: "scripted".to_string(),
+                        message: "context window exceeded".to_string(),
+                    });
+                }
+                if let Some(pacing) = self.pacing {
+                    tokio::time::sleep(pacing).await;
+                }
+                if tx.send(StreamUpdate::Chunk(chunk.clone())).await.is_err() {
+                    finish_reason = FinishReason::Cancelled;
+                    break;
+                }
+            }
+            let tool_messages = match &self.tool_call {
+                Some((tool, arguments, result)) => vec![
+                    (MessageRole::Function, arguments.clone()),
+                    (MessageRole::Tool, result.clone()),
+                ]
+                .into_iter()
+                .enumerate()
+                .map(|(i, (role, content))| {
+                    let id = "call-1".to_string();
+                    let mut message =
+                        Message::tool(ctx.conversation_id, role, tool.clone(), id, content);
+                    // Keep the call ordered before its result.
+                    message.created_at += chrono::Duration::milliseconds(i as i64);
+                    message
+                })
+                .collect(),
+                None => Vec::new(),
+            };
+            Ok(StreamOutcome {
+                sources: self.sources.clone(),
+                tool_messages,
+                model: "scripted".to_string(),
+                prompt_tokens: Some(ctx.user_message.len() as u64),
+                completion_tokens: Some(self.chunks.len() as u64),
+                finish_reason,
+            })
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<ModelInfo>, AppError>> {
+        Box::pin(async {
+            Ok(vec![ModelInfo { name: "scripted".to_string(), size: None, modified_at: None }])
+        })
+    }
+
+    fn loaded_models(&self) -> BoxFuture<'_, Result<Vec<LoadedModel>, AppError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn warm_up<'a>(&'a self, _model: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn pull_model<'a>(
+        &'a self,
+        _model: &'a str,
+        tx: mpsc::Sender<PullProgress>,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let steps = [("pulling manifest", None), ("downloading", Some(50)), ("success", None)];
+            for (status, completed) in steps {
+                let progress = PullProgress {
+                    status: status.to_string(),
+                    digest: None,
+                    total: completed.map(|_| 100),
+                    completed,
+                };
+                let _ = tx.send(progress).await;
+            }
+            self.model_missing.store(false, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    fn moderate<'a>(
+        &'a self,
+        _model: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(async move {
+            let flagged = self.unsafe_word.as_deref().is_some_and(|w| text.contains(w));
+            Ok(flagged.then(|| "S10".to_string()))
+        })
+    }
+
+    /// Echoes the transcript's first line, so tests can see what was covered.
+    fn summarize<'a>(
+        &'a self,
+        _model: &'a str,
+        transcript: &'a str,
+    ) -> BoxFuture<'a, Result<String, AppError>> {
+        let first = transcript.lines().next().unwrap_or_default();
+        Box::pin(async move { Ok(format!("Summary of {first}")) })
+    }
+
+    /// Numbered follow-ups, so tests can check how many were asked for.
+    fn suggest_follow_ups<'a>(
+        &'a self,
+        _model: &'a str,
+        _transcript: &'a str,
+        count: usize,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>> {
+        Box::pin(async move { Ok((1..=count).map(|n| format!("Follow-up {n}")).collect()) })
+    }
+
+    /// Bag-of-words vectors: texts sharing words point the same way, so
+    /// retrieval finds chunks that mention what the query does.
+    fn embed<'a>(
+        &'a self,
+        _model: &'a str,
+        texts: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<Vec<f32>>, AppError>> {
+        Box::pin(async move {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut vector = vec![0.0; 1024];
+                    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_') {
+                        if !word.is_empty() {
+                            let hash = word.to_lowercase().bytes().fold(7u32, |h, b| {
+                                h.wrapping_mul(31).wrapping_add(b as u32)
+                            });
+                            vector[hash as usize % 1024] += 1.0;
+                        }
+                    }
+                    vector
+                })
+                .collect())
+        })
+    }
+
+    /// Share of the query's words found in each document.
+    fn rerank<'a>(
+        &'a self,
+        _model: &'a str,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<f32>, AppError>> {
+        let words: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let scores = documents
+            .iter()
+            .map(|document| {
+                let document = document.to_lowercase();
+                let found = words.iter().filter(|w| document.contains(w.as_str())).count();
+                found as f32 / words.len().max(1) as f32
+            })
+            .collect();
+        Box::pin(async move { Ok(scores) })
+    }
+
+    fn generations(&self) -> Vec<GenerationInfo> {
+        Vec::new()
+    }
+
+    fn cancel_generation(&self, _id: &str) -> bool {
+        false
+    }
+
+    fn first_token_latency(&self) -> FirstTokenLatency {
+        FirstTokenLatency {
+            p95_ms: None,
+            threshold_ms: 0,
+            window_secs: 0,
+            samples: 0,
+            degraded: false,
+        }
+    }
+
+    fn tools(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Starts a mock Ollama whose `/api/chat` answers with `chunks`: streamed as
+/// NDJSON when the request asks for a stream, as one JSON body otherwise.
+/// `/api/tags` lists a single model, `/api/ps` has it loaded, `/api/generate`
+/// loads it and `/api/pull` reports a short download.
+pub async fn mock_ollama(chunks: &[&str]) -> MockServer {
+    slow_ollama(chunks, Duration::ZERO).await
+}
+
+/// [`mock_ollama`] taking `delay` to start each streamed reply.
+pub async fn slow_ollama(chunks: &[&str], delay: Duration) -> MockServer {
+    let server = MockServer::start().await;
+
+    let mut ndjson = String::new();
+    for chunk in chunks {
+        ndjson.push_str(&chat_line(chunk, false).to_string());
+        ndjson.push('\n');
+    }
+    let mut done = chat_line("", true);
+    done["done_reason"] = json!("stop");
+    done["prompt_eval_count"] = json!(12);
+    done["eval_count"] = json!(chunks.len());
+    ndjson.push_str(&done.to_string());
+    ndjson.push('\n');
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(json!({ "stream": true })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(ndjson, "application/x-ndjson")
+                .set_delay(delay),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(chat_line(&chunks.concat(), true)))
+        .mount(&server)
+        .await;
+
+    let pull = [
+        json!({ "status": "pulling manifest" }),
+        json!({ "status": "pulling 6a0746a1ec1a", "total": 2000, "completed": 1000 }),
+        json!({ "status": "success" }),
+    ];
+    let pull: String = pull.iter().map(|line| format!("{line}\n")).collect();
+    Mock::given(method("POST"))
+        .and(path("/api/pull"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(pull, "application/x-ndjson"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/tags"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "models": [{ "name": "llama3.2:latest", "size": 2019393189, "modified_at": "2024-01-01T00:00:00Z" }]
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "model": "llama3.2", "response": "", "done": true, "done_reason": "load"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/ps"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "models": [{
+                "name": "llama3.2:latest",
+                "model": "llama3.2:latest",
+                "size": 3500000000u64,
+                "size_vram": 3000000000u64,
+                "expires_at": "2024-01-01T00:05:00Z"
+            }]
+        })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+fn chat_line(content: &str, done: bool) -> Value {
+    json!({
+        "model": "llama3.2",
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": { "role": "assistant", "content": content },
+        "done": done,
+    })
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::agent::ScriptedAgent;
+use common::TestApp;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+/// One side of an MCP session: the SSE stream plus the URL to POST to.
+struct McpClient {
+    http: reqwest::Client,
+    events: reqwest::Response,
+    buffer: String,
+    endpoint: String,
+    next_id: u64,
+}
+
+impl McpClient {
+    async fn connect(app: &TestApp) -> Self {
+        let http = reqwest::Client::new();
+        let events = http.get(app.url("/mcp/sse")).send().await.unwrap();
+        let mut client =
+            Self { http, events, buffer: String::new(), endpoint: String::new(), next_id: 0 };
+        let (event, data) = client.next_event().await;
+        assert_eq!(event, "endpoint");
+        client.endpoint = app.url(&data);
+        client
+    }
+
+    /// Reads the next SSE event as (name, data), skipping keep-alives.
+    async fn next_event(&mut self) -> (String, String) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let raw: String = self.buffer.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    raw.lines().find_map(|l| l.strip_prefix(name)).map(|v| v.trim().to_string())
+                };
+                if let (Some(event), Some(data)) = (field("event:"), field("data:")) {
+                    return (event, data);
+                }
+                continue;
+            }
+            let chunk = self.events.chunk().await.unwrap().expect("SSE stream ended");
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    /// Sends a JSON-RPC request and returns the response from the stream.
+    async fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let resp = self.http.post(&self.endpoint).json(&body).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let (event, data) = self.next_event().await;
+        assert_eq!(event, "message");
+        let response: Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(response["id"], id);
+        response
+    }
+}
+
+#[tokio::test]
+async fn mcp_clients_read_conversations_and_send_messages() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hi!"]))).await;
+    let mut mcp = McpClient::connect(&app).await;
+
+    let init = mcp.request("initialize", json!({ "protocolVersion": "2024-11-05" })).await;
+    assert_eq!(init["result"]["protocolVersion"], "2024-11-05");
+    assert!(init["result"]["capabilities"]["tools"].is_object());
+
+    let tools = mcp.request("tools/list", json!({})).await;
+    assert_eq!(tools["result"]["tools"][0]["name"], "send_message");
+
+    let call = json!({ "name": "send_message", "arguments": { "message": "Hello" } });
+    let sent = mcp.request("tools/call", call).await;
+    assert_eq!(sent["result"]["isError"], false);
+    assert_eq!(sent["result"]["content"][0]["text"], "Hi!");
+    let conversation = sent["result"]["content"][1]["text"].as_str().unwrap();
+    let conv_id = conversation.strip_prefix("conversation_id: ").unwrap();
+
+    let listed = mcp.request("resources/list", json!({})).await;
+    let uri = format!("conversation://{conv_id}");
+    assert_eq!(listed["result"]["resources"][0]["uri"], uri.as_str());
+    assert_eq!(listed["result"]["resources"][0]["name"], "Hello");
+
+    let read = mcp.request("resources/read", json!({ "uri": uri })).await;
+    let messages: Value =
+        serde_json::from_str(read["result"]["contents"][0]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(messages[0]["content"], "Hello");
+    assert_eq!(messages[1]["content"], "Hi!");
+
+    let missing = mcp.request("resources/read", json!({ "uri": "conversation://nope" })).await;
+    assert_eq!(missing["error"]["code"], -32602);
+    let unknown = mcp.request("prompts/list", json!({})).await;
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    let resp = reqwest::Client::new()
+        .post(app.url("/mcp/messages?session_id=nope"))
+        .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,731 @@
+//! The real [`OllamaAgentService`] against a wiremock Ollama.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::ollama::{mock_ollama, slow_ollama};
+use common::{config_store, test_config, TestApp, TestDb};
+use futures_util::future::BoxFuture;
+use futures_util::{SinkExt, StreamExt};
+use rust_ai_experiments::agent::preamble::{ContextBlock, ContextKind, ContextProvider};
+use rust_ai_experiments::agent::{AgentService, OllamaAgentService, StreamUpdate};
+use rust_ai_experiments::config::AppConfig;
+use rust_ai_experiments::db::workspace_read_repository::WorkspaceReadRepository;
+use rust_ai_experiments::errors::AppError;
+use rust_ai_experiments::models::{
+    ChatContext, ChatRequest, FinishReason, Message as StoredMessage, MessageRole, ResponseFormat,
+    RetrievedChunk, ToolCallStatus, TurnPreferences, Verbosity, WebhookTool,
+};
+use rust_ai_experiments::service::workspace::Workspace;
+use rust_ai_experiments::telemetry::{self, OtlpConfig};
+use rust_ai_experiments::tools::workspace::WorkspaceAccess;
+use rust_ai_experiments::tools::ToolRegistry;
+use serde_json::{json, Value};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing_subscriber::layer::SubscriberExt;
+use uuid::Uuid;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const CONVERSATION_ID: Uuid = Uuid::from_u128(0xc1);
+
+fn context(message: &str) -> ChatContext {
+    ChatContext {
+        conversation_id: CONVERSATION_ID,
+        conversation_title: "Test".to_string(),
+        user_name: None,
+        language: None,
+        history: Vec::new(),
+        history_cutoff: None,
+        user_message: message.to_string(),
+        preferences: TurnPreferences::default(),
+        webhook_tools: Vec::new(),
+        retrieved: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn chat_returns_the_model_reply() {
+    let ollama = mock_ollama(&["The answer", " is 42."]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+
+    let reply = agent.chat(&context("What is the answer?")).await.unwrap();
+    assert_eq!(reply.content, "The answer is 42.");
+
+    let requests = ollama.received_requests().await.unwrap();
+    let body: Value = requests[0].body_json().unwrap();
+    assert_eq!(body["model"], "llama3.2");
+}
+
+#[tokio::test]
+async fn warm_up_loads_the_model_and_every_request_keeps_it_alive() {
+    let ollama = mock_ollama(&["Hi"]).await;
+    let config = config_store(AppConfig {
+        ollama_base_url: ollama.uri(),
+        keep_alive: Some("30m".to_string()),
+        ..AppConfig::default()
+    });
+    let agent = OllamaAgentService::new(config, ToolRegistry::new());
+
+    agent.warm_up("llama3.2").await.unwrap();
+    agent.chat(&context("Hello")).await.unwrap();
+
+    let requests = ollama.received_requests().await.unwrap();
+    assert_eq!(requests[0].url.path(), "/api/generate");
+    let warm_up: Value = requests[0].body_json().unwrap();
+    assert_eq!(warm_up, json!({ "model": "llama3.2", "keep_alive": "30m" }));
+    let chat: Value = requests[1].body_json().unwrap();
+    assert_eq!(chat["keep_alive"], "30m");
+    assert!(chat["options"].get("keep_alive").is_none(), "{chat}");
+}
+
+#[tokio::test]
+async fn preferences_override_model_temperature_prompt_and_length() {
+    let ollama = mock_ollama(&["Ahoy"]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let mut ctx = context("Hello");
+    ctx.preferences = TurnPreferences {
+        model: Some("mistral".to_string()),
+        system_prompt: Some("You are a ship's cook.".to_string()),
+        temperature: Some(0.3),
+        custom_instructions: Some("Talk like a pirate.".to_string()),
+        response_format: ResponseFormat::Json,
+        verbosity: Verbosity::Concise,
+    };
+
+    agent.chat(&ctx).await.unwrap();
+
+    let requests = ollama.received_requests().await.unwrap();
+    let body: Value = requests[0].body_json().unwrap();
+    assert_eq!(body["model"], "mistral");
+    assert_eq!(body["options"]["temperature"], 0.3);
+    let system = body["messages"][0]["content"].as_str().unwrap();
+    assert!(system.starts_with("You are a ship's cook."), "{system}");
+    assert!(system.contains("## Custom instructions\nTalk like a pirate."), "{system}");
+    assert!(system.contains("single JSON object"), "{system}");
+    assert!(system.contains("Keep replies short"), "{system}");
+    assert_eq!(body["options"]["num_predict"], 400);
+    assert_eq!(body["format"], json!({ "type": "object" }));
+}
+
+/// Adds standing instructions of its own, registered after the built-in
+/// retrieval provider.
+struct HouseRules;
+
+impl ContextProvider for HouseRules {
+    fn blocks<'a>(&'a self, _ctx: &'a ChatContext) -> BoxFuture<'a, Vec<ContextBlock>> {
+        Box::pin(async {
+            vec![ContextBlock {
+                kind: ContextKind::Instructions,
+                title: "House rules".to_string(),
+                content: "Never deploy on Fridays.".to_string(),
+            }]
+        })
+    }
+}
+
+#[tokio::test]
+async fn context_blocks_are_ordered_by_kind_whichever_provider_made_them() {
+    let ollama = mock_ollama(&["Noted"]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new())
+        .with_context_provider(Arc::new(HouseRules));
+    let mut ctx = context("Where is the retry logic?");
+    ctx.preferences.custom_instructions = Some("Answer in one paragraph.".to_string());
+    ctx.retrieved = vec![RetrievedChunk {
+        chunk_id: Uuid::from_u128(1),
+        document_id: Uuid::from_u128(2),
+        collection: "code".to_string(),
+        path: "src/retry.rs".to_string(),
+        language: Some("rust".to_string()),
+        content: "fn retry() {}".to_string(),
+        start_line: 3,
+        end_line: 3,
+        score: 1.0,
+        vector_rank: Some(1),
+        text_rank: None,
+        relevant: None,
+    }];
+
+    agent.chat(&ctx).await.unwrap();
+
+    let requests = ollama.received_requests().await.unwrap();
+    let body: Value = requests[0].body_json().unwrap();
+    let system = body["messages"][0]["content"].as_str().unwrap();
+    let place = |title: &str| system.find(&format!("## {title}\n")).expect(title);
+    assert!(place("Custom instructions") < place("House rules"), "{system}");
+    assert!(place("House rules") < place("Relevant code from the indexed codebase"), "{system}");
+    assert!(system.contains("src/retry.rs (lines 3-3):\n```rust\nfn retry() {}\n```"), "{system}");
+}
+
+#[tokio::test]
+async fn stream_chat_forwards_chunks() {
+    let ollama = mock_ollama(&["one ", "two ", "three"]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+
+    let outcome = agent.stream_chat(&context("Count"), tx).await.unwrap();
+    assert!(outcome.sources.is_empty());
+    assert_eq!(outcome.model, "llama3.2");
+    assert_eq!(outcome.prompt_tokens, Some(12));
+    assert_eq!(outcome.completion_tokens, Some(3));
+    assert_eq!(outcome.finish_reason, FinishReason::Stop);
+
+    let mut text = String::new();
+    while let Some(update) = rx.recv().await {
+        if let StreamUpdate::Chunk(chunk) = update {
+            text.push_str(&chunk);
+        }
+    }
+    assert_eq!(text, "one two three");
+}
+
+#[tokio::test]
+async fn list_models_reads_tags() {
+    let ollama = mock_ollama(&[]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+
+    let models = agent.list_models().await.unwrap();
+    assert_eq!(models.len(), 1);
+    assert_eq!(models[0].name, "llama3.2:latest");
+}
+
+#[tokio::test]
+async fn system_status_reports_loaded_models_and_host_stats() {
+    let ollama = mock_ollama(&[]).await;
+    let app = TestApp::spawn_with_ollama(&ollama).await;
+
+    let status: Value = reqwest::get(app.url("/api/system/status"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["current_model"], "llama3.2");
+    assert_eq!(status["ollama_reachable"], true);
+    assert_eq!(status["loaded_models"][0]["name"], "llama3.2:latest");
+    assert_eq!(status["loaded_models"][0]["size_vram"], 3000000000u64);
+    assert!(status["host"]["memory_total_bytes"].as_u64().unwrap() > 0);
+    assert!(status["host"]["cpus"].as_u64().unwrap() > 0);
+
+    // Without Ollama the host is still reported.
+    let config = test_config("http://127.0.0.1:9");
+    let agent = OllamaAgentService::new(config.clone(), ToolRegistry::new());
+    let app = TestApp::spawn_with(Arc::new(agent), config).await;
+    let status: Value = reqwest::get(app.url("/api/system/status"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["ollama_reachable"], false);
+    assert_eq!(status["loaded_models"], json!([]));
+    assert!(status["host"]["memory_total_bytes"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn pull_model_forwards_progress() {
+    let ollama = mock_ollama(&[]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+
+    agent.pull_model("llama3.2", tx).await.unwrap();
+
+    let mut statuses = Vec::new();
+    while let Some(progress) = rx.recv().await {
+        statuses.push(progress.status);
+    }
+    assert_eq!(statuses, ["pulling manifest", "pulling 6a0746a1ec1a", "success"]);
+}
+
+#[tokio::test]
+async fn missing_model_is_reported_as_not_found() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": "model \"llama3.2\" not found, try pulling it first"
+        })))
+        .mount(&ollama)
+        .await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let (tx, _rx) = tokio::sync::mpsc::channel(16);
+
+    let err = agent.stream_chat(&context("Hi"), tx).await.unwrap_err();
+    assert!(matches!(err, AppError::ModelNotFound { .. }), "got {err:?}");
+}
+
+#[tokio::test]
+async fn unreachable_ollama_is_reported_as_unavailable() {
+    // Nothing listens on the discard port.
+    let agent = OllamaAgentService::new(test_config("http://127.0.0.1:9"), ToolRegistry::new());
+
+    let err = agent.chat(&context("Hello?")).await.unwrap_err();
+    assert!(matches!(err, AppError::OllamaUnavailable { .. }), "got {err:?}");
+}
+
+#[tokio::test]
+async fn turns_too_big_for_memory_are_retried_with_half_the_history() {
+    let ollama = mock_ollama(&["ok"]).await;
+    // Prompts over four messages, system prompt included, do not fit.
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(|request: &wiremock::Request| {
+            let body: Value = request.body_json().unwrap();
+            body["messages"].as_array().is_some_and(|messages| messages.len() > 4)
+        })
+        .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+            "error": "model requires more system memory (9.2 GiB) than is available (4.1 GiB)"
+        })))
+        .with_priority(1)
+        .mount(&ollama)
+        .await;
+    let app = TestApp::spawn_with_ollama(&ollama).await;
+    let request = |conversation_id, message: &str| ChatRequest {
+        conversation_id,
+        message: message.to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+
+    let conv_id = app.service.chat(request(None, "One")).await.unwrap().conversation_id;
+    app.service.chat(request(Some(conv_id), "Two")).await.unwrap();
+    let third = app.service.chat(request(Some(conv_id), "Three")).await.unwrap();
+    assert_eq!(third.message.content, "ok");
+
+    // The note goes before the reply; the cutoff is the first reply.
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    assert_eq!(third.history_cutoff, Some(messages[1].id));
+    assert_eq!(messages[5].role, MessageRole::System);
+    assert!(messages[5].content.starts_with("Only the last 2 earlier messages"));
+    assert_eq!(messages[6].id, third.message.id);
+}
+
+#[tokio::test]
+async fn end_to_end_over_rest_and_websocket() {
+    let ollama = mock_ollama(&["Hi", " from", " Ollama"]).await;
+    let app = TestApp::spawn_with_ollama(&ollama).await;
+
+    let reply = app
+        .service
+        .chat(ChatRequest {
+            conversation_id: None,
+            message: "Hello".to_string(),
+            ephemeral: false,
+            verbosity: None,
+            stateless: None,
+            quoted_message_id: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(reply.message.content, "Hi from Ollama");
+
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+    let request = json!({ "message": "Again", "conversation_id": reply.conversation_id });
+    socket.send(Message::Text(request.to_string().into())).await.unwrap();
+
+    let mut last = Value::Null;
+    while let Some(Ok(Message::Text(text))) = socket.next().await {
+        last = serde_json::from_str(&text).unwrap();
+        if matches!(last["type"].as_str(), Some("stream_end" | "error")) {
+            break;
+        }
+    }
+    assert_eq!(last["type"], "stream_end", "got {last}");
+    assert_eq!(last["full_content"], "Hi from Ollama");
+}
+
+#[tokio::test]
+async fn stored_tool_calls_are_replayed_to_ollama() {
+    let ollama = mock_ollama(&["Done"]).await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let tool = |role, content: &str| {
+        let (name, id) = ("web_search".to_string(), "call-1".to_string());
+        StoredMessage::tool(CONVERSATION_ID, role, name, id, content.to_string())
+    };
+    let mut ctx = context("Thanks");
+    ctx.history = vec![
+        StoredMessage::new(CONVERSATION_ID, MessageRole::User, "Weather?".to_string()),
+        tool(MessageRole::Function, r#"{"query":"weather"}"#),
+        tool(MessageRole::Tool, "It is sunny"),
+        StoredMessage::new(CONVERSATION_ID, MessageRole::Assistant, "Sunny.".to_string()),
+    ];
+
+    agent.chat(&ctx).await.unwrap();
+
+    let requests = ollama.received_requests().await.unwrap();
+    let body: Value = requests[0].body_json().unwrap();
+    let messages = body["messages"].as_array().unwrap();
+    let call = messages.iter().find(|m| m["tool_calls"].as_array().is_some_and(|c| !c.is_empty()));
+    let call = call.expect("no assistant tool call sent");
+    assert_eq!(call["tool_calls"][0]["function"]["name"], "web_search");
+    assert_eq!(call["tool_calls"][0]["function"]["arguments"]["query"], "weather");
+    let result = messages.iter().find(|m| m["role"] == "tool").expect("no tool result sent");
+    assert_eq!(result["tool_name"], "web_search");
+    assert_eq!(result["content"], "It is sunny");
+}
+
+#[tokio::test]
+async fn webhook_tools_are_called_with_the_model_arguments() {
+    let ollama = mock_ollama(&["Order 7 has shipped."]).await;
+    // The first turn asks for the tool; later ones fall through to the reply.
+    let tool_call = json!({
+        "model": "llama3.2",
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": {
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{ "function": { "name": "lookup", "arguments": { "id": "7" } } }],
+        },
+        "done": true,
+    });
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(tool_call))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&ollama)
+        .await;
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/lookup"))
+        .and(header("authorization", "Bearer secret"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": "shipped" })))
+        .mount(&webhook)
+        .await;
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    let mut ctx = context("Where is order 7?");
+    ctx.webhook_tools = vec![WebhookTool {
+        id: Uuid::new_v4(),
+        conversation_id: None,
+        name: "lookup".to_string(),
+        description: "Looks up an order".to_string(),
+        parameters: json!({ "type": "object", "properties": { "id": { "type": "string" } } }),
+        url: format!("{}/lookup", webhook.uri()),
+        auth_header: Some("Bearer secret".to_string()),
+        created_at: chrono::Utc::now(),
+    }];
+
+    let reply = agent.chat(&ctx).await.unwrap();
+    assert_eq!(reply.content, "Order 7 has shipped.");
+
+    let calls = webhook.received_requests().await.unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].body_json::<Value>().unwrap(), json!({ "id": "7" }));
+    let requests = ollama.received_requests().await.unwrap();
+    let first: Value = requests[0].body_json().unwrap();
+    assert_eq!(first["tools"][0]["function"]["name"], "lookup");
+    let second: Value = requests[1].body_json().unwrap();
+    let result = second["messages"].as_array().unwrap().iter().find(|m| m["role"] == "tool");
+    let result = result.expect("no tool result sent");
+    assert!(result["content"].as_str().unwrap().contains("shipped"), "{result}");
+}
+
+#[tokio::test]
+async fn tool_calls_asked_for_together_run_at_once_and_answer_in_order() {
+    let ollama = mock_ollama(&["Done."]).await;
+    let call = |name: &str| json!({ "function": { "name": name, "arguments": {} } });
+    let tool_calls = json!({
+        "model": "llama3.2",
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": {
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [call("slow"), call("fast"), call("stuck")],
+        },
+        "done": true,
+    });
+    let ndjson = format!("{tool_calls}\n");
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(ndjson, "application/x-ndjson"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&ollama)
+        .await;
+    let webhook = MockServer::start().await;
+    for (name, delay) in [("slow", 600), ("fast", 0), ("stuck", 5000)] {
+        Mock::given(method("POST"))
+            .and(path(format!("/{name}")))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "from": name }))
+                    .set_delay(Duration::from_millis(delay)),
+            )
+            .mount(&webhook)
+            .await;
+    }
+    let config = config_store(AppConfig {
+        ollama_base_url: ollama.uri(),
+        tool_timeout_secs: 1,
+        ..AppConfig::default()
+    });
+    let agent = OllamaAgentService::new(config, ToolRegistry::new());
+    let mut ctx = context("Run them all");
+    ctx.webhook_tools = ["slow", "fast", "stuck"]
+        .map(|name| WebhookTool {
+            id: Uuid::new_v4(),
+            conversation_id: None,
+            name: name.to_string(),
+            description: format!("The {name} tool"),
+            parameters: json!({ "type": "object", "properties": {} }),
+            url: format!("{}/{name}", webhook.uri()),
+            auth_header: None,
+            created_at: chrono::Utc::now(),
+        })
+        .to_vec();
+    let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+
+    let outcome = agent.stream_chat(&ctx, tx).await.unwrap();
+
+    let mut finished = Vec::new();
+    while let Some(update) = rx.recv().await {
+        if let StreamUpdate::Tool { name, status, duration_ms: Some(_), .. } = update {
+            finished.push((name, status));
+        }
+    }
+    // The fast call is not held up behind the slow one.
+    assert_eq!(
+        finished,
+        [
+            ("fast".to_string(), ToolCallStatus::Done),
+            ("slow".to_string(), ToolCallStatus::Done),
+            ("stuck".to_string(), ToolCallStatus::TimedOut),
+        ]
+    );
+    let results: Vec<_> = outcome
+        .tool_messages
+        .iter()
+        .filter(|m| m.role == MessageRole::Tool)
+        .map(|m| m.tool_name.as_deref().unwrap())
+        .collect();
+    assert_eq!(results, ["slow", "fast", "stuck"]);
+
+    let requests = ollama.received_requests().await.unwrap();
+    let second: Value = requests[1].body_json().unwrap();
+    let sent: Vec<_> = second["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|m| m["role"] == "tool")
+        .map(|m| m["content"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(sent.len(), 3);
+    assert!(sent[0].contains("slow") && sent[1].contains("fast"), "{sent:?}");
+    assert!(sent[2].contains("did not answer"), "{sent:?}");
+}
+
+#[tokio::test]
+async fn workspace_tools_read_allowed_files_and_log_each_read() {
+    let dir = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() { println!(\"hello\"); }\n").unwrap();
+    std::fs::write(dir.join("notes.bin"), "binary").unwrap();
+    std::fs::write(dir.join(".env"), "SECRET=1").unwrap();
+    std::fs::write(dir.join("big.rs"), "x".repeat(2048)).unwrap();
+
+    let ollama = mock_ollama(&["It prints hello."]).await;
+    let call = |name: &str, arguments: Value| {
+        json!({ "function": { "name": name, "arguments": arguments } })
+    };
+    let tool_calls = json!({
+        "model": "llama3.2",
+        "created_at": "2024-01-01T00:00:00Z",
+        "message": {
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [
+                call("list_files", json!({})),
+                call("read_file", json!({ "path": "src/main.rs" })),
+                call("read_file", json!({ "path": ".env" })),
+                call("read_file", json!({ "path": "big.rs" })),
+                call("read_file", json!({ "path": "../outside.rs" })),
+            ],
+        },
+        "done": true,
+    });
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(format!("{tool_calls}\n"), "application/x-ndjson"),
+        )
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&ollama)
+        .await;
+    let db = TestDb::new().await;
+    let reads = WorkspaceReadRepository::new(db.pool.clone());
+    let access =
+        WorkspaceAccess::new(Workspace::new(&dir), 1024, vec!["rs".to_string()], reads.clone());
+    let tools = ToolRegistry::new().with_workspace(access);
+    assert_eq!(tools.names(), ["list_files", "read_file"]);
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), tools);
+    let (tx, _rx) = tokio::sync::mpsc::channel(64);
+
+    let outcome = agent.stream_chat(&context("What does main do?"), tx).await.unwrap();
+
+    let results: Vec<&str> = outcome
+        .tool_messages
+        .iter()
+        .filter(|m| m.role == MessageRole::Tool)
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(results.len(), 5);
+    // Hidden files and other extensions are not listed.
+    assert!(results[0].contains("src") && results[0].contains("big.rs"), "{}", results[0]);
+    assert!(!results[0].contains(".env") && !results[0].contains("notes.bin"), "{}", results[0]);
+    assert!(results[1].contains("println!"), "{}", results[1]);
+    assert!(results[2].contains("hidden"), "{}", results[2]);
+    assert!(results[3].contains("more than the 1024 allowed"), "{}", results[3]);
+    assert!(!results[4].contains("println!") && results[4].contains("workspace"), "{}", results[4]);
+
+    let logged = reads.find_recent(10).await.unwrap();
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].path, "src/main.rs");
+    assert_eq!(logged[0].conversation_id, CONVERSATION_ID);
+    assert_eq!(logged[0].bytes, 33);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn chat_spans_are_exported_over_otlp() {
+    let ollama = mock_ollama(&["Traced"]).await;
+    let collector = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/traces"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&collector)
+        .await;
+
+    let provider = OtlpConfig {
+        traces_endpoint: Some(format!("{}/v1/traces", collector.uri())),
+        service_name: "otlp-test".to_string(),
+        headers: HashMap::from([("x-api-key".to_string(), "secret".to_string())]),
+        export_interval: Duration::from_millis(50),
+    }
+    .tracer_provider()
+    .unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let agent = OllamaAgentService::new(test_config(&ollama.uri()), ToolRegistry::new());
+    agent.chat(&context("Trace me")).await.unwrap();
+    drop(guard);
+    // Exports from the provider's thread, which waits for the collector.
+    tokio::task::spawn_blocking(move || provider.force_flush()).await.unwrap().unwrap();
+
+    let exported = collector.received_requests().await.unwrap();
+    let request = exported.first().expect("no spans exported");
+    assert_eq!(request.headers["x-api-key"], "secret");
+    let body: Value = request.body_json().unwrap();
+    let resource = &body["resourceSpans"][0];
+    let attribute = |attributes: &Value, key: &str| {
+        attributes
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|a| a["key"] == key)
+            .map(|a| a["value"]["stringValue"].clone())
+    };
+    let service = attribute(&resource["resource"]["attributes"], "service.name");
+    assert_eq!(service, Some(json!("otlp-test")));
+
+    let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+    let span = spans.iter().find(|s| s["name"] == "run_chat").expect("run_chat span");
+    let conversation_id = attribute(&span["attributes"], "conversation_id");
+    assert_eq!(conversation_id, Some(json!(CONVERSATION_ID.to_string())));
+    assert_eq!(attribute(&span["attributes"], "model"), Some(json!("llama3.2")));
+    assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+}
+
+#[tokio::test]
+async fn admins_list_and_cancel_running_generations() {
+    let ollama = mock_ollama(&["never sent"]).await;
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .with_priority(1)
+        .mount(&ollama)
+        .await;
+    let app = TestApp::spawn_with_ollama(&ollama).await;
+    let client = reqwest::Client::new();
+
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+    let request = json!({ "message": "Take your time" });
+    socket.send(Message::Text(request.to_string().into())).await.unwrap();
+
+    let mut running = Value::Null;
+    for _ in 0..100 {
+        let listed: Value =
+            client.get(app.url("/api/admin/streams")).send().await.unwrap().json().await.unwrap();
+        if listed.as_array().is_some_and(|l| !l.is_empty()) {
+            running = listed[0].clone();
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(running["kind"], "chat", "got {running}");
+    assert_eq!(running["model"], "llama3.2");
+    assert!(running["conversation_id"].is_string(), "got {running}");
+
+    let id = running["id"].as_str().unwrap();
+    let url = app.url(&format!("/api/admin/streams/{id}"));
+    let resp = client.delete(&url).send().await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let mut last = Value::Null;
+    while let Some(Ok(Message::Text(text))) = socket.next().await {
+        last = serde_json::from_str(&text).unwrap();
+        if matches!(last["type"].as_str(), Some("stream_end" | "error")) {
+            break;
+        }
+    }
+    assert_eq!(last["type"], "error", "got {last}");
+    assert!(last["message"].as_str().unwrap().contains("cancelled"), "got {last}");
+
+    let listed: Value =
+        client.get(app.url("/api/admin/streams")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(listed, json!([]));
+    let resp = client.delete(&url).send().await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn slow_first_tokens_mark_the_service_degraded() {
+    let ollama = slow_ollama(&["Finally"], Duration::from_millis(100)).await;
+    let config = config_store(AppConfig {
+        ollama_base_url: ollama.uri(),
+        first_token_sla_ms: 50,
+        ..AppConfig::default()
+    });
+    let agent = Arc::new(OllamaAgentService::new(config.clone(), ToolRegistry::new()));
+    let app = TestApp::spawn_with(agent.clone(), config).await;
+    let readyz = || async {
+        let resp = reqwest::get(app.url("/readyz")).await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        resp.json::<Value>().await.unwrap()
+    };
+
+    let ready = readyz().await;
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["first_token"]["samples"], 0);
+
+    // Too few samples for a p95 until the fifth turn.
+    for turn in 1..=5 {
+        assert_eq!(readyz().await["degraded"], false, "before turn {turn}");
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        agent.stream_chat(&context("Hello"), tx).await.unwrap();
+    }
+
+    let degraded = readyz().await;
+    assert_eq!(degraded["status"], "degraded", "got {degraded}");
+    assert_eq!(degraded["degraded"], true);
+    assert_eq!(degraded["first_token"]["samples"], 5);
+    assert_eq!(degraded["first_token"]["threshold_ms"], 50);
+    assert!(degraded["first_token"]["p95_ms"].as_u64().unwrap() >= 100, "got {degraded}");
+}
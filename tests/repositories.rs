@@ -0,0 +1,469 @@
+mod common;
+
+use chrono::Utc;
+use common::TestDb;
+use rust_ai_experiments::app;
+use rust_ai_experiments::db::collection_repository::CollectionRepository;
+use rust_ai_experiments::db::conversation_repository::ConversationRepository;
+use rust_ai_experiments::db::document_repository::{
+    DocumentRepository, BY_COLLECTION, BY_POSITION, BY_RANKING,
+};
+use rust_ai_experiments::db::message_repository::MessageRepository;
+use rust_ai_experiments::db::stream_repository::StreamRepository;
+use rust_ai_experiments::models::{
+    ActiveStream, Chunker, Collection, Conversation, ConversationSort, Document, DocumentChunk,
+    Message, MessageRole, RetrievalPrecision, RetrievedChunk, Source,
+};
+use rust_ai_experiments::service::retrieval::{self, Weights};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+fn source(url: &str) -> Source {
+    Source {
+        url: url.to_string(),
+        title: format!("Title of {url}"),
+        snippet: "snippet".to_string(),
+        score: Some(0.5),
+    }
+}
+
+async fn collection(pool: &PgPool, name: &str) {
+    let collection = Collection {
+        name: name.to_string(),
+        description: None,
+        embedding_model: None,
+        chunker: Chunker::Auto,
+        chunk_max_lines: None,
+        chunk_overlap: None,
+        global: false,
+        created_at: Utc::now(),
+        documents: 0,
+        chunks: 0,
+    };
+    assert!(CollectionRepository::new(pool.clone()).save(&collection).await.unwrap());
+}
+
+/// A document of `collection` at `path` whose chunks are `embeddings`, one
+/// each, numbered in their content.
+fn indexed(
+    collection: &str,
+    path: &str,
+    embeddings: &[&[f32]],
+) -> (Document, Vec<DocumentChunk>) {
+    let document = Document {
+        id: Uuid::new_v4(),
+        collection: collection.to_string(),
+        source: "test".to_string(),
+        path: path.to_string(),
+        language: None,
+        content: None,
+        content_hash: None,
+        created_at: Utc::now(),
+    };
+    let chunks = embeddings
+        .iter()
+        .enumerate()
+        .map(|(i, embedding)| DocumentChunk {
+            id: Uuid::new_v4(),
+            document_id: document.id,
+            position: i as i32,
+            content: format!("{path} chunk {i}"),
+            start_line: 1,
+            end_line: 1,
+            embedding: embedding.to_vec(),
+            content_hash: None,
+            embedding_model: None,
+        })
+        .collect();
+    (document, chunks)
+}
+
+async fn conversation(db: &TestDb) -> Conversation {
+    let repo = ConversationRepository::new(db.pool.clone());
+    repo.save(&Conversation::new(uuid::Uuid::new_v4(), "Test".to_string()))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn conversations_round_trip() {
+    let db = TestDb::new().await;
+    let repo = ConversationRepository::new(db.pool.clone());
+
+    let saved = conversation(&db).await;
+    let found = repo.find_by_id(saved.id).await.unwrap().expect("conversation exists");
+    assert_eq!(found.title, "Test");
+
+    repo.update_timestamp(saved.id).await.unwrap();
+    let updated = repo.find_by_id(saved.id).await.unwrap().unwrap();
+    assert!(updated.updated_at >= saved.updated_at);
+
+    assert_eq!(repo.find_all(ConversationSort::Updated).await.unwrap().len(), 1);
+    assert!(repo.find_by_id(uuid::Uuid::new_v4()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn messages_keep_order_and_sources() {
+    let db = TestDb::new().await;
+    let repo = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+
+    let question = Message::new(conv.id, MessageRole::User, "question".to_string());
+    repo.save(&question).await.unwrap();
+    let answer = Message::new(conv.id, MessageRole::Assistant, "answer [1]".to_string())
+        .with_sources(vec![source("https://a.example"), source("https://b.example")]);
+    repo.save(&answer).await.unwrap();
+
+    let messages = repo.find_by_conversation_id(conv.id).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].role, MessageRole::User);
+    assert!(messages[0].sources.is_empty());
+    assert_eq!(messages[1].sources, answer.sources);
+    assert_eq!(messages[1].version_count, 1);
+
+    let found = repo.find_by_id(answer.id).await.unwrap().unwrap();
+    assert_eq!(found.sources, answer.sources);
+}
+
+#[tokio::test]
+async fn versions_keep_the_original_and_switch_sources() {
+    let db = TestDb::new().await;
+    let repo = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+
+    let answer = Message::new(conv.id, MessageRole::Assistant, "first".to_string())
+        .with_sources(vec![source("https://first.example")]);
+    repo.save(&answer).await.unwrap();
+
+    let regenerated = repo
+        .add_version(answer.id, "second", &[source("https://second.example")])
+        .await
+        .unwrap();
+    assert_eq!(regenerated.content, "second");
+    assert_eq!((regenerated.active_version, regenerated.version_count), (2, 2));
+    assert_eq!(regenerated.sources[0].url, "https://second.example");
+
+    let versions = repo.find_versions(answer.id).await.unwrap();
+    let contents: Vec<_> = versions.iter().map(|v| (v.content.as_str(), v.is_active)).collect();
+    assert_eq!(contents, [("first", false), ("second", true)]);
+
+    repo.set_active_version(answer.id, 1).await.unwrap();
+    let restored = repo.find_by_id(answer.id).await.unwrap().unwrap();
+    assert_eq!(restored.content, "first");
+    assert_eq!(restored.sources[0].url, "https://first.example");
+
+    let err = repo.set_active_version(answer.id, 7).await.unwrap_err();
+    assert!(err.is_not_found());
+}
+
+#[tokio::test]
+async fn large_messages_are_stored_compressed() {
+    let db = TestDb::new().await;
+    let repo = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+    let dump = "fn main() { println!(\"hello\"); }\n".repeat(500);
+    let stored_plain = |id: uuid::Uuid| {
+        let pool = db.pool.clone();
+        async move {
+            let (plain, compressed): (String, Option<Vec<u8>>) =
+                sqlx::query_as("SELECT content, content_compressed FROM messages WHERE id = $1")
+                    .bind(id)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            (plain, compressed.map(|c| c.len()))
+        }
+    };
+
+    let small = Message::new(conv.id, MessageRole::User, "short".to_string());
+    repo.save(&small).await.unwrap();
+    let large = Message::new(conv.id, MessageRole::Assistant, dump.clone());
+    repo.save(&large).await.unwrap();
+
+    assert_eq!(stored_plain(small.id).await, ("short".to_string(), None));
+    let (plain, compressed_len) = stored_plain(large.id).await;
+    assert!(plain.is_empty());
+    assert!(compressed_len.unwrap() < dump.len() / 10);
+    let messages = repo.find_by_conversation_id(conv.id).await.unwrap();
+    assert_eq!(messages[1].content, dump);
+
+    // Regenerating to a short reply and back keeps every version readable.
+    repo.add_version(large.id, "short now", &[]).await.unwrap();
+    assert_eq!(stored_plain(large.id).await, ("short now".to_string(), None));
+    repo.set_active_version(large.id, 1).await.unwrap();
+    assert_eq!(repo.find_by_id(large.id).await.unwrap().unwrap().content, dump);
+
+    // Rows written before compression existed are picked up by the backfill.
+    let legacy = Message::new(conv.id, MessageRole::Assistant, dump.clone());
+    sqlx::query(
+        "INSERT INTO messages (id, conversation_id, role, content) VALUES ($1, $2, 'ASSISTANT', $3)",
+    )
+    .bind(legacy.id)
+    .bind(conv.id)
+    .bind(&dump)
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    assert_eq!(repo.compress_existing(1).await.unwrap(), 1);
+    assert!(stored_plain(legacy.id).await.1.is_some());
+    assert_eq!(repo.find_by_id(legacy.id).await.unwrap().unwrap().content, dump);
+}
+
+#[tokio::test]
+async fn searches_find_text_inside_compressed_messages() {
+    let db = TestDb::new().await;
+    let conversations = ConversationRepository::new(db.pool.clone());
+    let messages = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+    let other = conversation(&db).await;
+    let dump = format!("{}fn Needle() {{}}\n", "fn main() {}\n".repeat(500));
+    messages.save(&Message::new(conv.id, MessageRole::Assistant, dump)).await.unwrap();
+    let short = Message::new(other.id, MessageRole::User, "a needle".to_string());
+    messages.save(&short).await.unwrap();
+
+    let (plain,): (String,) = sqlx::query_as(
+        "SELECT content FROM messages
+         WHERE conversation_id = $1 AND content_compressed IS NOT NULL",
+    )
+    .bind(conv.id)
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    assert!(plain.is_empty());
+    let ids = |found: Vec<Conversation>| found.into_iter().map(|c| c.id).collect::<Vec<_>>();
+    let found = ids(conversations.search("fn needle", 10).await.unwrap());
+    assert_eq!(found, [conv.id]);
+    let mut found = ids(conversations.search("NEEDLE", 10).await.unwrap());
+    found.sort();
+    let mut both = [conv.id, other.id];
+    both.sort();
+    assert_eq!(found, both);
+    assert_eq!(conversations.search("needle", 1).await.unwrap().len(), 1);
+    assert!(conversations.search("haystack", 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn active_streams_accumulate_their_reply_until_finished() {
+    let db = TestDb::new().await;
+    let repo = StreamRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+
+    let stream = ActiveStream {
+        socket_id: uuid::Uuid::new_v4().to_string(),
+        stream_id: "turn-1".to_string(),
+        conversation_id: conv.id,
+        node_id: uuid::Uuid::new_v4().to_string(),
+        start_event: r#"{"type":"stream_start"}"#.to_string(),
+        content: String::new(),
+    };
+    repo.start(&stream).await.unwrap();
+    repo.append(&stream.socket_id, "turn-1", "Hel").await.unwrap();
+    repo.append(&stream.socket_id, "turn-1", "lo").await.unwrap();
+
+    let found = repo.find_by_conversation(conv.id).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].content, "Hello");
+    assert_eq!(found[0].node_id, stream.node_id);
+
+    // Rows left by a server that went away stop counting once stale.
+    sqlx::query("UPDATE active_streams SET updated_at = NOW() - INTERVAL '1 hour'")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(repo.find_by_conversation(conv.id).await.unwrap().is_empty());
+
+    repo.start(&stream).await.unwrap();
+    repo.finish(&stream.socket_id, "turn-1").await.unwrap();
+    assert!(repo.find_by_conversation(conv.id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn deleting_a_conversation_cascades_to_its_messages() {
+    let db = TestDb::new().await;
+    let conversations = ConversationRepository::new(db.pool.clone());
+    let messages = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+
+    let answer = Message::new(conv.id, MessageRole::Assistant, "first".to_string())
+        .with_sources(vec![source("https://a.example")]);
+    messages.save(&answer).await.unwrap();
+    messages.add_version(answer.id, "second", &[]).await.unwrap();
+    messages.set_starred(answer.id, true).await.unwrap();
+
+    assert!(conversations.delete(conv.id).await.unwrap());
+    for table in ["messages", "message_sources", "message_versions", "starred_messages"] {
+        let (rows,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(rows, 0, "{table} still has rows");
+    }
+    assert!(!conversations.delete(conv.id).await.unwrap());
+}
+
+#[tokio::test]
+async fn schemas_migrate_side_by_side_and_search_their_own_chunks() {
+    let db = TestDb::new().await;
+    for schema in ["tenant_a", "tenant_b"] {
+        let pool = app::connect_database(&db.url, Some(schema)).await.unwrap();
+        let documents = DocumentRepository::new(pool.clone());
+        collection(&pool, "docs").await;
+        let path = format!("{schema}.md");
+        let (document, chunks) = indexed("docs", &path, &[&[1.0, 0.0], &[0.0, 1.0]]);
+        let paths = [document.path.clone()];
+        documents.sync_source("docs", "test", &[(document, chunks)], &paths).await.unwrap();
+
+        let collections = ["docs".to_string()];
+        let hits = documents.search_similar(&collections, &[1.0, 0.1], 5).await.unwrap();
+        let found: Vec<&str> = hits.iter().map(|hit| hit.content.as_str()).collect();
+        assert_eq!(found, [format!("{path} chunk 0"), format!("{path} chunk 1")]);
+
+        // Each schema gets its own index of two-dimensional embeddings.
+        let (indexes,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM pg_indexes
+             WHERE schemaname = $1 AND indexname = 'idx_document_chunks_embedding_2'",
+        )
+        .bind(schema)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(indexes, 1, "{schema}");
+    }
+}
+
+#[tokio::test]
+async fn rankings_that_disagree_are_fused_by_weight() {
+    let db = TestDb::new().await;
+    let documents = DocumentRepository::new(db.pool.clone());
+    collection(&db.pool, "docs").await;
+    // The first chunk is closer to the query's embedding, the second has
+    // more of its words.
+    let (document, mut chunks) = indexed("docs", "notes.md", &[&[1.0, 0.0], &[0.6, 0.8]]);
+    chunks[0].content = "invoice".to_string();
+    chunks[1].content = "invoice invoice totals".to_string();
+    let (closest, wordiest) = (chunks[0].id, chunks[1].id);
+    let paths = [document.path.clone()];
+    documents.sync_source("docs", "test", &[(document, chunks)], &paths).await.unwrap();
+
+    let collections = ["docs".to_string()];
+    let by_vector = documents.search_similar(&collections, &[1.0, 0.1], 5).await.unwrap();
+    let query = retrieval::any_word("invoice totals");
+    let by_text = documents.search_text(&collections, &query, 5).await.unwrap();
+    let ids = |hits: &[RetrievedChunk]| hits.iter().map(|hit| hit.chunk_id).collect::<Vec<_>>();
+    assert_eq!(ids(&by_vector), [closest, wordiest]);
+    assert_eq!(ids(&by_text), [wordiest, closest]);
+
+    let fused = |vector, text| {
+        retrieval::fuse(by_vector.clone(), by_text.clone(), Weights { vector, text })
+    };
+    let hits = fused(2.0, 1.0);
+    assert_eq!(ids(&hits), [closest, wordiest]);
+    assert_eq!((hits[0].vector_rank, hits[0].text_rank), (Some(1), Some(2)));
+    assert_eq!((hits[1].vector_rank, hits[1].text_rank), (Some(2), Some(1)));
+    assert!((hits[0].score - (2.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-6);
+    assert_eq!(ids(&fused(1.0, 2.0)), [wordiest, closest]);
+    let hits = fused(0.0, 1.0);
+    assert_eq!(ids(&hits), [wordiest, closest]);
+    assert_eq!(hits[0].score, 1.0 / 61.0);
+}
+
+#[tokio::test]
+async fn similarity_search_spans_collections_of_one_dimension() {
+    let db = TestDb::new().await;
+    let documents = DocumentRepository::new(db.pool.clone());
+    let small: [&[f32]; 2] = [&[1.0, 0.0], &[0.0, 1.0]];
+    let large: [&[f32]; 2] = [&[1.0, 0.0, 0.0], &[0.0, 0.0, 1.0]];
+    for (name, embeddings) in [("small", small), ("large", large)] {
+        collection(&db.pool, name).await;
+        let (document, chunks) = indexed(name, &format!("{name}.md"), &embeddings);
+        let paths = [document.path.clone()];
+        documents.sync_source(name, "test", &[(document, chunks)], &paths).await.unwrap();
+    }
+
+    // Searched together, each collection only answers queries of its
+    // embeddings' dimensions.
+    let collections = ["small".to_string(), "large".to_string()];
+    let found = |hits: Vec<RetrievedChunk>| {
+        hits.into_iter().map(|hit| hit.content).collect::<Vec<String>>()
+    };
+    let hits = documents.search_similar(&collections, &[0.1, 1.0], 5).await.unwrap();
+    assert_eq!(found(hits), ["small.md chunk 1", "small.md chunk 0"]);
+    let hits = documents.search_similar(&collections, &[1.0, 0.0, 0.1], 5).await.unwrap();
+    assert_eq!(found(hits), ["large.md chunk 0", "large.md chunk 1"]);
+    let hits = documents.search_similar(&collections, &[1.0; 4], 5).await.unwrap();
+    assert!(hits.is_empty());
+
+    let (indexes,): (Vec<String>,) = sqlx::query_as(
+        "SELECT ARRAY_AGG(indexname ORDER BY indexname) FROM pg_indexes
+         WHERE indexname LIKE 'idx_document_chunks_embedding_%'",
+    )
+    .fetch_one(&db.pool)
+    .await
+    .unwrap();
+    assert_eq!(indexes, ["idx_document_chunks_embedding_2", "idx_document_chunks_embedding_3"]);
+}
+
+#[tokio::test]
+async fn feedback_on_retrieved_chunks_is_counted_into_precision() {
+    let db = TestDb::new().await;
+    let documents = DocumentRepository::new(db.pool.clone());
+    let messages = MessageRepository::new(db.pool.clone());
+    let conv = conversation(&db).await;
+    collection(&db.pool, "docs").await;
+    let (document, mut chunks) =
+        indexed("docs", "notes.md", &[&[1.0, 0.0], &[0.6, 0.8], &[0.0, 1.0]]);
+    for (chunk, content) in chunks.iter_mut().zip(["invoice", "invoice totals", "receipts"]) {
+        chunk.content = content.to_string();
+    }
+    let paths = [document.path.clone()];
+    documents.sync_source("docs", "test", &[(document, chunks)], &paths).await.unwrap();
+
+    // Found by both rankings, by text only and by similarity only.
+    let collections = ["docs".to_string()];
+    let by_vector = documents.search_similar(&collections, &[0.9, 0.1], 3).await.unwrap();
+    let by_text = documents.search_text(&collections, "totals", 3).await.unwrap();
+    let retrieved = retrieval::fuse(by_vector, by_text, Weights { vector: 1.0, text: 5.0 });
+    let answer = Message::new(conv.id, MessageRole::Assistant, "answer".to_string());
+    messages.save(&answer).await.unwrap();
+    documents.save_retrieved(answer.id, 1, &retrieved).await.unwrap();
+    assert!(documents.find_precision(BY_RANKING).await.unwrap().is_empty());
+
+    let verdicts = [Some(true), Some(false), None];
+    for (chunk, relevant) in retrieved.iter().zip(verdicts) {
+        assert!(documents.rate_retrieved(answer.id, chunk.chunk_id, relevant).await.unwrap());
+    }
+    let unknown = Uuid::new_v4();
+    assert!(!documents.rate_retrieved(unknown, retrieved[0].chunk_id, Some(true)).await.unwrap());
+    let counts = |precision: Vec<RetrievalPrecision>| {
+        precision.into_iter().map(|p| (p.group, p.rated, p.relevant)).collect::<Vec<_>>()
+    };
+    let by_ranking = counts(documents.find_precision(BY_RANKING).await.unwrap());
+    assert_eq!(by_ranking, [("both".to_string(), 1, 1), ("vector".to_string(), 1, 0)]);
+    let by_position = counts(documents.find_precision(BY_POSITION).await.unwrap());
+    assert_eq!(by_position, [("1".to_string(), 1, 1), ("2".to_string(), 1, 0)]);
+    let by_collection = counts(documents.find_precision(BY_COLLECTION).await.unwrap());
+    assert_eq!(by_collection, [("docs".to_string(), 2, 1)]);
+}
+
+#[tokio::test]
+async fn embeddings_are_only_reused_for_the_model_that_made_them() {
+    let db = TestDb::new().await;
+    let documents = DocumentRepository::new(db.pool.clone());
+    collection(&db.pool, "docs").await;
+    let (document, mut chunks) = indexed("docs", "notes.md", &[&[1.0, 0.0], &[0.0, 1.0]]);
+    for (chunk, model) in chunks.iter_mut().zip([Some("small"), None]) {
+        chunk.content_hash = Some("same".to_string());
+        chunk.embedding_model = model.map(str::to_string);
+    }
+    let (id, paths) = (document.id, [document.path.clone()]);
+    documents.sync_source("docs", "test", &[(document, chunks)], &paths).await.unwrap();
+    let stored = documents.find_document_chunks(id).await.unwrap();
+    let models: Vec<_> = stored.into_iter().map(|chunk| chunk.embedding_model).collect();
+    assert_eq!(models, [Some("small".to_string()), None]);
+
+    let hashes = ["same".to_string()];
+    let reused = documents.find_embeddings("docs", "small", &hashes).await.unwrap();
+    assert_eq!(reused["same"], [1.0, 0.0]);
+    assert!(documents.find_embeddings("docs", "large", &hashes).await.unwrap().is_empty());
+    assert!(documents.find_embeddings("other", "small", &hashes).await.unwrap().is_empty());
+}
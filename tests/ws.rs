@@ -0,0 +1,631 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::agent::ScriptedAgent;
+use common::{config_store, TestApp};
+use futures_util::{SinkExt, StreamExt};
+use rust_ai_experiments::config::{AppConfig, EventBusConfig, ModelPrice};
+use rust_ai_experiments::models::{ChatRequest, MessageRole, Source, WsEvent, WsFrame};
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn send(socket: &mut Socket, body: Value) {
+    socket.send(Message::Text(body.to_string().into())).await.unwrap();
+}
+
+/// Reads events until the turn ends, returning all of them. Follow-up
+/// suggestions, which trail earlier turns, are skipped.
+async fn read_turn(socket: &mut Socket) -> Vec<Value> {
+    let mut events = Vec::new();
+    while let Some(msg) = socket.next().await {
+        let Message::Text(text) = msg.unwrap() else { continue };
+        let event: Value = serde_json::from_str(&text).unwrap();
+        if event["type"] == "follow_up_suggestions" {
+            continue;
+        }
+        let done = matches!(event["type"].as_str(), Some("stream_end" | "model_missing" | "busy" | "error"));
+        events.push(event);
+        if done {
+            break;
+        }
+    }
+    events
+}
+
+/// Reads the next `count` events, skipping follow-up suggestions.
+async fn next_events(socket: &mut Socket, count: usize) -> Vec<Value> {
+    let mut events = Vec::new();
+    while events.len() < count {
+        let Some(Ok(Message::Text(text))) = socket.next().await else { break };
+        let event: Value = serde_json::from_str(&text).unwrap();
+        if event["type"] != "follow_up_suggestions" {
+            events.push(event);
+        }
+    }
+    events
+}
+
+fn types(events: &[Value]) -> Vec<&str> {
+    events.iter().map(|e| e["type"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn streams_chunks_then_persists_the_reply() {
+    let sources = vec![Source {
+        url: "https://example.com".to_string(),
+        title: "Example".to_string(),
+        snippet: String::new(),
+        score: None,
+    }];
+    let agent = ScriptedAgent::replying(&["Hel", "lo"]).with_sources(sources);
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "Hi", "conversation_id": null })).await;
+    let events = read_turn(&mut socket).await;
+
+    assert_eq!(
+        types(&events),
+        ["stream_start", "stream_chunk", "stream_chunk", "stream_sources", "stream_end"]
+    );
+    let conv_id: Uuid = events[0]["conversation_id"].as_str().unwrap().parse().unwrap();
+    assert_eq!(events[4]["full_content"], "Hello");
+    assert_eq!(events[4]["model"], "scripted");
+    assert_eq!(events[4]["completion_tokens"], 2);
+    assert_eq!(events[4]["finish_reason"], "stop");
+    assert!(events[4]["duration_ms"].is_u64());
+
+    // Follow-up suggestions arrive once the turn has ended.
+    let Some(Ok(Message::Text(text))) = socket.next().await else { panic!("socket closed") };
+    let suggested: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(suggested["type"], "follow_up_suggestions");
+    assert_eq!(suggested["message_id"], events[4]["message_id"]);
+    assert_eq!(suggested["suggestions"], json!(["Follow-up 1", "Follow-up 2", "Follow-up 3"]));
+
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1].id.to_string(), events[4]["message_id"].as_str().unwrap());
+    assert_eq!(messages[1].sources.len(), 1);
+    assert_eq!(messages[1].suggestions.len(), 3);
+
+    // The same socket can carry further turns.
+    send(&mut socket, json!({ "message": "Again", "conversation_id": conv_id })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(events.last().unwrap()["type"], "stream_end");
+}
+
+#[tokio::test]
+async fn malformed_json_replies_are_stopped_early_and_repaired() {
+    let reply = r#"{"answer": 42}"#;
+    let agent = ScriptedAgent::replying(&["Sure! ", reply]).answering(reply);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "The answer?", "response_format": "json" })).await;
+    let events = read_turn(&mut socket).await;
+
+    // Streaming stops at the first chunk that cannot start a JSON document.
+    assert_eq!(types(&events), ["stream_start", "stream_chunk", "stream_end"]);
+    let end = &events[2];
+    assert_eq!(end["json_validation"]["status"], "repaired");
+    assert!(end["json_validation"]["error"].as_str().unwrap().contains("unexpected 'S'"));
+    assert_eq!(end["full_content"], reply);
+    assert_eq!(end["finish_reason"], "stop");
+
+    let seen = agent.seen();
+    assert!(seen[1].user_message.contains("not valid JSON"));
+    assert_eq!(seen[1].history.last().unwrap().content, "Sure! ");
+
+    let conv_id: Uuid = events[0]["conversation_id"].as_str().unwrap().parse().unwrap();
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    assert_eq!(messages[1].content, reply);
+
+    // Plain turns carry no validation.
+    send(&mut socket, json!({ "message": "Again", "conversation_id": conv_id })).await;
+    let events = read_turn(&mut socket).await;
+    assert!(events.last().unwrap().get("json_validation").is_none());
+}
+
+#[tokio::test]
+async fn chunks_can_be_regrouped_into_words_or_sentences() {
+    let chunks = ["Hel", "lo the", "re. How", " are", " you? Fi", "ne"];
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&chunks))).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    let mut streamed = HashMap::new();
+    for mode in ["token", "word", "sentence"] {
+        send(&mut socket, json!({ "message": "Hi", "chunking": mode })).await;
+        let events = read_turn(&mut socket).await;
+        let contents: Vec<&str> = events
+            .iter()
+            .filter(|e| e["type"] == "stream_chunk")
+            .map(|e| e["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents.concat(), "Hello there. How are you? Fine");
+        assert_eq!(events.last().unwrap()["full_content"], "Hello there. How are you? Fine");
+        streamed.insert(mode, contents.into_iter().map(str::to_string).collect::<Vec<_>>());
+    }
+
+    assert_eq!(streamed["token"], chunks);
+    assert_eq!(streamed["word"], ["Hello ", "there. ", "How ", "are you? ", "Fine"]);
+    assert_eq!(streamed["sentence"], ["Hello there. ", "How are you? ", "Fine"]);
+}
+
+#[tokio::test]
+async fn tool_calls_are_persisted_and_replayed() {
+    let agent = ScriptedAgent::replying(&["Sunny"]).calling_tool(
+        "web_search",
+        r#"{"query":"weather"}"#,
+        "It is sunny",
+    );
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "Weather?", "conversation_id": null })).await;
+    let events = read_turn(&mut socket).await;
+    let conv_id: Uuid = events[0]["conversation_id"].as_str().unwrap().parse().unwrap();
+    let progress: Vec<_> = events.iter().filter(|e| e["type"] == "tool_call").collect();
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[0]["name"], "web_search");
+    assert_eq!(progress[0]["status"], "running");
+    assert_eq!(progress[1]["status"], "done");
+    assert_eq!(progress[0]["call_id"], progress[1]["call_id"]);
+
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let roles: Vec<_> = messages.iter().map(|m| m.role).collect();
+    assert_eq!(
+        roles,
+        [MessageRole::User, MessageRole::Function, MessageRole::Tool, MessageRole::Assistant]
+    );
+    assert_eq!(messages[1].tool_name.as_deref(), Some("web_search"));
+    assert_eq!(messages[2].content, "It is sunny");
+    assert_eq!(messages[1].tool_call_id, messages[2].tool_call_id);
+
+    send(&mut socket, json!({ "message": "Thanks", "conversation_id": conv_id })).await;
+    read_turn(&mut socket).await;
+    assert_eq!(agent.seen()[1].history.len(), 4);
+}
+
+#[tokio::test]
+async fn multiplexes_concurrent_streams_by_id() {
+    // Each turn blocks until both are streaming, so serial handling would hang.
+    let agent = ScriptedAgent::replying(&["a", "b", "c"]).concurrent(2);
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "First", "stream_id": "one" })).await;
+    send(&mut socket, json!({ "message": "Second", "stream_id": "two" })).await;
+
+    let mut content: HashMap<String, String> = HashMap::new();
+    let mut ended = 0;
+    let read_all = async {
+        while ended < 2 {
+            let Some(Ok(Message::Text(text))) = socket.next().await else { break };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            let id = event["stream_id"].as_str().expect("events carry a stream id").to_string();
+            match event["type"].as_str().unwrap() {
+                "stream_chunk" => {
+                    content.entry(id).or_default().push_str(event["content"].as_str().unwrap())
+                }
+                "stream_end" => ended += 1,
+                "error" => panic!("unexpected error: {event}"),
+                _ => {}
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), read_all)
+        .await
+        .expect("both streams should finish");
+
+    assert_eq!(content["one"], "abc");
+    assert_eq!(content["two"], "abc");
+    assert_eq!(app.service.get_conversations().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn other_sockets_watch_a_conversation_stream_live() {
+    // The watched turn blocks until a second one streams, so the watcher is
+    // subscribed before any chunk is sent.
+    let agent = ScriptedAgent::replying(&["a", "b", "c"]).concurrent(2);
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let request = ChatRequest {
+        conversation_id: None,
+        message: "Hi".to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+    let conv_id = app.service.chat(request).await.unwrap().conversation_id;
+
+    let (mut watcher, _) = connect_async(app.events_url(conv_id)).await.unwrap();
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+    send(&mut socket, json!({ "message": "Again", "conversation_id": conv_id, "stream_id": "a" }))
+        .await;
+
+    let watch = async {
+        let Some(Ok(Message::Text(text))) = watcher.next().await else { panic!("closed") };
+        let start: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(start["type"], "stream_start");
+        assert_eq!(start["conversation_id"], conv_id.to_string());
+        send(&mut socket, json!({ "message": "Elsewhere", "stream_id": "b" })).await;
+        read_turn(&mut watcher).await
+    };
+    let events = tokio::time::timeout(Duration::from_secs(10), watch)
+        .await
+        .expect("the watched turn should finish");
+    assert!(events.iter().all(|e| e["stream_id"] == "a"));
+    assert_eq!(events.last().unwrap()["type"], "stream_end");
+    let content: String = events.iter().filter_map(|e| e["content"].as_str()).collect();
+    assert_eq!(content, "abc");
+
+    let (mut stranger, _) = connect_async(app.events_url(Uuid::new_v4())).await.unwrap();
+    assert_eq!(types(&read_turn(&mut stranger).await), ["error"]);
+}
+
+#[tokio::test]
+async fn replicas_on_a_postgres_bus_share_live_streams() {
+    // Both servers share the agent, whose turns block until two stream.
+    let agent = Arc::new(ScriptedAgent::replying(&["a", "b", "c"]).concurrent(2));
+    let config = || {
+        config_store(AppConfig { event_bus: EventBusConfig::Postgres, ..AppConfig::default() })
+    };
+    let first = TestApp::spawn_with(agent.clone(), config()).await;
+    let request = ChatRequest {
+        conversation_id: None,
+        message: "Hi".to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+    let conv_id = first.service.chat(request).await.unwrap().conversation_id;
+    let (mut socket, _) = connect_async(first.ws_url()).await.unwrap();
+    send(&mut socket, json!({ "message": "Again", "conversation_id": conv_id, "stream_id": "a" }))
+        .await;
+
+    // The second server starts once the turn is under way, so only the
+    // database can tell it about the start.
+    let pool = first.db.pool.clone();
+    let count = |sql: &'static str| {
+        let pool = pool.clone();
+        async move {
+            loop {
+                let (n,): (i64,) = sqlx::query_as(sql).fetch_one(&pool).await.unwrap();
+                if n > 0 {
+                    return n;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+    };
+    let stored = count("SELECT count(*) FROM active_streams");
+    tokio::time::timeout(Duration::from_secs(10), stored).await.expect("the turn is stored");
+    let second = first.replica(agent.clone(), config()).await;
+    // Wait for both servers to be listening on the bus.
+    let listening = async {
+        let sql = "SELECT count(*) - 1 FROM pg_stat_activity \
+                   WHERE datname = current_database() AND query LIKE 'LISTEN%'";
+        count(sql).await
+    };
+    tokio::time::timeout(Duration::from_secs(10), listening).await.expect("bus listeners");
+
+    let (mut watcher, _) = connect_async(second.events_url(conv_id)).await.unwrap();
+    let watch = async {
+        let Some(Ok(Message::Text(text))) = watcher.next().await else { panic!("closed") };
+        let start: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(start["type"], "stream_start");
+        assert_eq!(start["stream_id"], "a");
+        send(&mut socket, json!({ "message": "Elsewhere", "stream_id": "b" })).await;
+        read_turn(&mut watcher).await
+    };
+    let events = tokio::time::timeout(Duration::from_secs(10), watch)
+        .await
+        .expect("the watched turn should finish");
+    let content: String = events.iter().filter_map(|e| e["content"].as_str()).collect();
+    assert_eq!(content, "abc");
+    assert_eq!(events.last().unwrap()["full_content"], "abc");
+}
+
+#[tokio::test]
+async fn scheduled_prompts_post_replies_that_watchers_see() {
+    let agent = ScriptedAgent::replying(&["Standup", " notes"]);
+    let config = config_store(AppConfig { schedule_poll_secs: 1, ..AppConfig::default() });
+    let app = TestApp::spawn_with(Arc::new(agent.clone()), config).await;
+    let request = ChatRequest {
+        conversation_id: None,
+        message: "Hi".to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+    let conv_id = app.service.chat(request).await.unwrap().conversation_id;
+    let client = reqwest::Client::new();
+    let schedule =
+        |cron: &str| json!({ "conversation_id": conv_id, "cron": cron, "prompt": "Notes?" });
+
+    let invalid = client.post(app.url("/api/schedules")).json(&schedule("daily")).send().await;
+    assert_eq!(invalid.unwrap().status(), 400);
+
+    let (mut watcher, _) = connect_async(app.events_url(conv_id)).await.unwrap();
+    let created = client.post(app.url("/api/schedules")).json(&schedule("* * * * * *")).send();
+    let created: Value = created.await.unwrap().json().await.unwrap();
+    let events = tokio::time::timeout(Duration::from_secs(10), read_turn(&mut watcher))
+        .await
+        .expect("the scheduled prompt should run");
+    assert_eq!(types(&events), ["stream_start", "stream_chunk", "stream_end"]);
+    assert_eq!(events[2]["full_content"], "Standup notes");
+    assert_eq!(agent.seen()[1].user_message, "Notes?");
+    assert_eq!(agent.seen()[1].history.len(), 2);
+
+    let url = app.url(&format!("/api/schedules/{}", created["id"].as_str().unwrap()));
+    assert_eq!(client.delete(url).send().await.unwrap().status(), 204);
+    let listed = client.get(app.url("/api/schedules")).send().await.unwrap();
+    assert_eq!(listed.json::<Value>().await.unwrap(), json!([]));
+    // Only the reply is posted, not the prompt.
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    assert_eq!(messages.iter().filter(|m| m.role == MessageRole::User).count(), 1);
+    assert_eq!(messages[2].role, MessageRole::Assistant);
+}
+
+#[tokio::test]
+async fn a_streaming_conversation_refuses_other_turns_as_busy() {
+    // Turns block until two stream, so the first is still running meanwhile.
+    let agent = ScriptedAgent::replying(&["a", "b", "c"]).concurrent(2);
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let request = ChatRequest {
+        conversation_id: None,
+        message: "Hi".to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+    let conv_id = app.service.chat(request).await.unwrap().conversation_id;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    let session = async {
+        send(&mut socket, json!({ "message": "One", "conversation_id": conv_id, "stream_id": "a" }))
+            .await;
+        assert_eq!(types(&next_events(&mut socket, 1).await), ["stream_start"]);
+
+        // Same conversation, then same stream id: refused.
+        send(&mut socket, json!({ "message": "Two", "conversation_id": conv_id, "stream_id": "b" }))
+            .await;
+        let busy = read_turn(&mut socket).await;
+        assert_eq!(types(&busy), ["busy"]);
+        assert_eq!(busy[0]["stream_id"], "b");
+        assert_eq!(busy[0]["active_stream_id"], "a");
+        assert_eq!(busy[0]["conversation_id"], conv_id.to_string());
+        send(&mut socket, json!({ "message": "Three", "stream_id": "a" })).await;
+        assert_eq!(types(&read_turn(&mut socket).await), ["busy"]);
+
+        // Another conversation streams alongside, and both finish.
+        send(&mut socket, json!({ "message": "Elsewhere", "stream_id": "c" })).await;
+        for _ in 0..2 {
+            let end = read_turn(&mut socket).await.pop().unwrap();
+            assert_eq!(end["type"], "stream_end");
+            assert_eq!(end["full_content"], "abc");
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), session).await.expect("turns should finish");
+
+    // Only the turns that ran were saved.
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["Hi", "abc", "One", "abc"]);
+}
+
+#[tokio::test]
+async fn replace_cancels_the_running_turn_of_the_conversation() {
+    let agent = ScriptedAgent::replying(&["a", "b", "c"]).pacing(Duration::from_millis(500));
+    let app = TestApp::spawn(Arc::new(agent)).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    let session = async {
+        send(&mut socket, json!({ "message": "One", "stream_id": "a" })).await;
+        let start = next_events(&mut socket, 1).await;
+        assert_eq!(types(&start), ["stream_start"]);
+        let conv_id = start[0]["conversation_id"].clone();
+
+        // Replaced before anything streamed: no reply to keep.
+        let replace = json!({
+            "message": "Two", "conversation_id": conv_id, "stream_id": "b", "replace": true,
+        });
+        send(&mut socket, replace).await;
+        let events = next_events(&mut socket, 3).await;
+        assert_eq!(types(&events), ["error", "stream_start", "stream_chunk"]);
+        assert_eq!(events[0]["stream_id"], "a");
+        assert_eq!(events[0]["code"], "cancelled");
+        assert_eq!(events[1]["stream_id"], "b");
+
+        // Replaced midway: the partial reply is saved as cancelled.
+        let replace = json!({
+            "message": "Three", "conversation_id": conv_id, "stream_id": "c", "replace": true,
+        });
+        send(&mut socket, replace).await;
+        let cancelled = read_turn(&mut socket).await;
+        let end = cancelled.last().unwrap();
+        assert_eq!(end["type"], "stream_end");
+        assert_eq!(end["stream_id"], "b");
+        assert_eq!(end["finish_reason"], "cancelled");
+        assert_eq!(end["full_content"], "a");
+
+        let events = read_turn(&mut socket).await;
+        assert!(events.iter().all(|e| e["stream_id"] == "c"));
+        assert_eq!(events.last().unwrap()["finish_reason"], "stop");
+        assert_eq!(events.last().unwrap()["full_content"], "abc");
+        serde_json::from_value::<Uuid>(conv_id).unwrap()
+    };
+    let conv_id = tokio::time::timeout(Duration::from_secs(10), session)
+        .await
+        .expect("turns should finish");
+
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["One", "Two", "a", "Three", "abc"]);
+}
+
+#[tokio::test]
+async fn reports_errors_without_closing_the_socket() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::failing("model exploded"))).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    socket.send(Message::Text("not json".into())).await.unwrap();
+    let events = read_turn(&mut socket).await;
+    assert_eq!(types(&events), ["error"]);
+    assert!(events[0].get("stream_id").is_none());
+    assert_eq!(events[0]["code"], "invalid_request");
+
+    send(&mut socket, json!({ "message": "" })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(types(&events), ["error"]);
+    assert_eq!(events[0]["code"], "invalid_request");
+
+    send(&mut socket, json!({ "message": "Hi" })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(types(&events), ["stream_start", "error"]);
+    assert_eq!(events[1]["code"], "inference_failed");
+    assert!(events[1]["message"].as_str().unwrap().contains("model exploded"));
+}
+
+#[tokio::test]
+async fn turns_out_of_context_midway_fail_instead_of_streaming_again() {
+    let agent = ScriptedAgent::replying(&["Hel", "lo"]).out_of_context_after(1);
+    let app = TestApp::spawn(Arc::new(agent.clone())).await;
+    let request = ChatRequest {
+        conversation_id: None,
+        message: "One".to_string(),
+        ephemeral: false,
+        verbosity: None,
+        stateless: None,
+        quoted_message_id: None,
+    };
+    let conv_id = app.service.chat(request).await.unwrap().conversation_id;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "Two", "conversation_id": conv_id })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(types(&events), ["stream_start", "stream_chunk", "error"]);
+    assert_eq!(events[1]["content"], "Hel");
+    assert_eq!(events[2]["code"], "inference_failed");
+    // The first reply was not dropped to try again with less history.
+    assert_eq!(agent.seen().len(), 2);
+    assert_eq!(agent.seen()[1].history.len(), 2);
+}
+
+#[tokio::test]
+async fn hello_switches_the_socket_to_messagepack_frames() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hel", "lo"]))).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "type": "hello", "encoding": "msgpack" })).await;
+    let Some(Ok(Message::Text(ack))) = socket.next().await else { panic!("no hello") };
+    let ack: Value = serde_json::from_str(&ack).unwrap();
+    assert_eq!(ack, json!({ "type": "hello", "encoding": "msgpack" }));
+
+    // Requests may be MessagePack too.
+    let request = rmp_serde::to_vec_named(&json!({ "message": "Hi", "stream_id": "s1" })).unwrap();
+    socket.send(Message::Binary(request.into())).await.unwrap();
+
+    let mut events = Vec::new();
+    while let Some(msg) = socket.next().await {
+        let Message::Binary(bytes) = msg.unwrap() else { panic!("expected a binary frame") };
+        let frame: WsFrame = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(frame.stream_id.as_deref(), Some("s1"));
+        let done = matches!(frame.event, WsEvent::StreamEnd { .. });
+        events.push(frame.event);
+        if done {
+            break;
+        }
+    }
+    assert!(matches!(events[0], WsEvent::StreamStart { .. }));
+    let Some(WsEvent::StreamEnd { full_content, .. }) = events.last() else {
+        panic!("turn did not end: {events:?}")
+    };
+    assert_eq!(full_content, "Hello");
+}
+
+#[tokio::test]
+async fn missing_model_is_pulled_then_the_turn_retried() {
+    let app = TestApp::spawn(Arc::new(ScriptedAgent::replying(&["Hi"]).without_model())).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    send(&mut socket, json!({ "message": "Hello" })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(types(&events), ["stream_start", "model_missing"]);
+    assert_eq!(events[1]["model"], "llama3.2");
+    let conv_id: Uuid = events[0]["conversation_id"].as_str().unwrap().parse().unwrap();
+
+    let body = reqwest::Client::new()
+        .post(app.url("/api/models/pull"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body.matches("event: progress").count(), 3);
+    assert!(body.contains("event: done"), "got {body}");
+
+    send(&mut socket, json!({ "conversation_id": conv_id, "retry": true })).await;
+    let events = read_turn(&mut socket).await;
+    assert_eq!(events.last().unwrap()["full_content"], "Hi");
+
+    // The retried turn answered the original message rather than adding one.
+    let messages = app.service.get_messages(conv_id).await.unwrap();
+    let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["Hello", "Hi"]);
+
+    // Nothing is left to retry once the message has an answer.
+    send(&mut socket, json!({ "conversation_id": conv_id, "retry": true })).await;
+    assert_eq!(types(&read_turn(&mut socket).await), ["error"]);
+}
+
+#[tokio::test]
+async fn priced_models_report_cost_per_reply_and_conversation() {
+    // A dollar per token keeps the arithmetic exact.
+    let price = ModelPrice { input_per_million: 1e6, output_per_million: 2e6 };
+    let config = AppConfig {
+        pricing: HashMap::from([("scripted".to_string(), price)]),
+        ..AppConfig::default()
+    };
+    let agent = ScriptedAgent::replying(&["Hel", "lo"]);
+    let app = TestApp::spawn_with(Arc::new(agent), config_store(config)).await;
+    let (mut socket, _) = connect_async(app.ws_url()).await.unwrap();
+
+    // The scripted agent reports the message length as prompt tokens and one
+    // completion token per chunk.
+    send(&mut socket, json!({ "message": "Hi" })).await;
+    let events = read_turn(&mut socket).await;
+    let conv_id = events[0]["conversation_id"].as_str().unwrap().to_string();
+    assert_eq!(events.last().unwrap()["cost"], 6.0);
+    send(&mut socket, json!({ "message": "Again", "conversation_id": conv_id })).await;
+    assert_eq!(read_turn(&mut socket).await.last().unwrap()["cost"], 9.0);
+
+    let client = reqwest::Client::new();
+    let stats: Value = client
+        .get(app.url(&format!("/api/conversations/{conv_id}/stats")))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["prompt_tokens"], 7);
+    assert_eq!(stats["completion_tokens"], 4);
+    assert_eq!(stats["cost"], 15.0);
+    assert_eq!(stats["models"][0]["replies"], 2);
+
+    let url = app.url(&format!("/api/conversations/{}/stats", Uuid::new_v4()));
+    let res = client.get(url).send().await.unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+}